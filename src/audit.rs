@@ -0,0 +1,353 @@
+//! Authoritative nameserver audit (`netprobe dns-audit`): the delegation of a
+//! zone, and whether every server it names answers for it, and the same way.
+//!
+//! The delegation is read from a server of the parent zone, the NS set at the
+//! apex from the zone's own servers. Each address of each nameserver is then
+//! queried directly, without recursion: a server that does not answer
+//! authoritatively is lame, one with an older SOA serial has not caught up
+//! with the primary, and record sets that differ mean resolvers get different
+//! answers depending on the server they happen to pick.
+
+use crate::resolver::wire::{self, Message, Query, RData};
+use crate::resolver::{self, ResolveError, Resolver};
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Record types compared when none are given.
+pub const DEFAULT_TYPES: [u16; 4] = [wire::TYPE_A, wire::TYPE_AAAA, wire::TYPE_MX, wire::TYPE_TXT];
+
+#[derive(Debug, Clone)]
+pub struct AuditOptions {
+    /// Timeout of each query.
+    pub timeout: Duration,
+    /// Record types of the apex compared across servers (besides SOA and NS).
+    pub types: Vec<u16>,
+    /// Only query the nameservers over this address family.
+    pub family: Option<resolver::IpFamily>,
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        AuditOptions { timeout: Duration::from_secs(5), types: DEFAULT_TYPES.to_vec(), family: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditResult {
+    pub domain: String,
+    pub timestamp: String,
+    pub duration_ms: f64,
+    pub status: String, // "ok" | "warning" | "error"
+    /// Zone whose servers hold the delegation.
+    pub parent: Option<String>,
+    /// NS names in the parent's delegation.
+    pub delegation: Vec<String>,
+    /// NS names published at the apex by the zone's servers.
+    pub zone_ns: Vec<String>,
+    /// Every address of every nameserver, as queried.
+    pub servers: Vec<NameserverResult>,
+    /// Each record type, and which servers return which set.
+    pub records: Vec<RecordComparison>,
+    /// What is wrong, one line per problem.
+    pub issues: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NameserverResult {
+    pub name: String,
+    pub address: Option<String>,
+    pub status: String, // "ok" | "lame" | "unreachable"
+    /// SOA serial the server answers with.
+    pub serial: Option<u32>,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordComparison {
+    #[serde(rename = "type")]
+    pub rtype: String,
+    pub consistent: bool,
+    /// Distinct answers, the most common first.
+    pub sets: Vec<RecordSet>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordSet {
+    /// Records in presentation format, sorted; empty for no records.
+    pub records: Vec<String>,
+    /// Servers ("name (address)") returning this set.
+    pub servers: Vec<String>,
+}
+
+impl AuditResult {
+    fn failed(domain: String, started: Instant, error: String) -> Self {
+        AuditResult {
+            domain,
+            timestamp: chrono::Local::now().to_rfc3339(),
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            status: "error".to_string(),
+            parent: None,
+            delegation: Vec::new(),
+            zone_ns: Vec::new(),
+            servers: Vec::new(),
+            records: Vec::new(),
+            issues: Vec::new(),
+            error: Some(error),
+        }
+    }
+}
+
+/// One nameserver address and its answers, by record type.
+struct Server {
+    label: String,
+    answers: Vec<(u16, Vec<String>)>,
+}
+
+/// Audit the zone `domain`, finding its servers through `resolver`.
+pub async fn audit(domain: &str, resolver: &Resolver, options: &AuditOptions) -> AuditResult {
+    let started = Instant::now();
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    match zone_of(resolver, &domain).await {
+        Ok(zone) if zone == domain => {},
+        Ok(zone) => return AuditResult::failed(domain, started, format!("Not a zone: its records are served by the {} zone", zone)),
+        Err(e) => return AuditResult::failed(domain, started, e),
+    }
+
+    let mut issues = Vec::new();
+    // The root has no parent to delegate it.
+    let (parent, delegation) = match domain.split_once('.') {
+        Some((_, above)) => match delegation(resolver, &domain, above, options).await {
+            Ok((parent, names)) => (Some(parent), names),
+            Err(e) => {
+                issues.push(format!("Cannot read the delegation: {}", e));
+                (None, Vec::new())
+            },
+        },
+        None => (None, Vec::new()),
+    };
+    let apex_ns = match ns_names(resolver.query(&domain, wire::TYPE_NS).await) {
+        Ok(names) => names,
+        Err(e) if delegation.is_empty() => return AuditResult::failed(domain, started, format!("NS lookup failed: {}", e)),
+        Err(_) => Vec::new(),
+    };
+
+    // Every server either side names gets queried, so a stale one is caught too.
+    let mut names: Vec<String> = delegation.iter().chain(&apex_ns).cloned().collect();
+    names.sort();
+    names.dedup();
+    let mut types = vec![wire::TYPE_SOA, wire::TYPE_NS];
+    for &rtype in &options.types {
+        if !types.contains(&rtype) {
+            types.push(rtype);
+        }
+    }
+    let queried = join_all(names.iter().map(|name| nameserver(resolver, name, &domain, &types, options))).await;
+    let (mut servers, mut answering) = (Vec::new(), Vec::new());
+    for (results, answers) in queried {
+        servers.extend(results);
+        answering.extend(answers);
+    }
+    for server in servers.iter().filter(|server| server.status != "ok") {
+        let address = server.address.as_deref().map(|address| format!(" ({})", address)).unwrap_or_default();
+        issues.push(format!("{}{} is {}: {}", server.name, address, server.status, server.error.as_deref().unwrap_or("")));
+    }
+
+    // The apex NS set as the zone's servers agree on it; the most common one otherwise.
+    let records: Vec<RecordComparison> = types.iter().map(|&rtype| compare(rtype, &answering)).collect();
+    let zone_ns = records.iter().find(|comparison| comparison.rtype == "NS").and_then(|comparison| comparison.sets.first()).map(|set| set.records.iter().map(|name| name.trim_end_matches('.').to_string()).collect()).unwrap_or(apex_ns);
+    if !delegation.is_empty() && !zone_ns.is_empty() && delegation != zone_ns {
+        let parent = parent.as_deref().unwrap_or("the parent");
+        issues.push(format!("Delegation in {} ({}) differs from the NS records at the apex ({})", parent, delegation.join(", "), zone_ns.join(", ")));
+    }
+    let serials: Vec<u32> = servers.iter().filter_map(|server| server.serial).collect();
+    if let Some(newest) = serials.iter().copied().reduce(newer) {
+        for server in servers.iter().filter(|server| server.serial.is_some_and(|serial| serial != newest)) {
+            issues.push(format!("{} ({}) is out of sync: SOA serial {} behind {}", server.name, server.address.as_deref().unwrap_or("?"), server.serial.unwrap_or_default(), newest));
+        }
+    }
+    // SOA records differ by serial alone when a secondary lags, already reported above.
+    for comparison in records.iter().filter(|comparison| !comparison.consistent && comparison.rtype != "SOA") {
+        issues.push(format!("{} records differ between servers ({} distinct answers)", comparison.rtype, comparison.sets.len()));
+    }
+
+    let status = match (servers.iter().any(|server| server.status == "ok"), issues.is_empty()) {
+        (false, _) => "error",
+        (true, true) => "ok",
+        (true, false) => "warning",
+    };
+    AuditResult {
+        domain,
+        timestamp: chrono::Local::now().to_rfc3339(),
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        status: status.to_string(),
+        parent,
+        delegation,
+        zone_ns,
+        servers,
+        records,
+        issues,
+        error: None,
+    }
+}
+
+/// The zone `name` belongs to: the owner of the SOA in the answer or, below the apex, in the authority section.
+async fn zone_of(resolver: &Resolver, name: &str) -> Result<String, String> {
+    let response = resolver.query(name, wire::TYPE_SOA).await.map_err(|e| format!("SOA lookup failed: {}", e))?;
+    if response.message.rcode == wire::RCODE_NXDOMAIN {
+        return Err("The domain does not exist".to_string());
+    }
+    let message = &response.message;
+    message.answers.iter().chain(&message.authorities).find(|record| record.rtype == wire::TYPE_SOA).map(|record| record.name.trim_end_matches('.').to_ascii_lowercase()).ok_or_else(|| format!("No SOA record for {} or above it", name))
+}
+
+/// The parent zone of `domain` (the zone `above` is in) and the NS names it delegates `domain` to,
+/// asked of the parent's servers themselves: a resolver would answer with the apex NS set instead.
+async fn delegation(resolver: &Resolver, domain: &str, above: &str, options: &AuditOptions) -> Result<(String, Vec<String>), String> {
+    let parent = zone_of(resolver, above).await?;
+    let parent_ns = ns_names(resolver.query(&parent, wire::TYPE_NS).await).map_err(|e| format!("NS lookup of {} failed: {}", parent, e))?;
+    let mut last_error = format!("no server of {} answers", parent);
+    for name in &parent_ns {
+        let Ok(ips) = resolver.lookup_ip(name, options.family).await.map(|lookup| lookup.ips) else {
+            continue;
+        };
+        for ip in ips {
+            match ask(ip, domain, wire::TYPE_NS, options.timeout).await {
+                // A referral carries the NS set in the authority section; a server authoritative for both zones answers it.
+                Ok((message, _)) => {
+                    let names = names_of(message.answers.iter().chain(&message.authorities).filter(|record| record.name.trim_end_matches('.').eq_ignore_ascii_case(domain)));
+                    if names.is_empty() {
+                        return Err(format!("{} ({}) does not delegate {} ({})", name, ip, domain, wire::rcode_name(message.rcode)));
+                    }
+                    return Ok((parent, names));
+                },
+                Err(e) => last_error = format!("{} ({}): {}", name, ip, e),
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Query every address of the nameserver `name` for the apex records of `domain`.
+async fn nameserver(resolver: &Resolver, name: &str, domain: &str, types: &[u16], options: &AuditOptions) -> (Vec<NameserverResult>, Vec<Server>) {
+    let mut result = NameserverResult { name: name.to_string(), address: None, status: "unreachable".to_string(), serial: None, latency_ms: None, error: None };
+    let ips = match resolver.lookup_ip(name, options.family).await {
+        Ok(lookup) => lookup.ips,
+        Err(e) => {
+            result.error = Some(format!("Cannot resolve {}: {}", name, e));
+            return (vec![result], Vec::new());
+        },
+    };
+    let queried = join_all(ips.into_iter().map(|ip| server(ip, NameserverResult { address: Some(ip.to_string()), ..result.clone() }, domain, types, options.timeout))).await;
+    let (results, answers): (Vec<_>, Vec<_>) = queried.into_iter().unzip();
+    (results, answers.into_iter().flatten().collect())
+}
+
+/// Query one address; its answers count only if it is authoritative for `domain`.
+async fn server(ip: IpAddr, mut result: NameserverResult, domain: &str, types: &[u16], timeout: Duration) -> (NameserverResult, Option<Server>) {
+    let (soa, latency_ms) = match ask(ip, domain, wire::TYPE_SOA, timeout).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            result.error = Some(e.to_string());
+            return (result, None);
+        },
+    };
+    result.latency_ms = Some(latency_ms);
+    result.status = "lame".to_string();
+    result.serial = soa.answers.iter().find_map(|record| match &record.data {
+        RData::Soa { serial, .. } => Some(*serial),
+        _ => None,
+    });
+    if soa.rcode != wire::RCODE_NOERROR {
+        result.error = Some(format!("answers {}", wire::rcode_name(soa.rcode)));
+        return (result, None);
+    }
+    if !soa.authoritative || result.serial.is_none() {
+        result.serial = None;
+        result.error = Some("does not answer authoritatively for the zone".to_string());
+        return (result, None);
+    }
+    result.status = "ok".to_string();
+
+    let label = format!("{} ({})", result.name, ip);
+    let mut answers = vec![(wire::TYPE_SOA, presentation(&soa, domain, wire::TYPE_SOA))];
+    for &rtype in types.iter().filter(|&&rtype| rtype != wire::TYPE_SOA) {
+        match ask(ip, domain, rtype, timeout).await {
+            Ok((message, _)) => answers.push((rtype, presentation(&message, domain, rtype))),
+            Err(e) => answers.push((rtype, vec![format!("({})", e)])),
+        }
+    }
+    (result, Some(Server { label, answers }))
+}
+
+/// One non-recursive query to a nameserver on port 53.
+async fn ask(ip: IpAddr, name: &str, qtype: u16, timeout: Duration) -> Result<(Message, f64), ResolveError> {
+    let query = Query { recursion_desired: false, ..Query::new(name, qtype) };
+    let response = resolver::query_server(SocketAddr::new(ip, 53), &query, timeout).await?;
+    Ok((response.message, response.latency_ms))
+}
+
+/// Which servers return which `rtype` set.
+fn compare(rtype: u16, servers: &[Server]) -> RecordComparison {
+    let mut sets: Vec<RecordSet> = Vec::new();
+    for server in servers {
+        let Some((_, records)) = server.answers.iter().find(|(answered, _)| *answered == rtype) else {
+            continue;
+        };
+        match sets.iter_mut().find(|set| &set.records == records) {
+            Some(set) => set.servers.push(server.label.clone()),
+            None => sets.push(RecordSet { records: records.clone(), servers: vec![server.label.clone()] }),
+        }
+    }
+    sets.sort_by_key(|set| std::cmp::Reverse(set.servers.len()));
+    RecordComparison { rtype: wire::type_name(rtype), consistent: sets.len() <= 1, sets }
+}
+
+/// The `rtype` records of `name` in an answer, sorted, with names lowercased so case alone does not differ.
+fn presentation(message: &Message, name: &str, rtype: u16) -> Vec<String> {
+    let mut records: Vec<String> = message
+        .answers
+        .iter()
+        .filter(|record| record.rtype == rtype && record.name.trim_end_matches('.').eq_ignore_ascii_case(name))
+        .map(|record| match &record.data {
+            RData::Txt(_) => record.data.to_string(),
+            data => data.to_string().to_ascii_lowercase(),
+        })
+        .collect();
+    records.sort();
+    records.dedup();
+    records
+}
+
+/// NS target names in an NS lookup's answer, sorted.
+fn ns_names(response: Result<resolver::Response, ResolveError>) -> Result<Vec<String>, String> {
+    let response = response.map_err(|e| e.to_string())?;
+    match response.message.rcode {
+        wire::RCODE_NOERROR => {},
+        rcode => return Err(format!("server answered {}", wire::rcode_name(rcode))),
+    }
+    match names_of(response.message.answers.iter()) {
+        names if names.is_empty() => Err("no NS records".to_string()),
+        names => Ok(names),
+    }
+}
+
+fn names_of<'a>(records: impl Iterator<Item = &'a wire::Record>) -> Vec<String> {
+    let mut names: Vec<String> = records
+        .filter_map(|record| match &record.data {
+            RData::Ns(target) => Some(target.trim_end_matches('.').to_ascii_lowercase()),
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The later of two SOA serials, in serial number arithmetic (RFC 1982).
+fn newer(a: u32, b: u32) -> u32 {
+    if (b.wrapping_sub(a) as i32) > 0 { b } else { a }
+}
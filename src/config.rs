@@ -0,0 +1,305 @@
+//! Config file with default flags and named profiles (`--profile`, `--config`).
+//!
+//! `~/.config/netprobe/config.toml` holds settings named after the long flags.
+//! Top-level keys apply to every run; `[profiles.NAME]` tables add to them and
+//! may also list `targets`:
+//!
+//! ```toml
+//! timeout = 10
+//! header = ["User-Agent: netprobe"]
+//!
+//! [profiles.prod-api]
+//! targets = ["https://api.example.com/health"]
+//! expect-status = "200"
+//! output = "json"
+//! ```
+//!
+//! Only the part of TOML that flags need is read: strings, integers, floats,
+//! booleans and arrays of those.
+
+use std::path::{Path, PathBuf};
+
+/// A setting's value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// Command-line values of the setting: one per array element.
+    pub fn arguments(&self) -> Vec<String> {
+        match self {
+            Value::String(s) => vec![s.clone()],
+            Value::Integer(n) => vec![n.to_string()],
+            Value::Float(f) => vec![f.to_string()],
+            Value::Bool(b) => vec![b.to_string()],
+            Value::Array(values) => values.iter().flat_map(Value::arguments).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Top-level settings, in file order.
+    pub defaults: Vec<(String, Value)>,
+    /// Named profiles and their settings, in file order.
+    pub profiles: Vec<(String, Vec<(String, Value)>)>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/netprobe/config.toml`, else `~/.config/netprobe/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("netprobe").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        Config::parse(&data).map_err(|(line, e)| format!("{}:{}: {}", path.display(), line, e))
+    }
+
+    /// Parse a config document; errors carry their line number.
+    pub fn parse(data: &str) -> Result<Self, (usize, String)> {
+        let mut parser = Parser { chars: data.chars().collect(), pos: 0, line: 1 };
+        let mut config = Config::default();
+        // None: top level; Some(i): config.profiles[i].
+        let mut table: Option<usize> = None;
+        loop {
+            parser.skip_blank();
+            let Some(c) = parser.peek() else { break };
+            if c == '[' {
+                parser.pos += 1;
+                let path = parser.key_path(']').map_err(|e| (parser.line, e))?;
+                parser.expect(']').map_err(|e| (parser.line, e))?;
+                table = match path.as_slice() {
+                    [profiles, name] if profiles == "profiles" => {
+                        if config.profiles.iter().any(|(n, _)| n == name) {
+                            return Err((parser.line, format!("profile '{}' is defined twice", name)));
+                        }
+                        config.profiles.push((name.clone(), Vec::new()));
+                        Some(config.profiles.len() - 1)
+                    },
+                    _ => return Err((parser.line, format!("unknown table [{}] (expected [profiles.NAME])", path.join(".")))),
+                };
+            } else {
+                let line = parser.line;
+                let key = parser.key().map_err(|e| (line, e))?;
+                parser.skip_spaces();
+                parser.expect('=').map_err(|e| (line, e))?;
+                parser.skip_spaces();
+                let value = parser.value().map_err(|e| (parser.line, e))?;
+                let settings = match table {
+                    Some(i) => &mut config.profiles[i].1,
+                    None => &mut config.defaults,
+                };
+                if settings.iter().any(|(k, _)| *k == key) {
+                    return Err((line, format!("'{}' is set twice", key)));
+                }
+                settings.push((key, value));
+            }
+            parser.end_of_line().map_err(|e| (parser.line, e))?;
+        }
+        Ok(config)
+    }
+
+    /// The defaults, overridden key by key by `profile` when one is given.
+    pub fn settings(&self, profile: Option<&str>) -> Result<Vec<(String, Value)>, String> {
+        let mut settings = self.defaults.clone();
+        if let Some(name) = profile {
+            let Some((_, overrides)) = self.profiles.iter().find(|(n, _)| n == name) else {
+                let known: Vec<&str> = self.profiles.iter().map(|(n, _)| n.as_str()).collect();
+                if known.is_empty() {
+                    return Err(format!("Unknown profile '{}': the config file defines none", name));
+                }
+                return Err(format!("Unknown profile '{}' (defined: {})", name, known.join(", ")));
+            };
+            for (key, value) in overrides {
+                settings.retain(|(k, _)| k != key);
+                settings.push((key.clone(), value.clone()));
+            }
+        } else if settings.iter().any(|(k, _)| k == "targets") {
+            // Targets only make sense for a named set of checks.
+            return Err("'targets' can only be set in a [profiles.NAME] table".to_string());
+        }
+        Ok(settings)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, wanted: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == wanted => Ok(()),
+            Some('\n') | None => Err(format!("expected '{}' before the end of the line", wanted)),
+            Some(c) => Err(format!("expected '{}', found '{}'", wanted, c)),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Whitespace, newlines and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.next();
+                },
+                Some('#') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                },
+                _ => break,
+            }
+        }
+    }
+
+    /// Only spaces and a comment may follow a key/value pair or a table header.
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        if self.peek() == Some('#') {
+            while self.peek().is_some_and(|c| c != '\n') {
+                self.pos += 1;
+            }
+        }
+        match self.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            Some(c) => Err(format!("unexpected '{}' after the value", c)),
+        }
+    }
+
+    /// A bare (`prod-api`) or quoted (`"prod api"`) key.
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.string(),
+            _ => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(format!("expected a key, found '{}'", self.peek().map(String::from).unwrap_or_default()));
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            },
+        }
+    }
+
+    /// Dotted keys of a table header, up to `end`.
+    fn key_path(&mut self, end: char) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_spaces();
+            path.push(self.key()?);
+            self.skip_spaces();
+            match self.peek() {
+                Some('.') => self.pos += 1,
+                Some(c) if c == end => return Ok(path),
+                _ => return Err(format!("expected '.' or '{}' in the table name", end)),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.string().map(Value::String),
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_blank();
+                    match self.next() {
+                        Some(',') => {},
+                        Some(']') => return Ok(Value::Array(values)),
+                        _ => return Err("expected ',' or ']' in the array".to_string()),
+                    }
+                }
+            },
+            _ => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_')) {
+                    self.pos += 1;
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "" => Err("expected a value".to_string()),
+                    _ => {
+                        let number = word.replace('_', "");
+                        number
+                            .parse::<i64>()
+                            .map(Value::Integer)
+                            .or_else(|_| number.parse::<f64>().map(Value::Float))
+                            .map_err(|_| format!("invalid value '{}' (strings need quotes)", word))
+                    },
+                }
+            },
+        }
+    }
+
+    /// A basic ("...", with escapes) or literal ('...') string on one line.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.next().unwrap_or('"');
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(value),
+                Some('\\') if quote == '"' => {
+                    let escaped = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(u @ ('u' | 'U')) => {
+                            let digits = if u == 'u' { 4 } else { 8 };
+                            let hex: String = (0..digits).filter_map(|_| self.next()).collect();
+                            u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or_else(|| format!("invalid escape \\{}{}", u, hex))?
+                        },
+                        Some(c) => return Err(format!("invalid escape \\{}", c)),
+                        None => return Err("unterminated string".to_string()),
+                    };
+                    value.push(escaped);
+                },
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Full-screen watch dashboard (`--tui`): a panel per target with latency
+//! sparklines per stage, plus a rolling log of failures.
+//!
+//! Drawn with ratatui on crossterm, in raw mode on the alternate screen. The
+//! terminal is given back when the dashboard is dropped, and by a panic hook
+//! if the program panics first.
+
+use crate::probe::ProbeResult;
+use crate::stats::Summary;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers};
+use futures_util::StreamExt;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, RenderDirection, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+
+/// Samples kept per sparkline; wider terminals still only show this many.
+const HISTORY: usize = 240;
+/// Failures kept for the log at the bottom.
+const MAX_FAILURES: usize = 50;
+/// Width of the stage label and of the latest-value column around a sparkline.
+const LABEL_WIDTH: u16 = 7;
+const VALUE_WIDTH: u16 = 12;
+
+/// Latency series of one target; `None` marks a run in which the stage did not complete.
+#[derive(Debug, Default)]
+struct Panel {
+    target: String,
+    summary: Summary,
+    last_status: Option<bool>,
+    dns: VecDeque<Option<f64>>,
+    tcp: VecDeque<Option<f64>>,
+    tls: VecDeque<Option<f64>>,
+    http: VecDeque<Option<f64>>,
+    total: VecDeque<Option<f64>>,
+}
+
+#[derive(Debug)]
+struct Failure {
+    time: String,
+    target: String,
+    message: String,
+}
+
+/// Everything on screen, apart from the terminal it is drawn on.
+#[derive(Debug)]
+struct Board {
+    interval: Duration,
+    panels: Vec<Panel>,
+    failures: VecDeque<Failure>,
+}
+
+/// What a key press or terminal event asks of the watch loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// `q`, `Esc` or Ctrl-C.
+    Quit,
+    /// The terminal was resized.
+    Redraw,
+}
+
+/// Owns the terminal while watch mode runs; the screen is restored on drop.
+pub struct Dashboard {
+    board: Board,
+    terminal: DefaultTerminal,
+    events: EventStream,
+}
+
+impl Dashboard {
+    /// Switch to raw mode on the alternate screen, with one panel per target in the given order.
+    pub fn new(targets: &[String], interval: Duration) -> io::Result<Dashboard> {
+        // Also installs the panic hook that restores the terminal before the panic message is printed.
+        let terminal = ratatui::try_init().inspect_err(|_| ratatui::restore())?;
+        let board = Board::new(targets, interval);
+        let mut dashboard = Dashboard { board, terminal, events: EventStream::new() };
+        dashboard.draw()?;
+        Ok(dashboard)
+    }
+
+    pub fn record(&mut self, target: &str, result: &ProbeResult) {
+        self.board.record(target, result);
+    }
+
+    /// A probe that failed outright, without a result.
+    pub fn record_failure(&mut self, target: &str, message: String) {
+        self.board.record_failure(target, message);
+    }
+
+    /// Redraw the whole screen.
+    pub fn draw(&mut self) -> io::Result<()> {
+        self.terminal.draw(|frame| self.board.render(frame)).map(|_| ())
+    }
+
+    /// Wait for the next key press or resize the watch loop has to act on.
+    pub async fn input(&mut self) -> Input {
+        while let Some(event) = self.events.next().await {
+            match event {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Input::Quit,
+                    // Raw mode delivers Ctrl-C as a key press instead of SIGINT.
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Input::Quit,
+                    _ => {},
+                },
+                Ok(Event::Resize(..)) => return Input::Redraw,
+                Ok(_) => {},
+                Err(_) => break,
+            }
+        }
+        // The terminal cannot be read any more: only a signal stops the dashboard now.
+        std::future::pending().await
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+impl Board {
+    fn new(targets: &[String], interval: Duration) -> Board {
+        let panels = targets.iter().map(|target| Panel { target: target.clone(), ..Panel::default() }).collect();
+        Board { interval, panels, failures: VecDeque::new() }
+    }
+
+    fn record(&mut self, target: &str, result: &ProbeResult) {
+        let panel = self.panel(target);
+        let success = result.is_success();
+        panel.summary.record(success, result.duration_ms);
+        panel.last_status = Some(success);
+        panel.push([result.dns.latency_ms, result.tcp.latency_ms, result.tls.latency_ms, result.http.latency_ms, Some(result.duration_ms)]);
+
+        if !success {
+            let message = [&result.dns.error, &result.tcp.error, &result.tls.error, &result.http.error]
+                .into_iter()
+                .find_map(|e| e.clone())
+                .unwrap_or_else(|| format!("exit code {}", result.exit_code()));
+            self.log_failure(target, message);
+        }
+    }
+
+    /// A probe without a result counts as a failed run with no stage completed.
+    fn record_failure(&mut self, target: &str, message: String) {
+        let panel = self.panel(target);
+        panel.summary.record_failure();
+        panel.last_status = Some(false);
+        panel.push([None; 5]);
+        self.log_failure(target, message);
+    }
+
+    fn panel(&mut self, target: &str) -> &mut Panel {
+        let index = match self.panels.iter().position(|panel| panel.target == target) {
+            Some(index) => index,
+            None => {
+                self.panels.push(Panel { target: target.to_string(), ..Panel::default() });
+                self.panels.len() - 1
+            },
+        };
+        &mut self.panels[index]
+    }
+
+    fn log_failure(&mut self, target: &str, message: String) {
+        if self.failures.len() == MAX_FAILURES {
+            self.failures.pop_front();
+        }
+        self.failures.push_back(Failure { time: chrono::Local::now().format("%H:%M:%S").to_string(), target: target.to_string(), message });
+    }
+
+    /// Title line, the panels, then the failure log in whatever height is left.
+    fn render(&self, frame: &mut Frame) {
+        let constraints = std::iter::once(Constraint::Length(1))
+            .chain(self.panels.iter().map(|panel| Constraint::Length(panel.rows().len() as u16 + 2)))
+            .chain([Constraint::Min(3)]);
+        let areas = Layout::vertical(constraints).split(frame.area());
+
+        let title = Line::from(vec![
+            "📡 netprobe watch".bold(),
+            format!(" {} target(s), every {:?}  ", self.panels.len(), self.interval).into(),
+            "q to quit".dim(),
+        ]);
+        frame.render_widget(title, areas[0]);
+        for (panel, area) in self.panels.iter().zip(&areas[1..]) {
+            panel.render(frame, *area);
+        }
+
+        // Newest first; the list cuts off whatever does not fit.
+        let items: Vec<ListItem> = match self.failures.is_empty() {
+            true => vec![ListItem::new("none".dim())],
+            false => self
+                .failures
+                .iter()
+                .rev()
+                .map(|failure| ListItem::new(Line::from(vec![failure.time.clone().dim(), "  ".into(), format!("{}  {}", failure.target, failure.message).red()])))
+                .collect(),
+        };
+        let log = List::new(items).block(Block::bordered().title(format!(" Recent failures ({}) ", self.failures.len()).bold()));
+        frame.render_widget(log, areas[areas.len() - 1]);
+    }
+}
+
+impl Panel {
+    /// One run's DNS, TCP, TLS, HTTP and total latencies.
+    fn push(&mut self, latencies: [Option<f64>; 5]) {
+        for (series, latency) in [&mut self.dns, &mut self.tcp, &mut self.tls, &mut self.http, &mut self.total].into_iter().zip(latencies) {
+            if series.len() == HISTORY {
+                series.pop_front();
+            }
+            series.push_back(latency);
+        }
+    }
+
+    /// The stages shown, in order. Plain HTTP targets never get a TLS row.
+    fn rows(&self) -> Vec<(&'static str, &VecDeque<Option<f64>>)> {
+        [("DNS", &self.dns), ("TCP", &self.tcp), ("TLS", &self.tls), ("HTTP", &self.http), ("Total", &self.total)]
+            .into_iter()
+            .filter(|(label, series)| *label != "TLS" || series.iter().any(Option::is_some))
+            .collect()
+    }
+
+    fn render(&self, frame: &mut Frame, area: Rect) {
+        let status = match self.last_status {
+            Some(true) => "✔ up".green(),
+            Some(false) => "✖ down".red(),
+            None => "… waiting".dim(),
+        };
+        let snapshot = self.summary.snapshot();
+        let title = Line::from(vec![
+            " ".into(),
+            self.target.clone().bold(),
+            "  ".into(),
+            status,
+            format!("  {} runs, {:.1}% ok, p95 {:.2}ms ", snapshot.runs, snapshot.success_rate, snapshot.latency_p95_ms.unwrap_or(0.0)).into(),
+        ]);
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let rows = self.rows();
+        let lines = Layout::vertical(vec![Constraint::Length(1); rows.len()]).split(inner);
+        for ((label, series), line) in rows.into_iter().zip(lines.iter()) {
+            let [label_area, spark_area, value_area] = Layout::horizontal([Constraint::Length(LABEL_WIDTH), Constraint::Min(10), Constraint::Length(VALUE_WIDTH)]).areas(*line);
+            let latest = match series.back() {
+                Some(Some(ms)) => format!("{:.2}ms", ms),
+                Some(None) => "-".to_string(),
+                None => String::new(),
+            };
+            frame.render_widget(Line::from(format!(" {}", label)), label_area);
+            frame.render_widget(sparkline(series, spark_area.width as usize), spark_area);
+            frame.render_widget(Line::from(latest).right_aligned(), value_area);
+        }
+    }
+}
+
+/// The last `width` samples, newest on the right and scaled to the largest one shown;
+/// runs in which the stage did not complete are drawn as red dots.
+fn sparkline(series: &VecDeque<Option<f64>>, width: usize) -> Sparkline<'static> {
+    Sparkline::default()
+        .data(series.iter().rev().take(width).map(|sample| sample.map(|ms| (ms * 1000.0).round() as u64)))
+        .direction(RenderDirection::RightToLeft)
+        .absent_value_symbol("·")
+        .absent_value_style(Style::new().red())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::dns::DnsResult;
+    use crate::probe::http::HttpResult;
+    use crate::probe::tcp::TcpResult;
+    use crate::probe::tls::TlsResult;
+    use crate::probe::SCHEMA_VERSION;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    /// A run of `target` that resolved in `dns_ms`, then could not connect.
+    fn refused(target: &str, dns_ms: f64) -> ProbeResult {
+        let mut dns = DnsResult::pending();
+        dns.status = "ok".to_string();
+        dns.latency_ms = Some(dns_ms);
+        let mut tcp = TcpResult::pending(443);
+        tcp.status = "error".to_string();
+        tcp.error = Some("Connection refused".to_string());
+        ProbeResult {
+            schema_version: SCHEMA_VERSION,
+            target: target.to_string(),
+            timestamp: String::new(),
+            duration_ms: dns_ms + 1.0,
+            dns,
+            geo: None,
+            icmp: None,
+            tcp,
+            udp: None,
+            tls: TlsResult::skipped(),
+            http: HttpResult::pending(),
+            websocket: None,
+            cdn: None,
+            http3: None,
+            stacks: None,
+            assertions: Vec::new(),
+            latency_checks: Vec::new(),
+            stats: None,
+        }
+    }
+
+    fn screen(board: &Board, width: u16, height: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| board.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height).map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect::<String>() + "\n").collect()
+    }
+
+    #[test]
+    fn panels_wait_for_their_first_result() {
+        let board = Board::new(&["example.com".to_string()], Duration::from_secs(2));
+        let screen = screen(&board, 80, 14);
+        assert!(screen.contains("1 target(s), every 2s"));
+        assert!(screen.contains("example.com  … waiting  0 runs"));
+        assert!(screen.contains("Recent failures (0)"));
+        assert!(screen.contains("none"));
+    }
+
+    #[test]
+    fn failures_are_logged_newest_first() {
+        let mut board = Board::new(&["a.example".to_string()], Duration::from_secs(1));
+        board.record("a.example", &refused("a.example", 1.0));
+        board.record("b.example", &refused("b.example", 2.0));
+        assert_eq!(board.panels.len(), 2, "unknown targets get their own panel");
+
+        let screen = screen(&board, 80, 30);
+        assert!(screen.contains("a.example  ✖ down  1 runs, 0.0% ok"));
+        assert!(screen.contains("Recent failures (2)"));
+        let newest = screen.find("b.example  Connection refused").unwrap();
+        let oldest = screen.find("a.example  Connection refused").unwrap();
+        assert!(newest < oldest);
+        // The skipped TLS stage has no row.
+        assert!(!screen.contains(" TLS "));
+        assert!(screen.contains("2.00ms"));
+    }
+
+    #[test]
+    fn history_and_failure_log_are_bounded() {
+        let mut board = Board::new(&["a.example".to_string()], Duration::from_secs(1));
+        for i in 0..HISTORY + 10 {
+            board.record("a.example", &refused("a.example", i as f64));
+        }
+        assert_eq!(board.panels[0].dns.len(), HISTORY);
+        assert_eq!(board.panels[0].dns.front(), Some(&Some(10.0)));
+        assert_eq!(board.failures.len(), MAX_FAILURES);
+    }
+
+    #[test]
+    fn probes_without_a_result_count_as_failed_runs() {
+        let mut board = Board::new(&["a.example".to_string()], Duration::from_secs(1));
+        board.record("a.example", &refused("a.example", 1.0));
+        board.record_failure("a.example", "probe task panicked".to_string());
+        let panel = &board.panels[0];
+        assert_eq!((panel.summary.runs, panel.summary.successes), (2, 0));
+        assert_eq!(panel.summary.avg(), Some(2.0), "the failed run adds no latency");
+        assert_eq!(panel.dns.back(), Some(&None));
+        assert!(screen(&board, 80, 30).contains("a.example  probe task panicked"));
+    }
+}
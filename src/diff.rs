@@ -0,0 +1,168 @@
+//! Compare two sets of probe results (`netprobe diff`, `--baseline`).
+//!
+//! Results are compared as JSON documents rather than typed structs, so a
+//! baseline saved by an older netprobe still loads when new fields appear.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Latency changes smaller than this are noise, whatever the percentage.
+const MIN_REGRESSION_MS: f64 = 1.0;
+
+/// Stage latencies compared for regressions: (label, JSON pointer).
+const LATENCIES: [(&str, &str); 5] = [
+    ("dns", "/dns/latency_ms"),
+    ("tcp", "/tcp/latency_ms"),
+    ("tls", "/tls/latency_ms"),
+    ("http", "/http/latency_ms"),
+    ("total", "/duration_ms"),
+];
+
+/// Certificate fields whose change is reported.
+const CERTIFICATE_FIELDS: [&str; 4] = ["subject", "issuer", "not_after", "sans"];
+
+/// One difference between the baseline and the current result.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    /// "ip" | "certificate" | "status" | "stage" | "latency"
+    pub kind: String,
+    /// What changed, e.g. "ipv4", "certificate.issuer", "http.latency_ms".
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+    /// Relative latency change in percent (latency changes only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetDiff {
+    pub target: String,
+    pub status: String, // "unchanged" | "changed" | "added" | "removed"
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffReport {
+    /// Slowdown (in percent) above which a latency counts as a regression.
+    pub threshold_percent: f64,
+    pub targets: Vec<TargetDiff>,
+}
+
+impl DiffReport {
+    /// True if any target changed, appeared or disappeared.
+    pub fn has_changes(&self) -> bool {
+        self.targets.iter().any(|t| t.status != "unchanged")
+    }
+}
+
+/// Read results saved with `--json` (one object or an array) or `--output ndjson`.
+pub fn load(path: &Path) -> Result<Vec<Value>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let parse_error = |e: serde_json::Error| format!("{} is not a netprobe JSON result: {}", path.display(), e);
+
+    let results = match serde_json::from_str::<Value>(&text) {
+        Ok(Value::Array(results)) => results,
+        Ok(result) => vec![result],
+        // Not a single document: try one object per line.
+        Err(_) => text.lines().filter(|line| !line.trim().is_empty()).map(serde_json::from_str).collect::<Result<_, _>>().map_err(parse_error)?,
+    };
+    if let Some(bad) = results.iter().find(|r| r.get("target").and_then(Value::as_str).is_none()) {
+        return Err(format!("{} is not a netprobe JSON result (no \"target\" in {})", path.display(), truncate(&bad.to_string())));
+    }
+    Ok(results)
+}
+
+/// Match results by target and list what changed.
+pub fn diff(baseline: &[Value], current: &[Value], threshold_percent: f64) -> DiffReport {
+    let target = |result: &Value| result["target"].as_str().unwrap_or_default().to_string();
+    let mut targets = Vec::new();
+
+    for after in current {
+        let name = target(after);
+        // With repeated runs of a target, the last one is the reference.
+        match baseline.iter().rev().find(|before| target(before) == name) {
+            Some(before) => {
+                let changes = compare(before, after, threshold_percent);
+                let status = if changes.is_empty() { "unchanged" } else { "changed" };
+                targets.push(TargetDiff { target: name, status: status.to_string(), changes });
+            },
+            None => targets.push(TargetDiff { target: name, status: "added".to_string(), changes: Vec::new() }),
+        }
+    }
+    let seen: BTreeSet<String> = current.iter().map(target).collect();
+    let removed: BTreeSet<String> = baseline.iter().map(target).filter(|name| !seen.contains(name)).collect();
+    targets.extend(removed.into_iter().map(|name| TargetDiff { target: name, status: "removed".to_string(), changes: Vec::new() }));
+
+    DiffReport { threshold_percent, targets }
+}
+
+/// Differences between two results of the same target.
+pub fn compare(before: &Value, after: &Value, threshold_percent: f64) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut push = |kind: &str, field: String, before: &Value, after: &Value| {
+        changes.push(Change { kind: kind.to_string(), field, before: before.clone(), after: after.clone(), change_percent: None });
+    };
+
+    // Address sets are compared as sets: DNS round-robin reorders them constantly.
+    for family in ["ipv4", "ipv6"] {
+        let pointer = format!("/dns/{}", family);
+        let (old, new) = (address_set(before.pointer(&pointer)), address_set(after.pointer(&pointer)));
+        if old != new {
+            push("ip", family.to_string(), &Value::from(old.into_iter().collect::<Vec<_>>()), &Value::from(new.into_iter().collect::<Vec<_>>()));
+        }
+    }
+
+    for field in CERTIFICATE_FIELDS {
+        let pointer = format!("/tls/certificate/{}", field);
+        let (old, new) = (before.pointer(&pointer).unwrap_or(&Value::Null), after.pointer(&pointer).unwrap_or(&Value::Null));
+        if old != new {
+            push("certificate", format!("certificate.{}", field), old, new);
+        }
+    }
+
+    let (old, new) = (before.pointer("/http/status_code").unwrap_or(&Value::Null), after.pointer("/http/status_code").unwrap_or(&Value::Null));
+    if old != new {
+        push("status", "http.status_code".to_string(), old, new);
+    }
+
+    for stage in ["dns", "tcp", "tls"] {
+        let pointer = format!("/{}/status", stage);
+        let (old, new) = (before.pointer(&pointer).unwrap_or(&Value::Null), after.pointer(&pointer).unwrap_or(&Value::Null));
+        if old != new {
+            push("stage", format!("{}.status", stage), old, new);
+        }
+    }
+
+    for (stage, pointer) in LATENCIES {
+        let (Some(old), Some(new)) = (before.pointer(pointer).and_then(Value::as_f64), after.pointer(pointer).and_then(Value::as_f64)) else { continue };
+        if old <= 0.0 || new - old < MIN_REGRESSION_MS {
+            continue;
+        }
+        let percent = (new - old) * 100.0 / old;
+        if percent > threshold_percent {
+            changes.push(Change {
+                kind: "latency".to_string(),
+                field: format!("{}.latency_ms", stage),
+                before: Value::from(old),
+                after: Value::from(new),
+                change_percent: Some(percent),
+            });
+        }
+    }
+
+    changes
+}
+
+fn address_set(value: Option<&Value>) -> BTreeSet<String> {
+    value.and_then(Value::as_array).map(|ips| ips.iter().filter_map(Value::as_str).map(String::from).collect()).unwrap_or_default()
+}
+
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(60) {
+        Some((i, _)) => format!("{}...", &text[..i]),
+        None => text.to_string(),
+    }
+}
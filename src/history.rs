@@ -0,0 +1,208 @@
+//! Run history: every probe result persisted to a local SQLite database (`--history`).
+
+use crate::probe::{self, ProbeResult};
+use crate::stats::{Summary, SummarySnapshot};
+use chrono::{DateTime, Local, TimeZone};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where `--history` stores results when no path is given (under `$HOME`).
+pub const DEFAULT_PATH: &str = "~/.netprobe/history.db";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id          INTEGER PRIMARY KEY,
+        target      TEXT NOT NULL,
+        timestamp   TEXT NOT NULL,
+        unix_ms     INTEGER NOT NULL,
+        success     INTEGER NOT NULL,
+        exit_code   INTEGER NOT NULL,
+        ip          TEXT,
+        http_status INTEGER,
+        dns_ms      REAL,
+        tcp_ms      REAL,
+        tls_ms      REAL,
+        http_ms     REAL,
+        total_ms    REAL NOT NULL,
+        error       TEXT,
+        result      TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_by_target ON runs (target, unix_ms);
+";
+
+/// One stored run, as listed by `netprobe history`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub success: bool,
+    pub exit_code: i32,
+    pub ip: Option<String>,
+    pub http_status: Option<u16>,
+    pub dns_ms: Option<f64>,
+    pub tcp_ms: Option<f64>,
+    pub tls_ms: Option<f64>,
+    pub http_ms: Option<f64>,
+    pub total_ms: f64,
+    pub error: Option<String>,
+}
+
+/// Success rate and latency of the runs that started within one period.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendBucket {
+    pub start: String,
+    #[serde(flatten)]
+    pub summary: SummarySnapshot,
+}
+
+/// Everything `netprobe history` reports for one target.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryReport {
+    pub target: String,
+    pub since: String,
+    /// Over all runs in the window (latency is the whole-probe duration).
+    pub summary: SummarySnapshot,
+    /// Hourly buckets for windows up to two days, daily ones beyond.
+    pub bucket: String,
+    pub trend: Vec<TrendBucket>,
+    /// Oldest first.
+    pub runs: Vec<HistoryEntry>,
+}
+
+pub struct History {
+    db: Connection,
+    path: PathBuf,
+}
+
+impl std::fmt::Debug for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("History").field("path", &self.path).finish()
+    }
+}
+
+impl History {
+    /// Open the database at `path` (`~` expands to `$HOME`), creating it and its directory if needed.
+    pub fn open(path: &Path) -> Result<History, String> {
+        let path = expand_home(path);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        let db = Connection::open(&path).map_err(|e| format!("Cannot open history {}: {}", path.display(), e))?;
+        // Several netprobe processes may write at once; wait for the lock instead of failing.
+        db.busy_timeout(Duration::from_secs(5)).map_err(|e| e.to_string())?;
+        db.execute_batch(SCHEMA).map_err(|e| format!("Cannot initialise history {}: {}", path.display(), e))?;
+        Ok(History { db, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one probe result.
+    pub fn record(&self, result: &ProbeResult) -> Result<(), String> {
+        let unix_ms = DateTime::parse_from_rfc3339(&result.timestamp).map(|t| t.timestamp_millis()).unwrap_or_else(|_| Local::now().timestamp_millis());
+        let error = [&result.dns.error, &result.tcp.error, &result.tls.error, &result.http.error].into_iter().find_map(|e| e.as_deref());
+        let json = serde_json::to_string(result).map_err(|e| e.to_string())?;
+
+        self.db
+            .execute(
+                "INSERT INTO runs (target, timestamp, unix_ms, success, exit_code, ip, http_status, dns_ms, tcp_ms, tls_ms, http_ms, total_ms, error, result)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                params![
+                    result.target,
+                    result.timestamp,
+                    unix_ms,
+                    result.is_success(),
+                    result.exit_code(),
+                    result.dns.ip,
+                    result.http.status_code,
+                    result.dns.latency_ms,
+                    result.tcp.latency_ms,
+                    result.tls.latency_ms,
+                    result.http.latency_ms,
+                    result.duration_ms,
+                    error,
+                    json,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Runs of `target` (normalized like a probe target) that started within `since` of now.
+    pub fn query(&self, target: &str, since: Duration) -> Result<HistoryReport, String> {
+        let target = probe::normalize_target(target);
+        let now = Local::now();
+        let cutoff = now - chrono::Duration::from_std(since).map_err(|e| e.to_string())?;
+
+        let mut select = self
+            .db
+            .prepare(
+                "SELECT timestamp, unix_ms, success, exit_code, ip, http_status, dns_ms, tcp_ms, tls_ms, http_ms, total_ms, error
+                 FROM runs WHERE target = ?1 AND unix_ms >= ?2 ORDER BY unix_ms",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = select
+            .query_map(params![target, cutoff.timestamp_millis()], |row| {
+                let entry = HistoryEntry {
+                    timestamp: row.get(0)?,
+                    success: row.get(2)?,
+                    exit_code: row.get(3)?,
+                    ip: row.get(4)?,
+                    http_status: row.get(5)?,
+                    dns_ms: row.get(6)?,
+                    tcp_ms: row.get(7)?,
+                    tls_ms: row.get(8)?,
+                    http_ms: row.get(9)?,
+                    total_ms: row.get(10)?,
+                    error: row.get(11)?,
+                };
+                Ok((row.get::<_, i64>(1)?, entry))
+            })
+            .map_err(|e| e.to_string())?;
+        let (times, runs): (Vec<i64>, Vec<HistoryEntry>) = rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?.into_iter().unzip();
+
+        // Hourly trend for a couple of days, daily beyond that.
+        let (bucket, bucket_ms) = if since <= Duration::from_secs(48 * 3600) { ("hour", 3_600_000) } else { ("day", 86_400_000) };
+        let mut summary = Summary::default();
+        let mut trend: Vec<(i64, Summary)> = Vec::new();
+        for (run, &unix_ms) in runs.iter().zip(&times) {
+            summary.record(run.success, run.total_ms);
+            // Buckets are aligned on local time, so days start at local midnight.
+            let offset_ms = Local.timestamp_millis_opt(unix_ms).single().map_or(0, |t| t.offset().local_minus_utc() as i64 * 1000);
+            let start = (unix_ms + offset_ms).div_euclid(bucket_ms) * bucket_ms - offset_ms;
+            match trend.last_mut() {
+                Some((last, bucket)) if *last == start => bucket.record(run.success, run.total_ms),
+                _ => {
+                    let mut bucket = Summary::default();
+                    bucket.record(run.success, run.total_ms);
+                    trend.push((start, bucket));
+                },
+            }
+        }
+
+        Ok(HistoryReport {
+            target,
+            since: cutoff.to_rfc3339(),
+            summary: summary.snapshot(),
+            bucket: bucket.to_string(),
+            trend: trend
+                .into_iter()
+                .map(|(start, bucket)| TrendBucket {
+                    start: Local.timestamp_millis_opt(start).single().map(|t| t.to_rfc3339()).unwrap_or_default(),
+                    summary: bucket.snapshot(),
+                })
+                .collect(),
+            runs,
+        })
+    }
+}
+
+/// Replace a leading `~` with `$HOME`.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
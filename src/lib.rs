@@ -0,0 +1,38 @@
+//! NetProbe: a surgical network diagnostic library.
+//!
+//! The binary is a thin CLI over this crate. Other Rust programs can embed the
+//! same layered analysis (DNS -> TCP -> TLS -> HTTP) without shelling out:
+//!
+//! ```no_run
+//! use netprobe::{ProbeOptions, Prober};
+//!
+//! # async fn demo() -> Result<(), netprobe::ProbeError> {
+//! let result = Prober::run("github.com", &ProbeOptions::default()).await?;
+//! println!("HTTP status: {:?}", result.http.status_code);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod audit;
+pub mod config;
+pub mod dashboard;
+pub mod diff;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod logging;
+pub mod mail;
+pub mod mtr;
+pub mod mtu;
+pub mod output;
+pub mod probe;
+pub mod report;
+pub mod resolver;
+pub mod schema;
+pub mod stats;
+pub mod sweep;
+pub mod trace;
+pub mod whois;
+mod pkcs12;
+mod x509;
+
+pub use probe::{ProbeError, ProbeOptions, ProbeResult, Prober};
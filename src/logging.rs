@@ -0,0 +1,35 @@
+//! Internal events on stderr (`--log-level`, `--log-format`), apart from the results on stdout.
+//!
+//! The probe stages emit `tracing` events (resolver chosen, connection attempts,
+//! retries...) inside a `probe` span naming the target. [`init`] installs a
+//! `tracing-subscriber` formatter that prints them as text or one JSON object per line:
+//!
+//! ```text
+//! 2026-10-16T09:12:03.512204Z DEBUG probe{target="https://example.com"}: netprobe::probe::dns: resolving host="example.com" resolver="system"
+//! ```
+
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `TIMESTAMP LEVEL span{fields}: target: message key=value...`
+    Text,
+    /// One object per line: timestamp, level, target, span, message and fields.
+    Json,
+}
+
+/// Print events up to `level` to stderr for the rest of the process.
+/// Events of other crates (hyper, h2) are only shown at `TRACE`.
+pub fn init(level: Level, format: LogFormat) {
+    let filter = match level {
+        Level::TRACE => EnvFilter::new("trace"),
+        level => EnvFilter::new(format!("netprobe={}", level)),
+    };
+    let builder = tracing_subscriber::fmt().with_writer(std::io::stderr).with_env_filter(filter).with_ansi(false);
+    // Only fails when a subscriber is already installed, which then keeps the events.
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().flatten_event(true).with_current_span(true).with_span_list(false).try_init(),
+    };
+}
@@ -0,0 +1,229 @@
+//! Mail deliverability check (`netprobe mail`): MX hosts, STARTTLS and the
+//! SPF / DKIM / DMARC policies of a domain.
+//!
+//! Every MX is resolved and greeted on port 25 (see [`crate::probe::smtp`]);
+//! the policies are TXT records: SPF at the domain, DMARC at `_dmarc.DOMAIN`
+//! and DKIM keys at `SELECTOR._domainkey.DOMAIN` for a list of usual selectors.
+
+use crate::probe::smtp::{self, SmtpResult};
+use crate::probe::ProbeOptions;
+use crate::resolver::wire::{self, RData};
+use crate::resolver::{ResolveError, Resolver};
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// Selectors tried when none are given: the defaults of common providers and signers.
+pub const DKIM_SELECTORS: [&str; 8] = ["default", "dkim", "mail", "google", "selector1", "selector2", "k1", "s1"];
+
+#[derive(Debug, Clone)]
+pub struct MailOptions {
+    /// Port of the SMTP servers (25 for MX hosts).
+    pub port: u16,
+    /// Name given in EHLO.
+    pub helo: String,
+    /// DKIM selectors to look up.
+    pub dkim_selectors: Vec<String>,
+    /// Timeouts and certificate checks of the SMTP sessions.
+    pub probe: ProbeOptions,
+}
+
+impl Default for MailOptions {
+    fn default() -> Self {
+        MailOptions { port: 25, helo: "localhost".to_string(), dkim_selectors: DKIM_SELECTORS.iter().map(|s| s.to_string()).collect(), probe: ProbeOptions::default() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MailResult {
+    pub domain: String,
+    pub timestamp: String,
+    pub duration_ms: f64,
+    /// MX hosts by preference, lowest first.
+    pub mx: Vec<MxResult>,
+    /// Why there are no MX hosts to probe: lookup failure, none published, or a null MX.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mx_error: Option<String>,
+    pub spf: PolicyRecord,
+    pub dmarc: PolicyRecord,
+    /// DKIM keys found, one per selector that has one.
+    pub dkim: Vec<DkimKey>,
+    pub dkim_selectors_checked: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MxResult {
+    pub preference: u16,
+    pub exchange: String,
+    pub addresses: Vec<String>,
+    /// Session with the first address; `None` when the name does not resolve.
+    pub smtp: Option<SmtpResult>,
+    pub error: Option<String>,
+}
+
+/// An SPF or DMARC TXT record.
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyRecord {
+    pub status: String, // "ok" | "missing" | "invalid" | "error"
+    pub record: Option<String>,
+    /// DMARC `p=` tag: "none" | "quarantine" | "reject".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DkimKey {
+    pub selector: String,
+    pub record: String,
+}
+
+impl MailResult {
+    /// True when an MX accepts mail over verified STARTTLS and SPF and DMARC are published.
+    pub fn is_healthy(&self) -> bool {
+        let secure = self.mx.iter().any(|mx| mx.smtp.as_ref().is_some_and(|smtp| smtp.status == "ok" && smtp.tls.as_ref().is_some_and(|tls| tls.status != "error")));
+        secure && self.spf.status == "ok" && self.dmarc.status == "ok"
+    }
+}
+
+/// Run every check on `domain`, querying `resolver`.
+pub async fn check(domain: &str, resolver: &Resolver, options: &MailOptions) -> MailResult {
+    let started = Instant::now();
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    let dmarc_name = format!("_dmarc.{}", domain);
+    let selectors = options.dkim_selectors.iter().map(|selector| dkim_key(resolver, selector, &domain));
+    let (mx, spf, dmarc, dkim) = tokio::join!(
+        mx_hosts(resolver, &domain, options),
+        txt_policy(resolver, &domain, "v=spf1"),
+        txt_policy(resolver, &dmarc_name, "v=DMARC1"),
+        join_all(selectors)
+    );
+    let (mx, mx_error) = match mx {
+        Ok(mx) => (mx, None),
+        Err(e) => (Vec::new(), Some(e)),
+    };
+    let dmarc = PolicyRecord { policy: dmarc.record.as_deref().and_then(|record| tag(record, "p")), ..dmarc };
+
+    MailResult {
+        domain,
+        timestamp: chrono::Local::now().to_rfc3339(),
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        mx,
+        mx_error,
+        spf,
+        dmarc,
+        dkim: dkim.into_iter().flatten().collect(),
+        dkim_selectors_checked: options.dkim_selectors.clone(),
+    }
+}
+
+/// The MX hosts of `domain`, each resolved and greeted concurrently.
+async fn mx_hosts(resolver: &Resolver, domain: &str, options: &MailOptions) -> Result<Vec<MxResult>, String> {
+    let response = resolver.query(domain, wire::TYPE_MX).await.map_err(|e| format!("MX lookup failed: {}", e))?;
+    match response.message.rcode {
+        wire::RCODE_NOERROR => {},
+        wire::RCODE_NXDOMAIN => return Err("MX lookup failed: the domain does not exist".to_string()),
+        rcode => return Err(format!("MX lookup failed: server answered {}", wire::rcode_name(rcode))),
+    }
+    let mut hosts: Vec<(u16, String)> = response
+        .message
+        .answers
+        .iter()
+        .filter_map(|record| match &record.data {
+            RData::Mx { preference, exchange } => Some((*preference, exchange.trim_end_matches('.').to_ascii_lowercase())),
+            _ => None,
+        })
+        .collect();
+    hosts.sort();
+    match hosts.as_slice() {
+        [] => return Err("No MX records: senders fall back to the domain's own address".to_string()),
+        // RFC 7505: a single "0 ." record declares that the domain accepts no mail.
+        [(_, exchange)] if exchange.is_empty() => return Err("Null MX: the domain accepts no mail".to_string()),
+        _ => {},
+    }
+    Ok(join_all(hosts.into_iter().map(|(preference, exchange)| mx_host(resolver, preference, exchange, options))).await)
+}
+
+async fn mx_host(resolver: &Resolver, preference: u16, exchange: String, options: &MailOptions) -> MxResult {
+    let mut result = MxResult { preference, exchange, addresses: Vec::new(), smtp: None, error: None };
+    let ips = match resolver.lookup_ip(&result.exchange, options.probe.ip_family).await {
+        Ok(lookup) => lookup.ips,
+        Err(e) => {
+            result.error = Some(format!("Cannot resolve {}: {}", result.exchange, e));
+            return result;
+        },
+    };
+    result.addresses = ips.iter().map(|ip| ip.to_string()).collect();
+    let smtp = smtp::probe(SocketAddr::new(ips[0], options.port), &result.exchange, &options.helo, &options.probe).await;
+    result.error = smtp.error.clone();
+    result.smtp = Some(smtp);
+    result
+}
+
+/// The TXT record of `name` starting with `prefix` (case-insensitive); more than one is invalid.
+async fn txt_policy(resolver: &Resolver, name: &str, prefix: &str) -> PolicyRecord {
+    let mut result = PolicyRecord { status: "missing".to_string(), record: None, policy: None, error: None };
+    let records = match txt_records(resolver, name).await {
+        Ok(records) => records,
+        Err(ResolveError::NxDomain) => return result,
+        Err(e) => {
+            result.status = "error".to_string();
+            result.error = Some(e.to_string());
+            return result;
+        },
+    };
+    let matching: Vec<String> = records.into_iter().filter(|record| starts_with_tag(record, prefix)).collect();
+    match matching.as_slice() {
+        [] => {},
+        [record] => {
+            result.status = "ok".to_string();
+            result.record = Some(record.clone());
+        },
+        [first, ..] => {
+            result.status = "invalid".to_string();
+            result.record = Some(first.clone());
+            result.error = Some(format!("{} records published; receivers ignore them all", matching.len()));
+        },
+    }
+    result
+}
+
+async fn dkim_key(resolver: &Resolver, selector: &str, domain: &str) -> Option<DkimKey> {
+    let records = txt_records(resolver, &format!("{}._domainkey.{}", selector, domain)).await.ok()?;
+    // Keys published without the optional version tag still carry a `p=` tag.
+    let record = records.into_iter().find(|record| starts_with_tag(record, "v=DKIM1") || tag(record, "p").is_some())?;
+    Some(DkimKey { selector: selector.to_string(), record })
+}
+
+/// TXT records of `name`, the strings of each one joined as receivers do.
+async fn txt_records(resolver: &Resolver, name: &str) -> Result<Vec<String>, ResolveError> {
+    let response = resolver.query(name, wire::TYPE_TXT).await?;
+    match response.message.rcode {
+        wire::RCODE_NOERROR => {},
+        wire::RCODE_NXDOMAIN => return Err(ResolveError::NxDomain),
+        rcode => return Err(ResolveError::ServerError(rcode)),
+    }
+    Ok(response
+        .message
+        .answers
+        .iter()
+        .filter_map(|record| match &record.data {
+            RData::Txt(strings) => Some(strings.concat()),
+            _ => None,
+        })
+        .collect())
+}
+
+fn starts_with_tag(record: &str, prefix: &str) -> bool {
+    let record = record.trim_start();
+    record.get(..prefix.len()).is_some_and(|head| head.eq_ignore_ascii_case(prefix)) && record[prefix.len()..].chars().next().is_none_or(|c| c == ';' || c.is_whitespace())
+}
+
+/// Value of the `name=value` tag of a `;`-separated record.
+fn tag(record: &str, name: &str) -> Option<String> {
+    record.split(';').find_map(|part| {
+        let (key, value) = part.split_once('=')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+    })
+}
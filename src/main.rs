@@ -1,227 +1,2129 @@
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use colored::*;
-use serde::Serialize;
+use hyper::Method;
+use netprobe::stats::{Summary, SummarySnapshot};
+use netprobe::sweep::{self, SweepOptions};
+use netprobe::trace::{self, TraceOptions, TraceProtocol};
+use netprobe::mtr::{self, MtrOptions};
+use netprobe::mtu::{self, MtuOptions};
+use netprobe::probe::{exit, source, CaBundle, ClientIdentity, ClientSubnet, CookieJar, CtLogList, GeoDb, HttpAuth, HttpVersion, IpFamily, JsonPathCheck, LatencyLimits, PortRange, Proxy, ProxySettings, Regex, ResolveOverride, Service, SourceBinding, StatusPattern, UdpPayload, Upstream};
+#[cfg(feature = "history")]
+use netprobe::history::{self, History};
+use netprobe::dashboard::{Dashboard, Input};
+use netprobe::config::{Config, Value};
+use netprobe::audit::{self, AuditOptions};
+use netprobe::mail::MailOptions;
+use netprobe::whois::{self, WhoisOptions};
+use netprobe::output::Verbosity;
+use netprobe::resolver::{bench, propagation};
+use netprobe::resolver::ttl::{self, TtlWatch};
+use netprobe::{diff, logging, mail, output, probe, report, resolver, schema, ProbeOptions, ProbeResult, Prober};
 use std::collections::HashMap;
-use std::net::ToSocketAddrs;
-use std::time::{Duration, Instant};
-use url::Url;
+use std::io::IsTerminal;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-// --- JSON Data Structures ---
-// These structures ensure the JSON output is standardized and predictable.
+// --- CLI Arguments ---
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// One or more target URLs or IPs (e.g., google.com, 192.168.1.1)
+    targets: Vec<String>,
+
+    /// Read additional targets from a file, one per line ('-' for stdin)
+    #[arg(long, value_name = "FILE")]
+    targets_file: Option<String>,
+
+    /// Apply a named profile of the config file (targets, headers, checks, output...)
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Read defaults and profiles from FILE instead of ~/.config/netprobe/config.toml
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Output results in raw JSON format (ideal for scripting/pipelines); same as --output json
+    #[arg(long, short = 'j', conflicts_with = "output")]
+    json: bool,
+
+    /// Output format: human, json, ndjson (one compact object per line), csv, har (HTTP Archive) or junit (XML)
+    #[arg(long, short = 'o', value_enum, value_name = "FORMAT")]
+    output: Option<OutputFormat>,
+
+    /// Print the JSON Schema of the --json result and exit
+    #[arg(long)]
+    schema: bool,
+
+    /// Only print one status line per target (the exit code tells the rest)
+    #[arg(long, short = 'q', conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Show DNS answers, the TLS offer and request/response headers; -vv adds raw records and the certificate chain
+    #[arg(long, short = 'v', action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Print internal events (resolver chosen, connection attempts, retries) to stderr, up to this level
+    #[arg(long, value_enum, value_name = "LEVEL", default_value_t = LogLevel::Off)]
+    log_level: LogLevel,
+
+    /// Format of the --log-level events: text, or json with one object per line
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Timeout of each stage, e.g. 750ms, 1.5s, 2m (see --dns-timeout, --connect-timeout, --tls-timeout, --http-timeout)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "5s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Give up on the DNS lookup after this long (e.g. 500ms, 2s; default: --timeout)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    dns_timeout: Option<Duration>,
+
+    /// Give up on each TCP connect after this long (default: --timeout)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    connect_timeout: Option<Duration>,
+
+    /// Give up on the TLS handshake after this long (default: --timeout)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    tls_timeout: Option<Duration>,
+
+    /// Give up on the HTTP request, redirects included, after this long (default: --timeout)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    http_timeout: Option<Duration>,
+
+    /// Follow HTTP redirects (3xx)
+    #[arg(long, short = 'f', default_value_t = false)]
+    follow_redirects: bool,
+
+    /// HTTP method used for the request stage
+    #[arg(long, short = 'X', value_enum, default_value_t = HttpMethod::Head)]
+    method: HttpMethod,
+
+    /// Only offer HTTP/1.1 (no HTTP/2 over ALPN)
+    #[arg(long, conflicts_with = "http2_prior_knowledge")]
+    http1_only: bool,
+
+    /// Speak HTTP/2 only, without upgrade on plain HTTP (h2c)
+    #[arg(long)]
+    http2_prior_knowledge: bool,
+
+    /// Download the full body with GET and report its size and transfer speed
+    #[arg(long)]
+    download: bool,
+
+    /// Request body to send (e.g., '{"x":1}')
+    #[arg(long, short = 'd', value_name = "DATA", conflicts_with = "body_file")]
+    body: Option<String>,
+
+    /// Read the request body from a file ('-' for stdin)
+    #[arg(long, value_name = "FILE")]
+    body_file: Option<String>,
+
+    /// Content-Type of the request body (default: guessed, JSON or text)
+    #[arg(long, value_name = "TYPE")]
+    content_type: Option<String>,
+
+    /// Extra request header, e.g. -H "Authorization: Bearer x" (repeatable)
+    #[arg(long = "header", short = 'H', value_name = "NAME: VALUE", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// HTTP Basic credentials, e.g. --auth-basic admin:secret (also MQTT and LDAP credentials)
+    #[arg(long, value_name = "USER:PASS", value_parser = parse_credentials, conflicts_with_all = ["auth_bearer", "auth_digest"])]
+    auth_basic: Option<(String, String)>,
+
+    /// Bearer token sent as 'Authorization: Bearer TOKEN'
+    #[arg(long, value_name = "TOKEN", conflicts_with = "auth_digest")]
+    auth_bearer: Option<String>,
+
+    /// HTTP Digest credentials, answering the server's 401 challenge (MD5, SHA-256)
+    #[arg(long, value_name = "USER:PASS", value_parser = parse_credentials)]
+    auth_digest: Option<(String, String)>,
+
+    /// Cookie sent to the target host, e.g. --cookie "session=abc; lang=en" (repeatable)
+    #[arg(long = "cookie", value_name = "NAME=VALUE", value_delimiter = ';', value_parser = parse_cookie)]
+    cookies: Vec<(String, String)>,
+
+    /// Load cookies from a Netscape cookie file (as written by curl -c)
+    #[arg(long, value_name = "FILE")]
+    cookie_file: Option<PathBuf>,
+
+    /// Only record matching response headers: names, 'x-*' prefixes, '!name' to exclude (comma separated)
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',', conflicts_with = "no_headers")]
+    header_filter: Vec<String>,
+
+    /// Do not record response headers
+    #[arg(long)]
+    no_headers: bool,
+
+    /// Fail (exit 7) unless the HTTP status matches, e.g. 200 or 2xx (comma separated)
+    #[arg(long, value_name = "STATUS", value_delimiter = ',')]
+    expect_status: Vec<StatusPattern>,
+
+    /// Fail (exit 7) unless the response body contains this text (repeatable; implies GET)
+    #[arg(long, value_name = "TEXT")]
+    expect_body_contains: Vec<String>,
+
+    /// Fail (exit 7) unless the response body matches this regex (repeatable; implies GET)
+    #[arg(long, value_name = "REGEX", value_parser = probe::check::parse_regex)]
+    expect_body_regex: Vec<Regex>,
+
+    /// Fail (exit 7) unless the JSON body satisfies e.g. '$.status == "ok"' (repeatable; implies GET)
+    #[arg(long, value_name = "EXPR")]
+    expect_json_path: Vec<JsonPathCheck>,
+
+    /// Mark the run degraded (exit 9) if the whole probe takes longer (e.g. 500ms, 2s)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_latency: Option<Duration>,
+
+    /// Latency budget of the DNS stage
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_dns: Option<Duration>,
+
+    /// Latency budget of the TCP handshake
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_tcp: Option<Duration>,
+
+    /// Latency budget of the TLS handshake
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_tls: Option<Duration>,
+
+    /// Latency budget of the HTTP request
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_http: Option<Duration>,
+
+    /// Retry a failed DNS, TCP or HTTP stage up to N times
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Pause before the first retry, doubled after each one (e.g. 500ms, 2s)
+    #[arg(long, value_name = "DURATION", default_value = "1s", value_parser = parse_duration)]
+    retry_backoff: Duration,
+
+    /// Exit non-zero if the TLS certificate expires within N days
+    #[arg(long, value_name = "N")]
+    cert_warn_days: Option<i64>,
+
+    /// Show every certificate of the presented chain (key, signature, SHA-256 fingerprint)
+    #[arg(long)]
+    cert_chain: bool,
+
+    /// Like --cert-chain, and include each certificate PEM-encoded
+    #[arg(long)]
+    cert_pem: bool,
+
+    /// Check the certificate's revocation status (OCSP staple, OCSP responder, then CRL)
+    #[arg(long)]
+    check_revocation: bool,
+
+    /// Reconnect to test TLS session resumption and 0-RTT support
+    #[arg(long)]
+    check_resumption: bool,
+
+    /// Require Certificate Transparency: list the certificate's SCTs, fail if there is none
+    #[arg(long)]
+    check_ct: bool,
+
+    /// Check that the host's CAA records allow the CA that issued the certificate (warning otherwise)
+    #[arg(long)]
+    check_caa: bool,
+
+    /// Verify SCT signatures against this CT log list (log_list.json); implies --check-ct
+    #[arg(long, value_name = "FILE")]
+    ct_log_list: Option<PathBuf>,
+
+    /// Send ICMP echo requests to the resolved IP before the TCP handshake
+    #[arg(long)]
+    ping: bool,
+
+    /// Number of ICMP echo requests sent with --ping
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    ping_count: u32,
+
+    /// Query this DNS server instead of the system resolver (IP[:port], repeatable)
+    #[arg(long = "dns-server", value_name = "IP[:PORT]", value_parser = resolver::parse_server)]
+    dns_servers: Vec<SocketAddr>,
+
+    /// Resolve over DNS-over-HTTPS through this endpoint (repeatable)
+    #[arg(long, value_name = "URL", value_parser = resolver::parse_doh_url)]
+    doh: Vec<Upstream>,
+
+    /// Resolve over DNS-over-TLS through this server (IP[:PORT][#NAME], repeatable)
+    #[arg(long, value_name = "IP[:PORT]", value_parser = resolver::parse_dot_server)]
+    dot: Vec<Upstream>,
+
+    /// Resolve over DNS-over-QUIC through this server (quic://HOST[:PORT], repeatable)
+    #[arg(long, value_name = "URL", value_parser = resolver::parse_doq_url)]
+    doq: Vec<Upstream>,
+
+    /// Send this EDNS Client Subnet with DNS queries, to get the answer GeoDNS gives that network (e.g. 203.0.113.0/24)
+    #[arg(long, value_name = "SUBNET")]
+    ecs: Option<ClientSubnet>,
+
+    /// Use this IP for HOST:PORT instead of DNS, keeping Host and SNI (repeatable)
+    #[arg(long, value_name = "HOST:PORT:IP")]
+    resolve: Vec<ResolveOverride>,
+
+    /// TLS server name (SNI) to send and verify instead of the URL host
+    #[arg(long, value_name = "NAME")]
+    sni: Option<String>,
+
+    /// Trust the CA certificates of this PEM file instead of the bundled roots
+    #[arg(long, value_name = "FILE")]
+    cacert: Option<PathBuf>,
+
+    /// Do not verify the server certificate (the TLS step still reports the verdict)
+    #[arg(long, short = 'k')]
+    insecure: bool,
+
+    /// Client certificate for mutual TLS: PEM (chain, optionally with the key) or PKCS#12
+    #[arg(long, value_name = "FILE")]
+    cert: Option<PathBuf>,
+
+    /// Private key of --cert, if not in the same PEM file
+    #[arg(long, value_name = "FILE", requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Password of a PKCS#12 --cert
+    #[arg(long, value_name = "PASSWORD", requires = "cert")]
+    cert_password: Option<String>,
+
+    /// Send the HTTP request through this proxy (http://, https://, socks5://, socks5h://);
+    /// defaults to $HTTPS_PROXY / $HTTP_PROXY / $ALL_PROXY, minus $NO_PROXY
+    #[arg(long, value_name = "URL")]
+    proxy: Option<Proxy>,
+
+    /// Look up the reverse DNS (PTR) name of the resolved IP
+    #[arg(long)]
+    rdns: bool,
+
+    /// Validate DNSSEC for the target's address records, from the root trust anchor down;
+    /// a bogus answer fails the DNS stage (exit 3)
+    #[arg(long)]
+    dnssec: bool,
+
+    /// Resolve the target iteratively from the root servers, showing each referral hop (like dig +trace)
+    #[arg(long)]
+    dns_trace: bool,
+
+    /// Add country, city and ASN of the resolved IP (from local GeoLite2 databases)
+    #[arg(long)]
+    geo: bool,
+
+    /// MaxMind DB file used by --geo (repeatable, e.g. City + ASN); implies --geo
+    #[arg(long, value_name = "FILE")]
+    geo_db: Vec<PathBuf>,
+
+    /// Only use IPv4 addresses
+    #[arg(short = '4', conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only use IPv6 addresses
+    #[arg(short = '6')]
+    ipv6: bool,
+
+    /// Local address to send the probes from (on a multi-homed host)
+    #[arg(long, value_name = "IP")]
+    source_ip: Option<IpAddr>,
+
+    /// Network interface to send the probes out of, whatever the routes say (Linux only)
+    #[arg(long, value_name = "NAME")]
+    interface: Option<String>,
+
+    /// Mark the probe's packets with this DSCP: ef, afXY, csN, le, va, default or 0-63
+    #[arg(long, value_name = "CLASS", value_parser = source::parse_dscp)]
+    dscp: Option<u8>,
+
+    /// Mark the probe's packets with this raw TOS / traffic class byte (0-255 or 0x00-0xff)
+    #[arg(long, value_name = "BYTE", value_parser = source::parse_tos, conflicts_with = "dscp")]
+    tos: Option<u8>,
+
+    /// Probe over IPv4 and IPv6 independently and compare them side by side
+    #[arg(long, conflicts_with_all = ["ipv4", "ipv6"])]
+    compare_stacks: bool,
+
+    /// TCP-probe every resolved address and show which ones accept connections
+    #[arg(long)]
+    all_ips: bool,
+
+    /// Race the resolved addresses like a browser (RFC 8305, IPv6 first) and report which family won and by how much
+    #[arg(long)]
+    happy_eyeballs: bool,
+
+    /// Run the TCP, TLS and HTTP stages and --ping side by side: faster, but the connections overlap and skew each other's timings
+    #[arg(long)]
+    parallel: bool,
+
+    /// Identify the CDN / WAF in front of the target (headers, CNAME chain, IP ranges)
+    #[arg(long, conflicts_with_all = ["udp", "payload_hex"])]
+    detect_cdn: bool,
+
+    /// Grade the response's security headers (HSTS, CSP, framing, cookies...)
+    #[arg(long, conflicts_with_all = ["udp", "payload_hex"])]
+    security_audit: bool,
+
+    /// Send the request twice on the same connection and compare cold vs warm latency
+    #[arg(long, conflicts_with_all = ["udp", "payload_hex"])]
+    warm: bool,
+
+    /// Check for HTTP/3: Alt-Svc advertisement, then the request over QUIC, timed against the HTTP stage
+    #[arg(long, conflicts_with_all = ["udp", "payload_hex"])]
+    http3: bool,
+
+    /// Send a gRPC health check (grpc.health.v1) instead of the HTTP request; SERVICE defaults to the whole server
+    #[arg(long, value_name = "SERVICE", num_args = 0..=1, default_missing_value = "", conflicts_with_all = ["udp", "payload_hex", "service", "http1_only"])]
+    grpc: Option<String>,
+
+    /// Perform a WebSocket upgrade instead of the HTTP request (implied by ws:// and wss:// targets)
+    #[arg(long, conflicts_with_all = ["udp", "payload_hex", "service", "grpc"])]
+    ws: bool,
+
+    /// Once upgraded, send a WebSocket ping and time the pong
+    #[arg(long, conflicts_with_all = ["udp", "payload_hex", "service", "grpc"])]
+    ws_ping: bool,
+
+    /// Probe over UDP instead of TCP/TLS/HTTP; the payload defaults to one that suits the port
+    #[arg(long, value_name = "PAYLOAD", value_enum, num_args = 0..=1, default_missing_value = "auto")]
+    udp: Option<UdpMode>,
+
+    /// Hex bytes to send as the UDP payload (implies --udp)
+    #[arg(long, value_name = "HEX", value_parser = parse_hex, conflicts_with = "udp")]
+    payload_hex: Option<HexBytes>,
+
+    /// Greet this service instead of sending HTTP (detected on ports 21, 22, 25, 110, 123, 143, 161, 389, 445, 465, 587, 636, 990, 993, 995, 1883, 3306, 3389, 5060, 5061, 5432, 6379, 8883, 9092 and 27017)
+    #[arg(long, value_name = "NAME", value_enum, conflicts_with_all = ["udp", "payload_hex"])]
+    service: Option<ServiceArg>,
+
+    /// On FTP servers, also try an anonymous login and report its reply
+    #[arg(long)]
+    ftp_anonymous: bool,
+
+    /// SNMP community of --service snmp and --udp snmp
+    #[arg(long, value_name = "NAME", default_value = "public")]
+    community: String,
+
+    /// Also check these ports on the resolved address (e.g., 22,80,443,8000-8100)
+    #[arg(long, value_name = "PORTS", value_delimiter = ',')]
+    ports: Vec<PortRange>,
+
+    /// Scan --ports with raw SYN packets (half-open) instead of full connections: much faster on large ranges, and the services never see a connection. Needs raw-socket privileges (Linux only)
+    #[arg(long, requires = "ports")]
+    syn: bool,
+
+    /// Connect N more times (default 5) to report connect jitter, and the kernel's RTT and retransmits (Linux)
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "5", value_parser = clap::value_parser!(u32).range(2..=100))]
+    tcp_quality: Option<u32>,
+
+    /// Read what the server sends first after connecting, up to BYTES (default 256)
+    #[arg(long, value_name = "BYTES", num_args = 0..=1, default_missing_value = "256", value_parser = clap::value_parser!(u32).range(1..=65536))]
+    banner: Option<u32>,
+
+    /// How long to wait for the banner to start
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+    banner_timeout: Duration,
+
+    /// Maximum number of targets probed at the same time
+    #[arg(long, short = 'c', visible_alias = "max-concurrency", default_value_t = 10)]
+    concurrency: usize,
+
+    /// Targets started per second over a target list or watch round, e.g. 50/s or 600/m (0 for no limit)
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    rate: Option<f64>,
+
+    /// Repeat the probe N times per target and report latency statistics per stage
+    #[arg(long, short = 'n', value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+
+    /// Save every result to a SQLite history database (default: ~/.netprobe/history.db)
+    #[cfg(feature = "history")]
+    #[arg(long, value_name = "DB", num_args = 0..=1, default_missing_value = history::DEFAULT_PATH)]
+    history: Option<PathBuf>,
+
+    /// Compare the results with an earlier --json run and report what changed
+    #[arg(long, value_name = "FILE", conflicts_with = "watch")]
+    baseline: Option<PathBuf>,
+
+    /// Latency growth (in percent) that --baseline reports as a regression
+    #[arg(long, value_name = "PERCENT", default_value_t = 20.0)]
+    diff_threshold: f64,
+
+    /// Also write a report for incident tickets: HTML (.html) or Markdown (.md), chosen by extension
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["watch", "tui"])]
+    report: Option<PathBuf>,
+
+    /// Re-run the probe forever and keep a rolling summary (Ctrl-C to stop)
+    #[arg(long, short = 'w')]
+    watch: bool,
+
+    /// Watch in a full-screen dashboard with latency sparklines, `q` to quit (implies --watch; needs a terminal)
+    #[arg(long, conflicts_with_all = ["json", "output", "baseline"])]
+    tui: bool,
+
+    /// Pause between runs in watch mode (e.g., 500ms, 10s, 1m)
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = parse_interval)]
+    interval: Duration,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Trace the network path to a target hop by hop
+    Trace(TraceArgs),
+    /// Probe every hop of the path continuously and show loss and latency per hop
+    Mtr(MtrArgs),
+    /// Find the largest packet that reaches a target unfragmented (path MTU)
+    Mtu(MtuArgs),
+    /// Query DNS records of any type (MX, TXT, NS, SOA, CAA...)
+    Dns(DnsArgs),
+    /// Find the hosts of a subnet that answer on the given TCP ports
+    Sweep(SweepArgs),
+    /// Check a domain's mail setup: MX hosts, STARTTLS, SPF, DKIM and DMARC
+    Mail(MailArgs),
+    /// Look up a domain's registration: registrar, creation and expiry dates, nameservers
+    Whois(WhoisArgs),
+    /// Query every nameserver of a zone directly: lame delegations, SOA serials, differing records
+    DnsAudit(DnsAuditArgs),
+    /// Time lookups through several resolvers over repeated rounds and rank them by latency
+    DnsBench(DnsBenchArgs),
+    /// Compare two saved JSON results and highlight what changed
+    Diff(DiffArgs),
+    /// Show past runs of a target saved with --history, with trend summaries
+    #[cfg(feature = "history")]
+    History(HistoryArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct SweepArgs {
+    /// CIDR block to sweep (e.g., 10.0.0.0/24)
+    network: String,
+
+    /// Ports to try on every host (e.g., 443 or 22,80,8000-8100)
+    #[arg(long, short = 'p', value_name = "PORTS", value_delimiter = ',', default_value = "22,80,443")]
+    ports: Vec<PortRange>,
+
+    /// Connect timeout per port (e.g. 300ms, 1s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "1s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Maximum number of hosts probed at the same time
+    #[arg(long, short = 'c', visible_alias = "max-concurrency", default_value_t = 64)]
+    concurrency: usize,
+
+    /// Hosts started per second, e.g. 100 or 50/s (0 for no limit)
+    #[arg(long, value_name = "RATE", default_value = "100", value_parser = parse_rate)]
+    rate: f64,
+
+    /// Output the sweep in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct MailArgs {
+    /// The mail domain (e.g., example.com)
+    domain: String,
+
+    /// DKIM selectors to look for, comma separated (default: common ones such as google, selector1, k1)
+    #[arg(long, value_name = "SELECTORS", value_delimiter = ',')]
+    dkim_selector: Vec<String>,
+
+    /// Name announced in EHLO
+    #[arg(long, value_name = "NAME", default_value = "localhost")]
+    helo: String,
+
+    /// SMTP port of the MX hosts
+    #[arg(long, short = 'p', default_value_t = 25)]
+    port: u16,
+
+    /// DNS server to query (IP[:port], repeatable); defaults to the system's nameservers
+    #[arg(long, short = 's', value_name = "IP[:PORT]", value_parser = resolver::parse_server)]
+    server: Vec<SocketAddr>,
+
+    /// Trust the CA certificates of this PEM file instead of the bundled roots
+    #[arg(long, value_name = "FILE")]
+    cacert: Option<PathBuf>,
+
+    /// Timeout of each DNS query, connection and SMTP reply (e.g. 10s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "10s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Output the check in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct WhoisArgs {
+    /// The domain (e.g., example.com)
+    domain: String,
+
+    /// Exit non-zero if the domain expires within N days
+    #[arg(long, value_name = "N")]
+    expiry_warn_days: Option<i64>,
+
+    /// RDAP base URL to query instead of the one IANA lists for the TLD
+    #[arg(long, value_name = "URL", conflicts_with = "whois_server")]
+    rdap_url: Option<String>,
+
+    /// Whois server to query (HOST[:PORT]) instead of RDAP and IANA's referral
+    #[arg(long, value_name = "HOST")]
+    whois_server: Option<String>,
+
+    /// Timeout of each RDAP request and whois exchange (e.g. 10s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "10s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Send the RDAP requests through this proxy (http://, https://, socks5://, socks5h://);
+    /// defaults to $HTTPS_PROXY / $HTTP_PROXY / $ALL_PROXY, minus $NO_PROXY
+    #[arg(long, value_name = "URL")]
+    proxy: Option<Proxy>,
+
+    /// Output the registration in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct DnsAuditArgs {
+    /// The zone (e.g., example.com)
+    domain: String,
+
+    /// Record types compared across the nameservers besides SOA and NS, comma separated
+    #[arg(long = "type", short = 'T', value_name = "TYPES", value_delimiter = ',', default_value = "A,AAAA,MX,TXT")]
+    types: Vec<String>,
+
+    /// DNS server used to find the nameservers (IP[:port], repeatable); defaults to the system's nameservers
+    #[arg(long, short = 's', value_name = "IP[:PORT]", value_parser = resolver::parse_server)]
+    server: Vec<SocketAddr>,
+
+    /// Only query the nameservers' IPv4 addresses
+    #[arg(short = '4', conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only query the nameservers' IPv6 addresses
+    #[arg(short = '6')]
+    ipv6: bool,
+
+    /// Timeout of each query (e.g. 750ms, 5s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "5s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Output the audit in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct DnsBenchArgs {
+    /// Names to look up (default: a list of popular domains)
+    names: Vec<String>,
+
+    /// Record type to query
+    #[arg(long = "type", short = 'T', value_name = "TYPE", default_value = "A")]
+    rtype: String,
+
+    /// DNS server to benchmark (IP[:port], repeatable); default: the system's nameservers and well-known public resolvers
+    #[arg(long, short = 's', value_name = "IP[:PORT]", value_parser = resolver::parse_server)]
+    server: Vec<SocketAddr>,
+
+    /// DNS-over-HTTPS endpoint to benchmark (repeatable)
+    #[arg(long, value_name = "URL", value_parser = resolver::parse_doh_url)]
+    doh: Vec<Upstream>,
+
+    /// DNS-over-TLS server to benchmark (IP[:PORT][#NAME], repeatable)
+    #[arg(long, value_name = "IP[:PORT]", value_parser = resolver::parse_dot_server)]
+    dot: Vec<Upstream>,
+
+    /// DNS-over-QUIC server to benchmark (quic://HOST[:PORT], repeatable)
+    #[arg(long, value_name = "URL", value_parser = resolver::parse_doq_url)]
+    doq: Vec<Upstream>,
+
+    /// Rounds over the whole name list
+    #[arg(long, short = 'n', value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+
+    /// Timeout of each query (e.g. 750ms, 5s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Output the ranking in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Earlier result (--json or --output ndjson output)
+    baseline: PathBuf,
+
+    /// Result to compare against the baseline
+    current: PathBuf,
+
+    /// Report latencies that grew by more than this many percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 20.0)]
+    threshold: f64,
+
+    /// Output the differences in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[cfg(feature = "history")]
+#[derive(clap::Args, Debug)]
+struct HistoryArgs {
+    /// The target as it was probed (e.g., example.com)
+    target: String,
+
+    /// How far back to look (e.g., 30m, 24h, 7d)
+    #[arg(long, short = 's', value_name = "DURATION", default_value = "24h", value_parser = parse_duration)]
+    since: Duration,
+
+    /// History database to read
+    #[arg(long, value_name = "FILE", default_value = history::DEFAULT_PATH)]
+    db: PathBuf,
+
+    /// Number of most recent runs to list
+    #[arg(long, short = 'l', default_value_t = 20)]
+    limit: usize,
+
+    /// Output the report in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct DnsArgs {
+    /// The name to look up (e.g., example.com)
+    name: String,
+
+    /// Record types to query, comma separated
+    #[arg(long = "type", short = 'T', value_name = "TYPES", value_delimiter = ',', default_value = "A,AAAA")]
+    types: Vec<String>,
+
+    /// DNS server to query (IP[:port], repeatable); defaults to the system's nameservers
+    #[arg(long, short = 's', value_name = "IP[:PORT]", value_parser = resolver::parse_server)]
+    server: Vec<SocketAddr>,
+
+    /// Query this DNS-over-HTTPS endpoint (repeatable)
+    #[arg(long, value_name = "URL", value_parser = resolver::parse_doh_url)]
+    doh: Vec<Upstream>,
+
+    /// Query this DNS-over-TLS server (IP[:PORT][#NAME], repeatable)
+    #[arg(long, value_name = "IP[:PORT]", value_parser = resolver::parse_dot_server)]
+    dot: Vec<Upstream>,
+
+    /// Query this DNS-over-QUIC server (quic://HOST[:PORT], repeatable)
+    #[arg(long, value_name = "URL", value_parser = resolver::parse_doq_url)]
+    doq: Vec<Upstream>,
+
+    /// Query timeout (e.g. 750ms, 5s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "5s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Send this EDNS Client Subnet, to see the answer GeoDNS gives that network (e.g. 203.0.113.0/24)
+    #[arg(long, value_name = "SUBNET", conflicts_with = "propagation")]
+    ecs: Option<ClientSubnet>,
+
+    /// Ask a set of public resolvers (or the --server ones) in parallel and show which still return an old answer
+    #[arg(long, conflicts_with_all = ["doh", "dot", "doq"])]
+    propagation: bool,
+
+    /// The new record value(s) with --propagation (repeatable); default: the answer most resolvers return
+    #[arg(long, value_name = "VALUE", requires = "propagation")]
+    expect: Vec<String>,
+
+    /// Query repeatedly and follow the TTL countdown of the first type, flagging a resolver that ignores TTLs
+    #[arg(long, conflicts_with = "propagation")]
+    ttl_watch: bool,
+
+    /// Time between two queries with --ttl-watch (e.g. 5s, 1m)
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_interval, requires = "ttl_watch")]
+    interval: Duration,
+
+    /// Number of queries with --ttl-watch
+    #[arg(long, short = 'c', default_value_t = 12, requires = "ttl_watch")]
+    count: u32,
+
+    /// Output the records in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct TraceArgs {
+    /// The target host or IP (e.g., google.com, 192.168.1.1)
+    target: String,
+
+    /// Packet type used for the probes
+    #[arg(long, short = 'P', value_enum, default_value_t = TraceMode::Icmp)]
+    protocol: TraceMode,
+
+    /// Maximum number of hops (TTL) to try
+    #[arg(long, short = 'm', default_value_t = 30)]
+    max_hops: u8,
+
+    /// Number of probes sent per hop
+    #[arg(long, short = 'q', default_value_t = 3)]
+    queries: u8,
+
+    /// How long to wait for each probe's reply (e.g. 500ms, 2s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Do not resolve hop addresses to hostnames
+    #[arg(long, short = 'n')]
+    no_resolve: bool,
+
+    /// Output the full path in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct MtrArgs {
+    /// The target host or IP (e.g., google.com, 192.168.1.1)
+    target: String,
+
+    /// Packet type used for the probes
+    #[arg(long, short = 'P', value_enum, default_value_t = TraceMode::Icmp)]
+    protocol: TraceMode,
+
+    /// Maximum number of hops (TTL) to try
+    #[arg(long, short = 'm', default_value_t = 30)]
+    max_hops: u8,
+
+    /// Number of rounds [default: until Ctrl-C on a terminal, 10 with --json or when piped]
+    #[arg(long, short = 'c', value_parser = clap::value_parser!(u32).range(1..))]
+    count: Option<u32>,
+
+    /// Time between two rounds (e.g. 500ms, 2s)
+    #[arg(long, short = 'i', value_name = "DURATION", default_value = "1s", value_parser = parse_interval)]
+    interval: Duration,
+
+    /// How long to wait for the answers of a round (e.g. 500ms, 2s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "1s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Do not resolve hop addresses to hostnames
+    #[arg(long, short = 'n')]
+    no_resolve: bool,
+
+    /// Output the final statistics of every hop in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct MtuArgs {
+    /// The target host or IP (e.g., google.com, 192.168.1.1)
+    target: String,
+
+    /// Packet type used for the probes
+    #[arg(long, short = 'P', value_enum, default_value_t = TraceMode::Icmp)]
+    protocol: TraceMode,
+
+    /// Largest packet size tried, IP header included
+    #[arg(long, value_name = "BYTES", default_value_t = 9000, value_parser = clap::value_parser!(u16).range(1280..))]
+    max_size: u16,
+
+    /// Probes sent at a size before it counts as dropped
+    #[arg(long, short = 'q', default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..))]
+    attempts: u8,
+
+    /// How long to wait for each probe's reply (e.g. 500ms, 2s)
+    #[arg(long, short = 't', value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Do not resolve the limiting router's address to a hostname
+    #[arg(long, short = 'n')]
+    no_resolve: bool,
+
+    /// Output the result and every probe in JSON format
+    #[arg(long, short = 'j')]
+    json: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[value(rename_all = "UPPER")]
+enum HttpMethod {
+    Head,
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+}
+
+impl From<HttpMethod> for Method {
+    fn from(method: HttpMethod) -> Self {
+        match method {
+            HttpMethod::Head => Method::HEAD,
+            HttpMethod::Get => Method::GET,
+            HttpMethod::Post => Method::POST,
+            HttpMethod::Put => Method::PUT,
+            HttpMethod::Patch => Method::PATCH,
+            HttpMethod::Delete => Method::DELETE,
+            HttpMethod::Options => Method::OPTIONS,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Human,
+    Json,
+    Ndjson,
+    Csv,
+    Har,
+    Junit,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum TraceMode {
+    Icmp,
+    Udp,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum UdpMode {
+    /// DNS on 53, NTP on 123, SNMP on 161, QUIC on 443, SIP on 5060, an empty datagram elsewhere
+    Auto,
+    Dns,
+    Ntp,
+    Quic,
+    /// A SIP OPTIONS request
+    Sip,
+    /// An SNMPv2c GET for sysDescr and sysUpTime (--community)
+    Snmp,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ServiceArg {
+    Ssh,
+    /// SMTP with STARTTLS
+    Smtp,
+    /// SMTP over implicit TLS
+    Smtps,
+    Imap,
+    Imaps,
+    Pop3,
+    Pop3s,
+    /// FTP banner, FEAT and AUTH TLS
+    Ftp,
+    /// FTP over implicit TLS
+    Ftps,
+    Postgres,
+    Mysql,
+    Redis,
+    Mongodb,
+    /// MQTT CONNECT/CONNACK (credentials from --auth-basic)
+    Mqtt,
+    /// MQTT over implicit TLS
+    Mqtts,
+    /// Kafka ApiVersions: the APIs the broker serves
+    Kafka,
+    /// LDAP bind and StartTLS (credentials from --auth-basic DN:PASSWORD)
+    Ldap,
+    /// LDAP over implicit TLS
+    Ldaps,
+    /// SIP OPTIONS over UDP: the response code
+    Sip,
+    /// SIP OPTIONS over TCP
+    SipTcp,
+    /// SIP OPTIONS over TLS
+    Sips,
+    /// RDP negotiation: the security protocol the server picks
+    Rdp,
+    /// SMB2 NEGOTIATE: dialect, signing and capabilities
+    Smb,
+    /// SNMPv2c GET over UDP: sysDescr and sysUpTime (--community)
+    Snmp,
+    /// SNTP over UDP: stratum, clock offset and delay
+    Ntp,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    /// Also shows the events of the HTTP stack
+    Trace,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[tokio::main]
+async fn main() {
+    let matches = with_config(Args::command().get_matches());
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let level = match args.log_level {
+        LogLevel::Off => None,
+        LogLevel::Error => Some(tracing::Level::ERROR),
+        LogLevel::Warn => Some(tracing::Level::WARN),
+        LogLevel::Info => Some(tracing::Level::INFO),
+        LogLevel::Debug => Some(tracing::Level::DEBUG),
+        LogLevel::Trace => Some(tracing::Level::TRACE),
+    };
+    if let Some(level) = level {
+        let format = match args.log_format {
+            LogFormat::Text => logging::LogFormat::Text,
+            LogFormat::Json => logging::LogFormat::Json,
+        };
+        logging::init(level, format);
+    }
+
+    match args.command {
+        Some(Command::Trace(trace_args)) => run_trace(trace_args).await,
+        Some(Command::Mtr(mtr_args)) => run_mtr(mtr_args).await,
+        Some(Command::Mtu(mtu_args)) => run_mtu(mtu_args).await,
+        Some(Command::Dns(dns_args)) => run_dns(dns_args).await,
+        Some(Command::Sweep(sweep_args)) => run_sweep(sweep_args).await,
+        Some(Command::Mail(mail_args)) => run_mail(mail_args).await,
+        Some(Command::Whois(whois_args)) => run_whois(whois_args).await,
+        Some(Command::DnsAudit(audit_args)) => run_dns_audit(audit_args).await,
+        Some(Command::DnsBench(bench_args)) => run_dns_bench(bench_args).await,
+        Some(Command::Diff(diff_args)) => run_diff(diff_args),
+        #[cfg(feature = "history")]
+        Some(Command::History(history_args)) => run_history(history_args),
+        None if args.schema => println!("{}", serde_json::to_string_pretty(&schema::probe_result()).expect("schema serializes")),
+        None => run_probe(args).await,
+    }
+}
+
+/// Layer `NETPROBE_*` variables and the config file under the command line.
+/// The command line wins over variables, which win over the config file; a
+/// setting also gives way to those it conflicts with (`--json` over `output`).
+fn with_config(matches: ArgMatches) -> ArgMatches {
+    if matches.subcommand().is_some() {
+        return matches;
+    }
+    let command = Args::command();
+    let explicit = matches.get_one::<PathBuf>("config").cloned().or_else(|| std::env::var_os("NETPROBE_CONFIG").map(PathBuf::from));
+    let profile = matches.get_one::<String>("profile").cloned().or_else(|| std::env::var("NETPROBE_PROFILE").ok());
+    let mut layers = vec![environment_settings(&command)];
+    if let Some(path) = explicit.clone().or_else(Config::default_path) {
+        // Without a profile, a missing default config file is not an error.
+        if explicit.is_some() || profile.is_some() || path.exists() {
+            let settings = Config::load(&path).and_then(|config| config.settings(profile.as_deref())).unwrap_or_else(|e| {
+                eprintln!("{} {}", "✖".red(), e);
+                std::process::exit(exit::USAGE);
+            });
+            let settings = settings.into_iter().map(|(key, value)| match find_setting(&command, &key) {
+                Some(arg) => (arg, value),
+                None => {
+                    eprintln!("{} {}: unknown setting '{}'", "✖".red(), path.display(), key);
+                    std::process::exit(exit::USAGE);
+                },
+            });
+            layers.push(settings.collect());
+        }
+    }
+
+    let conflicts = |a: &clap::Arg, b: &clap::Arg| command.get_arg_conflicts_with(a).iter().any(|c| c.get_id() == b.get_id());
+    // Arguments set by a higher layer.
+    let mut decided: Vec<&clap::Arg> = command.get_arguments().filter(|a| matches.value_source(a.get_id().as_str()) == Some(ValueSource::CommandLine)).collect();
+    let mut argv: Vec<std::ffi::OsString> = std::env::args_os().take(1).collect();
+    for settings in layers {
+        let mut applied = Vec::new();
+        for (arg, value) in settings {
+            if decided.iter().any(|other| other.get_id() == arg.get_id() || conflicts(arg, other) || conflicts(other, arg)) {
+                continue;
+            }
+            match (arg.get_long(), value) {
+                (None, value) => argv.extend(value.arguments().into_iter().map(Into::into)),
+                (Some(long), Value::Bool(true)) => argv.push(format!("--{}", long).into()),
+                (Some(_), Value::Bool(false)) => {},
+                // `verbose = 2` stands for -vv.
+                (Some(long), Value::Integer(n)) if matches!(arg.get_action(), ArgAction::Count) => argv.extend((0..n).map(|_| format!("--{}", long).into())),
+                (Some(long), value) => argv.extend(value.arguments().into_iter().map(|v| format!("--{}={}", long, v).into())),
+            }
+            applied.push(arg);
+        }
+        decided.extend(applied);
+    }
+    argv.extend(std::env::args_os().skip(1));
+    Args::command().try_get_matches_from(argv).unwrap_or_else(|e| e.exit())
+}
+
+/// Settings are named after the long flag (`expect-status`), positionals after their field (`targets`).
+fn find_setting<'a>(command: &'a clap::Command, key: &str) -> Option<&'a clap::Arg> {
+    let long = key.replace('_', "-");
+    if matches!(long.as_str(), "profile" | "config") {
+        return None;
+    }
+    command.get_arguments().find(|a| a.get_long().map_or(a.get_id() == key, |l| l == long))
+}
+
+/// `NETPROBE_<FLAG>` variables, e.g. `NETPROBE_TIMEOUT=10` for `--timeout 10`.
+/// Switches take 1/0 or true/false; repeatable flags take comma-separated lists.
+fn environment_settings(command: &clap::Command) -> Vec<(&clap::Arg, Value)> {
+    let mut settings = Vec::new();
+    // `vars()` would panic on any variable that is not UTF-8, even one of no concern to us.
+    for (name, value) in std::env::vars_os() {
+        if !name.as_encoded_bytes().starts_with(b"NETPROBE_") {
+            continue;
+        }
+        let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else {
+            eprintln!("{} {}: not valid UTF-8", "✖".red(), name.to_string_lossy());
+            std::process::exit(exit::USAGE);
+        };
+        let (name, value) = (name.to_string(), value.to_string());
+        let Some(key) = name.strip_prefix("NETPROBE_").filter(|key| !matches!(*key, "CONFIG" | "PROFILE")) else { continue };
+        let Some(arg) = find_setting(command, &key.to_ascii_lowercase()) else {
+            eprintln!("{} Ignoring {}: there is no --{} flag", "⚠".yellow(), name, key.to_ascii_lowercase().replace('_', "-"));
+            continue;
+        };
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => match value.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Value::Bool(true),
+                "0" | "false" | "no" | "off" | "" => Value::Bool(false),
+                _ => {
+                    eprintln!("{} {}: expected true or false, got '{}'", "✖".red(), name, value);
+                    std::process::exit(exit::USAGE);
+                },
+            },
+            ArgAction::Count => match value.parse() {
+                Ok(n) => Value::Integer(n),
+                Err(_) => {
+                    eprintln!("{} {}: expected a number, got '{}'", "✖".red(), name, value);
+                    std::process::exit(exit::USAGE);
+                },
+            },
+            ArgAction::Append => Value::Array(value.split(',').map(|v| Value::String(v.trim().to_string())).filter(|v| *v != Value::String(String::new())).collect()),
+            _ => Value::String(value),
+        };
+        settings.push((arg, value));
+    }
+    settings
+}
+
+async fn run_trace(args: TraceArgs) {
+    let options = TraceOptions {
+        protocol: match args.protocol {
+            TraceMode::Icmp => TraceProtocol::Icmp,
+            TraceMode::Udp => TraceProtocol::Udp,
+        },
+        max_hops: args.max_hops,
+        queries: args.queries,
+        timeout: args.timeout,
+        resolve_names: !args.no_resolve,
+    };
+
+    let target = args.target.clone();
+    let outcome = tokio::task::spawn_blocking(move || trace::trace(&target, &options)).await.expect("trace task panicked");
+    match outcome {
+        Ok(result) if args.json => output::print_trace_json(&result),
+        Ok(result) => output::print_trace_human(&result),
+        Err(e) => {
+            eprintln!("{} {}", "✖".red(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_mtr(args: MtrArgs) {
+    // The table is redrawn in place after every round, which only works on a terminal.
+    let live = !args.json && std::io::stdout().is_terminal();
+    let options = MtrOptions {
+        protocol: match args.protocol {
+            TraceMode::Icmp => TraceProtocol::Icmp,
+            TraceMode::Udp => TraceProtocol::Udp,
+        },
+        max_hops: args.max_hops,
+        rounds: args.count.or(if live { None } else { Some(10) }),
+        interval: args.interval,
+        timeout: args.timeout,
+        resolve_names: !args.no_resolve,
+    };
+
+    if !args.json {
+        output::print_mtr_start(&args.target, &options);
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let (target, stopped) = (args.target.clone(), stop.clone());
+    let mut task = tokio::task::spawn_blocking(move || {
+        let mut drawn = 0;
+        mtr::run(&target, &options, &stopped, |result| {
+            if live {
+                drawn = output::draw_mtr_table(result, drawn);
+            }
+        })
+    });
+    // Ctrl-C ends the run after the current round, and the statistics so far are the result.
+    let outcome = tokio::select! {
+        outcome = &mut task => outcome,
+        _ = tokio::signal::ctrl_c() => {
+            stop.store(true, Ordering::Relaxed);
+            task.await
+        },
+    };
+    match outcome.expect("mtr task panicked") {
+        Ok(result) if args.json => output::print_mtr_json(&result),
+        Ok(result) => output::print_mtr_human(&result, live),
+        Err(e) => {
+            eprintln!("{} {}", "✖".red(), e);
+            std::process::exit(1);
+        }
+    }
+}
 
-#[derive(Serialize)]
-struct ProbeResult {
-    target: String,
-    timestamp: String,
-    dns: DnsResult,
-    tcp: TcpResult,
-    http: HttpResult,
+async fn run_mtu(args: MtuArgs) {
+    let options = MtuOptions {
+        protocol: match args.protocol {
+            TraceMode::Icmp => TraceProtocol::Icmp,
+            TraceMode::Udp => TraceProtocol::Udp,
+        },
+        max_size: args.max_size,
+        attempts: args.attempts,
+        timeout: args.timeout,
+        resolve_names: !args.no_resolve,
+    };
+
+    if !args.json {
+        output::print_mtu_start(&args.target, &options);
+    }
+    let target = args.target.clone();
+    let outcome = tokio::task::spawn_blocking(move || mtu::discover(&target, &options)).await.expect("mtu task panicked");
+    match outcome {
+        Ok(result) if args.json => output::print_mtu_json(&result),
+        Ok(result) => output::print_mtu_human(&result),
+        Err(e) => {
+            eprintln!("{} {}", "✖".red(), e);
+            std::process::exit(1);
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct DnsResult {
-    status: String, // "ok" | "error"
-    ip: Option<String>,
-    latency_ms: Option<f64>,
-    error: Option<String>,
+async fn run_dns(args: DnsArgs) {
+    let mut types = Vec::new();
+    for name in &args.types {
+        match resolver::wire::type_from_name(name) {
+            Some(rtype) => types.push(rtype),
+            None => {
+                eprintln!("{} Unknown record type: {}", "✖".red(), name);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args.propagation {
+        return run_propagation(&args, &types).await;
+    }
+
+    let mut servers = upstreams(&args.server, &args.dot, &args.doh, &args.doq);
+    if servers.is_empty() {
+        servers = resolver::system_servers().into_iter().map(Upstream::Plain).collect();
+    }
+    if servers.is_empty() {
+        eprintln!("{} No system nameserver found; pass one with --server", "✖".red());
+        std::process::exit(1);
+    }
+
+    let resolver = resolver::Resolver::new(servers, args.timeout).with_client_subnet(args.ecs);
+    if args.ttl_watch {
+        return run_ttl_watch(&args, types[0], &resolver).await;
+    }
+    let lookup = resolver::records::lookup(&resolver, &args.name, &types).await;
+    if args.json {
+        output::print_records_json(&lookup);
+    } else {
+        output::print_records_human(&lookup);
+    }
+    if lookup.queries.iter().any(|q| q.status != "ok") {
+        std::process::exit(1);
+    }
 }
 
-#[derive(Serialize)]
-struct TcpResult {
-    status: String,
-    port: u16,
-    latency_ms: Option<f64>,
-    error: Option<String>,
+/// `netprobe dns --ttl-watch`: `count` queries `interval` apart, each printed as it comes in.
+async fn run_ttl_watch(args: &DnsArgs, rtype: u16, resolver: &resolver::Resolver) {
+    let authoritative = ttl::authoritative_ttl(resolver, &args.name, rtype, args.timeout).await;
+    let mut watch = TtlWatch::new(&args.name, rtype, authoritative);
+    if !args.json {
+        output::print_ttl_watch_start(&watch, args.interval);
+    }
+    let started = std::time::Instant::now();
+    let mut ticker = tokio::time::interval(args.interval);
+    for _ in 0..args.count.max(1) {
+        ticker.tick().await;
+        watch.push(ttl::sample(resolver, &args.name, rtype, started).await);
+        if let (false, Some(sample)) = (args.json, watch.samples.last()) {
+            output::print_ttl_sample(sample);
+        }
+    }
+    if args.json {
+        output::print_ttl_watch_json(&watch);
+    } else {
+        output::print_ttl_watch_summary(&watch);
+    }
+    if watch.status != "ok" || watch.samples.iter().all(|sample| sample.ttl.is_none()) {
+        std::process::exit(1);
+    }
 }
 
-#[derive(Serialize)]
-struct HttpResult {
-    status_code: Option<u16>,
-    latency_ms: Option<f64>,
-    headers: Option<HashMap<String, String>>,
-    error: Option<String>,
+/// `netprobe dns --propagation`: each type asked of every public resolver, or of the `--server` ones.
+async fn run_propagation(args: &DnsArgs, types: &[u16]) {
+    if !args.expect.is_empty() && types.len() > 1 {
+        eprintln!("{} --expect needs a single record type (-T)", "✖".red());
+        std::process::exit(1);
+    }
+    let resolvers = match args.server.as_slice() {
+        [] => propagation::public_resolvers(),
+        servers => servers.iter().map(|addr| ("Server".to_string(), "-".to_string(), *addr)).collect(),
+    };
+    let checks = types.iter().map(|&rtype| propagation::check(&args.name, rtype, &resolvers, &args.expect, args.timeout));
+    let results = futures_util::future::join_all(checks).await;
+    if args.json {
+        output::print_propagation_json(&results);
+    } else {
+        results.iter().for_each(output::print_propagation_human);
+    }
+    if results.iter().any(|result| result.status != "ok") {
+        std::process::exit(1);
+    }
 }
 
-// --- CLI Arguments ---
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// The target URL or IP (e.g., google.com, 192.168.1.1)
-    target: String,
+async fn run_sweep(args: SweepArgs) {
+    let options = SweepOptions {
+        ports: PortRange::expand(&args.ports),
+        timeout: args.timeout,
+        concurrency: args.concurrency,
+        rate: Some(args.rate).filter(|&rate| rate > 0.0),
+    };
+    let (network, addresses) = sweep::hosts(&args.network).unwrap_or_else(|e| {
+        eprintln!("{} {}", "✖".red(), e);
+        std::process::exit(1);
+    });
+    if !args.json {
+        output::print_sweep_start(&network.to_string(), addresses.len(), &options);
+    }
+    match sweep::sweep(&args.network, &options).await {
+        Ok(result) => {
+            if args.json {
+                output::print_sweep_json(&result);
+            } else {
+                output::print_sweep_human(&result);
+            }
+            if result.hosts_alive == 0 {
+                std::process::exit(exit::TCP);
+            }
+        },
+        Err(e) => {
+            eprintln!("{} {}", "✖".red(), e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    /// Output results in raw JSON format (ideal for scripting/pipelines)
-    #[arg(long, short = 'j')]
-    json: bool,
+async fn run_mail(args: MailArgs) {
+    let mut servers = upstreams(&args.server, &[], &[], &[]);
+    if servers.is_empty() {
+        servers = resolver::system_servers().into_iter().map(Upstream::Plain).collect();
+    }
+    if servers.is_empty() {
+        eprintln!("{} No system nameserver found; pass one with --server", "✖".red());
+        std::process::exit(1);
+    }
 
-    /// Set a custom timeout in seconds
-    #[arg(long, short = 't', default_value_t = 5)]
-    timeout: u64,
+    let probe = ProbeOptions {
+        timeout: args.timeout,
+        ca_bundle: args.cacert.as_ref().map(|path| {
+            CaBundle::load(path).unwrap_or_else(|e| {
+                eprintln!("{} {}", "✖".red(), e);
+                std::process::exit(1);
+            })
+        }),
+        ..ProbeOptions::default()
+    };
+    let mut options = MailOptions { port: args.port, helo: args.helo.clone(), probe, ..MailOptions::default() };
+    if !args.dkim_selector.is_empty() {
+        options.dkim_selectors = args.dkim_selector.clone();
+    }
 
-    /// Follow HTTP redirects (3xx)
-    #[arg(long, short = 'f', default_value_t = false)]
-    follow_redirects: bool,
+    let resolver = resolver::Resolver::new(servers, args.timeout);
+    let result = mail::check(&args.domain, &resolver, &options).await;
+    if args.json {
+        output::print_mail_json(&result);
+    } else {
+        output::print_mail_human(&result);
+    }
+    if !result.is_healthy() {
+        std::process::exit(1);
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let args = Args::parse();
+async fn run_dns_audit(args: DnsAuditArgs) {
+    let mut types = Vec::new();
+    for name in &args.types {
+        match resolver::wire::type_from_name(name) {
+            Some(rtype) => types.push(rtype),
+            None => {
+                eprintln!("{} Unknown record type: {}", "✖".red(), name);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    // 1. Input Sanitization & Parsing
-    // Automatically prepend https:// if no scheme is provided for convenience.
-    let target_input = if !args.target.contains("://") {
-        format!("https://{}", args.target)
+    let mut servers = upstreams(&args.server, &[], &[], &[]);
+    if servers.is_empty() {
+        servers = resolver::system_servers().into_iter().map(Upstream::Plain).collect();
+    }
+    if servers.is_empty() {
+        eprintln!("{} No system nameserver found; pass one with --server", "✖".red());
+        std::process::exit(1);
+    }
+
+    let family = match (args.ipv4, args.ipv6) {
+        (true, _) => Some(IpFamily::V4),
+        (_, true) => Some(IpFamily::V6),
+        _ => None,
+    };
+    let options = AuditOptions { timeout: args.timeout, types, family };
+    let resolver = resolver::Resolver::new(servers, args.timeout);
+    let result = audit::audit(&args.domain, &resolver, &options).await;
+    if args.json {
+        output::print_audit_json(&result);
     } else {
-        args.target.clone()
+        output::print_audit_human(&result);
+    }
+    if result.status != "ok" {
+        std::process::exit(1);
+    }
+}
+
+async fn run_dns_bench(args: DnsBenchArgs) {
+    let Some(rtype) = resolver::wire::type_from_name(&args.rtype) else {
+        eprintln!("{} Unknown record type: {}", "✖".red(), args.rtype);
+        std::process::exit(1);
+    };
+    let servers = upstreams(&args.server, &args.dot, &args.doh, &args.doq);
+    let resolvers: Vec<(Option<String>, Upstream)> = match servers.is_empty() {
+        true => resolver::system_servers().into_iter().map(|addr| (Some("System".to_string()), Upstream::Plain(addr))).chain(bench::default_resolvers()).collect(),
+        false => servers.into_iter().map(|server| (bench::label(&server), server)).collect(),
+    };
+    let names: Vec<String> = match args.names.is_empty() {
+        true => bench::BENCH_NAMES.iter().map(|name| name.to_string()).collect(),
+        false => args.names,
     };
 
-    let url = match Url::parse(&target_input) {
-        Ok(u) => u,
-        Err(e) => {
-            eprintln!("{} Invalid URL format: {}", "✖".red(), e);
+    if !args.json {
+        output::print_bench_start(&names, &args.rtype.to_ascii_uppercase(), resolvers.len(), args.iterations);
+    }
+    let result = bench::run(&names, rtype, &resolvers, args.iterations, args.timeout).await;
+    if args.json {
+        output::print_bench_json(&result);
+    } else {
+        output::print_bench_human(&result);
+    }
+    if result.status != "ok" {
+        std::process::exit(1);
+    }
+}
+
+async fn run_whois(args: WhoisArgs) {
+    let options = WhoisOptions { timeout: args.timeout, rdap_url: args.rdap_url, whois_server: args.whois_server, expiry_warn_days: args.expiry_warn_days, proxy: proxy_settings(args.proxy) };
+    let result = whois::lookup(&args.domain, &options).await;
+    if args.json {
+        output::print_whois_json(&result);
+    } else {
+        output::print_whois_human(&result);
+    }
+    match result.status.as_str() {
+        "error" => std::process::exit(exit::ERROR),
+        "warning" => std::process::exit(exit::CERT_EXPIRY),
+        _ => {},
+    }
+}
+
+fn run_diff(args: DiffArgs) {
+    let load = |path: &PathBuf| {
+        diff::load(path).unwrap_or_else(|e| {
+            eprintln!("{} {}", "✖".red(), e);
             std::process::exit(1);
-        }
+        })
     };
+    let report = diff::diff(&load(&args.baseline), &load(&args.current), args.threshold);
+    if args.json {
+        output::print_diff_json(&report);
+    } else {
+        output::print_diff_human(&report);
+    }
+    if report.has_changes() {
+        std::process::exit(exit::CHANGED);
+    }
+}
 
-    let host = url.host_str().unwrap_or("").to_string();
-    // Default ports: 443 for https, 80 for http, or use specified port
-    let port = url.port_or_known_default().unwrap_or(443);
+#[cfg(feature = "history")]
+fn run_history(args: HistoryArgs) {
+    let report = History::open(&args.db).and_then(|history| history.query(&args.target, args.since));
+    match report {
+        Ok(report) if args.json => output::print_history_json(&report),
+        Ok(report) => output::print_history_human(&report, args.limit),
+        Err(e) => {
+            eprintln!("{} {}", "✖".red(), e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    // Initialize result structure
-    let mut probe_data = ProbeResult {
-        target: target_input.clone(),
-        timestamp: chrono::Local::now().to_rfc3339(),
-        dns: DnsResult { status: "pending".to_string(), ip: None, latency_ms: None, error: None },
-        tcp: TcpResult { status: "pending".to_string(), port, latency_ms: None, error: None },
-        http: HttpResult { status_code: None, latency_ms: None, headers: None, error: None },
+async fn run_probe(args: Args) {
+    let mut options = ProbeOptions {
+        timeout: args.timeout,
+        dns_timeout: args.dns_timeout,
+        connect_timeout: args.connect_timeout,
+        tls_timeout: args.tls_timeout,
+        http_timeout: args.http_timeout,
+        follow_redirects: args.follow_redirects,
+        method: if args.download { Method::GET } else { args.method.into() },
+        http_version: match (args.http1_only, args.http2_prior_knowledge) {
+            (true, _) => HttpVersion::Http1,
+            (_, true) => HttpVersion::Http2,
+            _ => HttpVersion::Negotiate,
+        },
+        body: None,
+        content_type: args.content_type.clone(),
+        headers: args.headers.clone(),
+        auth: match (&args.auth_basic, &args.auth_bearer, &args.auth_digest) {
+            (Some((user, password)), _, _) => Some(HttpAuth::Basic { user: user.clone(), password: password.clone() }),
+            (_, Some(token), _) => Some(HttpAuth::Bearer(token.clone())),
+            (_, _, Some((user, password))) => Some(HttpAuth::Digest { user: user.clone(), password: password.clone() }),
+            _ => None,
+        },
+        cookies: args.cookies.clone(),
+        cookie_jar: args.cookie_file.as_ref().map(|path| {
+            CookieJar::load(path).unwrap_or_else(|e| {
+                eprintln!("{} {}", "✖".red(), e);
+                std::process::exit(1);
+            })
+        }),
+        capture_headers: !args.no_headers,
+        header_filter: args.header_filter.clone(),
+        verbose: args.verbose > 0,
+        cert_warn_days: args.cert_warn_days,
+        ping_count: args.ping.then_some(args.ping_count),
+        dns_servers: upstreams(&args.dns_servers, &args.dot, &args.doh, &args.doq),
+        client_subnet: args.ecs,
+        resolve: args.resolve.clone(),
+        sni: args.sni.clone(),
+        ca_bundle: args.cacert.as_ref().map(|path| {
+            CaBundle::load(path).unwrap_or_else(|e| {
+                eprintln!("{} {}", "✖".red(), e);
+                std::process::exit(1);
+            })
+        }),
+        insecure: args.insecure,
+        cert_chain: args.cert_chain || args.cert_pem || args.verbose > 1,
+        cert_pem: args.cert_pem,
+        check_revocation: args.check_revocation,
+        check_resumption: args.check_resumption,
+        check_ct: args.check_ct || args.ct_log_list.is_some(),
+        check_caa: args.check_caa,
+        ct_logs: args.ct_log_list.as_ref().map(|path| {
+            CtLogList::load(path).unwrap_or_else(|e| {
+                eprintln!("{} {}", "✖".red(), e);
+                std::process::exit(1);
+            })
+        }),
+        client_identity: args.cert.as_ref().map(|cert| {
+            ClientIdentity::load(cert, args.key.as_deref(), args.cert_password.as_deref()).unwrap_or_else(|e| {
+                eprintln!("{} Cannot load client certificate: {}", "✖".red(), e);
+                std::process::exit(1);
+            })
+        }),
+        proxy: proxy_settings(args.proxy.clone()),
+        all_ips: args.all_ips,
+        happy_eyeballs: args.happy_eyeballs,
+        parallel: args.parallel,
+        ports: PortRange::expand(&args.ports),
+        syn: args.syn,
+        tcp_quality: args.tcp_quality,
+        banner: args.banner.map(|bytes| bytes as usize),
+        banner_timeout: args.banner_timeout,
+        reverse_dns: args.rdns,
+        dnssec: args.dnssec,
+        dns_trace: args.dns_trace,
+        geo: None,
+        ip_family: match (args.ipv4, args.ipv6) {
+            (true, _) => Some(IpFamily::V4),
+            (_, true) => Some(IpFamily::V6),
+            _ => None,
+        },
+        source: SourceBinding { ip: args.source_ip, interface: args.interface.clone(), tos: args.dscp.map(|dscp| dscp << 2).or(args.tos) },
+        compare_stacks: args.compare_stacks,
+        expect_status: args.expect_status.clone(),
+        expect_body_contains: args.expect_body_contains.clone(),
+        expect_body_regex: args.expect_body_regex.clone(),
+        expect_json_path: args.expect_json_path.clone(),
+        latency_limits: LatencyLimits { total: args.max_latency, dns: args.max_dns, tcp: args.max_tcp, tls: args.max_tls, http: args.max_http },
+        retries: args.retries,
+        retry_backoff: args.retry_backoff,
+        detect_cdn: args.detect_cdn,
+        security_audit: args.security_audit,
+        warm: args.warm,
+        http3: args.http3,
+        grpc: args.grpc.clone(),
+        websocket: args.ws || args.ws_ping,
+        websocket_ping: args.ws_ping,
+        udp: match (&args.payload_hex, args.udp) {
+            (Some(HexBytes(bytes)), _) => Some(UdpPayload::Raw(bytes.clone())),
+            (None, Some(UdpMode::Auto)) => Some(UdpPayload::Auto),
+            (None, Some(UdpMode::Dns)) => Some(UdpPayload::Dns),
+            (None, Some(UdpMode::Ntp)) => Some(UdpPayload::Ntp),
+            (None, Some(UdpMode::Quic)) => Some(UdpPayload::Quic),
+            (None, Some(UdpMode::Sip)) => Some(UdpPayload::Sip),
+            (None, Some(UdpMode::Snmp)) => Some(UdpPayload::Snmp(args.community.clone())),
+            (None, None) => None,
+        },
+        service: args.service.map(|service| match service {
+            ServiceArg::Ssh => Service::Ssh,
+            ServiceArg::Smtp => Service::Smtp,
+            ServiceArg::Smtps => Service::Smtps,
+            ServiceArg::Imap => Service::Imap,
+            ServiceArg::Imaps => Service::Imaps,
+            ServiceArg::Pop3 => Service::Pop3,
+            ServiceArg::Pop3s => Service::Pop3s,
+            ServiceArg::Postgres => Service::Postgres,
+            ServiceArg::Mysql => Service::Mysql,
+            ServiceArg::Redis => Service::Redis,
+            ServiceArg::Ftp => Service::Ftp,
+            ServiceArg::Ftps => Service::Ftps,
+            ServiceArg::Mongodb => Service::Mongodb,
+            ServiceArg::Mqtt => Service::Mqtt,
+            ServiceArg::Mqtts => Service::Mqtts,
+            ServiceArg::Kafka => Service::Kafka,
+            ServiceArg::Ldap => Service::Ldap,
+            ServiceArg::Ldaps => Service::Ldaps,
+            ServiceArg::Sip => Service::Sip,
+            ServiceArg::SipTcp => Service::SipTcp,
+            ServiceArg::Sips => Service::Sips,
+            ServiceArg::Rdp => Service::Rdp,
+            ServiceArg::Smb => Service::Smb,
+            ServiceArg::Snmp => Service::Snmp,
+            ServiceArg::Ntp => Service::Ntp,
+        }),
+        ftp_anonymous: args.ftp_anonymous,
+        snmp_community: args.community.clone(),
+        count: args.count,
     };
+    // Body assertions need a body: upgrade the default HEAD request to GET.
+    if options.needs_body() && options.method == Method::HEAD {
+        options.method = Method::GET;
+    }
 
-    // UI Header (only if not in JSON mode)
-    if !args.json {
-        println!("\n🔍 Probing Target: {}", target_input.bold().cyan());
-        println!("{}", "--------------------------------------------------".dimmed());
-    }
-
-    // --- STEP 1: DNS Resolution ---
-    let start_dns = Instant::now();
-    let socket_addr_str = format!("{}:{}", host, port);
-    // Blocking call is acceptable here for simplicity in a CLI tool
-    let ip_lookup = socket_addr_str.to_socket_addrs();
-    let dns_duration = start_dns.elapsed().as_secs_f64() * 1000.0;
-
-    let resolved_ip = match ip_lookup {
-        Ok(mut addrs) => {
-            if let Some(ip) = addrs.next() {
-                probe_data.dns.status = "ok".to_string();
-                probe_data.dns.ip = Some(ip.ip().to_string());
-                probe_data.dns.latency_ms = Some(dns_duration);
-
-                if !args.json {
-                    println!("1. DNS Resolution   {} {} ({:.2}ms)", "✅".green(), ip.ip().to_string().yellow(), dns_duration);
-                }
-                Some(ip)
-            } else {
-                probe_data.dns.status = "error".to_string();
-                probe_data.dns.error = Some("No IP found".to_string());
-                if !args.json { println!("1. DNS Resolution   {} Failed: No IP found", "❌".red()); }
-                None
+    options.body = match (&args.body, &args.body_file) {
+        (Some(body), _) => Some(body.clone().into_bytes()),
+        (None, Some(path)) => match read_input(path) {
+            Ok(body) => Some(body),
+            Err(e) => {
+                eprintln!("{} Cannot read request body from {}: {}", "✖".red(), path, e);
+                std::process::exit(1);
             }
         },
-        Err(e) => {
-            probe_data.dns.status = "error".to_string();
-            probe_data.dns.error = Some(e.to_string());
-            if !args.json { println!("1. DNS Resolution   {} Error: {}", "❌".red(), e); }
-            None
-        }
+        (None, None) => None,
     };
 
-    // --- STEP 2: TCP Handshake ---
-    if let Some(ip) = resolved_ip {
-        let start_tcp = Instant::now();
-        // Attempt TCP connection with timeout
-        match std::net::TcpStream::connect_timeout(&ip, Duration::from_secs(args.timeout)) {
-            Ok(_) => {
-                let tcp_duration = start_tcp.elapsed().as_secs_f64() * 1000.0;
-                probe_data.tcp.status = "ok".to_string();
-                probe_data.tcp.latency_ms = Some(tcp_duration);
+    if args.geo || !args.geo_db.is_empty() {
+        match GeoDb::open(&args.geo_db) {
+            Ok(db) => options.geo = Some(Arc::new(db)),
+            Err(e) => {
+                eprintln!("{} {}", "✖".red(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut targets = args.targets.clone();
+    if let Some(path) = &args.targets_file {
+        match read_targets(path) {
+            Ok(from_file) => targets.extend(from_file),
+            Err(e) => {
+                eprintln!("{} Cannot read targets from {}: {}", "✖".red(), path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if targets.is_empty() && args.targets_file.is_none() {
+        eprintln!("{} No targets: give them on the command line or as `targets` in the profile", "✖".red());
+        std::process::exit(exit::USAGE);
+    }
+
+    let recorder = Recorder::open(&args);
+    let baseline = args.baseline.as_ref().map(|path| {
+        diff::load(path).unwrap_or_else(|e| {
+            eprintln!("{} {}", "✖".red(), e);
+            std::process::exit(1);
+        })
+    });
+
+    if let Some(Err(e)) = args.report.as_deref().map(report::ReportFormat::from_path) {
+        eprintln!("{} {}", "✖".red(), e);
+        std::process::exit(exit::USAGE);
+    }
+
+    if args.watch || args.tui {
+        run_watch(targets, options, &args, &recorder).await;
+        return;
+    }
+
+    // A single command-line target keeps the plain JSON object shape;
+    // anything else is streamed as a JSON array.
+    let single = targets.len() == 1 && args.targets_file.is_none();
+
+    let format = output_format(&args);
+    let mut json_array = (format == OutputFormat::Json && !single).then(output::JsonArrayWriter::begin);
+    let mut har = (format == OutputFormat::Har).then(output::HarWriter::begin);
+    let mut junit = (format == OutputFormat::Junit).then(output::JunitWriter::begin);
+    if format == OutputFormat::Csv {
+        output::print_csv_header();
+    }
+    let mut exit_code = exit::OK;
+    let mut compared = Vec::new();
+    let mut reported = Vec::new();
 
-                if !args.json {
-                    println!("2. TCP Handshake    {} Port {} Open ({:.2}ms)", "✅".green(), port, tcp_duration);
+    // Results are printed as each probe completes. Ctrl-C stops waiting for the
+    // rest, and the documents below are still closed so the output stays valid.
+    let total = targets.len();
+    let (mut probed, mut succeeded) = (0, 0);
+    let mut interrupted = false;
+    let interrupt = tokio::signal::ctrl_c();
+    tokio::pin!(interrupt);
+    let started = std::time::Instant::now();
+    let mut stream = Prober::run_stream(targets, options, args.concurrency, args.rate.filter(|&rate| rate > 0.0));
+    loop {
+        let (target, outcome) = tokio::select! {
+            next = stream.recv() => match next {
+                Some(next) => next,
+                None => break,
+            },
+            _ = &mut interrupt => {
+                interrupted = true;
+                break;
+            },
+        };
+        probed += 1;
+        match outcome {
+            Ok(result) => {
+                exit_code = worst_exit_code(exit_code, result.exit_code());
+                succeeded += usize::from(result.is_success());
+                recorder.record(&result);
+                if baseline.is_some() {
+                    compared.push(serde_json::to_value(&result).unwrap());
+                }
+                match (format, json_array.as_mut()) {
+                    (_, Some(writer)) => writer.push(&result),
+                    (OutputFormat::Har, None) => har.as_mut().unwrap().push(&result),
+                    (OutputFormat::Junit, None) => junit.as_mut().unwrap().push(&result),
+                    (OutputFormat::Json, None) => output::print_json(&result),
+                    (OutputFormat::Ndjson, None) => output::print_ndjson(&result),
+                    (OutputFormat::Csv, None) => output::print_csv_row(&result),
+                    (OutputFormat::Human, None) => output::print_human(&result, verbosity(&args)),
+                }
+                if args.report.is_some() {
+                    reported.push(result);
                 }
             },
             Err(e) => {
-                probe_data.tcp.status = "error".to_string();
-                probe_data.tcp.error = Some(e.to_string());
+                eprintln!("{} {}: {}", "✖".red(), target, e);
+                exit_code = worst_exit_code(exit_code, exit::ERROR);
+            }
+        }
+    }
 
-                if !args.json {
-                    println!("2. TCP Handshake    {} Connection Refused or Timeout", "❌".red());
-                }
-                // We continue to HTTP check even if TCP fails, just in case of weird proxy setups,
-                // though usually it will fail there too.
+    // A single target cut short still gets its document: what the probe had got so far.
+    if interrupted && single {
+        for result in stream.interrupted() {
+            match format {
+                OutputFormat::Json => output::print_json(&result),
+                OutputFormat::Ndjson => output::print_ndjson(&result),
+                OutputFormat::Csv => output::print_csv_row(&result),
+                OutputFormat::Har => har.as_mut().unwrap().push(&result),
+                OutputFormat::Junit => junit.as_mut().unwrap().push(&result),
+                OutputFormat::Human => output::print_human(&result, verbosity(&args)),
             }
         }
     }
+    if let Some(writer) = json_array {
+        writer.finish();
+    }
+    if let Some(writer) = har {
+        writer.finish();
+    }
+    if let Some(writer) = junit {
+        writer.finish();
+    }
+    if let Some(path) = &args.report {
+        if let Err(e) = report::write(path, &reported) {
+            eprintln!("{} {}", "✖".red(), e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(baseline) = baseline {
+        let report = diff::diff(&baseline, &compared, args.diff_threshold);
+        // Machine-readable output owns stdout; the JSON variants stay parseable.
+        match format {
+            OutputFormat::Human => output::print_diff_human(&report),
+            _ => eprintln!("{}", serde_json::to_string(&report).unwrap()),
+        }
+        if report.has_changes() {
+            exit_code = worst_exit_code(exit_code, exit::CHANGED);
+        }
+    }
 
-    // --- STEP 3: HTTP/HTTPS Request ---
-    let start_http = Instant::now();
+    if interrupted {
+        eprintln!("{} Interrupted: {} of {} targets probed ({} ok, {} failed)", "⚠".yellow(), probed, total, succeeded, probed - succeeded);
+        exit_code = exit::INTERRUPTED;
+    } else if total > 1 && !args.quiet {
+        output::print_batch_summary(probed, succeeded, started.elapsed(), stream.start_rate());
+    }
 
-    // Configure Redirect Policy
-    let redirect_policy = if args.follow_redirects {
-        reqwest::redirect::Policy::limited(10)
-    } else {
-        reqwest::redirect::Policy::none()
-    };
-
-    // Build Client with Timeout and Policy
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(args.timeout))
-        .redirect(redirect_policy)
-        .user_agent("NetProbe/1.0") // Good practice to identify your tool
-        .build()
-        .unwrap_or_default();
-
-    // Send HEAD request (lighter than GET)
-    match client.head(&target_input).send().await {
-        Ok(response) => {
-            let http_duration = start_http.elapsed().as_secs_f64() * 1000.0;
-            let status = response.status();
-
-            probe_data.http.status_code = Some(status.as_u16());
-            probe_data.http.latency_ms = Some(http_duration);
-
-            // Capture relevant headers
-            let mut headers_map = HashMap::new();
-            if let Some(h) = response.headers().get("server") {
-                headers_map.insert("server".to_string(), h.to_str().unwrap_or("unknown").to_string());
-            }
-            if let Some(h) = response.headers().get("content-type") {
-                headers_map.insert("content-type".to_string(), h.to_str().unwrap_or("unknown").to_string());
-            }
-            probe_data.http.headers = Some(headers_map);
-
-            if !args.json {
-                if status.is_success() {
-                    println!("3. HTTP Request     {} Status: {} ({:.2}ms)", "✅".green(), status, http_duration);
-                } else if status.is_redirection() {
-                    println!("3. HTTP Request     {} Status: {} (Redirect) ({:.2}ms)", "⚠️".yellow(), status, http_duration);
-                } else {
-                    println!("3. HTTP Request     {} Status: {} ({:.2}ms)", "❌".red(), status, http_duration);
+    std::process::exit(exit_code);
+}
+
+fn verbosity(args: &Args) -> Verbosity {
+    match (args.quiet, args.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Verbose,
+        (false, _) => Verbosity::Debug,
+    }
+}
+
+/// With several targets, report the most fundamental failure (lowest non-zero code).
+fn worst_exit_code(current: i32, code: i32) -> i32 {
+    match (current, code) {
+        (exit::OK, code) | (code, exit::OK) => code,
+        (current, code) => current.min(code),
+    }
+}
+
+/// Probe every target once per interval, forever. JSON mode emits one NDJSON record per run, CSV one row.
+async fn run_watch(targets: Vec<String>, options: ProbeOptions, args: &Args, recorder: &Recorder) {
+    // Invalid targets would fail on every iteration, so reject them up front.
+    for target in &targets {
+        if let Err(e) = probe::parse_target(target) {
+            eprintln!("{} {}: {}", "✖".red(), target, e);
+            std::process::exit(1);
+        }
+    }
+
+    if args.tui {
+        if !std::io::stdout().is_terminal() {
+            eprintln!("{} --tui needs a terminal on stdout; use --watch to stream the results instead", "✖".red());
+            std::process::exit(exit::USAGE);
+        }
+        let mut dashboard = Dashboard::new(&targets, args.interval).unwrap_or_else(|e| {
+            eprintln!("{} Cannot open the dashboard: {}", "✖".red(), e);
+            std::process::exit(1);
+        });
+        // A signal must drop the dashboard too, so the terminal gets its normal screen back.
+        tokio::select! {
+            _ = watch_dashboard(&mut dashboard, &targets, &options, args, recorder) => {},
+            _ = tokio::signal::ctrl_c() => {},
+        }
+        return;
+    }
+
+    let format = output_format(args);
+    if matches!(format, OutputFormat::Har | OutputFormat::Junit) {
+        eprintln!("{} --output {} writes one document at the end of a run; it cannot be combined with --watch", "✖".red(), format.to_possible_value().unwrap().get_name());
+        std::process::exit(exit::USAGE);
+    }
+    if format == OutputFormat::Csv {
+        output::print_csv_header();
+    }
+
+    let mut summaries: HashMap<String, Summary> = HashMap::new();
+    // Results are printed whole, so stopping between two of them leaves the output intact.
+    tokio::select! {
+        _ = watch_stream(format, &mut summaries, &targets, &options, args, recorder) => {},
+        _ = tokio::signal::ctrl_c() => {},
+    }
+    let totals: Vec<(String, SummarySnapshot)> = targets.iter().filter_map(|target| Some((target.clone(), summaries.get(target)?.snapshot()))).collect();
+    output::print_watch_totals(&totals);
+    std::process::exit(exit::INTERRUPTED);
+}
+
+/// The plain watch loop: every result is printed as it arrives.
+async fn watch_stream(format: OutputFormat, summaries: &mut HashMap<String, Summary>, targets: &[String], options: &ProbeOptions, args: &Args, recorder: &Recorder) {
+    let mut ticker = tokio::time::interval(args.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let mut stream = Prober::run_stream(targets.to_vec(), options.clone(), args.concurrency, args.rate.filter(|&rate| rate > 0.0));
+        while let Some((target, outcome)) = stream.recv().await {
+            let summary = summaries.entry(target.clone()).or_default();
+            let result = match outcome {
+                Ok(result) => result,
+                Err(e) => {
+                    eprintln!("{} {}: {}", "✖".red(), target, e);
+                    summary.record_failure();
+                    continue;
+                },
+            };
+            recorder.record(&result);
+            summary.record(result.is_success(), result.duration_ms);
+            match format {
+                // Pretty JSON cannot be streamed forever, so both JSON formats emit NDJSON.
+                OutputFormat::Json | OutputFormat::Ndjson => output::print_ndjson(&result),
+                OutputFormat::Csv => output::print_csv_row(&result),
+                OutputFormat::Human => {
+                    let verbosity = verbosity(args);
+                    output::print_human(&result, verbosity);
+                    if verbosity > Verbosity::Quiet {
+                        output::print_watch_summary(&summary.snapshot());
+                    }
+                },
+                OutputFormat::Har | OutputFormat::Junit => unreachable!("rejected before the loop"),
+            }
+        }
+    }
+}
+
+/// The `--tui` variant of the watch loop: every result, and every resize, redraws the
+/// dashboard until `q` is pressed.
+async fn watch_dashboard(dashboard: &mut Dashboard, targets: &[String], options: &ProbeOptions, args: &Args, recorder: &Recorder) {
+    let (results, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let probing = async {
+        let mut ticker = tokio::time::interval(args.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let mut stream = Prober::run_stream(targets.to_vec(), options.clone(), args.concurrency, args.rate.filter(|&rate| rate > 0.0));
+            while let Some((target, outcome)) = stream.recv().await {
+                if let Ok(result) = &outcome {
+                    recorder.record(result);
                 }
+                let _ = results.send((target, outcome));
             }
-        },
-        Err(e) => {
-            probe_data.http.error = Some(e.to_string());
-            if !args.json {
-                println!("3. HTTP Request     {} Error: {}", "❌".red(), e);
+        }
+    };
+    let screen = async {
+        loop {
+            tokio::select! {
+                Some((target, outcome)) = received.recv() => match outcome {
+                    Ok(result) => dashboard.record(&target, &result),
+                    Err(e) => dashboard.record_failure(&target, e.to_string()),
+                },
+                input = dashboard.input() => match input {
+                    Input::Quit => return,
+                    Input::Redraw => {},
+                },
+            }
+            if dashboard.draw().is_err() {
+                return;
             }
         }
+    };
+    tokio::select! {
+        _ = probing => {},
+        _ = screen => {},
     }
+}
 
-    // Final Output
-    if args.json {
-        // Print raw JSON for piping
-        let json_output = serde_json::to_string_pretty(&probe_data).unwrap();
-        println!("{}", json_output);
+/// Saves results to the `--history` database, if one was requested.
+struct Recorder {
+    #[cfg(feature = "history")]
+    history: Option<History>,
+}
+
+impl Recorder {
+    fn open(args: &Args) -> Recorder {
+        #[cfg(feature = "history")]
+        {
+            let history = args.history.as_ref().map(|path| {
+                History::open(path).unwrap_or_else(|e| {
+                    eprintln!("{} {}", "✖".red(), e);
+                    std::process::exit(1);
+                })
+            });
+            Recorder { history }
+        }
+        #[cfg(not(feature = "history"))]
+        {
+            let _ = args;
+            Recorder {}
+        }
+    }
+
+    /// A failed write is reported but never aborts the probe run.
+    fn record(&self, result: &ProbeResult) {
+        #[cfg(feature = "history")]
+        if let Some(history) = &self.history {
+            if let Err(e) = history.record(result) {
+                eprintln!("{} Cannot save to {}: {}", "⚠".yellow(), history.path().display(), e);
+            }
+        }
+        #[cfg(not(feature = "history"))]
+        let _ = result;
+    }
+}
+
+/// The output format selected by `--output`, or `--json` as its shorthand.
+fn output_format(args: &Args) -> OutputFormat {
+    match (args.output, args.json) {
+        (Some(format), _) => format,
+        (None, true) => OutputFormat::Json,
+        (None, false) => OutputFormat::Human,
+    }
+}
+
+/// `--proxy` for every scheme, or the proxy environment variables when it is not given.
+fn proxy_settings(proxy: Option<Proxy>) -> ProxySettings {
+    match proxy {
+        Some(proxy) => ProxySettings::all(proxy),
+        None => ProxySettings::from_env().unwrap_or_else(|e| {
+            eprintln!("{} Invalid proxy: {}", "✖".red(), e);
+            std::process::exit(exit::USAGE);
+        }),
+    }
+}
+
+/// Every DNS server given on the command line: plain ones first, then DoT, DoH and DoQ.
+fn upstreams(plain: &[SocketAddr], dot: &[Upstream], doh: &[Upstream], doq: &[Upstream]) -> Vec<Upstream> {
+    plain.iter().copied().map(Upstream::Plain).chain(dot.iter().cloned()).chain(doh.iter().cloned()).chain(doq.iter().cloned()).collect()
+}
+
+/// Parse a curl-style `Name: value` header.
+fn parse_header(input: &str) -> Result<(String, String), String> {
+    let (name, value) = input.split_once(':').ok_or_else(|| format!("invalid header '{}' (expected 'Name: value')", input))?;
+    let name = name.trim();
+    if hyper::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+        return Err(format!("invalid header name '{}'", name));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Parse `user:password` credentials; the password may contain ':'.
+fn parse_credentials(input: &str) -> Result<(String, String), String> {
+    let (user, password) = input.split_once(':').ok_or("expected USER:PASS")?;
+    Ok((user.to_string(), password.to_string()))
+}
+
+/// Parse a `name=value` cookie.
+fn parse_cookie(input: &str) -> Result<(String, String), String> {
+    let (name, value) = input.trim().split_once('=').ok_or_else(|| format!("invalid cookie '{}' (expected NAME=VALUE)", input.trim()))?;
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(format!("invalid cookie name '{}'", name));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Raw bytes given in hex on the command line.
+#[derive(Clone, Debug)]
+struct HexBytes(Vec<u8>);
+
+/// Parse hex bytes such as `deadbeef`, `0xde ad be ef` or `de:ad:be:ef`.
+fn parse_hex(input: &str) -> Result<HexBytes, String> {
+    let digits: String = input.trim().trim_start_matches("0x").chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in '{}'", input));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("invalid hex '{}'", input)))
+        .collect::<Result<_, _>>()
+        .map(HexBytes)
+}
+
+/// Parse a human-friendly duration: `750ms`, `1.5s`, `2m`, `1h30m`, `7d`, or bare seconds.
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let invalid = || format!("invalid duration '{}'", input);
+    if let Ok(seconds) = input.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).map_err(|_| invalid());
+    }
+    if input.is_empty() {
+        return Err(invalid());
+    }
+    // One or more NUMBER UNIT pairs, as in `1m30s`.
+    let mut seconds = 0.0;
+    let mut rest = input;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(split);
+        let value: f64 = number.parse().map_err(|_| invalid())?;
+        let tail = tail.trim_start();
+        let end = tail.find(|c: char| !c.is_alphabetic()).unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(end);
+        let scale = match unit {
+            "ns" | "nsec" => 1e-9,
+            "us" | "µs" | "usec" => 1e-6,
+            "ms" | "msec" | "millis" => 1e-3,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+            "h" | "hr" | "hour" | "hours" => 3600.0,
+            "d" | "day" | "days" => 86400.0,
+            "" => return Err(format!("missing unit after '{}' in '{}' (use ms, s, m, h or d)", number, input)),
+            other => return Err(format!("unknown duration unit '{}' (use ms, s, m, h or d)", other)),
+        };
+        seconds += value * scale;
+        rest = tail.trim_start();
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// Parse the pause between two runs, which has to be longer than zero.
+fn parse_interval(input: &str) -> Result<Duration, String> {
+    match parse_duration(input)? {
+        interval if interval.is_zero() => Err(format!("interval '{}' must be longer than zero", input.trim())),
+        interval => Ok(interval),
+    }
+}
+
+/// Parse a start rate: `50`, `50/s`, `600/m` or `3600/h`, as starts per second.
+fn parse_rate(input: &str) -> Result<f64, String> {
+    let (count, per) = input.trim().split_once('/').unwrap_or((input.trim(), "s"));
+    let seconds = match per.trim() {
+        "s" | "sec" | "second" => 1.0,
+        "m" | "min" | "minute" => 60.0,
+        "h" | "hour" => 3600.0,
+        other => return Err(format!("unknown rate unit '/{}' (use /s, /m or /h)", other)),
+    };
+    let rate = match count.trim().parse::<f64>() {
+        Ok(count) if count.is_finite() && count >= 0.0 => count / seconds,
+        _ => return Err(format!("invalid rate '{}' (expected e.g. 50/s or 600/m)", input)),
+    };
+    match rate {
+        0.0 => Ok(rate),
+        _ if (probe::MIN_RATE..=probe::MAX_RATE).contains(&rate) => Ok(rate),
+        _ => Err(format!("rate '{}' is out of range (between 1 a day and {} per second, or 0 for no limit)", input, probe::MAX_RATE)),
+    }
+}
+
+/// Read one target per line from `path` (or stdin for "-"), skipping blanks and # comments.
+fn read_targets(path: &str) -> std::io::Result<Vec<String>> {
+    let content = String::from_utf8(read_input(path)?).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Contents of `path`, or of stdin for "-".
+fn read_input(path: &str) -> std::io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut buf = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut buf)?;
+        Ok(buf)
     } else {
-        println!("{}", "--------------------------------------------------".dimmed());
+        std::fs::read(path)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,285 @@
+//! Continuous traceroute with per-hop statistics (`netprobe mtr`).
+//!
+//! Every round sends one probe to each distance at once, like `mtr`, and
+//! matches the answers back to their hop by sequence number. Over the rounds
+//! each hop accumulates its loss and round-trip times; loss at one hop that
+//! does not carry on to the next is usually just a router slow to answer
+//! probes, not lost traffic. Uses the same raw ICMP socket as `netprobe trace`.
+//! Hop names are looked up on a thread of their own, so that a slow PTR query
+//! never holds up the answers (and inflates their round-trip times).
+
+use crate::probe::{self, dns, ProbeError};
+use crate::stats::{self, LatencyStats};
+use crate::trace::{self, Reply, TraceProtocol};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct MtrOptions {
+    pub protocol: TraceProtocol,
+    pub max_hops: u8,
+    /// Rounds to run; `None` runs until stopped.
+    pub rounds: Option<u32>,
+    /// Time between the starts of two rounds.
+    pub interval: Duration,
+    /// How long to wait for the answers of a round.
+    pub timeout: Duration,
+    /// Look up PTR names for every hop.
+    pub resolve_names: bool,
+}
+
+impl Default for MtrOptions {
+    fn default() -> Self {
+        MtrOptions { protocol: TraceProtocol::Icmp, max_hops: 30, rounds: None, interval: Duration::from_secs(1), timeout: Duration::from_secs(1), resolve_names: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MtrResult {
+    pub target: String,
+    pub timestamp: String,
+    pub destination: String,
+    pub protocol: TraceProtocol,
+    pub rounds: u32,
+    pub reached: bool,
+    /// Up to the destination once it answered, otherwise up to the farthest hop that did.
+    pub hops: Vec<MtrHop>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MtrHop {
+    pub ttl: u8,
+    /// Addresses that answered at this distance, first seen first (several under load balancing).
+    pub ips: Vec<String>,
+    pub hostname: Option<String>,
+    pub sent: u32,
+    pub received: u32,
+    pub loss_percent: f64,
+    pub last_ms: Option<f64>,
+    pub latency: Option<LatencyStats>,
+    #[serde(skip)]
+    rtts: Rtts,
+}
+
+impl MtrHop {
+    fn new(ttl: u8) -> MtrHop {
+        MtrHop { ttl, ips: Vec::new(), hostname: None, sent: 0, received: 0, loss_percent: 0.0, last_ms: None, latency: None, rtts: Rtts::default() }
+    }
+}
+
+/// Round-trip times of one hop: min, max, average and deviation over every reply,
+/// percentiles over the last `stats::WINDOW`, so a run of days keeps a fixed size.
+#[derive(Debug, Clone, Default)]
+struct Rtts {
+    count: usize,
+    min: f64,
+    max: f64,
+    sum: f64,
+    sum_squares: f64,
+    recent: VecDeque<f64>,
+}
+
+impl Rtts {
+    fn push(&mut self, rtt: f64) {
+        self.min = if self.count == 0 { rtt } else { self.min.min(rtt) };
+        self.max = self.max.max(rtt);
+        self.count += 1;
+        self.sum += rtt;
+        self.sum_squares += rtt * rtt;
+        if self.recent.len() == stats::WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(rtt);
+    }
+
+    fn stats(&self) -> Option<LatencyStats> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as f64;
+        let avg = self.sum / n;
+        let recent: Vec<f64> = self.recent.iter().copied().collect();
+        Some(LatencyStats {
+            samples: self.count,
+            min_ms: self.min,
+            avg_ms: avg,
+            max_ms: self.max,
+            p50_ms: stats::percentile(&recent, 50.0)?,
+            p95_ms: stats::percentile(&recent, 95.0)?,
+            p99_ms: stats::percentile(&recent, 99.0)?,
+            stddev_ms: (self.sum_squares / n - avg * avg).max(0.0).sqrt(),
+        })
+    }
+}
+
+/// Resolve `target` and probe every hop of the path round after round, calling
+/// `on_round` with the statistics so far, until `options.rounds` or `stop` is set.
+pub fn run(target: &str, options: &MtrOptions, stop: &AtomicBool, mut on_round: impl FnMut(&MtrResult)) -> Result<MtrResult, ProbeError> {
+    let url = probe::parse_target(target)?;
+    let host = url.host_str().unwrap_or("").to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let (dns_result, resolved) = dns::resolve_system_blocking(&host, port);
+    let dest = match resolved.first() {
+        Some(addr) => addr.ip(),
+        None => return Err(ProbeError::Trace(dns_result.error.unwrap_or_else(|| "DNS resolution failed".to_string()))),
+    };
+
+    let receiver = trace::open_receiver(dest).map_err(|e| ProbeError::Trace(e.to_string()))?;
+    let sender = trace::open_sender(dest, options.protocol).map_err(|e| ProbeError::Trace(e.to_string()))?;
+    let socket = sender.as_ref().unwrap_or(&receiver);
+    let ident = std::process::id() as u16;
+
+    let mut result = MtrResult {
+        target: target.to_string(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+        destination: dest.to_string(),
+        protocol: options.protocol,
+        rounds: 0,
+        reached: false,
+        hops: (1..=options.max_hops).map(MtrHop::new).collect(),
+    };
+    // Distance of the destination, once it answered.
+    let mut distance: Option<u8> = None;
+    let resolver = options.resolve_names.then(reverse_resolver);
+    let mut asked: HashSet<IpAddr> = HashSet::new();
+    let mut names: HashMap<IpAddr, String> = HashMap::new();
+    let mut seq: u16 = 0;
+
+    while options.rounds.is_none_or(|rounds| result.rounds < rounds) && !stop.load(Ordering::Relaxed) {
+        let started = Instant::now();
+        // Sequence number of each probe of the round, with its hop and when it left.
+        let mut pending: HashMap<u16, (usize, Instant)> = HashMap::new();
+        for ttl in 1..=distance.unwrap_or(options.max_hops) {
+            seq = seq.wrapping_add(1);
+            let hop = &mut result.hops[ttl as usize - 1];
+            hop.sent += 1;
+            let sent = trace::set_hop_limit(socket, dest, ttl).and_then(|_| trace::send_probe(socket, dest, options.protocol, ident, seq));
+            if sent.is_ok() {
+                pending.insert(seq, (ttl as usize - 1, Instant::now()));
+            }
+        }
+
+        let deadline = Instant::now() + options.timeout;
+        while !pending.is_empty() {
+            let Some((answered, reply, from)) = receive(&receiver, dest, options.protocol, ident, deadline) else {
+                break;
+            };
+            let Some((index, sent_at)) = pending.remove(&answered) else {
+                continue;
+            };
+            let hop = &mut result.hops[index];
+            let rtt = sent_at.elapsed().as_secs_f64() * 1000.0;
+            hop.received += 1;
+            hop.last_ms = Some(rtt);
+            hop.rtts.push(rtt);
+            if !hop.ips.contains(&from.to_string()) {
+                hop.ips.push(from.to_string());
+            }
+            if let (Some((ask, _)), true) = (&resolver, asked.insert(from)) {
+                let _ = ask.send(from);
+            }
+            if matches!(reply, Reply::Destination(_)) && distance.is_none_or(|d| hop.ttl < d) {
+                distance = Some(hop.ttl);
+            }
+        }
+
+        // The names found since the last round; the others show up in a later one.
+        if let Some((_, answers)) = &resolver {
+            names.extend(answers.try_iter().filter_map(|(ip, name)| Some((ip, name?))));
+            name_hops(&mut result.hops, &names);
+        }
+
+        result.rounds += 1;
+        if let Some(d) = distance {
+            result.reached = true;
+            result.hops.truncate(d as usize);
+        }
+        for hop in &mut result.hops {
+            hop.loss_percent = if hop.sent == 0 { 0.0 } else { (hop.sent - hop.received) as f64 * 100.0 / hop.sent as f64 };
+            hop.latency = hop.rtts.stats();
+        }
+        on_round(&shown(&result));
+
+        let finished = options.rounds.is_some_and(|rounds| result.rounds >= rounds);
+        if !finished {
+            // Sleep in short steps so that a stop request is seen quickly.
+            while started.elapsed() < options.interval && !stop.load(Ordering::Relaxed) {
+                std::thread::sleep(options.interval.saturating_sub(started.elapsed()).min(Duration::from_millis(100)));
+            }
+        }
+    }
+
+    // A run of a set number of rounds waits for the lookups still under way; a stopped one does not.
+    if let Some((ask, answers)) = resolver {
+        drop(ask);
+        match stop.load(Ordering::Relaxed) {
+            true => names.extend(answers.try_iter().filter_map(|(ip, name)| Some((ip, name?)))),
+            false => names.extend(answers.iter().filter_map(|(ip, name)| Some((ip, name?)))),
+        }
+        name_hops(&mut result.hops, &names);
+    }
+    Ok(shown(&result))
+}
+
+/// Name each hop still without one after the first of its addresses that has a name.
+fn name_hops(hops: &mut [MtrHop], names: &HashMap<IpAddr, String>) {
+    for hop in hops.iter_mut().filter(|hop| hop.hostname.is_none()) {
+        hop.hostname = hop.ips.iter().find_map(|ip| names.get(&ip.parse().ok()?).cloned());
+    }
+}
+
+/// A thread that looks up the PTR name of each address sent to it, and sends back what it found.
+/// It ends when the sender is dropped.
+fn reverse_resolver() -> (mpsc::Sender<IpAddr>, mpsc::Receiver<(IpAddr, Option<String>)>) {
+    let (ask, questions) = mpsc::channel();
+    let (tell, answers) = mpsc::channel();
+    std::thread::spawn(move || {
+        for ip in questions {
+            if tell.send((ip, dns::reverse_lookup(ip))).is_err() {
+                break;
+            }
+        }
+    });
+    (ask, answers)
+}
+
+/// `result` without the silent hops past the farthest one that answered, while the destination has not.
+fn shown(result: &MtrResult) -> MtrResult {
+    let mut shown = result.clone();
+    if !shown.reached {
+        let farthest = shown.hops.iter().rposition(|hop| hop.received > 0).map_or(0, |i| i + 1);
+        shown.hops.truncate(farthest);
+    }
+    shown
+}
+
+/// Read ICMP messages until one answers a probe of ours or `deadline` passes:
+/// its sequence number, what it says, and who sent it.
+fn receive(receiver: &socket2::Socket, dest: IpAddr, protocol: TraceProtocol, ident: u16, deadline: Instant) -> Option<(u16, Reply, IpAddr)> {
+    let mut buf = [MaybeUninit::<u8>::uninit(); 1500];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return None;
+        }
+        receiver.set_read_timeout(Some(remaining)).ok()?;
+        let (n, from) = match receiver.recv_from(&mut buf) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => continue,
+            Err(_) => return None,
+        };
+        // SAFETY: recv_from initialized the first `n` bytes.
+        let packet: Vec<u8> = buf[..n].iter().map(|b| unsafe { b.assume_init() }).collect();
+        let Some(from) = from.as_socket().map(|a| a.ip()) else { continue };
+        if let Some((seq, reply)) = trace::answer(&packet, dest, from, protocol, ident) {
+            return Some((seq, reply, from));
+        }
+    }
+}
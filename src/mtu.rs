@@ -0,0 +1,343 @@
+//! Path MTU discovery (`netprobe mtu`).
+//!
+//! Packets are sent with the Don't Fragment bit set (IPv6 routers never
+//! fragment) and the largest size that still reaches the target is found by
+//! binary search. A router that cannot forward a packet answers "fragmentation
+//! needed" (IPv4) or "packet too big" (IPv6) with its next-hop MTU; when the
+//! larger packets vanish without that error, the path is a PMTUD black hole.
+//! Replies are read on a raw ICMP socket, so this requires the same privileges
+//! as `netprobe trace`.
+
+use crate::probe::{self, dns, icmp, ProbeError};
+use crate::trace::TraceProtocol;
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Destination port of UDP probes, as in classic traceroute.
+const UDP_PORT: u16 = 33434;
+
+// ICMP "unreachable" messages, and those refusing a packet for its size.
+const DEST_UNREACHABLE_V4: u8 = 3;
+const FRAGMENTATION_NEEDED_V4: u8 = 4;
+const DEST_UNREACHABLE_V6: u8 = 1;
+const PACKET_TOO_BIG_V6: u8 = 2;
+
+#[derive(Debug, Clone)]
+pub struct MtuOptions {
+    pub protocol: TraceProtocol,
+    /// Largest packet size tried, IP header included.
+    pub max_size: u16,
+    /// Probes sent at a size before it is given up as dropped.
+    pub attempts: u8,
+    /// How long to wait for each probe's reply.
+    pub timeout: Duration,
+    /// Look up the PTR name of the router that limits the path.
+    pub resolve_names: bool,
+}
+
+impl Default for MtuOptions {
+    fn default() -> Self {
+        MtuOptions { protocol: TraceProtocol::Icmp, max_size: 9000, attempts: 2, timeout: Duration::from_secs(2), resolve_names: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MtuResult {
+    pub target: String,
+    pub timestamp: String,
+    pub destination: String,
+    pub protocol: TraceProtocol,
+    /// Largest packet, IP header included, that reached the destination unfragmented.
+    pub path_mtu: u16,
+    /// Where the larger packets stop; `None` when `max_size` itself got through.
+    pub limit: Option<MtuLimit>,
+    /// Every size tried, in order.
+    pub probes: Vec<MtuProbe>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MtuLimit {
+    /// "router" (it answered fragmentation needed / packet too big) | "interface" (our own
+    /// interface's MTU) | "silent" (dropped without an ICMP error: a PMTUD black hole)
+    pub kind: String,
+    /// Router that refused the packet.
+    pub ip: Option<String>,
+    pub hostname: Option<String>,
+    /// MTU of the next link, as reported by the router.
+    pub next_hop_mtu: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MtuProbe {
+    pub size: u16,
+    pub outcome: String, // "ok" | "too-big" | "timeout"
+    /// Router that refused the packet for its size.
+    pub from: Option<String>,
+    /// Round-trip time of the answer (echo reply, port unreachable or the router's error).
+    pub rtt_ms: Option<f64>,
+}
+
+/// What became of a probe of a given size.
+enum Outcome {
+    Fits(f64),
+    /// Refused by a router (its address and reported MTU), or by our own interface.
+    TooBig(Option<IpAddr>, Option<u16>, f64),
+    Local,
+    Timeout,
+}
+
+/// Resolve `target` and search for the largest packet that reaches it unfragmented.
+pub fn discover(target: &str, options: &MtuOptions) -> Result<MtuResult, ProbeError> {
+    let url = probe::parse_target(target)?;
+    let host = url.host_str().unwrap_or("").to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let (dns_result, resolved) = dns::resolve_system_blocking(&host, port);
+    let dest = match resolved.first() {
+        Some(addr) => addr.ip(),
+        None => return Err(ProbeError::Mtu(dns_result.error.unwrap_or_else(|| "DNS resolution failed".to_string()))),
+    };
+
+    let receiver = open_receiver(dest).map_err(|e| ProbeError::Mtu(e.to_string()))?;
+    let sender = match options.protocol {
+        // ICMP echoes go out on the receiving socket itself.
+        TraceProtocol::Icmp => None,
+        TraceProtocol::Udp => {
+            let domain = if dest.is_ipv4() { Domain::IPV4 } else { Domain::IPV6 };
+            let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP)).map_err(|e| ProbeError::Mtu(e.to_string()))?;
+            let any: SocketAddr = if dest.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+            socket.bind(&any.into()).map_err(|e| ProbeError::Mtu(e.to_string()))?;
+            Some(socket)
+        },
+    };
+    let socket = sender.as_ref().unwrap_or(&receiver);
+    set_dont_fragment(socket, dest).map_err(|e| ProbeError::Mtu(e.to_string()))?;
+
+    let mut result = MtuResult {
+        target: target.to_string(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+        destination: dest.to_string(),
+        protocol: options.protocol,
+        path_mtu: 0,
+        limit: None,
+        probes: Vec::new(),
+    };
+    let mut search = Search { receiver: &receiver, socket, dest, options, ident: std::process::id() as u16, seq: 0 };
+
+    // The smallest packet every link must carry (RFC 791, RFC 8200) has to get through first.
+    let (mut low, mut high) = (if dest.is_ipv4() { 68 } else { 1280 }, options.max_size);
+    if !matches!(search.send(low, &mut result), Outcome::Fits(_)) {
+        return Err(ProbeError::Mtu(format!("No answer from {} even to {}-byte packets", dest, low)));
+    }
+    // A router's reported MTU is tried next, when it is plausible: usually it is the answer.
+    let mut hint = None;
+    while low < high {
+        let hinted = hint.take();
+        let size = hinted.unwrap_or(low + (high - low).div_ceil(2));
+        match search.send(size, &mut result) {
+            // Taking the router at its word: one byte more would be refused again.
+            Outcome::Fits(_) if hinted.is_some() => (low, high) = (size, size),
+            Outcome::Fits(_) => low = size,
+            Outcome::TooBig(ip, next_hop_mtu, _) => {
+                high = size - 1;
+                match next_hop_mtu.filter(|mtu| (low..size).contains(mtu)) {
+                    // Already known to get through: the path MTU is found.
+                    Some(mtu) if mtu == low => high = low,
+                    mtu => hint = mtu,
+                }
+                result.limit = Some(MtuLimit { kind: "router".to_string(), ip: ip.map(|ip| ip.to_string()), hostname: None, next_hop_mtu });
+            },
+            Outcome::Local => {
+                high = size - 1;
+                result.limit = Some(MtuLimit { kind: "interface".to_string(), ip: None, hostname: None, next_hop_mtu: None });
+            },
+            Outcome::Timeout => {
+                high = size - 1;
+                result.limit = Some(MtuLimit { kind: "silent".to_string(), ip: None, hostname: None, next_hop_mtu: None });
+            },
+        }
+    }
+    result.path_mtu = low;
+
+    if let Some(limit) = result.limit.as_mut().filter(|_| options.resolve_names) {
+        limit.hostname = limit.ip.as_ref().and_then(|ip| ip.parse().ok()).and_then(dns::reverse_lookup);
+    }
+    Ok(result)
+}
+
+/// Sends the probes and matches the answers to them.
+struct Search<'a> {
+    receiver: &'a Socket,
+    socket: &'a Socket,
+    dest: IpAddr,
+    options: &'a MtuOptions,
+    ident: u16,
+    seq: u16,
+}
+
+impl Search<'_> {
+    /// Send a `size`-byte packet (up to `attempts` times) and record what became of it.
+    fn send(&mut self, size: u16, result: &mut MtuResult) -> Outcome {
+        let ip_header = if self.dest.is_ipv4() { 20 } else { 40 };
+        // ICMP echo and UDP headers are both 8 bytes.
+        let payload = size as usize - ip_header - 8;
+        let mut outcome = Outcome::Timeout;
+        for _ in 0..self.options.attempts.max(1) {
+            self.seq = self.seq.wrapping_add(1);
+            let sent_at = Instant::now();
+            let sent = match self.options.protocol {
+                TraceProtocol::Icmp => self.socket.send_to(&icmp::echo_request(self.dest, self.ident, self.seq, payload), &SocketAddr::new(self.dest, 0).into()),
+                TraceProtocol::Udp => self.socket.send_to(&vec![0u8; payload], &SocketAddr::new(self.dest, UDP_PORT.wrapping_add(self.seq)).into()),
+            };
+            outcome = match sent {
+                // Larger than our own interface's MTU: the kernel refuses to send it.
+                Err(e) if exceeds_interface(&e) => Outcome::Local,
+                Err(_) => Outcome::Timeout,
+                Ok(_) => self.wait_for_reply(sent_at),
+            };
+            if !matches!(outcome, Outcome::Timeout) {
+                break;
+            }
+        }
+        let (name, from, rtt) = match outcome {
+            Outcome::Fits(rtt) => ("ok", None, Some(rtt)),
+            Outcome::TooBig(from, _, rtt) => ("too-big", from, Some(rtt)),
+            Outcome::Local => ("too-big", None, None),
+            Outcome::Timeout => ("timeout", None, None),
+        };
+        tracing::debug!(size, outcome = name, "mtu probe");
+        result.probes.push(MtuProbe { size, outcome: name.to_string(), from: from.map(|ip| ip.to_string()), rtt_ms: rtt });
+        outcome
+    }
+
+    /// Read ICMP messages until one answers the current probe or the timeout expires.
+    fn wait_for_reply(&self, sent_at: Instant) -> Outcome {
+        let mut buf = [MaybeUninit::<u8>::uninit(); 65_536];
+        loop {
+            let remaining = self.options.timeout.saturating_sub(sent_at.elapsed());
+            if remaining.is_zero() || self.receiver.set_read_timeout(Some(remaining)).is_err() {
+                return Outcome::Timeout;
+            }
+            let (n, from) = match self.receiver.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => continue,
+                Err(_) => return Outcome::Timeout,
+            };
+            // SAFETY: recv_from initialized the first `n` bytes.
+            let packet: Vec<u8> = buf[..n].iter().map(|b| unsafe { b.assume_init() }).collect();
+            let Some(from) = from.as_socket().map(|a| a.ip()) else { continue };
+            let rtt = sent_at.elapsed().as_secs_f64() * 1000.0;
+            match self.classify(&packet, from) {
+                Some(Ok(())) => return Outcome::Fits(rtt),
+                Some(Err(mtu)) => return Outcome::TooBig(Some(from), mtu, rtt),
+                None => continue,
+            }
+        }
+    }
+
+    /// Whether `packet` (as read from the raw socket) answers the current probe: `Ok` when
+    /// the destination got it, `Err` with the next-hop MTU when a router refused it for its size.
+    fn classify(&self, packet: &[u8], from: IpAddr) -> Option<Result<(), Option<u16>>> {
+        let v4 = self.dest.is_ipv4();
+        let message = icmp::icmp_payload(packet, v4)?;
+        let (kind, code) = (*message.first()?, *message.get(1)?);
+
+        if kind == if v4 { icmp::ECHO_REPLY_V4 } else { icmp::ECHO_REPLY_V6 } {
+            let ours = self.options.protocol == TraceProtocol::Icmp
+                && from == self.dest
+                && message.len() >= 8
+                && u16::from_be_bytes([message[4], message[5]]) == self.ident
+                && u16::from_be_bytes([message[6], message[7]]) == self.seq;
+            return ours.then_some(Ok(()));
+        }
+        let too_big = if v4 { kind == DEST_UNREACHABLE_V4 && code == FRAGMENTATION_NEEDED_V4 } else { kind == PACKET_TOO_BIG_V6 };
+        // Any other "unreachable" from the destination (port unreachable) means the UDP probe arrived.
+        let arrived = !too_big && kind == if v4 { DEST_UNREACHABLE_V4 } else { DEST_UNREACHABLE_V6 } && from == self.dest;
+        if !too_big && !arrived {
+            return None;
+        }
+
+        // Error messages quote the offending packet after the 8-byte ICMP header.
+        let quoted = message.get(8..)?;
+        let (inner_proto, inner) = if v4 {
+            let ihl = (*quoted.first()? & 0x0f) as usize * 4;
+            (*quoted.get(9)?, quoted.get(ihl..)?)
+        } else {
+            (*quoted.get(6)?, quoted.get(40..)?)
+        };
+        if inner.len() < 8 {
+            return None;
+        }
+        let ours = match self.options.protocol {
+            TraceProtocol::Icmp => {
+                inner_proto == if v4 { 1 } else { 58 } && u16::from_be_bytes([inner[4], inner[5]]) == self.ident && u16::from_be_bytes([inner[6], inner[7]]) == self.seq
+            },
+            TraceProtocol::Udp => inner_proto == 17 && u16::from_be_bytes([inner[2], inner[3]]) == UDP_PORT.wrapping_add(self.seq),
+        };
+        if !ours {
+            return None;
+        }
+        if arrived {
+            return Some(Ok(()));
+        }
+        // IPv4 carries the next-hop MTU in bytes 6-7 (RFC 1191), IPv6 in bytes 4-7; 0 when the router does not say.
+        let mtu = if v4 { u16::from_be_bytes([message[6], message[7]]) as u32 } else { u32::from_be_bytes([message[4], message[5], message[6], message[7]]) };
+        Some(Err(u16::try_from(mtu).ok().filter(|mtu| *mtu > 0)))
+    }
+}
+
+/// Raw ICMP socket on which the answers arrive.
+fn open_receiver(dest: IpAddr) -> io::Result<Socket> {
+    let (domain, protocol) = if dest.is_ipv4() { (Domain::IPV4, Protocol::ICMPV4) } else { (Domain::IPV6, Protocol::ICMPV6) };
+    Socket::new(domain, Type::RAW, Some(protocol)).map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => io::Error::new(e.kind(), icmp::PRIVILEGE_HINT),
+        _ => e,
+    })
+}
+
+/// Set Don't Fragment and stop the kernel from applying the path MTU it has cached,
+/// so every size tried really goes out as one packet (`IP_PMTUDISC_PROBE`).
+#[cfg(target_os = "linux")]
+fn set_dont_fragment(socket: &Socket, dest: IpAddr) -> io::Result<()> {
+    if dest.is_ipv4() {
+        set_option(socket, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_PROBE)
+    } else {
+        // IPv6 leaves fragmentation to the sender: without IPV6_DONTFRAG the kernel would split the packet itself.
+        set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, libc::IPV6_PMTUDISC_PROBE)?;
+        set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG, 1)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_option(socket: &Socket, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    // SAFETY: `value` is a c_int that outlives the call, and its size is passed alongside it.
+    let rc = unsafe {
+        libc::setsockopt(socket.as_raw_fd(), level, name, &value as *const libc::c_int as *const libc::c_void, std::mem::size_of::<libc::c_int>() as libc::socklen_t)
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Whether a send failed because the packet is larger than our interface's MTU.
+#[cfg(target_os = "linux")]
+fn exceeds_interface(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EMSGSIZE)
+}
+
+/// Don't Fragment is only set on Linux.
+#[cfg(not(target_os = "linux"))]
+fn set_dont_fragment(_socket: &Socket, _dest: IpAddr) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "path MTU discovery is only supported on Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exceeds_interface(_e: &io::Error) -> bool {
+    false
+}
@@ -0,0 +1,1936 @@
+//! Rendering of probe results for humans (colored) and machines (JSON).
+
+use crate::audit::AuditResult;
+use crate::diff::{Change, DiffReport};
+use crate::mail::MailResult;
+use crate::sweep::{SweepOptions, SweepResult};
+#[cfg(feature = "history")]
+use crate::history::HistoryReport;
+use crate::probe::stacks::StackResult;
+use crate::probe::http::HttpTiming;
+use crate::probe::source::dscp_name;
+use crate::probe::{exit, Attempt, DnsResult, GeoResult, PortResult, ProbeResult, QualityResult, RaceResult, ServiceResult};
+use crate::resolver::bench::Bench;
+use crate::resolver::dnssec::DnssecResult;
+use crate::resolver::iterative::DnsTrace;
+use crate::resolver::propagation::Propagation;
+use crate::resolver::records::RecordLookup;
+use crate::resolver::ttl::{TtlSample, TtlWatch};
+use crate::stats::SummarySnapshot;
+use crate::mtr::{MtrOptions, MtrResult};
+use crate::mtu::{MtuOptions, MtuResult};
+use crate::trace::{TraceProtocol, TraceResult};
+use crate::whois::WhoisResult;
+use colored::*;
+use serde_json::json;
+use std::io::Write;
+use url::Url;
+
+const SEPARATOR: &str = "--------------------------------------------------";
+
+/// How much of a result the human output shows (`-q`, `-v`, `-vv`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// One status line per target.
+    Quiet,
+    Normal,
+    /// Adds DNS answers, the TLS ClientHello offer and the request/response headers.
+    Verbose,
+    /// Adds raw record data and the certificate chain.
+    Debug,
+}
+
+/// Print the colored, step-by-step report.
+pub fn print_human(result: &ProbeResult, verbosity: Verbosity) {
+    if verbosity == Verbosity::Quiet {
+        print_status_line(result);
+        return;
+    }
+    println!("\n🔍 Probing Target: {}", result.target.bold().cyan());
+    println!("{}", SEPARATOR.dimmed());
+
+    // Steps are numbered as they are printed, so optional stages slot in naturally.
+    let mut step = 0;
+    let mut next_step = || {
+        step += 1;
+        step
+    };
+
+    // DNS
+    let dns = &result.dns;
+    match (&dns.ip, &dns.error) {
+        (Some(ip), _) => {
+            let via = match &dns.server {
+                Some(server) => format!(" via {}{}", server, transport_label(&dns.transport)).dimmed().to_string(),
+                None if dns.transport == "override" => " (--resolve)".dimmed().to_string(),
+                None => String::new(),
+            };
+            println!("{}. DNS Resolution   {} {}{} ({:.2}ms)", next_step(), "✅".green(), ip.yellow(), via, dns.latency_ms.unwrap_or(0.0));
+            let mut details = Vec::new();
+            if dns.ipv4.len() + dns.ipv6.len() > 1 {
+                details.push(format!("IPv4           {}", if dns.ipv4.is_empty() { "-".to_string() } else { dns.ipv4.join(", ") }));
+                details.push(format!("IPv6           {}", if dns.ipv6.is_empty() { "-".to_string() } else { dns.ipv6.join(", ") }));
+            }
+            if let Some(handshake) = dns.handshake_ms {
+                details.push(format!("Handshake      {:.2}ms", handshake));
+            }
+            if let Some(ttl) = dns.ttl {
+                details.push(format!("TTL            {}s", ttl));
+            }
+            if let Some(subnet) = &dns.client_subnet {
+                details.push(format!("Client subnet  {} {}", subnet, ecs_scope(dns.client_subnet_scope).dimmed()));
+            }
+            if let Some(ptr) = &dns.ptr {
+                details.push(format!("PTR            {}", ptr.cyan()));
+            }
+            if let Some(dnssec) = &dns.dnssec {
+                details.push(dnssec_line(dnssec));
+                // The chain explains a failure on its own; a secure one only with -v.
+                if dnssec.status != "secure" || verbosity >= Verbosity::Verbose {
+                    for step in &dnssec.chain {
+                        details.push(format!("  {} {:<6} {} {}", dnssec_icon(&step.status), step.record, step.name, step.detail.dimmed()));
+                    }
+                }
+            }
+            if let Some(geo) = &result.geo {
+                details.push(format!("Geo            {}", geo_summary(geo)));
+            }
+            if verbosity >= Verbosity::Verbose {
+                for answer in &dns.answers {
+                    details.push(format!("Answer         {} {} IN {} {}", answer.name, answer.ttl, answer.rtype, answer.data.cyan()));
+                    if verbosity >= Verbosity::Debug {
+                        details.push(format!("  {}", format!("rdata {}", answer.raw).dimmed()));
+                    }
+                }
+            }
+            if let Some(trace) = &dns.trace {
+                details.extend(dns_trace_lines(trace));
+            }
+            details.extend(attempt_lines(&dns.attempts));
+            print_tree(&details);
+        },
+        (None, Some(e)) if e == "No IP found" => println!("{}. DNS Resolution   {} Failed: No IP found", next_step(), "❌".red()),
+        (None, Some(e)) => println!("{}. DNS Resolution   {} Error: {}", next_step(), "❌".red(), e),
+        (None, None) if dns.status == "interrupted" => println!("{}. DNS Resolution   {} Interrupted", next_step(), "⏹".yellow()),
+        (None, None) => {}
+    }
+    if dns.ip.is_none() {
+        let mut details = dns.trace.as_ref().map(dns_trace_lines).unwrap_or_default();
+        details.extend(attempt_lines(&dns.attempts));
+        print_tree(&details);
+    }
+
+    // ICMP (only with --ping)
+    if let Some(icmp) = &result.icmp {
+        match (icmp.rtt_min_ms, icmp.rtt_avg_ms, icmp.rtt_max_ms) {
+            (Some(min), Some(avg), Some(max)) => {
+                let icon = if icmp.received == icmp.transmitted { "✅".green() } else { "⚠️".yellow() };
+                println!(
+                    "{}. ICMP Ping        {} {}/{} replies, {:.0}% loss, min/avg/max {:.2}/{:.2}/{:.2}ms",
+                    next_step(),
+                    icon,
+                    icmp.received,
+                    icmp.transmitted,
+                    icmp.loss_percent,
+                    min,
+                    avg,
+                    max
+                );
+            },
+            _ => println!("{}. ICMP Ping        {} {}", next_step(), "❌".red(), icmp.error.as_deref().unwrap_or("No reply")),
+        }
+    }
+
+    // TCP (skipped when DNS failed)
+    let tcp = &result.tcp;
+    match tcp.status.as_str() {
+        "ok" => println!("{}. TCP Handshake    {} Port {} Open ({:.2}ms)", next_step(), "✅".green(), tcp.port, tcp.latency_ms.unwrap_or(0.0)),
+        "error" => {
+            let family = match result.dns.family.as_deref() {
+                Some("ipv6") => " (IPv6)",
+                Some("ipv4") => " (IPv4)",
+                _ => "",
+            };
+            let failure = tcp.failure.map_or("Connection Failed", |failure| failure.describe());
+            println!("{}. TCP Handshake    {} {}{}", next_step(), "❌".red(), failure, family)
+        },
+        "interrupted" => println!("{}. TCP Handshake    {} Interrupted", next_step(), "⏹".yellow()),
+        _ => {}
+    }
+    let mut details = attempt_lines(&tcp.attempts);
+    if let Some(source) = &tcp.source {
+        details.push(format!("Source         {}", source));
+    }
+    if let Some(dscp) = tcp.dscp {
+        details.push(format!("DSCP           {} ({})", dscp_name(dscp), dscp));
+    }
+    if let Some(race) = &tcp.happy_eyeballs {
+        details.push(format!("Happy Eyeballs {}", race_line(race, &result.dns)));
+    }
+    for addr in tcp.all_ips.iter().flatten() {
+        match addr.latency_ms {
+            Some(ms) if addr.status == "ok" => details.push(format!("{:<15} {} Open ({:.2}ms)", addr.ip, "✅".green(), ms)),
+            _ => details.push(format!("{:<15} {} {}", addr.ip, "❌".red(), addr.error.as_deref().unwrap_or("Connection failed"))),
+        }
+    }
+    details.extend(port_lines(&tcp.ports));
+    if let Some(quality) = &tcp.quality {
+        details.extend(quality_lines(quality));
+    }
+    match tcp.banner.as_deref() {
+        Some("") => details.push(format!("Banner         {}", "nothing sent (the server waits for the client)".dimmed())),
+        Some(banner) => details.push(format!("Banner         {}", banner)),
+        None => {},
+    }
+    if let Some(service) = &tcp.service {
+        details.extend(service_lines(service));
+    }
+    print_tree(&details);
+
+    // UDP (replaces TCP, TLS and HTTP)
+    if let Some(udp) = &result.udp {
+        let latency = udp.latency_ms.map(|ms| format!(" ({:.2}ms)", ms)).unwrap_or_default();
+        match udp.outcome.as_str() {
+            "reply" if udp.status == "error" => println!("{}. UDP Probe        {} Port {} {}{}", next_step(), "❌".red(), udp.port, udp.error.as_deref().unwrap_or("invalid reply"), latency),
+            "reply" => println!("{}. UDP Probe        {} Port {} replied to {}{}", next_step(), "✅".green(), udp.port, udp.payload, latency),
+            "unreachable" => println!("{}. UDP Probe        {} Port {} {}{}", next_step(), "❌".red(), udp.port, udp.error.as_deref().unwrap_or("unreachable"), latency),
+            _ => println!("{}. UDP Probe        {} Port {} {} (open or filtered)", next_step(), "❌".red(), udp.port, udp.error.as_deref().unwrap_or("no reply")),
+        }
+        // The NTP and SNMP lines below say more than the one-line reply.
+        let mut details: Vec<String> = udp.reply.iter().filter(|_| udp.ntp.is_none() && udp.snmp.is_none()).cloned().collect();
+        if let Some(source) = &udp.source {
+            details.push(format!("Source         {}", source));
+        }
+        if let Some(dscp) = udp.dscp {
+            details.push(format!("DSCP           {} ({})", dscp_name(dscp), dscp));
+        }
+        if let Some(ntp) = &udp.ntp {
+            let offset = format!("{:+.3}ms", ntp.offset_ms);
+            let offset = if ntp.offset_ms.abs() < 100.0 { offset.green() } else { offset.yellow() };
+            details.push(format!("Stratum        {} (reference {})", ntp.stratum, ntp.reference));
+            details.push(format!("Offset         {} {}", offset, "(server clock minus ours)".dimmed()));
+            details.push(format!("Delay          {:.3}ms", ntp.delay_ms));
+            details.push(format!("Root           delay {:.3}ms, dispersion {:.3}ms", ntp.root_delay_ms, ntp.root_dispersion_ms));
+            if ntp.leap != "none" {
+                details.push(format!("Leap           {}", ntp.leap.yellow()));
+            }
+            details.push(format!("Server time    {}", ntp.server_time));
+        }
+        if let Some(snmp) = &udp.snmp {
+            details.push(format!("System         {}", snmp.sys_descr.as_deref().unwrap_or("-")));
+            details.push(format!("Uptime         {}", snmp.uptime_seconds.map(uptime).unwrap_or_else(|| "-".to_string())));
+            details.push(format!("Version        SNMPv{}", snmp.version));
+        }
+        print_tree(&details);
+    }
+
+    // TLS (https only)
+    let tls = &result.tls;
+    match tls.status.as_str() {
+        "ok" => println!(
+            "{}. TLS Handshake    {} {} {} ({:.2}ms)",
+            next_step(),
+            "✅".green(),
+            tls.protocol.as_deref().unwrap_or("?"),
+            tls.cipher_suite.as_deref().unwrap_or("?").dimmed(),
+            tls.latency_ms.unwrap_or(0.0)
+        ),
+        "warning" => println!(
+            "{}. TLS Handshake    {} {} {} ({:.2}ms)",
+            next_step(),
+            "⚠️".yellow(),
+            tls.protocol.as_deref().unwrap_or("?"),
+            tls.cipher_suite.as_deref().unwrap_or("?").dimmed(),
+            tls.latency_ms.unwrap_or(0.0)
+        ),
+        "error" => println!("{}. TLS Handshake    {} Error: {}", next_step(), "❌".red(), tls.error.as_deref().unwrap_or("unknown")),
+        "interrupted" => println!("{}. TLS Handshake    {} Interrupted", next_step(), "⏹".yellow()),
+        _ => {}
+    }
+    let mut details = Vec::new();
+    if let Some(sni) = tls.sni.as_ref().filter(|_| verbosity >= Verbosity::Verbose) {
+        details.push(format!("SNI            {}", sni));
+        let offered = if tls.alpn_offered.is_empty() { "nothing".to_string() } else { tls.alpn_offered.join(", ") };
+        details.push(format!("ALPN           offered {}, agreed {}", offered, tls.alpn.as_deref().unwrap_or("none")));
+        if let Some(stapled) = tls.ocsp_stapled {
+            details.push(format!("OCSP staple    {}", if stapled { "yes" } else { "no" }));
+        }
+    }
+    if let Some(cert) = &tls.certificate {
+        details.push(format!("Subject        {}", cert.subject));
+        details.push(format!("Issuer         {}", cert.issuer));
+        if !cert.sans.is_empty() {
+            details.push(format!("SANs           {}", cert.sans.join(", ")));
+        }
+        let expiry = format!("{} days", cert.days_until_expiry);
+        let expiry = if cert.days_until_expiry < 0 {
+            expiry.red()
+        } else if tls.warning.is_some() {
+            expiry.yellow()
+        } else {
+            expiry.normal()
+        };
+        details.push(format!("Expires        {} ({})", cert.not_after, expiry));
+    }
+    match (tls.verification.as_deref(), &tls.verify_error, &tls.trust_anchor) {
+        (Some("skipped"), Some(e), _) => details.push(format!("Verification   {}", format!("skipped (--insecure), would fail: {}", e).yellow())),
+        (Some("skipped"), None, _) => details.push(format!("Verification   {}", "skipped (--insecure)".yellow())),
+        (Some("ok"), _, Some(anchor)) if tls.trust_store.as_deref() != Some("bundled") => {
+            details.push(format!("Trusted by     {} {}", anchor, format!("({})", tls.trust_store.as_deref().unwrap_or("?")).dimmed()))
+        },
+        _ => {}
+    }
+    match (tls.client_cert_requested, &tls.client_cert) {
+        (Some(true), Some(subject)) => details.push(format!("Client cert    requested, sent {}", subject)),
+        (Some(true), None) => details.push(format!("Client cert    {}", "requested, none sent (--cert)".yellow())),
+        _ => {}
+    }
+    if let Some(revocation) = &tls.revocation {
+        let source = match (revocation.method.as_deref(), &revocation.url) {
+            (Some("ocsp-staple"), _) => " (stapled OCSP)".to_string(),
+            (Some("ocsp"), Some(url)) => format!(" (OCSP {})", url),
+            (Some("crl"), Some(url)) => format!(" (CRL {})", url),
+            _ => String::new(),
+        };
+        let status = match revocation.status.as_str() {
+            "good" => "good".green(),
+            "revoked" => format!("revoked {}", revocation.revoked_at.as_deref().unwrap_or("")).trim_end().red(),
+            "unknown" => "unknown to the responder".yellow(),
+            _ => format!("not checked: {}", revocation.error.as_deref().unwrap_or("unknown error")).yellow(),
+        };
+        details.push(format!("Revocation     {}{}", status, source.dimmed()));
+    }
+    if let Some(ct) = &tls.ct {
+        let summary = match (ct.valid, ct.scts.len()) {
+            (_, 0) => "no SCTs".red(),
+            (Some(0), n) => format!("{} SCT(s), none valid", n).red(),
+            (Some(valid), n) => format!("{} SCT(s), {} verified", n, valid).green(),
+            (None, n) => format!("{} SCT(s) {}", n, "(not verified: --ct-log-list)".dimmed()).normal(),
+        };
+        details.push(format!("CT             {}", summary));
+        for sct in &ct.scts {
+            let mark = match sct.verified {
+                Some(true) => "✔".green(),
+                Some(false) => "✖".red(),
+                None => "·".dimmed(),
+            };
+            let log = sct.log.clone().unwrap_or_else(|| format!("log {}", sct.log_id));
+            details.push(format!("  {} {} {}", mark, log, format!("({}, {})", sct.source, sct.timestamp.as_deref().unwrap_or("?")).dimmed()));
+        }
+    }
+    if let Some(caa) = &tls.caa {
+        let property = if caa.wildcard { "issuewild" } else { "issue" };
+        let at = caa.domain.as_deref().unwrap_or("");
+        let summary = match caa.status.as_str() {
+            "authorized" => format!("{} allowed by {} {}", caa.issuer_domains.first().map(String::as_str).unwrap_or("issuer"), property, at).green(),
+            "unauthorized" => caa.error.clone().unwrap_or_default().yellow(),
+            "unrestricted" => "no CAA policy, any CA may issue".dimmed(),
+            "unknown" => format!("issuer not recognised; {} {} allows {}", at, property, caa.allowed.join(", ")).yellow(),
+            _ => format!("not checked: {}", caa.error.as_deref().unwrap_or("unknown error")).yellow(),
+        };
+        details.push(format!("CAA            {}", summary));
+    }
+    if let Some(resumption) = &tls.resumption {
+        let summary = match (resumption.resumed, resumption.supported, &resumption.error) {
+            (_, _, Some(e)) => format!("not checked: {}", e).yellow(),
+            (true, _, _) => {
+                let mechanism = resumption.mechanism.as_deref().unwrap_or("session");
+                format!("resumed with {} in {:.2}ms (full: {:.2}ms)", mechanism, resumption.resumed_ms.unwrap_or_default(), resumption.full_ms).green()
+            },
+            (false, true, _) => "session issued but not resumed".yellow(),
+            (false, false, _) => "not supported (no session or ticket issued)".yellow(),
+        };
+        details.push(format!("Resumption     {}", summary));
+        match (resumption.early_data, resumption.max_early_data) {
+            (Some(true), Some(max)) => details.push(format!("0-RTT          {} (up to {})", "allowed".green(), format_bytes(max as u64))),
+            (Some(false), _) => details.push(format!("0-RTT          {}", "not offered".dimmed())),
+            _ => {},
+        }
+    }
+    if !tls.chain.is_empty() {
+        details.push(format!("Chain          {} certificate(s)", tls.chain.len()));
+    }
+    print_tree(&details);
+    for (i, cert) in tls.chain.iter().enumerate() {
+        println!("   [{}] {}", i, cert.subject.bold());
+        print_tree(&[
+            format!("Issuer         {}", cert.issuer),
+            format!("Valid          {} → {}", cert.not_before, cert.not_after),
+            format!("Key            {} {}", cert.key_type, format!("({})", cert.signature_algorithm).dimmed()),
+            format!("SHA-256        {}", cert.sha256_fingerprint.dimmed()),
+        ]);
+        if let Some(pem) = &cert.pem {
+            print!("{}", pem);
+        }
+    }
+    if let Some(warning) = &tls.warning {
+        let warning = if tls.status == "error" { warning.red() } else { warning.yellow() };
+        println!("   {} {}", "⚠".yellow(), warning);
+    }
+
+    // HTTP
+    let http = &result.http;
+    if let Some(code) = http.status_code {
+        let latency = http.latency_ms.unwrap_or(0.0);
+        let n = next_step();
+        match hyper::StatusCode::from_u16(code) {
+            Ok(status) if status.is_success() => println!("{}. HTTP Request     {} Status: {} ({:.2}ms)", n, "✅".green(), status, latency),
+            Ok(status) if status.is_redirection() => println!("{}. HTTP Request     {} Status: {} (Redirect) ({:.2}ms)", n, "⚠️".yellow(), status, latency),
+            Ok(status) => println!("{}. HTTP Request     {} Status: {} ({:.2}ms)", n, "❌".red(), status, latency),
+            Err(_) => println!("{}. HTTP Request     {} Status: {} ({:.2}ms)", n, "❌".red(), code, latency),
+        }
+    } else if let Some(e) = &http.error {
+        println!("{}. HTTP Request     {} Error: {}", next_step(), "❌".red(), e);
+    }
+    let mut details = attempt_lines(&http.attempts);
+    if let Some(proxy) = &http.proxy {
+        details.push(format!("Proxy          {}", proxy.cyan()));
+    }
+    if let Some(auth) = &http.auth {
+        let outcome = if http.status_code == Some(401) { " (rejected)".red() } else { "".normal() };
+        details.push(format!("Auth           {}{}", auth, outcome));
+    }
+    if !http.cookies.is_empty() {
+        details.push(format!("Cookies        {} set ({})", http.cookies.len(), http.cookies.join(", ").dimmed()));
+    }
+    if let Some(version) = &http.version {
+        let alpn = result.tls.alpn.as_deref().map(|alpn| format!(" (ALPN {})", alpn)).unwrap_or_default();
+        details.push(format!("Protocol       {}{}", version, alpn.dimmed()));
+    }
+    if let Some(grpc) = &http.grpc {
+        let service = if grpc.service.is_empty() { "server".to_string() } else { format!("service {}", grpc.service) };
+        let latency = grpc.latency_ms.map(|ms| format!(" ({:.2}ms)", ms)).unwrap_or_default();
+        match (&grpc.serving_status, &grpc.error) {
+            (Some(serving), None) => details.push(format!("gRPC health    {} {}{}", "✅".green(), format!("{} {}", service, serving).green(), latency)),
+            (_, error) => details.push(format!("gRPC health    {} {}{}", "❌".red(), format!("{}: {}", service, error.as_deref().unwrap_or("failed")).red(), latency)),
+        }
+    }
+    // Only worth a line when the request ended up elsewhere than the DNS stage's address (redirects).
+    if let Some(peer) = http.peer_ip.as_ref().filter(|peer| http.proxy.is_none() && result.dns.ip.as_ref() != Some(*peer)) {
+        details.push(format!("Peer           {}", peer.yellow()));
+    }
+    for hop in &http.redirects {
+        details.push(format!("{} {} {} ({:.2}ms)", "↪".yellow(), hop.status.to_string().yellow(), hop.url, hop.latency_ms));
+    }
+    if let (Some(last), Some(code)) = (http.redirects.last(), http.status_code) {
+        let code = if code < 400 { code.to_string().green() } else { code.to_string().red() };
+        details.push(format!("{} {} {}", "✔".green(), code, last.location));
+    }
+    if let Some(security) = &http.security {
+        let grade = match security.grade.as_str() {
+            "A" => security.grade.green(),
+            "B" | "C" => security.grade.yellow(),
+            _ => security.grade.red(),
+        };
+        details.push(format!("Security       {} ({}/100)", grade.bold(), security.score));
+        for finding in &security.findings {
+            let mark = match finding.status.as_str() {
+                "ok" => "✔".green(),
+                "warning" => "⚠".yellow(),
+                _ => "✖".red(),
+            };
+            details.push(format!("  {} {:<26} {}", mark, finding.header, finding.message.dimmed()));
+        }
+    }
+    if let Some(download) = &http.download {
+        details.push(format!("Body           {} in {:.2}ms ({})", format_bytes(download.bytes), download.duration_ms, format!("{:.2} MB/s", download.throughput_mb_s).cyan()));
+    }
+    if let Some(warm) = &http.warm_request {
+        match (warm.warm_ms, &warm.error) {
+            (Some(warm_ms), _) => {
+                let saved = match warm.saved_ms.unwrap_or_default() {
+                    saved if saved >= 0.0 => format!("{:.2}ms saved", saved),
+                    saved => format!("{:.2}ms slower", -saved),
+                };
+                details.push(format!("Warm           {:.2}ms → {} on the same connection ({})", warm.cold_ms, format!("{:.2}ms", warm_ms).green(), saved));
+            },
+            (None, error) => details.push(format!("Warm           {}", error.as_deref().unwrap_or("failed").red())),
+        }
+    }
+    if let Some(timing) = &http.timing {
+        details.extend(waterfall(timing));
+    }
+    if let Some(request) = http.request.as_ref().filter(|_| verbosity >= Verbosity::Verbose) {
+        details.push(format!("{} {} {}", ">".dimmed(), request.method, request.uri));
+        details.extend(request.headers.iter().map(|(name, value)| format!("{} {}: {}", ">".dimmed(), name, value)));
+        if let Some(headers) = &http.response_headers {
+            details.extend(headers.iter().map(|(name, value)| format!("{} {}: {}", "<".dimmed(), name, String::from_utf8_lossy(value.as_bytes()))));
+        }
+    }
+    print_tree(&details);
+
+    // WebSocket (instead of the HTTP request with --ws)
+    if let Some(ws) = &result.websocket {
+        match (ws.status.as_str(), ws.handshake_ms) {
+            ("ok", Some(ms)) => println!("{}. WebSocket        {} 101 Switching Protocols ({:.2}ms)", next_step(), "✅".green(), ms),
+            _ => println!("{}. WebSocket        {} {}", next_step(), "❌".red(), ws.error.as_deref().unwrap_or("Upgrade failed")),
+        }
+        let mut details = vec![format!("URL            {}", ws.url)];
+        if let Some(protocol) = &ws.protocol {
+            details.push(format!("Subprotocol    {}", protocol));
+        }
+        if let Some(ms) = ws.ping_ms {
+            details.push(format!("Ping           pong in {}", format!("{:.2}ms", ms).green()));
+        }
+        if ws.messages_received > 0 {
+            details.push(format!("Messages       {} received before closing", ws.messages_received));
+        }
+        if let Some(close) = &ws.close {
+            let code = close.code.map(|code| format!(" {}", code)).unwrap_or_default();
+            let reason = close.reason.as_deref().map(|reason| format!(" \"{}\"", reason)).unwrap_or_default();
+            let summary = match (close.behavior.as_str(), close.initiated_by.as_str()) {
+                ("clean", "server") => format!("closed by the server{}{}", code, reason).yellow(),
+                ("clean", _) => format!("clean{}{}", code, reason).green(),
+                ("dropped", _) => "connection dropped without a close frame".yellow(),
+                _ => "no answer to the close frame".yellow(),
+            };
+            details.push(format!("Close          {} ({:.2}ms)", summary, close.latency_ms));
+        }
+        print_tree(&details);
+    }
+
+    // HTTP/3 (only with --http3)
+    if let Some(h3) = &result.http3 {
+        let advertised = if h3.advertised { "advertised".green() } else { "not advertised".yellow() };
+        match (h3.status.as_str(), h3.status_code) {
+            ("ok", Some(code)) => println!("{}. HTTP/3           {} {} on UDP {} ({})", next_step(), "✅".green(), code, h3.port, advertised),
+            _ => println!("{}. HTTP/3           {} {} ({})", next_step(), "❌".red(), h3.error.as_deref().unwrap_or("unavailable"), advertised),
+        }
+        let mut details: Vec<String> = h3.alt_svc.iter().map(|alt_svc| format!("Alt-Svc        {}", alt_svc)).collect();
+        if let (Some(quic), Some(tcp_tls)) = (h3.handshake_ms, h3.tcp_tls_ms) {
+            details.push(format!("Setup          QUIC {:.2}ms vs TCP+TLS {:.2}ms", quic, tcp_tls));
+        }
+        if let (Some(h3_ms), Some(http_ms)) = (h3.latency_ms, h3.http_latency_ms) {
+            let version = h3.http_version.as_deref().unwrap_or("HTTP");
+            details.push(format!("First byte     HTTP/3 {:.2}ms vs {} {:.2}ms", h3_ms, version, http_ms));
+        }
+        print_tree(&details);
+    }
+
+    // CDN (only with --detect-cdn)
+    if let Some(cdn) = &result.cdn {
+        let edge = match (&cdn.provider, &cdn.waf) {
+            (Some(provider), Some(waf)) if provider != waf => format!("{} (WAF: {})", provider.cyan(), waf.cyan()),
+            (Some(provider), _) => provider.cyan().to_string(),
+            (None, Some(waf)) => format!("no CDN (WAF: {})", waf.cyan()),
+            (None, None) => "none detected".yellow().to_string(),
+        };
+        println!("{}. CDN / WAF        {}", next_step(), edge);
+        let mut details = Vec::new();
+        if !cdn.cname_chain.is_empty() {
+            details.push(format!("CNAME          {}", cdn.cname_chain.join(" → ")));
+        }
+        details.extend(cdn.evidence.iter().map(|evidence| format!("{} {}", "·".dimmed(), evidence.dimmed())));
+        print_tree(&details);
+    }
+
+    // Assertions (only with --expect-*)
+    if !result.assertions.is_empty() {
+        let failed = result.assertions.iter().filter(|a| !a.passed).count();
+        let total = result.assertions.len();
+        if failed == 0 {
+            println!("{}. Assertions       {} {}/{} passed", next_step(), "✅".green(), total, total);
+        } else {
+            println!("{}. Assertions       {} {}/{} failed", next_step(), "❌".red(), failed, total);
+        }
+        let lines: Vec<String> = result
+            .assertions
+            .iter()
+            .map(|a| {
+                let icon = if a.passed { "✔".green() } else { "✖".red() };
+                format!("{} {:<14} expected {}, got {}", icon, a.name, a.expected, a.actual.as_deref().unwrap_or("nothing"))
+            })
+            .collect();
+        print_tree(&lines);
+    }
+
+    // Latency budgets (only with --max-*)
+    if !result.latency_checks.is_empty() {
+        let degraded = result.latency_checks.iter().filter(|c| c.status == "degraded").count();
+        if degraded == 0 {
+            println!("{}. Latency Budget   {} All stages within limits", next_step(), "✅".green());
+        } else {
+            println!("{}. Latency Budget   {} {} stage(s) degraded", next_step(), "⚠️".yellow(), degraded);
+        }
+        let lines: Vec<String> = result
+            .latency_checks
+            .iter()
+            .map(|c| {
+                let latency = c.latency_ms.map(|ms| format!("{:.2}ms", ms)).unwrap_or_else(|| "-".to_string());
+                let latency = match c.status.as_str() {
+                    "degraded" => latency.yellow(),
+                    "ok" => latency.green(),
+                    _ => latency.dimmed(),
+                };
+                format!("{:<6} {:>10} / {}ms", c.stage.to_uppercase(), latency, c.limit_ms)
+            })
+            .collect();
+        print_tree(&lines);
+    }
+
+    // Dual-stack comparison (only with --compare-stacks)
+    if let Some(stacks) = &result.stacks {
+        println!("{}", SEPARATOR.dimmed());
+        println!("{}", "   Stack  Status  DNS        TCP        TLS        HTTP".bold());
+        print_stack_row("IPv4", &stacks.ipv4);
+        print_stack_row("IPv6", &stacks.ipv6);
+        for (name, stack) in [("IPv4", &stacks.ipv4), ("IPv6", &stacks.ipv6)] {
+            if let Some(e) = &stack.error {
+                println!("   {} {} {}", "⚠".yellow(), name, e.dimmed());
+            }
+        }
+    }
+
+    // Sample statistics (only with --count)
+    if let Some(stats) = &result.stats {
+        println!("{}", SEPARATOR.dimmed());
+        let failed = format!("{} failed ({:.1}%)", stats.failures, stats.failure_rate);
+        let failed = if stats.failures == 0 { failed.green() } else { failed.red() };
+        println!("   {} samples, {}; details above are from the last one", stats.count, failed);
+        println!("{}", "   Stage  Min       Avg       Max       p50       p95       p99       StdDev".bold());
+        for (name, stage) in [("DNS", &stats.dns), ("TCP", &stats.tcp), ("TLS", &stats.tls), ("HTTP", &stats.http), ("Total", &stats.total)] {
+            if let Some(s) = stage {
+                let ms = |v: f64| format!("{:.2}ms", v);
+                println!(
+                    "   {:<6} {:<9} {:<9} {:<9} {:<9} {:<9} {:<9} {}",
+                    name,
+                    ms(s.min_ms),
+                    ms(s.avg_ms),
+                    ms(s.max_ms),
+                    ms(s.p50_ms),
+                    ms(s.p95_ms),
+                    ms(s.p99_ms),
+                    ms(s.stddev_ms)
+                );
+            }
+        }
+    }
+
+    println!("{}", SEPARATOR.dimmed());
+}
+
+/// `-q`: the verdict, the first error (or the HTTP status) and the total time, on one line.
+fn print_status_line(result: &ProbeResult) {
+    let code = result.exit_code();
+    let icon = match (code, result.is_success()) {
+        (exit::OK, _) => "✅".green(),
+        (_, true) => "⚠️".yellow(),
+        (_, false) => "❌".red(),
+    };
+    let service_error = result.tcp.service.as_ref().and_then(|service| service.error.clone());
+    let grpc_error = result.http.grpc.as_ref().and_then(|grpc| grpc.error.clone());
+    let websocket_error = result.websocket.as_ref().and_then(|ws| ws.error.clone());
+    let errors = [&result.dns.error, &result.tcp.error, &service_error, &result.tls.error, &result.http.error, &grpc_error, &websocket_error];
+    let detail = match (errors.into_iter().find_map(|e| e.as_deref()), result.http.status_code, &result.udp, &result.tcp.service) {
+        (Some(e), _, _, _) => e.to_string(),
+        (None, _, _, _) if result.websocket.is_some() => "WebSocket 101".to_string(),
+        (None, Some(_), _, _) if result.http.grpc.is_some() => format!("gRPC {}", result.http.grpc.as_ref().and_then(|grpc| grpc.serving_status.as_deref()).unwrap_or_default()),
+        (None, Some(status), _, _) => format!("HTTP {}", status),
+        (None, None, Some(udp), _) => match &udp.ntp {
+            Some(ntp) => format!("NTP stratum {}, offset {:+.3}ms", ntp.stratum, ntp.offset_ms),
+            None if udp.snmp.is_some() => format!("SNMP {}", udp.snmp.as_ref().and_then(|snmp| snmp.sys_descr.as_deref()).and_then(|descr| descr.lines().next()).unwrap_or("response")),
+            None if udp.payload == "sip" => udp.reply.clone().unwrap_or_else(|| format!("UDP {}", udp.outcome)),
+            None => format!("UDP {}", udp.outcome),
+        },
+        (None, None, None, Some(service)) => format!("{} {}", service.protocol.to_uppercase(), service.banner.as_deref().unwrap_or_default()),
+        (None, None, None, None) => String::new(),
+    };
+    let exit = if code == exit::OK { String::new() } else { format!(" [exit {}]", code) };
+    println!("{} {} {} ({:.2}ms){}", icon, result.target.bold(), detail, result.duration_ms, exit.dimmed());
+}
+
+/// "12d 03:04:05" from seconds.
+fn uptime(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}d {:02}:{:02}:{:02}", seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60)
+}
+
+fn print_stack_row(name: &str, stack: &StackResult) {
+    let ms = |v: Option<f64>| v.map(|v| format!("{:.2}ms", v)).unwrap_or_else(|| "-".to_string());
+    let icon = if stack.status == "ok" { "✅".green() } else { "❌".red() };
+    let code = stack.http_status.map(|c| c.to_string()).unwrap_or_else(|| "-".to_string());
+    println!(
+        "   {:<6} {} {:<4} {:<10} {:<10} {:<10} {}",
+        name,
+        icon,
+        code,
+        ms(stack.dns_ms),
+        ms(stack.tcp_ms),
+        ms(stack.tls_ms),
+        ms(stack.http_ms)
+    );
+}
+
+/// Print raw, pretty JSON for piping.
+pub fn print_json(result: &ProbeResult) {
+    let json_output = serde_json::to_string_pretty(result).unwrap();
+    println!("{}", json_output);
+}
+
+/// Print several results as a single JSON array.
+pub fn print_json_array(results: &[ProbeResult]) {
+    let json_output = serde_json::to_string_pretty(results).unwrap();
+    println!("{}", json_output);
+}
+
+/// Print one compact JSON object per line (NDJSON), flushed right away for log shippers.
+pub fn print_ndjson(result: &ProbeResult) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", serde_json::to_string(result).unwrap());
+    let _ = stdout.flush();
+}
+
+/// Print the history of one target: summary, trend buckets, then the most recent `limit` runs.
+#[cfg(feature = "history")]
+pub fn print_history_human(report: &HistoryReport, limit: usize) {
+    let time = |timestamp: &str, format: &str| chrono::DateTime::parse_from_rfc3339(timestamp).map(|t| t.format(format).to_string()).unwrap_or_else(|_| timestamp.to_string());
+    let ms = |v: Option<f64>| v.map(|v| format!("{:.2}ms", v)).unwrap_or_else(|| "-".to_string());
+
+    println!("\n📜 History: {}", report.target.bold().cyan());
+    println!("{}", SEPARATOR.dimmed());
+    if report.runs.is_empty() {
+        println!("No runs since {}", time(&report.since, "%Y-%m-%d %H:%M"));
+        println!("{}", SEPARATOR.dimmed());
+        return;
+    }
+
+    let summary = &report.summary;
+    println!(
+        "{} runs since {}, {} successful, total latency min/avg/p95 {}/{}/{}",
+        summary.runs,
+        time(&report.since, "%Y-%m-%d %H:%M"),
+        success_rate(summary),
+        ms(summary.latency_min_ms),
+        ms(summary.latency_avg_ms),
+        ms(summary.latency_p95_ms)
+    );
+
+    println!("\n{}", format!("Trend (per {})", report.bucket).bold());
+    for bucket in &report.trend {
+        let s = &bucket.summary;
+        println!("   {}  {:>4} runs  {:>7}  avg {:>10}  p95 {:>10}", time(&bucket.start, "%Y-%m-%d %H:%M"), s.runs, success_rate(s), ms(s.latency_avg_ms), ms(s.latency_p95_ms));
+    }
+
+    let recent = &report.runs[report.runs.len().saturating_sub(limit)..];
+    println!("\n{}", format!("Last {} runs", recent.len()).bold());
+    println!("{}", "   Time                 Status  DNS        TCP        TLS        HTTP       Total".bold());
+    for run in recent {
+        let icon = if run.success { "✅".green() } else { "❌".red() };
+        let code = run.http_status.map(|c| c.to_string()).unwrap_or_else(|| "-".to_string());
+        println!(
+            "   {}  {} {:<4} {:<10} {:<10} {:<10} {:<10} {}",
+            time(&run.timestamp, "%Y-%m-%d %H:%M:%S"),
+            icon,
+            code,
+            ms(run.dns_ms),
+            ms(run.tcp_ms),
+            ms(run.tls_ms),
+            ms(run.http_ms),
+            ms(Some(run.total_ms))
+        );
+        if let Some(e) = &run.error {
+            println!("   {} {}", "⚠".yellow(), e.dimmed());
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+}
+
+/// Success rate colored green (all good), yellow (some failures) or red (nothing succeeded).
+fn success_rate(summary: &SummarySnapshot) -> ColoredString {
+    let rate = format!("{:.1}%", summary.success_rate);
+    if summary.successes == summary.runs {
+        rate.green()
+    } else if summary.successes == 0 {
+        rate.red()
+    } else {
+        rate.yellow()
+    }
+}
+
+/// Print the history report as pretty JSON.
+#[cfg(feature = "history")]
+pub fn print_history_json(report: &HistoryReport) {
+    println!("{}", serde_json::to_string_pretty(report).unwrap());
+}
+
+/// Print what changed per target between two runs.
+pub fn print_diff_human(report: &DiffReport) {
+    println!("\n🔀 Diff (latency regressions above {}%)", report.threshold_percent);
+    println!("{}", SEPARATOR.dimmed());
+    for target in &report.targets {
+        match target.status.as_str() {
+            "unchanged" => println!("{} {} {}", "✔".green(), target.target, "unchanged".dimmed()),
+            "added" => println!("{} {} {}", "+".green(), target.target, "not in baseline".dimmed()),
+            "removed" => println!("{} {} {}", "-".red(), target.target, "missing from current run".dimmed()),
+            _ => {
+                println!("{} {}", "✖".yellow(), target.target.bold());
+                let lines: Vec<String> = target.changes.iter().map(change_line).collect();
+                print_tree(&lines);
+            },
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+}
+
+fn change_line(change: &Change) -> String {
+    let value = |v: &serde_json::Value| match v {
+        serde_json::Value::Null => "-".to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(items) => items.iter().map(|i| i.as_str().map(String::from).unwrap_or_else(|| i.to_string())).collect::<Vec<_>>().join(", "),
+        serde_json::Value::Number(n) if change.kind == "latency" => format!("{:.2}ms", n.as_f64().unwrap_or_default()),
+        other => other.to_string(),
+    };
+    let percent = change.change_percent.map(|p| format!(" ({})", format!("+{:.1}%", p).red())).unwrap_or_default();
+    format!("{:<24} {} → {}{}", change.field, value(&change.before).dimmed(), value(&change.after).yellow(), percent)
+}
+
+/// Print the diff report as pretty JSON.
+pub fn print_diff_json(report: &DiffReport) {
+    println!("{}", serde_json::to_string_pretty(report).unwrap());
+}
+
+/// Stable column set of `--output csv`.
+pub const CSV_COLUMNS: [&str; 8] = ["target", "timestamp", "dns_ms", "tcp_ms", "tls_ms", "http_status", "http_ms", "error"];
+
+/// Print the CSV header line.
+pub fn print_csv_header() {
+    println!("{}", CSV_COLUMNS.join(","));
+}
+
+/// Print one CSV row; missing values are empty and `error` is the first stage error.
+pub fn print_csv_row(result: &ProbeResult) {
+    let ms = |v: Option<f64>| v.map(|v| format!("{:.3}", v)).unwrap_or_default();
+    let error = [&result.dns.error, &result.tcp.error, &result.tls.error, &result.http.error].into_iter().find_map(|e| e.clone()).unwrap_or_default();
+    let row = [
+        result.target.clone(),
+        result.timestamp.clone(),
+        ms(result.dns.latency_ms),
+        ms(result.tcp.latency_ms),
+        ms(result.tls.latency_ms),
+        result.http.status_code.map(|c| c.to_string()).unwrap_or_default(),
+        ms(result.http.latency_ms),
+        error,
+    ];
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+    let _ = stdout.flush();
+}
+
+/// Quote a CSV field when it contains a separator, a quote or a line break (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Print the rolling watch-mode summary for one target.
+pub fn print_watch_summary(summary: &SummarySnapshot) {
+    let rate = success_rate(summary);
+    println!(
+        "📈 Runs: {} | Success: {} ({}/{}) | Latency min/avg/p95: {:.2}/{:.2}/{:.2}ms",
+        summary.runs,
+        rate,
+        summary.successes,
+        summary.runs,
+        summary.latency_min_ms.unwrap_or(0.0),
+        summary.latency_avg_ms.unwrap_or(0.0),
+        summary.latency_p95_ms.unwrap_or(0.0)
+    );
+}
+
+/// Final per-target summary of a watch session stopped with Ctrl-C. Written to
+/// stderr so that JSON, NDJSON and CSV on stdout stay machine-readable.
+pub fn print_watch_totals(totals: &[(String, SummarySnapshot)]) {
+    eprintln!();
+    eprintln!("{} Stopped. Summary of the session:", "⏹".bold());
+    for (target, summary) in totals {
+        eprintln!(
+            "   {:<40} Runs: {} | Success: {} ({}/{}) | Latency min/avg/p95: {:.2}/{:.2}/{:.2}ms",
+            target,
+            summary.runs,
+            success_rate(summary),
+            summary.successes,
+            summary.runs,
+            summary.latency_min_ms.unwrap_or(0.0),
+            summary.latency_avg_ms.unwrap_or(0.0),
+            summary.latency_p95_ms.unwrap_or(0.0)
+        );
+    }
+}
+
+/// Totals of a run over several targets and the rate they were started at, when
+/// known. Written to stderr so that JSON, NDJSON and CSV on stdout stay machine-readable.
+pub fn print_batch_summary(probed: usize, succeeded: usize, elapsed: std::time::Duration, rate: Option<f64>) {
+    let rate = rate.map(|rate| format!(", {:.1} targets/s", rate)).unwrap_or_default();
+    eprintln!("📊 {} targets probed ({} ok, {} failed) in {:.2}s{}", probed, succeeded, probed - succeeded, elapsed.as_secs_f64(), rate);
+}
+
+/// Streams a JSON array element by element, so the output stays valid JSON
+/// while results are printed as soon as they are available.
+pub struct JsonArrayWriter {
+    count: usize,
+}
+
+impl JsonArrayWriter {
+    pub fn begin() -> Self {
+        println!("[");
+        JsonArrayWriter { count: 0 }
+    }
+
+    pub fn push(&mut self, result: &ProbeResult) {
+        if self.count > 0 {
+            println!(",");
+        }
+        print!("{}", serde_json::to_string_pretty(result).unwrap());
+        self.count += 1;
+    }
+
+    pub fn finish(self) {
+        if self.count > 0 {
+            println!();
+        }
+        println!("]");
+    }
+}
+
+/// Collects an HTTP Archive (HAR 1.2) of every request, redirects included,
+/// printed as one document once all results are in.
+pub struct HarWriter {
+    entries: Vec<serde_json::Value>,
+}
+
+impl HarWriter {
+    pub fn begin() -> Self {
+        HarWriter { entries: Vec::new() }
+    }
+
+    pub fn push(&mut self, result: &ProbeResult) {
+        self.entries.extend(har_entries(result));
+    }
+
+    pub fn finish(self) {
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "netprobe", "version": env!("CARGO_PKG_VERSION") },
+                "entries": self.entries,
+            }
+        });
+        println!("{}", serde_json::to_string_pretty(&har).unwrap());
+    }
+}
+
+/// One HAR entry per redirect hop plus the final request; hops only know their total time.
+fn har_entries(result: &ProbeResult) -> Vec<serde_json::Value> {
+    let http = &result.http;
+    let Some(status) = http.status_code else { return Vec::new() };
+    let version = http.version.clone().unwrap_or_else(|| "HTTP/1.1".to_string());
+    let mut started = chrono::DateTime::parse_from_rfc3339(&result.timestamp).unwrap_or_default();
+    let status_text = |code: u16| hyper::StatusCode::from_u16(code).ok().and_then(|s| s.canonical_reason()).unwrap_or_default();
+    let request = |method: &str, url: &str| {
+        let query: Vec<_> = Url::parse(url).map(|url| url.query_pairs().map(|(name, value)| json!({ "name": name, "value": value })).collect()).unwrap_or_default();
+        json!({ "method": method, "url": url, "httpVersion": version, "cookies": [], "headers": [], "queryString": query, "headersSize": -1, "bodySize": -1 })
+    };
+
+    let mut entries = Vec::new();
+    for hop in &http.redirects {
+        entries.push(json!({
+            "startedDateTime": started.to_rfc3339(),
+            "time": hop.latency_ms,
+            "request": request(&hop.method, &hop.url),
+            "response": {
+                "status": hop.status,
+                "statusText": status_text(hop.status),
+                "httpVersion": version,
+                "cookies": [],
+                "headers": [{ "name": "Location", "value": hop.location }],
+                "content": { "size": 0, "mimeType": "" },
+                "redirectURL": hop.location,
+                "headersSize": -1,
+                "bodySize": -1,
+            },
+            "cache": {},
+            "timings": { "send": 0, "wait": hop.latency_ms, "receive": 0 },
+        }));
+        started += chrono::Duration::microseconds((hop.latency_ms * 1000.0) as i64);
+    }
+
+    let url = match http.redirects.last() {
+        Some(hop) => hop.location.clone(),
+        None => Url::parse(&result.target).map(String::from).unwrap_or_else(|_| result.target.clone()),
+    };
+    let timing = http.timing.clone().unwrap_or_default();
+    // HAR counts TLS inside `connect` and a proxy tunnel as `blocked`.
+    let connect = timing.connect_ms + timing.tls_ms.unwrap_or(0.0);
+    let headers: Vec<_> = http.headers.iter().flatten().map(|(name, value)| json!({ "name": name, "value": value })).collect();
+    let mime_type = http.headers.as_ref().and_then(|h| h.get("content-type")).cloned().unwrap_or_default();
+    let body_size = http.download.as_ref().map(|d| d.bytes as i64);
+    let mut entry = json!({
+        "startedDateTime": started.to_rfc3339(),
+        "time": timing.proxy_ms.unwrap_or(0.0) + timing.dns_ms + connect + timing.ttfb_ms + timing.transfer_ms,
+        "request": request(http.method.as_deref().unwrap_or("HEAD"), &url),
+        "response": {
+            "status": status,
+            "statusText": status_text(status),
+            "httpVersion": version,
+            "cookies": [],
+            "headers": headers,
+            "content": { "size": body_size.unwrap_or(0), "mimeType": mime_type },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": body_size.unwrap_or(-1),
+        },
+        "cache": {},
+        "timings": {
+            "blocked": timing.proxy_ms.unwrap_or(-1.0),
+            "dns": timing.dns_ms,
+            "connect": connect,
+            "ssl": timing.tls_ms.unwrap_or(-1.0),
+            "send": 0,
+            "wait": timing.ttfb_ms,
+            "receive": timing.transfer_ms,
+        },
+    });
+    if let Some(ip) = &http.peer_ip {
+        entry["serverIPAddress"] = json!(ip);
+    }
+    entries.push(entry);
+    entries
+}
+
+/// Collects a JUnit XML report: one test suite per target, one test case per
+/// stage, assertion and latency budget, printed once all results are in.
+pub struct JunitWriter {
+    suites: Vec<String>,
+    tests: usize,
+    failures: usize,
+    time_s: f64,
+}
+
+/// Outcome of one JUnit test case.
+enum Verdict {
+    Passed,
+    Failed(String),
+    Skipped,
+}
+
+impl JunitWriter {
+    pub fn begin() -> Self {
+        JunitWriter { suites: Vec::new(), tests: 0, failures: 0, time_s: 0.0 }
+    }
+
+    pub fn push(&mut self, result: &ProbeResult) {
+        let cases = junit_cases(result);
+        let failures = cases.iter().filter(|(_, _, verdict)| matches!(verdict, Verdict::Failed(_))).count();
+        let skipped = cases.iter().filter(|(_, _, verdict)| matches!(verdict, Verdict::Skipped)).count();
+        let time_s = result.duration_ms / 1000.0;
+        let mut suite = format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\" timestamp=\"{}\">\n",
+            xml_escape(&result.target),
+            cases.len(),
+            failures,
+            skipped,
+            time_s,
+            xml_escape(&result.timestamp)
+        );
+        for (name, ms, verdict) in &cases {
+            let open = format!("    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"", xml_escape(&result.target), xml_escape(name), ms.unwrap_or(0.0) / 1000.0);
+            match verdict {
+                Verdict::Passed => suite.push_str(&format!("{}/>\n", open)),
+                Verdict::Skipped => suite.push_str(&format!("{}>\n      <skipped/>\n    </testcase>\n", open)),
+                Verdict::Failed(message) => suite.push_str(&format!("{}>\n      <failure message=\"{}\"/>\n    </testcase>\n", open, xml_escape(message))),
+            }
+        }
+        suite.push_str("  </testsuite>\n");
+        self.suites.push(suite);
+        self.tests += cases.len();
+        self.failures += failures;
+        self.time_s += time_s;
+    }
+
+    pub fn finish(self) {
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        let _ = writeln!(stdout, "<testsuites name=\"netprobe\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.3}\">", self.tests, self.failures, self.time_s);
+        for suite in &self.suites {
+            let _ = write!(stdout, "{}", suite);
+        }
+        let _ = writeln!(stdout, "</testsuites>");
+    }
+}
+
+/// Test cases of one result: each stage that ran or was skipped, then every assertion and latency budget.
+fn junit_cases(result: &ProbeResult) -> Vec<(String, Option<f64>, Verdict)> {
+    let stage = |status: &str, error: &Option<String>| match status {
+        "error" => Verdict::Failed(error.clone().unwrap_or_else(|| "failed".to_string())),
+        "skipped" | "pending" | "interrupted" => Verdict::Skipped,
+        _ => Verdict::Passed,
+    };
+    let mut cases = vec![("dns".to_string(), result.dns.latency_ms, stage(&result.dns.status, &result.dns.error))];
+    if let Some(icmp) = &result.icmp {
+        cases.push(("icmp".to_string(), icmp.rtt_avg_ms, stage(&icmp.status, &icmp.error)));
+    }
+    if let Some(udp) = &result.udp {
+        cases.push(("udp".to_string(), udp.latency_ms, stage(&udp.status, &udp.error)));
+    } else {
+        cases.push(("tcp".to_string(), result.tcp.latency_ms, stage(&result.tcp.status, &result.tcp.error)));
+        if let Some(service) = &result.tcp.service {
+            cases.push((service.protocol.clone(), service.greeting_ms, stage(&service.status, &service.error)));
+        }
+        // A certificate about to expire fails the run (exit code 8), so it fails the case too.
+        let tls = match (&result.tls.warning, stage(&result.tls.status, &result.tls.error)) {
+            (Some(warning), Verdict::Passed) => Verdict::Failed(warning.clone()),
+            (_, verdict) => verdict,
+        };
+        cases.push(("tls".to_string(), result.tls.latency_ms, tls));
+        let http = match (result.http.status_code, &result.http.error) {
+            (Some(code), _) if code >= 400 => Verdict::Failed(format!("HTTP {}", code)),
+            (Some(_), _) => Verdict::Passed,
+            (None, Some(e)) => Verdict::Failed(e.clone()),
+            (None, None) => Verdict::Skipped,
+        };
+        if let Some(ws) = &result.websocket {
+            cases.push(("websocket".to_string(), ws.handshake_ms, stage(&ws.status, &ws.error)));
+        } else {
+            cases.push(("http".to_string(), result.http.latency_ms, http));
+        }
+        if let Some(grpc) = &result.http.grpc {
+            cases.push(("grpc".to_string(), grpc.latency_ms, stage(&grpc.status, &grpc.error)));
+        }
+    }
+    for assertion in &result.assertions {
+        let verdict = match assertion.passed {
+            true => Verdict::Passed,
+            false => Verdict::Failed(format!("expected {}, got {}", assertion.expected, assertion.actual.as_deref().unwrap_or("nothing"))),
+        };
+        cases.push((format!("assert {}", assertion.name), None, verdict));
+    }
+    for check in &result.latency_checks {
+        let verdict = match check.status.as_str() {
+            "degraded" => Verdict::Failed(format!("{:.2}ms over the {:.2}ms budget", check.latency_ms.unwrap_or_default(), check.limit_ms)),
+            "skipped" => Verdict::Skipped,
+            _ => Verdict::Passed,
+        };
+        cases.push((format!("latency {}", check.stage), check.latency_ms, verdict));
+    }
+    cases
+}
+
+/// Escape text for an XML attribute or element.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+/// Print a traceroute as one line per hop, `*` marking probes without reply.
+pub fn print_trace_human(trace: &TraceResult) {
+    println!("\n🧭 Tracing Route: {} ({})", trace.target.bold().cyan(), trace.destination);
+    println!("{}", SEPARATOR.dimmed());
+    for hop in &trace.hops {
+        let rtts: Vec<String> = hop
+            .rtts_ms
+            .iter()
+            .map(|rtt| match rtt {
+                Some(ms) => format!("{:.2}ms", ms),
+                None => "*".to_string(),
+            })
+            .collect();
+        match (&hop.ip, &hop.hostname) {
+            (Some(ip), Some(name)) => println!("{:>2}. {} ({})  {}", hop.ttl, name, ip.yellow(), rtts.join("  ")),
+            (Some(ip), None) => println!("{:>2}. {}  {}", hop.ttl, ip.yellow(), rtts.join("  ")),
+            (None, _) => println!("{:>2}. {}", hop.ttl, rtts.join("  ").dimmed()),
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+    if trace.reached {
+        let hops = trace.hops.len();
+        println!("{} Destination reached in {} hop{}", "✅".green(), hops, if hops == 1 { "" } else { "s" });
+    } else {
+        println!("{} Destination not reached", "❌".red());
+    }
+}
+
+/// Announce an mtr run before the first round is in.
+pub fn print_mtr_start(target: &str, options: &MtrOptions) {
+    let protocol = match options.protocol {
+        TraceProtocol::Icmp => "ICMP",
+        TraceProtocol::Udp => "UDP",
+    };
+    let rounds = match options.rounds {
+        Some(rounds) => format!("{} rounds", rounds),
+        None => "Ctrl-C to stop".to_string(),
+    };
+    println!("\n🧭 MTR: {} ({} probes every {:?}, {})", target.bold().cyan(), protocol, options.interval, rounds.dimmed());
+    println!("{}", SEPARATOR.dimmed());
+}
+
+/// Redraw the hop table over the `drawn` lines printed last time; returns how many it printed.
+pub fn draw_mtr_table(result: &MtrResult, drawn: usize) -> usize {
+    let lines = mtr_table_lines(result);
+    let mut out = String::new();
+    if drawn > 0 {
+        out.push_str(&format!("\x1b[{}A\x1b[J", drawn));
+    }
+    for line in &lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(out.as_bytes());
+    let _ = stdout.flush();
+    lines.len()
+}
+
+/// Print the final hop table (unless it is already on screen) and where loss starts.
+pub fn print_mtr_human(result: &MtrResult, drawn: bool) {
+    if !drawn {
+        for line in mtr_table_lines(result) {
+            println!("{}", line);
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+    let rounds = format!("{} round{}", result.rounds, if result.rounds == 1 { "" } else { "s" });
+    if !result.reached {
+        println!("{} Destination not reached after {}", "❌".red(), rounds);
+        return;
+    }
+    let hops = result.hops.len();
+    println!("{} Destination reached in {} hop{}, {}", "✅".green(), hops, if hops == 1 { "" } else { "s" }, rounds);
+    // Loss that carries on to the destination is real; loss at a single hop is a router slow to answer.
+    if let Some(last) = result.hops.last().filter(|hop| hop.loss_percent > 0.0) {
+        let start = result.hops.iter().rposition(|hop| hop.loss_percent == 0.0).map_or(0, |i| i + 1);
+        let hop = &result.hops[start];
+        let at = hop.ips.first().map(|ip| format!(" ({})", ip)).unwrap_or_default();
+        println!("   {} Loss from hop {}{} on: {:.1}% at the destination", "⚠️".yellow(), hop.ttl, at, last.loss_percent);
+    }
+}
+
+/// Print the final statistics of an mtr run as pretty JSON.
+pub fn print_mtr_json(result: &MtrResult) {
+    println!("{}", serde_json::to_string_pretty(result).unwrap());
+}
+
+/// Header and one row per hop, mtr style.
+fn mtr_table_lines(result: &MtrResult) -> Vec<String> {
+    let mut lines = vec![format!("{:>3}  {:<40} {:>6} {:>5} {:>9} {:>9} {:>9} {:>9} {:>9}", "", "Host", "Loss%", "Snt", "Last", "Avg", "Best", "Wrst", "StDev").bold().to_string()];
+    let ms = |value: Option<f64>| value.map(|ms| format!("{:.2}", ms)).unwrap_or_else(|| "-".to_string());
+    for hop in &result.hops {
+        let host = match (hop.ips.first(), &hop.hostname) {
+            (None, _) => "???".to_string(),
+            (Some(ip), Some(name)) => format!("{} ({})", name, ip),
+            (Some(ip), None) => ip.clone(),
+        };
+        let host = match hop.ips.len() {
+            0 | 1 => host,
+            n => format!("{} +{}", host, n - 1),
+        };
+        let loss = format!("{:>5.1}%", hop.loss_percent);
+        let loss = if hop.loss_percent == 0.0 {
+            loss.green()
+        } else if hop.loss_percent < 100.0 {
+            loss.yellow()
+        } else {
+            loss.red()
+        };
+        let latency = hop.latency.as_ref();
+        lines.push(format!(
+            "{:>3}. {:<40} {} {:>5} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            hop.ttl,
+            truncate_chars(&host, 40),
+            loss,
+            hop.sent,
+            ms(hop.last_ms),
+            ms(latency.map(|l| l.avg_ms)),
+            ms(latency.map(|l| l.min_ms)),
+            ms(latency.map(|l| l.max_ms)),
+            ms(latency.map(|l| l.stddev_ms))
+        ));
+    }
+    lines
+}
+
+/// `text` cut to `width` characters, with an ellipsis when it was longer.
+fn truncate_chars(text: &str, width: usize) -> String {
+    match text.char_indices().nth(width.saturating_sub(1)) {
+        Some((i, _)) if text.chars().count() > width => format!("{}…", &text[..i]),
+        _ => text.to_string(),
+    }
+}
+
+/// Announce a path MTU search before it starts, since dropped probes take a while.
+pub fn print_mtu_start(target: &str, options: &MtuOptions) {
+    let protocol = match options.protocol {
+        TraceProtocol::Icmp => "ICMP",
+        TraceProtocol::Udp => "UDP",
+    };
+    println!("\n📏 Path MTU: {} (up to {} bytes, {} probes)", target.bold().cyan(), options.max_size, protocol);
+    println!("{}", SEPARATOR.dimmed());
+}
+
+/// Print every size tried and the path MTU found.
+pub fn print_mtu_human(result: &MtuResult) {
+    for probe in &result.probes {
+        let rtt = probe.rtt_ms.map(|ms| format!(" ({:.2}ms)", ms)).unwrap_or_default();
+        match (probe.outcome.as_str(), &probe.from) {
+            ("ok", _) => println!("{:>5} bytes  {} reached {}{}", probe.size, "✅".green(), result.destination, rtt.dimmed()),
+            ("too-big", Some(from)) => println!("{:>5} bytes  {} too big for {}{}", probe.size, "❌".red(), from.yellow(), rtt.dimmed()),
+            ("too-big", None) => println!("{:>5} bytes  {} larger than our interface's MTU", probe.size, "❌".red()),
+            _ => println!("{:>5} bytes  {} {}", probe.size, "❌".red(), "no answer".dimmed()),
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+    let Some(limit) = &result.limit else {
+        println!("{} Path MTU ≥ {} bytes: the largest size tried got through", "✅".green(), result.path_mtu);
+        return;
+    };
+    // ICMP echo and UDP headers are both 8 bytes, after a 20-byte IPv4 or 40-byte IPv6 header.
+    let headers = if result.destination.contains(':') { 48 } else { 28 };
+    println!("{} Path MTU {} bytes {}", "✅".green(), result.path_mtu.to_string().bold(), format!("({} bytes of payload)", result.path_mtu.saturating_sub(headers)).dimmed());
+    match limit.kind.as_str() {
+        "router" => {
+            let router = match (&limit.ip, &limit.hostname) {
+                (Some(ip), Some(name)) => format!("{} ({})", name, ip),
+                (Some(ip), None) => ip.clone(),
+                _ => "a router".to_string(),
+            };
+            let mtu = limit.next_hop_mtu.map(|mtu| format!(", next-hop MTU {}", mtu)).unwrap_or_default();
+            println!("   Larger packets are refused by {}{}", router.yellow(), mtu);
+        },
+        "interface" => println!("   Larger packets exceed the MTU of this host's own interface"),
+        _ => println!("   {} Larger packets are dropped without an ICMP error (PMTUD black hole)", "⚠️".yellow()),
+    }
+}
+
+/// Print a path MTU search as pretty JSON.
+pub fn print_mtu_json(result: &MtuResult) {
+    println!("{}", serde_json::to_string_pretty(result).unwrap());
+}
+
+/// Announce a sweep before it starts, since large blocks take a while.
+pub fn print_sweep_start(network: &str, hosts: usize, options: &SweepOptions) {
+    let ports: Vec<String> = options.ports.iter().map(u16::to_string).collect();
+    println!("\n🛰️  Sweeping {} ({} hosts) on port(s) {}", network.bold(), hosts, ports.join(","));
+    println!("{}", SEPARATOR.dimmed());
+}
+
+/// Print the alive hosts of a sweep and how many answered.
+pub fn print_sweep_human(result: &SweepResult) {
+    for host in &result.hosts {
+        let ports = match host.open_ports.is_empty() {
+            true => "no open port (refused)".dimmed().to_string(),
+            false => host.open_ports.iter().map(u16::to_string).collect::<Vec<_>>().join(", "),
+        };
+        let latency = host.latency_ms.map(|ms| format!(" ({:.2}ms)", ms)).unwrap_or_default();
+        println!("{:<15} {} {}{}", host.ip, "✅".green(), ports, latency.dimmed());
+    }
+    if result.hosts.is_empty() {
+        println!("{} No host answered", "❌".red());
+    }
+    println!("{}", SEPARATOR.dimmed());
+    let share = result.hosts_alive as f64 * 100.0 / result.hosts_scanned.max(1) as f64;
+    println!(
+        "📊 {}/{} hosts alive ({:.1}%) in {:.2}s, {:.1} hosts/s",
+        result.hosts_alive,
+        result.hosts_scanned,
+        share,
+        result.duration_ms / 1000.0,
+        result.rate_per_s
+    );
+}
+
+/// Print a sweep as pretty JSON.
+pub fn print_sweep_json(result: &SweepResult) {
+    println!("{}", serde_json::to_string_pretty(result).unwrap());
+}
+
+/// Print a traceroute as pretty JSON.
+pub fn print_trace_json(trace: &TraceResult) {
+    println!("{}", serde_json::to_string_pretty(trace).unwrap());
+}
+
+/// How an answer to a query with a client subnet was scoped.
+fn ecs_scope(scope: Option<u8>) -> String {
+    match scope {
+        Some(0) => "(scope /0: same answer for every network)".to_string(),
+        Some(prefix) => format!("(answer tailored to /{})", prefix),
+        None => "(ignored by the server)".to_string(),
+    }
+}
+
+/// Print record lookups grouped by type, one zone-file style line per record.
+pub fn print_records_human(lookup: &RecordLookup) {
+    println!("\n🔎 DNS Lookup: {}", lookup.name.bold().cyan());
+    if let Some(subnet) = &lookup.client_subnet {
+        println!("Client subnet: {}", subnet.bold());
+    }
+    println!("{}", SEPARATOR.dimmed());
+    for query in &lookup.queries {
+        let via = query.server.as_ref().map(|s| format!("via {}{}", s, transport_label(query.transport.as_deref().unwrap_or("")))).unwrap_or_default();
+        let latency = |ms: f64| match query.handshake_ms {
+            Some(handshake) => format!("{:.2}ms, handshake {:.2}ms", ms, handshake),
+            None => format!("{:.2}ms", ms),
+        };
+        match (&query.error, query.latency_ms) {
+            (None, Some(ms)) if lookup.client_subnet.is_some() => println!("{:<6} {} {} ({}) {} {}", query.rtype.bold(), "✅".green(), query.rcode.as_deref().unwrap_or(""), latency(ms), via.dimmed(), ecs_scope(query.client_subnet_scope).dimmed()),
+            (None, Some(ms)) => println!("{:<6} {} {} ({}) {}", query.rtype.bold(), "✅".green(), query.rcode.as_deref().unwrap_or(""), latency(ms), via.dimmed()),
+            (Some(e), _) => println!("{:<6} {} {}", query.rtype.bold(), "❌".red(), e),
+            (None, None) => {}
+        }
+        if query.records.is_empty() && query.error.is_none() {
+            println!("   {}", "(no records)".dimmed());
+        }
+        for record in &query.records {
+            println!("   {}  {}  {}  {}", record.name, record.ttl.to_string().dimmed(), record.rtype, record.data.yellow());
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+}
+
+pub fn print_propagation_human(result: &Propagation) {
+    println!("\n🌍 DNS Propagation: {} {}", result.name.bold().cyan(), result.rtype.bold());
+    println!("{}", SEPARATOR.dimmed());
+    let width = result.resolvers.iter().map(|resolver| resolver.name.len() + resolver.server.len() + 3).max().unwrap_or(0);
+    for resolver in &result.resolvers {
+        let who = format!("{} ({})", resolver.name, resolver.server);
+        let timing = match (resolver.ttl, resolver.latency_ms) {
+            (Some(ttl), Some(ms)) => format!("TTL {}, {:.2}ms", ttl, ms),
+            (None, Some(ms)) => format!("{:.2}ms", ms),
+            _ => String::new(),
+        };
+        let records = if resolver.records.is_empty() { "(no records)".to_string() } else { resolver.records.join(", ") };
+        match resolver.status.as_str() {
+            "current" => println!("{} {:<width$} {:<8} {} {}", "✅".green(), who, resolver.region.dimmed(), records.green(), format!("({})", timing).dimmed()),
+            "stale" => println!("{} {:<width$} {:<8} {} {}", "⚠️".yellow(), who, resolver.region.dimmed(), records.yellow(), format!("(stale, {})", timing).dimmed()),
+            _ => println!("{} {:<width$} {:<8} {}", "❌".red(), who, resolver.region.dimmed(), resolver.error.as_deref().unwrap_or("").red()),
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+    let current = result.resolvers.iter().filter(|resolver| resolver.status == "current").count();
+    let answered = result.resolvers.iter().filter(|resolver| resolver.status != "error").count();
+    let value = if result.current.is_empty() { "no records".to_string() } else { result.current.join(", ") };
+    match result.status.as_str() {
+        "error" => println!("{} {}", "❌".red(), "No resolver answered".red()),
+        _ => println!("{}/{} resolvers return {}", current, answered, value.bold()),
+    }
+}
+
+pub fn print_propagation_json(results: &[Propagation]) {
+    println!("{}", serde_json::to_string_pretty(results).unwrap());
+}
+
+/// Announce a benchmark before it runs, as it takes a while.
+pub fn print_bench_start(names: &[String], rtype: &str, resolvers: usize, iterations: u32) {
+    let what = match names {
+        [name] => name.clone(),
+        names => format!("{} names", names.len()),
+    };
+    println!("\n⏱️  DNS Benchmark: {} {} {}", what.bold().cyan(), rtype.bold(), format!("({} resolvers × {} round{})", resolvers, iterations, if iterations == 1 { "" } else { "s" }).dimmed());
+    println!("{}", SEPARATOR.dimmed());
+}
+
+/// Print the ranking, fastest first, with the latency distribution of each resolver.
+pub fn print_bench_human(result: &Bench) {
+    let who = |name: &Option<String>, server: &str| match name {
+        Some(name) => format!("{} ({})", name, server),
+        None => server.to_string(),
+    };
+    let width = result.resolvers.iter().map(|resolver| who(&resolver.name, &resolver.server).chars().count()).max().unwrap_or(0);
+    for resolver in &result.resolvers {
+        let label = format!("{:<width$}", who(&resolver.name, &resolver.server));
+        let failures = format!("{}/{} failed", resolver.failures, resolver.queries);
+        match (resolver.rank, &resolver.latency) {
+            (Some(rank), Some(latency)) => {
+                let timing = format!("median {:>8.2}ms  p95 {:>8.2}ms  min {:>7.2}ms  max {:>8.2}ms", latency.p50_ms, latency.p95_ms, latency.min_ms, latency.max_ms);
+                let failures = if resolver.failures > 0 { format!("  {}", failures).yellow().to_string() } else { String::new() };
+                let label = if rank == 1 { label.green().bold().to_string() } else { label };
+                println!("{:>2}. {}  {}{}", rank, label, timing, failures);
+            },
+            _ => println!(" {}  {}  {} {}", "❌".red(), label, failures.red(), format!("({})", resolver.error.as_deref().unwrap_or("")).dimmed()),
+        }
+    }
+    println!("{}", SEPARATOR.dimmed());
+    match result.resolvers.first().filter(|resolver| resolver.rank.is_some()) {
+        Some(fastest) => {
+            let median = fastest.latency.as_ref().map(|latency| latency.p50_ms).unwrap_or_default();
+            println!("🏆 Fastest: {} {}", who(&fastest.name, &fastest.server).bold(), format!("(median {:.2}ms)", median).dimmed());
+        },
+        None => println!("{} {}", "❌".red(), "No resolver answered".red()),
+    }
+}
+
+pub fn print_bench_json(result: &Bench) {
+    println!("{}", serde_json::to_string_pretty(result).unwrap());
+}
+
+pub fn print_ttl_watch_start(watch: &TtlWatch, interval: std::time::Duration) {
+    println!("\n⏳ TTL Watch: {} {} {}", watch.name.bold().cyan(), watch.rtype.bold(), format!("(every {:?})", interval).dimmed());
+    match watch.authoritative_ttl {
+        Some(ttl) => println!("Authoritative TTL: {}s", ttl.to_string().bold()),
+        None => println!("Authoritative TTL: {}", "unknown (no authoritative answer)".dimmed()),
+    }
+    println!("{}", SEPARATOR.dimmed());
+}
+
+/// One line per query, printed as the watch goes.
+pub fn print_ttl_sample(sample: &TtlSample) {
+    let at = format!("{:>6.1}s", sample.elapsed_s);
+    let Some(ttl) = sample.ttl else {
+        println!("{} {} {}", "❌".red(), at.dimmed(), sample.error.as_deref().unwrap_or("").red());
+        return;
+    };
+    let expected = sample.expected_ttl.map(|expected| format!(", expected ~{}s", expected)).unwrap_or_default();
+    let details = format!("({}{}, {:.2}ms via {})", sample.verdict, expected, sample.latency_ms.unwrap_or(0.0), sample.server.as_deref().unwrap_or("?")).dimmed();
+    let ttl = format!("TTL {:>6}s", ttl);
+    match sample.verdict.as_str() {
+        "above_authoritative" | "reset" | "stalled" => println!("{} {} {} {} {}", "⚠️".yellow(), at.dimmed(), ttl.yellow(), sample.records.join(", "), details),
+        _ => println!("{} {} {} {} {}", "✅".green(), at.dimmed(), ttl, sample.records.join(", "), details),
+    }
+}
+
+pub fn print_ttl_watch_summary(watch: &TtlWatch) {
+    println!("{}", SEPARATOR.dimmed());
+    if watch.samples.iter().all(|sample| sample.ttl.is_none()) {
+        println!("{} {}", "❌".red(), "No answer with a TTL".red());
+    } else if watch.findings.is_empty() {
+        println!("{} {}", "✅".green(), "The TTL counted down with the clock".green());
+    } else {
+        println!("{} {}", "⚠️".yellow(), "The resolver does not honour the TTL".yellow());
+        print_tree(&watch.findings);
+    }
+}
+
+pub fn print_ttl_watch_json(watch: &TtlWatch) {
+    println!("{}", serde_json::to_string_pretty(watch).unwrap());
+}
+
+pub fn print_mail_human(result: &MailResult) {
+    println!("\n📬 Mail Check: {}", result.domain.bold().cyan());
+    println!("{}", SEPARATOR.dimmed());
+
+    println!("{}", "MX hosts".bold());
+    if let Some(e) = &result.mx_error {
+        println!("   {} {}", "❌".red(), e);
+    }
+    for mx in &result.mx {
+        let host = format!("{} {} {}", mx.preference, mx.exchange, format!("({})", mx.addresses.join(", ")).dimmed());
+        let Some(smtp) = &mx.smtp else {
+            println!("   {} {}", "❌".red(), host);
+            print_tree(&[mx.error.clone().unwrap_or_default()]);
+            continue;
+        };
+        // A failed handshake is detailed by the Verification line below.
+        let (icon, summary) = match (&smtp.tls, &smtp.error) {
+            (Some(tls), None) => ("✅".green(), format!("STARTTLS {}", tls.protocol.as_deref().unwrap_or("?")).green()),
+            (Some(_), Some(_)) => ("❌".red(), "STARTTLS failed".red()),
+            (None, None) => ("⚠️".yellow(), "no STARTTLS".yellow()),
+            (None, Some(e)) => ("❌".red(), e.red()),
+        };
+        println!("   {} {} {}", icon, host, summary);
+        let mut details = Vec::new();
+        if let (Some(banner), Some(ms)) = (&smtp.banner, smtp.banner_ms) {
+            details.push(format!("Banner         {} {}", banner, format!("({:.2}ms)", ms).dimmed()));
+        }
+        if !smtp.extensions.is_empty() {
+            details.push(format!("Extensions     {}", smtp.extensions.join(", ").dimmed()));
+        }
+        if let Some(tls) = &smtp.tls {
+            if let Some(cert) = &tls.certificate {
+                details.push(format!("Certificate    {} (expires in {} days)", cert.subject, cert.days_until_expiry));
+            }
+            match (tls.verification.as_deref(), &tls.error) {
+                (Some("ok"), _) => details.push(format!("Verification   {}", "ok".green())),
+                (_, Some(e)) => details.push(format!("Verification   {}", e.red())),
+                _ => {},
+            }
+        }
+        print_tree(&details);
+    }
+
+    for (name, policy) in [("SPF", &result.spf), ("DMARC", &result.dmarc)] {
+        let record = policy.record.as_deref().unwrap_or_default();
+        match policy.status.as_str() {
+            "ok" => {
+                let policy = policy.policy.as_ref().map(|p| format!("p={} ", p).bold().to_string()).unwrap_or_default();
+                println!("{:<6} {} {}{}", name.bold(), "✅".green(), policy, record.dimmed())
+            },
+            "missing" => println!("{:<6} {} no record published", name.bold(), "❌".red()),
+            _ => println!("{:<6} {} {} {}", name.bold(), "❌".red(), policy.error.as_deref().unwrap_or("invalid"), record.dimmed()),
+        }
+    }
+    if result.dkim.is_empty() {
+        println!("{:<6} {} no key under {}", "DKIM".bold(), "⚠️".yellow(), result.dkim_selectors_checked.join(", ").dimmed());
+    }
+    for key in &result.dkim {
+        // Keys are long; the start of the record says what it is.
+        let record: String = key.record.chars().take(60).collect();
+        let ellipsis = if key.record.chars().count() > 60 { "…" } else { "" };
+        println!("{:<6} {} {}: {}{}", "DKIM".bold(), "✅".green(), key.selector, record.dimmed(), ellipsis.dimmed());
+    }
+    println!("{}", SEPARATOR.dimmed());
+}
+
+pub fn print_mail_json(result: &MailResult) {
+    println!("{}", serde_json::to_string_pretty(result).unwrap());
+}
+
+pub fn print_audit_human(result: &AuditResult) {
+    println!("\n🧭 DNS Audit: {}", result.domain.bold().cyan());
+    println!("{}", SEPARATOR.dimmed());
+    if let Some(e) = &result.error {
+        println!("{} {}", "❌".red(), e.red());
+        println!("{}", SEPARATOR.dimmed());
+        return;
+    }
+    let names = |names: &[String]| if names.is_empty() { "-".to_string() } else { names.join(", ") };
+    let parent = result.parent.as_deref().map(|parent| format!("(in {})", parent)).unwrap_or_default();
+    println!("{:<15}{} {}", "Delegation".bold(), names(&result.delegation), parent.dimmed());
+    println!("{:<15}{}", "Zone NS".bold(), names(&result.zone_ns));
+
+    println!("{}", "Nameservers".bold());
+    for server in &result.servers {
+        let host = format!("{} {}", server.name, format!("({})", server.address.as_deref().unwrap_or("-")).dimmed());
+        match (server.status.as_str(), server.serial, server.latency_ms) {
+            ("ok", Some(serial), Some(ms)) => println!("   {} {} serial {} {}", "✅".green(), host, serial, format!("({:.2}ms)", ms).dimmed()),
+            (status, _, _) => println!("   {} {} {}", "❌".red(), host, format!("{}: {}", status, server.error.as_deref().unwrap_or("")).red()),
+        }
+    }
+
+    println!("{}", "Records".bold());
+    for comparison in &result.records {
+        match comparison.sets.as_slice() {
+            [] => println!("   {} {:<6} {}", "⚠️".yellow(), comparison.rtype, "no server answered".dimmed()),
+            [set] if set.records.is_empty() => println!("   {} {:<6} {}", "✅".green(), comparison.rtype, "(no records)".dimmed()),
+            [set] => println!("   {} {:<6} {}", "✅".green(), comparison.rtype, set.records.join(", ").dimmed()),
+            sets => {
+                println!("   {} {:<6} {}", "⚠️".yellow(), comparison.rtype, format!("{} distinct answers", sets.len()).yellow());
+                let lines: Vec<String> = sets
+                    .iter()
+                    .map(|set| {
+                        let records = if set.records.is_empty() { "(no records)".to_string() } else { set.records.join(", ") };
+                        format!("{} {}", records, format!("← {}", set.servers.join(", ")).dimmed())
+                    })
+                    .collect();
+                for (i, line) in lines.iter().enumerate() {
+                    println!("      {} {}", if i + 1 == lines.len() { "└─" } else { "├─" }, line);
+                }
+            },
+        }
+    }
+
+    match result.issues.as_slice() {
+        [] => println!("{} {}", "✅".green(), "All nameservers answer authoritatively and agree".green()),
+        issues => {
+            for issue in issues {
+                println!("{} {}", "⚠️".yellow(), issue.yellow());
+            }
+        },
+    }
+    println!("{}", SEPARATOR.dimmed());
+}
+
+pub fn print_audit_json(result: &AuditResult) {
+    println!("{}", serde_json::to_string_pretty(result).unwrap());
+}
+
+pub fn print_whois_human(result: &WhoisResult) {
+    println!("\n🌐 Domain Registration: {}", result.domain.bold().cyan());
+    println!("{}", SEPARATOR.dimmed());
+    if let (Some(source), Some(server)) = (&result.source, &result.server) {
+        println!("{:<15}{} {}", "Source".bold(), source.to_uppercase(), server.dimmed());
+    }
+    if let Some(reason) = &result.rdap_error {
+        println!("{:<15}{}", "RDAP".bold(), format!("unavailable: {}", reason).dimmed());
+    }
+    if let Some(e) = &result.error {
+        println!("{} {}", "❌".red(), e.red());
+        println!("{}", SEPARATOR.dimmed());
+        return;
+    }
+    let date = |date: &Option<String>| date.as_deref().map(|date| date.get(..10).unwrap_or(date).to_string()).unwrap_or_else(|| "-".to_string());
+    println!("{:<15}{}", "Registrar".bold(), result.registrar.as_deref().unwrap_or("-"));
+    println!("{:<15}{}", "Created".bold(), date(&result.created));
+    let expiry = match (result.days_until_expiry, &result.warning) {
+        (Some(days), Some(_)) if days < 0 => format!("(expired {} days ago)", -days).red(),
+        (Some(days), Some(_)) => format!("(in {} days)", days).red(),
+        (Some(days), None) if days < 30 => format!("(in {} days)", days).yellow(),
+        (Some(days), None) => format!("(in {} days)", days).green(),
+        (None, _) => "".normal(),
+    };
+    println!("{:<15}{} {}", "Expires".bold(), date(&result.expires), expiry);
+    println!("{:<15}{}", "Nameservers".bold(), if result.nameservers.is_empty() { "-".to_string() } else { result.nameservers.join(", ") });
+    if !result.statuses.is_empty() {
+        println!("{:<15}{}", "Status".bold(), result.statuses.join(", ").dimmed());
+    }
+    if let Some(warning) = &result.warning {
+        println!("{} {}", "⚠️".yellow(), warning.yellow());
+    }
+    println!("{}", SEPARATOR.dimmed());
+}
+
+pub fn print_whois_json(result: &WhoisResult) {
+    println!("{}", serde_json::to_string_pretty(result).unwrap());
+}
+
+/// "512 B", "1.50 KB", "12.30 MB"... (decimal units, as for throughput).
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=999 => format!("{} B", bytes),
+        1_000..=999_999 => format!("{:.2} KB", bytes as f64 / 1e3),
+        1_000_000..=999_999_999 => format!("{:.2} MB", bytes as f64 / 1e6),
+        _ => format!("{:.2} GB", bytes as f64 / 1e9),
+    }
+}
+
+/// Width of the waterfall bars, in characters, for the whole request.
+const WATERFALL_WIDTH: f64 = 30.0;
+
+/// One line per phase, each bar starting where the previous one ended.
+fn waterfall(timing: &HttpTiming) -> Vec<String> {
+    let mut phases = vec![("DNS", timing.dns_ms), ("Connect", timing.connect_ms)];
+    if let Some(proxy) = timing.proxy_ms {
+        phases.push(("Proxy", proxy));
+    }
+    if let Some(tls) = timing.tls_ms {
+        phases.push(("TLS", tls));
+    }
+    phases.push(("TTFB", timing.ttfb_ms));
+    phases.push(("Transfer", timing.transfer_ms));
+
+    let scale = WATERFALL_WIDTH / timing.total_ms.max(f64::EPSILON);
+    let mut elapsed = 0.0;
+    phases
+        .into_iter()
+        .map(|(name, phase_ms)| {
+            let offset = (elapsed * scale).round() as usize;
+            let width = ((phase_ms * scale).round() as usize).max(1);
+            elapsed += phase_ms;
+            format!("{:<14}{:>9.2}ms  {}{}", name, phase_ms, " ".repeat(offset), "█".repeat(width).cyan())
+        })
+        .collect()
+}
+
+/// "Attempts  3: ✖ 5000.12ms, ✔ 1.20ms" when a stage needed more than one try, else nothing.
+fn attempt_lines(attempts: &[Attempt]) -> Vec<String> {
+    if attempts.len() < 2 {
+        return Vec::new();
+    }
+    let tries: Vec<String> = attempts
+        .iter()
+        .map(|a| match a.error {
+            Some(_) => format!("{} {:.2}ms", "✖".red(), a.latency_ms),
+            None => format!("{} {:.2}ms", "✔".green(), a.latency_ms),
+        })
+        .collect();
+    vec![format!("Attempts       {}: {}", attempts.len(), tries.join(", "))]
+}
+
+/// Print sub-lines under a step, closing the branch on the last one.
+/// Open ports one per line; closed and filtered ones are listed individually only for
+/// short port lists, so a range scan stays readable.
+fn port_lines(ports: &[PortResult]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for port in ports.iter().filter(|port| port.status == "open" || ports.len() <= 10) {
+        lines.push(match port.status.as_str() {
+            "open" => format!("Port {:<6} {} Open ({:.2}ms)", port.port, "✅".green(), port.latency_ms.unwrap_or(0.0)),
+            "closed" => format!("Port {:<6} {} Closed", port.port, "❌".red()),
+            _ => format!("Port {:<6} {} Filtered {}", port.port, "⚠️".yellow(), port.error.as_deref().unwrap_or("").dimmed()),
+        });
+    }
+    if ports.len() > 10 {
+        let count = |status: &str| ports.iter().filter(|port| port.status == status).count();
+        lines.push(format!("{} ports: {} open, {} closed, {} filtered", ports.len(), count("open"), count("closed"), count("filtered")).dimmed().to_string());
+    }
+    lines
+}
+
+/// Spread of the repeated connect times, then what the kernel measured on them.
+fn quality_lines(quality: &QualityResult) -> Vec<String> {
+    let mut lines = Vec::new();
+    let established = format!("{}/{} connected", quality.established, quality.connections);
+    let established = if quality.established < quality.connections { established.yellow().to_string() } else { established };
+    match (&quality.connect, quality.jitter_ms) {
+        (Some(connect), jitter) => lines.push(format!(
+            "Quality        {}, connect {:.2}/{:.2}/{:.2}ms (min/avg/max), jitter {}",
+            established,
+            connect.min_ms,
+            connect.avg_ms,
+            connect.max_ms,
+            jitter.map_or("-".to_string(), |ms| format!("{:.2}ms", ms))
+        )),
+        (None, _) => lines.push(format!("Quality        {}", established)),
+    }
+    if let (Some(rtt), Some(var), Some(retransmits)) = (quality.rtt_ms, quality.rtt_var_ms, quality.retransmits) {
+        let retransmits = match retransmits {
+            0 => "no retransmits".to_string(),
+            1 => "1 retransmit".yellow().to_string(),
+            n => format!("{} retransmits", n).yellow().to_string(),
+        };
+        let window = match (quality.mss, quality.cwnd) {
+            (Some(mss), Some(cwnd)) => format!(", MSS {}, cwnd {}", mss, cwnd).dimmed().to_string(),
+            _ => String::new(),
+        };
+        lines.push(format!("Kernel RTT     {:.2}ms ± {:.2}ms, {}{}", rtt, var, retransmits, window));
+    }
+    lines
+}
+
+/// Greeting, capabilities and STARTTLS offer of a mail service; algorithms and host keys of SSH;
+/// the APIs of a Kafka broker; the anonymous login of FTP.
+fn service_lines(service: &ServiceResult) -> Vec<String> {
+    let label = format!("{:<15}", service.protocol.to_uppercase());
+    let mut lines = Vec::new();
+    match (service.status.as_str(), &service.error) {
+        ("skipped", _) => lines.push(format!("{}{}", label, "not greeted (TLS handshake failed)".dimmed())),
+        (_, Some(e)) => lines.push(format!("{}{} {}", label, "❌".red(), e)),
+        _ => {
+            let latency = service.greeting_ms.map(|ms| format!(" ({:.2}ms)", ms)).unwrap_or_default();
+            let greeting = service.banner.as_deref().or(service.version.as_deref()).unwrap_or("handshake complete");
+            lines.push(format!("{}{} {}{}", label, "✅".green(), greeting, latency));
+        },
+    }
+    if let (Some(_), Some(version)) = (&service.banner, &service.version) {
+        lines.push(format!("Version        {}", version));
+    }
+    if !service.capabilities.is_empty() {
+        lines.push(format!("Capabilities   {}", service.capabilities.join(", ").dimmed()));
+    }
+    match (service.starttls, service.tls_mode.as_deref()) {
+        (Some(true), _) => lines.push(format!("STARTTLS       {}", "offered".green())),
+        (Some(false), _) => lines.push(format!("STARTTLS       {}", "not offered".red())),
+        (None, Some("implicit")) => lines.push("TLS            implicit (from the first byte)".to_string()),
+        _ => {},
+    }
+    if let Some(ssh) = &service.ssh {
+        lines.push(format!("Key exchange   {}", ssh.kex_algorithms.join(", ").dimmed()));
+        lines.push(format!("Host key algs  {}", ssh.host_key_algorithms.join(", ").dimmed()));
+        lines.push(format!("Ciphers        {}", ssh.ciphers.join(", ").dimmed()));
+        lines.push(format!("MACs           {}", ssh.macs.join(", ").dimmed()));
+        for key in &ssh.host_keys {
+            let bits = key.bits.map(|bits| format!(" ({} bits)", bits)).unwrap_or_default();
+            lines.push(format!("Host key       {}{} {}", key.algorithm, bits, key.fingerprint));
+        }
+        if let Some(e) = &ssh.host_key_error {
+            lines.push(format!("Host key       {}", format!("not fetched: {}", e).yellow()));
+        }
+    }
+    if let Some(login) = &service.ftp_login {
+        let latency = login.latency_ms.map(|ms| format!(" ({:.2}ms)", ms)).unwrap_or_default();
+        lines.push(match login.status.as_str() {
+            "ok" => format!("Anonymous      {} allowed: {}{}", "✅".green(), login.reply.as_deref().unwrap_or_default(), latency),
+            "refused" => format!("Anonymous      {} refused: {}{}", "❌".red(), login.reply.as_deref().unwrap_or_default(), latency),
+            _ => format!("Anonymous      {} {}", "❌".red(), login.error.as_deref().unwrap_or_default()),
+        });
+    }
+    if let Some(kafka) = &service.kafka {
+        let apis: Vec<String> = kafka.api_versions.iter().map(|api| format!("{} v{}-{}", api.name, api.min_version, api.max_version)).collect();
+        lines.push(format!("APIs           {}", apis.join(", ").dimmed()));
+    }
+    lines
+}
+
+fn print_tree(lines: &[String]) {
+    for (i, line) in lines.iter().enumerate() {
+        println!("   {} {}", if i + 1 == lines.len() { "└─" } else { "├─" }, line);
+    }
+}
+
+/// "Trace . → com → example.com", then one line per server asked.
+fn dns_trace_lines(trace: &DnsTrace) -> Vec<String> {
+    let mut zones: Vec<&str> = Vec::new();
+    for hop in trace.hops.iter().filter(|hop| hop.error.is_none()) {
+        if zones.last() != Some(&hop.zone.as_str()) {
+            zones.push(&hop.zone);
+        }
+    }
+    let summary = format!("({} hops, {:.2}ms)", trace.hops.len(), trace.duration_ms).dimmed();
+    let mut lines = vec![match &trace.error {
+        None => format!("Trace          {} {}", zones.join(" → "), summary),
+        Some(e) => format!("Trace          {} {} {}", zones.join(" → "), e.red(), summary),
+    }];
+    let width = trace.hops.iter().map(|hop| hop.zone.len()).max().unwrap_or(1);
+    for hop in &trace.hops {
+        let server = format!("{} ({})", hop.server, hop.address);
+        let outcome = match (&hop.error, &hop.referral) {
+            (Some(e), _) => e.red().to_string(),
+            (None, Some(zone)) => format!("→ {} {}", zone, format!("[{}]", hop.nameservers.join(", ")).dimmed()),
+            (None, None) if hop.answers.is_empty() => hop.rcode.clone().unwrap_or_default(),
+            (None, None) => hop.answers.join(", ").cyan().to_string(),
+        };
+        let latency = hop.latency_ms.map(|ms| format!("{:.2}ms", ms)).unwrap_or_default();
+        lines.push(format!("  {:<width$} {} {} {}", hop.zone, server.dimmed(), outcome, latency.dimmed()));
+    }
+    lines
+}
+
+/// "DNSSEC ✅ secure", or the status and why the answer is not secure.
+fn dnssec_line(dnssec: &DnssecResult) -> String {
+    let status = match dnssec.status.as_str() {
+        "secure" => dnssec.status.green(),
+        "bogus" => dnssec.status.red(),
+        _ => dnssec.status.yellow(),
+    };
+    match &dnssec.reason {
+        Some(reason) => format!("DNSSEC         {} {}: {}", dnssec_icon(&dnssec.status), status, reason),
+        None => format!("DNSSEC         {} {}", dnssec_icon(&dnssec.status), status),
+    }
+}
+
+fn dnssec_icon(status: &str) -> ColoredString {
+    match status {
+        "secure" => "✅".green(),
+        "bogus" => "❌".red(),
+        _ => "⚠️".yellow(),
+    }
+}
+
+/// "Paris, France (FR) · AS13335 Cloudflare, Inc." or the lookup error.
+fn geo_summary(geo: &GeoResult) -> String {
+    if let Some(e) = &geo.error {
+        return e.dimmed().to_string();
+    }
+    let place = [geo.city.clone(), geo.country.as_ref().map(|c| format!("{} ({})", c, geo.country_code.as_deref().unwrap_or("?")))]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ");
+    let network = match (geo.asn, &geo.as_org) {
+        (Some(asn), Some(org)) => format!("AS{} {}", asn, org),
+        (Some(asn), None) => format!("AS{}", asn),
+        (None, Some(org)) => org.clone(),
+        (None, None) => String::new(),
+    };
+    [place, network].into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join(" · ")
+}
+
+/// Outcome of a Happy Eyeballs race: the winning family and its lead over the other one.
+fn race_line(race: &RaceResult, dns: &DnsResult) -> String {
+    let family = |name: &str| if name == "ipv6" { "IPv6" } else { "IPv4" };
+    let (Some(winner), Some(ip)) = (&race.winner, &race.winner_ip) else {
+        return "no address accepted the connection".red().to_string();
+    };
+    let other = if winner == "ipv6" { "ipv4" } else { "ipv6" };
+    let lead = match race.margin_ms {
+        Some(margin) => format!(" by {:.2}ms over {}", margin, family(other)),
+        None if race.attempts.iter().any(|attempt| attempt.family == other) => format!(", {} failed", family(other)),
+        None if (other == "ipv6" && dns.ipv6.is_empty()) || (other == "ipv4" && dns.ipv4.is_empty()) => format!(", no {} address", family(other)),
+        None => format!(" before {} was tried", family(other)),
+    };
+    format!("{} won{} {}", family(winner).green(), lead, format!("({})", ip).dimmed())
+}
+
+/// Suffix naming non-default DNS transports, e.g. " (DoT)".
+fn transport_label(transport: &str) -> &'static str {
+    match transport {
+        "tcp" => " (TCP)",
+        "tls" => " (DoT)",
+        "https" => " (DoH)",
+        "quic" => " (DoQ)",
+        _ => "",
+    }
+}
+
+/// Print record lookups as pretty JSON.
+pub fn print_records_json(lookup: &RecordLookup) {
+    println!("{}", serde_json::to_string_pretty(lookup).unwrap());
+}
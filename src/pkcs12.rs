@@ -0,0 +1,392 @@
+//! Minimal PKCS#12 (`.p12` / `.pfx`) reader for client certificates.
+//!
+//! Supports what current OpenSSL writes by default: PBES2 (PBKDF2 + AES-CBC)
+//! encrypted bags, or no encryption at all, with an HMAC integrity check.
+//! The legacy RC2 / 3DES schemes are reported as unsupported.
+//!
+//! Only the ASN.1 structure is read here: PBKDF2 and the HMAC come from ring,
+//! AES-CBC from the RustCrypto `aes` and `cbc` crates.
+
+use crate::x509::{oid_to_string, Der, TAG_INTEGER, TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE};
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, KeyIvInit};
+use ring::{digest, hmac, pbkdf2};
+use std::num::NonZeroU32;
+
+const OID_DATA: &str = "1.2.840.113549.1.7.1";
+const OID_ENCRYPTED_DATA: &str = "1.2.840.113549.1.7.6";
+const OID_KEY_BAG: &str = "1.2.840.113549.1.12.10.1.1";
+const OID_SHROUDED_KEY_BAG: &str = "1.2.840.113549.1.12.10.1.2";
+const OID_CERT_BAG: &str = "1.2.840.113549.1.12.10.1.3";
+const OID_X509_CERTIFICATE: &str = "1.2.840.113549.1.9.22.1";
+const OID_LOCAL_KEY_ID: &str = "1.2.840.113549.1.9.21";
+const OID_PBES2: &str = "1.2.840.113549.1.5.13";
+const OID_PBKDF2: &str = "1.2.840.113549.1.5.12";
+
+const TAG_SET: u8 = 0x31;
+const TAG_CONTEXT_0: u8 = 0xa0;
+const TAG_CONTEXT_0_PRIMITIVE: u8 = 0x80;
+
+/// Certificates (leaf first) and the PKCS#8 private key found in a PKCS#12 file.
+#[derive(Debug)]
+pub(crate) struct Identity {
+    pub certificates: Vec<Vec<u8>>,
+    pub key: Vec<u8>,
+}
+
+/// One safe bag's payload, with its `localKeyId` attribute pairing keys and certificates.
+struct Bag {
+    local_key_id: Option<Vec<u8>>,
+    content: BagContent,
+}
+
+enum BagContent {
+    Key(Vec<u8>),
+    Certificate(Vec<u8>),
+}
+
+/// Decode `der`, checking its MAC and decrypting its bags with `password`.
+pub(crate) fn parse(der: &[u8], password: &str) -> Result<Identity, String> {
+    let malformed = || "not a valid PKCS#12 file".to_string();
+    let pfx = Der::new(der).expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let mut fields = Der::new(pfx.content);
+    fields.expect(TAG_INTEGER).ok_or_else(malformed)?;
+    let (content_type, auth_safe) = content_info(fields.expect(TAG_SEQUENCE).ok_or_else(malformed)?.content).ok_or_else(malformed)?;
+    if content_type != OID_DATA {
+        return Err("PKCS#12 files signed with a public key are not supported".to_string());
+    }
+    let auth_safe = octet_string(auth_safe).ok_or_else(malformed)?;
+    if let Some(mac_data) = fields.expect(TAG_SEQUENCE) {
+        verify_mac(mac_data.content, &auth_safe, password)?;
+    }
+
+    let mut bags = Vec::new();
+    let mut contents = Der::new(Der::new(&auth_safe).expect(TAG_SEQUENCE).ok_or_else(malformed)?.content);
+    while let Some(info) = contents.expect(TAG_SEQUENCE) {
+        let (content_type, content) = content_info(info.content).ok_or_else(malformed)?;
+        let safe_contents = match content_type.as_str() {
+            OID_DATA => octet_string(content).ok_or_else(malformed)?,
+            OID_ENCRYPTED_DATA => decrypt_data(content, password)?,
+            other => return Err(format!("unsupported PKCS#12 content type {}", other)),
+        };
+        read_bags(&safe_contents, password, &mut bags)?;
+    }
+
+    let (mut keys, mut certificates) = (Vec::new(), Vec::new());
+    for bag in bags {
+        match bag.content {
+            BagContent::Key(key) => keys.push((bag.local_key_id, key)),
+            BagContent::Certificate(cert) => certificates.push((bag.local_key_id, cert)),
+        }
+    }
+    let (key_id, key) = keys.into_iter().next().ok_or("PKCS#12 file holds no private key")?;
+    if certificates.is_empty() {
+        return Err("PKCS#12 file holds no certificate".to_string());
+    }
+    // The certificate sharing the key's localKeyId is the leaf; the others are its chain.
+    if let Some(leaf) = certificates.iter().position(|(id, _)| id.is_some() && *id == key_id) {
+        certificates.swap(0, leaf);
+    }
+    Ok(Identity { certificates: certificates.into_iter().map(|(_, cert)| cert).collect(), key })
+}
+
+/// `ContentInfo`: content type OID and the `[0] EXPLICIT` content.
+fn content_info(der: &[u8]) -> Option<(String, &[u8])> {
+    let mut fields = Der::new(der);
+    let content_type = oid_to_string(fields.expect(TAG_OID)?.content);
+    let content = fields.expect(TAG_CONTEXT_0)?.content;
+    Some((content_type, content))
+}
+
+/// The bytes of an OCTET STRING, primitive or constructed (BER) from chunks.
+fn octet_string(der: &[u8]) -> Option<Vec<u8>> {
+    let tlv = Der::new(der).read()?;
+    match tlv.tag {
+        TAG_OCTET_STRING | TAG_CONTEXT_0_PRIMITIVE => Some(tlv.content.to_vec()),
+        0x24 | TAG_CONTEXT_0 => chunks(tlv.content),
+        _ => None,
+    }
+}
+
+fn chunks(content: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Der::new(content);
+    let mut bytes = Vec::new();
+    while reader.peek_tag().is_some() {
+        bytes.extend_from_slice(reader.expect(TAG_OCTET_STRING)?.content);
+    }
+    Some(bytes)
+}
+
+fn read_bags(safe_contents: &[u8], password: &str, bags: &mut Vec<Bag>) -> Result<(), String> {
+    let malformed = || "malformed PKCS#12 bag".to_string();
+    let mut reader = Der::new(Der::new(safe_contents).expect(TAG_SEQUENCE).ok_or_else(malformed)?.content);
+    while let Some(bag) = reader.expect(TAG_SEQUENCE) {
+        let mut fields = Der::new(bag.content);
+        let bag_type = oid_to_string(fields.expect(TAG_OID).ok_or_else(malformed)?.content);
+        let value = fields.expect(TAG_CONTEXT_0).ok_or_else(malformed)?.content;
+        let local_key_id = fields.expect(TAG_SET).and_then(|attributes| local_key_id(attributes.content));
+        let content = match bag_type.as_str() {
+            OID_KEY_BAG => BagContent::Key(value.to_vec()),
+            OID_SHROUDED_KEY_BAG => {
+                let info = Der::new(value).expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+                let mut fields = Der::new(info.content);
+                let algorithm = fields.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+                let encrypted = fields.expect(TAG_OCTET_STRING).ok_or_else(malformed)?;
+                BagContent::Key(decrypt(algorithm.content, encrypted.content, password)?)
+            },
+            OID_CERT_BAG => {
+                let cert_bag = Der::new(value).expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+                let mut fields = Der::new(cert_bag.content);
+                let cert_type = oid_to_string(fields.expect(TAG_OID).ok_or_else(malformed)?.content);
+                if cert_type != OID_X509_CERTIFICATE {
+                    continue;
+                }
+                BagContent::Certificate(octet_string(fields.expect(TAG_CONTEXT_0).ok_or_else(malformed)?.content).ok_or_else(malformed)?)
+            },
+            // CRLs, secrets and nested safe contents are of no use for a client identity.
+            _ => continue,
+        };
+        bags.push(Bag { local_key_id, content });
+    }
+    Ok(())
+}
+
+fn local_key_id(attributes: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Der::new(attributes);
+    while let Some(attribute) = reader.expect(TAG_SEQUENCE) {
+        let mut fields = Der::new(attribute.content);
+        if oid_to_string(fields.expect(TAG_OID)?.content) == OID_LOCAL_KEY_ID {
+            return Some(Der::new(fields.expect(TAG_SET)?.content).expect(TAG_OCTET_STRING)?.content.to_vec());
+        }
+    }
+    None
+}
+
+/// `EncryptedData`: version, then `EncryptedContentInfo`.
+fn decrypt_data(der: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    let malformed = || "malformed PKCS#12 encrypted data".to_string();
+    let encrypted_data = Der::new(der).expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let mut fields = Der::new(encrypted_data.content);
+    fields.expect(TAG_INTEGER).ok_or_else(malformed)?;
+    let info = fields.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let mut fields = Der::new(info.content);
+    fields.expect(TAG_OID).ok_or_else(malformed)?;
+    let algorithm = fields.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let rest = fields.read().ok_or_else(malformed)?;
+    let ciphertext = match rest.tag {
+        TAG_CONTEXT_0_PRIMITIVE => rest.content.to_vec(),
+        TAG_CONTEXT_0 => chunks(rest.content).ok_or_else(malformed)?,
+        _ => return Err(malformed()),
+    };
+    decrypt(algorithm.content, &ciphertext, password)
+}
+
+/// Decrypt with the PBES2 scheme described by `algorithm` (an AlgorithmIdentifier body).
+fn decrypt(algorithm: &[u8], ciphertext: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    let malformed = || "malformed PKCS#12 encryption parameters".to_string();
+    let mut fields = Der::new(algorithm);
+    let scheme = oid_to_string(fields.expect(TAG_OID).ok_or_else(malformed)?.content);
+    if scheme != OID_PBES2 {
+        return Err(format!(
+            "PKCS#12 file uses legacy encryption ({}); re-export it with `openssl pkcs12 -export -keypbe AES-256-CBC -certpbe AES-256-CBC`",
+            scheme
+        ));
+    }
+    let mut params = Der::new(fields.expect(TAG_SEQUENCE).ok_or_else(malformed)?.content);
+    let kdf = params.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let cipher = params.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+
+    // Key derivation: PBKDF2 with an HMAC PRF (SHA-1 unless stated).
+    let mut kdf = Der::new(kdf.content);
+    if oid_to_string(kdf.expect(TAG_OID).ok_or_else(malformed)?.content) != OID_PBKDF2 {
+        return Err("unsupported PKCS#12 key derivation (expected PBKDF2)".to_string());
+    }
+    let mut kdf_params = Der::new(kdf.expect(TAG_SEQUENCE).ok_or_else(malformed)?.content);
+    let salt = kdf_params.expect(TAG_OCTET_STRING).ok_or_else(malformed)?.content;
+    let iterations = integer(kdf_params.expect(TAG_INTEGER).ok_or_else(malformed)?.content).and_then(|n| NonZeroU32::new(n as u32)).ok_or_else(malformed)?;
+    kdf_params.optional(TAG_INTEGER);
+    let prf = match kdf_params.expect(TAG_SEQUENCE) {
+        Some(prf) => oid_to_string(Der::new(prf.content).expect(TAG_OID).ok_or_else(malformed)?.content),
+        None => "1.2.840.113549.2.7".to_string(),
+    };
+    let prf = match prf.as_str() {
+        "1.2.840.113549.2.7" => pbkdf2::PBKDF2_HMAC_SHA1,
+        "1.2.840.113549.2.9" => pbkdf2::PBKDF2_HMAC_SHA256,
+        "1.2.840.113549.2.10" => pbkdf2::PBKDF2_HMAC_SHA384,
+        "1.2.840.113549.2.11" => pbkdf2::PBKDF2_HMAC_SHA512,
+        other => return Err(format!("unsupported PKCS#12 PBKDF2 function {}", other)),
+    };
+
+    // Cipher: AES-CBC with the IV as parameter.
+    let mut cipher = Der::new(cipher.content);
+    let key_len = match oid_to_string(cipher.expect(TAG_OID).ok_or_else(malformed)?.content).as_str() {
+        "2.16.840.1.101.3.4.1.2" => 16,
+        "2.16.840.1.101.3.4.1.22" => 24,
+        "2.16.840.1.101.3.4.1.42" => 32,
+        other => return Err(format!("unsupported PKCS#12 cipher {}", other)),
+    };
+    let iv: [u8; 16] = cipher.expect(TAG_OCTET_STRING).ok_or_else(malformed)?.content.try_into().map_err(|_| malformed())?;
+
+    let mut key = vec![0; key_len];
+    pbkdf2::derive(prf, iterations, salt, password.as_bytes(), &mut key);
+    decrypt_aes_cbc(&key, &iv, ciphertext).ok_or_else(|| "cannot decrypt PKCS#12 file (wrong password?)".to_string())
+}
+
+/// Check the HMAC of `MacData` over the authenticated safe, keyed from `password`.
+fn verify_mac(mac_data: &[u8], auth_safe: &[u8], password: &str) -> Result<(), String> {
+    let malformed = || "malformed PKCS#12 MAC".to_string();
+    let mut fields = Der::new(mac_data);
+    let digest_info = fields.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let salt = fields.expect(TAG_OCTET_STRING).ok_or_else(malformed)?.content;
+    let iterations = fields.expect(TAG_INTEGER).and_then(|n| integer(n.content)).unwrap_or(1);
+
+    let mut digest_info = Der::new(digest_info.content);
+    let algorithm = digest_info.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let expected = digest_info.expect(TAG_OCTET_STRING).ok_or_else(malformed)?.content;
+    let (hash, mac) = match oid_to_string(Der::new(algorithm.content).expect(TAG_OID).ok_or_else(malformed)?.content).as_str() {
+        "1.3.14.3.2.26" => (&digest::SHA1_FOR_LEGACY_USE_ONLY, hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY),
+        "2.16.840.1.101.3.4.2.1" => (&digest::SHA256, hmac::HMAC_SHA256),
+        "2.16.840.1.101.3.4.2.2" => (&digest::SHA384, hmac::HMAC_SHA384),
+        "2.16.840.1.101.3.4.2.3" => (&digest::SHA512, hmac::HMAC_SHA512),
+        other => return Err(format!("unsupported PKCS#12 MAC digest {}", other)),
+    };
+
+    // An empty password may have been encoded with or without its terminator.
+    let mut candidates = vec![bmp_string(password)];
+    if password.is_empty() {
+        candidates.push(Vec::new());
+    }
+    for candidate in candidates {
+        let key = pkcs12_kdf(hash, &candidate, salt, iterations, 3, hash.output_len());
+        if hmac::verify(&hmac::Key::new(mac, &key), auth_safe, expected).is_ok() {
+            return Ok(());
+        }
+    }
+    Err("PKCS#12 integrity check failed (wrong password?)".to_string())
+}
+
+/// Password as a NUL-terminated UTF-16BE string, the form the PKCS#12 KDF takes.
+fn bmp_string(password: &str) -> Vec<u8> {
+    password.encode_utf16().chain([0]).flat_map(u16::to_be_bytes).collect()
+}
+
+/// Key derivation of RFC 7292, appendix B.2; `id` 3 derives MAC keys.
+fn pkcs12_kdf(hash: &'static digest::Algorithm, password: &[u8], salt: &[u8], iterations: u64, id: u8, len: usize) -> Vec<u8> {
+    let v = hash.block_len();
+    let fill = |data: &[u8]| -> Vec<u8> {
+        if data.is_empty() {
+            return Vec::new();
+        }
+        data.iter().copied().cycle().take(v * data.len().div_ceil(v)).collect()
+    };
+    let mut input = fill(salt);
+    input.extend(fill(password));
+
+    let mut output = Vec::with_capacity(len);
+    while output.len() < len {
+        let mut block = [vec![id; v], input.clone()].concat();
+        for _ in 0..iterations.max(1) {
+            block = digest::digest(hash, &block).as_ref().to_vec();
+        }
+        output.extend_from_slice(&block);
+        // I_j = (I_j + B + 1) mod 2^(8v) for every v-byte block of I, B being A repeated to v bytes.
+        let b: Vec<u8> = block.iter().copied().cycle().take(v).collect();
+        for chunk in input.chunks_mut(v) {
+            let mut carry = 1u16;
+            for (byte, add) in chunk.iter_mut().zip(&b).rev() {
+                let sum = *byte as u16 + *add as u16 + carry;
+                *byte = sum as u8;
+                carry = sum >> 8;
+            }
+        }
+    }
+    output.truncate(len);
+    output
+}
+
+/// Non-negative INTEGER that fits in a u64.
+fn integer(content: &[u8]) -> Option<u64> {
+    if content.is_empty() || content.len() > 9 || content[0] & 0x80 != 0 {
+        return None;
+    }
+    Some(content.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+/// AES-CBC with PKCS#7 padding, AES-128/192/256 by the length of `key`; `None` if the padding is wrong.
+fn decrypt_aes_cbc(key: &[u8], iv: &[u8; 16], data: &[u8]) -> Option<Vec<u8>> {
+    match key.len() {
+        16 => cbc::Decryptor::<aes::Aes128>::new_from_slices(key, iv).ok()?.decrypt_padded_vec_mut::<Pkcs7>(data).ok(),
+        24 => cbc::Decryptor::<aes::Aes192>::new_from_slices(key, iv).ok()?.decrypt_padded_vec_mut::<Pkcs7>(data).ok(),
+        32 => cbc::Decryptor::<aes::Aes256>::new_from_slices(key, iv).ok()?.decrypt_padded_vec_mut::<Pkcs7>(data).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: &[u8] = include_bytes!("../tests/data/identity.p12");
+    const UNENCRYPTED: &[u8] = include_bytes!("../tests/data/plain.p12");
+    const LEAF: &[u8] = include_bytes!("../tests/data/leaf.der");
+    const CA: &[u8] = include_bytes!("../tests/data/ca.der");
+    const KEY: &[u8] = include_bytes!("../tests/data/leaf.key.der");
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn reads_an_encrypted_identity() {
+        let identity = parse(IDENTITY, "secret").unwrap();
+        assert_eq!(identity.certificates, [LEAF, CA]);
+        assert_eq!(identity.key, KEY);
+    }
+
+    #[test]
+    fn reads_an_unencrypted_identity() {
+        let identity = parse(UNENCRYPTED, "").unwrap();
+        assert_eq!(identity.certificates, [LEAF]);
+        assert_eq!(identity.key, KEY);
+    }
+
+    #[test]
+    fn rejects_a_wrong_password() {
+        assert!(parse(IDENTITY, "Secret").unwrap_err().contains("wrong password"));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        for len in 0..IDENTITY.len() {
+            assert!(parse(&IDENTITY[..len], "secret").is_err(), "prefix of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        // Any change to the authenticated content fails the MAC.
+        let mut corrupt = IDENTITY.to_vec();
+        corrupt[IDENTITY.len() / 2] ^= 0x01;
+        assert!(parse(&corrupt, "secret").is_err());
+        assert_eq!(parse(b"\x30\x03\x02\x01\x03", "").unwrap_err(), "not a valid PKCS#12 file");
+        assert!(parse(&[0xff; 64], "").is_err());
+    }
+
+    #[test]
+    fn decrypts_aes_cbc() {
+        let iv: [u8; 16] = hex("0f0e0d0c0b0a09080706050403020100").try_into().unwrap();
+        let key = hex("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let vectors = [
+            (16, "d2cba64da75953ef05d341590d06a211607c075f4db493f08b56b50864c6c482"),
+            (24, "c4dc714ced809b1f3cefef3459f83c5c61caed3951db48cd5e4b96813a5b8020"),
+            (32, "f63c183d5e1dc1ad34769c45ffc56edd7ec1d3928abf15e30d3ccefbbd6acd4c"),
+        ];
+        for (key_len, ciphertext) in vectors {
+            let plain = decrypt_aes_cbc(&key[..key_len], &iv, &hex(ciphertext)).unwrap();
+            assert_eq!(plain, b"NetProbe AES-CBC test vector", "AES-{}", key_len * 8);
+        }
+        // Bad padding, and a length that is not a whole number of blocks.
+        assert!(decrypt_aes_cbc(&key[..16], &iv, &hex("d2cba64da75953ef05d341590d06a211")).is_none());
+        assert!(decrypt_aes_cbc(&key[..16], &iv, &[0; 15]).is_none());
+    }
+}
@@ -0,0 +1,211 @@
+//! Optional: HTTP authentication (`--auth-basic`, `--auth-bearer`, `--auth-digest`).
+//!
+//! Basic and Bearer credentials are sent with the first request. Digest needs
+//! the server's nonce, so the request is sent without credentials first and
+//! replayed once with an answer to the `WWW-Authenticate` challenge (RFC 7616).
+
+use hyper::header::{self, HeaderMap};
+use hyper::Method;
+use ring::rand::SecureRandom;
+
+/// Credentials of the HTTP stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpAuth {
+    Basic { user: String, password: String },
+    Bearer(String),
+    Digest { user: String, password: String },
+}
+
+impl HttpAuth {
+    /// "basic" | "bearer" | "digest"
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            HttpAuth::Basic { .. } => "basic",
+            HttpAuth::Bearer(_) => "bearer",
+            HttpAuth::Digest { .. } => "digest",
+        }
+    }
+
+    /// `Authorization` value sent up front; `None` for Digest, which waits for a challenge.
+    pub(crate) fn preemptive(&self) -> Option<String> {
+        use base64::Engine;
+        match self {
+            HttpAuth::Basic { user, password } => Some(format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password)))),
+            HttpAuth::Bearer(token) => Some(format!("Bearer {}", token)),
+            HttpAuth::Digest { .. } => None,
+        }
+    }
+
+    /// `Authorization` value answering the Digest challenge of a 401 response, and the algorithm used.
+    pub(crate) fn respond(&self, headers: &HeaderMap, method: &Method, uri: &str) -> Option<(String, &'static str)> {
+        let HttpAuth::Digest { user, password } = self else { return None };
+        // Servers may offer several algorithms, one challenge each; take the strongest we support.
+        let challenge = headers
+            .get_all(header::WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(Challenge::parse)
+            .filter_map(|c| Some((Algorithm::parse(c.param("algorithm").unwrap_or("MD5"))?, c)))
+            .max_by_key(|(algorithm, _)| algorithm.strength)?;
+        let (algorithm, challenge) = challenge;
+        let realm = challenge.param("realm").unwrap_or_default();
+        let nonce = challenge.param("nonce")?;
+        // qop=auth only: auth-int would need a hash of the body.
+        let qop = match challenge.param("qop") {
+            Some(offered) if offered.split(',').any(|q| q.trim().eq_ignore_ascii_case("auth")) => Some("auth"),
+            Some(_) => return None,
+            None => None,
+        };
+
+        let mut bytes = [0u8; 8];
+        ring::rand::SystemRandom::new().fill(&mut bytes).ok()?;
+        let cnonce: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let nc = "00000001";
+
+        let mut ha1 = algorithm.hash(&format!("{}:{}:{}", user, realm, password));
+        if algorithm.session {
+            ha1 = algorithm.hash(&format!("{}:{}:{}", ha1, nonce, cnonce));
+        }
+        let ha2 = algorithm.hash(&format!("{}:{}", method, uri));
+        let response = match qop {
+            Some(qop) => algorithm.hash(&format!("{}:{}:{}:{}:{}:{}", ha1, nonce, nc, cnonce, qop, ha2)),
+            None => algorithm.hash(&format!("{}:{}:{}", ha1, nonce, ha2)),
+        };
+
+        let mut value = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+            quote(user),
+            quote(realm),
+            quote(nonce),
+            quote(uri),
+            algorithm.name,
+            response
+        );
+        if let Some(qop) = qop {
+            value.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+        }
+        if let Some(opaque) = challenge.param("opaque") {
+            value.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        Some((value, algorithm.name))
+    }
+}
+
+/// A `Digest` challenge of a `WWW-Authenticate` header.
+struct Challenge {
+    params: Vec<(String, String)>,
+}
+
+impl Challenge {
+    fn parse(header: &str) -> Option<Challenge> {
+        let (scheme, rest) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("Digest") {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut chars = rest.chars().peekable();
+        loop {
+            while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+            let name: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=' && *c != ',')).collect();
+            if name.is_empty() {
+                break;
+            }
+            let mut value = String::new();
+            if chars.next_if_eq(&'=').is_some() {
+                if chars.next_if_eq(&'"').is_some() {
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' => value.extend(chars.next()),
+                            '"' => break,
+                            c => value.push(c),
+                        }
+                    }
+                } else {
+                    value = std::iter::from_fn(|| chars.next_if(|c| *c != ',')).collect::<String>().trim().to_string();
+                }
+            }
+            params.push((name.trim().to_ascii_lowercase(), value));
+        }
+        Some(Challenge { params })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+struct Algorithm {
+    name: &'static str,
+    digest: Option<&'static ring::digest::Algorithm>,
+    session: bool,
+    strength: u8,
+}
+
+impl Algorithm {
+    fn parse(name: &str) -> Option<Algorithm> {
+        let (name, digest, session, strength) = match name.to_ascii_uppercase().as_str() {
+            "MD5" => ("MD5", None, false, 0),
+            "MD5-SESS" => ("MD5-sess", None, true, 0),
+            "SHA-256" => ("SHA-256", Some(&ring::digest::SHA256), false, 1),
+            "SHA-256-SESS" => ("SHA-256-sess", Some(&ring::digest::SHA256), true, 1),
+            "SHA-512-256" => ("SHA-512-256", Some(&ring::digest::SHA512_256), false, 2),
+            "SHA-512-256-SESS" => ("SHA-512-256-sess", Some(&ring::digest::SHA512_256), true, 2),
+            _ => return None,
+        };
+        Some(Algorithm { name, digest, session, strength })
+    }
+
+    /// Lowercase hex digest of `data`.
+    fn hash(&self, data: &str) -> String {
+        let digest = match self.digest {
+            Some(algorithm) => ring::digest::digest(algorithm, data.as_bytes()).as_ref().to_vec(),
+            None => md5(data.as_bytes()).to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Escape a quoted-string parameter.
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// MD5 (RFC 1321): still the default Digest algorithm, and not in ring.
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15, 21, 6, 10, 15,
+        21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    let constants: Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32).collect();
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in message.chunks(64) {
+        let words: Vec<u32> = block.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(constants[i]).wrapping_add(words[g]).rotate_left(SHIFTS[i]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    let mut digest = [0u8; 16];
+    for (chunk, word) in digest.chunks_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
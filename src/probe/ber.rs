@@ -0,0 +1,113 @@
+//! The subset of BER (X.690) that LDAP and SNMP messages need: one-byte tags
+//! and definite lengths.
+
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const NULL: u8 = 0x05;
+pub(crate) const OBJECT_IDENTIFIER: u8 = 0x06;
+pub(crate) const ENUMERATED: u8 = 0x0a;
+pub(crate) const SEQUENCE: u8 = 0x30;
+
+/// Tag, length and value.
+pub(crate) fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![tag];
+    match value.len() {
+        short @ 0..=0x7f => bytes.push(short as u8),
+        long => {
+            let length = (long as u32).to_be_bytes();
+            let skip = length.iter().take_while(|&&byte| byte == 0).count();
+            bytes.push(0x80 | (4 - skip) as u8);
+            bytes.extend(&length[skip..]);
+        },
+    }
+    bytes.extend(value);
+    bytes
+}
+
+/// An INTEGER in the fewest bytes, as BER requires.
+pub(crate) fn integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    // A leading byte can go when it only repeats the sign of the next one.
+    while start < 7 && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0)) {
+        start += 1;
+    }
+    tlv(INTEGER, &bytes[start..])
+}
+
+/// Value of an INTEGER (or TimeTicks, Counter...) of up to 8 bytes.
+pub(crate) fn signed(value: &[u8]) -> Option<i64> {
+    if value.is_empty() || value.len() > 8 {
+        return None;
+    }
+    let fill = if value[0] & 0x80 != 0 { 0xff } else { 0 };
+    let mut bytes = [fill; 8];
+    bytes[8 - value.len()..].copy_from_slice(value);
+    Some(i64::from_be_bytes(bytes))
+}
+
+/// Elements one after the other.
+pub(crate) struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn next(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.0.split_first()?;
+        let (&first, mut rest) = rest.split_first()?;
+        let length = match first {
+            short @ 0..=0x7f => usize::from(short),
+            long @ 0x81..=0x84 => {
+                let (bytes, after) = rest.split_at_checked(usize::from(long & 0x7f))?;
+                rest = after;
+                bytes.iter().fold(0, |length, &byte| length << 8 | usize::from(byte))
+            },
+            _ => return None,
+        };
+        let (value, rest) = rest.split_at_checked(length)?;
+        self.0 = rest;
+        Some((tag, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_integers_in_the_fewest_bytes() {
+        assert_eq!(integer(0), [INTEGER, 1, 0x00]);
+        assert_eq!(integer(127), [INTEGER, 1, 0x7f]);
+        assert_eq!(integer(128), [INTEGER, 2, 0x00, 0x80]);
+        assert_eq!(integer(-1), [INTEGER, 1, 0xff]);
+        assert_eq!(integer(-129), [INTEGER, 2, 0xff, 0x7f]);
+        for value in [0, 1, -1, 255, -256, 65_536, i64::MAX, i64::MIN] {
+            let encoded = integer(value);
+            assert_eq!(Reader(&encoded).next().and_then(|(_, value)| signed(value)), Some(value));
+        }
+        assert_eq!(signed(&[]), None);
+        assert_eq!(signed(&[1; 9]), None);
+    }
+
+    #[test]
+    fn reads_back_long_lengths() {
+        for len in [0, 127, 128, 255, 256, 70_000] {
+            let value = vec![0xa5; len];
+            let encoded = [tlv(OCTET_STRING, &value), tlv(NULL, &[])].concat();
+            let mut reader = Reader(&encoded);
+            assert_eq!(reader.next(), Some((OCTET_STRING, value.as_slice())));
+            assert_eq!(reader.next(), Some((NULL, &[][..])));
+            assert_eq!(reader.next(), None);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_elements() {
+        let encoded = tlv(SEQUENCE, &tlv(OCTET_STRING, &[0x42; 300]));
+        for len in 0..encoded.len() {
+            assert_eq!(Reader(&encoded[..len]).next(), None, "prefix of {} bytes", len);
+        }
+        // Indefinite and five-byte lengths, and a length past the end.
+        for bad in [&[SEQUENCE, 0x80, 0, 0][..], &[SEQUENCE, 0x85, 0, 0, 0, 0, 1, 0], &[SEQUENCE, 0x84, 0xff, 0xff, 0xff, 0xff]] {
+            assert_eq!(Reader(bad).next(), None, "{:02x?}", bad);
+        }
+    }
+}
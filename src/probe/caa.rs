@@ -0,0 +1,165 @@
+//! Optional: CAA policy check (`--check-caa`).
+//!
+//! CAA records (RFC 8659) name the CAs allowed to issue certificates for a
+//! domain. The relevant set is the host's own or, when it has none, that of
+//! its closest ancestor with any; `issuewild` replaces `issue` for wildcard
+//! certificates. The certificate's issuer is matched to the CAA identifiers of
+//! the well-known CAs below: CAs only check CAA at issuance, so a mismatch means
+//! the policy changed since, or the certificate came from somewhere unexpected.
+
+use super::dns;
+use super::tls::CertificateInfo;
+use super::ProbeOptions;
+use crate::resolver::{self, wire, Resolver, Upstream};
+use serde::Serialize;
+
+/// Issuer organisation (or common name) → the CAA identifiers its certificates are issued under.
+const CA_DOMAINS: [(&str, &[&str]); 22] = [
+    ("Let's Encrypt", &["letsencrypt.org"]),
+    ("Google Trust Services", &["pki.goog", "google.com"]),
+    ("DigiCert", &["digicert.com", "symantec.com", "geotrust.com", "rapidssl.com", "thawte.com", "digitalcertvalidation.com"]),
+    ("Cloudflare", &["digicert.com"]),
+    ("QuoVadis", &["quovadisglobal.com", "digicert.com"]),
+    ("Sectigo", &["sectigo.com", "comodoca.com", "comodo.com", "usertrust.com", "trust-provider.com"]),
+    ("COMODO", &["sectigo.com", "comodoca.com", "comodo.com", "usertrust.com", "trust-provider.com"]),
+    ("ZeroSSL", &["sectigo.com", "zerossl.com"]),
+    ("Amazon", &["amazon.com", "amazontrust.com", "awstrust.com", "amazonaws.com"]),
+    ("GlobalSign", &["globalsign.com"]),
+    ("GoDaddy", &["godaddy.com", "starfieldtech.com"]),
+    ("Starfield", &["starfieldtech.com", "godaddy.com"]),
+    ("Entrust", &["entrust.net", "affirmtrust.com"]),
+    ("Microsoft", &["microsoft.com"]),
+    ("Buypass", &["buypass.com", "buypass.no"]),
+    ("SSL.com", &["ssl.com"]),
+    ("Certum", &["certum.pl", "certum.eu"]),
+    ("Actalis", &["actalis.it"]),
+    ("HARICA", &["harica.gr"]),
+    ("IdenTrust", &["identrust.com"]),
+    ("Trustwave", &["trustwave.com"]),
+    ("Telia", &["telia.com", "teliasonera.com"]),
+];
+
+/// Property tags of RFC 8659 and its extensions; an unknown one marked critical forbids issuance.
+const KNOWN_TAGS: [&str; 6] = ["issue", "issuewild", "iodef", "contactemail", "contactphone", "issuemail"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaaResult {
+    /// "authorized" | "unauthorized" | "unrestricted" (no CAA records: any CA may issue)
+    /// | "unknown" (the issuer is not a CA NetProbe can map) | "error"
+    pub status: String,
+    /// Name the CAA records were found at: the host or its closest ancestor with any.
+    pub domain: Option<String>,
+    /// The records, in presentation format.
+    pub records: Vec<String>,
+    /// Whether `issuewild` was the property checked (a wildcard certificate).
+    pub wildcard: bool,
+    /// CAA identifiers the policy allows; empty when it forbids every CA.
+    pub allowed: Vec<String>,
+    /// CAA identifiers of the certificate's issuer, e.g. "letsencrypt.org".
+    pub issuer_domains: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Check that the CA behind `cert` may issue for `host` according to its CAA records.
+pub async fn check(host: &str, cert: &CertificateInfo, options: &ProbeOptions) -> CaaResult {
+    let servers = if options.dns_servers.is_empty() {
+        resolver::system_servers().into_iter().map(Upstream::Plain).collect()
+    } else {
+        options.dns_servers.clone()
+    };
+    let resolver = dns::resolver(servers, options);
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let wildcard = !cert.sans.iter().any(|san| san.eq_ignore_ascii_case(&host)) && cert.sans.iter().any(|san| san.starts_with("*.") && host.split_once('.').is_some_and(|(_, parent)| san[2..].eq_ignore_ascii_case(parent)));
+    let issuer_domains = issuer_domains(&cert.issuer);
+    let mut result = CaaResult { status: "error".to_string(), domain: None, records: Vec::new(), wildcard, allowed: Vec::new(), issuer_domains, error: None };
+
+    let records = match relevant_set(&resolver, &host).await {
+        Ok(Some((domain, records))) => {
+            result.domain = Some(domain);
+            records
+        },
+        Ok(None) => {
+            result.status = "unrestricted".to_string();
+            return result;
+        },
+        Err(e) => {
+            result.error = Some(e);
+            return result;
+        },
+    };
+    result.records = records.iter().map(|(flags, tag, value)| format!("{} {} {:?}", flags, tag, value)).collect();
+
+    if let Some((_, tag, _)) = records.iter().find(|(flags, tag, _)| flags & 0x80 != 0 && !KNOWN_TAGS.contains(&tag.to_ascii_lowercase().as_str())) {
+        result.status = "unauthorized".to_string();
+        result.error = Some(format!("Unknown critical CAA property \"{}\" forbids every CA", tag));
+        return result;
+    }
+    // issuewild, when present, is the only property that applies to a wildcard certificate.
+    let property = if wildcard && records.iter().any(|(_, tag, _)| tag.eq_ignore_ascii_case("issuewild")) { "issuewild" } else { "issue" };
+    let values: Vec<&String> = records.iter().filter(|(_, tag, _)| tag.eq_ignore_ascii_case(property)).map(|(_, _, value)| value).collect();
+    if values.is_empty() {
+        // Only iodef or other properties: issuance is not restricted.
+        result.status = "unrestricted".to_string();
+        return result;
+    }
+    result.allowed = values.iter().map(|value| value.split(';').next().unwrap_or_default().trim().trim_end_matches('.').to_ascii_lowercase()).filter(|domain| !domain.is_empty()).collect();
+    result.allowed.dedup();
+
+    result.status = if result.issuer_domains.iter().any(|domain| result.allowed.contains(domain)) {
+        "authorized"
+    } else if result.issuer_domains.is_empty() {
+        "unknown"
+    } else {
+        "unauthorized"
+    }
+    .to_string();
+    if result.status == "unauthorized" {
+        result.error = Some(match result.allowed.is_empty() {
+            true => format!("CAA at {} forbids every CA", result.domain.as_deref().unwrap_or(&host)),
+            false => format!("CAA at {} only allows {}, not {}", result.domain.as_deref().unwrap_or(&host), result.allowed.join(", "), result.issuer_domains[0]),
+        });
+    }
+    result
+}
+
+/// The CAA records of `host` or its closest ancestor with any, as (flags, tag, value); `None` if no level has any.
+async fn relevant_set(resolver: &Resolver, host: &str) -> Result<Option<(String, Vec<(u8, String, String)>)>, String> {
+    let mut name = host;
+    loop {
+        let response = resolver.query(name, wire::TYPE_CAA).await.map_err(|e| format!("CAA lookup of {} failed: {}", name, e))?;
+        match response.message.rcode {
+            wire::RCODE_NOERROR | wire::RCODE_NXDOMAIN => {},
+            rcode => return Err(format!("CAA lookup of {} answered {}", name, wire::rcode_name(rcode))),
+        }
+        let records: Vec<(u8, String, String)> = response
+            .message
+            .answers
+            .iter()
+            .filter_map(|record| match &record.data {
+                wire::RData::Caa { flags, tag, value } => Some((*flags, tag.clone(), value.clone())),
+                _ => None,
+            })
+            .collect();
+        if !records.is_empty() {
+            return Ok(Some((name.to_string(), records)));
+        }
+        match name.split_once('.') {
+            Some((_, parent)) if !parent.is_empty() => name = parent,
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// CAA identifiers of the CA named in an issuer DN ("CN=R11, O=Let's Encrypt, C=US").
+fn issuer_domains(issuer: &str) -> Vec<String> {
+    let attribute = |key: &str| issuer.split(", ").find_map(|part| part.strip_prefix(key)).unwrap_or_default().to_ascii_lowercase();
+    let (organization, common_name) = (attribute("O="), attribute("CN="));
+    CA_DOMAINS
+        .iter()
+        .find(|(ca, _)| {
+            let ca = ca.to_ascii_lowercase();
+            organization.contains(&ca) || common_name.contains(&ca)
+        })
+        .map(|(_, domains)| domains.iter().map(|domain| domain.to_string()).collect())
+        .unwrap_or_default()
+}
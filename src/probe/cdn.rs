@@ -0,0 +1,190 @@
+//! Optional: CDN and WAF detection (`--detect-cdn`).
+//!
+//! Fingerprints the edge in front of the target from three independent
+//! sources: response headers and cookies, the CNAME chain of the host, and the
+//! published address ranges of the providers that have stable ones.
+
+use hyper::header::{self, HeaderMap};
+use ipnet::IpNet;
+use serde::Serialize;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CdnResult {
+    /// CDN the traffic goes through, the one with the most evidence; `None` if no CDN was recognised.
+    pub provider: Option<String>,
+    /// Web application firewall recognised in front of the origin, if any.
+    pub waf: Option<String>,
+    /// Aliases of the host, in resolution order.
+    pub cname_chain: Vec<String>,
+    /// Every signature that matched, e.g. "Cloudflare: header cf-ray".
+    pub evidence: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Cdn,
+    Waf,
+}
+
+/// A response header, optionally required to contain `value` (case-insensitive).
+struct HeaderSignature {
+    provider: &'static str,
+    kind: Kind,
+    name: &'static str,
+    value: Option<&'static str>,
+}
+
+const fn h(provider: &'static str, kind: Kind, name: &'static str, value: Option<&'static str>) -> HeaderSignature {
+    HeaderSignature { provider, kind, name, value }
+}
+
+const HEADERS: &[HeaderSignature] = &[
+    h("Cloudflare", Kind::Cdn, "cf-ray", None),
+    h("Cloudflare", Kind::Cdn, "cf-cache-status", None),
+    h("Cloudflare", Kind::Cdn, "server", Some("cloudflare")),
+    h("Akamai", Kind::Cdn, "x-akamai-transformed", None),
+    h("Akamai", Kind::Cdn, "x-akamai-request-id", None),
+    h("Akamai", Kind::Cdn, "akamai-grn", None),
+    h("Akamai", Kind::Cdn, "akamai-cache-status", None),
+    h("Akamai", Kind::Cdn, "server", Some("akamaighost")),
+    h("Fastly", Kind::Cdn, "x-fastly-request-id", None),
+    h("Fastly", Kind::Cdn, "fastly-debug-digest", None),
+    h("Fastly", Kind::Cdn, "x-served-by", Some("cache-")),
+    h("CloudFront", Kind::Cdn, "x-amz-cf-id", None),
+    h("CloudFront", Kind::Cdn, "x-amz-cf-pop", None),
+    h("CloudFront", Kind::Cdn, "via", Some("cloudfront")),
+    h("Azure Front Door", Kind::Cdn, "x-azure-ref", None),
+    h("Google Cloud CDN", Kind::Cdn, "via", Some("1.1 google")),
+    h("Vercel", Kind::Cdn, "x-vercel-id", None),
+    h("Netlify", Kind::Cdn, "x-nf-request-id", None),
+    h("Bunny CDN", Kind::Cdn, "server", Some("bunnycdn")),
+    h("KeyCDN", Kind::Cdn, "server", Some("keycdn")),
+    h("Sucuri", Kind::Waf, "x-sucuri-id", None),
+    h("Sucuri", Kind::Waf, "server", Some("sucuri")),
+    h("Imperva", Kind::Waf, "x-iinfo", None),
+    h("Imperva", Kind::Waf, "x-cdn", Some("incapsula")),
+    h("Imperva", Kind::Waf, "x-cdn", Some("imperva")),
+    h("F5 BIG-IP", Kind::Waf, "server", Some("bigip")),
+];
+
+/// Cookie name prefixes set by WAFs.
+const COOKIES: &[(&str, &str)] = &[("Imperva", "incap_ses_"), ("Imperva", "visid_incap_"), ("F5 BIG-IP", "BIGipServer"), ("AWS WAF", "aws-waf-token"), ("Barracuda", "barra_counter_session")];
+
+/// CNAME target suffixes.
+const CNAMES: &[(&str, Kind, &str)] = &[
+    ("Cloudflare", Kind::Cdn, ".cdn.cloudflare.net"),
+    ("Akamai", Kind::Cdn, ".akamaiedge.net"),
+    ("Akamai", Kind::Cdn, ".akamai.net"),
+    ("Akamai", Kind::Cdn, ".edgekey.net"),
+    ("Akamai", Kind::Cdn, ".edgesuite.net"),
+    ("Akamai", Kind::Cdn, ".akamaized.net"),
+    ("Fastly", Kind::Cdn, ".fastly.net"),
+    ("Fastly", Kind::Cdn, ".fastlylb.net"),
+    ("CloudFront", Kind::Cdn, ".cloudfront.net"),
+    ("Azure Front Door", Kind::Cdn, ".azurefd.net"),
+    ("Azure Front Door", Kind::Cdn, ".azureedge.net"),
+    ("Vercel", Kind::Cdn, ".vercel-dns.com"),
+    ("Netlify", Kind::Cdn, ".netlify.app"),
+    ("Bunny CDN", Kind::Cdn, ".b-cdn.net"),
+    ("Imperva", Kind::Waf, ".incapdns.net"),
+];
+
+/// Address ranges published by Cloudflare (cloudflare.com/ips) and Fastly (api.fastly.com/public-ip-list).
+const RANGES: &[(&str, &str)] = &[
+    ("Cloudflare", "173.245.48.0/20"),
+    ("Cloudflare", "103.21.244.0/22"),
+    ("Cloudflare", "103.22.200.0/22"),
+    ("Cloudflare", "103.31.4.0/22"),
+    ("Cloudflare", "141.101.64.0/18"),
+    ("Cloudflare", "108.162.192.0/18"),
+    ("Cloudflare", "190.93.240.0/20"),
+    ("Cloudflare", "188.114.96.0/20"),
+    ("Cloudflare", "197.234.240.0/22"),
+    ("Cloudflare", "198.41.128.0/17"),
+    ("Cloudflare", "162.158.0.0/15"),
+    ("Cloudflare", "104.16.0.0/13"),
+    ("Cloudflare", "104.24.0.0/14"),
+    ("Cloudflare", "172.64.0.0/13"),
+    ("Cloudflare", "131.0.72.0/22"),
+    ("Cloudflare", "2400:cb00::/32"),
+    ("Cloudflare", "2606:4700::/32"),
+    ("Cloudflare", "2803:f800::/32"),
+    ("Cloudflare", "2405:b500::/32"),
+    ("Cloudflare", "2405:8100::/32"),
+    ("Cloudflare", "2a06:98c0::/29"),
+    ("Cloudflare", "2c0f:f248::/32"),
+    ("Fastly", "23.235.32.0/20"),
+    ("Fastly", "43.249.72.0/22"),
+    ("Fastly", "103.244.50.0/24"),
+    ("Fastly", "103.245.222.0/23"),
+    ("Fastly", "103.245.224.0/24"),
+    ("Fastly", "104.156.80.0/20"),
+    ("Fastly", "140.248.64.0/18"),
+    ("Fastly", "140.248.128.0/17"),
+    ("Fastly", "146.75.0.0/17"),
+    ("Fastly", "151.101.0.0/16"),
+    ("Fastly", "157.52.64.0/18"),
+    ("Fastly", "167.82.0.0/17"),
+    ("Fastly", "167.82.128.0/20"),
+    ("Fastly", "167.82.160.0/20"),
+    ("Fastly", "167.82.224.0/20"),
+    ("Fastly", "172.111.64.0/18"),
+    ("Fastly", "185.31.16.0/22"),
+    ("Fastly", "199.27.72.0/21"),
+    ("Fastly", "199.232.0.0/16"),
+    ("Fastly", "2a04:4e40::/32"),
+    ("Fastly", "2a04:4e42::/32"),
+];
+
+/// Match the response `headers`, the host's `cname_chain` and the address `ip` against known edges.
+pub fn detect(headers: Option<&HeaderMap>, cname_chain: Vec<String>, ip: Option<IpAddr>) -> CdnResult {
+    let mut matches: Vec<(&str, Kind, String)> = Vec::new();
+
+    if let Some(headers) = headers {
+        for signature in HEADERS {
+            let found = headers.get_all(signature.name).iter().filter_map(|v| v.to_str().ok()).find(|v| signature.value.is_none_or(|wanted| v.to_ascii_lowercase().contains(wanted)));
+            if let Some(value) = found {
+                let detail = match signature.value {
+                    Some(_) => format!("header {}: {}", signature.name, value),
+                    None => format!("header {}", signature.name),
+                };
+                matches.push((signature.provider, signature.kind, detail));
+            }
+        }
+        for cookie in headers.get_all(header::SET_COOKIE).iter().filter_map(|v| v.to_str().ok()) {
+            let name = cookie.split('=').next().unwrap_or_default().trim();
+            if let Some((provider, _)) = COOKIES.iter().find(|(_, prefix)| name.starts_with(prefix)) {
+                matches.push((provider, Kind::Waf, format!("cookie {}", name)));
+            }
+        }
+    }
+    for alias in &cname_chain {
+        if let Some((provider, kind, _)) = CNAMES.iter().find(|(_, _, suffix)| alias.ends_with(suffix)) {
+            matches.push((provider, *kind, format!("CNAME {}", alias)));
+        }
+    }
+    if let Some(ip) = ip {
+        if let Some((provider, range)) = RANGES.iter().find(|(_, range)| range.parse::<IpNet>().is_ok_and(|net| net.contains(&ip))) {
+            matches.push((provider, Kind::Cdn, format!("IP {} in {}", ip, range)));
+        }
+    }
+
+    let leader = |kind: Kind| {
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for (provider, _, _) in matches.iter().filter(|(_, k, _)| *k == kind) {
+            match counts.iter_mut().find(|(p, _)| p == provider) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((provider, 1)),
+            }
+        }
+        // First seen wins ties: headers are checked first and are the most specific.
+        counts.iter().rev().max_by_key(|(_, count)| *count).map(|(provider, _)| provider.to_string())
+    };
+    CdnResult {
+        provider: leader(Kind::Cdn),
+        waf: leader(Kind::Waf),
+        cname_chain,
+        evidence: matches.into_iter().map(|(provider, _, detail)| format!("{}: {}", provider, detail)).collect(),
+    }
+}
@@ -0,0 +1,295 @@
+//! Assertions evaluated once every stage has run (`--expect-*`).
+
+use super::{ProbeOptions, ProbeResult};
+use regex::RegexBuilder;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub use regex::Regex;
+
+/// Compiled size cap of an `--expect-body-regex` pattern: nested counted repeats
+/// such as `((a?){1000}){200}` blow up far past it and are rejected.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Outcome of one expectation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Assertion {
+    /// What was checked, e.g. "status".
+    pub name: String,
+    pub expected: String,
+    /// Observed value; `None` when the stage produced nothing to check.
+    pub actual: Option<String>,
+    pub passed: bool,
+}
+
+/// Latency budget of one stage (`--max-dns`, `--max-latency`...).
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyCheck {
+    /// "dns" | "tcp" | "tls" | "http" | "total"
+    pub stage: String,
+    pub status: String, // "ok" | "degraded" | "skipped"
+    pub limit_ms: f64,
+    /// Measured latency; `None` when the stage did not complete.
+    pub latency_ms: Option<f64>,
+}
+
+/// Latency thresholds; a stage slower than its limit is reported as degraded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyLimits {
+    /// Whole probe, all stages included.
+    pub total: Option<Duration>,
+    pub dns: Option<Duration>,
+    pub tcp: Option<Duration>,
+    pub tls: Option<Duration>,
+    pub http: Option<Duration>,
+}
+
+/// An accepted HTTP status: exact (`200`) or a whole class (`2xx`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusPattern {
+    Exact(u16),
+    Class(u8),
+}
+
+impl StatusPattern {
+    pub fn matches(self, code: u16) -> bool {
+        match self {
+            StatusPattern::Exact(expected) => code == expected,
+            StatusPattern::Class(class) => code / 100 == class as u16,
+        }
+    }
+}
+
+impl FromStr for StatusPattern {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let invalid = || format!("invalid status '{}' (expected e.g. 200 or 2xx)", input);
+        match input.to_ascii_lowercase().strip_suffix("xx") {
+            Some(class) => match class.parse::<u8>() {
+                Ok(class @ 1..=5) => Ok(StatusPattern::Class(class)),
+                _ => Err(invalid()),
+            },
+            None => match input.parse::<u16>() {
+                Ok(code @ 100..=599) => Ok(StatusPattern::Exact(code)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for StatusPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusPattern::Exact(code) => write!(f, "{}", code),
+            StatusPattern::Class(class) => write!(f, "{}xx", class),
+        }
+    }
+}
+
+/// A `--expect-json-path` check: `$.a.b[0]` alone (the value exists), or followed by
+/// `==`, `!=`, `<`, `<=`, `>`, `>=` and a JSON literal, e.g. `$.status == "ok"`.
+#[derive(Debug, Clone)]
+pub struct JsonPathCheck {
+    expression: String,
+    path: Vec<PathSegment>,
+    comparison: Option<(Comparison, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl JsonPathCheck {
+    /// The value at the path, if the document has one.
+    fn select<'a>(&self, document: &'a Value) -> Option<&'a Value> {
+        self.path.iter().try_fold(document, |value, segment| match segment {
+            PathSegment::Key(key) => value.get(key.as_str()),
+            PathSegment::Index(index) => value.get(*index),
+        })
+    }
+
+    fn holds(&self, value: &Value) -> bool {
+        let Some((comparison, expected)) = &self.comparison else { return true };
+        match comparison {
+            Comparison::Eq => value == expected,
+            Comparison::Ne => value != expected,
+            ordering => match (value.as_f64(), expected.as_f64()) {
+                (Some(actual), Some(expected)) => match ordering {
+                    Comparison::Lt => actual < expected,
+                    Comparison::Le => actual <= expected,
+                    Comparison::Gt => actual > expected,
+                    _ => actual >= expected,
+                },
+                _ => false,
+            },
+        }
+    }
+}
+
+impl FromStr for JsonPathCheck {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let expression = input.trim();
+        let invalid = |why: &str| format!("invalid JSON path '{}': {}", expression, why);
+        let mut rest = expression.strip_prefix('$').ok_or_else(|| invalid("must start with '$'"))?;
+
+        let mut path = Vec::new();
+        loop {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid("empty key after '.'"));
+                }
+                path.push(PathSegment::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| invalid("missing ']'"))?;
+                let inner = after[..end].trim();
+                let quoted = inner.len() >= 2 && (inner.starts_with('"') && inner.ends_with('"') || inner.starts_with('\'') && inner.ends_with('\''));
+                if quoted {
+                    path.push(PathSegment::Key(inner[1..inner.len() - 1].to_string()));
+                } else {
+                    path.push(PathSegment::Index(inner.parse().map_err(|_| invalid("index must be a number or a quoted key"))?));
+                }
+                rest = &after[end + 1..];
+            } else {
+                break;
+            }
+        }
+
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(JsonPathCheck { expression: expression.to_string(), path, comparison: None });
+        }
+        // Two-character operators first, so "<=" is not read as "<".
+        let operators = [("==", Comparison::Eq), ("!=", Comparison::Ne), ("<=", Comparison::Le), (">=", Comparison::Ge), ("<", Comparison::Lt), (">", Comparison::Gt)];
+        let (operator, comparison) = operators.iter().find(|(op, _)| rest.starts_with(op)).ok_or_else(|| invalid("expected ==, !=, <, <=, > or >="))?;
+        let literal = rest[operator.len()..].trim();
+        let expected: Value = serde_json::from_str(literal).map_err(|_| invalid("right-hand side must be a JSON value, e.g. \"ok\" or 42"))?;
+
+        Ok(JsonPathCheck { expression: expression.to_string(), path, comparison: Some((*comparison, expected)) })
+    }
+}
+
+impl fmt::Display for JsonPathCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// Check `result` against every expectation in `options`.
+pub fn evaluate(result: &ProbeResult, options: &ProbeOptions) -> Vec<Assertion> {
+    let mut assertions = Vec::new();
+
+    if !options.expect_status.is_empty() {
+        let code = result.http.status_code;
+        assertions.push(Assertion {
+            name: "status".to_string(),
+            expected: options.expect_status.iter().map(ToString::to_string).collect::<Vec<_>>().join(" | "),
+            actual: code.map(|c| c.to_string()),
+            passed: code.is_some_and(|c| options.expect_status.iter().any(|p| p.matches(c))),
+        });
+    }
+
+    // Body checks run against the captured response body; without one they all fail.
+    let body = result.http.body.as_deref().map(String::from_utf8_lossy);
+    for needle in &options.expect_body_contains {
+        let found = body.as_deref().map(|body| body.contains(needle.as_str()));
+        assertions.push(Assertion {
+            name: "body-contains".to_string(),
+            expected: format!("{:?}", needle),
+            actual: found.map(|found| if found { "found" } else { "not found" }.to_string()),
+            passed: found == Some(true),
+        });
+    }
+    for regex in &options.expect_body_regex {
+        let matched = body.as_deref().map(|body| regex.is_match(body));
+        assertions.push(Assertion {
+            name: "body-regex".to_string(),
+            expected: format!("/{}/", regex),
+            actual: matched.map(|matched| if matched { "match" } else { "no match" }.to_string()),
+            passed: matched == Some(true),
+        });
+    }
+    if !options.expect_json_path.is_empty() {
+        let document = body.as_deref().map(serde_json::from_str::<Value>);
+        for check in &options.expect_json_path {
+            let (actual, passed) = match &document {
+                Some(Ok(document)) => match check.select(document) {
+                    Some(value) => (Some(value.to_string()), check.holds(value)),
+                    None => (Some("missing".to_string()), false),
+                },
+                Some(Err(_)) => (Some("invalid JSON body".to_string()), false),
+                None => (None, false),
+            };
+            assertions.push(Assertion { name: "json-path".to_string(), expected: check.to_string(), actual, passed });
+        }
+    }
+
+    assertions
+}
+
+/// Compare each stage's latency with its limit in `options.latency_limits`.
+pub fn latency_checks(result: &ProbeResult, options: &ProbeOptions) -> Vec<LatencyCheck> {
+    let limits = &options.latency_limits;
+    let stages = [
+        ("dns", limits.dns, result.dns.latency_ms),
+        ("tcp", limits.tcp, result.tcp.latency_ms),
+        ("tls", limits.tls, result.tls.latency_ms),
+        ("http", limits.http, result.http.latency_ms),
+        ("total", limits.total, Some(result.duration_ms)),
+    ];
+
+    stages
+        .into_iter()
+        .filter_map(|(stage, limit, latency_ms)| {
+            let limit_ms = limit?.as_secs_f64() * 1000.0;
+            let status = match latency_ms {
+                Some(ms) if ms > limit_ms => "degraded",
+                Some(_) => "ok",
+                None => "skipped",
+            };
+            Some(LatencyCheck { stage: stage.to_string(), status: status.to_string(), limit_ms, latency_ms })
+        })
+        .collect()
+}
+
+/// Parse an `--expect-body-regex` pattern (syntax of the `regex` crate, matched in linear time).
+pub fn parse_regex(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern).size_limit(REGEX_SIZE_LIMIT).build().map_err(|e| format!("invalid regex '{}': {}", pattern, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_regex;
+
+    #[test]
+    fn regex_matches_anywhere() {
+        assert!(parse_regex(r#""version":\s*"2\.\d+"#).unwrap().is_match(r#"{"version": "2.14"}"#));
+        assert!(!parse_regex("^ok$").unwrap().is_match("not ok"));
+    }
+
+    #[test]
+    fn regex_rejects_invalid_and_oversized_patterns() {
+        assert!(parse_regex("(a").unwrap_err().starts_with("invalid regex '(a'"));
+        assert!(parse_regex("((a?){1000}){200}").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Optional: cookie jar of the HTTP stage (`--cookie`, `--cookie-file`).
+//!
+//! Cookies set along a redirect chain are sent on the following hops, as a
+//! browser would, so login redirects and sticky-session load balancers behave
+//! as they do for real clients. Matching follows RFC 6265: domain, path,
+//! `Secure` and expiry.
+
+use chrono::{DateTime, Utc};
+use hyper::header::{self, HeaderMap};
+use std::path::Path;
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Lowercase, without leading dot.
+    pub domain: String,
+    /// Only sent to `domain` itself, not its subdomains.
+    pub host_only: bool,
+    pub path: String,
+    pub secure: bool,
+    /// Unix time; `None` for session cookies.
+    pub expires: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    pub cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// Load a Netscape cookie file, as written by curl (`-c`) and browser extensions.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let mut jar = CookieJar::default();
+        for (number, line) in data.lines().enumerate() {
+            // curl marks HttpOnly cookies with a prefix that otherwise reads as a comment.
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, subdomains, cookie_path, secure, expires, name, value] = fields[..] else {
+                return Err(format!("{}:{}: expected 7 tab-separated fields (Netscape cookie file)", path.display(), number + 1));
+            };
+            let expires: i64 = expires.trim().parse().map_err(|_| format!("{}:{}: invalid expiry '{}'", path.display(), number + 1, expires))?;
+            jar.insert(Cookie {
+                name: name.to_string(),
+                value: value.trim_end_matches('\r').to_string(),
+                domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+                host_only: !subdomains.eq_ignore_ascii_case("TRUE"),
+                path: cookie_path.to_string(),
+                secure: secure.eq_ignore_ascii_case("TRUE"),
+                expires: (expires != 0).then_some(expires),
+            });
+        }
+        Ok(jar)
+    }
+
+    /// Add a cookie given on the command line, sent to `host` only.
+    pub fn set(&mut self, host: &str, name: &str, value: &str) {
+        let cookie = Cookie { name: name.to_string(), value: value.to_string(), domain: host.to_ascii_lowercase(), host_only: true, path: "/".to_string(), secure: false, expires: None };
+        self.insert(cookie);
+    }
+
+    /// Record the `Set-Cookie` headers of a response to `url`; returns the names of the cookies set.
+    pub(crate) fn store(&mut self, url: &Url, headers: &HeaderMap) -> Vec<String> {
+        let mut names = Vec::new();
+        for value in headers.get_all(header::SET_COOKIE).iter().filter_map(|v| v.to_str().ok()) {
+            if let Some(cookie) = parse_set_cookie(url, value) {
+                names.push(cookie.name.clone());
+                self.insert(cookie);
+            }
+        }
+        names
+    }
+
+    /// `Cookie` header for a request to `url`, longest paths first as RFC 6265 recommends.
+    pub(crate) fn header(&self, url: &Url) -> Option<String> {
+        let host = url.host_str()?.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+        let now = Utc::now().timestamp();
+        let mut matching: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|c| if c.host_only { host == c.domain } else { domain_matches(&host, &c.domain) })
+            .filter(|c| path_matches(url.path(), &c.path))
+            .filter(|c| !c.secure || url.scheme() == "https")
+            .filter(|c| c.expires.is_none_or(|expires| expires > now))
+            .collect();
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        (!matching.is_empty()).then(|| matching.iter().map(|c| format!("{}={}", c.name, c.value)).collect::<Vec<_>>().join("; "))
+    }
+
+    /// Replace the cookie of the same name, domain and path; an expired one is only removed.
+    fn insert(&mut self, cookie: Cookie) {
+        self.cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+        if cookie.expires.is_none_or(|expires| expires > Utc::now().timestamp()) {
+            self.cookies.push(cookie);
+        }
+    }
+}
+
+fn parse_set_cookie(url: &Url, header: &str) -> Option<Cookie> {
+    let host = url.host_str()?.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let mut cookie = Cookie { name: name.to_string(), value: value.trim().trim_matches('"').to_string(), domain: host.clone(), host_only: true, path: default_path(url.path()), secure: false, expires: None };
+    let mut max_age = None;
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                // A server may only set cookies for its own domain or a parent of it.
+                if !domain_matches(&host, &domain) {
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            },
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "secure" => cookie.secure = true,
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" => cookie.expires = DateTime::parse_from_rfc2822(&value.replace('-', " ")).ok().map(|t| t.timestamp()),
+            _ => {},
+        }
+    }
+    // Max-Age wins over Expires; zero or less deletes the cookie.
+    if let Some(max_age) = max_age {
+        cookie.expires = Some(Utc::now().timestamp().saturating_add(max_age.max(-1)));
+    }
+    Some(cookie)
+}
+
+/// `host` is `domain` or one of its subdomains (never for IP addresses).
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.') && host.parse::<std::net::IpAddr>().is_err())
+}
+
+fn path_matches(request: &str, cookie: &str) -> bool {
+    request == cookie || (request.starts_with(cookie) && (cookie.ends_with('/') || request[cookie.len()..].starts_with('/')))
+}
+
+/// The directory of the request path, for cookies without a `Path` attribute.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => path[..i].to_string(),
+    }
+}
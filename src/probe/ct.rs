@@ -0,0 +1,196 @@
+//! Optional: Certificate Transparency check (`--check-ct`).
+//!
+//! Collects the signed certificate timestamps (SCTs) of the leaf certificate,
+//! both embedded by the CA and sent in the TLS handshake, and names the logs
+//! that issued them. With a log list (`--ct-log-list`, the JSON published by
+//! Google at https://www.gstatic.com/ct/log_list/v3/log_list.json) every SCT
+//! signature is also verified; without one the SCTs are reported as-is.
+
+use crate::x509;
+use base64::Engine;
+use chrono::{TimeZone, Utc};
+use rustls::Certificate;
+use serde::Serialize;
+use std::path::Path;
+
+/// SCT version 1 (RFC 6962), the only one deployed.
+const SCT_V1: u8 = 0;
+/// `LogEntryType` of an SCT sent in the handshake (over the certificate itself)
+/// and of an embedded one (over the precertificate).
+const X509_ENTRY: u16 = 0;
+const PRECERT_ENTRY: u16 = 1;
+
+/// One log of a CT log list.
+#[derive(Debug, Clone)]
+pub struct CtLog {
+    /// SHA-256 of the log's public key, which SCTs identify the log by.
+    pub id: Vec<u8>,
+    pub description: String,
+    pub operator: String,
+    /// The log's public key (SubjectPublicKeyInfo, DER).
+    pub key: Vec<u8>,
+}
+
+/// CT logs known by ID, loaded from a `log_list.json` (v3 schema).
+#[derive(Debug, Clone)]
+pub struct CtLogList {
+    pub path: String,
+    pub logs: Vec<CtLog>,
+}
+
+impl CtLogList {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let document: serde_json::Value = serde_json::from_str(&data).map_err(|e| format!("{} is not JSON: {}", path.display(), e))?;
+        let decode = |value: &serde_json::Value| value.as_str().and_then(|text| base64::engine::general_purpose::STANDARD.decode(text).ok());
+        let mut logs = Vec::new();
+        for operator in document["operators"].as_array().into_iter().flatten() {
+            let name = operator["name"].as_str().unwrap_or_default();
+            for log in operator["logs"].as_array().into_iter().flatten() {
+                if let (Some(id), Some(key)) = (decode(&log["log_id"]), decode(&log["key"])) {
+                    logs.push(CtLog { id, description: log["description"].as_str().unwrap_or_default().to_string(), operator: name.to_string(), key });
+                }
+            }
+        }
+        if logs.is_empty() {
+            return Err(format!("No CT log in {} (expected the v3 log_list.json format)", path.display()));
+        }
+        Ok(CtLogList { path: path.display().to_string(), logs })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CtResult {
+    /// "ok" when at least one SCT is present (and verifies, given a log list), "error" otherwise.
+    pub status: String,
+    pub scts: Vec<SctInfo>,
+    /// SCTs whose signature verified; `None` without a log list.
+    pub valid: Option<usize>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SctInfo {
+    /// Base64 log ID, as in log lists.
+    pub log_id: String,
+    /// Description of the log, e.g. "Google 'Argon2026h1' log", when the log list knows it.
+    pub log: Option<String>,
+    pub operator: Option<String>,
+    /// When the log promised to include the certificate.
+    pub timestamp: Option<String>,
+    /// "embedded" (in the certificate) | "tls" (handshake extension)
+    pub source: String,
+    /// Signature check against the log's key; `None` when the log is unknown or no list was given.
+    pub verified: Option<bool>,
+}
+
+/// A decoded `SignedCertificateTimestamp`.
+struct Sct<'a> {
+    log_id: &'a [u8],
+    timestamp: u64,
+    extensions: &'a [u8],
+    /// TLS `SignatureAndHashAlgorithm`: hash then signature.
+    algorithm: (u8, u8),
+    signature: &'a [u8],
+}
+
+/// Report the SCTs of the leaf of `chain`; `tls_scts` are those of the handshake extension.
+pub fn check(chain: &[Certificate], tls_scts: &[Vec<u8>], logs: Option<&CtLogList>) -> CtResult {
+    let Some(leaf) = chain.first() else {
+        return CtResult { status: "error".to_string(), scts: Vec::new(), valid: None, error: Some("No certificate presented".to_string()) };
+    };
+    let cert = x509::Certificate::parse(&leaf.0);
+    let issuer = chain.get(1).and_then(|c| x509::Certificate::parse(&c.0));
+
+    let embedded = cert.as_ref().and_then(|cert| cert.extension(x509::OID_SCT_LIST)).and_then(|value| x509::Der::new(value).expect(x509::TAG_OCTET_STRING)).map(|list| sct_list(list.content)).unwrap_or_default();
+    let mut scts = Vec::new();
+    for (source, raw) in embedded.into_iter().map(|sct| ("embedded", sct)).chain(tls_scts.iter().map(|sct| ("tls", sct.as_slice()))) {
+        let Some(sct) = parse_sct(raw) else { continue };
+        let log = logs.and_then(|list| list.logs.iter().find(|log| log.id == sct.log_id));
+        let entry = match source {
+            "embedded" => precert_entry(cert.as_ref(), issuer.as_ref()),
+            _ => Some(x509_entry(&leaf.0)),
+        };
+        let verified = log.zip(entry).map(|(log, entry)| verify(&sct, log, &entry));
+        scts.push(SctInfo {
+            log_id: base64::engine::general_purpose::STANDARD.encode(sct.log_id),
+            log: log.map(|log| log.description.clone()),
+            operator: log.map(|log| log.operator.clone()),
+            timestamp: Utc.timestamp_millis_opt(sct.timestamp as i64).single().map(|t| t.to_rfc3339()),
+            source: source.to_string(),
+            verified: if logs.is_some() { Some(verified.unwrap_or(false)) } else { None },
+        });
+    }
+
+    let valid = logs.map(|_| scts.iter().filter(|sct| sct.verified == Some(true)).count());
+    let error = match valid {
+        _ if scts.is_empty() => Some("Certificate has no SCTs (not logged in Certificate Transparency)".to_string()),
+        Some(0) => Some(format!("None of the {} SCT(s) verifies against a known CT log", scts.len())),
+        _ => None,
+    };
+    CtResult { status: if error.is_none() { "ok" } else { "error" }.to_string(), scts, valid, error }
+}
+
+/// Split a `SignedCertificateTimestampList` into its serialized SCTs.
+fn sct_list(data: &[u8]) -> Vec<&[u8]> {
+    let mut scts = Vec::new();
+    let Some(mut rest) = data.get(2..) else { return scts };
+    while let Some(len) = rest.get(..2).map(|len| u16::from_be_bytes([len[0], len[1]]) as usize) {
+        let Some(sct) = rest.get(2..2 + len) else { break };
+        scts.push(sct);
+        rest = &rest[2 + len..];
+    }
+    scts
+}
+
+fn parse_sct(data: &[u8]) -> Option<Sct<'_>> {
+    if *data.first()? != SCT_V1 {
+        return None;
+    }
+    let log_id = data.get(1..33)?;
+    let timestamp = u64::from_be_bytes(data.get(33..41)?.try_into().ok()?);
+    let ext_len = u16::from_be_bytes(data.get(41..43)?.try_into().ok()?) as usize;
+    let extensions = data.get(43..43 + ext_len)?;
+    let rest = data.get(43 + ext_len..)?;
+    let algorithm = (*rest.first()?, *rest.get(1)?);
+    let sig_len = u16::from_be_bytes(rest.get(2..4)?.try_into().ok()?) as usize;
+    let signature = rest.get(4..4 + sig_len)?;
+    Some(Sct { log_id, timestamp, extensions, algorithm, signature })
+}
+
+/// The signed entry of a handshake SCT: the certificate itself.
+fn x509_entry(cert: &[u8]) -> Vec<u8> {
+    let mut entry = X509_ENTRY.to_be_bytes().to_vec();
+    entry.extend_from_slice(&(cert.len() as u32).to_be_bytes()[1..]);
+    entry.extend_from_slice(cert);
+    entry
+}
+
+/// The signed entry of an embedded SCT: the issuer's key hash and the certificate's TBS minus the SCT list.
+fn precert_entry(cert: Option<&x509::Certificate>, issuer: Option<&x509::Certificate>) -> Option<Vec<u8>> {
+    let tbs = cert?.tbs_without_extension(x509::OID_SCT_LIST)?;
+    let mut entry = PRECERT_ENTRY.to_be_bytes().to_vec();
+    entry.extend_from_slice(ring::digest::digest(&ring::digest::SHA256, issuer?.raw_public_key_info).as_ref());
+    entry.extend_from_slice(&(tbs.len() as u32).to_be_bytes()[1..]);
+    entry.extend_from_slice(&tbs);
+    Some(entry)
+}
+
+/// Check the log's signature over the SCT's `digitally-signed` structure (RFC 6962, 3.2).
+fn verify(sct: &Sct, log: &CtLog, entry: &[u8]) -> bool {
+    let Some((key_type, key)) = x509::parse_public_key_info(&log.key) else {
+        return false;
+    };
+    // Only SHA-256 with ECDSA or RSA is allowed for logs.
+    let algorithm = match sct.algorithm {
+        (4, 3) => "ecdsa-with-SHA256",
+        (4, 1) => "sha256WithRSAEncryption",
+        _ => return false,
+    };
+    let mut signed = vec![SCT_V1, 0]; // signature_type certificate_timestamp
+    signed.extend_from_slice(&sct.timestamp.to_be_bytes());
+    signed.extend_from_slice(entry);
+    signed.extend_from_slice(&(sct.extensions.len() as u16).to_be_bytes());
+    signed.extend_from_slice(sct.extensions);
+    x509::verify_signature(&key_type, key, algorithm, &signed, sct.signature)
+}
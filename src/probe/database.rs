@@ -0,0 +1,281 @@
+//! Database handshakes (`--service postgres|mysql|redis|mongodb`, or ports 5432,
+//! 3306, 6379 and 27017).
+//!
+//! Each exchange goes just far enough to prove the protocol without credentials:
+//! PostgreSQL answers an SSLRequest, MySQL sends its handshake, Redis answers
+//! PING and MongoDB the `hello` command. PostgreSQL and MySQL are upgraded to
+//! TLS when they support it, so the TLS stage reports their certificate.
+
+use super::service::{self, Service, ServiceResult};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Largest MySQL packet, Redis bulk reply or MongoDB message accepted.
+const MAX_MESSAGE: usize = 1024 * 1024;
+
+/// PostgreSQL SSLRequest: length 8, then the code 1234.5679.
+const PG_SSL_REQUEST: [u8; 8] = [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f];
+
+/// MySQL capability flags.
+const MYSQL_COMPRESS: u32 = 0x20;
+const MYSQL_PROTOCOL_41: u32 = 0x200;
+const MYSQL_SSL: u32 = 0x800;
+const MYSQL_SECURE_CONNECTION: u32 = 0x8000;
+const MYSQL_PLUGIN_AUTH: u32 = 0x80000;
+
+const MONGO_OP_MSG: i32 = 2013;
+
+/// Run the handshake of `service`; true when the connection is ready for the TLS handshake.
+pub(super) async fn probe(stream: &mut TcpStream, service: Service, result: &mut ServiceResult, timeout: Duration) -> Result<bool, String> {
+    let started = Instant::now();
+    let work = async {
+        match service {
+            Service::Postgres => postgres(stream, result, started).await,
+            Service::Mysql => mysql(stream, result, started).await,
+            Service::Redis => redis(stream, result, started, timeout).await.map(|_| false),
+            _ => mongodb(stream, result, started).await.map(|_| false),
+        }
+    };
+    tokio::time::timeout(timeout, work).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))?
+}
+
+async fn postgres(stream: &mut TcpStream, result: &mut ServiceResult, started: Instant) -> Result<bool, String> {
+    stream.write_all(&PG_SSL_REQUEST).await.map_err(|e| e.to_string())?;
+    let reply = stream.read_u8().await.map_err(|_| "Connection closed by the server".to_string())?;
+    result.greeting_ms = Some(ms_since(started));
+    match reply {
+        b'S' | b'N' => {
+            result.starttls = Some(reply == b'S');
+            Ok(reply == b'S')
+        },
+        // Servers older than 7.0 answer with an ErrorResponse.
+        b'E' => Err("Server rejected the SSLRequest (PostgreSQL before 7.0?)".to_string()),
+        other => Err(format!("Not a PostgreSQL server: answered 0x{:02x} to SSLRequest", other)),
+    }
+}
+
+async fn mysql(stream: &mut TcpStream, result: &mut ServiceResult, started: Instant) -> Result<bool, String> {
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await.map_err(|_| "Connection closed by the server".to_string())?;
+    let length = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+    if length == 0 || length > MAX_MESSAGE {
+        return Err("Not a MySQL server: invalid handshake packet".to_string());
+    }
+    let mut packet = vec![0; length];
+    stream.read_exact(&mut packet).await.map_err(|_| "Connection closed by the server".to_string())?;
+    result.greeting_ms = Some(ms_since(started));
+
+    match packet[0] {
+        10 => {},
+        // An ERR packet instead of the handshake: this host may not connect, too many connections...
+        0xff => {
+            let message = packet.get(3..).map(|rest| String::from_utf8_lossy(rest.strip_prefix(b"#").map_or(rest, |rest| rest.get(5..).unwrap_or_default())).into_owned());
+            return Err(format!("Server refused the connection: {}", message.unwrap_or_default()));
+        },
+        other => return Err(format!("Not a MySQL server: protocol version {}", other)),
+    }
+    let version_end = packet.iter().skip(1).position(|&byte| byte == 0).ok_or("Not a MySQL server: truncated handshake")? + 1;
+    result.version = Some(String::from_utf8_lossy(&packet[1..version_end]).into_owned());
+    // Connection id (4), first auth data (8) and filler (1) come before the flags.
+    let rest = packet.get(version_end + 14..).filter(|rest| rest.len() >= 2).ok_or("Not a MySQL server: truncated handshake")?;
+    let mut capabilities = u16::from_le_bytes([rest[0], rest[1]]) as u32;
+    if let Some(upper) = rest.get(5..7) {
+        capabilities |= (u16::from_le_bytes([upper[0], upper[1]]) as u32) << 16;
+    }
+    if capabilities & MYSQL_PLUGIN_AUTH != 0 {
+        // Auth data length (1), reserved (10) and the rest of the auth data (at least 13 bytes).
+        let data_length = rest.get(7).map_or(0, |&len| (len as usize).saturating_sub(8).max(13));
+        if let Some(plugin) = rest.get(18 + data_length..) {
+            let plugin = plugin.split(|&byte| byte == 0).next().unwrap_or_default();
+            result.capabilities.push(format!("AUTH={}", String::from_utf8_lossy(plugin)));
+        }
+    }
+    for (flag, name) in [(MYSQL_SSL, "SSL"), (MYSQL_COMPRESS, "COMPRESS")] {
+        if capabilities & flag != 0 {
+            result.capabilities.push(name.to_string());
+        }
+    }
+
+    let ssl = capabilities & MYSQL_SSL != 0;
+    result.starttls = Some(ssl);
+    if ssl {
+        // SSLRequest: the first 32 bytes of a handshake response, sequence 1.
+        let mut request = vec![32, 0, 0, 1];
+        request.extend((MYSQL_SSL | MYSQL_PROTOCOL_41 | MYSQL_SECURE_CONNECTION | MYSQL_PLUGIN_AUTH).to_le_bytes());
+        request.extend((16u32 << 20).to_le_bytes());
+        request.push(45); // utf8mb4
+        request.extend([0; 23]);
+        stream.write_all(&request).await.map_err(|e| e.to_string())?;
+    }
+    Ok(ssl)
+}
+
+async fn redis(stream: &mut TcpStream, result: &mut ServiceResult, started: Instant, timeout: Duration) -> Result<(), String> {
+    service::write_line(stream, "PING").await?;
+    let reply = service::read_line(stream, timeout).await?;
+    result.greeting_ms = Some(ms_since(started));
+    // "-NOAUTH Authentication required." and "-DENIED ... protected mode" come from Redis too.
+    let pong = match reply.split_at_checked(1) {
+        Some(("+", text)) => text == "PONG",
+        Some(("-", _)) => false,
+        _ => return Err(format!("Not a Redis server: {}", reply)),
+    };
+    result.banner = Some(reply[1..].to_string());
+    if !pong {
+        return Ok(());
+    }
+
+    service::write_line(stream, "INFO server").await?;
+    let header = service::read_line(stream, timeout).await?;
+    let Some(length) = header.strip_prefix('$').and_then(|length| length.parse::<usize>().ok()).filter(|&length| length <= MAX_MESSAGE) else {
+        return Ok(());
+    };
+    let mut info = vec![0; length + 2];
+    stream.read_exact(&mut info).await.map_err(|_| "Connection closed by the server".to_string())?;
+    let info = String::from_utf8_lossy(&info);
+    result.version = info.lines().find_map(|line| line.strip_prefix("redis_version:")).map(str::to_string);
+    for key in ["redis_mode:", "os:"] {
+        if let Some(value) = info.lines().find_map(|line| line.strip_prefix(key)) {
+            result.capabilities.push(format!("{}={}", key.trim_end_matches(':'), value));
+        }
+    }
+    Ok(())
+}
+
+async fn mongodb(stream: &mut TcpStream, result: &mut ServiceResult, started: Instant) -> Result<(), String> {
+    let hello = mongo_command(stream, 1, "hello").await?;
+    result.greeting_ms = Some(ms_since(started));
+    // Servers before 4.4.2 only know the legacy name.
+    let hello = if bson_ok(&hello) { hello } else { mongo_command(stream, 2, "isMaster").await? };
+    if !bson_ok(&hello) {
+        return Err(format!("hello failed: {}", bson_string(&hello, "errmsg").unwrap_or_default()));
+    }
+    let role = match (bson_string(&hello, "msg").as_deref(), bson_bool(&hello, "isWritablePrimary").or(bson_bool(&hello, "ismaster")), bson_bool(&hello, "secondary")) {
+        (Some("isdbgrid"), _, _) => "mongos",
+        (_, Some(true), _) => "primary",
+        (_, _, Some(true)) => "secondary",
+        _ => "other",
+    };
+    result.capabilities.push(format!("role={}", role));
+    if let Some(set) = bson_string(&hello, "setName") {
+        result.capabilities.push(format!("replicaSet={}", set));
+    }
+    if let Some(BsonValue::Int(wire)) = bson_get(&hello, "maxWireVersion") {
+        result.capabilities.push(format!("maxWireVersion={}", wire));
+    }
+    // buildInfo needs no authentication; a refusal only leaves the version out.
+    if let Ok(info) = mongo_command(stream, 3, "buildInfo").await {
+        result.version = bson_string(&info, "version");
+    }
+    Ok(())
+}
+
+/// Send `{<command>: 1, $db: "admin"}` in an OP_MSG and return the top-level fields of the reply.
+async fn mongo_command(stream: &mut TcpStream, request_id: i32, command: &str) -> Result<Vec<(String, BsonValue)>, String> {
+    let mut body = vec![0x10];
+    body.extend(command.as_bytes());
+    body.push(0);
+    body.extend(1i32.to_le_bytes());
+    body.push(0x02);
+    body.extend(b"$db\0");
+    body.extend(6i32.to_le_bytes());
+    body.extend(b"admin\0");
+    body.push(0);
+    let mut document = ((body.len() + 4) as i32).to_le_bytes().to_vec();
+    document.extend(body);
+
+    let mut message = Vec::new();
+    message.extend(((16 + 4 + 1 + document.len()) as i32).to_le_bytes());
+    message.extend(request_id.to_le_bytes());
+    message.extend(0i32.to_le_bytes());
+    message.extend(MONGO_OP_MSG.to_le_bytes());
+    message.extend(0u32.to_le_bytes()); // flags
+    message.push(0); // section kind: body
+    message.extend(document);
+    stream.write_all(&message).await.map_err(|e| e.to_string())?;
+
+    let length = stream.read_i32_le().await.map_err(|_| "Connection closed by the server (MongoDB before 3.6, or not MongoDB)".to_string())?;
+    let length = usize::try_from(length).ok().filter(|length| (21..=MAX_MESSAGE).contains(length)).ok_or("Not a MongoDB server: invalid message length")?;
+    let mut reply = vec![0; length - 4];
+    stream.read_exact(&mut reply).await.map_err(|_| "Connection closed by the server".to_string())?;
+    // Request id, response to, opcode, flags and section kind precede the document.
+    if i32::from_le_bytes([reply[8], reply[9], reply[10], reply[11]]) != MONGO_OP_MSG || reply[16] != 0 {
+        return Err("Not a MongoDB server: unexpected reply".to_string());
+    }
+    bson_fields(&reply[17..]).ok_or_else(|| "Not a MongoDB server: unreadable reply".to_string())
+}
+
+#[derive(Debug, Clone)]
+enum BsonValue {
+    Double(f64),
+    String(String),
+    Bool(bool),
+    Int(i64),
+    Other,
+}
+
+/// Top-level fields of a BSON document; nested documents and arrays are skipped.
+fn bson_fields(document: &[u8]) -> Option<Vec<(String, BsonValue)>> {
+    let length = i32::from_le_bytes(document.get(..4)?.try_into().ok()?) as usize;
+    let mut rest = document.get(4..length.checked_sub(1)?)?;
+    let mut fields = Vec::new();
+    while let Some((&kind, after)) = rest.split_first() {
+        let name_end = after.iter().position(|&byte| byte == 0)?;
+        let name = String::from_utf8_lossy(&after[..name_end]).into_owned();
+        let value = &after[name_end + 1..];
+        let i32_at = |bytes: &[u8]| bytes.get(..4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        let (parsed, size) = match kind {
+            0x01 => (BsonValue::Double(f64::from_le_bytes(value.get(..8)?.try_into().ok()?)), 8),
+            0x02 => {
+                let size = i32_at(value)? as usize;
+                (BsonValue::String(String::from_utf8_lossy(value.get(4..4 + size.checked_sub(1)?)?).into_owned()), 4 + size)
+            },
+            0x03 | 0x04 => (BsonValue::Other, i32_at(value)? as usize),
+            0x05 => (BsonValue::Other, 5 + i32_at(value)? as usize),
+            0x07 => (BsonValue::Other, 12),
+            0x08 => (BsonValue::Bool(*value.first()? != 0), 1),
+            0x09 | 0x11 => (BsonValue::Other, 8),
+            0x0a => (BsonValue::Other, 0),
+            0x10 => (BsonValue::Int(i32_at(value)? as i64), 4),
+            0x12 => (BsonValue::Int(i64::from_le_bytes(value.get(..8)?.try_into().ok()?)), 8),
+            0x13 => (BsonValue::Other, 16),
+            // Rare types (regex, code, min/max keys): stop with what was read.
+            _ => break,
+        };
+        fields.push((name, parsed));
+        rest = value.get(size..)?;
+    }
+    Some(fields)
+}
+
+fn bson_get<'a>(fields: &'a [(String, BsonValue)], name: &str) -> Option<&'a BsonValue> {
+    fields.iter().find(|(key, _)| key == name).map(|(_, value)| value)
+}
+
+fn bson_string(fields: &[(String, BsonValue)], name: &str) -> Option<String> {
+    match bson_get(fields, name)? {
+        BsonValue::String(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn bson_bool(fields: &[(String, BsonValue)], name: &str) -> Option<bool> {
+    match bson_get(fields, name)? {
+        BsonValue::Bool(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// `ok: 1`, as a double or an integer.
+fn bson_ok(fields: &[(String, BsonValue)]) -> bool {
+    match bson_get(fields, "ok") {
+        Some(BsonValue::Double(ok)) => *ok == 1.0,
+        Some(BsonValue::Int(ok)) => *ok == 1,
+        _ => false,
+    }
+}
+
+fn ms_since(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
@@ -0,0 +1,359 @@
+//! Step 1: DNS resolution.
+
+use super::error_code::ErrorCode;
+use super::retry::Attempt;
+use super::{http, tls};
+use super::ProbeOptions;
+use crate::resolver::dnssec::{self, DnssecResult};
+use crate::resolver::iterative::{self, DnsTrace};
+use crate::resolver::records::RecordInfo;
+use crate::resolver::{self, wire, IpFamily, ResolveError, Resolver, Upstream};
+use serde::Serialize;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnsResult {
+    pub status: String, // "ok" | "error" | "skipped" (behind a proxy)
+    /// The address used by the following stages (first one returned).
+    pub ip: Option<String>,
+    /// Full record set, split by address family.
+    pub ipv4: Vec<String>,
+    pub ipv6: Vec<String>,
+    /// Family ("ipv4" | "ipv6") of `ip`, i.e. the path the other stages take.
+    pub family: Option<String>,
+    pub latency_ms: Option<f64>,
+    /// Part of `latency_ms` spent on the TLS or QUIC handshake (DoT, DoQ).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake_ms: Option<f64>,
+    /// Lowest TTL of the address records, i.e. how long they may still be cached;
+    /// `None` from the system resolver, which does not expose it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    /// Client subnet sent with the queries (`--ecs`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_subnet: Option<String>,
+    /// Scope prefix the server answered for: how much of the client subnet the answer
+    /// depends on, 0 meaning not at all. `None` when the server ignored the option.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_subnet_scope: Option<u8>,
+    /// DNS server that answered; `None` when the system resolver was used.
+    pub server: Option<String>,
+    /// How the answer was obtained: "system" | "udp" | "tcp" | "tls" | "https" | "quic" | "override" (`--resolve`).
+    pub transport: String,
+    /// Reverse (PTR) name of `ip` (`--rdns`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ptr: Option<String>,
+    pub error: Option<String>,
+    /// Why the lookup failed: "timeout" | "nxdomain" | "no_records" | "server_error" | "malformed" | "http" | "quic" | "io".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    /// Stable code of `error`, e.g. "DNS_NXDOMAIN".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+    /// Each lookup when retrying (`--retries`), the last one being reported above.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+    /// Answer sections of the A/AAAA responses (`-v` with `--dns-server`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub answers: Vec<RecordInfo>,
+    /// Chain of trust of the address records (`--dnssec`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dnssec: Option<DnssecResult>,
+    /// Referral path from the root to the address records (`--dns-trace`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<DnsTrace>,
+}
+
+impl DnsResult {
+    pub(crate) fn pending() -> Self {
+        DnsResult { status: "pending".to_string(), ip: None, ipv4: Vec::new(), ipv6: Vec::new(), family: None, latency_ms: None, handshake_ms: None, ttl: None, client_subnet: None, client_subnet_scope: None, server: None, transport: "system".to_string(), ptr: None, error: None, failure: None, error_code: None, attempts: Vec::new(), answers: Vec::new(), dnssec: None, trace: None }
+    }
+
+    pub(crate) fn skipped() -> Self {
+        DnsResult { status: "skipped".to_string(), ..DnsResult::pending() }
+    }
+
+    /// Mark the lookup successful with the given addresses (first one is used for probing).
+    fn succeed(&mut self, ips: &[IpAddr], latency_ms: f64) {
+        self.status = "ok".to_string();
+        self.ip = ips.first().map(|ip| ip.to_string());
+        self.family = ips.first().map(|ip| IpFamily::of(*ip).name().to_string());
+        self.ipv4 = ips.iter().filter(|ip| ip.is_ipv4()).map(|ip| ip.to_string()).collect();
+        self.ipv6 = ips.iter().filter(|ip| ip.is_ipv6()).map(|ip| ip.to_string()).collect();
+        self.latency_ms = Some(latency_ms);
+    }
+
+    fn fail(&mut self, error: ResolveError) {
+        self.status = "error".to_string();
+        self.failure = Some(error.kind().to_string());
+        self.error_code = Some(ErrorCode::resolve(&error));
+        self.error = Some(error.to_string());
+    }
+}
+
+/// A fixed address for a host and port, as written in `--resolve example.com:443:10.0.0.5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveOverride {
+    pub host: String,
+    pub port: u16,
+    pub ip: IpAddr,
+}
+
+impl ResolveOverride {
+    fn matches(&self, host: &str, port: u16) -> bool {
+        self.port == port && self.host.eq_ignore_ascii_case(host.trim_end_matches('.'))
+    }
+}
+
+impl FromStr for ResolveOverride {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let invalid = || format!("invalid override '{}' (expected host:port:ip)", input);
+        let (host, rest) = input.split_once(':').ok_or_else(invalid)?;
+        let (port, ip) = rest.split_once(':').ok_or_else(invalid)?;
+        let port = match port.parse::<u16>() {
+            Ok(port @ 1..) => port,
+            _ => return Err(format!("invalid port '{}' in '{}'", port, input)),
+        };
+        let ip = ip.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().map_err(|_| format!("invalid IP address '{}' in '{}'", ip, input))?;
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(ResolveOverride { host: host.trim_end_matches('.').to_string(), port, ip })
+    }
+}
+
+impl fmt::Display for ResolveOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.host, self.port, self.ip)
+    }
+}
+
+/// Resolve `host`, through the configured DNS servers if any, otherwise the system resolver.
+/// Returns every address found; the first one is the address the other stages use.
+/// A matching `--resolve` override short-circuits the lookup.
+pub async fn resolve(host: &str, port: u16, options: &ProbeOptions) -> (DnsResult, Vec<SocketAddr>) {
+    if let Some(pinned) = options.resolve.iter().find(|pinned| pinned.matches(host, port)) {
+        tracing::debug!(host, ip = %pinned.ip, "using --resolve override");
+        let mut result = DnsResult::pending();
+        result.succeed(&[pinned.ip], 0.0);
+        result.transport = "override".to_string();
+        return (result, vec![SocketAddr::new(pinned.ip, port)]);
+    }
+    let literal = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>();
+    if (options.dns_servers.is_empty() && options.client_subnet.is_none()) || literal.is_ok() {
+        tracing::debug!(host, resolver = "system", "resolving");
+        let (mut result, mut addrs) = resolve_system(host, port, options.dns_timeout()).await;
+        if let Some(family) = options.ip_family {
+            addrs.retain(|a| IpFamily::of(a.ip()) == family);
+            if result.status == "ok" {
+                let ips: Vec<IpAddr> = addrs.iter().map(|a| a.ip()).collect();
+                if ips.is_empty() {
+                    result = DnsResult::pending();
+                    result.fail(ResolveError::NoRecords);
+                    result.error = Some(format!("No {} address found", if family == IpFamily::V4 { "IPv4" } else { "IPv6" }));
+                } else {
+                    let latency = result.latency_ms.unwrap_or(0.0);
+                    result.succeed(&ips, latency);
+                }
+            }
+        }
+        log_outcome(&result);
+        return (result, addrs);
+    }
+
+    // The system resolver cannot send a client subnet: its nameservers are asked directly instead.
+    let upstreams = match options.dns_servers.is_empty() {
+        true => resolver::system_servers().into_iter().map(Upstream::Plain).collect(),
+        false => options.dns_servers.clone(),
+    };
+    let mut result = DnsResult::pending();
+    result.transport = upstreams.first().map(|server| server.transport().to_string()).unwrap_or_default();
+    result.client_subnet = options.client_subnet.map(|subnet| subnet.to_string());
+    let resolver = resolver(upstreams.clone(), options).with_client_subnet(options.client_subnet);
+    let servers: Vec<String> = upstreams.iter().map(|server| server.to_string()).collect();
+    tracing::debug!(host, resolver = servers.join(", "), "resolving");
+    let outcome = resolver.lookup_ip(host, options.ip_family).await;
+    let (result, addrs) = match outcome {
+        Ok(lookup) => {
+            result.succeed(&lookup.ips, lookup.latency_ms);
+            result.server = Some(lookup.server.to_string());
+            result.transport = lookup.transport.to_string();
+            result.handshake_ms = lookup.handshake_ms;
+            result.ttl = lookup.ttl;
+            result.client_subnet_scope = lookup.client_subnet_scope;
+            if options.verbose {
+                result.answers = lookup.answers;
+            }
+            (result, lookup.ips.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+        },
+        Err(e) => {
+            result.fail(e);
+            (result, Vec::new())
+        }
+    };
+    log_outcome(&result);
+    (result, addrs)
+}
+
+/// A resolver asking `servers` within the DNS budget. DoH queries go through the probe's
+/// proxy, with the TLS settings of the HTTP stage.
+pub(crate) fn resolver(servers: Vec<Upstream>, options: &ProbeOptions) -> Resolver {
+    let doh = servers.iter().any(|server| matches!(server, Upstream::Https(_)));
+    let client = doh.then(|| http::side_client(&options.proxy, tls::client_config(options), options.dns_timeout()));
+    Resolver::new(servers, options.dns_timeout()).with_http_client(client)
+}
+
+fn log_outcome(result: &DnsResult) {
+    match (&result.error, &result.server) {
+        (Some(e), _) => tracing::debug!(error = e.as_str(), failure = result.failure.as_deref(), "lookup failed"),
+        (None, server) => tracing::debug!(addresses = [result.ipv4.as_slice(), result.ipv6.as_slice()].concat().join(", "), server = server.as_deref(), latency_ms = result.latency_ms, "resolved"),
+    }
+}
+
+/// PTR name of `ip`, asked of the configured DNS servers if any, otherwise the system resolver.
+pub async fn ptr(ip: IpAddr, options: &ProbeOptions) -> Option<String> {
+    if options.dns_servers.is_empty() {
+        let lookup = tokio::task::spawn_blocking(move || reverse_lookup(ip));
+        return tokio::time::timeout(options.dns_timeout(), lookup).await.ok()?.ok().flatten();
+    }
+
+    let resolver = resolver(options.dns_servers.clone(), options);
+    let response = resolver.query(&resolver::reverse_name(ip), wire::TYPE_PTR).await.ok()?;
+    response.message.answers.iter().find_map(|record| match &record.data {
+        wire::RData::Ptr(name) => Some(name.trim_end_matches('.').to_string()),
+        _ => None,
+    })
+}
+
+/// Aliases `host` goes through before its address records, in order (`www.example.com` ->
+/// `example.cdn.net` -> ...). The system resolver does not expose them, so without
+/// `--dns-server` the nameservers of `/etc/resolv.conf` are queried directly.
+pub async fn cname_chain(host: &str, options: &ProbeOptions) -> Vec<String> {
+    let servers = if options.dns_servers.is_empty() {
+        resolver::system_servers().into_iter().map(Upstream::Plain).collect()
+    } else {
+        options.dns_servers.clone()
+    };
+    let Ok(response) = resolver(servers, options).query(host, wire::TYPE_A).await else {
+        return Vec::new();
+    };
+    let mut chain = Vec::new();
+    let mut name = host.trim_end_matches('.').to_ascii_lowercase();
+    // Follow the chain rather than trust the answer order.
+    while let Some(target) = response.message.answers.iter().find_map(|record| match &record.data {
+        wire::RData::Cname(target) if record.name.trim_end_matches('.').eq_ignore_ascii_case(&name) => Some(target.trim_end_matches('.').to_ascii_lowercase()),
+        _ => None,
+    }) {
+        if chain.contains(&target) {
+            break;
+        }
+        chain.push(target.clone());
+        name = target;
+    }
+    chain
+}
+
+/// DNSSEC status of the `ip`-family address records of `host`, validated from the
+/// root trust anchor (`--dnssec`). Like [`cname_chain`], it needs a DNS server of
+/// its own: the system resolver neither returns signatures nor says how it judged them.
+pub async fn dnssec(host: &str, ip: IpAddr, options: &ProbeOptions) -> DnssecResult {
+    let servers = if options.dns_servers.is_empty() {
+        resolver::system_servers().into_iter().map(Upstream::Plain).collect()
+    } else {
+        options.dns_servers.clone()
+    };
+    let qtype = if ip.is_ipv4() { wire::TYPE_A } else { wire::TYPE_AAAA };
+    dnssec::validate(&resolver(servers, options), host, qtype).await
+}
+
+/// Referrals from the root servers down to the address records of `host` (`--dns-trace`):
+/// of the family of `ip` when it resolved, else A records unless `-6`.
+pub async fn trace(host: &str, ip: Option<IpAddr>, options: &ProbeOptions) -> DnsTrace {
+    let servers = if options.dns_servers.is_empty() {
+        resolver::system_servers().into_iter().map(Upstream::Plain).collect()
+    } else {
+        options.dns_servers.clone()
+    };
+    let qtype = match (ip, options.ip_family) {
+        (Some(IpAddr::V6(_)), _) | (None, Some(IpFamily::V6)) => wire::TYPE_AAAA,
+        _ => wire::TYPE_A,
+    };
+    iterative::trace(host, qtype, options.ip_family, &resolver(servers, options), options.dns_timeout()).await
+}
+
+/// Resolve `host` with the system's resolver configuration, returning every address
+/// found. Gives up after `timeout`.
+pub async fn resolve_system(host: &str, port: u16, timeout: Duration) -> (DnsResult, Vec<SocketAddr>) {
+    let mut result = DnsResult::pending();
+    let started = Instant::now();
+    // Boxed like the other hickory lookups: its future is large.
+    let outcome = Box::pin(resolver::lookup_system(host, timeout)).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match outcome {
+        Ok(ips) => {
+            result.succeed(&ips, latency_ms);
+            (result, ips.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+        },
+        Err(e) => {
+            let timed_out = matches!(e, ResolveError::Timeout);
+            result.fail(e);
+            if timed_out {
+                result.error = Some(format!("DNS lookup timed out after {:.0} ms", latency_ms));
+            }
+            (result, Vec::new())
+        },
+    }
+}
+
+/// [`resolve_system`] for the blocking traceroute, MTR and MTU code, within the probe's
+/// default timeout: on the current runtime when called from its blocking pool, else
+/// on a runtime of its own.
+pub fn resolve_system_blocking(host: &str, port: u16) -> (DnsResult, Vec<SocketAddr>) {
+    let timeout = Duration::from_secs(5);
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => handle.block_on(resolve_system(host, port, timeout)),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("a current-thread runtime builds")
+            .block_on(resolve_system(host, port, timeout)),
+    }
+}
+
+/// Reverse (PTR) lookup through the system resolver; `None` when no name is registered.
+#[cfg(unix)]
+pub fn reverse_lookup(ip: IpAddr) -> Option<String> {
+    let addr = socket2::SockAddr::from(SocketAddr::new(ip, 0));
+    let mut host = [0 as libc::c_char; 1025];
+    // SAFETY: `addr` is a valid sockaddr of the given length and `host` is a writable buffer
+    // whose size is passed alongside it; getnameinfo NUL-terminates on success.
+    let rc = unsafe {
+        libc::getnameinfo(
+            addr.as_ptr(),
+            addr.len(),
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+    if rc != 0 {
+        return None;
+    }
+    // SAFETY: getnameinfo succeeded, so `host` holds a NUL-terminated string.
+    let name = unsafe { std::ffi::CStr::from_ptr(host.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Reverse lookups are not supported on this platform.
+#[cfg(not(unix))]
+pub fn reverse_lookup(_ip: IpAddr) -> Option<String> {
+    None
+}
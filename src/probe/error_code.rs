@@ -0,0 +1,206 @@
+//! Stable error codes (`error_code` of each stage).
+//!
+//! A stage's `error` is written for people and quotes the operating system or
+//! the library that failed, so its wording changes between platforms and
+//! versions. Its `error_code` does not: scripts should branch on the code, and
+//! read the message only to show it. A code keeps its name and meaning once
+//! released; new ones may be added.
+
+use super::tcp::TcpFailure;
+use crate::resolver::ResolveError;
+use rustls::CertificateError;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The DNS server did not answer in time.
+    DnsTimeout,
+    /// The name does not exist.
+    DnsNxdomain,
+    /// The name exists but has no address (of the family asked for).
+    DnsNoRecords,
+    /// The server failed the query (SERVFAIL, REFUSED...).
+    DnsServerError,
+    /// The answer could not be decoded.
+    DnsMalformed,
+    /// The query could not be carried: socket, DoH or DoQ failure.
+    DnsTransport,
+
+    TcpRefused,
+    TcpTimeout,
+    TcpUnreachable,
+    TcpReset,
+    /// Blocked by the local system (firewall rule, missing privileges).
+    TcpDenied,
+    /// The local end could not be set up (source address, interface).
+    TcpLocal,
+    TcpOther,
+
+    /// The connection of the TLS stage could not be opened.
+    TlsConnect,
+    TlsTimeout,
+    /// The handshake failed: an alert from the server, no common version or cipher suite.
+    TlsHandshake,
+    /// The host cannot be sent as a server name.
+    TlsServerName,
+    /// The server does not offer STARTTLS, which the service needs.
+    TlsNoStarttls,
+    TlsExpiredCert,
+    TlsNotYetValidCert,
+    /// The certificate is not valid for the host name.
+    TlsHostnameMismatch,
+    /// The chain does not lead to a trusted CA.
+    TlsUntrustedCert,
+    TlsRevokedCert,
+    /// Any other reason the certificate was rejected (bad signature, encoding, purpose).
+    TlsInvalidCert,
+    /// The certificate is not logged in Certificate Transparency (`--check-ct`).
+    TlsCtMissing,
+
+    /// The whole request, redirects included, did not finish within the HTTP timeout.
+    HttpTimeout,
+    /// The request could not be built (bad URL, header or credentials).
+    HttpInvalidRequest,
+    /// The name of the host (or of the proxy) did not resolve.
+    HttpDns,
+    HttpConnect,
+    /// The proxy refused or failed the tunnel.
+    HttpProxy,
+    /// The TLS handshake of the request failed, the certificate included.
+    HttpTls,
+    /// The server closed the connection before a full response.
+    HttpConnectionClosed,
+    /// The response is not valid HTTP.
+    HttpMalformedResponse,
+    HttpTooManyRedirects,
+    /// Any other failure of the HTTP exchange (HTTP/2 not negotiated, stream reset...).
+    HttpProtocol,
+
+    /// No reply to the datagram.
+    UdpTimeout,
+    /// ICMP said the port or host is unreachable.
+    UdpUnreachable,
+    /// The reply is not what the payload asks for (unsynchronized NTP server, SIP error...).
+    UdpBadReply,
+    /// The socket could not be set up.
+    UdpLocal,
+
+    /// The connection of the WebSocket stage could not be opened.
+    WsConnect,
+    WsTimeout,
+    WsTls,
+    /// The server answered the upgrade with something other than a WebSocket switch.
+    WsRejected,
+    /// The server closed the connection before the exchange was over.
+    WsClosed,
+    /// Anything else the server got wrong (not HTTP, bad frames).
+    WsProtocol,
+
+    /// No ICMP socket: neither unprivileged ping sockets nor raw-socket privileges.
+    IcmpPermission,
+    /// The socket could not be set up otherwise (source address, interface).
+    IcmpLocal,
+    /// Sending or receiving failed (no route to the host...).
+    IcmpSocket,
+    /// No echo reply before the timeout.
+    IcmpTimeout,
+
+    /// The UDP socket or the QUIC configuration could not be set up.
+    Http3Local,
+    /// No QUIC connection: nothing answered on the UDP port, or the handshake failed (certificate included).
+    Http3Handshake,
+    Http3Timeout,
+    /// The HTTP/3 request or response failed after the handshake.
+    Http3Protocol,
+
+    /// The connection of the service stage could not be opened.
+    ServiceConnect,
+    ServiceTimeout,
+    /// The TLS handshake of an implicit-TLS port failed.
+    ServiceTls,
+    /// The server refused the session, answered something the protocol does not expect, or too late.
+    ServiceProtocol,
+
+    SmtpConnect,
+    SmtpTimeout,
+    /// The server refused the session, EHLO or STARTTLS.
+    SmtpRejected,
+    /// The connection closed, or a reply was malformed or late.
+    SmtpProtocol,
+    /// The TLS handshake after STARTTLS failed.
+    SmtpTls,
+
+    /// The endpoint does not speak gRPC (HTTP status other than 200, no `grpc-status`).
+    GrpcUnsupported,
+    /// The call failed with a `grpc-status` other than OK (no health service, unknown service...).
+    GrpcCallFailed,
+    /// The response message could not be decoded.
+    GrpcMalformed,
+    /// The health service answered NOT_SERVING, UNKNOWN or SERVICE_UNKNOWN.
+    GrpcNotServing,
+}
+
+impl ErrorCode {
+    pub fn resolve(error: &ResolveError) -> ErrorCode {
+        match error {
+            ResolveError::Timeout => ErrorCode::DnsTimeout,
+            ResolveError::NxDomain => ErrorCode::DnsNxdomain,
+            ResolveError::NoRecords => ErrorCode::DnsNoRecords,
+            ResolveError::ServerError(_) => ErrorCode::DnsServerError,
+            ResolveError::Malformed => ErrorCode::DnsMalformed,
+            ResolveError::Http(_) | ResolveError::Quic(_) | ResolveError::Io(_) => ErrorCode::DnsTransport,
+        }
+    }
+
+    /// Why the certificate chain was rejected, from the verifier's error.
+    pub fn certificate(error: &rustls::Error) -> ErrorCode {
+        match error {
+            rustls::Error::InvalidCertificate(CertificateError::Expired) => ErrorCode::TlsExpiredCert,
+            rustls::Error::InvalidCertificate(CertificateError::NotValidYet) => ErrorCode::TlsNotYetValidCert,
+            rustls::Error::InvalidCertificate(CertificateError::NotValidForName) => ErrorCode::TlsHostnameMismatch,
+            rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer) => ErrorCode::TlsUntrustedCert,
+            rustls::Error::InvalidCertificate(CertificateError::Revoked) => ErrorCode::TlsRevokedCert,
+            _ => ErrorCode::TlsInvalidCert,
+        }
+    }
+}
+
+impl From<TcpFailure> for ErrorCode {
+    fn from(failure: TcpFailure) -> Self {
+        match failure {
+            TcpFailure::Refused => ErrorCode::TcpRefused,
+            TcpFailure::Timeout => ErrorCode::TcpTimeout,
+            TcpFailure::Unreachable => ErrorCode::TcpUnreachable,
+            TcpFailure::Reset => ErrorCode::TcpReset,
+            TcpFailure::Denied => ErrorCode::TcpDenied,
+            TcpFailure::Local => ErrorCode::TcpLocal,
+            TcpFailure::Other => ErrorCode::TcpOther,
+        }
+    }
+}
+
+/// An error message with its code, for the stages that build their error up with `?`.
+#[derive(Debug)]
+pub(crate) struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl CodedError {
+    pub fn new(code: ErrorCode, message: impl fmt::Display) -> Self {
+        CodedError { code, message: message.to_string() }
+    }
+}
+
+/// Give the error of a `Result` its code: `connect(addr).await.code(ErrorCode::HttpConnect)?`.
+pub(crate) trait WithCode<T> {
+    fn code(self, code: ErrorCode) -> Result<T, CodedError>;
+}
+
+impl<T, E: fmt::Display> WithCode<T> for Result<T, E> {
+    fn code(self, code: ErrorCode) -> Result<T, CodedError> {
+        self.map_err(|e| CodedError::new(code, e))
+    }
+}
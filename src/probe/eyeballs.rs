@@ -0,0 +1,121 @@
+//! Optional: Happy Eyeballs connection race (`--happy-eyeballs`, RFC 8305).
+//!
+//! Browsers do not connect to the first resolved address: they try the
+//! addresses in turn, alternating families and starting with IPv6, and start
+//! the next attempt whenever the previous one has not succeeded within 250ms.
+//! The first connection established wins. The race here does the same and
+//! lets the attempts already under way finish, so the report also tells by how
+//! much the other family lost.
+
+use super::source::SourceBinding;
+use super::IpFamily;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Head start of each attempt over the next one ("Connection Attempt Delay", RFC 8305 5).
+pub const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RaceResult {
+    /// Family of the first connection established: "ipv6" | "ipv4"; `None` when every attempt failed.
+    pub winner: Option<String>,
+    pub winner_ip: Option<String>,
+    /// How much later the other family's first connection was established; `None` when
+    /// it failed, was not tried because the race was already won, or has no address.
+    pub margin_ms: Option<f64>,
+    /// The attempts started, in order.
+    pub attempts: Vec<RaceAttempt>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RaceAttempt {
+    pub ip: String,
+    pub family: String,
+    /// When the attempt started, from the start of the race.
+    pub started_ms: f64,
+    pub status: String, // "won" | "ok" (connected after the winner) | "error"
+    /// Handshake time of this attempt alone.
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Race connections to `addrs` as a Happy Eyeballs client would; each attempt gives up after `timeout`.
+pub async fn race(addrs: &[SocketAddr], timeout: Duration, source: &SourceBinding) -> RaceResult {
+    let order = interleave(addrs);
+    let started = Instant::now();
+    let elapsed_ms = || started.elapsed().as_secs_f64() * 1000.0;
+    let mut result = RaceResult { winner: None, winner_ip: None, margin_ms: None, attempts: Vec::new() };
+    // Completion time of the first connection of each family, from the start of the race.
+    let mut connected: [Option<f64>; 2] = [None, None];
+    let mut pending = FuturesUnordered::new();
+    let mut next = order.iter().enumerate();
+    let mut start_next = true;
+
+    loop {
+        if start_next && result.winner.is_none() {
+            if let Some((i, &addr)) = next.next() {
+                result.attempts.push(RaceAttempt { ip: addr.ip().to_string(), family: IpFamily::of(addr.ip()).name().to_string(), started_ms: elapsed_ms(), status: "error".to_string(), latency_ms: None, error: None });
+                pending.push(attempt(i, addr, timeout, source));
+            }
+        }
+        if pending.is_empty() {
+            break;
+        }
+        // Once the race is won, the attempts under way only finish (each within `timeout`).
+        let wait = if result.winner.is_some() { timeout } else { ATTEMPT_DELAY };
+        let Ok(Some((i, outcome))) = tokio::time::timeout(wait, pending.next()).await else {
+            // No connection within the delay: the next address gets its turn.
+            start_next = true;
+            continue;
+        };
+        let family = IpFamily::of(order[i].ip());
+        match outcome {
+            Ok(latency) => {
+                start_next = false;
+                result.attempts[i].latency_ms = Some(latency);
+                result.attempts[i].status = if result.winner.is_none() { "won" } else { "ok" }.to_string();
+                connected[(family == IpFamily::V6) as usize].get_or_insert(elapsed_ms());
+                if result.winner.is_none() {
+                    tracing::debug!(ip = %order[i].ip(), latency_ms = latency, "happy eyeballs race won");
+                    result.winner = Some(family.name().to_string());
+                    result.winner_ip = Some(order[i].ip().to_string());
+                }
+            },
+            Err(e) => {
+                // A failed attempt does not wait for the delay (RFC 8305 5).
+                start_next = true;
+                result.attempts[i].error = Some(e);
+            },
+        }
+    }
+
+    if let (Some(winner), [Some(v4), Some(v6)]) = (&result.winner, connected) {
+        result.margin_ms = Some(if winner == "ipv6" { v4 - v6 } else { v6 - v4 });
+    }
+    result
+}
+
+/// Connect to `addr`, returning the attempt's index with its handshake time or error.
+async fn attempt(i: usize, addr: SocketAddr, timeout: Duration, source: &SourceBinding) -> (usize, Result<f64, String>) {
+    let started = Instant::now();
+    let outcome = match tokio::time::timeout(timeout, source.connect(addr)).await {
+        Ok(Ok(_)) => Ok(started.elapsed().as_secs_f64() * 1000.0),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("connection timed out".to_string()),
+    };
+    (i, outcome)
+}
+
+/// Alternate the families, IPv6 first, keeping the resolver's order within each (RFC 8305 4).
+fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let (mut v6, mut v4) = (addrs.iter().filter(|addr| addr.is_ipv6()), addrs.iter().filter(|addr| addr.is_ipv4()));
+    let mut order = Vec::with_capacity(addrs.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return order,
+            (a, b) => order.extend(a.into_iter().chain(b).copied()),
+        }
+    }
+}
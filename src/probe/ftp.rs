@@ -0,0 +1,154 @@
+//! FTP server check (`--service ftp|ftps`, ports 21 and 990): welcome banner,
+//! FEAT, `AUTH TLS` (RFC 4217) and, with `--ftp-anonymous`, an anonymous login.
+//!
+//! `AUTH TLS` must come before `USER`, and the upgraded connection belongs to
+//! the TLS stage, so on port 21 the login runs on a second connection in clear:
+//! anonymous credentials are no secret. On 990 both run over the implicit TLS.
+
+use super::service::ServiceResult;
+use super::smtp::ms_since;
+use super::ProbeOptions;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Longest reply accepted, FEAT lists included.
+const MAX_REPLY: usize = 64 * 1024;
+/// Password of the anonymous login: by convention, an email address.
+const ANONYMOUS_PASSWORD: &str = "netprobe@";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FtpLogin {
+    pub status: String, // "ok" | "refused" | "error"
+    pub user: String,
+    /// Last reply of the login, e.g. "230 Login successful."
+    pub reply: Option<String>,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Banner, FEAT and `AUTH TLS` on port 21, then the anonymous login if asked.
+/// True when the connection is ready for the TLS handshake.
+pub(super) async fn probe(stream: &mut TcpStream, addr: SocketAddr, result: &mut ServiceResult, options: &ProbeOptions) -> Result<bool, String> {
+    let timeout = options.timeout;
+    greet(stream, result, timeout).await?;
+    let offered = match command(stream, "AUTH TLS", timeout).await? {
+        (234, _) => true,
+        // 500/502: unknown command, 504: not for this mechanism, 534: refused by policy.
+        _ => false,
+    };
+    result.starttls = Some(offered);
+    if options.ftp_anonymous {
+        let login = async {
+            let mut second = tokio::time::timeout(options.connect_timeout(), options.source.connect(addr)).await.map_err(|_| "Connection timed out".to_string())?.map_err(|e| e.to_string())?;
+            expect_greeting(&mut second, timeout).await?;
+            let login = anonymous_login(&mut second, timeout).await;
+            let _ = second.write_all(b"QUIT\r\n").await;
+            login
+        };
+        result.ftp_login = Some(login.await.unwrap_or_else(FtpLogin::failed));
+    }
+    Ok(offered)
+}
+
+/// Banner, FEAT and the anonymous login if asked, over implicit TLS (port 990).
+pub(super) async fn probe_tls<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, options: &ProbeOptions) -> Result<(), String> {
+    greet(stream, result, options.timeout).await?;
+    if options.ftp_anonymous {
+        result.ftp_login = Some(anonymous_login(stream, options.timeout).await.unwrap_or_else(FtpLogin::failed));
+    }
+    Ok(())
+}
+
+/// Welcome banner and the features listed by FEAT (none when the server lacks it).
+async fn greet<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, timeout: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    let (code, lines) = read_reply(stream, timeout).await?;
+    result.greeting_ms = Some(ms_since(started));
+    result.banner = Some(lines.join(" "));
+    if code != 220 {
+        return Err(format!("Server refused the session: {} {}", code, lines.join(" ")));
+    }
+    if let (211, lines) = command(stream, "FEAT", timeout).await? {
+        // The first and last lines frame the list ("Features:", "End").
+        result.capabilities = lines.iter().skip(1).take(lines.len().saturating_sub(2)).map(|line| line.trim().to_string()).filter(|line| !line.is_empty()).collect();
+    }
+    Ok(())
+}
+
+/// The greeting of a connection opened for the login.
+async fn expect_greeting<S: AsyncRead + Unpin>(stream: &mut S, timeout: Duration) -> Result<(), String> {
+    match read_reply(stream, timeout).await? {
+        (220, _) => Ok(()),
+        (code, lines) => Err(format!("Server refused the session: {} {}", code, lines.join(" "))),
+    }
+}
+
+/// USER anonymous, then PASS when asked for it.
+async fn anonymous_login<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, timeout: Duration) -> Result<FtpLogin, String> {
+    let started = Instant::now();
+    let (mut code, mut lines) = command(stream, "USER anonymous", timeout).await?;
+    if code == 331 {
+        (code, lines) = command(stream, &format!("PASS {}", ANONYMOUS_PASSWORD), timeout).await?;
+    }
+    Ok(FtpLogin {
+        status: if code == 230 { "ok" } else { "refused" }.to_string(),
+        user: "anonymous".to_string(),
+        reply: Some(format!("{} {}", code, lines.join(" ")).trim_end().to_string()),
+        latency_ms: Some(ms_since(started)),
+        error: None,
+    })
+}
+
+impl FtpLogin {
+    fn failed(error: String) -> Self {
+        FtpLogin { status: "error".to_string(), user: "anonymous".to_string(), reply: None, latency_ms: None, error: Some(error) }
+    }
+}
+
+async fn command<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, line: &str, timeout: Duration) -> Result<(u16, Vec<String>), String> {
+    stream.write_all(format!("{}\r\n", line).as_bytes()).await.map_err(|e| e.to_string())?;
+    read_reply(stream, timeout).await
+}
+
+/// Read a reply: `220 text`, or `211-first` ... `211 last` with free-form lines between (RFC 959, 4.2).
+/// Bytes are read one at a time so nothing past the reply is consumed before `AUTH TLS`.
+async fn read_reply<S: AsyncRead + Unpin>(stream: &mut S, timeout: Duration) -> Result<(u16, Vec<String>), String> {
+    let read = async {
+        let mut lines = Vec::new();
+        let mut line = Vec::new();
+        let mut code = None;
+        let mut total = 0;
+        loop {
+            let byte = stream.read_u8().await.map_err(|_| "Connection closed by the server".to_string())?;
+            total += 1;
+            if total > MAX_REPLY {
+                return Err("Reply too long".to_string());
+            }
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line).trim_end_matches('\r').to_string();
+            line.clear();
+            let prefix = text.get(..3).and_then(|prefix| prefix.parse::<u16>().ok());
+            let first = match (code, prefix) {
+                (Some(first), _) => first,
+                (None, Some(prefix)) => *code.insert(prefix),
+                (None, None) => return Err(format!("Not an FTP reply: {}", text)),
+            };
+            match (prefix, text.as_bytes().get(3)) {
+                // The last line repeats the code, followed by a space (or nothing).
+                (Some(prefix), separator) if prefix == first && separator != Some(&b'-') => {
+                    lines.push(text.get(4..).unwrap_or_default().to_string());
+                    return Ok((first, lines));
+                },
+                (Some(prefix), Some(b'-')) if prefix == first => lines.push(text.get(4..).unwrap_or_default().to_string()),
+                _ => lines.push(text),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, read).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))?
+}
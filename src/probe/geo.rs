@@ -0,0 +1,113 @@
+//! Optional: GeoIP / ASN enrichment of the resolved address (`--geo`).
+
+use maxminddb::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Where `geoipupdate` and distribution packages install the GeoLite2 databases.
+pub const DEFAULT_PATHS: &[&str] = &[
+    "/usr/share/GeoIP/GeoLite2-City.mmdb",
+    "/usr/share/GeoIP/GeoLite2-Country.mmdb",
+    "/usr/share/GeoIP/GeoLite2-ASN.mmdb",
+    "/var/lib/GeoIP/GeoLite2-City.mmdb",
+    "/var/lib/GeoIP/GeoLite2-Country.mmdb",
+    "/var/lib/GeoIP/GeoLite2-ASN.mmdb",
+    "/usr/local/share/GeoIP/GeoLite2-City.mmdb",
+    "/usr/local/share/GeoIP/GeoLite2-Country.mmdb",
+    "/usr/local/share/GeoIP/GeoLite2-ASN.mmdb",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GeoResult {
+    pub status: String, // "ok" | "error"
+    /// ISO 3166-1 alpha-2 code, e.g. "FR".
+    pub country_code: Option<String>,
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The fields read from a record of any edition; those of the other editions are absent.
+#[derive(Deserialize)]
+struct Record {
+    country: Option<Place>,
+    registered_country: Option<Place>,
+    city: Option<Place>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Place {
+    iso_code: Option<String>,
+    #[serde(default)]
+    names: BTreeMap<String, String>,
+}
+
+impl Place {
+    fn name(&self) -> Option<String> {
+        self.names.get("en").cloned()
+    }
+}
+
+/// One or more MaxMind DB files (City/Country and ASN editions), opened once
+/// and shared by every probe.
+pub struct GeoDb {
+    paths: Vec<PathBuf>,
+    readers: Vec<Reader<Vec<u8>>>,
+}
+
+impl fmt::Debug for GeoDb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeoDb").field("paths", &self.paths).finish()
+    }
+}
+
+impl GeoDb {
+    /// Open the given `.mmdb` files, or every database found in `DEFAULT_PATHS` when empty.
+    pub fn open(paths: &[PathBuf]) -> Result<GeoDb, String> {
+        let paths: Vec<PathBuf> = if paths.is_empty() {
+            DEFAULT_PATHS.iter().map(PathBuf::from).filter(|p| p.exists()).collect()
+        } else {
+            paths.to_vec()
+        };
+        if paths.is_empty() {
+            return Err("No GeoIP database found; pass one with --geo-db".to_string());
+        }
+
+        let readers = paths
+            .iter()
+            .map(|path| Reader::open_readfile(path).map_err(|e| format!("{}: {}", path.display(), e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(GeoDb { paths, readers })
+    }
+
+    /// Merge what every database knows about `ip`.
+    pub fn lookup(&self, ip: IpAddr) -> GeoResult {
+        let mut result =
+            GeoResult { status: "error".to_string(), country_code: None, country: None, city: None, asn: None, as_org: None, error: None };
+
+        // A corrupt record reads as no data, like an address the database does not cover.
+        for record in self.readers.iter().filter_map(|reader| reader.lookup::<Record>(ip).ok()) {
+            if result.country_code.is_none() {
+                let country = record.country.as_ref().filter(|place| place.iso_code.is_some()).or(record.registered_country.as_ref());
+                result.country_code = country.and_then(|place| place.iso_code.clone());
+                result.country = country.and_then(Place::name);
+            }
+            result.city = result.city.take().or_else(|| record.city.as_ref().and_then(Place::name));
+            result.asn = result.asn.or(record.autonomous_system_number);
+            result.as_org = result.as_org.take().or(record.autonomous_system_organization);
+            result.status = "ok".to_string();
+        }
+
+        if result.status != "ok" {
+            result.error = Some(format!("No GeoIP data for {}", ip));
+        }
+        result
+    }
+}
@@ -0,0 +1,201 @@
+//! Optional: gRPC health check (`--grpc`).
+//!
+//! Replaces the request of the HTTP stage with a `grpc.health.v1.Health/Check`
+//! call: a POST over HTTP/2 whose body is a length-prefixed protobuf message.
+//! The call's outcome comes back in the `grpc-status` trailer, and the serving
+//! status in the response message. Both messages have a single field, so they
+//! are encoded and decoded by hand.
+
+use super::error_code::ErrorCode;
+use hyper::header::HeaderMap;
+use serde::Serialize;
+
+/// Path of the standard health checking method.
+pub const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GrpcResult {
+    /// "ok" when the server answered SERVING, "error" otherwise.
+    pub status: String,
+    /// Service asked about; empty for the server as a whole.
+    pub service: String,
+    /// "SERVING", "NOT_SERVING", "UNKNOWN" or "SERVICE_UNKNOWN".
+    pub serving_status: Option<String>,
+    /// `grpc-status` of the call, e.g. 0 (OK) or 12 (UNIMPLEMENTED: no health service).
+    pub grpc_status: Option<u32>,
+    /// Status name of `grpc_status`, e.g. "UNIMPLEMENTED".
+    pub grpc_code: Option<String>,
+    /// `grpc-message` of a failed call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc_message: Option<String>,
+    /// From sending the request to the end of the response trailers.
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "GRPC_NOT_SERVING".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+}
+
+/// Framed `HealthCheckRequest { service }`: compression flag, big-endian length, message.
+pub(crate) fn request_body(service: &str) -> Vec<u8> {
+    let mut message = Vec::new();
+    if !service.is_empty() {
+        message.push(0x0a); // field 1, length-delimited
+        push_varint(&mut message, service.len() as u64);
+        message.extend(service.as_bytes());
+    }
+    let mut body = vec![0];
+    body.extend((message.len() as u32).to_be_bytes());
+    body.extend(message);
+    body
+}
+
+/// Read the outcome of the call from the response.
+/// A failed call may have no body and carry `grpc-status` in its headers ("trailers-only").
+pub(crate) fn response(service: &str, status_code: u16, headers: &HeaderMap, trailers: Option<&HeaderMap>, body: &[u8], latency_ms: f64) -> GrpcResult {
+    let mut result = GrpcResult { status: "error".to_string(), service: service.to_string(), serving_status: None, grpc_status: None, grpc_code: None, grpc_message: None, latency_ms: Some(latency_ms), error: None, error_code: None };
+    let header = |name: &str| trailers.and_then(|trailers| trailers.get(name)).or_else(|| headers.get(name)).and_then(|value| value.to_str().ok());
+    if status_code != 200 {
+        result.error = Some(format!("HTTP status {} instead of 200: not a gRPC endpoint", status_code));
+        result.error_code = Some(ErrorCode::GrpcUnsupported);
+        return result;
+    }
+    let Some(status) = header("grpc-status").and_then(|status| status.parse::<u32>().ok()) else {
+        result.error = Some("No grpc-status in the response: not a gRPC server".to_string());
+        result.error_code = Some(ErrorCode::GrpcUnsupported);
+        return result;
+    };
+    result.grpc_status = Some(status);
+    result.grpc_code = Some(code_name(status).to_string());
+    result.grpc_message = header("grpc-message").map(percent_decode).filter(|message| !message.is_empty());
+    if status != 0 {
+        let explanation = match status {
+            12 => " (the server has no health service)",
+            5 => " (unknown service)",
+            _ => "",
+        };
+        let message = result.grpc_message.as_deref().map(|message| format!(": {}", message)).unwrap_or_default();
+        result.error = Some(format!("Call failed with {}{}{}", code_name(status), explanation, message));
+        result.error_code = Some(ErrorCode::GrpcCallFailed);
+        return result;
+    }
+
+    let serving = match serving_status(body) {
+        Ok(serving) => serving,
+        Err(e) => {
+            result.error = Some(e);
+            result.error_code = Some(ErrorCode::GrpcMalformed);
+            return result;
+        },
+    };
+    result.serving_status = Some(serving.to_string());
+    if serving == "SERVING" {
+        result.status = "ok".to_string();
+    } else {
+        result.error = Some(format!("Health status {}", serving));
+        result.error_code = Some(ErrorCode::GrpcNotServing);
+    }
+    result
+}
+
+/// Serving status of a framed `HealthCheckResponse`; an absent field is its zero value, UNKNOWN.
+fn serving_status(body: &[u8]) -> Result<&'static str, String> {
+    let (header, rest) = body.split_at_checked(5).ok_or("Empty response message")?;
+    if header[0] != 0 {
+        return Err("Compressed response message (not requested)".to_string());
+    }
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let mut message = rest.get(..length).ok_or("Truncated response message")?;
+    let mut status = 0;
+    while let Some((&key, rest)) = message.split_first() {
+        message = rest;
+        // Skip whatever fields a newer server may add.
+        match key & 0x07 {
+            0 => {
+                let value = read_varint(&mut message).ok_or("Malformed response message")?;
+                if key >> 3 == 1 {
+                    status = value;
+                }
+            },
+            2 => {
+                let length = read_varint(&mut message).ok_or("Malformed response message")? as usize;
+                message = message.get(length..).ok_or("Malformed response message")?;
+            },
+            1 => message = message.get(8..).ok_or("Malformed response message")?,
+            5 => message = message.get(4..).ok_or("Malformed response message")?,
+            _ => return Err("Malformed response message".to_string()),
+        }
+    }
+    Ok(match status {
+        1 => "SERVING",
+        2 => "NOT_SERVING",
+        3 => "SERVICE_UNKNOWN",
+        _ => "UNKNOWN",
+    })
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Canonical name of a gRPC status code.
+fn code_name(code: u32) -> &'static str {
+    match code {
+        0 => "OK",
+        1 => "CANCELLED",
+        2 => "UNKNOWN",
+        3 => "INVALID_ARGUMENT",
+        4 => "DEADLINE_EXCEEDED",
+        5 => "NOT_FOUND",
+        6 => "ALREADY_EXISTS",
+        7 => "PERMISSION_DENIED",
+        8 => "RESOURCE_EXHAUSTED",
+        9 => "FAILED_PRECONDITION",
+        10 => "ABORTED",
+        11 => "OUT_OF_RANGE",
+        12 => "UNIMPLEMENTED",
+        13 => "INTERNAL",
+        14 => "UNAVAILABLE",
+        15 => "DATA_LOSS",
+        16 => "UNAUTHENTICATED",
+        _ => "UNRECOGNIZED",
+    }
+}
+
+/// `grpc-message` is percent-encoded.
+fn percent_decode(message: &str) -> String {
+    let bytes = message.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,635 @@
+//! Step 4: HTTP/HTTPS request.
+//!
+//! The request runs over a connection we open ourselves (DNS, TCP, TLS, then
+//! hyper for the HTTP exchange) so that each phase can be timed separately.
+
+use super::auth::HttpAuth;
+use super::cookies::CookieJar;
+use super::error_code::{CodedError, ErrorCode, WithCode};
+use super::grpc::{self, GrpcResult};
+use super::retry::Attempt;
+use super::security::{self, SecurityAudit};
+use super::source::SourceBinding;
+use super::proxy::{self, Proxy, ProxyKind, ProxySettings};
+use super::{dns, tls, ProbeOptions};
+use hyper::body::HttpBody;
+use hyper::header::{self, HeaderMap};
+use hyper::{Body, Method, Request};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// Same limit reqwest applies by default.
+const MAX_REDIRECTS: usize = 10;
+/// Credentials that must not leak to another host on redirect.
+const SENSITIVE_HEADERS: [header::HeaderName; 3] = [header::AUTHORIZATION, header::PROXY_AUTHORIZATION, header::COOKIE];
+const USER_AGENT: &str = "NetProbe/1.0";
+/// Body assertions only look at the start of large responses.
+const MAX_CAPTURED_BODY: usize = 1024 * 1024;
+
+/// Which HTTP version the request stage may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HttpVersion {
+    /// HTTP/2 when the server agrees through ALPN, HTTP/1.1 otherwise (and always over plain HTTP).
+    #[default]
+    Negotiate,
+    /// HTTP/1.1 only (`--http1-only`).
+    Http1,
+    /// HTTP/2 only; over plain HTTP it is spoken without upgrade (`--http2-prior-knowledge`).
+    Http2,
+}
+
+impl HttpVersion {
+    /// Protocols offered in the TLS ALPN extension.
+    pub(crate) fn alpn_protocols(self) -> Vec<Vec<u8>> {
+        match self {
+            HttpVersion::Negotiate => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            HttpVersion::Http1 => vec![b"http/1.1".to_vec()],
+            HttpVersion::Http2 => vec![b"h2".to_vec()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HttpResult {
+    pub status_code: Option<u16>,
+    /// Protocol of the response: "HTTP/1.0", "HTTP/1.1" or "HTTP/2".
+    pub version: Option<String>,
+    /// Address the request was sent to (the last hop when following redirects); the proxy's when proxied.
+    pub peer_ip: Option<String>,
+    /// Authentication scheme the final request carried, e.g. "basic" or "digest SHA-256".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<String>,
+    /// Names of the cookies the server set, on any hop; they were sent on the following ones.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cookies: Vec<String>,
+    /// Proxy the request went through, without credentials.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Wall-clock time of the whole stage, redirects included.
+    pub latency_ms: Option<f64>,
+    /// Phase breakdown of the request whose status is reported (the last hop when following redirects).
+    pub timing: Option<HttpTiming>,
+    /// Response headers (lowercase names, repeated ones joined with ", "), filtered by `header_filter`;
+    /// `None` with `capture_headers` off.
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Every redirect followed (`follow_redirects`), in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<RedirectHop>,
+    /// Security headers grade of the final response (`--security-audit`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security: Option<SecurityAudit>,
+    /// Size and speed of the response body (GET requests).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<DownloadStats>,
+    /// The final request replayed on the same connection (`--warm`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warm_request: Option<WarmRequest>,
+    /// The final request as sent (`-v`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<SentRequest>,
+    /// Outcome of the health check the request carried (`--grpc`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc: Option<GrpcResult>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "HTTP_TIMEOUT".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+    /// Each request when retrying (`--retries`); the fields above describe the last one.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+    /// First `MAX_CAPTURED_BODY` bytes of the response body, kept for body assertions only.
+    #[serde(skip)]
+    pub(crate) body: Option<Vec<u8>>,
+    /// `Alt-Svc` response header, kept for HTTP/3 discovery even when headers are not captured.
+    #[serde(skip)]
+    pub(crate) alt_svc: Option<String>,
+    /// Every response header, unfiltered, for CDN detection.
+    #[serde(skip)]
+    pub(crate) response_headers: Option<HeaderMap>,
+    /// Method of the final request, for HAR output.
+    #[serde(skip)]
+    pub(crate) method: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadStats {
+    /// Body bytes actually received.
+    pub bytes: u64,
+    /// `Content-Length` announced by the server, if any.
+    pub content_length: Option<u64>,
+    pub duration_ms: f64,
+    /// Decimal megabytes per second over the body transfer.
+    pub throughput_mb_s: f64,
+}
+
+/// Cold vs warm latency of the same request: the first pays for DNS, TCP and
+/// TLS, the second reuses the connection (keep-alive or HTTP/2).
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmRequest {
+    /// "ok" when the connection could be reused, "error" otherwise.
+    pub status: String,
+    pub status_code: Option<u16>,
+    /// The first request, connection setup included.
+    pub cold_ms: f64,
+    /// The second request, on the open connection.
+    pub warm_ms: Option<f64>,
+    /// What connection reuse saves per request.
+    pub saved_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// One 3xx response on the way to the final URL.
+#[derive(Debug, Clone, Serialize)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+    /// Absolute target of the `Location` header.
+    pub location: String,
+    pub latency_ms: f64,
+    /// Method of the request, for HAR output.
+    #[serde(skip)]
+    pub(crate) method: String,
+}
+
+/// Request line and headers of a request, as handed to the connection.
+#[derive(Debug, Clone, Serialize)]
+pub struct SentRequest {
+    pub method: String,
+    /// Path and query, or the absolute URI over HTTP/2 and to a forwarding proxy.
+    pub uri: String,
+    /// Lowercase names, repeated ones joined with ", ".
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Where the time of one request went, like curl's `-w` timers (but as durations, not cumulative).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HttpTiming {
+    pub dns_ms: f64,
+    pub connect_ms: f64,
+    /// Opening the tunnel through the proxy (CONNECT or SOCKS5, plus TLS to an https:// proxy);
+    /// `None` without a proxy or when it forwards the request. With a proxy, `dns_ms` and
+    /// `connect_ms` are those of the proxy.
+    pub proxy_ms: Option<f64>,
+    /// `None` for plain HTTP.
+    pub tls_ms: Option<f64>,
+    /// From sending the request to receiving the response headers.
+    pub ttfb_ms: f64,
+    /// Reading the response body.
+    pub transfer_ms: f64,
+    pub total_ms: f64,
+}
+
+impl HttpResult {
+    pub(crate) fn pending() -> Self {
+        HttpResult { status_code: None, version: None, peer_ip: None, auth: None, cookies: Vec::new(), proxy: None, latency_ms: None, timing: None, headers: None, redirects: Vec::new(), security: None, download: None, warm_request: None, request: None, grpc: None, error: None, error_code: None, body: None, alt_svc: None, response_headers: None, method: None, attempts: Vec::new() }
+    }
+}
+
+/// Anything hyper can speak HTTP over: a TCP stream, with or without TLS.
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// One request/response exchange on a fresh connection.
+struct Exchange {
+    method: Method,
+    status: u16,
+    version: String,
+    peer: SocketAddr,
+    headers: HeaderMap,
+    timing: HttpTiming,
+    body_bytes: u64,
+    /// Captured body, when `ProbeOptions::needs_body`.
+    body: Option<Vec<u8>>,
+    /// Authentication scheme sent, set by `follow`.
+    auth: Option<String>,
+    /// Second request on the same connection, with `--warm`.
+    warm: Option<WarmRequest>,
+    /// Names of the cookies set along the redirect chain, set by `follow`.
+    cookies: Vec<String>,
+    /// The request sent, with `ProbeOptions::verbose`.
+    request: Option<SentRequest>,
+    /// Response trailers, read for gRPC only.
+    trailers: Option<HeaderMap>,
+}
+
+/// Send the request (HEAD unless another method is configured) to `url` and capture
+/// status, latency, phase timings, key headers and, for GET, the download speed.
+pub async fn request(url: &str, options: &ProbeOptions) -> HttpResult {
+    let mut result = HttpResult::pending();
+    result.proxy = Url::parse(url).ok().and_then(|url| options.proxy.for_url(&url).map(|proxy| proxy.to_string()));
+    let start_http = Instant::now();
+
+    // Hops are collected as they happen, so the chain is kept even if a later hop fails.
+    let mut redirects = Vec::new();
+    let outcome = tokio::time::timeout(options.http_timeout(), follow(url, options, &mut redirects)).await;
+    result.redirects = redirects;
+    match outcome {
+        Ok(Ok(exchange)) => {
+            result.method = Some(exchange.method.to_string());
+            result.status_code = Some(exchange.status);
+            result.version = Some(exchange.version);
+            result.peer_ip = Some(exchange.peer.ip().to_string());
+            result.auth = exchange.auth;
+            result.cookies = exchange.cookies;
+            result.latency_ms = Some(ms_since(start_http));
+
+            result.alt_svc = exchange.headers.get(header::ALT_SVC).and_then(|v| v.to_str().ok()).map(String::from);
+            if options.capture_headers {
+                result.headers = Some(capture_headers(&exchange.headers, &options.header_filter));
+            }
+            if options.security_audit {
+                let final_url = result.redirects.last().map_or(url, |hop| hop.location.as_str());
+                let scheme = Url::parse(final_url).map(|url| url.scheme().to_string()).unwrap_or_default();
+                result.security = Some(security::audit(&exchange.headers, &scheme));
+            }
+
+            if options.method == Method::GET {
+                let seconds = exchange.timing.transfer_ms / 1000.0;
+                result.download = Some(DownloadStats {
+                    bytes: exchange.body_bytes,
+                    content_length: exchange.headers.get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse().ok()),
+                    duration_ms: exchange.timing.transfer_ms,
+                    throughput_mb_s: if seconds > 0.0 { exchange.body_bytes as f64 / seconds / 1_000_000.0 } else { 0.0 },
+                });
+            }
+            if let Some(service) = &options.grpc {
+                let body = exchange.body.as_deref().unwrap_or_default();
+                let call_ms = exchange.timing.ttfb_ms + exchange.timing.transfer_ms;
+                result.grpc = Some(grpc::response(service, exchange.status, &exchange.headers, exchange.trailers.as_ref(), body, call_ms));
+            }
+            result.warm_request = exchange.warm;
+            result.request = exchange.request;
+            result.timing = Some(exchange.timing);
+            result.body = exchange.body;
+            result.response_headers = Some(exchange.headers);
+        },
+        Ok(Err(e)) => {
+            result.error = Some(e.message);
+            result.error_code = Some(e.code);
+        },
+        Err(_) => {
+            result.error = Some(format!("Request timed out after {:.1}s", options.http_timeout().as_secs_f64()));
+            result.error_code = Some(ErrorCode::HttpTimeout);
+        },
+    }
+
+    result
+}
+
+/// Run the exchange, chasing `Location` headers when `follow_redirects` is set
+/// and recording each redirect in `redirects`.
+async fn follow(url: &str, options: &ProbeOptions, redirects: &mut Vec<RedirectHop>) -> Result<Exchange, CodedError> {
+    let mut url = Url::parse(url).code(ErrorCode::HttpInvalidRequest)?;
+    let mut method = options.method.clone();
+    let mut body = options.body.as_deref();
+    // A health check is a POST to a fixed path, whatever the target's.
+    let grpc_body = options.grpc.as_deref().map(grpc::request_body);
+    if let Some(grpc_body) = &grpc_body {
+        url.set_path(grpc::HEALTH_CHECK_PATH);
+        url.set_query(None);
+        method = Method::POST;
+        body = Some(grpc_body);
+    }
+    let mut extra_headers = custom_headers(&options.headers).code(ErrorCode::HttpInvalidRequest)?;
+    let auth = options.auth.as_ref();
+    if let Some(value) = auth.and_then(HttpAuth::preemptive) {
+        extra_headers.insert(header::AUTHORIZATION, header::HeaderValue::from_str(&value).map_err(|_| "Invalid characters in credentials").code(ErrorCode::HttpInvalidRequest)?);
+    }
+    // Credentials stay with the host they were given for, as the Authorization header does.
+    let auth_host = url.host_str().map(String::from);
+    let mut jar = options.cookie_jar.clone().unwrap_or_default();
+    for (name, value) in &options.cookies {
+        jar.set(auth_host.as_deref().unwrap_or_default(), name, value);
+    }
+    let mut cookies_set = Vec::new();
+    loop {
+        let mut exchange = exchange(&url, options, &method, body, &with_cookies(&extra_headers, &jar, &url).code(ErrorCode::HttpInvalidRequest)?).await?;
+        cookies_set.extend(jar.store(&url, &exchange.headers));
+        let preemptive = extra_headers.contains_key(header::AUTHORIZATION) && auth.is_some_and(|auth| auth.preemptive().is_some());
+        exchange.auth = auth.filter(|_| preemptive).map(|auth| auth.scheme().to_string());
+        if exchange.status == 401 && url.host_str() == auth_host.as_deref() {
+            let uri = &url[url::Position::BeforePath..url::Position::AfterQuery];
+            if let Some((value, algorithm)) = auth.and_then(|auth| auth.respond(&exchange.headers, &method, uri)) {
+                let mut headers = with_cookies(&extra_headers, &jar, &url).code(ErrorCode::HttpInvalidRequest)?;
+                headers.insert(header::AUTHORIZATION, header::HeaderValue::from_str(&value).map_err(|_| "Invalid characters in credentials").code(ErrorCode::HttpInvalidRequest)?);
+                exchange = self::exchange(&url, options, &method, body, &headers).await?;
+                cookies_set.extend(jar.store(&url, &exchange.headers));
+                exchange.auth = Some(format!("digest {}", algorithm));
+            }
+        }
+        let location = exchange.headers.get(header::LOCATION).and_then(|l| l.to_str().ok());
+        let location = match location {
+            Some(location) if options.follow_redirects && (300..400).contains(&exchange.status) => location,
+            _ => {
+                exchange.cookies = cookies_set;
+                return Ok(exchange);
+            },
+        };
+        if redirects.len() == MAX_REDIRECTS {
+            return Err(CodedError::new(ErrorCode::HttpTooManyRedirects, format!("Too many redirects (more than {})", MAX_REDIRECTS)));
+        }
+        let next = url.join(location).map_err(|e| format!("Invalid redirect location '{}': {}", location, e)).code(ErrorCode::HttpMalformedResponse)?;
+        redirects.push(RedirectHop { url: url.to_string(), status: exchange.status, location: next.to_string(), latency_ms: exchange.timing.total_ms, method: method.to_string() });
+        if next.host_str() != url.host_str() {
+            for name in &SENSITIVE_HEADERS {
+                extra_headers.remove(name);
+            }
+        }
+        tracing::debug!(status = exchange.status, location = %next, "following redirect");
+        url = next;
+        // As browsers do (Fetch spec): 303 turns any method but HEAD into a plain GET, 301/302
+        // only a POST; everything else is replayed as is, body included.
+        let to_get = match exchange.status {
+            303 => method != Method::HEAD,
+            301 | 302 => method == Method::POST,
+            _ => false,
+        };
+        if to_get {
+            method = Method::GET;
+            body = None;
+        }
+    }
+}
+
+/// Resolve, connect, handshake and send one request, timing each phase.
+async fn exchange(url: &Url, options: &ProbeOptions, method: &Method, body: Option<&[u8]>, extra_headers: &HeaderMap) -> Result<Exchange, CodedError> {
+    let host = url.host_str().ok_or("URL has no host").code(ErrorCode::HttpInvalidRequest)?;
+    let port = url.port_or_known_default().ok_or("URL has no port").code(ErrorCode::HttpInvalidRequest)?;
+    let started = Instant::now();
+    let mut timing = HttpTiming::default();
+
+    // Through a proxy, the lookup and connect phases are those of the proxy.
+    let proxy = options.proxy.for_url(url);
+    let (dns_result, addrs) = match proxy {
+        Some(proxy) => dns::resolve(&proxy.host, proxy.port, options).await,
+        None => dns::resolve(host, port, options).await,
+    };
+    if let Some(e) = dns_result.error {
+        let message = match proxy {
+            Some(proxy) => format!("DNS lookup of proxy {} failed: {}", proxy, e),
+            None => format!("DNS lookup failed: {}", e),
+        };
+        return Err(CodedError::new(ErrorCode::HttpDns, message));
+    }
+    // SOCKS5 without remote resolution needs the target's address too.
+    let target_ip = match proxy {
+        Some(proxy) if proxy.kind == ProxyKind::Socks5 => match dns::resolve(host, port, options).await {
+            (_, addrs) if !addrs.is_empty() => Some(addrs[0].ip()),
+            (dns_result, _) => return Err(CodedError::new(ErrorCode::HttpDns, format!("DNS lookup failed: {}", dns_result.error.unwrap_or_default()))),
+        },
+        _ => None,
+    };
+    timing.dns_ms = ms_since(started);
+
+    let phase = Instant::now();
+    let tcp = connect_any(&addrs, options.connect_timeout(), &options.source).await.code(ErrorCode::HttpConnect)?;
+    let peer = tcp.peer_addr().code(ErrorCode::HttpConnect)?;
+    timing.connect_ms = ms_since(phase);
+
+    let mut stream: Box<dyn Io> = Box::new(tcp);
+    let mut forwarded = false;
+    if let Some(proxy) = proxy {
+        let phase = Instant::now();
+        if proxy.kind == ProxyKind::Https {
+            let config = rustls::ClientConfig::builder().with_safe_defaults().with_root_certificates(tls::root_store()).with_no_client_auth();
+            let server_name = tls::server_name(&proxy.host).code(ErrorCode::HttpProxy)?;
+            let handshake = TlsConnector::from(Arc::new(config)).connect(server_name, stream).await;
+            stream = Box::new(handshake.map_err(|e| format!("TLS handshake with proxy {} failed: {}", proxy, e)).code(ErrorCode::HttpProxy)?);
+        }
+        let target = host.trim_start_matches('[').trim_end_matches(']');
+        match proxy.kind {
+            ProxyKind::Socks5 | ProxyKind::Socks5h => proxy::socks5_connect(&mut stream, proxy, target, target_ip, port).await.code(ErrorCode::HttpProxy)?,
+            _ if proxy.tunnels(url, options.http_version() == HttpVersion::Http2) => proxy::http_connect(&mut stream, proxy, host, port).await.code(ErrorCode::HttpProxy)?,
+            _ => forwarded = true,
+        }
+        if !forwarded || proxy.kind == ProxyKind::Https {
+            timing.proxy_ms = Some(ms_since(phase));
+        }
+    }
+
+    let (stream, http2): (Box<dyn Io>, bool) = if url.scheme() == "https" {
+        let phase = Instant::now();
+        let server_name = tls::server_name(options.sni.as_deref().unwrap_or(host)).code(ErrorCode::HttpInvalidRequest)?;
+        let mut config = tls::client_config(options);
+        config.alpn_protocols = options.http_version().alpn_protocols();
+        let stream = TlsConnector::from(Arc::new(config)).connect(server_name, stream).await.map_err(|e| format!("TLS handshake failed: {}", e)).code(ErrorCode::HttpTls)?;
+        timing.tls_ms = Some(ms_since(phase));
+        let http2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+        if options.http_version() == HttpVersion::Http2 && !http2 {
+            return Err(CodedError::new(ErrorCode::HttpProtocol, "Server did not negotiate HTTP/2 (ALPN h2)"));
+        }
+        (Box::new(stream), http2)
+    } else {
+        (stream, options.http_version() == HttpVersion::Http2)
+    };
+
+    let (mut sender, connection) = hyper::client::conn::Builder::new().http2_only(http2).handshake(stream).await.map_err(response_error)?;
+    tokio::spawn(async move {
+        // Errors surface through `send_request`; the driver has nothing more to report.
+        let _ = connection.await;
+    });
+
+    // HTTP/2 carries the authority in the request URI instead of a Host header;
+    // a forwarding proxy needs the absolute URI as well.
+    let build = || -> Result<Request<Body>, String> {
+        let mut request = if http2 {
+            Request::builder().uri(&url[..url::Position::AfterQuery])
+        } else {
+            let uri = if forwarded { &url[..url::Position::AfterQuery] } else { &url[url::Position::BeforePath..url::Position::AfterQuery] };
+            Request::builder().uri(uri).header(header::HOST, &url[url::Position::BeforeHost..url::Position::AfterPort])
+        };
+        if let Some(authorization) = proxy.filter(|_| forwarded).and_then(|proxy| proxy.authorization()) {
+            request = request.header(header::PROXY_AUTHORIZATION, authorization);
+        }
+        request = request
+            .method(method.clone())
+            .header(header::USER_AGENT, USER_AGENT)
+            .header(header::ACCEPT, "*/*");
+        if options.grpc.is_some() {
+            request = request.header(header::CONTENT_TYPE, "application/grpc").header(header::TE, "trailers");
+        } else if let Some(body) = body {
+            request = request.header(header::CONTENT_TYPE, options.content_type.as_deref().unwrap_or_else(|| guess_content_type(body)));
+        }
+        let mut request = request.body(body.map(|b| Body::from(b.to_vec())).unwrap_or_else(Body::empty)).map_err(|e| e.to_string())?;
+        // User headers replace the defaults of the same name (Host, User-Agent...).
+        for name in extra_headers.keys() {
+            request.headers_mut().remove(name);
+        }
+        request.headers_mut().extend(extra_headers.clone());
+        Ok(request)
+    };
+    let request = build().code(ErrorCode::HttpInvalidRequest)?;
+    tracing::debug!(method = %method, url = %url, http2, "sending request");
+    let sent = options.verbose.then(|| SentRequest { method: method.to_string(), uri: request.uri().to_string(), headers: capture_headers(request.headers(), &[]) });
+
+    let phase = Instant::now();
+    let response = sender.send_request(request).await.map_err(response_error)?;
+    timing.ttfb_ms = ms_since(phase);
+
+    let phase = Instant::now();
+    let (parts, mut body) = response.into_parts();
+    let mut body_bytes = 0;
+    let mut captured = (options.needs_body() || options.grpc.is_some()).then(Vec::new);
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(response_error)?;
+        body_bytes += chunk.len() as u64;
+        if let Some(captured) = captured.as_mut() {
+            let room = MAX_CAPTURED_BODY.saturating_sub(captured.len());
+            captured.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        }
+    }
+    // gRPC sends the outcome of the call after the body.
+    let trailers = match options.grpc {
+        Some(_) => body.trailers().await.map_err(response_error)?,
+        None => None,
+    };
+    timing.transfer_ms = ms_since(phase);
+    timing.total_ms = ms_since(started);
+
+    let version = match parts.version {
+        hyper::Version::HTTP_2 => "HTTP/2",
+        hyper::Version::HTTP_10 => "HTTP/1.0",
+        _ => "HTTP/1.1",
+    };
+    // Only the last hop is worth a second request: redirects being followed are left alone.
+    let redirected = options.follow_redirects && parts.status.is_redirection() && parts.headers.contains_key(header::LOCATION);
+    let warm = match options.warm && !redirected {
+        true => Some(warm_request(&mut sender, build().code(ErrorCode::HttpInvalidRequest)?, timing.total_ms).await),
+        false => None,
+    };
+    Ok(Exchange { method: method.clone(), status: parts.status.as_u16(), peer, version: version.to_string(), headers: parts.headers, timing, body_bytes, body: captured, warm, auth: None, cookies: Vec::new(), request: sent, trailers })
+}
+
+/// Client of netprobe's own requests (OCSP and CRLs, DoH, RDAP). They go through `proxy`
+/// with the `tls` settings of the HTTP stage, but carry none of the target's headers,
+/// credentials or cookies.
+pub(crate) fn side_client(proxy: &ProxySettings, tls: rustls::ClientConfig, timeout: Duration) -> reqwest::Client {
+    let proxy = proxy.clone();
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .user_agent(USER_AGENT)
+        .use_preconfigured_tls(tls)
+        // Replaces reqwest's own reading of the proxy variables: `proxy` already has them.
+        .proxy(reqwest::Proxy::custom(move |url| proxy.for_url(url).map(Proxy::url)))
+        .build()
+        .expect("a client with a rustls configuration builds")
+}
+
+/// A failed exchange with the server, once connected, coded by what went wrong.
+fn response_error(e: hyper::Error) -> CodedError {
+    let code = if e.is_parse() {
+        ErrorCode::HttpMalformedResponse
+    } else if e.is_incomplete_message() || e.is_closed() || e.is_canceled() {
+        ErrorCode::HttpConnectionClosed
+    } else {
+        ErrorCode::HttpProtocol
+    };
+    CodedError::new(code, e)
+}
+
+/// Send the request again on the connection of the first one, now warm.
+async fn warm_request(sender: &mut hyper::client::conn::SendRequest<Body>, request: Request<Body>, cold_ms: f64) -> WarmRequest {
+    let failed = |error: String| WarmRequest { status: "error".to_string(), status_code: None, cold_ms, warm_ms: None, saved_ms: None, error: Some(error) };
+    // HTTP/1.0 servers and `Connection: close` end the connection after the first response.
+    if std::future::poll_fn(|cx| sender.poll_ready(cx)).await.is_err() {
+        return failed("Server closed the connection after the first response (no keep-alive)".to_string());
+    }
+    let started = Instant::now();
+    let response = match sender.send_request(request).await {
+        Ok(response) => response,
+        Err(e) => return failed(e.to_string()),
+    };
+    let status_code = response.status().as_u16();
+    let mut body = response.into_body();
+    while let Some(chunk) = body.data().await {
+        if let Err(e) = chunk {
+            return failed(e.to_string());
+        }
+    }
+    let warm_ms = ms_since(started);
+    WarmRequest { status: "ok".to_string(), status_code: Some(status_code), cold_ms, warm_ms: Some(warm_ms), saved_ms: Some(cold_ms - warm_ms), error: None }
+}
+
+/// Response headers whose names pass `filters`, with repeated headers joined.
+fn capture_headers(headers: &HeaderMap, filters: &[String]) -> BTreeMap<String, String> {
+    let mut captured = BTreeMap::new();
+    for name in headers.keys().filter(|name| header_wanted(name.as_str(), filters)) {
+        let values: Vec<String> = headers.get_all(name).iter().map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned()).collect();
+        captured.insert(name.to_string(), values.join(", "));
+    }
+    captured
+}
+
+/// Filters are header names, optionally ending in `*` to match a prefix (`x-*`).
+/// A leading `!` denies; when any allow pattern is given, only matching headers are kept.
+fn header_wanted(name: &str, filters: &[String]) -> bool {
+    let matches = |pattern: &str| match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(&prefix.to_ascii_lowercase()),
+        None => name.eq_ignore_ascii_case(pattern),
+    };
+    let (deny, allow): (Vec<&str>, Vec<&str>) = filters.iter().map(String::as_str).partition(|f| f.starts_with('!'));
+    if deny.iter().any(|f| matches(&f[1..])) {
+        return false;
+    }
+    allow.is_empty() || allow.iter().any(|f| matches(f))
+}
+
+/// Validate `(name, value)` pairs into a header map, keeping repeated names.
+fn custom_headers(headers: &[(String, String)]) -> Result<HeaderMap, String> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name '{}'", name))?;
+        let value = header::HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header '{}'", name))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// `extra_headers` plus the jar's cookies for `url`, unless a `Cookie` header was given explicitly.
+fn with_cookies(extra_headers: &HeaderMap, jar: &CookieJar, url: &Url) -> Result<HeaderMap, String> {
+    let mut headers = extra_headers.clone();
+    if let Some(cookies) = jar.header(url).filter(|_| !headers.contains_key(header::COOKIE)) {
+        headers.insert(header::COOKIE, header::HeaderValue::from_str(&cookies).map_err(|_| "Invalid characters in cookies")?);
+    }
+    Ok(headers)
+}
+
+/// JSON bodies are by far the most common when probing APIs; anything else is sent as text.
+fn guess_content_type(body: &[u8]) -> &'static str {
+    match serde_json::from_slice::<serde::de::IgnoredAny>(body) {
+        Ok(_) => "application/json",
+        Err(_) => "text/plain; charset=utf-8",
+    }
+}
+
+/// Connect to the first address that accepts, in resolver order, giving each
+/// address its own connect timeout so a black-holed one does not eat the budget.
+async fn connect_any(addrs: &[SocketAddr], timeout: Duration, source: &SourceBinding) -> Result<TcpStream, String> {
+    let mut last_error = "No address to connect to".to_string();
+    for addr in addrs {
+        tracing::debug!(%addr, "connecting");
+        match tokio::time::timeout(timeout, source.connect(*addr)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => {
+                tracing::debug!(%addr, error = %e, "connection failed");
+                last_error = format!("Connection to {} failed: {}", addr, e);
+            },
+            Err(_) => {
+                tracing::debug!(%addr, "connection timed out");
+                last_error = format!("Connection to {} timed out after {:.1}s", addr, timeout.as_secs_f64());
+            },
+        }
+    }
+    Err(last_error)
+}
+
+fn ms_since(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
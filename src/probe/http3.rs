@@ -0,0 +1,141 @@
+//! Optional: HTTP/3 (`--http3`).
+//!
+//! Checks whether the server advertises HTTP/3 in `Alt-Svc`, then makes a QUIC
+//! handshake (quinn) on the advertised UDP port and sends the request over it (h3).
+//! Both are timed against the TCP connect, TLS handshake and first byte of the
+//! HTTP stage.
+
+use super::error_code::{CodedError, ErrorCode, WithCode};
+use super::http::HttpResult;
+use super::{tls, ProbeOptions};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use url::Url;
+
+const USER_AGENT: &str = "NetProbe/1.0";
+/// Application protocol of HTTP/3.
+const ALPN: &[u8] = b"h3";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Http3Result {
+    /// "ok" when the request got a response over HTTP/3, "error" otherwise.
+    pub status: String,
+    /// The response advertised an `h3` protocol in `Alt-Svc`.
+    pub advertised: bool,
+    pub alt_svc: Option<String>,
+    /// UDP port probed: the advertised one, or the target port.
+    pub port: u16,
+    /// QUIC handshake, TLS 1.3 included.
+    pub handshake_ms: Option<f64>,
+    /// From sending the request to receiving the response headers.
+    pub ttfb_ms: Option<f64>,
+    /// Handshake plus time to first byte: the HTTP/3 side of the comparison.
+    pub latency_ms: Option<f64>,
+    pub status_code: Option<u16>,
+    /// Protocol of the HTTP stage, e.g. "HTTP/2".
+    pub http_version: Option<String>,
+    /// TCP connect plus TLS handshake of the HTTP stage, against `handshake_ms`.
+    pub tcp_tls_ms: Option<f64>,
+    /// TCP connect, TLS handshake and time to first byte of the HTTP stage, against `latency_ms`.
+    /// `None` when the HTTP stage followed redirects: its timing is then that of another URL.
+    pub http_latency_ms: Option<f64>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "HTTP3_HANDSHAKE".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+}
+
+/// Request `url` over HTTP/3 from `ip`, using the `Alt-Svc` header of the HTTP stage to find the port.
+pub async fn probe(ip: SocketAddr, url: &Url, http: &HttpResult, options: &ProbeOptions) -> Http3Result {
+    let alt_svc = http.alt_svc.clone();
+    let advertised_port = alt_svc.as_deref().and_then(h3_port);
+    let port = advertised_port.unwrap_or(ip.port());
+    let timing = http.timing.as_ref();
+    let mut result = Http3Result {
+        status: "error".to_string(),
+        advertised: advertised_port.is_some(),
+        alt_svc,
+        port,
+        handshake_ms: None,
+        ttfb_ms: None,
+        latency_ms: None,
+        status_code: None,
+        http_version: http.version.clone(),
+        tcp_tls_ms: timing.map(|t| t.connect_ms + t.tls_ms.unwrap_or(0.0)),
+        http_latency_ms: timing.filter(|_| http.redirects.is_empty()).map(|t| t.connect_ms + t.tls_ms.unwrap_or(0.0) + t.ttfb_ms),
+        error: None,
+        error_code: None,
+    };
+
+    let target = SocketAddr::new(ip.ip(), port);
+    match tokio::time::timeout(options.http_timeout(), request(target, url, options, &mut result)).await {
+        Ok(Ok(status_code)) => {
+            result.status = "ok".to_string();
+            result.status_code = Some(status_code);
+            result.latency_ms = result.handshake_ms.zip(result.ttfb_ms).map(|(handshake, ttfb)| handshake + ttfb);
+        },
+        Ok(Err(e)) => {
+            result.error = Some(e.message);
+            result.error_code = Some(e.code);
+        },
+        Err(_) => {
+            result.error_code = Some(ErrorCode::Http3Timeout);
+            result.error = Some(match result.handshake_ms {
+                Some(_) => "HTTP/3 request timed out".to_string(),
+                None => format!("No QUIC handshake on UDP {} before the timeout", port),
+            })
+        },
+    }
+    tracing::debug!(port, status = result.status.as_str(), handshake_ms = result.handshake_ms, ttfb_ms = result.ttfb_ms, "http/3");
+    result
+}
+
+/// QUIC handshake, then a `GET` of `url` on the first request stream. Records the
+/// handshake and time to first byte in `result` as they happen, and returns the status.
+async fn request(target: SocketAddr, url: &Url, options: &ProbeOptions, result: &mut Http3Result) -> Result<u16, CodedError> {
+    let host = url.host_str().ok_or("URL has no host").code(ErrorCode::Http3Local)?;
+    let config = tls::quic_client_config(tls::verifier(options), ALPN).code(ErrorCode::Http3Local)?;
+    let socket = options.source.udp(target).map_err(|e| format!("Cannot open a UDP socket: {}", e)).code(ErrorCode::Http3Local)?;
+    socket.set_nonblocking(true).code(ErrorCode::Http3Local)?;
+    let mut endpoint = quinn::Endpoint::new(quinn::EndpointConfig::default(), None, socket, Arc::new(quinn::TokioRuntime)).code(ErrorCode::Http3Local)?;
+    endpoint.set_default_client_config(config);
+
+    let started = Instant::now();
+    let connecting = endpoint.connect(target, host.trim_start_matches('[').trim_end_matches(']')).map_err(|e| CodedError::new(ErrorCode::Http3Local, format!("QUIC connection failed: {}", e)))?;
+    let connection = connecting.await.map_err(|e| CodedError::new(ErrorCode::Http3Handshake, format!("QUIC handshake failed on UDP {}: {}", target.port(), e)))?;
+    result.handshake_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+
+    let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(connection.clone())).await.map_err(|e| CodedError::new(ErrorCode::Http3Protocol, format!("HTTP/3 setup failed: {}", e)))?;
+    let exchange = async {
+        let request = http::Request::get(url.as_str()).header(http::header::USER_AGENT, USER_AGENT).body(()).map_err(|e| e.to_string())?;
+        let sent = Instant::now();
+        let mut stream = send_request.send_request(request).await.map_err(|e| format!("HTTP/3 request failed: {}", e))?;
+        stream.finish().await.map_err(|e| format!("HTTP/3 request failed: {}", e))?;
+        let response = stream.recv_response().await.map_err(|e| format!("HTTP/3 response failed: {}", e))?;
+        result.ttfb_ms = Some(sent.elapsed().as_secs_f64() * 1000.0);
+        // Only the headers are compared; the body is left unread.
+        stream.stop_sending(h3::error::Code::H3_NO_ERROR);
+        Ok(response.status().as_u16())
+    };
+    let status = tokio::select! {
+        status = exchange => status,
+        closed = driver.wait_idle() => Err(format!("HTTP/3 connection closed: {}", closed)),
+    };
+    connection.close(0u32.into(), b"");
+    status.code(ErrorCode::Http3Protocol)
+}
+
+/// Port of the first `h3` (or draft `h3-NN`) alternative in an `Alt-Svc` value,
+/// e.g. `h3=":443"; ma=86400, h3-29=":443"`.
+fn h3_port(alt_svc: &str) -> Option<u16> {
+    alt_svc.split(',').find_map(|alternative| {
+        let (protocol, authority) = alternative.split(';').next()?.split_once('=')?;
+        let protocol = protocol.trim();
+        if protocol != "h3" && !protocol.starts_with("h3-") {
+            return None;
+        }
+        authority.trim().trim_matches('"').rsplit_once(':')?.1.parse().ok()
+    })
+}
@@ -0,0 +1,195 @@
+//! Optional ICMP echo (ping) stage.
+
+use super::error_code::ErrorCode;
+use super::source::SourceBinding;
+use serde::Serialize;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+pub(crate) const ECHO_REQUEST_V4: u8 = 8;
+pub(crate) const ECHO_REPLY_V4: u8 = 0;
+pub(crate) const ECHO_REQUEST_V6: u8 = 128;
+pub(crate) const ECHO_REPLY_V6: u8 = 129;
+
+/// Shown when neither an unprivileged ping socket nor a raw socket can be opened.
+pub(crate) const PRIVILEGE_HINT: &str = "ICMP needs raw-socket privileges. Run as root/Administrator, grant the capability with \
+`sudo setcap cap_net_raw+ep $(which netprobe)`, or allow unprivileged ping sockets with \
+`sudo sysctl -w net.ipv4.ping_group_range=\"0 2147483647\"`";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IcmpResult {
+    pub status: String, // "ok" | "error"
+    pub transmitted: u32,
+    pub received: u32,
+    pub loss_percent: f64,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_avg_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    /// DSCP the echo requests were marked with (`--dscp`, `--tos`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "ICMP_PERMISSION".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+}
+
+/// An ICMP socket plus whether the kernel strips the IP header for us.
+pub(crate) struct IcmpSocket {
+    pub socket: Socket,
+    /// Raw IPv4 sockets deliver the IP header; datagram ("ping") sockets do not.
+    pub includes_ip_header: bool,
+}
+
+/// Open an ICMP socket for `ip`'s family, preferring unprivileged datagram
+/// sockets and falling back to raw sockets.
+pub(crate) fn open_socket(ip: IpAddr) -> io::Result<IcmpSocket> {
+    let (domain, protocol) = match ip {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+    match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+        Ok(socket) => Ok(IcmpSocket { socket, includes_ip_header: false }),
+        Err(_) => match Socket::new(domain, Type::RAW, Some(protocol)) {
+            Ok(socket) => Ok(IcmpSocket { socket, includes_ip_header: ip.is_ipv4() }),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(io::Error::new(e.kind(), PRIVILEGE_HINT)),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+/// RFC 1071 internet checksum.
+pub(crate) fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data.chunks(2).map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32).sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Build an echo request. The ICMPv6 checksum is filled in by the kernel.
+pub(crate) fn echo_request(ip: IpAddr, ident: u16, seq: u16, payload_len: usize) -> Vec<u8> {
+    let kind = if ip.is_ipv4() { ECHO_REQUEST_V4 } else { ECHO_REQUEST_V6 };
+    let mut packet = vec![kind, 0, 0, 0];
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend((0..payload_len).map(|i| (i % 256) as u8));
+    if ip.is_ipv4() {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+/// Strip the IPv4 header (if present) and return the ICMP message.
+pub(crate) fn icmp_payload(buf: &[u8], includes_ip_header: bool) -> Option<&[u8]> {
+    if includes_ip_header {
+        let ihl = (*buf.first()? & 0x0f) as usize * 4;
+        buf.get(ihl..)
+    } else {
+        Some(buf)
+    }
+}
+
+/// Send `count` echo requests to `ip` from `source` and summarize the round-trip times.
+pub fn ping(ip: IpAddr, count: u32, timeout: Duration, source: &SourceBinding) -> IcmpResult {
+    let mut result = IcmpResult {
+        status: "error".to_string(),
+        transmitted: 0,
+        received: 0,
+        loss_percent: 100.0,
+        rtt_min_ms: None,
+        rtt_avg_ms: None,
+        rtt_max_ms: None,
+        dscp: None,
+        error: None,
+        error_code: None,
+    };
+
+    let icmp = match open_socket(ip) {
+        Ok(icmp) => icmp,
+        Err(e) => {
+            result.error_code = Some(if e.kind() == io::ErrorKind::PermissionDenied { ErrorCode::IcmpPermission } else { ErrorCode::IcmpLocal });
+            result.error = Some(e.to_string());
+            return result;
+        },
+    };
+    if let Err(e) = source.apply(&icmp.socket, ip) {
+        result.error_code = Some(ErrorCode::IcmpLocal);
+        result.error = Some(e.to_string());
+        return result;
+    }
+    result.dscp = source.dscp();
+    let target = SockAddr::from(SocketAddr::new(ip, 0));
+    let ident = std::process::id() as u16;
+    let reply_kind = if ip.is_ipv4() { ECHO_REPLY_V4 } else { ECHO_REPLY_V6 };
+
+    let mut rtts = Vec::new();
+    let mut last_error = None;
+    for seq in 0..count as u16 {
+        if seq > 0 {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+
+        let packet = echo_request(ip, ident, seq, 32);
+        let sent_at = Instant::now();
+        if let Err(e) = icmp.socket.send_to(&packet, &target) {
+            last_error = Some((ErrorCode::IcmpSocket, e.to_string()));
+            result.transmitted += 1;
+            continue;
+        }
+        result.transmitted += 1;
+
+        // Wait for the matching reply, ignoring unrelated ICMP traffic.
+        let mut buf = [0u8; 1500];
+        loop {
+            let remaining = timeout.saturating_sub(sent_at.elapsed());
+            if remaining.is_zero() {
+                last_error = Some((ErrorCode::IcmpTimeout, "Request timed out".to_string()));
+                break;
+            }
+            let _ = icmp.socket.set_read_timeout(Some(remaining));
+            let n = match (&icmp.socket).read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    last_error = Some((ErrorCode::IcmpTimeout, "Request timed out".to_string()));
+                    break;
+                },
+                Err(e) => {
+                    last_error = Some((ErrorCode::IcmpSocket, e.to_string()));
+                    break;
+                }
+            };
+            let Some(reply) = icmp_payload(&buf[..n], icmp.includes_ip_header) else { continue };
+            if reply.len() < 8 || reply[0] != reply_kind {
+                continue;
+            }
+            // Datagram sockets rewrite the identifier, so only raw sockets can check it.
+            let reply_seq = u16::from_be_bytes([reply[6], reply[7]]);
+            let reply_ident = u16::from_be_bytes([reply[4], reply[5]]);
+            if reply_seq == seq && (!icmp.includes_ip_header || reply_ident == ident) {
+                rtts.push(sent_at.elapsed().as_secs_f64() * 1000.0);
+                break;
+            }
+        }
+    }
+
+    result.received = rtts.len() as u32;
+    if result.transmitted > 0 {
+        result.loss_percent = (result.transmitted - result.received) as f64 * 100.0 / result.transmitted as f64;
+    }
+    if rtts.is_empty() {
+        let (code, error) = last_error.unwrap_or((ErrorCode::IcmpTimeout, "No reply".to_string()));
+        result.error_code = Some(code);
+        result.error = Some(error);
+    } else {
+        result.status = "ok".to_string();
+        result.rtt_min_ms = rtts.iter().copied().reduce(f64::min);
+        result.rtt_max_ms = rtts.iter().copied().reduce(f64::max);
+        result.rtt_avg_ms = Some(rtts.iter().sum::<f64>() / rtts.len() as f64);
+    }
+
+    result
+}
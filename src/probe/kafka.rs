@@ -0,0 +1,127 @@
+//! Kafka broker check (`--service kafka`, port 9092): one ApiVersions request.
+//!
+//! ApiVersions is the first request every client sends, before authenticating,
+//! and version 0 of it is answered by all brokers since 0.10. The reply lists
+//! each API the broker serves with its version range, which tells a client (and
+//! the reader) what the broker is able to do.
+
+use super::service::ServiceResult;
+use super::smtp::ms_since;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const API_VERSIONS: i16 = 18;
+const CORRELATION_ID: i32 = 0x6e70;
+const CLIENT_ID: &str = "netprobe";
+/// Largest response accepted; a broker's list takes about 600 bytes.
+const MAX_RESPONSE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KafkaResult {
+    /// APIs the broker serves, in the order it listed them.
+    pub api_versions: Vec<KafkaApi>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KafkaApi {
+    pub api_key: i16,
+    /// "Produce", "Fetch"...; "ApiKey 70" for keys newer than netprobe.
+    pub name: String,
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+/// Send ApiVersions (v0) and read the broker's list of APIs.
+pub(super) async fn probe<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, timeout: Duration) -> Result<(), String> {
+    // Request header v1: api key, api version, correlation id, client id; the v0 body is empty.
+    let mut request = Vec::new();
+    request.extend(API_VERSIONS.to_be_bytes());
+    request.extend(0i16.to_be_bytes());
+    request.extend(CORRELATION_ID.to_be_bytes());
+    request.extend((CLIENT_ID.len() as i16).to_be_bytes());
+    request.extend(CLIENT_ID.as_bytes());
+    let mut packet = (request.len() as i32).to_be_bytes().to_vec();
+    packet.extend(request);
+
+    let started = Instant::now();
+    stream.write_all(&packet).await.map_err(|e| e.to_string())?;
+    let response = tokio::time::timeout(timeout, read_response(stream)).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))??;
+    result.greeting_ms = Some(ms_since(started));
+
+    let mut reader = Reader(&response);
+    let not_kafka = || "Not a Kafka broker: invalid ApiVersions response".to_string();
+    if reader.i32().ok_or_else(not_kafka)? != CORRELATION_ID {
+        return Err(not_kafka());
+    }
+    let error_code = reader.i16().ok_or_else(not_kafka)?;
+    if error_code != 0 {
+        return Err(format!("Broker answered ApiVersions with error code {}", error_code));
+    }
+    let count = reader.i32().ok_or_else(not_kafka)?;
+    if count < 0 || count as usize * 6 > reader.0.len() {
+        return Err(not_kafka());
+    }
+    let mut api_versions = Vec::new();
+    for _ in 0..count {
+        let (api_key, min_version, max_version) = (reader.i16().ok_or_else(not_kafka)?, reader.i16().ok_or_else(not_kafka)?, reader.i16().ok_or_else(not_kafka)?);
+        let name = api_name(api_key).map_or_else(|| format!("ApiKey {}", api_key), str::to_string);
+        api_versions.push(KafkaApi { api_key, name, min_version, max_version });
+    }
+    result.banner = Some(format!("ApiVersions: {} APIs", api_versions.len()));
+    result.kafka = Some(KafkaResult { api_versions });
+    Ok(())
+}
+
+/// The response after its size prefix.
+async fn read_response<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, String> {
+    let closed = |_| "Connection closed by the server (not a Kafka broker, or a listener expecting TLS or SASL)".to_string();
+    let size = stream.read_i32().await.map_err(closed)?;
+    if size < 6 || size as usize > MAX_RESPONSE {
+        return Err(format!("Not a Kafka broker: response size {}", size));
+    }
+    let mut response = vec![0; size as usize];
+    stream.read_exact(&mut response).await.map_err(closed)?;
+    Ok(response)
+}
+
+/// Big-endian integers from the front of a response.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn i16(&mut self) -> Option<i16> {
+        let (value, rest) = self.0.split_first_chunk()?;
+        self.0 = rest;
+        Some(i16::from_be_bytes(*value))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        let (value, rest) = self.0.split_first_chunk()?;
+        self.0 = rest;
+        Some(i32::from_be_bytes(*value))
+    }
+}
+
+/// Name of an API key, as in the Kafka protocol guide.
+fn api_name(key: i16) -> Option<&'static str> {
+    const NAMES: [&str; 52] = [
+        "Produce", "Fetch", "ListOffsets", "Metadata", "LeaderAndIsr", "StopReplica", "UpdateMetadata", "ControlledShutdown", "OffsetCommit", "OffsetFetch",
+        "FindCoordinator", "JoinGroup", "Heartbeat", "LeaveGroup", "SyncGroup", "DescribeGroups", "ListGroups", "SaslHandshake", "ApiVersions", "CreateTopics",
+        "DeleteTopics", "DeleteRecords", "InitProducerId", "OffsetForLeaderEpoch", "AddPartitionsToTxn", "AddOffsetsToTxn", "EndTxn", "WriteTxnMarkers", "TxnOffsetCommit", "DescribeAcls",
+        "CreateAcls", "DeleteAcls", "DescribeConfigs", "AlterConfigs", "AlterReplicaLogDirs", "DescribeLogDirs", "SaslAuthenticate", "CreatePartitions", "CreateDelegationToken", "RenewDelegationToken",
+        "ExpireDelegationToken", "DescribeDelegationToken", "DeleteGroups", "ElectLeaders", "IncrementalAlterConfigs", "AlterPartitionReassignments", "ListPartitionReassignments", "OffsetDelete", "DescribeClientQuotas", "AlterClientQuotas",
+        "DescribeUserScramCredentials", "AlterUserScramCredentials",
+    ];
+    match key {
+        55 => Some("DescribeQuorum"),
+        56 => Some("AlterPartition"),
+        57 => Some("UpdateFeatures"),
+        60 => Some("DescribeCluster"),
+        61 => Some("DescribeProducers"),
+        65 => Some("DescribeTransactions"),
+        66 => Some("ListTransactions"),
+        67 => Some("AllocateProducerIds"),
+        68 => Some("ConsumerGroupHeartbeat"),
+        _ => usize::try_from(key).ok().and_then(|key| NAMES.get(key).copied()),
+    }
+}
@@ -0,0 +1,200 @@
+//! LDAP directory check (`--service ldap|ldaps`, ports 389 and 636): a bind and,
+//! on 389, the StartTLS extended operation (RFC 4511, 4513).
+//!
+//! The bind is anonymous unless `--auth-basic DN:PASSWORD` gives a simple bind.
+//! A password is never sent in clear: on 389 it is bound on a second connection
+//! upgraded with StartTLS, while the first one goes to the TLS stage, which
+//! reports on the certificate.
+
+use super::ber::{tlv, Reader, ENUMERATED, INTEGER, OCTET_STRING, SEQUENCE};
+use super::service::{self, ServiceResult};
+use super::smtp::ms_since;
+use super::{HttpAuth, ProbeOptions};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const BIND_REQUEST: u8 = 0x60;
+const BIND_RESPONSE: u8 = 0x61;
+const UNBIND_REQUEST: u8 = 0x42;
+const EXTENDED_REQUEST: u8 = 0x77;
+const EXTENDED_RESPONSE: u8 = 0x78;
+/// Context tag [0]: simple authentication in a bind, the request name in an extended operation.
+const CONTEXT_0: u8 = 0x80;
+const START_TLS_OID: &str = "1.3.6.1.4.1.1466.20037";
+/// Largest response accepted.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// Result code and diagnostic message of an LDAP response.
+struct Response {
+    code: u8,
+    message: String,
+}
+
+/// Bind, then StartTLS (port 389). True when the connection is ready for the TLS handshake.
+pub(super) async fn probe(stream: &mut TcpStream, addr: SocketAddr, host: &str, result: &mut ServiceResult, options: &ProbeOptions) -> Result<bool, String> {
+    let timeout = options.timeout;
+    let credentials = credentials(options);
+    // Anonymous binds go on this connection. StartTLS is still checked after a
+    // rejected bind; a bind left unanswered ends the probe.
+    let bound = match credentials {
+        None => bind(stream, result, None, 1, timeout).await,
+        Some(_) => Ok(()),
+    };
+    if bound.is_err() && result.banner.is_none() {
+        return bound.map(|_| false);
+    }
+    let offered = start_tls(stream, 2, timeout).await?;
+    result.starttls = Some(offered);
+    bound?;
+    if credentials.is_none() || !offered {
+        return match credentials {
+            Some(_) => Err("StartTLS not offered: the password is not sent in clear (try --service ldaps)".to_string()),
+            None => Ok(offered),
+        };
+    }
+
+    let mut second = match tokio::time::timeout(options.connect_timeout(), options.source.connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(e.to_string()),
+        Err(_) => return Err("Connection timed out".to_string()),
+    };
+    if !start_tls(&mut second, 1, timeout).await? {
+        return Err("StartTLS refused on the connection for the bind".to_string());
+    }
+    let mut tls = service::connect_tls(second, host, options).await?;
+    bind(&mut tls, result, credentials, 2, timeout).await?;
+    unbind(&mut tls, 3).await;
+    Ok(true)
+}
+
+/// Bind over implicit TLS (port 636).
+pub(super) async fn probe_tls<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, options: &ProbeOptions) -> Result<(), String> {
+    bind(stream, result, credentials(options), 1, options.timeout).await?;
+    unbind(stream, 2).await;
+    Ok(())
+}
+
+/// Bind DN and password of a simple bind.
+fn credentials(options: &ProbeOptions) -> Option<(&str, &str)> {
+    match &options.auth {
+        Some(HttpAuth::Basic { user, password }) => Some((user.as_str(), password.as_str())),
+        _ => None,
+    }
+}
+
+/// Simple bind, anonymous without `credentials`; its result code goes in `banner`, a failure is an error.
+async fn bind<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, credentials: Option<(&str, &str)>, id: u8, timeout: Duration) -> Result<(), String> {
+    let (dn, password) = credentials.unwrap_or_default();
+    let mut bind = tlv(INTEGER, &[3]);
+    bind.extend(tlv(OCTET_STRING, dn.as_bytes()));
+    bind.extend(tlv(CONTEXT_0, password.as_bytes()));
+    let started = Instant::now();
+    let response = exchange(stream, id, &tlv(BIND_REQUEST, &bind), BIND_RESPONSE, timeout).await?;
+    result.greeting_ms = Some(ms_since(started));
+    result.banner = Some(format!("{} {}", response.code, result_code(response.code)));
+    result.capabilities = vec![match credentials {
+        Some(_) => "bind=simple".to_string(),
+        None => "bind=anonymous".to_string(),
+    }];
+    match response.code {
+        0 => Ok(()),
+        code => Err(format!("Bind rejected: {} {}{}", code, result_code(code), diagnostic(&response.message))),
+    }
+}
+
+/// StartTLS extended operation; false when the server declines it.
+async fn start_tls<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, id: u8, timeout: Duration) -> Result<bool, String> {
+    let request = tlv(EXTENDED_REQUEST, &tlv(CONTEXT_0, START_TLS_OID.as_bytes()));
+    let response = exchange(stream, id, &request, EXTENDED_RESPONSE, timeout).await?;
+    Ok(response.code == 0)
+}
+
+async fn unbind<S: AsyncWrite + Unpin>(stream: &mut S, id: u8) {
+    let mut message = tlv(INTEGER, &[id]);
+    message.extend([UNBIND_REQUEST, 0]);
+    let _ = stream.write_all(&tlv(SEQUENCE, &message)).await;
+}
+
+/// Send `operation` as message `id` and read the response of type `expected`.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, id: u8, operation: &[u8], expected: u8, timeout: Duration) -> Result<Response, String> {
+    let mut message = tlv(INTEGER, &[id]);
+    message.extend(operation);
+    stream.write_all(&tlv(SEQUENCE, &message)).await.map_err(|e| e.to_string())?;
+    let reply = tokio::time::timeout(timeout, read_message(stream)).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))??;
+
+    let not_ldap = || "Not an LDAP server: invalid response".to_string();
+    let mut reader = Reader(&reply);
+    let (_, message_id) = reader.next().ok_or_else(not_ldap)?;
+    let (tag, op) = reader.next().ok_or_else(not_ldap)?;
+    let mut op = Reader(op);
+    let response = match (op.next(), op.next(), op.next()) {
+        (Some((ENUMERATED, [code])), Some((OCTET_STRING, _)), Some((OCTET_STRING, message))) => Response { code: *code, message: String::from_utf8_lossy(message).into_owned() },
+        _ => return Err(not_ldap()),
+    };
+    // Message 0 is an unsolicited notification: the server is closing the connection.
+    if message_id == [0] && tag == EXTENDED_RESPONSE {
+        return Err(format!("Server closed the connection: {} {}{}", response.code, result_code(response.code), diagnostic(&response.message)));
+    }
+    if message_id != [id] || tag != expected {
+        return Err(not_ldap());
+    }
+    Ok(response)
+}
+
+/// One LDAPMessage: the content of its outer SEQUENCE.
+async fn read_message<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, String> {
+    let closed = |_| "Connection closed by the server".to_string();
+    if stream.read_u8().await.map_err(closed)? != SEQUENCE {
+        return Err("Not an LDAP server: invalid response".to_string());
+    }
+    let length = match stream.read_u8().await.map_err(closed)? {
+        short @ 0..=0x7f => usize::from(short),
+        long @ 0x81..=0x84 => {
+            let mut length = 0;
+            for _ in 0..long & 0x7f {
+                length = length << 8 | usize::from(stream.read_u8().await.map_err(closed)?);
+            }
+            length
+        },
+        _ => return Err("Not an LDAP server: invalid response".to_string()),
+    };
+    if length > MAX_MESSAGE {
+        return Err(format!("Not an LDAP server: {} byte message", length));
+    }
+    let mut message = vec![0; length];
+    stream.read_exact(&mut message).await.map_err(closed)?;
+    Ok(message)
+}
+
+/// ": message" when the server gave one.
+fn diagnostic(message: &str) -> String {
+    match message.trim() {
+        "" => String::new(),
+        message => format!(": {}", message),
+    }
+}
+
+/// Name of an LDAP result code (RFC 4511, appendix A).
+fn result_code(code: u8) -> &'static str {
+    match code {
+        0 => "success",
+        1 => "operationsError",
+        2 => "protocolError",
+        3 => "timeLimitExceeded",
+        7 => "authMethodNotSupported",
+        8 => "strongerAuthRequired",
+        13 => "confidentialityRequired",
+        32 => "noSuchObject",
+        34 => "invalidDNSyntax",
+        48 => "inappropriateAuthentication",
+        49 => "invalidCredentials",
+        50 => "insufficientAccessRights",
+        51 => "busy",
+        52 => "unavailable",
+        53 => "unwillingToPerform",
+        80 => "other",
+        _ => "unknown result code",
+    }
+}
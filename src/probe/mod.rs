@@ -0,0 +1,1028 @@
+//! The probe pipeline: DNS resolution, TCP handshake, TLS handshake and HTTP request.
+//!
+//! Once the name is resolved, the stages that do not need each other's results run
+//! side by side: the TCP handshake, the TLS handshake and the HTTP request each make
+//! their own connection, while the DNS follow-ups and the ping go on alongside.
+
+pub mod auth;
+pub mod ber;
+pub mod caa;
+pub mod cdn;
+pub mod check;
+pub mod cookies;
+pub mod ct;
+pub mod database;
+pub mod dns;
+pub mod error_code;
+pub mod eyeballs;
+pub mod ftp;
+pub mod geo;
+pub mod grpc;
+pub mod http;
+pub mod http3;
+pub mod icmp;
+pub mod kafka;
+pub mod ldap;
+pub mod mqtt;
+pub mod ntp;
+pub mod proxy;
+pub mod quality;
+pub mod rdp;
+pub mod resumption;
+pub mod retry;
+pub mod revocation;
+pub mod security;
+pub mod service;
+pub mod sip;
+pub mod smb;
+pub mod smtp;
+pub mod snmp;
+pub mod source;
+pub mod ssh;
+pub mod stacks;
+pub mod syn;
+pub mod tcp;
+pub mod tls;
+pub mod udp;
+pub mod websocket;
+
+use crate::stats::ProbeStats;
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::Instrument;
+use url::Url;
+
+pub use auth::HttpAuth;
+pub use cdn::CdnResult;
+pub use check::{Assertion, JsonPathCheck, LatencyCheck, LatencyLimits, Regex, StatusPattern};
+pub use cookies::CookieJar;
+pub use ct::{CtLogList, CtResult};
+pub use dns::{DnsResult, ResolveOverride};
+pub use error_code::ErrorCode;
+pub use eyeballs::RaceResult;
+pub use geo::{GeoDb, GeoResult};
+pub use grpc::GrpcResult;
+pub use http::{HttpResult, HttpVersion};
+pub use http3::Http3Result;
+pub use icmp::IcmpResult;
+pub use ntp::NtpResult;
+pub use proxy::{Proxy, ProxySettings};
+pub use quality::QualityResult;
+pub use resumption::ResumptionResult;
+pub use retry::Attempt;
+pub use revocation::RevocationResult;
+pub use security::SecurityAudit;
+pub use service::{Service, ServiceResult};
+pub use source::SourceBinding;
+pub use stacks::StackComparison;
+pub use tcp::{PortRange, PortResult, TcpResult};
+pub use tls::{CaBundle, ClientIdentity, TlsResult};
+pub use udp::{UdpPayload, UdpResult};
+pub use websocket::WebSocketResult;
+pub use crate::resolver::wire::ClientSubnet;
+pub use crate::resolver::{IpFamily, Upstream};
+
+// --- JSON Data Structures ---
+// These structures ensure the JSON output is standardized and predictable.
+
+/// Version of the JSON layout of `ProbeResult`, raised when a field is removed,
+/// renamed or changes meaning; new fields alone do not raise it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    /// `SCHEMA_VERSION` of the report (`--schema` prints the schema).
+    pub schema_version: u32,
+    pub target: String,
+    pub timestamp: String,
+    /// Wall-clock time of the whole probe, all stages included.
+    pub duration_ms: f64,
+    pub dns: DnsResult,
+    /// Location and network owner of the resolved IP (`--geo`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geo: Option<GeoResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icmp: Option<IcmpResult>,
+    pub tcp: TcpResult,
+    /// Replaces the TCP, TLS and HTTP stages (`--udp`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udp: Option<UdpResult>,
+    pub tls: TlsResult,
+    pub http: HttpResult,
+    /// Replaces the HTTP request (`--ws`, `ws://` and `wss://` targets).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub websocket: Option<WebSocketResult>,
+    /// CDN / WAF in front of the target (`--detect-cdn`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cdn: Option<CdnResult>,
+    /// HTTP/3 advertisement and request (`--http3`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http3: Option<Http3Result>,
+    /// IPv4 vs IPv6 side by side (`--compare-stacks`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stacks: Option<StackComparison>,
+    /// Outcome of every `--expect-*` check.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub assertions: Vec<Assertion>,
+    /// Stage latencies against `--max-*` thresholds.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub latency_checks: Vec<LatencyCheck>,
+    /// Latency distribution over every sample (`--count`); the fields above are the last sample.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ProbeStats>,
+}
+
+// --- Options & Errors ---
+
+/// Knobs controlling a single probe run.
+#[derive(Debug, Clone)]
+pub struct ProbeOptions {
+    /// Timeout of each stage that has no timeout of its own below (and of ICMP and UDP probes).
+    pub timeout: Duration,
+    /// Budget of the DNS stage (system resolver or `dns_servers`); `None` uses `timeout`.
+    pub dns_timeout: Option<Duration>,
+    /// Budget of each TCP connect (including port scans); `None` uses `timeout`.
+    pub connect_timeout: Option<Duration>,
+    /// Budget of the TLS handshake and its follow-up checks (revocation, resumption); `None` uses `timeout`.
+    pub tls_timeout: Option<Duration>,
+    /// Budget of the HTTP request, redirects included; `None` uses `timeout`.
+    pub http_timeout: Option<Duration>,
+    /// Follow HTTP 3xx redirects instead of reporting them.
+    pub follow_redirects: bool,
+    /// HTTP method of the request stage; GET also downloads and measures the body.
+    pub method: hyper::Method,
+    /// HTTP version policy of the request stage (ALPN offer, prior knowledge).
+    pub http_version: HttpVersion,
+    /// Request body sent with the HTTP request.
+    pub body: Option<Vec<u8>>,
+    /// `Content-Type` of `body`; guessed (JSON or text) when unset.
+    pub content_type: Option<String>,
+    /// Extra request headers; they replace netprobe's defaults of the same name.
+    pub headers: Vec<(String, String)>,
+    /// Credentials of the HTTP stage (`--auth-basic`, `--auth-bearer`, `--auth-digest`).
+    pub auth: Option<HttpAuth>,
+    /// Cookies sent to the target host (`--cookie`).
+    pub cookies: Vec<(String, String)>,
+    /// Cookies loaded from a Netscape cookie file, sent wherever they match (`--cookie-file`).
+    pub cookie_jar: Option<CookieJar>,
+    /// Record response headers in `HttpResult.headers`.
+    pub capture_headers: bool,
+    /// Which response headers to record, e.g. `["server", "x-*", "!set-cookie"]`; empty keeps all.
+    pub header_filter: Vec<String>,
+    /// Keep the raw exchanges for `-v`: DNS answer records and the request headers sent.
+    pub verbose: bool,
+    /// Flag the TLS stage when the leaf certificate expires within this many days.
+    pub cert_warn_days: Option<i64>,
+    /// Number of ICMP echo requests to send after DNS; `None` skips the ping stage.
+    pub ping_count: Option<u32>,
+    /// DNS servers to query (plain, DoT or DoH), in order; empty means the system resolver.
+    pub dns_servers: Vec<Upstream>,
+    /// Resolve as a client in this network would (EDNS Client Subnet); queries the
+    /// system's nameservers directly when `dns_servers` is empty.
+    pub client_subnet: Option<ClientSubnet>,
+    /// Fixed addresses for `host:port` pairs, used instead of DNS by every stage (`--resolve`).
+    pub resolve: Vec<ResolveOverride>,
+    /// TLS server name sent and verified instead of the URL host (`--sni`).
+    pub sni: Option<String>,
+    /// CA certificates trusted instead of the bundled roots (`--cacert`).
+    pub ca_bundle: Option<CaBundle>,
+    /// Accept any server certificate; the TLS stage still reports what verification would say.
+    pub insecure: bool,
+    /// Report every certificate of the presented chain in `TlsResult.chain`.
+    pub cert_chain: bool,
+    /// Include each chain certificate PEM-encoded (implies `cert_chain`).
+    pub cert_pem: bool,
+    /// Look the leaf certificate up in its OCSP staple, OCSP responder or CRL.
+    pub check_revocation: bool,
+    /// Reconnect with the first handshake's session to test resumption and 0-RTT.
+    pub check_resumption: bool,
+    /// Report the leaf certificate's SCTs and fail the TLS stage when none is valid.
+    pub check_ct: bool,
+    /// Match the certificate's issuer against the host's CAA records; a CA they do not allow is a TLS warning.
+    pub check_caa: bool,
+    /// CT logs SCT signatures are verified against; without it SCTs are only listed.
+    pub ct_logs: Option<CtLogList>,
+    /// Client certificate presented when the server asks for one (mutual TLS).
+    pub client_identity: Option<ClientIdentity>,
+    /// Proxies of the HTTP stage; a proxied target skips the direct DNS, TCP and TLS stages.
+    pub proxy: ProxySettings,
+    /// TCP-probe every resolved address, not just the first.
+    pub all_ips: bool,
+    /// Race connections to the resolved addresses as browsers do (RFC 8305) and use the winner.
+    pub happy_eyeballs: bool,
+    /// Run the TCP, TLS and HTTP stages and the ping side by side. Off by default: their
+    /// connections to the target would overlap and skew each other's timings.
+    pub parallel: bool,
+    /// Additional ports to check on the resolved address, reported in `TcpResult.ports`.
+    pub ports: Vec<u16>,
+    /// Scan `ports` with raw SYNs instead of full connections (`--syn`, privileged).
+    pub syn: bool,
+    /// Connect this many more times to measure connect jitter and read `TCP_INFO` (`TcpResult.quality`).
+    pub tcp_quality: Option<u32>,
+    /// Read up to this many bytes the server sends right after the TCP handshake (`TcpResult.banner`).
+    pub banner: Option<usize>,
+    /// How long to wait for the banner to start.
+    pub banner_timeout: Duration,
+    /// Look up the PTR name of the resolved address.
+    pub reverse_dns: bool,
+    /// Validate the DNSSEC chain of trust of the address records.
+    pub dnssec: bool,
+    /// Resolve the target iteratively from the root servers and report each referral.
+    pub dns_trace: bool,
+    /// GeoIP/ASN databases used to enrich the resolved address; `None` skips enrichment.
+    pub geo: Option<Arc<GeoDb>>,
+    /// Restrict resolution and connections to one address family (`-4` / `-6`).
+    pub ip_family: Option<IpFamily>,
+    /// Local address and/or interface the probe's connections go out from (not DNS).
+    pub source: SourceBinding,
+    /// Additionally probe over IPv4 and IPv6 independently and compare them.
+    pub compare_stacks: bool,
+    /// Accepted HTTP statuses; any other status (or no response) fails the probe.
+    pub expect_status: Vec<StatusPattern>,
+    /// Text the response body must contain.
+    pub expect_body_contains: Vec<String>,
+    /// Patterns the response body must match.
+    pub expect_body_regex: Vec<Regex>,
+    /// Checks on the response body parsed as JSON.
+    pub expect_json_path: Vec<JsonPathCheck>,
+    /// Per-stage and overall latency budgets.
+    pub latency_limits: LatencyLimits,
+    /// Extra tries for a failed DNS, TCP or HTTP stage.
+    pub retries: u32,
+    /// Pause before the first retry; doubled before each following one.
+    pub retry_backoff: Duration,
+    /// Fingerprint the CDN / WAF from headers, CNAME chain and address ranges.
+    pub detect_cdn: bool,
+    /// Grade the response's security headers in `HttpResult.security`.
+    pub security_audit: bool,
+    /// Replay the final request on the same connection to compare cold and warm latency.
+    pub warm: bool,
+    /// Look for HTTP/3: an `h3` Alt-Svc advertisement and a QUIC listener on UDP.
+    pub http3: bool,
+    /// Make the HTTP request a gRPC health check of this service (empty for the whole server),
+    /// reported in `HttpResult.grpc`; implies HTTP/2.
+    pub grpc: Option<String>,
+    /// Make the HTTP request a WebSocket upgrade, reported in `ProbeResult.websocket`;
+    /// `ws://` and `wss://` targets imply it.
+    pub websocket: bool,
+    /// Send a ping frame once upgraded and time its pong.
+    pub websocket_ping: bool,
+    /// Probe over UDP with this payload instead of running TCP, TLS and HTTP.
+    pub udp: Option<UdpPayload>,
+    /// Greet this service after the TCP handshake instead of sending HTTP (or query
+    /// it over UDP, for NTP); `None` picks one from the port of bare `host:port` targets (22, 25, 123...).
+    pub service: Option<Service>,
+    /// Also try an anonymous login on FTP servers, reported in `ServiceResult.ftp_login`.
+    pub ftp_anonymous: bool,
+    /// Community of SNMP requests (`--service snmp`, `--udp snmp`).
+    pub snmp_community: String,
+    /// Number of times the whole probe is repeated; above 1, `ProbeResult.stats` is filled.
+    pub count: u32,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        ProbeOptions {
+            timeout: Duration::from_secs(5),
+            dns_timeout: None,
+            connect_timeout: None,
+            tls_timeout: None,
+            http_timeout: None,
+            follow_redirects: false,
+            method: hyper::Method::HEAD,
+            http_version: HttpVersion::Negotiate,
+            body: None,
+            content_type: None,
+            headers: Vec::new(),
+            auth: None,
+            cookies: Vec::new(),
+            cookie_jar: None,
+            capture_headers: true,
+            header_filter: Vec::new(),
+            verbose: false,
+            cert_warn_days: None,
+            ping_count: None,
+            dns_servers: Vec::new(),
+            client_subnet: None,
+            resolve: Vec::new(),
+            sni: None,
+            proxy: ProxySettings::default(),
+            ca_bundle: None,
+            insecure: false,
+            cert_chain: false,
+            cert_pem: false,
+            check_revocation: false,
+            check_resumption: false,
+            check_ct: false,
+            check_caa: false,
+            ct_logs: None,
+            client_identity: None,
+            all_ips: false,
+            happy_eyeballs: false,
+            parallel: false,
+            ports: Vec::new(),
+            syn: false,
+            tcp_quality: None,
+            banner: None,
+            banner_timeout: Duration::from_secs(2),
+            reverse_dns: false,
+            dnssec: false,
+            dns_trace: false,
+            geo: None,
+            ip_family: None,
+            source: SourceBinding::default(),
+            compare_stacks: false,
+            expect_status: Vec::new(),
+            expect_body_contains: Vec::new(),
+            expect_body_regex: Vec::new(),
+            expect_json_path: Vec::new(),
+            latency_limits: LatencyLimits::default(),
+            retries: 0,
+            retry_backoff: Duration::from_secs(1),
+            detect_cdn: false,
+            security_audit: false,
+            warm: false,
+            http3: false,
+            grpc: None,
+            websocket: false,
+            websocket_ping: false,
+            udp: None,
+            service: None,
+            ftp_anonymous: false,
+            snmp_community: snmp::DEFAULT_COMMUNITY.to_string(),
+            count: 1,
+        }
+    }
+}
+
+impl ProbeOptions {
+    /// True when an assertion needs the response body to be kept.
+    pub fn needs_body(&self) -> bool {
+        !(self.expect_body_contains.is_empty() && self.expect_body_regex.is_empty() && self.expect_json_path.is_empty())
+    }
+
+    /// Budget of the DNS stage.
+    pub fn dns_timeout(&self) -> Duration {
+        self.dns_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Budget of a TCP connect.
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Budget of the TLS handshake.
+    pub fn tls_timeout(&self) -> Duration {
+        self.tls_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Budget of the HTTP request.
+    pub fn http_timeout(&self) -> Duration {
+        self.http_timeout.unwrap_or(self.timeout)
+    }
+
+    /// HTTP version policy of the request stage: HTTP/2 only for gRPC.
+    pub fn http_version(&self) -> HttpVersion {
+        if self.grpc.is_some() { HttpVersion::Http2 } else { self.http_version }
+    }
+}
+
+/// Errors that prevent a probe from starting at all.
+/// Stage failures (DNS, TCP, HTTP) are reported inside `ProbeResult` instead.
+#[derive(Debug)]
+pub enum ProbeError {
+    InvalidTarget(url::ParseError),
+    /// The traceroute could not run (DNS failure, missing raw-socket privileges...).
+    Trace(String),
+    /// Path MTU discovery could not run (DNS failure, no answer, missing raw-socket privileges...).
+    Mtu(String),
+    /// A sweep target that is not a usable CIDR block.
+    InvalidNetwork(String),
+    /// The task running the probe panicked or was cancelled.
+    Task(String),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::InvalidTarget(e) => write!(f, "Invalid URL format: {}", e),
+            ProbeError::Trace(e) => write!(f, "Traceroute failed: {}", e),
+            ProbeError::Mtu(e) => write!(f, "Path MTU discovery failed: {}", e),
+            ProbeError::InvalidNetwork(e) => write!(f, "Invalid network: {}", e),
+            ProbeError::Task(e) => write!(f, "Probe task failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+// --- Prober ---
+
+/// Entry point of the library: runs every stage against a target.
+pub struct Prober;
+
+impl Prober {
+    /// Probe `target` (URL, domain, or IP with optional port) layer by layer,
+    /// `options.count` times in a row.
+    pub async fn run(target: &str, options: &ProbeOptions) -> Result<ProbeResult, ProbeError> {
+        Prober::run_tracked(target, options, None).await
+    }
+
+    /// Like `run`, keeping `progress` up to date as the stages complete.
+    pub async fn run_tracked(target: &str, options: &ProbeOptions, progress: Option<&Progress>) -> Result<ProbeResult, ProbeError> {
+        // Every internal event of the probe carries its target (`--log-level`).
+        Prober::run_counted(target, options, progress).instrument(tracing::info_span!("probe", target = target)).await
+    }
+
+    async fn run_counted(target: &str, options: &ProbeOptions, progress: Option<&Progress>) -> Result<ProbeResult, ProbeError> {
+        if options.count <= 1 {
+            return Prober::run_sample(target, options, progress).await;
+        }
+
+        let mut samples = Vec::with_capacity(options.count as usize);
+        for sample in 1..=options.count {
+            tracing::debug!(sample, count = options.count, "taking sample");
+            samples.push(Prober::run_sample(target, options, progress).await?);
+        }
+        let stats = ProbeStats::from_results(&samples);
+        let mut result = samples.pop().expect("count is at least 2");
+        result.stats = Some(stats);
+        Ok(result)
+    }
+
+    /// One probe of `target`, with the dual-stack comparison when enabled.
+    async fn run_sample(target: &str, options: &ProbeOptions, progress: Option<&Progress>) -> Result<ProbeResult, ProbeError> {
+        if !options.compare_stacks {
+            return Prober::run_once(target, options, progress).await;
+        }
+
+        let family_options = |family| ProbeOptions { ip_family: Some(family), compare_stacks: false, ..options.clone() };
+        let (v4_options, v6_options) = (family_options(IpFamily::V4), family_options(IpFamily::V6));
+        let (main, v4, v6) = tokio::join!(
+            Prober::run_once(target, options, progress),
+            Prober::run_once(target, &v4_options, None),
+            Prober::run_once(target, &v6_options, None)
+        );
+
+        let mut result = main?;
+        result.stacks = Some(StackComparison { ipv4: (&v4?).into(), ipv6: (&v6?).into() });
+        Ok(result)
+    }
+
+    /// One pass through every stage, without the dual-stack comparison.
+    async fn run_once(target: &str, options: &ProbeOptions, progress: Option<&Progress>) -> Result<ProbeResult, ProbeError> {
+        let started = Instant::now();
+        let target_input = normalize_target(target);
+        let url = parse_target(target)?;
+
+        let host = url.host_str().unwrap_or("").to_string();
+        // Default ports: 443 for https, 80 for http, or use specified port
+        let port = url.port_or_known_default().unwrap_or(443);
+
+        let mut probe_data = ProbeResult {
+            schema_version: SCHEMA_VERSION,
+            target: target_input.clone(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            duration_ms: 0.0,
+            dns: DnsResult::pending(),
+            geo: None,
+            icmp: None,
+            tcp: TcpResult::pending(port),
+            udp: None,
+            tls: if matches!(url.scheme(), "https" | "wss") { TlsResult::pending() } else { TlsResult::skipped() },
+            http: HttpResult::pending(),
+            websocket: None,
+            cdn: None,
+            http3: None,
+            stacks: None,
+            assertions: Vec::new(),
+            latency_checks: Vec::new(),
+            stats: None,
+        };
+
+        // Only for bare `host:port` targets or `--service`: an explicit scheme (or `--ws`) asks for HTTP on that port.
+        let service = options.service.or_else(|| if target.contains("://") || options.websocket { None } else { Service::for_port(port) });
+        // UDP services are probed like `--udp` with their payload.
+        let udp = service.and_then(Service::udp_payload).or_else(|| options.udp.clone()).map(|payload| payload.resolve(port, &options.snmp_community));
+        let websocket = options.websocket || matches!(url.scheme(), "ws" | "wss");
+        let checkpoint = |probe_data: &ProbeResult, http_pending: bool| {
+            if let Some(progress) = progress {
+                progress.update(probe_data, started, http_pending);
+            }
+        };
+        let http_expected = udp.is_none() && service.is_none() && !websocket;
+        checkpoint(&probe_data, http_expected);
+
+        // --- STEP 1: DNS Resolution ---
+        // Through a proxy, the HTTP stage is the only path to the target: the direct stages are skipped.
+        let resolved = if udp.is_none() && options.proxy.for_url(&url).is_some() {
+            probe_data.dns = DnsResult::skipped();
+            probe_data.tcp = TcpResult::skipped(port);
+            probe_data.tls = TlsResult::skipped();
+            Vec::new()
+        } else {
+            let ((dns, resolved), attempts) = retry::run(options, "dns", || dns::resolve(&host, port, options), |(dns, _)| dns.error.clone()).await;
+            probe_data.dns = dns;
+            probe_data.dns.attempts = attempts;
+            resolved
+        };
+        // --- Optional: Happy Eyeballs race, whose winner every following stage uses ---
+        let race = match options.happy_eyeballs && udp.is_none() && !resolved.is_empty() {
+            true => Some(eyeballs::race(&resolved, options.connect_timeout(), &options.source).await),
+            false => None,
+        };
+        let winner = race.as_ref().and_then(|race| resolved.iter().find(|addr| race.winner_ip.as_deref() == Some(&addr.ip().to_string()))).copied();
+        if let Some(addr) = winner {
+            probe_data.dns.ip = Some(addr.ip().to_string());
+            probe_data.dns.family = Some(IpFamily::of(addr.ip()).name().to_string());
+        }
+        let resolved_ip = winner.or_else(|| resolved.first().copied());
+        if let (Some(ip), Some(db)) = (resolved_ip, &options.geo) {
+            probe_data.geo = Some(db.lookup(ip.ip()));
+        }
+        checkpoint(&probe_data, http_expected);
+
+        // From here on, the DNS follow-ups run side by side with each other and with the rest:
+        // they go to the DNS servers, not the target. The ping and the connection stages below
+        // reach the target, so they run one after the other unless `--parallel`.
+        let sequential = !options.parallel;
+        // An address literal or a --resolve override has no records to validate or trace.
+        let domain = probe_data.dns.transport != "override" && matches!(url.host(), Some(url::Host::Domain(_)));
+        // Also when the lookup failed: the trace shows where the chain of referrals breaks.
+        let dns_trace = options.dns_trace && domain && !matches!(probe_data.dns.status.as_str(), "skipped" | "pending");
+        let dns_ok = probe_data.dns.status == "ok";
+        let lookups = async {
+            tokio::join!(
+                async {
+                    match (resolved_ip, options.reverse_dns) {
+                        (Some(ip), true) => dns::ptr(ip.ip(), options).await,
+                        _ => None,
+                    }
+                },
+                async {
+                    match (resolved_ip, options.dnssec && domain) {
+                        (Some(ip), true) => Some(dns::dnssec(&host, ip.ip(), options).await),
+                        _ => None,
+                    }
+                },
+                async {
+                    match dns_trace {
+                        true => Some(dns::trace(&host, resolved_ip.map(|ip| ip.ip()), options).await),
+                        false => None,
+                    }
+                },
+            )
+        };
+
+        // --- Optional: ICMP Echo ---
+        let ping = async {
+            let (Some(ip), Some(count)) = (resolved_ip, options.ping_count) else {
+                return None;
+            };
+            let (timeout, source) = (options.timeout, options.source.clone());
+            tokio::task::spawn_blocking(move || icmp::ping(ip.ip(), count, timeout, &source)).await.ok()
+        };
+
+        let stages = async {
+            // --- Optional: UDP instead of TCP/TLS/HTTP ---
+            if let Some(payload) = &udp {
+                probe_data.tcp = TcpResult::skipped(port);
+                probe_data.tls = TlsResult::skipped();
+                if let Some(ip) = resolved_ip {
+                    probe_data.udp = Some(udp::probe(ip, payload, options.timeout, &options.source).await);
+                }
+                return;
+            }
+
+            // --- STEPS 2 to 4: TCP handshake, TLS handshake and HTTP request ---
+            // Each stage makes its own connection, one after the other so that no handshake
+            // overlaps another (side by side with `--parallel`). The TLS handshake only counts
+            // if the TCP one succeeded. The HTTP request is made even if TCP fails, just in
+            // case of weird proxy setups, though usually it will fail there too.
+            let tcp = async {
+                let ip = resolved_ip?;
+                let banner = options.banner.map(|max| (max, options.banner_timeout));
+                Some(retry::run(options, "tcp", || tcp::connect(ip, options.connect_timeout(), banner, &options.source), |tcp| tcp.error.clone()).await)
+            };
+            let all_ips = async {
+                match (resolved_ip, options.all_ips) {
+                    (Some(_), true) => Some(tcp::connect_all(&resolved, options.connect_timeout(), &options.source).await),
+                    _ => None,
+                }
+            };
+            let tls = async {
+                match (resolved_ip, url.scheme(), service) {
+                    (Some(ip), "https" | "wss", None) => {
+                        // Offer what the HTTP stage will, so `alpn` shows what it gets; WebSocket upgrades need HTTP/1.1.
+                        let alpn = if websocket { HttpVersion::Http1.alpn_protocols() } else { options.http_version().alpn_protocols() };
+                        Some(tls::handshake(ip, options.sni.as_deref().unwrap_or(&host), alpn, options).await)
+                    },
+                    _ => None,
+                }
+            };
+            let http = async {
+                if service.is_some() || websocket {
+                    return None;
+                }
+                // Pin the target to the address the earlier stages used, so every stage reports on the same server.
+                let mut http_options = options.clone();
+                if let Some(ip) = resolved_ip {
+                    http_options.resolve.push(ResolveOverride { host: host.clone(), port, ip: ip.ip() });
+                }
+                Some(retry::run(options, "http", || http::request(&target_input, &http_options), |http| http.error.clone()).await)
+            };
+            let cname_chain = async {
+                match url.host() {
+                    Some(url::Host::Domain(domain)) if options.detect_cdn && service.is_none() && !websocket && dns_ok => dns::cname_chain(domain, options).await,
+                    _ => Vec::new(),
+                }
+            };
+            // The CNAME chain is a DNS lookup: it runs alongside the connection stages.
+            let ((tcp, all_ips), ((tls, http), cname_chain)) = both(sequential, both(sequential, tcp, all_ips), async { tokio::join!(both(sequential, Box::pin(tls), Box::pin(http)), cname_chain) }).await;
+
+            match tcp {
+                Some((tcp, attempts)) => {
+                    probe_data.tcp = tcp;
+                    probe_data.tcp.attempts = attempts;
+                    probe_data.tcp.happy_eyeballs = race;
+                    probe_data.tcp.all_ips = all_ips;
+                },
+                // No address to connect to.
+                None => probe_data.tcp = TcpResult::skipped(port),
+            }
+            let connected = probe_data.tcp.status == "ok";
+            match (tls, connected) {
+                (Some(tls), true) => probe_data.tls = tls,
+                // No connection to shake hands over: the report must not be left "pending".
+                _ if probe_data.tls.status == "pending" => probe_data.tls = TlsResult::skipped(),
+                _ => {},
+            }
+            if let Some((http, attempts)) = http {
+                probe_data.http = http;
+                probe_data.http.attempts = attempts;
+            }
+            checkpoint(&probe_data, false);
+
+            // Bulk work comes after, so that its traffic does not weigh on the timings above.
+            if let (Some(ip), true) = (resolved_ip, !options.ports.is_empty()) {
+                probe_data.tcp.ports = match options.syn {
+                    true => {
+                        let (ports, timeout, source) = (options.ports.clone(), options.connect_timeout(), options.source.clone());
+                        let scan = tokio::task::spawn_blocking(move || syn::scan(ip.ip(), &ports, timeout, &source)).await;
+                        match scan.map_err(|e| e.to_string()).and_then(|scan| scan.map_err(|e| e.to_string())) {
+                            Ok(ports) => ports,
+                            // The scan could not start: every port is unknown, for that reason.
+                            Err(e) => options.ports.iter().map(|&port| PortResult { port, status: "filtered".to_string(), latency_ms: None, error: Some(e.clone()) }).collect(),
+                        }
+                    },
+                    false => tcp::scan_ports(ip.ip(), &options.ports, options.connect_timeout(), &options.source).await,
+                };
+            }
+            if let (Some(ip), Some(count), true) = (resolved_ip, options.tcp_quality, connected) {
+                probe_data.tcp.quality = Some(quality::measure(ip, count, options.connect_timeout(), &options.source).await);
+            }
+
+            // --- Services (mail, SSH, databases): greeting instead of HTTP ---
+            if let Some(service) = service {
+                probe_data.tls = TlsResult::skipped();
+                if let (Some(ip), true) = (resolved_ip, connected) {
+                    let sni = options.sni.as_deref().unwrap_or(&host);
+                    if service.implicit_tls() {
+                        probe_data.tls = tls::handshake(ip, sni, service.alpn(), options).await;
+                    }
+                    probe_data.tcp.service = Some(if probe_data.tls.status == "error" {
+                        ServiceResult::skipped(service)
+                    } else {
+                        let (greeting, upgraded) = service::probe(ip, sni, service, options).await;
+                        match (upgraded, greeting.starttls) {
+                            (Some(handshake), _) => probe_data.tls = handshake,
+                            (None, Some(false)) if service.requires_starttls() => probe_data.tls = TlsResult::failed(ErrorCode::TlsNoStarttls, "STARTTLS not offered by the server".to_string()),
+                            _ => {},
+                        }
+                        greeting
+                    });
+                }
+                return;
+            }
+
+            // --- WebSocket: upgrade instead of the HTTP request ---
+            if websocket {
+                if let (Some(ip), true, false) = (resolved_ip, connected, probe_data.tls.status == "error") {
+                    probe_data.websocket = Some(websocket::probe(ip, &url, options).await);
+                }
+                return;
+            }
+
+            // --- HTTP follow-ups ---
+            if let (Some(ip), true) = (resolved_ip, options.http3) {
+                probe_data.http3 = Some(Box::pin(http3::probe(ip, &url, &probe_data.http, options)).await);
+            }
+            if options.detect_cdn {
+                probe_data.cdn = Some(cdn::detect(probe_data.http.response_headers.as_ref(), cname_chain, resolved_ip.map(|ip| ip.ip())));
+            }
+            probe_data.assertions = check::evaluate(&probe_data, options);
+        };
+
+        let ((ptr, dnssec, trace), (icmp, ())) = tokio::join!(Box::pin(lookups), both(sequential, ping, Box::pin(stages)));
+        probe_data.dns.ptr = ptr;
+        probe_data.dns.dnssec = dnssec;
+        probe_data.dns.trace = trace;
+        probe_data.icmp = icmp;
+
+        probe_data.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+        probe_data.latency_checks = check::latency_checks(&probe_data, options);
+        tracing::info!(duration_ms = probe_data.duration_ms, exit_code = probe_data.exit_code(), "probe finished");
+        Ok(probe_data)
+    }
+
+    /// Probe several targets concurrently, with at most `concurrency` probes in flight.
+    /// Results come back in the same order as `targets`.
+    pub async fn run_many(targets: &[String], options: &ProbeOptions, concurrency: usize) -> Vec<Result<ProbeResult, ProbeError>> {
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+
+        let handles: Vec<_> = targets
+            .iter()
+            .cloned()
+            .map(|target| {
+                let semaphore = semaphore.clone();
+                let options = options.clone();
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await.expect("semaphore closed");
+                    Prober::run(&target, &options).await
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(handle.await.unwrap_or_else(|e| Err(ProbeError::Task(e.to_string()))));
+        }
+        results
+    }
+
+    /// Like `run_many`, but yields each `(target, outcome)` pair as soon as its probe
+    /// completes, so callers can stream output for long target lists. With `rate`
+    /// (see `pacer` for its range), at most that many probes start per second.
+    pub fn run_stream(targets: Vec<String>, options: ProbeOptions, concurrency: usize, rate: Option<f64>) -> ProbeStream {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let starts = Arc::new(Mutex::new(Starts::default()));
+        let in_flight = Arc::new(Mutex::new(Vec::new()));
+
+        let driver = tokio::spawn({
+            let (targets, tx, starts, in_flight) = (targets.clone(), tx.clone(), starts.clone(), in_flight.clone());
+            async move {
+                let mut pacing = rate.and_then(pacer);
+                for target in targets {
+                    if let Some(pacing) = pacing.as_mut() {
+                        pacing.tick().await;
+                    }
+                    let permit = semaphore.clone().acquire_owned().await.expect("semaphore closed");
+                    // Nobody is listening any more (Ctrl-C): start nothing new.
+                    if tx.is_closed() {
+                        break;
+                    }
+                    starts.lock().unwrap().record(Instant::now());
+                    let progress = Progress::default();
+                    in_flight.lock().unwrap().push(progress.clone());
+                    let probe = tokio::spawn({
+                        let (target, options, progress) = (target.clone(), options.clone(), progress.clone());
+                        async move { Prober::run_tracked(&target, &options, Some(&progress)).await }
+                    });
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        let _permit = permit;
+                        let outcome = probe.await.unwrap_or_else(|e| Err(ProbeError::Task(e.to_string())));
+                        progress.finish();
+                        // The receiver may have been dropped; nothing left to report to.
+                        let _ = tx.send((target, outcome));
+                    });
+                }
+            }
+        });
+        // Should the driver itself fail, the targets it did not start fail with it rather than vanish.
+        tokio::spawn({
+            let starts = starts.clone();
+            async move {
+                if let Err(e) = driver.await {
+                    let started = starts.lock().unwrap().count;
+                    for target in targets.into_iter().skip(started) {
+                        let _ = tx.send((target, Err(ProbeError::Task(e.to_string()))));
+                    }
+                }
+            }
+        });
+
+        ProbeStream { rx, starts, in_flight }
+    }
+}
+
+/// Outcomes of `Prober::run_stream`, in the order the probes complete.
+pub struct ProbeStream {
+    rx: mpsc::UnboundedReceiver<(String, Result<ProbeResult, ProbeError>)>,
+    starts: Arc<Mutex<Starts>>,
+    /// One per started probe, emptied when it reports.
+    in_flight: Arc<Mutex<Vec<Progress>>>,
+}
+
+impl ProbeStream {
+    /// The next `(target, outcome)` pair; `None` once every started probe has reported.
+    pub async fn recv(&mut self) -> Option<(String, Result<ProbeResult, ProbeError>)> {
+        self.rx.recv().await
+    }
+
+    /// Probes started per second so far, from the first start to the last;
+    /// `None` until two have started.
+    pub fn start_rate(&self) -> Option<f64> {
+        self.starts.lock().unwrap().rate()
+    }
+
+    /// What the probes still running have got so far (see `Progress::interrupted`), in the order they started.
+    pub fn interrupted(&self) -> Vec<ProbeResult> {
+        self.in_flight.lock().unwrap().iter().filter_map(Progress::interrupted).collect()
+    }
+}
+
+/// The state of a probe as its stages complete, to report what it got should it be cut short (Ctrl-C).
+#[derive(Debug, Clone, Default)]
+pub struct Progress(Arc<Mutex<Option<Partial>>>);
+
+#[derive(Debug)]
+struct Partial {
+    result: ProbeResult,
+    started: Instant,
+    /// The HTTP request is yet to be stored in `result`.
+    http_pending: bool,
+}
+
+impl Progress {
+    fn update(&self, result: &ProbeResult, started: Instant, http_pending: bool) {
+        *self.0.lock().unwrap() = Some(Partial { result: result.clone(), started, http_pending });
+    }
+
+    fn finish(&self) {
+        self.0.lock().unwrap().take();
+    }
+
+    /// The result so far, with the stages that had not finished marked "interrupted";
+    /// `None` if the probe has not started or is over.
+    pub fn interrupted(&self) -> Option<ProbeResult> {
+        let partial = self.0.lock().unwrap();
+        let Partial { result, started, http_pending } = partial.as_ref()?;
+        let mut result = result.clone();
+        for status in [&mut result.dns.status, &mut result.tcp.status, &mut result.tls.status] {
+            if status == "pending" {
+                *status = "interrupted".to_string();
+            }
+        }
+        if *http_pending {
+            result.http.error = Some("Interrupted before the response".to_string());
+        }
+        result.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+        Some(result)
+    }
+}
+
+/// When the probes of a run started.
+#[derive(Debug, Default)]
+pub(crate) struct Starts {
+    first: Option<Instant>,
+    last: Option<Instant>,
+    count: usize,
+}
+
+impl Starts {
+    pub(crate) fn record(&mut self, at: Instant) {
+        self.first.get_or_insert(at);
+        self.last = Some(at);
+        self.count += 1;
+    }
+
+    /// N starts span N - 1 intervals.
+    pub(crate) fn rate(&self) -> Option<f64> {
+        let span = self.last?.duration_since(self.first?).as_secs_f64();
+        (self.count > 1 && span > 0.0).then(|| (self.count - 1) as f64 / span)
+    }
+}
+
+/// Slowest `--rate` accepted: one start a day.
+pub const MIN_RATE: f64 = 1.0 / 86_400.0;
+/// Fastest `--rate` accepted, per second; above it pacing means nothing next to the scheduler's own latency.
+pub const MAX_RATE: f64 = 1_000_000.0;
+
+/// Ticks `rate` times per second. A start held up by the concurrency limit delays the
+/// next ones rather than letting them burst to catch up. `None` when `rate` gives no
+/// usable period: not positive, or so high or so low that the period rounds to zero
+/// or overflows; `MIN_RATE..=MAX_RATE` is always usable.
+pub(crate) fn pacer(rate: f64) -> Option<tokio::time::Interval> {
+    let period = Duration::try_from_secs_f64(1.0 / rate).ok().filter(|period| !period.is_zero())?;
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    Some(interval)
+}
+
+/// `a` and `b` side by side, or one after the other when `sequential`.
+async fn both<A: Future, B: Future>(sequential: bool, a: A, b: B) -> (A::Output, B::Output) {
+    match sequential {
+        true => (a.await, b.await),
+        false => tokio::join!(a, b),
+    }
+}
+
+impl ProbeResult {
+    /// True when no stage failed and the HTTP request got a non-error status (< 400)
+    /// and, with `--grpc`, a SERVING answer; or, with `--udp`, when the datagram got a reply;
+    /// or, with `--ws`, when the upgrade succeeded.
+    pub fn is_success(&self) -> bool {
+        if let Some(udp) = &self.udp {
+            return self.dns.status != "error" && udp.status == "ok";
+        }
+        let stage_failed = [self.dns.status.as_str(), self.tcp.status.as_str(), self.tls.status.as_str()].contains(&"error")
+            || self.icmp.as_ref().is_some_and(|icmp| icmp.status == "error");
+        if let Some(websocket) = &self.websocket {
+            return !stage_failed && websocket.status == "ok";
+        }
+        let grpc_failed = self.http.grpc.as_ref().is_some_and(|grpc| grpc.status == "error");
+        !stage_failed && !grpc_failed && self.http.status_code.is_some_and(|code| code < 400)
+    }
+
+    /// The `exit::*` code describing the most fundamental failure, or `exit::OK`.
+    pub fn exit_code(&self) -> i32 {
+        let request_failed = match &self.websocket {
+            Some(websocket) => websocket.status == "error",
+            None => (self.http.status_code.is_none() && self.tcp.service.is_none()) || self.http.grpc.as_ref().is_some_and(|grpc| grpc.status == "error"),
+        };
+        if self.dns.status == "error" || self.dns.dnssec.as_ref().is_some_and(|dnssec| dnssec.status == "bogus") {
+            exit::DNS
+        } else if self.tcp.status == "error" || self.udp.as_ref().is_some_and(|udp| udp.status == "error") {
+            exit::TCP
+        } else if self.udp.is_some() {
+            exit::OK
+        } else if self.tls.status == "error" {
+            exit::TLS
+        } else if self.tcp.service.as_ref().is_some_and(|service| service.status == "error") {
+            exit::TCP
+        } else if request_failed {
+            exit::HTTP
+        } else if self.assertions.iter().any(|a| !a.passed) {
+            exit::ASSERTION
+        } else if self.tls.warning.is_some() {
+            exit::CERT_EXPIRY
+        } else if self.latency_checks.iter().any(|c| c.status == "degraded") {
+            exit::DEGRADED
+        } else {
+            exit::OK
+        }
+    }
+}
+
+// --- Exit Codes ---
+
+/// Process exit codes of `netprobe`, one per failing layer so scripts can tell them apart.
+pub mod exit {
+    pub const OK: i32 = 0;
+    /// Bad input, unreadable file, or a probe that could not start at all.
+    pub const ERROR: i32 = 1;
+    /// Invalid command line (reported by the argument parser).
+    pub const USAGE: i32 = 2;
+    /// DNS lookup failed, or the answer is DNSSEC-bogus (`--dnssec`).
+    pub const DNS: i32 = 3;
+    /// The TCP handshake (or the `--udp` probe, or the greeting of a `--service`) failed.
+    pub const TCP: i32 = 4;
+    pub const TLS: i32 = 5;
+    /// The HTTP request got no response (or the `--grpc` health check did not answer SERVING,
+    /// or the `--ws` upgrade failed).
+    pub const HTTP: i32 = 6;
+    /// An `--expect-*` assertion failed.
+    pub const ASSERTION: i32 = 7;
+    /// The certificate expires within `--cert-warn-days` or comes from a CA its host's CAA records do not allow
+    /// (`--check-caa`); for `netprobe whois`, the domain expires within `--expiry-warn-days`.
+    pub const CERT_EXPIRY: i32 = 8;
+    /// A stage was slower than its `--max-*` threshold.
+    pub const DEGRADED: i32 = 9;
+    /// The result differs from the baseline (`--baseline`, `netprobe diff`).
+    pub const CHANGED: i32 = 10;
+    /// Stopped with Ctrl-C; the results gathered so far were still written (128 + SIGINT, as shells report it).
+    pub const INTERRUPTED: i32 = 130;
+}
+
+/// Automatically prepend https:// if no scheme is provided for convenience.
+/// Bare IPv6 addresses are bracketed so they form a valid URL host.
+pub fn normalize_target(target: &str) -> String {
+    if target.parse::<std::net::Ipv6Addr>().is_ok() {
+        format!("https://[{}]", target)
+    } else if !target.contains("://") {
+        format!("https://{}", target)
+    } else {
+        target.to_string()
+    }
+}
+
+/// Sanitize and parse user input into a URL.
+pub fn parse_target(target: &str) -> Result<Url, ProbeError> {
+    Url::parse(&normalize_target(target)).map_err(ProbeError::InvalidTarget)
+}
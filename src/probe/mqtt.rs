@@ -0,0 +1,123 @@
+//! MQTT broker check (`--service mqtt`, ports 1883 and 8883): one CONNECT / CONNACK
+//! exchange (MQTT 3.1.1), then DISCONNECT.
+//!
+//! The CONNECT carries a random client id and a clean session, so nothing
+//! lingers on the broker; `--auth-basic` supplies a username and password.
+//! The CONNACK return code tells whether the broker accepted the session.
+
+use super::service::ServiceResult;
+use super::smtp::ms_since;
+use super::{HttpAuth, ProbeOptions};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const DISCONNECT: u8 = 0xe0;
+/// Protocol level of MQTT 3.1.1, the version every broker speaks.
+const PROTOCOL_LEVEL: u8 = 4;
+const FLAG_USERNAME: u8 = 0x80;
+const FLAG_PASSWORD: u8 = 0x40;
+const FLAG_CLEAN_SESSION: u8 = 0x02;
+/// Seconds; the session ends long before.
+const KEEP_ALIVE: u16 = 60;
+
+/// Send CONNECT and read the CONNACK. A refused session is an error, its return code in `banner`.
+pub(super) async fn probe<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, options: &ProbeOptions, timeout: Duration) -> Result<(), String> {
+    let mut id = [0u8; 4];
+    SystemRandom::new().fill(&mut id).map_err(|_| "No random numbers".to_string())?;
+    let client_id = format!("netprobe-{}", id.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    let credentials = match &options.auth {
+        Some(HttpAuth::Basic { user, password }) => Some((user.as_str(), password.as_str())),
+        _ => None,
+    };
+
+    let mut flags = FLAG_CLEAN_SESSION;
+    let mut payload = string(&client_id);
+    if let Some((user, password)) = credentials {
+        flags |= FLAG_USERNAME | FLAG_PASSWORD;
+        payload.extend(string(user));
+        payload.extend(string(password));
+    }
+    let mut body = string("MQTT");
+    body.extend([PROTOCOL_LEVEL, flags]);
+    body.extend(KEEP_ALIVE.to_be_bytes());
+    body.extend(payload);
+    let mut packet = vec![CONNECT];
+    push_length(&mut packet, body.len());
+    packet.extend(body);
+
+    let started = Instant::now();
+    stream.write_all(&packet).await.map_err(|e| e.to_string())?;
+    let reply = tokio::time::timeout(timeout, read_connack(stream)).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))??;
+    result.greeting_ms = Some(ms_since(started));
+    let [flags, code] = reply[..] else {
+        return Err("Not an MQTT broker: unexpected reply to CONNECT".to_string());
+    };
+    result.version = Some("3.1.1".to_string());
+    result.banner = Some(format!("{} {}", code, return_code(code)));
+    if credentials.is_some() {
+        result.capabilities.push("AUTH=password".to_string());
+    }
+    if flags & 0x01 != 0 {
+        result.capabilities.push("session-present".to_string());
+    }
+    if code != 0 {
+        return Err(format!("Broker refused the connection: {}", return_code(code)));
+    }
+    let _ = stream.write_all(&[DISCONNECT, 0]).await;
+    Ok(())
+}
+
+/// Body of the CONNACK brokers answer CONNECT with: acknowledge flags and return code.
+async fn read_connack<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, String> {
+    let closed = |_| "Connection closed by the server (not an MQTT broker, or it dropped the client)".to_string();
+    let kind = stream.read_u8().await.map_err(closed)? & 0xf0;
+    let mut length = 0usize;
+    for shift in (0..28).step_by(7) {
+        let byte = stream.read_u8().await.map_err(closed)?;
+        length |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    if kind != CONNACK || length > 64 {
+        return Err("Not an MQTT broker: unexpected reply to CONNECT".to_string());
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.map_err(closed)?;
+    Ok(body)
+}
+
+/// UTF-8 string with its 2-byte length.
+fn string(text: &str) -> Vec<u8> {
+    let mut bytes = (text.len() as u16).to_be_bytes().to_vec();
+    bytes.extend(text.as_bytes());
+    bytes
+}
+
+/// Remaining length: 7 bits per byte, high bit set when more follow.
+fn push_length(packet: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        packet.push(if length > 0 { byte | 0x80 } else { byte });
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+/// Meaning of a CONNACK return code.
+fn return_code(code: u8) -> &'static str {
+    match code {
+        0 => "Connection accepted",
+        1 => "Unacceptable protocol version",
+        2 => "Client identifier rejected",
+        3 => "Server unavailable",
+        4 => "Bad user name or password",
+        5 => "Not authorized",
+        _ => "Unknown return code",
+    }
+}
@@ -0,0 +1,127 @@
+//! NTP server check (`--service ntp`, port 123): one SNTP exchange (RFC 4330).
+//!
+//! The request carries our transmit time, which the server echoes as the
+//! originate timestamp of its reply. With the server's receive and transmit
+//! timestamps and the arrival time, the four timestamps give the clock offset
+//! and the round-trip delay the way an NTP client computes them.
+
+use serde::Serialize;
+use std::net::Ipv4Addr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds from the NTP era (1900-01-01) to the Unix epoch.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+const NTP_PACKET: usize = 48;
+/// Leap indicator 3: the server's clock is not synchronized.
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+/// Stratum 16 means unsynchronized; 0 is a Kiss-o'-Death packet.
+const STRATUM_UNSYNCHRONIZED: u8 = 16;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NtpResult {
+    pub version: u8,
+    /// 1 for a primary server (GPS, atomic clock...), one more per hop below it.
+    pub stratum: u8,
+    /// "none", "+1s" or "-1s" (leap second announced), or "unsynchronized".
+    pub leap: String,
+    /// Reference clock code at stratum 1 ("GPS", "PPS"...), upstream server address below.
+    pub reference: String,
+    /// How far the server's clock is ahead of ours (negative: behind).
+    pub offset_ms: f64,
+    /// Round trip of the exchange, without the time the server held the request.
+    pub delay_ms: f64,
+    /// Round trip to the stratum 1 clock, as the server reports it.
+    pub root_delay_ms: f64,
+    /// Error bound of the server's clock relative to the stratum 1 clock.
+    pub root_dispersion_ms: f64,
+    /// The server's transmit timestamp.
+    pub server_time: String,
+}
+
+/// An SNTP client request stamped with `sent`.
+pub(crate) fn request(sent: SystemTime) -> Vec<u8> {
+    let mut packet = vec![0; NTP_PACKET];
+    packet[0] = 0x23; // LI 0, version 4, mode 3 (client)
+    packet[40..48].copy_from_slice(&timestamp(sent));
+    packet
+}
+
+/// Decode the server's reply to `request(sent)`, received at `received`.
+pub(crate) fn decode(reply: &[u8], sent: SystemTime, received: SystemTime) -> Result<NtpResult, String> {
+    if reply.len() < NTP_PACKET {
+        return Err(format!("{} bytes, too short for an NTP packet", reply.len()));
+    }
+    if reply[0] & 0x07 != 4 {
+        return Err(format!("Not an NTP server reply (mode {})", reply[0] & 0x07));
+    }
+    if reply[24..32] != timestamp(sent) {
+        return Err("Reply does not answer our request (originate timestamp differs)".to_string());
+    }
+    let stratum = reply[1];
+    let reference = &reply[12..16];
+    if stratum == 0 {
+        return Err(format!("Kiss-o'-Death from the server: {}", String::from_utf8_lossy(reference).trim_end_matches('\0')));
+    }
+
+    let t1 = seconds(sent);
+    let t2 = ntp_seconds(&reply[32..40]);
+    let t3 = ntp_seconds(&reply[40..48]);
+    let t4 = seconds(received);
+    let server_time = UNIX_EPOCH + std::time::Duration::from_secs_f64((t3 - NTP_UNIX_OFFSET as f64).max(0.0));
+    Ok(NtpResult {
+        version: (reply[0] >> 3) & 0x07,
+        stratum,
+        leap: match reply[0] >> 6 {
+            0 => "none",
+            1 => "+1s",
+            2 => "-1s",
+            _ => "unsynchronized",
+        }
+        .to_string(),
+        reference: match stratum {
+            1 => String::from_utf8_lossy(reference).trim_end_matches('\0').to_string(),
+            _ => Ipv4Addr::new(reference[0], reference[1], reference[2], reference[3]).to_string(),
+        },
+        offset_ms: ((t2 - t1) + (t3 - t4)) / 2.0 * 1000.0,
+        delay_ms: ((t4 - t1) - (t3 - t2)) * 1000.0,
+        root_delay_ms: short_seconds(&reply[4..8]) * 1000.0,
+        root_dispersion_ms: short_seconds(&reply[8..12]) * 1000.0,
+        server_time: chrono::DateTime::<chrono::Utc>::from(server_time).to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+    })
+}
+
+/// Why a decoded reply is not a usable time source, if it is not.
+pub(crate) fn unsynchronized(ntp: &NtpResult) -> Option<String> {
+    match (ntp.leap.as_str(), ntp.stratum) {
+        (_, stratum) if stratum >= STRATUM_UNSYNCHRONIZED => Some(format!("Server is not synchronized (stratum {})", stratum)),
+        ("unsynchronized", _) => Some(format!("Server is not synchronized (leap indicator {})", LEAP_UNSYNCHRONIZED)),
+        _ => None,
+    }
+}
+
+/// 64-bit NTP timestamp: seconds since 1900, then a 32-bit fraction.
+fn timestamp(time: SystemTime) -> [u8; 8] {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = (since_epoch.as_secs() + NTP_UNIX_OFFSET) as u32;
+    let fraction = ((u64::from(since_epoch.subsec_nanos()) << 32) / 1_000_000_000) as u32;
+    let mut bytes = [0; 8];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    bytes[4..].copy_from_slice(&fraction.to_be_bytes());
+    bytes
+}
+
+/// `time` in seconds since 1900.
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64() + NTP_UNIX_OFFSET as f64
+}
+
+fn ntp_seconds(bytes: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    f64::from(seconds) + f64::from(fraction) / 4_294_967_296.0
+}
+
+/// 32-bit NTP short format: 16 bits of seconds, 16 of fraction.
+fn short_seconds(bytes: &[u8]) -> f64 {
+    f64::from(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])) / 65_536.0
+}
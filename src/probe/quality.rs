@@ -0,0 +1,127 @@
+//! Optional: TCP connection quality (`--tcp-quality`).
+//!
+//! One connect time says little: the handshake may have been lucky. Several
+//! connections in a row give its spread and jitter. On each, two bytes (a CRLF,
+//! which HTTP servers skip and line-based protocols treat as an empty line) are
+//! sent and their acknowledgment awaited, so that the kernel's view of the
+//! connection (`TCP_INFO`, Linux only) includes a data round trip: its smoothed
+//! RTT and variance, and the segments it had to send again, SYNs included.
+
+use super::source::SourceBinding;
+use crate::stats::LatencyStats;
+use serde::Serialize;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+/// Sent on each connection to get a data round trip.
+const PAYLOAD: &[u8] = b"\r\n";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityResult {
+    pub connections: u32,
+    pub established: u32,
+    /// Connect times of the established connections.
+    pub connect: Option<LatencyStats>,
+    /// Mean difference between consecutive connect times.
+    pub jitter_ms: Option<f64>,
+    /// Kernel's smoothed RTT, averaged over the connections (`TCP_INFO`).
+    pub rtt_ms: Option<f64>,
+    /// Kernel's RTT variance, averaged over the connections.
+    pub rtt_var_ms: Option<f64>,
+    /// Segments sent again over all the connections, SYNs included; any is a sign of loss.
+    pub retransmits: Option<u32>,
+    /// Maximum segment size and congestion window (in segments) of the last connection.
+    pub mss: Option<u32>,
+    pub cwnd: Option<u32>,
+    /// Why the connections that failed did.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// What `TCP_INFO` says about one connection.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct TcpInfo {
+    rtt_ms: f64,
+    rtt_var_ms: f64,
+    retransmits: u32,
+    unacked: u32,
+    mss: u32,
+    cwnd: u32,
+}
+
+/// Connect to `addr` `count` times in a row from `source`, each connection giving up after `timeout`.
+pub async fn measure(addr: SocketAddr, count: u32, timeout: Duration, source: &SourceBinding) -> QualityResult {
+    let mut connects = Vec::new();
+    let mut infos = Vec::new();
+    let mut errors = Vec::new();
+    for _ in 0..count {
+        let started = Instant::now();
+        match source.connect_timeout(addr, timeout).await {
+            Ok(mut stream) => {
+                connects.push(started.elapsed().as_secs_f64() * 1000.0);
+                if let Some(info) = exchange(&mut stream, timeout).await {
+                    infos.push(info);
+                }
+            },
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    tracing::debug!(%addr, established = connects.len(), count, "tcp quality measured");
+
+    let average = |value: fn(&TcpInfo) -> f64| (!infos.is_empty()).then(|| infos.iter().map(value).sum::<f64>() / infos.len() as f64);
+    QualityResult {
+        connections: count,
+        established: connects.len() as u32,
+        connect: LatencyStats::from_samples(&connects),
+        jitter_ms: (connects.len() > 1).then(|| connects.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum::<f64>() / (connects.len() - 1) as f64),
+        rtt_ms: average(|info| info.rtt_ms),
+        rtt_var_ms: average(|info| info.rtt_var_ms),
+        retransmits: (!infos.is_empty()).then(|| infos.iter().map(|info| info.retransmits).sum()),
+        mss: infos.last().map(|info| info.mss),
+        cwnd: infos.last().map(|info| info.cwnd),
+        errors,
+    }
+}
+
+/// Send `PAYLOAD` and wait for it to be acknowledged, then read `TCP_INFO`.
+async fn exchange(stream: &mut TcpStream, timeout: Duration) -> Option<TcpInfo> {
+    tcp_info(stream).ok()?;
+    stream.write_all(PAYLOAD).await.ok()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        let info = tcp_info(stream).ok()?;
+        if info.unacked == 0 || Instant::now() >= deadline {
+            return Some(info);
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn tcp_info(stream: &TcpStream) -> io::Result<TcpInfo> {
+    use std::os::fd::AsRawFd;
+    // SAFETY: tcp_info is plain data, and the kernel writes at most `len` bytes of it.
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let ok = unsafe { libc::getsockopt(stream.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_INFO, &mut info as *mut libc::tcp_info as *mut libc::c_void, &mut len) } == 0;
+    if !ok {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(TcpInfo {
+        rtt_ms: info.tcpi_rtt as f64 / 1000.0,
+        rtt_var_ms: info.tcpi_rttvar as f64 / 1000.0,
+        retransmits: info.tcpi_total_retrans,
+        unacked: info.tcpi_unacked,
+        mss: info.tcpi_snd_mss,
+        cwnd: info.tcpi_snd_cwnd,
+    })
+}
+
+/// Other systems only get the connect times.
+#[cfg(not(target_os = "linux"))]
+fn tcp_info(_stream: &TcpStream) -> io::Result<TcpInfo> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "TCP_INFO is only read on Linux"))
+}
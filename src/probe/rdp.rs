@@ -0,0 +1,107 @@
+//! Remote Desktop check (`--service rdp`, port 3389): the X.224 Connection
+//! Request that opens every RDP session, with an RDP Negotiation Request
+//! ([MS-RDPBCGR] 2.2.1.1).
+//!
+//! A listening RDP server answers with the security protocol it picked (TLS,
+//! CredSSP for Network Level Authentication...) or a negotiation failure; either
+//! proves the service itself is up, unlike a firewall that accepts the TCP
+//! handshake and then stays silent. Nothing goes past the negotiation.
+
+use super::service::ServiceResult;
+use super::smtp::ms_since;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const TPKT_VERSION: u8 = 3;
+const X224_CONNECTION_REQUEST: u8 = 0xe0;
+const X224_CONNECTION_CONFIRM: u8 = 0xd0;
+const TYPE_RDP_NEG_REQ: u8 = 1;
+const TYPE_RDP_NEG_RSP: u8 = 2;
+const TYPE_RDP_NEG_FAILURE: u8 = 3;
+/// TLS, CredSSP and CredSSP with Early User Authorization: what current clients offer.
+const REQUESTED_PROTOCOLS: u32 = 0x1 | 0x2 | 0x8;
+const COOKIE: &[u8] = b"Cookie: mstshash=netprobe\r\n";
+
+/// Send the Connection Request and read the server's choice of security protocol.
+pub(super) async fn probe<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, timeout: Duration) -> Result<(), String> {
+    let mut x224 = vec![0, X224_CONNECTION_REQUEST, 0, 0, 0, 0, 0];
+    x224.extend(COOKIE);
+    x224.extend([TYPE_RDP_NEG_REQ, 0, 8, 0]);
+    x224.extend(REQUESTED_PROTOCOLS.to_le_bytes());
+    // The length indicator counts the TPDU without itself.
+    x224[0] = (x224.len() - 1) as u8;
+    let mut packet = vec![TPKT_VERSION, 0];
+    packet.extend(((x224.len() + 4) as u16).to_be_bytes());
+    packet.extend(x224);
+
+    let started = Instant::now();
+    stream.write_all(&packet).await.map_err(|e| e.to_string())?;
+    let reply = tokio::time::timeout(timeout, read_tpkt(stream)).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))??;
+    result.greeting_ms = Some(ms_since(started));
+
+    if reply.get(1).map(|code| code & 0xf0) != Some(X224_CONNECTION_CONFIRM) {
+        return Err("Not an RDP server: no X.224 Connection Confirm".to_string());
+    }
+    // Servers older than RDP 5.2 confirm without a negotiation response: standard RDP security.
+    let Some(negotiation) = reply.get(7..15) else {
+        result.banner = Some("Standard RDP Security (no negotiation)".to_string());
+        return Ok(());
+    };
+    let value = u32::from_le_bytes([negotiation[4], negotiation[5], negotiation[6], negotiation[7]]);
+    match negotiation[0] {
+        TYPE_RDP_NEG_RSP => {
+            result.banner = Some(protocol_name(value).to_string());
+            result.capabilities = FLAGS.iter().filter(|(flag, _)| negotiation[1] & flag != 0).map(|(_, name)| name.to_string()).collect();
+        },
+        // The server is there but wants something else: still an answer from RDP.
+        TYPE_RDP_NEG_FAILURE => result.banner = Some(format!("Negotiation failure: {}", failure_name(value))),
+        other => return Err(format!("Not an RDP server: negotiation type {}", other)),
+    }
+    Ok(())
+}
+
+/// One TPKT packet (RFC 1006) without its header.
+async fn read_tpkt<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, String> {
+    let closed = |_| "Connection closed by the server (not RDP, or refused by its policy)".to_string();
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await.map_err(closed)?;
+    let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    if header[0] != TPKT_VERSION || !(7..=1024).contains(&length) {
+        return Err("Not an RDP server: invalid TPKT header".to_string());
+    }
+    let mut packet = vec![0; length - 4];
+    stream.read_exact(&mut packet).await.map_err(closed)?;
+    Ok(packet)
+}
+
+/// Flags of an RDP Negotiation Response.
+const FLAGS: [(u8, &str); 5] = [
+    (0x01, "EXTENDED_CLIENT_DATA"),
+    (0x02, "DYNVC_GFX"),
+    (0x08, "RESTRICTED_ADMIN_MODE"),
+    (0x10, "REDIRECTED_AUTHENTICATION_MODE"),
+    (0x20, "CORRELATION_INFO"),
+];
+
+fn protocol_name(protocol: u32) -> &'static str {
+    match protocol {
+        0 => "Standard RDP Security",
+        1 => "TLS",
+        2 => "CredSSP (NLA)",
+        4 => "RDSTLS",
+        8 => "CredSSP with Early User Authorization (NLA)",
+        _ => "unknown security protocol",
+    }
+}
+
+fn failure_name(code: u32) -> &'static str {
+    match code {
+        1 => "SSL required by server",
+        2 => "SSL not allowed by server",
+        3 => "SSL certificate not on server",
+        4 => "inconsistent flags",
+        5 => "CredSSP required by server",
+        6 => "SSL with user authentication required by server",
+        _ => "unknown failure code",
+    }
+}
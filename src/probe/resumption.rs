@@ -0,0 +1,158 @@
+//! Optional: TLS session resumption and 0-RTT support (`--check-resumption`).
+//!
+//! The first handshake records whatever the server hands out for resuming:
+//! a TLS 1.2 session (ID or ticket) or TLS 1.3 tickets, whose
+//! `max_early_data_size` tells whether 0-RTT is allowed. A second connection
+//! then offers it; an abbreviated handshake skips the certificate, so the
+//! verifier not being called means the session was resumed.
+
+use super::ProbeOptions;
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue, Tls13ClientSessionValue};
+use rustls::{NamedGroup, ServerName};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// TLS 1.3 tickets arrive after the handshake; how long to wait for the first one.
+const TICKET_WAIT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResumptionResult {
+    /// "ok" when the second handshake ran, "error" otherwise.
+    pub status: String,
+    /// The server issued a session or ticket to resume with.
+    pub supported: bool,
+    /// The second handshake was abbreviated.
+    pub resumed: bool,
+    /// "session" (TLS 1.2 session ID or ticket) | "ticket" (TLS 1.3 PSK)
+    pub mechanism: Option<String>,
+    /// The TLS 1.3 ticket allows 0-RTT early data; `None` for TLS 1.2.
+    pub early_data: Option<bool>,
+    /// Bytes of early data the server accepts, when it does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_early_data: Option<u32>,
+    /// The first, full handshake.
+    pub full_ms: f64,
+    /// The second handshake.
+    pub resumed_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Session cache that notes what the server issued.
+pub(crate) struct SessionRecorder {
+    inner: ClientSessionMemoryCache,
+    tls12: AtomicBool,
+    tls13: AtomicBool,
+    max_early_data: AtomicU32,
+    ticket: Notify,
+}
+
+impl SessionRecorder {
+    pub(crate) fn new() -> Self {
+        SessionRecorder { inner: ClientSessionMemoryCache::new(256), tls12: AtomicBool::new(false), tls13: AtomicBool::new(false), max_early_data: AtomicU32::new(0), ticket: Notify::new() }
+    }
+}
+
+impl ClientSessionStore for SessionRecorder {
+    fn set_kx_hint(&self, server_name: &ServerName, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: &ServerName, value: Tls12ClientSessionValue) {
+        self.tls12.store(true, Ordering::Relaxed);
+        self.inner.set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName) {
+        self.inner.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(&self, server_name: &ServerName, value: Tls13ClientSessionValue) {
+        self.tls13.store(true, Ordering::Relaxed);
+        self.max_early_data.fetch_max(value.max_early_data_size(), Ordering::Relaxed);
+        self.inner.insert_tls13_ticket(server_name, value);
+        self.ticket.notify_one();
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName) -> Option<Tls13ClientSessionValue> {
+        self.inner.take_tls13_ticket(server_name)
+    }
+}
+
+/// Reconnect to `addr` with the session of `first`; `verified` tells whether the
+/// verifier saw a certificate since it was last asked. `full_ms` is left to the caller.
+pub(crate) async fn check(
+    first: &mut TlsStream<TcpStream>,
+    sessions: &SessionRecorder,
+    connector: &TlsConnector,
+    addr: SocketAddr,
+    server_name: ServerName,
+    verified: impl Fn() -> bool,
+    options: &ProbeOptions,
+) -> ResumptionResult {
+    let timeout = options.tls_timeout();
+    let tls13 = first.get_ref().1.protocol_version() == Some(rustls::ProtocolVersion::TLSv1_3);
+    if tls13 {
+        // Reading processes the NewSessionTicket messages; no application data is expected.
+        let mut buf = [0u8; 1];
+        tokio::select! {
+            _ = first.read(&mut buf) => {},
+            _ = sessions.ticket.notified() => {},
+            _ = tokio::time::sleep(TICKET_WAIT.min(timeout)) => {},
+        }
+    }
+    let supported = if tls13 { sessions.tls13.load(Ordering::Relaxed) } else { sessions.tls12.load(Ordering::Relaxed) };
+    let max_early_data = sessions.max_early_data.load(Ordering::Relaxed);
+    let mut result = ResumptionResult {
+        status: "ok".to_string(),
+        supported,
+        resumed: false,
+        mechanism: None,
+        early_data: (tls13 && supported).then_some(max_early_data > 0),
+        max_early_data: (max_early_data > 0).then_some(max_early_data),
+        full_ms: 0.0,
+        resumed_ms: None,
+        error: None,
+    };
+    if !supported {
+        return result;
+    }
+
+    // Single-threaded servers only accept the next connection once this one is closed.
+    let _ = first.shutdown().await;
+    let started = Instant::now();
+    let handshake = async {
+        let stream = options.source.connect(addr).await.map_err(|e| e.to_string())?;
+        connector.connect(server_name, stream).await.map_err(|e| e.to_string())
+    };
+    match tokio::time::timeout(timeout, handshake).await {
+        Ok(Ok(_)) => {
+            result.resumed_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+            result.resumed = !verified();
+            result.mechanism = result.resumed.then(|| if tls13 { "ticket" } else { "session" }.to_string());
+        },
+        Ok(Err(e)) => {
+            result.status = "error".to_string();
+            result.error = Some(format!("Second handshake failed: {}", e));
+        },
+        Err(_) => {
+            result.status = "error".to_string();
+            result.error = Some("Second handshake timed out".to_string());
+        },
+    }
+    result
+}
@@ -0,0 +1,44 @@
+//! Optional: re-run a failed stage with exponential backoff (`--retries`).
+
+use super::ProbeOptions;
+use serde::Serialize;
+use std::future::Future;
+use std::time::Instant;
+
+/// One try of a stage.
+#[derive(Debug, Clone, Serialize)]
+pub struct Attempt {
+    pub latency_ms: f64,
+    pub error: Option<String>,
+}
+
+/// Run `stage` (named `name` in the logs) until `error` reports success or `options.retries` extra tries are used up,
+/// sleeping `retry_backoff`, then twice that, and so on between tries.
+/// Attempts are only recorded when retries are enabled, so single-shot output is unchanged.
+pub(crate) async fn run<T, F, Fut>(options: &ProbeOptions, name: &str, mut stage: F, error: impl Fn(&T) -> Option<String>) -> (T, Vec<Attempt>)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut attempts = Vec::new();
+    let mut backoff = options.retry_backoff;
+    loop {
+        let started = Instant::now();
+        let outcome = stage().await;
+        let failure = error(&outcome);
+        let failed = failure.is_some();
+        if let (Some(e), true) = (&failure, attempts.len() < options.retries as usize) {
+            tracing::warn!(stage = name, attempt = attempts.len() + 1, error = e.as_str(), backoff_ms = backoff.as_millis() as u64, "stage failed, retrying");
+        }
+        attempts.push(Attempt { latency_ms: started.elapsed().as_secs_f64() * 1000.0, error: failure });
+
+        if !failed || attempts.len() > options.retries as usize {
+            if options.retries == 0 {
+                attempts.clear();
+            }
+            return (outcome, attempts);
+        }
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
@@ -0,0 +1,321 @@
+//! Optional: certificate revocation check (`--check-revocation`).
+//!
+//! The leaf certificate is looked up, in order, in the OCSP response stapled
+//! to the handshake, at the OCSP responders of its authorityInfoAccess
+//! extension, then in the CRLs of its cRLDistributionPoints. Responses are only
+//! trusted when signed by the issuer (or by a responder it delegated to), so
+//! the issuer must be part of the presented chain.
+
+use crate::x509::{self, Der, Tlv, TAG_ENUMERATED, TAG_GENERALIZED_TIME, TAG_INTEGER, TAG_NULL, TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rustls::Certificate;
+use serde::Serialize;
+use std::time::Instant;
+
+/// id-pkix-ocsp-basic: the only OCSP response type in use.
+const OID_OCSP_BASIC: &str = "1.3.6.1.5.5.7.48.1.1";
+/// id-kp-OCSPSigning: marks a certificate the issuer delegated OCSP signing to.
+const OID_KP_OCSP_SIGNING: &str = "1.3.6.1.5.5.7.3.9";
+/// id-ce-cRLReasons, in a CRL entry's extensions.
+const OID_CRL_REASON: &str = "2.5.29.21";
+/// id-sha1 (1.3.14.3.2.26), the CertID hash every responder accepts.
+const SHA1_OID: [u8; 5] = [0x2b, 0x0e, 0x03, 0x02, 0x1a];
+/// Clock skew tolerated on `thisUpdate` / `nextUpdate`.
+const MAX_SKEW_MINUTES: i64 = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RevocationResult {
+    /// "good" | "revoked" | "unknown" (the responder does not know the certificate) | "error"
+    pub status: String,
+    /// Where the answer came from: "ocsp-staple" | "ocsp" | "crl".
+    pub method: Option<String>,
+    /// Responder or CRL queried; `None` for a staple.
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<String>,
+    /// CRL reason code name, e.g. "keyCompromise".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// What a signed OCSP response or CRL says about one serial number.
+#[derive(Debug, Clone)]
+enum Verdict {
+    Good,
+    Revoked { at: DateTime<Utc>, reason: Option<String> },
+    Unknown,
+}
+
+impl RevocationResult {
+    fn failed(error: String) -> Self {
+        RevocationResult { status: "error".to_string(), method: None, url: None, revoked_at: None, reason: None, latency_ms: None, error: Some(error) }
+    }
+
+    fn answered(verdict: Verdict, method: &str, url: Option<String>, started: Instant) -> Self {
+        let (status, revoked_at, reason) = match verdict {
+            Verdict::Good => ("good", None, None),
+            Verdict::Revoked { at, reason } => ("revoked", Some(at.to_rfc3339()), reason),
+            Verdict::Unknown => ("unknown", None, None),
+        };
+        RevocationResult {
+            status: status.to_string(),
+            method: Some(method.to_string()),
+            url,
+            revoked_at,
+            reason,
+            latency_ms: Some(started.elapsed().as_secs_f64() * 1000.0),
+            error: None,
+        }
+    }
+}
+
+/// Revocation status of the leaf of `chain`, using `staple` (possibly empty) first.
+/// OCSP responders and CRLs are fetched with `client`.
+pub async fn check(chain: &[Certificate], staple: &[u8], client: &reqwest::Client) -> RevocationResult {
+    let started = Instant::now();
+    let (Some(leaf), Some(issuer)) = (chain.first().and_then(|c| x509::Certificate::parse(&c.0)), chain.get(1).and_then(|c| x509::Certificate::parse(&c.0))) else {
+        return RevocationResult::failed("The server did not present the issuer certificate".to_string());
+    };
+
+    let mut errors = Vec::new();
+    if !staple.is_empty() {
+        match ocsp_verdict(staple, &leaf, &issuer) {
+            Ok(verdict) => return RevocationResult::answered(verdict, "ocsp-staple", None, started),
+            Err(e) => errors.push(format!("stapled OCSP response: {}", e)),
+        }
+    }
+
+    for url in leaf.ocsp_urls() {
+        let request = ocsp_request(&leaf, &issuer);
+        let response = client.post(&url).header(reqwest::header::CONTENT_TYPE, "application/ocsp-request").body(request).send().await;
+        match fetch(response).await.and_then(|body| ocsp_verdict(&body, &leaf, &issuer)) {
+            Ok(verdict) => return RevocationResult::answered(verdict, "ocsp", Some(url), started),
+            Err(e) => errors.push(format!("{}: {}", url, e)),
+        }
+    }
+    for url in leaf.crl_urls().into_iter().filter(|url| url.starts_with("http")) {
+        match fetch(client.get(&url).send().await).await.and_then(|body| crl_verdict(&body, &leaf, &issuer)) {
+            Ok(verdict) => return RevocationResult::answered(verdict, "crl", Some(url), started),
+            Err(e) => errors.push(format!("{}: {}", url, e)),
+        }
+    }
+
+    let mut result = RevocationResult::failed(if errors.is_empty() {
+        "The certificate names no OCSP responder or CRL".to_string()
+    } else {
+        errors.join("; ")
+    });
+    result.latency_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+    result
+}
+
+async fn fetch(response: reqwest::Result<reqwest::Response>) -> Result<Vec<u8>, String> {
+    let response = response.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    response.bytes().await.map(|body| body.to_vec()).map_err(|e| e.to_string())
+}
+
+// --- OCSP (RFC 6960) ---
+
+/// CertID of `leaf`: SHA-1 of the issuer's name and key, and the serial number.
+fn cert_id(leaf: &x509::Certificate, issuer: &x509::Certificate) -> Vec<u8> {
+    let sha1 = |data: &[u8]| ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, data);
+    let algorithm = x509::encode(TAG_SEQUENCE, &[x509::encode(TAG_OID, &SHA1_OID), x509::encode(TAG_NULL, &[])].concat());
+    let fields = [
+        algorithm,
+        x509::encode(TAG_OCTET_STRING, sha1(leaf.raw_issuer).as_ref()),
+        x509::encode(TAG_OCTET_STRING, sha1(issuer.public_key).as_ref()),
+        x509::encode(TAG_INTEGER, leaf.serial),
+    ];
+    x509::encode(TAG_SEQUENCE, &fields.concat())
+}
+
+/// OCSPRequest for one certificate, without nonce or signature.
+fn ocsp_request(leaf: &x509::Certificate, issuer: &x509::Certificate) -> Vec<u8> {
+    let request = x509::encode(TAG_SEQUENCE, &cert_id(leaf, issuer));
+    let request_list = x509::encode(TAG_SEQUENCE, &request);
+    let tbs_request = x509::encode(TAG_SEQUENCE, &request_list);
+    x509::encode(TAG_SEQUENCE, &tbs_request)
+}
+
+/// Check the signature and freshness of an OCSPResponse and read the status of `leaf`.
+fn ocsp_verdict(der: &[u8], leaf: &x509::Certificate, issuer: &x509::Certificate) -> Result<Verdict, String> {
+    let malformed = || "malformed OCSP response".to_string();
+    let response = Der::new(der).expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let mut fields = Der::new(response.content);
+    let status = fields.expect(TAG_ENUMERATED).ok_or_else(malformed)?;
+    match status.content {
+        [0] => {},
+        [code] => return Err(format!("responder answered {}", ocsp_response_status(*code))),
+        _ => return Err(malformed()),
+    }
+    let bytes = fields.expect(0xa0).and_then(|wrapper| Der::new(wrapper.content).expect(TAG_SEQUENCE)).ok_or_else(malformed)?;
+    let mut bytes = Der::new(bytes.content);
+    let kind = bytes.expect(TAG_OID).map(|oid| x509::oid_to_string(oid.content)).ok_or_else(malformed)?;
+    if kind != OID_OCSP_BASIC {
+        return Err(format!("unsupported OCSP response type {}", kind));
+    }
+    let basic = bytes.expect(TAG_OCTET_STRING).and_then(|basic| Der::new(basic.content).expect(TAG_SEQUENCE)).ok_or_else(malformed)?;
+
+    // BasicOCSPResponse { tbsResponseData, signatureAlgorithm, signature, certs [0] }
+    let mut basic = Der::new(basic.content);
+    let data = basic.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let algorithm = basic.expect(TAG_SEQUENCE).and_then(|alg| Der::new(alg.content).expect(TAG_OID)).ok_or_else(malformed)?;
+    let signature = basic.expect(x509::TAG_BIT_STRING).ok_or_else(malformed)?;
+    let algorithm = x509::signature_algorithm_name(&x509::oid_to_string(algorithm.content));
+    let signed_by = |signer: &x509::Certificate| x509::verify_signature(&signer.key_type, signer.public_key, &algorithm, data.raw, x509::bit_string(&signature));
+    if !signed_by(issuer) {
+        // A delegated responder: its certificate comes with the response.
+        let delegated = basic
+            .optional(0xa0)
+            .and_then(|certs| Der::new(certs.content).expect(TAG_SEQUENCE))
+            .and_then(|certs| Der::new(certs.content).expect(TAG_SEQUENCE))
+            .and_then(|cert| x509::Certificate::parse(cert.raw));
+        match delegated {
+            Some(responder) if responder.is_signed_by(issuer) && responder.has_extended_key_usage(OID_KP_OCSP_SIGNING) && signed_by(&responder) => {},
+            _ => return Err("response not signed by the issuer or a responder it authorised".to_string()),
+        }
+    }
+
+    // ResponseData { version [0], responderID, producedAt, responses, extensions [1] }
+    let mut data = Der::new(data.content);
+    data.optional(0xa0);
+    data.read().ok_or_else(malformed)?; // responderID
+    data.expect(TAG_GENERALIZED_TIME).ok_or_else(malformed)?;
+    let responses = data.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let mut responses = Der::new(responses.content);
+    while let Some(single) = responses.expect(TAG_SEQUENCE) {
+        let mut single = Der::new(single.content);
+        let id = single.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+        if serial_of(&id) != Some(leaf.serial) {
+            continue;
+        }
+        let status = single.read().ok_or_else(malformed)?;
+        let this_update = single.expect(TAG_GENERALIZED_TIME).as_ref().and_then(x509::parse_time).ok_or_else(malformed)?;
+        let next_update = single.optional(0xa0).and_then(|next| Der::new(next.content).read()).as_ref().and_then(x509::parse_time);
+        check_freshness(this_update, next_update)?;
+        return Ok(match status.tag {
+            0x80 => Verdict::Good,
+            0xa1 => {
+                let mut info = Der::new(status.content);
+                let at = info.read().as_ref().and_then(x509::parse_time).ok_or_else(malformed)?;
+                let reason = info.optional(0xa0).and_then(|reason| Der::new(reason.content).expect(TAG_ENUMERATED)).and_then(|code| code.content.first().copied());
+                Verdict::Revoked { at, reason: reason.map(crl_reason) }
+            },
+            _ => Verdict::Unknown,
+        });
+    }
+    Err("no status for this certificate in the response".to_string())
+}
+
+/// Serial number of a CertID (the issuer hashes are left unchecked: the signature already ties the response to the issuer).
+fn serial_of<'a>(cert_id: &Tlv<'a>) -> Option<&'a [u8]> {
+    let mut fields = Der::new(cert_id.content);
+    fields.expect(TAG_SEQUENCE)?;
+    fields.expect(TAG_OCTET_STRING)?;
+    fields.expect(TAG_OCTET_STRING)?;
+    Some(fields.expect(TAG_INTEGER)?.content)
+}
+
+fn check_freshness(this_update: DateTime<Utc>, next_update: Option<DateTime<Utc>>) -> Result<(), String> {
+    let now = Utc::now();
+    let skew = ChronoDuration::minutes(MAX_SKEW_MINUTES);
+    if this_update > now + skew {
+        return Err(format!("issued in the future ({})", this_update.to_rfc3339()));
+    }
+    match next_update {
+        Some(next) if next + skew < now => Err(format!("stale, next update was due {}", next.to_rfc3339())),
+        _ => Ok(()),
+    }
+}
+
+fn ocsp_response_status(code: u8) -> String {
+    match code {
+        1 => "malformedRequest",
+        2 => "internalError",
+        3 => "tryLater",
+        5 => "sigRequired",
+        6 => "unauthorized",
+        other => return format!("status {}", other),
+    }
+    .to_string()
+}
+
+/// Name of a CRLReason code (RFC 5280, 5.3.1).
+fn crl_reason(code: u8) -> String {
+    match code {
+        0 => "unspecified",
+        1 => "keyCompromise",
+        2 => "cACompromise",
+        3 => "affiliationChanged",
+        4 => "superseded",
+        5 => "cessationOfOperation",
+        6 => "certificateHold",
+        8 => "removeFromCRL",
+        9 => "privilegeWithdrawn",
+        10 => "aACompromise",
+        other => return format!("reason {}", other),
+    }
+    .to_string()
+}
+
+// --- CRL (RFC 5280) ---
+
+/// Check the signature and freshness of a DER CRL and look `leaf` up in it.
+fn crl_verdict(der: &[u8], leaf: &x509::Certificate, issuer: &x509::Certificate) -> Result<Verdict, String> {
+    let malformed = || "malformed CRL (expected DER)".to_string();
+    let list = Der::new(der).expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let mut fields = Der::new(list.content);
+    let tbs = fields.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let algorithm = fields.expect(TAG_SEQUENCE).and_then(|alg| Der::new(alg.content).expect(TAG_OID)).ok_or_else(malformed)?;
+    let signature = fields.expect(x509::TAG_BIT_STRING).ok_or_else(malformed)?;
+    let algorithm = x509::signature_algorithm_name(&x509::oid_to_string(algorithm.content));
+    if !x509::verify_signature(&issuer.key_type, issuer.public_key, &algorithm, tbs.raw, x509::bit_string(&signature)) {
+        return Err("CRL not signed by the issuer".to_string());
+    }
+
+    // TBSCertList { version, signature, issuer, thisUpdate, nextUpdate, revokedCertificates, crlExtensions [0] }
+    let mut tbs = Der::new(tbs.content);
+    tbs.optional(TAG_INTEGER);
+    tbs.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    tbs.expect(TAG_SEQUENCE).ok_or_else(malformed)?;
+    let this_update = tbs.read().as_ref().and_then(x509::parse_time).ok_or_else(malformed)?;
+    let next_update = match tbs.peek_tag() {
+        Some(x509::TAG_UTC_TIME | TAG_GENERALIZED_TIME) => tbs.read().as_ref().and_then(x509::parse_time),
+        _ => None,
+    };
+    check_freshness(this_update, next_update)?;
+
+    let Some(revoked) = tbs.optional(TAG_SEQUENCE) else {
+        return Ok(Verdict::Good);
+    };
+    let mut entries = Der::new(revoked.content);
+    while let Some(entry) = entries.expect(TAG_SEQUENCE) {
+        let mut entry = Der::new(entry.content);
+        if entry.expect(TAG_INTEGER).map(|serial| serial.content) != Some(leaf.serial) {
+            continue;
+        }
+        let at = entry.read().as_ref().and_then(x509::parse_time).ok_or_else(malformed)?;
+        return Ok(Verdict::Revoked { at, reason: entry.optional(TAG_SEQUENCE).and_then(|extensions| entry_reason(extensions.content)) });
+    }
+    Ok(Verdict::Good)
+}
+
+/// The reasonCode extension of a CRL entry.
+fn entry_reason(extensions: &[u8]) -> Option<String> {
+    let mut extensions = Der::new(extensions);
+    while let Some(extension) = extensions.expect(TAG_SEQUENCE) {
+        let mut fields = Der::new(extension.content);
+        if fields.expect(TAG_OID).map(|oid| x509::oid_to_string(oid.content)).as_deref() != Some(OID_CRL_REASON) {
+            continue;
+        }
+        fields.optional(x509::TAG_BOOLEAN);
+        let value = fields.expect(TAG_OCTET_STRING)?;
+        return Der::new(value.content).expect(TAG_ENUMERATED)?.content.first().map(|&code| crl_reason(code));
+    }
+    None
+}
@@ -0,0 +1,178 @@
+//! Optional: security headers audit (`--security-audit`).
+//!
+//! Grades the final response on the headers browsers enforce: HSTS, CSP,
+//! X-Frame-Options, X-Content-Type-Options, Referrer-Policy, and the flags of
+//! every cookie it sets. Each check is worth a fixed number of points out of 100.
+
+use hyper::header::{self, HeaderMap};
+use serde::Serialize;
+
+/// HSTS max-age below which the policy counts as weak: 180 days.
+const HSTS_MIN_MAX_AGE: u64 = 180 * 24 * 3600;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SecurityAudit {
+    /// 0 to 100.
+    pub score: u32,
+    /// "A" (90+) | "B" (75+) | "C" (60+) | "D" (40+) | "F"
+    pub grade: String,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    /// Header checked, lowercase; "set-cookie" covers every cookie.
+    pub header: String,
+    /// "ok" | "warning" | "missing"
+    pub status: String,
+    pub value: Option<String>,
+    pub message: String,
+    pub points: u32,
+    pub max_points: u32,
+}
+
+impl Finding {
+    fn new(header: &str, status: &str, value: Option<&str>, message: impl Into<String>, points: u32, max_points: u32) -> Self {
+        Finding { header: header.to_string(), status: status.to_string(), value: value.map(String::from), message: message.into(), points, max_points }
+    }
+}
+
+/// Audit the response headers of a request to a URL with scheme `scheme`.
+pub fn audit(headers: &HeaderMap, scheme: &str) -> SecurityAudit {
+    let value = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    let csp = value(header::CONTENT_SECURITY_POLICY);
+    let findings = vec![
+        hsts(value(header::STRICT_TRANSPORT_SECURITY), scheme == "https"),
+        content_security_policy(csp),
+        frame_options(value(header::X_FRAME_OPTIONS), csp),
+        content_type_options(value(header::X_CONTENT_TYPE_OPTIONS)),
+        referrer_policy(value(header::REFERRER_POLICY)),
+        cookies(headers, scheme == "https"),
+    ];
+    let score = findings.iter().map(|f| f.points).sum();
+    let grade = match score {
+        90.. => "A",
+        75..=89 => "B",
+        60..=74 => "C",
+        40..=59 => "D",
+        _ => "F",
+    };
+    SecurityAudit { score, grade: grade.to_string(), findings }
+}
+
+fn hsts(value: Option<&str>, https: bool) -> Finding {
+    const POINTS: u32 = 25;
+    let name = "strict-transport-security";
+    if !https {
+        return Finding::new(name, "missing", value, "Served over plain HTTP: nothing forces HTTPS", 0, POINTS);
+    }
+    let Some(value) = value else {
+        return Finding::new(name, "missing", None, "No HSTS: the first visit can be downgraded to HTTP", 0, POINTS);
+    };
+    let max_age = directive(value, "max-age").and_then(|age| age.trim_matches('"').parse::<u64>().ok());
+    match max_age {
+        Some(age) if age >= HSTS_MIN_MAX_AGE => {
+            let subdomains = if directive(value, "includeSubDomains").is_some() { ", subdomains included" } else { "" };
+            Finding::new(name, "ok", Some(value), format!("max-age {} days{}", age / 86_400, subdomains), POINTS, POINTS)
+        },
+        Some(age) => Finding::new(name, "warning", Some(value), format!("max-age is only {} days (at least 180 recommended)", age / 86_400), POINTS / 2, POINTS),
+        None => Finding::new(name, "warning", Some(value), "No valid max-age directive", 0, POINTS),
+    }
+}
+
+fn content_security_policy(value: Option<&str>) -> Finding {
+    const POINTS: u32 = 25;
+    let name = "content-security-policy";
+    let Some(value) = value else {
+        return Finding::new(name, "missing", None, "No CSP: injected scripts run unrestricted", 0, POINTS);
+    };
+    // What limits scripts: script-src, else default-src.
+    let scripts = directive(value, "script-src").or_else(|| directive(value, "default-src"));
+    let weaknesses: Vec<&str> = [("'unsafe-inline'", "allows inline scripts"), ("'unsafe-eval'", "allows eval()")]
+        .into_iter()
+        .filter(|(keyword, _)| scripts.is_some_and(|sources| sources.split_whitespace().any(|s| s.eq_ignore_ascii_case(keyword))))
+        .map(|(_, weakness)| weakness)
+        .collect();
+    if scripts.is_none() {
+        Finding::new(name, "warning", Some(value), "Neither script-src nor default-src: scripts are not restricted", POINTS / 2, POINTS)
+    } else if !weaknesses.is_empty() {
+        Finding::new(name, "warning", Some(value), format!("Policy {}", weaknesses.join(" and ")), POINTS / 2, POINTS)
+    } else {
+        Finding::new(name, "ok", Some(value), "Scripts restricted", POINTS, POINTS)
+    }
+}
+
+fn frame_options(value: Option<&str>, csp: Option<&str>) -> Finding {
+    const POINTS: u32 = 10;
+    let name = "x-frame-options";
+    match value.map(str::to_ascii_uppercase).as_deref() {
+        Some("DENY" | "SAMEORIGIN") => Finding::new(name, "ok", value, "Framing restricted", POINTS, POINTS),
+        _ if csp.and_then(|csp| directive(csp, "frame-ancestors")).is_some() => Finding::new(name, "ok", value, "Framing restricted by CSP frame-ancestors", POINTS, POINTS),
+        Some(_) => Finding::new(name, "warning", value, "Unrecognised value (expected DENY or SAMEORIGIN)", 0, POINTS),
+        None => Finding::new(name, "missing", None, "The page can be framed (clickjacking)", 0, POINTS),
+    }
+}
+
+fn content_type_options(value: Option<&str>) -> Finding {
+    const POINTS: u32 = 10;
+    let name = "x-content-type-options";
+    match value {
+        Some(v) if v.eq_ignore_ascii_case("nosniff") => Finding::new(name, "ok", value, "MIME sniffing disabled", POINTS, POINTS),
+        Some(_) => Finding::new(name, "warning", value, "Unrecognised value (expected nosniff)", 0, POINTS),
+        None => Finding::new(name, "missing", None, "Browsers may MIME-sniff responses", 0, POINTS),
+    }
+}
+
+fn referrer_policy(value: Option<&str>) -> Finding {
+    const POINTS: u32 = 10;
+    let name = "referrer-policy";
+    // Several comma-separated policies may be given; the last one the browser knows wins.
+    let policy = value.and_then(|v| v.rsplit(',').next()).map(|p| p.trim().to_ascii_lowercase());
+    match policy.as_deref() {
+        Some("no-referrer" | "same-origin" | "strict-origin" | "strict-origin-when-cross-origin") => Finding::new(name, "ok", value, "Referrer limited", POINTS, POINTS),
+        Some("unsafe-url" | "no-referrer-when-downgrade" | "origin" | "origin-when-cross-origin") => {
+            Finding::new(name, "warning", value, "Policy leaks the referrer to other sites", POINTS / 2, POINTS)
+        },
+        Some(_) => Finding::new(name, "warning", value, "Unrecognised policy", 0, POINTS),
+        None => Finding::new(name, "missing", None, "No policy: the browser default applies", 0, POINTS),
+    }
+}
+
+/// Every `Set-Cookie` needs HttpOnly and SameSite, plus Secure over HTTPS; points are shared between cookies.
+fn cookies(headers: &HeaderMap, https: bool) -> Finding {
+    const POINTS: u32 = 20;
+    let name = "set-cookie";
+    let cookies: Vec<&str> = headers.get_all(header::SET_COOKIE).iter().filter_map(|v| v.to_str().ok()).collect();
+    if cookies.is_empty() {
+        return Finding::new(name, "ok", None, "No cookies set", POINTS, POINTS);
+    }
+    let mut problems = Vec::new();
+    for cookie in &cookies {
+        let mut parts = cookie.split(';');
+        let cookie_name = parts.next().and_then(|pair| pair.split_once('=')).map_or("?", |(n, _)| n.trim());
+        let attributes: Vec<String> = parts.map(|a| a.split('=').next().unwrap_or_default().trim().to_ascii_lowercase()).collect();
+        let missing: Vec<&str> = [("Secure", https), ("HttpOnly", true), ("SameSite", true)]
+            .into_iter()
+            .filter(|(attribute, required)| *required && !attributes.contains(&attribute.to_ascii_lowercase()))
+            .map(|(attribute, _)| attribute)
+            .collect();
+        if !missing.is_empty() {
+            problems.push(format!("{} lacks {}", cookie_name, missing.join(", ")));
+        }
+    }
+    let points = POINTS * (cookies.len() - problems.len()) as u32 / cookies.len() as u32;
+    if problems.is_empty() {
+        Finding::new(name, "ok", None, format!("{} cookie(s), all flagged", cookies.len()), points, POINTS)
+    } else {
+        Finding::new(name, "warning", None, problems.join("; "), points, POINTS)
+    }
+}
+
+/// Value of the `name` directive of a `;`-separated header (HSTS, CSP), case-insensitive.
+fn directive<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.split(';').find_map(|part| {
+        let part = part.trim();
+        let (key, value) = part.split_once(|c: char| c == '=' || c.is_whitespace()).unwrap_or((part, ""));
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
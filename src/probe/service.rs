@@ -0,0 +1,477 @@
+//! Protocol-aware TCP stage for services on their well-known ports (or `--service`).
+//!
+//! SMTP (25, 587), IMAP (143) and POP3 (110) are greeted in clear and upgraded
+//! with STARTTLS (`STLS` for POP3) when the server offers it; their implicit-TLS
+//! ports (465, 993, 995) are greeted after the handshake of the TLS stage. FTP (21,
+//! 990) is greeted and upgraded with `AUTH TLS` likewise (see [`ftp`]). SSH
+//! (22) is described by [`ssh`], databases (3306, 5432, 6379, 27017) by
+//! [`database`]. Only greetings and capability lists are exchanged: nothing logs in,
+//! except MQTT (1883, 8883 over TLS), whose CONNECT is the greeting (see [`mqtt`]).
+//! Kafka brokers (9092) list their APIs in answer to ApiVersions (see [`kafka`]).
+//! LDAP (389, 636 over TLS) answers a bind and StartTLS (see [`ldap`]), SIP over
+//! TCP or TLS (5061) an OPTIONS request (see [`sip`]). RDP (3389) and SMB (445)
+//! answer the first message of a session (see [`rdp`], [`smb`]): a port that is
+//! open but filtered beyond the handshake fails there.
+//! NTP (123) is a UDP service: it runs as the UDP probe instead (see [`super::ntp`]).
+
+use super::database;
+use super::error_code::ErrorCode;
+use super::ftp::{self, FtpLogin};
+use super::kafka::{self, KafkaResult};
+use super::ldap;
+use super::mqtt;
+use super::rdp;
+use super::sip;
+use super::smb;
+use super::smtp;
+use super::ssh::{self, SshResult};
+use super::tls::{self, TlsResult};
+use super::udp::UdpPayload;
+use super::ProbeOptions;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// Longest line accepted from the server.
+const MAX_LINE: usize = 8 * 1024;
+/// Most lines read for one IMAP or POP3 response.
+const MAX_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Smtp,
+    /// SMTP over implicit TLS (465).
+    Smtps,
+    Imap,
+    Imaps,
+    Pop3,
+    Pop3s,
+    Ftp,
+    /// FTP over implicit TLS (990).
+    Ftps,
+    Ssh,
+    Postgres,
+    Mysql,
+    Redis,
+    Mongodb,
+    Mqtt,
+    /// MQTT over implicit TLS (8883).
+    Mqtts,
+    Kafka,
+    Ldap,
+    /// LDAP over implicit TLS (636).
+    Ldaps,
+    /// SIP over UDP (5060), reported in `ProbeResult.udp`.
+    Sip,
+    SipTcp,
+    /// SIP over implicit TLS (5061).
+    Sips,
+    Rdp,
+    Smb,
+    /// SNMP over UDP (161), reported in `ProbeResult.udp`.
+    Snmp,
+    /// SNTP over UDP, reported in `ProbeResult.udp`.
+    Ntp,
+}
+
+impl Service {
+    /// The service expected on a well-known port.
+    pub fn for_port(port: u16) -> Option<Service> {
+        match port {
+            22 => Some(Service::Ssh),
+            25 | 587 => Some(Service::Smtp),
+            465 => Some(Service::Smtps),
+            143 => Some(Service::Imap),
+            993 => Some(Service::Imaps),
+            110 => Some(Service::Pop3),
+            995 => Some(Service::Pop3s),
+            21 => Some(Service::Ftp),
+            990 => Some(Service::Ftps),
+            3306 => Some(Service::Mysql),
+            5432 => Some(Service::Postgres),
+            6379 => Some(Service::Redis),
+            27017 => Some(Service::Mongodb),
+            1883 => Some(Service::Mqtt),
+            8883 => Some(Service::Mqtts),
+            9092 => Some(Service::Kafka),
+            389 => Some(Service::Ldap),
+            636 => Some(Service::Ldaps),
+            5060 => Some(Service::Sip),
+            5061 => Some(Service::Sips),
+            3389 => Some(Service::Rdp),
+            445 => Some(Service::Smb),
+            123 => Some(Service::Ntp),
+            161 => Some(Service::Snmp),
+            _ => None,
+        }
+    }
+
+    /// Protocol spoken once the connection (and its TLS, if implicit) is up.
+    pub fn protocol(self) -> &'static str {
+        match self {
+            Service::Smtp | Service::Smtps => "smtp",
+            Service::Imap | Service::Imaps => "imap",
+            Service::Pop3 | Service::Pop3s => "pop3",
+            Service::Ftp | Service::Ftps => "ftp",
+            Service::Ssh => "ssh",
+            Service::Postgres => "postgres",
+            Service::Mysql => "mysql",
+            Service::Redis => "redis",
+            Service::Mongodb => "mongodb",
+            Service::Mqtt | Service::Mqtts => "mqtt",
+            Service::Kafka => "kafka",
+            Service::Ldap | Service::Ldaps => "ldap",
+            Service::Sip | Service::SipTcp | Service::Sips => "sip",
+            Service::Rdp => "rdp",
+            Service::Smb => "smb",
+            Service::Snmp => "snmp",
+            Service::Ntp => "ntp",
+        }
+    }
+
+    /// Datagram of a service spoken over UDP: the TCP, TLS and HTTP stages make way for the UDP probe.
+    pub fn udp_payload(self) -> Option<UdpPayload> {
+        match self {
+            Service::Ntp => Some(UdpPayload::Ntp),
+            Service::Sip => Some(UdpPayload::Sip),
+            Service::Snmp => Some(UdpPayload::Snmp(super::snmp::DEFAULT_COMMUNITY.to_string())),
+            _ => None,
+        }
+    }
+
+    /// TLS starts with the connection rather than after STARTTLS.
+    pub fn implicit_tls(self) -> bool {
+        matches!(self, Service::Smtps | Service::Imaps | Service::Pop3s | Service::Ftps | Service::Mqtts | Service::Ldaps | Service::Sips)
+    }
+
+    /// The TLS stage has something to report: an upgrade or an implicit handshake.
+    pub fn uses_tls(self) -> bool {
+        !matches!(self, Service::Ssh | Service::Redis | Service::Mongodb | Service::Mqtt | Service::Kafka | Service::Sip | Service::SipTcp | Service::Rdp | Service::Smb | Service::Snmp | Service::Ntp)
+    }
+
+    /// Mail and directory servers are expected to offer STARTTLS: without it the TLS stage fails.
+    /// Databases on private networks often go without, so there it is only reported.
+    pub fn requires_starttls(self) -> bool {
+        matches!(self.protocol(), "smtp" | "imap" | "pop3" | "ldap")
+    }
+
+    /// Protocols offered in ALPN by the TLS handshake.
+    pub(crate) fn alpn(self) -> Vec<Vec<u8>> {
+        match self {
+            Service::Postgres => vec![b"postgresql".to_vec()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Polite end of a session that is not upgraded.
+    fn quit(self) -> Option<&'static str> {
+        match self.protocol() {
+            "imap" => Some("A9 LOGOUT"),
+            "smtp" | "pop3" | "ftp" | "redis" => Some("QUIT"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceResult {
+    pub protocol: String, // "smtp" | "imap" | "pop3" | "ftp" | "ssh" | "postgres" | "mysql" | "redis" | "mongodb" | "mqtt" | "kafka" | "ldap" | "sip" | "rdp" | "smb"
+    pub status: String,   // "ok" | "error" | "skipped" (implicit TLS failed)
+    /// "starttls" (upgraded in the session) or "implicit" (TLS from the first byte);
+    /// `None` for protocols probed without TLS (SSH, Redis, MongoDB, MQTT on 1883, Kafka, SIP over TCP, RDP, SMB).
+    pub tls_mode: Option<String>,
+    /// The greeting, without the status code or `* OK` / `+OK`; the identification
+    /// line for SSH, the answer to PING for Redis, the CONNACK or bind result code for MQTT and LDAP.
+    pub banner: Option<String>,
+    /// Server version, when the handshake tells it (MySQL, Redis, MongoDB; the `Server` header of SIP;
+    /// the dialect of SMB).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// From the connection being usable (after the handshake on implicit-TLS ports)
+    /// to the server's first answer: its greeting, or the reply to the first request.
+    pub greeting_ms: Option<f64>,
+    /// Announced in answer to EHLO, CAPABILITY or CAPA; flags of the database handshakes.
+    pub capabilities: Vec<String>,
+    /// STARTTLS (STLS, AUTH TLS, SSLRequest...) was offered; `None` on implicit-TLS ports.
+    pub starttls: Option<bool>,
+    /// Algorithms and host keys (`ssh` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh: Option<SshResult>,
+    /// APIs and version ranges served by the broker (`kafka` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kafka: Option<KafkaResult>,
+    /// Anonymous login (`ftp` with `--ftp-anonymous` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ftp_login: Option<FtpLogin>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "SERVICE_PROTOCOL".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+}
+
+impl ServiceResult {
+    pub(crate) fn pending(service: Service) -> Self {
+        let tls_mode = match service.implicit_tls() {
+            true => "implicit",
+            false => "starttls",
+        };
+        ServiceResult {
+            protocol: service.protocol().to_string(),
+            status: "error".to_string(),
+            tls_mode: service.uses_tls().then(|| tls_mode.to_string()),
+            banner: None,
+            version: None,
+            greeting_ms: None,
+            capabilities: Vec::new(),
+            starttls: None,
+            ssh: None,
+            kafka: None,
+            ftp_login: None,
+            error: None,
+            error_code: None,
+        }
+    }
+
+    pub(crate) fn skipped(service: Service) -> Self {
+        ServiceResult { status: "skipped".to_string(), ..ServiceResult::pending(service) }
+    }
+
+    fn fail(&mut self, code: ErrorCode, error: String) {
+        self.error = Some(error);
+        self.error_code = Some(code);
+    }
+}
+
+/// Greet `service` on `addr`. On STARTTLS ports the upgraded handshake (verified
+/// for `host`) is returned as well; it stands for the TLS stage of the probe.
+pub async fn probe(addr: SocketAddr, host: &str, service: Service, options: &ProbeOptions) -> (ServiceResult, Option<TlsResult>) {
+    let mut result = ServiceResult::pending(service);
+    let timeout = options.timeout;
+    let mut stream = match tokio::time::timeout(options.connect_timeout(), options.source.connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            result.fail(ErrorCode::ServiceConnect, e.to_string());
+            return (result, None);
+        },
+        Err(_) => {
+            result.fail(ErrorCode::ServiceTimeout, "Connection timed out".to_string());
+            return (result, None);
+        },
+    };
+    tracing::debug!(%addr, protocol = service.protocol(), "greeting service");
+
+    if service == Service::Ssh {
+        match ssh::probe(&mut stream, addr, &mut result, options).await {
+            Ok(ssh) => {
+                result.status = "ok".to_string();
+                result.ssh = Some(ssh);
+            },
+            Err(e) => result.fail(ErrorCode::ServiceProtocol, e),
+        }
+        return (result, None);
+    }
+    if service.implicit_tls() {
+        // The TLS stage already reported on the certificate; this connection only carries the greeting.
+        let local = stream.local_addr();
+        let mut stream = match connect_tls(stream, host, options).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                result.fail(ErrorCode::ServiceTls, e);
+                return (result, None);
+            },
+        };
+        let greeted = match service {
+            Service::Mqtts => mqtt::probe(&mut stream, &mut result, options, timeout).await.map(|_| false),
+            Service::Ldaps => ldap::probe_tls(&mut stream, &mut result, options).await.map(|_| false),
+            Service::Ftps => ftp::probe_tls(&mut stream, &mut result, options).await.map(|_| false),
+            Service::Sips => match local {
+                Ok(local) => sip::probe(&mut stream, "TLS", local, &sip_target(host, addr), &mut result, timeout).await.map(|_| false),
+                Err(e) => Err(e.to_string()),
+            },
+            _ => dialogue(&mut stream, service, &mut result, false, timeout).await,
+        };
+        match greeted {
+            Ok(_) => {
+                result.status = "ok".to_string();
+                if let Some(quit) = service.quit() {
+                    let _ = write_line(&mut stream, quit).await;
+                }
+            },
+            Err(e) => result.fail(ErrorCode::ServiceProtocol, e),
+        }
+        return (result, None);
+    }
+
+    let upgrade = match service {
+        Service::Postgres | Service::Mysql | Service::Redis | Service::Mongodb => database::probe(&mut stream, service, &mut result, timeout).await,
+        Service::Mqtt => mqtt::probe(&mut stream, &mut result, options, timeout).await.map(|_| false),
+        Service::Kafka => kafka::probe(&mut stream, &mut result, timeout).await.map(|_| false),
+        Service::Ldap => ldap::probe(&mut stream, addr, host, &mut result, options).await,
+        Service::Ftp => ftp::probe(&mut stream, addr, &mut result, options).await,
+        Service::Rdp => rdp::probe(&mut stream, &mut result, timeout).await.map(|_| false),
+        Service::Smb => smb::probe(&mut stream, &mut result, timeout).await.map(|_| false),
+        Service::SipTcp => match stream.local_addr() {
+            Ok(local) => sip::probe(&mut stream, "TCP", local, &sip_target(host, addr), &mut result, timeout).await.map(|_| false),
+            Err(e) => Err(e.to_string()),
+        },
+        _ => dialogue(&mut stream, service, &mut result, true, timeout).await,
+    };
+    match upgrade {
+        Ok(true) => {
+            // Resumption needs a second STARTTLS dialogue; it is not tried here.
+            let options = ProbeOptions { check_resumption: false, ..options.clone() };
+            let handshake = tls::handshake_on(stream, host, service.alpn(), &options).await;
+            result.status = "ok".to_string();
+            (result, Some(handshake))
+        },
+        Ok(false) => {
+            result.status = "ok".to_string();
+            if let Some(quit) = service.quit() {
+                let _ = write_line(&mut stream, quit).await;
+            }
+            (result, None)
+        },
+        Err(e) => {
+            result.fail(ErrorCode::ServiceProtocol, e);
+            (result, None)
+        },
+    }
+}
+
+/// Request URI host of a SIP OPTIONS: the name and port the user gave.
+fn sip_target(host: &str, addr: SocketAddr) -> String {
+    match host.contains(':') {
+        true => format!("[{}]:{}", host.trim_matches(['[', ']']), addr.port()),
+        false => format!("{}:{}", host, addr.port()),
+    }
+}
+
+/// TLS for `host` on a connection of the service's own, verified like the TLS stage that reports on it.
+pub(super) async fn connect_tls(stream: TcpStream, host: &str, options: &ProbeOptions) -> Result<TlsStream<TcpStream>, String> {
+    let config = rustls::ClientConfig::builder().with_safe_defaults().with_custom_certificate_verifier(tls::verifier(options)).with_client_cert_resolver(tls::ClientAuth::new(options));
+    let connect = async { TlsConnector::from(Arc::new(config)).connect(tls::server_name(host)?, stream).await.map_err(|e| format!("TLS handshake failed: {}", e)) };
+    tokio::time::timeout(options.tls_timeout(), connect).await.map_err(|_| "Handshake timed out".to_string())?
+}
+
+/// Greeting, capabilities and, when `starttls` and offered, the upgrade command.
+/// True when the connection is ready for the TLS handshake.
+async fn dialogue<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, service: Service, result: &mut ServiceResult, starttls: bool, timeout: Duration) -> Result<bool, String> {
+    let started = Instant::now();
+    let upgrade_command = match service.protocol() {
+        "smtp" => {
+            let (code, lines) = smtp::read_reply(stream, timeout).await?;
+            result.banner = Some(lines.join(" "));
+            result.greeting_ms = Some(smtp::ms_since(started));
+            if code != 220 {
+                return Err(format!("Server refused the session: {} {}", code, lines.join(" ")));
+            }
+            let (code, lines) = smtp::command(stream, "EHLO localhost", timeout).await?;
+            if code != 250 {
+                return Err(format!("EHLO rejected: {} {}", code, lines.join(" ")));
+            }
+            // The first line is the server's name; the others are extensions.
+            result.capabilities = lines.into_iter().skip(1).collect();
+            "STARTTLS"
+        },
+        "imap" => {
+            let greeting = read_line(stream, timeout).await?;
+            result.greeting_ms = Some(smtp::ms_since(started));
+            let Some(banner) = greeting.strip_prefix("* OK").or_else(|| greeting.strip_prefix("* PREAUTH")) else {
+                return Err(format!("Server refused the session: {}", greeting));
+            };
+            result.banner = Some(banner.trim().to_string());
+            let (status, lines) = imap_command(stream, "A1 CAPABILITY", timeout).await?;
+            if !status.starts_with("OK") {
+                return Err(format!("CAPABILITY rejected: {}", status));
+            }
+            result.capabilities = lines.iter().filter_map(|line| line.strip_prefix("* CAPABILITY ")).flat_map(|line| line.split_whitespace().map(str::to_string)).collect();
+            "A2 STARTTLS"
+        },
+        _ => {
+            let greeting = read_line(stream, timeout).await?;
+            result.greeting_ms = Some(smtp::ms_since(started));
+            let Some(banner) = greeting.strip_prefix("+OK") else {
+                return Err(format!("Server refused the session: {}", greeting));
+            };
+            result.banner = Some(banner.trim().to_string());
+            write_line(stream, "CAPA").await?;
+            // Servers without CAPA (RFC 2449) answer -ERR: no capabilities, not a failure.
+            if read_line(stream, timeout).await?.starts_with("+OK") {
+                for _ in 0..MAX_LINES {
+                    match read_line(stream, timeout).await? {
+                        line if line == "." => break,
+                        line => result.capabilities.push(line),
+                    }
+                }
+            }
+            "STLS"
+        },
+    };
+    if !starttls {
+        return Ok(false);
+    }
+
+    let offered = result.capabilities.iter().any(|capability| {
+        let name = capability.split_whitespace().next().unwrap_or_default();
+        name.eq_ignore_ascii_case("STARTTLS") || name.eq_ignore_ascii_case("STLS")
+    });
+    result.starttls = Some(offered);
+    if !offered {
+        return Ok(false);
+    }
+    let refused = match service.protocol() {
+        "smtp" => match smtp::command(stream, upgrade_command, timeout).await? {
+            (220, _) => None,
+            (code, lines) => Some(format!("{} {}", code, lines.join(" "))),
+        },
+        "imap" => Some(imap_command(stream, upgrade_command, timeout).await?.0).filter(|status| !status.starts_with("OK")),
+        _ => {
+            write_line(stream, upgrade_command).await?;
+            Some(read_line(stream, timeout).await?).filter(|reply| !reply.starts_with("+OK"))
+        },
+    };
+    match refused {
+        Some(reply) => Err(format!("STARTTLS rejected: {}", reply)),
+        None => Ok(true),
+    }
+}
+
+/// Send a tagged IMAP command; the untagged lines and the status after the tag.
+async fn imap_command<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, line: &str, timeout: Duration) -> Result<(String, Vec<String>), String> {
+    write_line(stream, line).await?;
+    let tag = line.split(' ').next().unwrap_or_default();
+    let mut lines = Vec::new();
+    for _ in 0..MAX_LINES {
+        let reply = read_line(stream, timeout).await?;
+        match reply.strip_prefix(tag).and_then(|rest| rest.strip_prefix(' ')) {
+            Some(status) => return Ok((status.to_string(), lines)),
+            None => lines.push(reply),
+        }
+    }
+    Err("Response too long".to_string())
+}
+
+pub(super) async fn write_line<S: AsyncWrite + Unpin>(stream: &mut S, line: &str) -> Result<(), String> {
+    stream.write_all(format!("{}\r\n", line).as_bytes()).await.map_err(|e| e.to_string())
+}
+
+/// Read one line, without its CRLF. Bytes are read one at a time so nothing
+/// past it is consumed before the TLS handshake.
+pub(super) async fn read_line<S: AsyncRead + Unpin>(stream: &mut S, timeout: Duration) -> Result<String, String> {
+    let read = async {
+        let mut line = Vec::new();
+        loop {
+            match stream.read_u8().await.map_err(|_| "Connection closed by the server".to_string())? {
+                b'\n' => return Ok(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string()),
+                _ if line.len() >= MAX_LINE => return Err("Line too long".to_string()),
+                byte => line.push(byte),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, read).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))?
+}
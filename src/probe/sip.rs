@@ -0,0 +1,143 @@
+//! SIP server check: one OPTIONS request (RFC 3261, 11), over UDP (`--service sip`,
+//! port 5060, or `--udp sip`), TCP (`--service sip-tcp`) or TLS (`--service sips`, 5061).
+//!
+//! OPTIONS asks a server what it supports without setting up a call, so any
+//! answer proves a SIP stack is listening. Authentication challenges (401, 407)
+//! and refusals (403, 404, 405) still do; only 5xx and 6xx answers, where the
+//! server is up but cannot serve, fail the probe.
+
+use super::service::ServiceResult;
+use super::smtp::ms_since;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Longest response head accepted over TCP and TLS.
+const MAX_HEAD: usize = 16 * 1024;
+/// Provisional (1xx) responses skipped before the final one.
+const MAX_PROVISIONAL: usize = 5;
+
+/// The parts of a response worth reporting.
+pub(crate) struct SipResponse {
+    pub code: u16,
+    pub reason: String,
+    /// `Server` or `User-Agent` header.
+    pub server: Option<String>,
+    /// Methods in the `Allow` header.
+    pub allow: Vec<String>,
+}
+
+impl SipResponse {
+    /// "200 OK".
+    pub(crate) fn status(&self) -> String {
+        format!("{} {}", self.code, self.reason)
+    }
+
+    /// Why the answer fails the probe: 5xx and 6xx.
+    pub(crate) fn failure(&self) -> Option<String> {
+        (self.code >= 500).then(|| format!("Server answered OPTIONS with {}", self.status()))
+    }
+}
+
+/// An OPTIONS request for `target`, sent over `transport` ("UDP", "TCP" or "TLS") from `local`.
+pub(crate) fn options_request(transport: &str, local: SocketAddr, target: &str) -> Vec<u8> {
+    let mut random = [0u8; 12];
+    // Unique values only need to differ between probes; a failed fill leaves zeros.
+    let _ = SystemRandom::new().fill(&mut random);
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    format!(
+        "OPTIONS sip:{target} SIP/2.0\r\n\
+         Via: SIP/2.0/{transport} {local};branch=z9hG4bK{branch};rport\r\n\
+         Max-Forwards: 70\r\n\
+         From: <sip:netprobe@{local}>;tag={tag}\r\n\
+         To: <sip:{target}>\r\n\
+         Call-ID: {call_id}@netprobe\r\n\
+         CSeq: 1 OPTIONS\r\n\
+         Contact: <sip:netprobe@{local};transport={lower}>\r\n\
+         Accept: application/sdp\r\n\
+         User-Agent: netprobe/{version}\r\n\
+         Content-Length: 0\r\n\r\n",
+        lower = transport.to_lowercase(),
+        branch = hex(&random[..4]),
+        tag = hex(&random[4..8]),
+        call_id = hex(&random[8..]),
+        version = env!("CARGO_PKG_VERSION"),
+    )
+    .into_bytes()
+}
+
+/// Parse the head of a SIP response (a UDP datagram, or the head read over TCP).
+pub(crate) fn parse(reply: &[u8]) -> Result<SipResponse, String> {
+    let text = String::from_utf8_lossy(reply);
+    let mut lines = text.lines();
+    let status = lines.next().unwrap_or_default();
+    let not_sip = || format!("Not a SIP response: {}", status.chars().take(60).collect::<String>());
+    let rest = status.strip_prefix("SIP/2.0 ").ok_or_else(not_sip)?;
+    let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+    let code = code.parse().map_err(|_| not_sip())?;
+    let mut response = SipResponse { code, reason: reason.trim().to_string(), server: None, allow: Vec::new() };
+    for line in lines.take_while(|line| !line.is_empty()) {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "server" | "user-agent" if response.server.is_none() => response.server = Some(value.trim().to_string()),
+            "allow" => response.allow.extend(value.split(',').map(|method| method.trim().to_string()).filter(|method| !method.is_empty())),
+            _ => {},
+        }
+    }
+    Ok(response)
+}
+
+/// OPTIONS over a TCP or TLS connection from `local` to `target`; the answer goes in `result`.
+pub(super) async fn probe<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, transport: &str, local: SocketAddr, target: &str, result: &mut ServiceResult, timeout: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    stream.write_all(&options_request(transport, local, target)).await.map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream);
+    let mut provisional = 0;
+    let response = loop {
+        let head = tokio::time::timeout(timeout, read_head(&mut reader)).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))??;
+        let response = parse(&head)?;
+        if response.code >= 200 || provisional == MAX_PROVISIONAL {
+            break response;
+        }
+        provisional += 1;
+        // A provisional answer carries no body worth reading; skip it by its length.
+        let length = content_length(&head);
+        let mut body = vec![0; length.min(MAX_HEAD)];
+        reader.read_exact(&mut body).await.map_err(|e| e.to_string())?;
+    };
+    result.greeting_ms = Some(ms_since(started));
+    result.banner = Some(response.status());
+    result.version = response.server.clone();
+    result.capabilities = response.allow.clone();
+    match response.failure() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Lines up to the blank one that ends a message head.
+async fn read_head<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<Vec<u8>, String> {
+    let mut head = Vec::new();
+    loop {
+        let read = reader.read_until(b'\n', &mut head).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("Connection closed by the server".to_string());
+        }
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            return Ok(head);
+        }
+        if head.len() > MAX_HEAD {
+            return Err(format!("Not a SIP response: no end of headers in {} bytes", MAX_HEAD));
+        }
+    }
+}
+
+fn content_length(head: &[u8]) -> usize {
+    String::from_utf8_lossy(head)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length") || name.trim().eq_ignore_ascii_case("l"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,142 @@
+//! SMB file server check (`--service smb`, port 445): one SMB2 NEGOTIATE
+//! ([MS-SMB2] 2.2.3) offering dialects 2.0.2 to 3.1.1.
+//!
+//! The answer names the dialect the server picked, whether it requires
+//! signing, and its capabilities (encryption, multichannel...). Like RDP, it
+//! tells a server that answers from a port that is merely open. No session is
+//! set up: nothing authenticates.
+
+use super::service::ServiceResult;
+use super::smtp::ms_since;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const SMB2_MAGIC: [u8; 4] = [0xfe, b'S', b'M', b'B'];
+const SMB1_MAGIC: [u8; 4] = [0xff, b'S', b'M', b'B'];
+const HEADER: usize = 64;
+const DIALECTS: [u16; 5] = [0x0202, 0x0210, 0x0300, 0x0302, 0x0311];
+const SECURITY_SIGNING_ENABLED: u16 = 0x1;
+const SECURITY_SIGNING_REQUIRED: u16 = 0x2;
+/// SMB2_PREAUTH_INTEGRITY_CAPABILITIES, required with dialect 3.1.1.
+const CONTEXT_PREAUTH_INTEGRITY: u16 = 1;
+const HASH_SHA512: u16 = 1;
+/// Largest response accepted; a negotiate response with its security blob is a few hundred bytes.
+const MAX_RESPONSE: usize = 64 * 1024;
+
+/// Send NEGOTIATE and report the dialect, signing and capabilities of the answer.
+pub(super) async fn probe<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, result: &mut ServiceResult, timeout: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    stream.write_all(&negotiate_request()).await.map_err(|e| e.to_string())?;
+    let reply = tokio::time::timeout(timeout, read_message(stream)).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))??;
+    result.greeting_ms = Some(ms_since(started));
+
+    if reply.starts_with(&SMB1_MAGIC) {
+        result.banner = Some("Dialect 1 only (SMB2 and later not supported)".to_string());
+        result.version = Some("1".to_string());
+        return Ok(());
+    }
+    let not_smb = || "Not an SMB server: invalid NEGOTIATE response".to_string();
+    if !reply.starts_with(&SMB2_MAGIC) || reply.len() < HEADER + 8 {
+        return Err(not_smb());
+    }
+    let status = u32::from_le_bytes(reply[8..12].try_into().map_err(|_| not_smb())?);
+    if status != 0 {
+        return Err(format!("Server refused NEGOTIATE: NTSTATUS 0x{:08x}", status));
+    }
+    let body = &reply[HEADER..];
+    let u16_at = |at: usize| body.get(at..at + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]));
+    let security_mode = u16_at(2).ok_or_else(not_smb)?;
+    let dialect = u16_at(4).ok_or_else(not_smb)?;
+    let capabilities = body.get(24..28).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).ok_or_else(not_smb)?;
+
+    let version = dialect_name(dialect);
+    let signing = match security_mode {
+        mode if mode & SECURITY_SIGNING_REQUIRED != 0 => "signing required",
+        mode if mode & SECURITY_SIGNING_ENABLED != 0 => "signing enabled",
+        _ => "signing disabled",
+    };
+    result.banner = Some(format!("Dialect {}, {}", version, signing));
+    result.version = Some(version);
+    result.capabilities = CAPABILITIES.iter().filter(|(flag, _)| capabilities & flag != 0).map(|(_, name)| name.to_string()).collect();
+    Ok(())
+}
+
+/// NEGOTIATE with a preauth integrity context, framed for direct TCP (a 4-byte length).
+fn negotiate_request() -> Vec<u8> {
+    let mut message = SMB2_MAGIC.to_vec();
+    message.extend(64u16.to_le_bytes()); // StructureSize
+    message.resize(14, 0); // CreditCharge, Status, Command (0: NEGOTIATE)
+    message.extend(1u16.to_le_bytes()); // CreditRequest
+    message.resize(HEADER, 0); // Flags, NextCommand, MessageId, TreeId, SessionId, Signature
+
+    let mut guid = [0u8; 16];
+    let mut salt = [0u8; 32];
+    // Both only need to vary between clients; a failed fill leaves zeros.
+    let rng = SystemRandom::new();
+    let _ = rng.fill(&mut guid);
+    let _ = rng.fill(&mut salt);
+    // Negotiate contexts start 8-byte aligned after the dialects.
+    let context_offset = (HEADER + 36 + DIALECTS.len() * 2).next_multiple_of(8);
+    message.extend(36u16.to_le_bytes());
+    message.extend((DIALECTS.len() as u16).to_le_bytes());
+    message.extend(SECURITY_SIGNING_ENABLED.to_le_bytes());
+    message.extend([0; 2]); // Reserved
+    message.extend([0; 4]); // Capabilities
+    message.extend(guid);
+    message.extend((context_offset as u32).to_le_bytes());
+    message.extend(1u16.to_le_bytes()); // NegotiateContextCount
+    message.extend([0; 2]); // Reserved2
+    for dialect in DIALECTS {
+        message.extend(dialect.to_le_bytes());
+    }
+    message.resize(context_offset, 0);
+
+    let mut preauth = 1u16.to_le_bytes().to_vec(); // HashAlgorithmCount
+    preauth.extend((salt.len() as u16).to_le_bytes());
+    preauth.extend(HASH_SHA512.to_le_bytes());
+    preauth.extend(salt);
+    message.extend(CONTEXT_PREAUTH_INTEGRITY.to_le_bytes());
+    message.extend((preauth.len() as u16).to_le_bytes());
+    message.extend([0; 4]); // Reserved
+    message.extend(preauth);
+
+    let mut packet = (message.len() as u32).to_be_bytes().to_vec();
+    packet.extend(message);
+    packet
+}
+
+/// One message after its 4-byte direct TCP header (a zero byte, then a 24-bit length).
+async fn read_message<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, String> {
+    let closed = |_| "Connection closed by the server (not SMB, or refused by its policy)".to_string();
+    let header = stream.read_u32().await.map_err(closed)?;
+    let length = (header & 0x00ff_ffff) as usize;
+    if header >> 24 != 0 || length > MAX_RESPONSE {
+        return Err("Not an SMB server: invalid message header".to_string());
+    }
+    let mut message = vec![0; length];
+    stream.read_exact(&mut message).await.map_err(closed)?;
+    Ok(message)
+}
+
+/// Global capabilities of an SMB2 NEGOTIATE response.
+const CAPABILITIES: [(u32, &str); 7] = [
+    (0x01, "DFS"),
+    (0x02, "LEASING"),
+    (0x04, "LARGE_MTU"),
+    (0x08, "MULTI_CHANNEL"),
+    (0x10, "PERSISTENT_HANDLES"),
+    (0x20, "DIRECTORY_LEASING"),
+    (0x40, "ENCRYPTION"),
+];
+
+fn dialect_name(dialect: u16) -> String {
+    match dialect {
+        0x0202 => "2.0.2".to_string(),
+        0x0210 => "2.1".to_string(),
+        0x0300 => "3.0".to_string(),
+        0x0302 => "3.0.2".to_string(),
+        0x0311 => "3.1.1".to_string(),
+        other => format!("0x{:04x}", other),
+    }
+}
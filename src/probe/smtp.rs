@@ -0,0 +1,155 @@
+//! SMTP session: greeting, EHLO and STARTTLS upgrade (`netprobe mail`).
+//!
+//! The dialogue stops after the TLS handshake: no mail transaction is started,
+//! so probing a server leaves nothing in its queue.
+
+use super::error_code::{CodedError, ErrorCode, WithCode};
+use super::tls::{self, TlsResult};
+use super::ProbeOptions;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Longest reply accepted, all lines included.
+const MAX_REPLY: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SmtpResult {
+    pub status: String, // "ok" | "error"
+    pub address: String,
+    pub connect_ms: Option<f64>,
+    /// The 220 greeting, without the code.
+    pub banner: Option<String>,
+    /// From the connection to the end of the greeting.
+    pub banner_ms: Option<f64>,
+    /// Extensions announced in answer to EHLO (`STARTTLS`, `SIZE 35882577`...).
+    pub extensions: Vec<String>,
+    /// STARTTLS was announced.
+    pub starttls: bool,
+    /// Handshake after STARTTLS, with the certificate and its verification.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsResult>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "SMTP_REJECTED".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+}
+
+impl SmtpResult {
+    fn pending(addr: SocketAddr) -> Self {
+        SmtpResult { status: "error".to_string(), address: addr.to_string(), connect_ms: None, banner: None, banner_ms: None, extensions: Vec::new(), starttls: false, tls: None, error: None, error_code: None }
+    }
+
+    fn fail(&mut self, code: ErrorCode, error: String) {
+        self.error = Some(error);
+        self.error_code = Some(code);
+    }
+}
+
+/// Connect to `addr`, read the greeting, say EHLO as `helo` and upgrade with
+/// STARTTLS when offered, verifying the certificate for `host`.
+pub async fn probe(addr: SocketAddr, host: &str, helo: &str, options: &ProbeOptions) -> SmtpResult {
+    let mut result = SmtpResult::pending(addr);
+    let started = Instant::now();
+    let mut stream = match tokio::time::timeout(options.connect_timeout(), options.source.connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            result.fail(ErrorCode::SmtpConnect, e.to_string());
+            return result;
+        },
+        Err(_) => {
+            result.fail(ErrorCode::SmtpTimeout, "Connection timed out".to_string());
+            return result;
+        },
+    };
+    result.connect_ms = Some(ms_since(started));
+
+    let timeout = options.timeout;
+    match session(&mut stream, &mut result, helo, timeout, started).await {
+        Ok(true) => {
+            // Resumption needs a second STARTTLS dialogue; it is not tried here.
+            let options = ProbeOptions { check_resumption: false, ..options.clone() };
+            let handshake = tls::handshake_on(stream, host, Vec::new(), &options).await;
+            result.status = if handshake.status == "error" { "error" } else { "ok" }.to_string();
+            if let Some(e) = &handshake.error {
+                result.fail(ErrorCode::SmtpTls, format!("TLS after STARTTLS: {}", e));
+            }
+            result.tls = Some(handshake);
+        },
+        Ok(false) => {
+            result.status = "ok".to_string();
+            let _ = command(&mut stream, "QUIT", timeout).await;
+        },
+        Err(e) => result.fail(e.code, e.message),
+    }
+    result
+}
+
+/// Greeting, EHLO and STARTTLS; true when the connection is ready for the TLS handshake.
+async fn session(stream: &mut TcpStream, result: &mut SmtpResult, helo: &str, timeout: Duration, started: Instant) -> Result<bool, CodedError> {
+    let (code, lines) = read_reply(stream, timeout).await.code(ErrorCode::SmtpProtocol)?;
+    result.banner = Some(lines.join(" "));
+    result.banner_ms = Some(ms_since(started));
+    if code != 220 {
+        return Err(CodedError::new(ErrorCode::SmtpRejected, format!("Server refused the session: {} {}", code, lines.join(" "))));
+    }
+
+    let (code, lines) = command(stream, &format!("EHLO {}", helo), timeout).await.code(ErrorCode::SmtpProtocol)?;
+    if code != 250 {
+        return Err(CodedError::new(ErrorCode::SmtpRejected, format!("EHLO rejected: {} {}", code, lines.join(" "))));
+    }
+    // The first line is the server's name; the others are extensions.
+    result.extensions = lines.into_iter().skip(1).collect();
+    result.starttls = result.extensions.iter().any(|extension| extension.eq_ignore_ascii_case("STARTTLS"));
+    if !result.starttls {
+        return Ok(false);
+    }
+
+    let (code, lines) = command(stream, "STARTTLS", timeout).await.code(ErrorCode::SmtpProtocol)?;
+    if code != 220 {
+        return Err(CodedError::new(ErrorCode::SmtpRejected, format!("STARTTLS rejected: {} {}", code, lines.join(" "))));
+    }
+    Ok(true)
+}
+
+/// Send one command line and read its reply.
+pub(super) async fn command<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, line: &str, timeout: Duration) -> Result<(u16, Vec<String>), String> {
+    stream.write_all(format!("{}\r\n", line).as_bytes()).await.map_err(|e| e.to_string())?;
+    read_reply(stream, timeout).await
+}
+
+/// Read a (possibly multi-line) reply: `250-first`, `250-second`, `250 last`.
+/// Bytes are read one at a time so nothing past the reply is consumed before STARTTLS.
+pub(super) async fn read_reply<S: AsyncRead + Unpin>(stream: &mut S, timeout: Duration) -> Result<(u16, Vec<String>), String> {
+    let read = async {
+        let mut lines = Vec::new();
+        let mut line = Vec::new();
+        let mut total = 0;
+        loop {
+            let byte = stream.read_u8().await.map_err(|_| "Connection closed by the server".to_string())?;
+            total += 1;
+            if total > MAX_REPLY {
+                return Err("Reply too long".to_string());
+            }
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line).trim_end_matches('\r').to_string();
+            line.clear();
+            let code = text.get(..3).and_then(|code| code.parse::<u16>().ok()).ok_or_else(|| format!("Not an SMTP reply: {}", text))?;
+            let last = text.as_bytes().get(3) != Some(&b'-');
+            lines.push(text.get(4..).unwrap_or_default().to_string());
+            if last {
+                return Ok((code, lines));
+            }
+        }
+    };
+    tokio::time::timeout(timeout, read).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))?
+}
+
+pub(super) fn ms_since(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
@@ -0,0 +1,102 @@
+//! SNMP agent check (`--service snmp`, UDP port 161, or `--udp snmp`): one SNMPv2c
+//! GET for sysDescr and sysUpTime, with the `--community` (default "public").
+//!
+//! Agents drop requests whose community they do not accept without answering,
+//! so silence means either no agent or the wrong community.
+
+use super::ber::{self, Reader, NULL, OBJECT_IDENTIFIER, OCTET_STRING, SEQUENCE};
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_COMMUNITY: &str = "public";
+const VERSION_2C: i64 = 1;
+const GET_REQUEST: u8 = 0xa0;
+const GET_RESPONSE: u8 = 0xa2;
+const TIME_TICKS: u8 = 0x43;
+/// 1.3.6.1.2.1.1.1.0 and 1.3.6.1.2.1.1.3.0.
+const SYS_DESCR: [u8; 8] = [0x2b, 6, 1, 2, 1, 1, 1, 0];
+const SYS_UPTIME: [u8; 8] = [0x2b, 6, 1, 2, 1, 1, 3, 0];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SnmpResult {
+    pub version: String,
+    /// sysDescr: typically the vendor, model and firmware.
+    pub sys_descr: Option<String>,
+    /// sysUpTime: since the agent (re)started.
+    pub uptime_seconds: Option<f64>,
+}
+
+/// A GET for sysDescr and sysUpTime; `sent` gives the request id.
+pub(crate) fn request(community: &str, sent: SystemTime) -> Vec<u8> {
+    let mut bindings = Vec::new();
+    for oid in [SYS_DESCR, SYS_UPTIME] {
+        let mut binding = ber::tlv(OBJECT_IDENTIFIER, &oid);
+        binding.extend(ber::tlv(NULL, &[]));
+        bindings.extend(ber::tlv(SEQUENCE, &binding));
+    }
+    let mut pdu = ber::integer(request_id(sent));
+    pdu.extend(ber::integer(0)); // error-status
+    pdu.extend(ber::integer(0)); // error-index
+    pdu.extend(ber::tlv(SEQUENCE, &bindings));
+    let mut message = ber::integer(VERSION_2C);
+    message.extend(ber::tlv(OCTET_STRING, community.as_bytes()));
+    message.extend(ber::tlv(GET_REQUEST, &pdu));
+    ber::tlv(SEQUENCE, &message)
+}
+
+/// Decode the agent's answer to `request(_, sent)`.
+pub(crate) fn decode(reply: &[u8], sent: SystemTime) -> Result<SnmpResult, String> {
+    let not_snmp = || format!("{} bytes, not an SNMP response", reply.len());
+    let message = match Reader(reply).next() {
+        Some((SEQUENCE, message)) => message,
+        _ => return Err(not_snmp()),
+    };
+    let mut message = Reader(message);
+    let (version, pdu) = match (message.next(), message.next(), message.next()) {
+        (Some((_, version)), Some((OCTET_STRING, _)), Some((GET_RESPONSE, pdu))) => (ber::signed(version), pdu),
+        _ => return Err(not_snmp()),
+    };
+    let mut pdu = Reader(pdu);
+    let (id, status, bindings) = match (pdu.next(), pdu.next(), pdu.next(), pdu.next()) {
+        (Some((_, id)), Some((_, status)), Some(_), Some((SEQUENCE, bindings))) => (ber::signed(id), ber::signed(status).unwrap_or(-1), bindings),
+        _ => return Err(not_snmp()),
+    };
+    if id != Some(request_id(sent)) {
+        return Err("Reply does not answer our request (request id differs)".to_string());
+    }
+    if status != 0 {
+        return Err(format!("Agent answered with error {}", error_name(status)));
+    }
+
+    let mut result = SnmpResult { version: if version == Some(VERSION_2C) { "2c" } else { "1" }.to_string(), sys_descr: None, uptime_seconds: None };
+    let mut bindings = Reader(bindings);
+    while let Some((_, binding)) = bindings.next() {
+        let mut binding = Reader(binding);
+        // noSuchObject and noSuchInstance (0x80, 0x81) leave the field empty.
+        match (binding.next(), binding.next()) {
+            (Some((OBJECT_IDENTIFIER, oid)), Some((OCTET_STRING, value))) if oid == SYS_DESCR => result.sys_descr = Some(String::from_utf8_lossy(value).trim().to_string()),
+            (Some((OBJECT_IDENTIFIER, oid)), Some((TIME_TICKS, value))) if oid == SYS_UPTIME => result.uptime_seconds = ber::signed(value).map(|ticks| ticks as f64 / 100.0),
+            _ => {},
+        }
+    }
+    Ok(result)
+}
+
+/// Request id of a request sent at `sent`: positive, and different for each probe.
+fn request_id(sent: SystemTime) -> i64 {
+    i64::from(sent.duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos() & 0x7fff_ffff)
+}
+
+fn error_name(status: i64) -> String {
+    let name = match status {
+        1 => "tooBig",
+        2 => "noSuchName",
+        3 => "badValue",
+        4 => "readOnly",
+        5 => "genErr",
+        6 => "noAccess",
+        16 => "authorizationError",
+        _ => return status.to_string(),
+    };
+    format!("{} ({})", status, name)
+}
@@ -0,0 +1,162 @@
+//! Optional: local end of the probe's connections (`--source-ip`, `--interface`,
+//! `--dscp`).
+//!
+//! On a multi-homed host the routing table decides which way a connection goes
+//! out. `--source-ip` binds the probe's sockets to one of the host's addresses,
+//! `--interface` (Linux only, `SO_BINDTODEVICE`) to a network interface whatever
+//! the routes say. `--dscp` / `--tos` mark every packet sent with a traffic
+//! class, for networks that route or queue by it. DNS lookups are left to the
+//! system and are not affected.
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceBinding {
+    pub ip: Option<IpAddr>,
+    pub interface: Option<String>,
+    /// IPv4 TOS / IPv6 traffic class byte: the DSCP in the upper six bits.
+    pub tos: Option<u8>,
+}
+
+impl SourceBinding {
+    /// Whether the sockets are bound to an address or interface.
+    pub fn is_set(&self) -> bool {
+        self.ip.is_some() || self.interface.is_some()
+    }
+
+    /// DSCP the packets are marked with.
+    pub fn dscp(&self) -> Option<u8> {
+        self.tos.map(|tos| tos >> 2)
+    }
+
+    /// A socket of `target`'s family, set up as asked and not connected yet.
+    pub(crate) fn socket(&self, target: SocketAddr, kind: Type, protocol: Option<Protocol>) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(target), kind, protocol)?;
+        self.apply(&socket, target.ip())?;
+        Ok(socket)
+    }
+
+    /// Mark `socket`, which will talk to `target`, and bind it to the interface and address asked for.
+    pub(crate) fn apply(&self, socket: &Socket, target: IpAddr) -> io::Result<()> {
+        if let Some(tos) = self.tos {
+            set_tos(socket, target, tos)?;
+        }
+        if let Some(interface) = &self.interface {
+            bind_device(socket, interface)?;
+        }
+        if let Some(ip) = self.ip {
+            if ip.is_ipv4() != target.is_ipv4() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Source address {} cannot reach {} (other address family)", ip, target)));
+            }
+            socket.bind(&SocketAddr::new(ip, 0).into()).map_err(|e| io::Error::new(e.kind(), format!("Cannot bind to {}: {}", ip, e)))?;
+        }
+        Ok(())
+    }
+
+    /// Asynchronous TCP connection to `addr` from the bound source.
+    pub(crate) async fn connect(&self, addr: SocketAddr) -> io::Result<tokio::net::TcpStream> {
+        if *self == Self::default() {
+            return tokio::net::TcpStream::connect(addr).await;
+        }
+        let socket = self.socket(addr, Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_nonblocking(true)?;
+        tokio::net::TcpSocket::from_std_stream(socket.into()).connect(addr).await
+    }
+
+    /// Asynchronous TCP connection to `addr` from the bound source, giving up after `timeout`.
+    pub(crate) async fn connect_timeout(&self, addr: SocketAddr, timeout: Duration) -> io::Result<tokio::net::TcpStream> {
+        match tokio::time::timeout(timeout, self.connect(addr)).await {
+            Ok(connected) => connected,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "connection timed out")),
+        }
+    }
+
+    /// UDP socket connected to `addr` from the bound source.
+    pub(crate) fn udp(&self, addr: SocketAddr) -> io::Result<std::net::UdpSocket> {
+        let socket = self.socket(addr, Type::DGRAM, Some(Protocol::UDP))?;
+        if self.ip.is_none() {
+            let any: SocketAddr = if addr.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+            socket.bind(&any.into())?;
+        }
+        socket.connect(&addr.into())?;
+        Ok(socket.into())
+    }
+}
+
+impl fmt::Display for SourceBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.ip, &self.interface) {
+            (Some(ip), Some(interface)) => write!(f, "{} on {}", ip, interface),
+            (Some(ip), None) => write!(f, "{}", ip),
+            (None, Some(interface)) => write!(f, "{}", interface),
+            (None, None) => write!(f, "default route"),
+        }
+    }
+}
+
+/// Parse a DSCP: a per-hop behaviour name (`ef`, `af41`, `cs1`, `le`...) or a number from 0 to 63.
+pub fn parse_dscp(input: &str) -> Result<u8, String> {
+    let name = input.to_ascii_lowercase();
+    let dscp = match name.as_str() {
+        "default" | "be" | "df" => Some(0),
+        "le" => Some(1),
+        "va" => Some(44),
+        "ef" => Some(46),
+        _ => match (name.strip_prefix("cs"), name.strip_prefix("af")) {
+            (Some(class), _) => class.parse::<u8>().ok().filter(|class| *class <= 7).map(|class| class * 8),
+            (_, Some(af)) => match af.as_bytes() {
+                [class @ b'1'..=b'4', drop @ b'1'..=b'3'] => Some((class - b'0') * 8 + (drop - b'0') * 2),
+                _ => None,
+            },
+            _ => name.parse::<u8>().ok().filter(|dscp| *dscp <= 63),
+        },
+    };
+    dscp.ok_or_else(|| format!("invalid DSCP '{}' (expected ef, afXY, csN, le, va, default or 0-63)", input))
+}
+
+/// Parse a raw TOS / traffic class byte, decimal or `0x` hex.
+pub fn parse_tos(input: &str) -> Result<u8, String> {
+    let value = match input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => input.parse::<u8>(),
+    };
+    value.map_err(|_| format!("invalid TOS '{}' (expected 0-255 or 0x00-0xff)", input))
+}
+
+/// Name of a DSCP, e.g. "EF" or "AF41"; the number alone for the code points without one.
+pub fn dscp_name(dscp: u8) -> String {
+    match dscp {
+        0 => "default".to_string(),
+        1 => "LE".to_string(),
+        44 => "VA".to_string(),
+        46 => "EF".to_string(),
+        n if n % 8 == 0 => format!("CS{}", n / 8),
+        n if n % 2 == 0 && (1..=4).contains(&(n / 8)) && (1..=3).contains(&(n % 8 / 2)) => format!("AF{}{}", n / 8, (n % 8) / 2),
+        n => n.to_string(),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+fn set_tos(socket: &Socket, target: IpAddr, tos: u8) -> io::Result<()> {
+    let set = if target.is_ipv4() { socket.set_tos(tos as u32) } else { socket.set_tclass_v6(tos as u32) };
+    set.map_err(|e| io::Error::new(e.kind(), format!("Cannot mark packets with TOS {:#04x}: {}", tos, e)))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")))]
+fn set_tos(_socket: &Socket, _target: IpAddr, _tos: u8) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--dscp / --tos are not supported on this platform"))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "fuchsia"))]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes())).map_err(|e| io::Error::new(e.kind(), format!("Cannot bind to interface {}: {}", interface, e)))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "fuchsia")))]
+fn bind_device(_socket: &Socket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--interface is only supported on Linux"))
+}
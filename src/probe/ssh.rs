@@ -0,0 +1,282 @@
+//! SSH identification, algorithms and host keys (port 22, `--service ssh`).
+//!
+//! The server's KEXINIT lists the algorithms it accepts. Host keys only come in
+//! the reply to a key exchange, so one ECDH exchange is started per host key
+//! type and abandoned as soon as the key has arrived: nothing is authenticated
+//! and no session keys are derived.
+
+use super::service::ServiceResult;
+use super::ProbeOptions;
+use base64::Engine;
+use futures_util::future::join_all;
+use ring::agreement::{self, EphemeralPrivateKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const CLIENT_ID: &str = "SSH-2.0-netprobe";
+/// Longest packet accepted; real KEXINIT and key exchange replies are a few KB.
+const MAX_PACKET: usize = 256 * 1024;
+/// Lines a server may send before its identification (RFC 4253 §4.2).
+const MAX_PRE_BANNER_LINES: usize = 50;
+
+const MSG_DISCONNECT: u8 = 1;
+const MSG_IGNORE: u8 = 2;
+const MSG_DEBUG: u8 = 4;
+const MSG_KEXINIT: u8 = 20;
+const MSG_KEX_ECDH_INIT: u8 = 30;
+const MSG_KEX_ECDH_REPLY: u8 = 31;
+
+/// Host key algorithms asked for, one group per key: the RSA signature variants share theirs.
+const HOST_KEY_TYPES: [&[&str]; 5] = [&["ssh-ed25519"], &["ecdsa-sha2-nistp256"], &["ecdsa-sha2-nistp384"], &["ecdsa-sha2-nistp521"], &["rsa-sha2-512", "rsa-sha2-256", "ssh-rsa"]];
+
+/// Key exchanges netprobe can start, by preference.
+const KEX_ALGORITHMS: [&str; 4] = ["curve25519-sha256", "curve25519-sha256@libssh.org", "ecdh-sha2-nistp256", "ecdh-sha2-nistp384"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SshResult {
+    /// What follows `SSH-2.0-`, e.g. "OpenSSH_9.6p1 Ubuntu-3ubuntu13".
+    pub software: String,
+    pub kex_algorithms: Vec<String>,
+    pub host_key_algorithms: Vec<String>,
+    /// Server-to-client ciphers and MACs, in the server's order of preference.
+    pub ciphers: Vec<String>,
+    pub macs: Vec<String>,
+    pub host_keys: Vec<HostKey>,
+    /// Why host keys are missing (no key exchange in common...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_key_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostKey {
+    /// Key type: "ssh-ed25519", "ssh-rsa", "ecdsa-sha2-nistp256"...
+    pub algorithm: String,
+    pub bits: Option<u32>,
+    /// As printed by `ssh-keygen -l`: "SHA256:" and the unpadded base64 digest.
+    pub fingerprint: String,
+}
+
+/// Server algorithm lists of a KEXINIT message.
+struct KexInit {
+    kex: Vec<String>,
+    host_key: Vec<String>,
+    ciphers_c2s: Vec<String>,
+    ciphers_s2c: Vec<String>,
+    macs_c2s: Vec<String>,
+    macs_s2c: Vec<String>,
+    compression_c2s: Vec<String>,
+    compression_s2c: Vec<String>,
+}
+
+/// Read the identification and algorithms of the server on `stream`, then
+/// fetch its host keys over fresh connections to `addr`.
+pub(super) async fn probe(stream: &mut TcpStream, addr: SocketAddr, result: &mut ServiceResult, options: &ProbeOptions) -> Result<SshResult, String> {
+    let timeout = options.timeout;
+    let started = Instant::now();
+    let banner = within(timeout, identify(stream)).await?;
+    result.greeting_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+    result.banner = Some(banner.clone());
+    let software = match banner.strip_prefix("SSH-2.0-").or_else(|| banner.strip_prefix("SSH-1.99-")) {
+        Some(software) => software.to_string(),
+        None => return Err(format!("Server only speaks SSH protocol 1: {}", banner)),
+    };
+    let kexinit = within(timeout, async { parse_kexinit(&read_message(stream, MSG_KEXINIT).await?) }).await?;
+
+    let mut ssh = SshResult {
+        software,
+        kex_algorithms: kexinit.kex.clone(),
+        host_key_algorithms: kexinit.host_key.clone(),
+        ciphers: kexinit.ciphers_s2c.clone(),
+        macs: kexinit.macs_s2c.clone(),
+        host_keys: Vec::new(),
+        host_key_error: None,
+    };
+    let Some(kex) = KEX_ALGORITHMS.into_iter().find(|kex| kexinit.kex.iter().any(|offered| offered == kex)) else {
+        ssh.host_key_error = Some("No key exchange in common (netprobe speaks curve25519 and ECDH)".to_string());
+        return Ok(ssh);
+    };
+    let wanted: Vec<&str> = HOST_KEY_TYPES.iter().filter_map(|group| group.iter().copied().find(|algorithm| kexinit.host_key.iter().any(|offered| offered == algorithm))).collect();
+    let fetches = wanted.iter().map(|algorithm| within(timeout, host_key(addr, algorithm, kex, &kexinit, options)));
+    for (algorithm, fetched) in wanted.iter().zip(join_all(fetches).await) {
+        match fetched {
+            Ok(key) => ssh.host_keys.push(key),
+            Err(e) => {
+                tracing::debug!(%addr, algorithm, error = %e, "host key not fetched");
+                ssh.host_key_error.get_or_insert(format!("{}: {}", algorithm, e));
+            },
+        }
+    }
+    Ok(ssh)
+}
+
+/// Start a key exchange offering only `algorithm` and read the host key from the server's reply.
+async fn host_key(addr: SocketAddr, algorithm: &str, kex: &str, server: &KexInit, options: &ProbeOptions) -> Result<HostKey, String> {
+    let mut stream = match tokio::time::timeout(options.connect_timeout(), options.source.connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(e.to_string()),
+        Err(_) => return Err("Connection timed out".to_string()),
+    };
+    identify(&mut stream).await?;
+    read_message(&mut stream, MSG_KEXINIT).await?;
+
+    let rng = SystemRandom::new();
+    let mut cookie = [0u8; 16];
+    rng.fill(&mut cookie).map_err(|_| "No random numbers".to_string())?;
+    // The server's own cipher, MAC and compression lists always have one in common with it.
+    let mut payload = vec![MSG_KEXINIT];
+    payload.extend(cookie);
+    for list in [&[kex.to_string()][..], &[algorithm.to_string()], &server.ciphers_c2s, &server.ciphers_s2c, &server.macs_c2s, &server.macs_s2c, &server.compression_c2s, &server.compression_s2c, &[], &[]] {
+        put_string(&mut payload, list.join(",").as_bytes());
+    }
+    payload.push(0); // first_kex_packet_follows
+    payload.extend(0u32.to_be_bytes());
+    write_packet(&mut stream, &payload).await?;
+
+    let curve = if kex.starts_with("curve25519") {
+        &agreement::X25519
+    } else if kex.ends_with("nistp256") {
+        &agreement::ECDH_P256
+    } else {
+        &agreement::ECDH_P384
+    };
+    let private = EphemeralPrivateKey::generate(curve, &rng).map_err(|_| "Cannot generate a key".to_string())?;
+    let public = private.compute_public_key().map_err(|_| "Cannot generate a key".to_string())?;
+    let mut payload = vec![MSG_KEX_ECDH_INIT];
+    put_string(&mut payload, public.as_ref());
+    write_packet(&mut stream, &payload).await?;
+
+    let reply = read_message(&mut stream, MSG_KEX_ECDH_REPLY).await?;
+    let blob = Reader(&reply[1..]).string().ok_or("Truncated key exchange reply")?;
+    HostKey::from_blob(blob).ok_or_else(|| "Unreadable host key".to_string())
+}
+
+impl HostKey {
+    fn from_blob(blob: &[u8]) -> Option<Self> {
+        let mut reader = Reader(blob);
+        let algorithm = String::from_utf8_lossy(reader.string()?).into_owned();
+        let bits = match algorithm.as_str() {
+            "ssh-ed25519" => Some(256),
+            "ecdsa-sha2-nistp256" => Some(256),
+            "ecdsa-sha2-nistp384" => Some(384),
+            "ecdsa-sha2-nistp521" => Some(521),
+            "ssh-rsa" => {
+                let _exponent = reader.string()?;
+                // The modulus is an mpint: big-endian, with a leading zero byte when its top bit is set.
+                let modulus = reader.string()?;
+                let modulus = &modulus[modulus.iter().position(|&byte| byte != 0)?..];
+                Some((modulus.len() as u32 - 1) * 8 + (8 - modulus[0].leading_zeros()))
+            },
+            _ => None,
+        };
+        let digest = ring::digest::digest(&ring::digest::SHA256, blob);
+        Some(HostKey { algorithm, bits, fingerprint: format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(digest)) })
+    }
+}
+
+/// Send our identification and read the server's, skipping the lines it may send first.
+async fn identify(stream: &mut TcpStream) -> Result<String, String> {
+    stream.write_all(format!("{}\r\n", CLIENT_ID).as_bytes()).await.map_err(|e| e.to_string())?;
+    for _ in 0..MAX_PRE_BANNER_LINES {
+        let mut line = Vec::new();
+        loop {
+            match stream.read_u8().await.map_err(|_| "Connection closed before an SSH identification".to_string())? {
+                b'\n' => break,
+                // Identifications are at most 255 bytes; anything longer is not SSH.
+                _ if line.len() >= 255 => return Err(format!("Not an SSH server: {}", String::from_utf8_lossy(&line))),
+                byte => line.push(byte),
+            }
+        }
+        let line = String::from_utf8_lossy(&line).trim_end_matches('\r').to_string();
+        if line.starts_with("SSH-") {
+            return Ok(line);
+        }
+    }
+    Err("Not an SSH server: no identification line".to_string())
+}
+
+/// Payload of the next message of type `wanted`, skipping IGNORE and DEBUG messages.
+async fn read_message(stream: &mut TcpStream, wanted: u8) -> Result<Vec<u8>, String> {
+    loop {
+        let length = stream.read_u32().await.map_err(|_| "Connection closed by the server".to_string())? as usize;
+        if !(5..=MAX_PACKET).contains(&length) {
+            return Err(format!("Invalid packet length {}", length));
+        }
+        let mut packet = vec![0; length];
+        stream.read_exact(&mut packet).await.map_err(|_| "Connection closed by the server".to_string())?;
+        let padding = packet[0] as usize;
+        let Some(payload) = packet.get(1..length.saturating_sub(padding)).filter(|payload| !payload.is_empty()) else {
+            return Err("Invalid packet padding".to_string());
+        };
+        match payload[0] {
+            message if message == wanted => return Ok(payload.to_vec()),
+            MSG_IGNORE | MSG_DEBUG => continue,
+            MSG_DISCONNECT => {
+                let mut reader = Reader(&payload[1..]);
+                let reason = reader.u32().and_then(|_| reader.string()).map(String::from_utf8_lossy).unwrap_or_default();
+                return Err(format!("Server disconnected: {}", reason));
+            },
+            message => return Err(format!("Unexpected message {} (expected {})", message, wanted)),
+        }
+    }
+}
+
+async fn write_packet(stream: &mut TcpStream, payload: &[u8]) -> Result<(), String> {
+    // Length, padding length, payload and padding add up to a multiple of 8, with at least 4 bytes of padding.
+    let mut padding = 8 - (5 + payload.len()) % 8;
+    if padding < 4 {
+        padding += 8;
+    }
+    let mut packet = Vec::with_capacity(5 + payload.len() + padding);
+    packet.extend(((1 + payload.len() + padding) as u32).to_be_bytes());
+    packet.push(padding as u8);
+    packet.extend(payload);
+    packet.resize(packet.len() + padding, 0);
+    stream.write_all(&packet).await.map_err(|e| e.to_string())
+}
+
+fn parse_kexinit(payload: &[u8]) -> Result<KexInit, String> {
+    // Message type and the 16-byte cookie come before the lists.
+    let mut reader = Reader(payload.get(17..).ok_or("Truncated KEXINIT")?);
+    let mut list = || reader.string().map(|list| String::from_utf8_lossy(list).split(',').filter(|name| !name.is_empty()).map(str::to_string).collect()).ok_or("Truncated KEXINIT");
+    Ok(KexInit {
+        kex: list()?,
+        host_key: list()?,
+        ciphers_c2s: list()?,
+        ciphers_s2c: list()?,
+        macs_c2s: list()?,
+        macs_s2c: list()?,
+        compression_c2s: list()?,
+        compression_s2c: list()?,
+    })
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend((value.len() as u32).to_be_bytes());
+    buffer.extend(value);
+}
+
+/// Cursor over the `uint32` and `string` fields of an SSH message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn u32(&mut self) -> Option<u32> {
+        let (value, rest) = self.0.split_first_chunk::<4>()?;
+        self.0 = rest;
+        Some(u32::from_be_bytes(*value))
+    }
+
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let length = self.u32()? as usize;
+        let value = self.0.get(..length)?;
+        self.0 = &self.0[length..];
+        Some(value)
+    }
+}
+
+async fn within<T>(timeout: Duration, work: impl std::future::Future<Output = Result<T, String>>) -> Result<T, String> {
+    tokio::time::timeout(timeout, work).await.map_err(|_| format!("No reply within {:.1}s", timeout.as_secs_f64()))?
+}
@@ -0,0 +1,48 @@
+//! Dual-stack comparison: the same probe forced over IPv4 and over IPv6.
+
+use super::ProbeResult;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StackComparison {
+    pub ipv4: StackResult,
+    pub ipv6: StackResult,
+}
+
+/// Condensed view of one family's probe.
+#[derive(Debug, Clone, Serialize)]
+pub struct StackResult {
+    pub status: String, // "ok" | "error"
+    pub ip: Option<String>,
+    pub dns_ms: Option<f64>,
+    pub tcp_ms: Option<f64>,
+    pub tls_ms: Option<f64>,
+    pub http_ms: Option<f64>,
+    pub http_status: Option<u16>,
+    /// First failing stage's error, prefixed with the stage name.
+    pub error: Option<String>,
+}
+
+impl From<&ProbeResult> for StackResult {
+    fn from(result: &ProbeResult) -> Self {
+        let error = [
+            ("DNS", &result.dns.error),
+            ("TCP", &result.tcp.error),
+            ("TLS", &result.tls.error),
+            ("HTTP", &result.http.error),
+        ]
+        .into_iter()
+        .find_map(|(stage, error)| error.as_ref().map(|e| format!("{}: {}", stage, e)));
+
+        StackResult {
+            status: if result.is_success() { "ok" } else { "error" }.to_string(),
+            ip: result.dns.ip.clone(),
+            dns_ms: result.dns.latency_ms,
+            tcp_ms: result.tcp.latency_ms,
+            tls_ms: result.tls.latency_ms,
+            http_ms: result.http.latency_ms,
+            http_status: result.http.status_code,
+            error,
+        }
+    }
+}
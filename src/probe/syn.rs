@@ -0,0 +1,216 @@
+//! Optional: half-open port scan (`--syn`).
+//!
+//! Instead of a full connection per port, a raw TCP SYN is sent to every port
+//! and the answers are read on the same raw socket: SYN-ACK means open, RST
+//! closed, silence filtered. The kernel, which knows nothing of the SYN, answers
+//! the SYN-ACK with a RST, so the server never sees an accepted connection. No
+//! thread or socket per port: large ranges take about one timeout. Needs
+//! raw-socket privileges.
+
+use super::icmp;
+use super::source::SourceBinding;
+use super::tcp::PortResult;
+use ring::rand::{SecureRandom, SystemRandom};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+pub(crate) const PRIVILEGE_HINT: &str = "--syn needs raw-socket privileges. Run as root, or grant the capability with \
+`sudo setcap cap_net_raw+ep $(which netprobe)`";
+
+/// Passes over the ports that did not answer; a lost SYN or SYN-ACK should not make an open port look filtered.
+const PASSES: usize = 2;
+/// SYNs sent between two reads of the answers, so that they do not pile up in the socket's buffer.
+const BATCH: usize = 64;
+
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// Raw TCP socket for `ip`'s family, or the reason it cannot be opened.
+#[cfg(target_os = "linux")]
+pub fn open_socket(ip: IpAddr) -> io::Result<Socket> {
+    let domain = if ip.is_ipv4() { Domain::IPV4 } else { Domain::IPV6 };
+    Socket::new(domain, Type::RAW, Some(Protocol::TCP)).map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => io::Error::new(e.kind(), PRIVILEGE_HINT),
+        _ => e,
+    })
+}
+
+/// Other systems do not hand incoming TCP segments to raw sockets.
+#[cfg(not(target_os = "linux"))]
+pub fn open_socket(_ip: IpAddr) -> io::Result<Socket> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--syn is only supported on Linux"))
+}
+
+/// Send a SYN to `ip` on each of `ports` from `source` and classify the answers,
+/// each port giving up after `timeout`.
+pub fn scan(ip: IpAddr, ports: &[u16], timeout: Duration, source: &SourceBinding) -> io::Result<Vec<PortResult>> {
+    let socket = open_socket(ip)?;
+    source.apply(&socket, ip)?;
+    socket.set_nonblocking(true)?;
+    // The checksum covers our address: ask the routing table which one goes towards `ip`.
+    let local = source.udp(SocketAddr::new(ip, 9))?.local_addr()?.ip();
+
+    let mut random = [0u8; 6];
+    SystemRandom::new().fill(&mut random).map_err(|_| io::Error::other("no random source"))?;
+    let mut scan = Scan {
+        socket,
+        target: ip,
+        local,
+        local_port: 32768 + u16::from_be_bytes([random[0], random[1]]) % 28_000,
+        isn: u32::from_be_bytes([random[2], random[3], random[4], random[5]]),
+        sent: HashMap::new(),
+        answers: HashMap::new(),
+    };
+
+    for _ in 0..PASSES {
+        let unanswered: Vec<u16> = ports.iter().copied().filter(|port| !scan.answers.contains_key(port)).collect();
+        if unanswered.is_empty() {
+            break;
+        }
+        for (i, port) in unanswered.into_iter().enumerate() {
+            scan.send_syn(port)?;
+            if i % BATCH == BATCH - 1 {
+                scan.read_answers(Instant::now());
+            }
+        }
+        scan.read_answers(Instant::now() + timeout);
+    }
+    tracing::debug!(%ip, ports = ports.len(), answered = scan.answers.len(), "syn scan done");
+
+    Ok(ports
+        .iter()
+        .map(|&port| match scan.answers.remove(&port) {
+            Some(result) => result,
+            None => PortResult { port, status: "filtered".to_string(), latency_ms: None, error: Some(format!("No answer to SYN within {:?}", timeout)) },
+        })
+        .collect())
+}
+
+struct Scan {
+    socket: Socket,
+    target: IpAddr,
+    local: IpAddr,
+    local_port: u16,
+    /// Sequence number of every SYN; the answers acknowledge it plus one.
+    isn: u32,
+    /// When the last SYN went out to each port.
+    sent: HashMap<u16, Instant>,
+    answers: HashMap<u16, PortResult>,
+}
+
+impl Scan {
+    fn send_syn(&mut self, port: u16) -> io::Result<()> {
+        let segment = syn_segment(self.local, self.target, self.local_port, port, self.isn);
+        let to = SocketAddr::new(self.target, 0).into();
+        loop {
+            match self.socket.send_to(&segment, &to) {
+                Ok(_) => break,
+                // The interface queue is full: let it drain.
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || queue_full(&e) => std::thread::sleep(Duration::from_millis(1)),
+                Err(e) => return Err(e),
+            }
+        }
+        self.sent.insert(port, Instant::now());
+        Ok(())
+    }
+
+    /// Read the answers already received, and keep waiting for more until `deadline`.
+    fn read_answers(&mut self, deadline: Instant) {
+        let mut buf = [MaybeUninit::<u8>::uninit(); 1500];
+        loop {
+            let (n, from) = match self.socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) if Instant::now() >= deadline || self.sent.len() == self.answers.len() => return,
+                Err(_) => {
+                    std::thread::sleep(Duration::from_millis(1));
+                    continue;
+                },
+            };
+            if from.as_socket().map(|addr| addr.ip()) != Some(self.target) {
+                continue;
+            }
+            // SAFETY: recv_from initialized the first `n` bytes.
+            let packet: Vec<u8> = buf[..n].iter().map(|b| unsafe { b.assume_init() }).collect();
+            // IPv4 raw sockets deliver the IP header, IPv6 ones do not.
+            let segment = match self.target.is_ipv4() {
+                true => packet.first().and_then(|first| packet.get((first & 0x0f) as usize * 4..)),
+                false => Some(&packet[..]),
+            };
+            if let Some((port, flags)) = segment.and_then(|segment| self.ours(segment)) {
+                let latency_ms = self.sent.get(&port).map(|sent| sent.elapsed().as_secs_f64() * 1000.0);
+                let result = match flags & (SYN | ACK) == SYN | ACK {
+                    true => PortResult { port, status: "open".to_string(), latency_ms, error: None },
+                    false => PortResult { port, status: "closed".to_string(), latency_ms: None, error: None },
+                };
+                self.answers.entry(port).or_insert(result);
+            }
+        }
+    }
+
+    /// The port and flags of `segment` if it answers one of our SYNs with a SYN-ACK or a RST.
+    fn ours(&self, segment: &[u8]) -> Option<(u16, u8)> {
+        let header = segment.get(..20)?;
+        let port = u16::from_be_bytes([header[0], header[1]]);
+        let to_port = u16::from_be_bytes([header[2], header[3]]);
+        let ack = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        let flags = header[13];
+        let answer = flags & (SYN | ACK) == SYN | ACK || flags & RST != 0;
+        (to_port == self.local_port && ack == self.isn.wrapping_add(1) && answer && self.sent.contains_key(&port)).then_some((port, flags))
+    }
+}
+
+/// A SYN with the MSS option most stacks send, checksummed over the pseudo-header.
+fn syn_segment(local: IpAddr, target: IpAddr, local_port: u16, port: u16, isn: u32) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(24);
+    segment.extend_from_slice(&local_port.to_be_bytes());
+    segment.extend_from_slice(&port.to_be_bytes());
+    segment.extend_from_slice(&isn.to_be_bytes());
+    segment.extend_from_slice(&[0, 0, 0, 0]); // acknowledgment number
+    segment.extend_from_slice(&[6 << 4, SYN]); // 6 words of header, flags
+    segment.extend_from_slice(&64_240u16.to_be_bytes()); // window
+    segment.extend_from_slice(&[0, 0, 0, 0]); // checksum, urgent pointer
+    segment.extend_from_slice(&[2, 4, 0x05, 0xb4]); // MSS 1460
+
+    let mut pseudo = Vec::with_capacity(40 + segment.len());
+    match (local, target) {
+        (IpAddr::V4(local), IpAddr::V4(target)) => {
+            pseudo.extend_from_slice(&local.octets());
+            pseudo.extend_from_slice(&target.octets());
+            pseudo.extend_from_slice(&[0, 6]);
+            pseudo.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        },
+        _ => {
+            pseudo.extend_from_slice(&ipv6_octets(local));
+            pseudo.extend_from_slice(&ipv6_octets(target));
+            pseudo.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, 6]);
+        },
+    }
+    pseudo.extend_from_slice(&segment);
+    let checksum = icmp::checksum(&pseudo);
+    segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+    segment
+}
+
+#[cfg(target_os = "linux")]
+fn queue_full(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENOBUFS)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn queue_full(_e: &io::Error) -> bool {
+    false
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
@@ -0,0 +1,310 @@
+//! Step 2: TCP handshake.
+
+use super::error_code::ErrorCode;
+use super::eyeballs::RaceResult;
+use super::quality::QualityResult;
+use super::retry::Attempt;
+use super::service::ServiceResult;
+use super::source::SourceBinding;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures_util::future::join_all;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+
+/// Connections in flight at once when scanning `--ports`.
+const MAX_PARALLEL_PORTS: usize = 256;
+/// Once a banner has started, how long to wait for the rest of it.
+const BANNER_LINGER: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TcpResult {
+    pub status: String,
+    pub port: u16,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+    /// Why the connection failed, when it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<TcpFailure>,
+    /// Stable code of `error`, e.g. "TCP_REFUSED".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+    /// Local address the connection went out from (`--source-ip`, `--interface`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// DSCP the packets were marked with (`--dscp`, `--tos`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+    /// Connection race between the resolved addresses (`--happy-eyeballs`); the winner is the address probed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub happy_eyeballs: Option<RaceResult>,
+    /// Per-address results when every resolved IP is probed (`--all-ips`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_ips: Option<Vec<AddressResult>>,
+    /// Each connection try when retrying (`--retries`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+    /// Extra ports checked on the same address (`--ports`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<PortResult>,
+    /// What the server sent first (`--banner`), non-printable bytes escaped; empty
+    /// when it sent nothing in time, as servers waiting for the client (HTTP) do.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    /// Greeting of the service on a well-known port (SSH, SMTP, IMAP, POP3).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<ServiceResult>,
+    /// Connect spread and kernel metrics over repeated connections (`--tcp-quality`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<QualityResult>,
+}
+
+/// Outcome of connecting to one specific resolved address.
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressResult {
+    pub ip: String,
+    pub status: String,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<TcpFailure>,
+}
+
+/// Why a connection failed. Each calls for a different fix: a refusal means the
+/// host is up but nothing listens, a timeout that something drops the packets, an
+/// unreachable that a router or the local routing table has no way there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TcpFailure {
+    /// The host answered the SYN with a RST: nothing listens on the port.
+    Refused,
+    /// No answer at all: a firewall drops the packets, or the host is down.
+    Timeout,
+    /// ICMP unreachable from a router, or no route from this host.
+    Unreachable,
+    /// The connection was reset or aborted while being set up.
+    Reset,
+    /// The local system refused to send (a local firewall rule, missing privileges).
+    Denied,
+    /// The local end could not be set up (source address not available, bad interface).
+    Local,
+    Other,
+}
+
+impl TcpFailure {
+    pub fn of(error: &io::Error) -> TcpFailure {
+        match error.kind() {
+            io::ErrorKind::ConnectionRefused => TcpFailure::Refused,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => TcpFailure::Timeout,
+            io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable | io::ErrorKind::NetworkDown => TcpFailure::Unreachable,
+            io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted => TcpFailure::Reset,
+            io::ErrorKind::PermissionDenied => TcpFailure::Denied,
+            io::ErrorKind::AddrNotAvailable | io::ErrorKind::AddrInUse | io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported | io::ErrorKind::NotFound => TcpFailure::Local,
+            _ => TcpFailure::Other,
+        }
+    }
+
+    /// Short description for the human report, e.g. "Connection Refused".
+    pub fn describe(self) -> &'static str {
+        match self {
+            TcpFailure::Refused => "Connection Refused",
+            TcpFailure::Timeout => "Connection Timed Out",
+            TcpFailure::Unreachable => "Host Unreachable",
+            TcpFailure::Reset => "Connection Reset",
+            TcpFailure::Denied => "Blocked Locally",
+            TcpFailure::Local => "Cannot Set Up Local End",
+            TcpFailure::Other => "Connection Failed",
+        }
+    }
+}
+
+/// State of one scanned port.
+#[derive(Debug, Clone, Serialize)]
+pub struct PortResult {
+    pub port: u16,
+    pub status: String, // "open" | "closed" | "filtered"
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// A single port or an inclusive range, as written in `--ports 22,8000-8100`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    /// Sorted, de-duplicated ports covered by `ranges`.
+    pub fn expand(ranges: &[PortRange]) -> Vec<u16> {
+        let mut ports: Vec<u16> = ranges.iter().flat_map(|range| range.start..=range.end).collect();
+        ports.sort_unstable();
+        ports.dedup();
+        ports
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let port = |text: &str| match text.trim().parse::<u16>() {
+            Ok(port @ 1..) => Ok(port),
+            _ => Err(format!("invalid port '{}' (expected 1-65535)", text.trim())),
+        };
+        let (start, end) = match input.split_once('-') {
+            Some((start, end)) => (port(start)?, port(end)?),
+            None => (port(input)?, port(input)?),
+        };
+        if start > end {
+            return Err(format!("invalid port range '{}' (start above end)", input));
+        }
+        Ok(PortRange { start, end })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.start == self.end {
+            true => write!(f, "{}", self.start),
+            false => write!(f, "{}-{}", self.start, self.end),
+        }
+    }
+}
+
+impl TcpResult {
+    pub(crate) fn pending(port: u16) -> Self {
+        TcpResult { status: "pending".to_string(), port, latency_ms: None, error: None, failure: None, error_code: None, source: None, dscp: None, happy_eyeballs: None, all_ips: None, attempts: Vec::new(), ports: Vec::new(), banner: None, service: None, quality: None }
+    }
+
+    pub(crate) fn skipped(port: u16) -> Self {
+        TcpResult { status: "skipped".to_string(), ..TcpResult::pending(port) }
+    }
+}
+
+/// Attempt a TCP connection to `addr` from `source`, giving up after `timeout`. With
+/// `banner` (bytes, wait), read up to that many bytes the server sends on its own.
+pub async fn connect(addr: SocketAddr, timeout: Duration, banner: Option<(usize, Duration)>, source: &SourceBinding) -> TcpResult {
+    let mut result = TcpResult::pending(addr.port());
+
+    tracing::debug!(%addr, "connecting");
+    let start_tcp = Instant::now();
+    match source.connect_timeout(addr, timeout).await {
+        Ok(stream) => {
+            let tcp_duration = start_tcp.elapsed().as_secs_f64() * 1000.0;
+            result.status = "ok".to_string();
+            result.latency_ms = Some(tcp_duration);
+            if source.is_set() {
+                result.source = stream.local_addr().ok().map(|local| local.to_string());
+            }
+            result.dscp = source.dscp();
+            tracing::debug!(%addr, latency_ms = tcp_duration, "connected");
+            if let Some((max, wait)) = banner {
+                result.banner = Some(sanitize(&read_banner(stream, max, wait).await));
+            }
+        },
+        Err(e) => {
+            tracing::debug!(%addr, error = %e, "connection failed");
+            result.status = "error".to_string();
+            result.error = Some(e.to_string());
+            result.failure = Some(TcpFailure::of(&e));
+            result.error_code = result.failure.map(ErrorCode::from);
+        }
+    }
+
+    result
+}
+
+/// Up to `max` bytes arriving within `wait`; the read stops early at end of stream
+/// or when the server pauses after sending something.
+async fn read_banner(mut stream: TcpStream, max: usize, wait: Duration) -> Vec<u8> {
+    let deadline = Instant::now() + wait;
+    let mut banner = vec![0; max];
+    let mut len = 0;
+    while len < max {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let patience = if len == 0 { remaining } else { remaining.min(BANNER_LINGER) };
+        if patience.is_zero() {
+            break;
+        }
+        match tokio::time::timeout(patience, stream.read(&mut banner[len..])).await {
+            Ok(Ok(0)) | Ok(Err(_)) | Err(_) => break,
+            Ok(Ok(n)) => len += n,
+        }
+    }
+    banner.truncate(len);
+    banner
+}
+
+/// Printable ASCII as is, line breaks and tabs as `\r` `\n` `\t`, other bytes as `\xNN`;
+/// the final line break is dropped.
+fn sanitize(bytes: &[u8]) -> String {
+    let end = bytes.iter().rposition(|&byte| byte != b'\r' && byte != b'\n').map_or(0, |i| i + 1);
+    let mut text = String::with_capacity(end);
+    for &byte in &bytes[..end] {
+        match byte {
+            b'\r' => text.push_str("\\r"),
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            0x20..=0x7e => text.push(byte as char),
+            _ => text.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    text
+}
+
+/// Connect to every address concurrently, reporting which ones accept connections.
+pub async fn connect_all(addrs: &[SocketAddr], timeout: Duration, source: &SourceBinding) -> Vec<AddressResult> {
+    let attempts = addrs.iter().map(|&addr| async move { (addr, connect(addr, timeout, None, source).await) });
+    join_all(attempts)
+        .await
+        .into_iter()
+        .map(|(addr, tcp)| AddressResult { ip: addr.ip().to_string(), status: tcp.status, latency_ms: tcp.latency_ms, error: tcp.error, failure: tcp.failure })
+        .collect()
+}
+
+/// Connect to `ip` on each of `ports` concurrently. A refused connection means the
+/// port is closed; no answer (or an ICMP unreachable) means something filters it.
+pub async fn scan_ports(ip: IpAddr, ports: &[u16], timeout: Duration, source: &SourceBinding) -> Vec<PortResult> {
+    let semaphore = Arc::new(Semaphore::new(MAX_PARALLEL_PORTS));
+    let handles: Vec<_> = ports
+        .iter()
+        .map(|&port| {
+            let (semaphore, source) = (semaphore.clone(), source.clone());
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("semaphore closed");
+                scan_port(SocketAddr::new(ip, port), timeout, &source).await
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        if let Ok(port) = handle.await {
+            results.push(port);
+        }
+    }
+    results
+}
+
+async fn scan_port(addr: SocketAddr, timeout: Duration, source: &SourceBinding) -> PortResult {
+    let started = Instant::now();
+    let (status, error) = match source.connect_timeout(addr, timeout).await {
+        Ok(_) => ("open", None),
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => ("closed", None),
+        Err(e) => ("filtered", Some(e.to_string())),
+    };
+    PortResult {
+        port: addr.port(),
+        status: status.to_string(),
+        latency_ms: (status == "open").then(|| started.elapsed().as_secs_f64() * 1000.0),
+        error,
+    }
+}
@@ -0,0 +1,597 @@
+//! Step 3: TLS handshake and certificate inspection.
+
+use super::caa::{self, CaaResult};
+use super::ct::{self, CtResult};
+use super::error_code::ErrorCode;
+use super::http;
+use super::resumption::{self, ResumptionResult, SessionRecorder};
+use super::revocation::{self, RevocationResult};
+use super::ProbeOptions;
+use crate::{pkcs12, x509};
+use base64::Engine;
+use rustls::client::{ResolvesClientCert, Resumption, ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName, SignatureScheme};
+use serde::Serialize;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsResult {
+    pub status: String, // "ok" | "warning" | "error" | "skipped"
+    pub latency_ms: Option<f64>,
+    pub protocol: Option<String>,
+    pub cipher_suite: Option<String>,
+    /// Application protocol agreed through ALPN ("h2", "http/1.1"), if any.
+    pub alpn: Option<String>,
+    /// Server name sent in the ClientHello (`-v`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sni: Option<String>,
+    /// Application protocols offered through ALPN, in order (`-v`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alpn_offered: Vec<String>,
+    pub certificate: Option<CertificateInfo>,
+    /// Every certificate the server presented, leaf first (`--cert-chain`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub chain: Vec<ChainCertificate>,
+    /// Chain verification: "ok" | "failed" | "skipped" (`--insecure`).
+    pub verification: Option<String>,
+    /// Why verification would have failed, when `--insecure` skipped it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_error: Option<String>,
+    /// Name of the CA the chain was verified against.
+    pub trust_anchor: Option<String>,
+    /// Where that CA comes from: "bundled" (Mozilla roots) or the `--cacert` file.
+    pub trust_store: Option<String>,
+    /// The server asked for a client certificate (mutual TLS).
+    pub client_cert_requested: Option<bool>,
+    /// The server stapled an OCSP response to the handshake.
+    pub ocsp_stapled: Option<bool>,
+    /// Revocation status of the leaf certificate (`--check-revocation`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<RevocationResult>,
+    /// Certificate Transparency timestamps of the leaf certificate (`--check-ct`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ct: Option<CtResult>,
+    /// Whether the host's CAA records allow the certificate's issuer (`--check-caa`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caa: Option<CaaResult>,
+    /// Session resumption and 0-RTT support, from a second handshake (`--check-resumption`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resumption: Option<ResumptionResult>,
+    /// Subject of the client certificate sent in answer (`--cert`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    /// Set when the leaf certificate expires within `--cert-warn-days`.
+    pub warning: Option<String>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "TLS_EXPIRED_CERT".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+}
+
+/// Details of the leaf certificate presented by the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub sans: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+    pub days_until_expiry: i64,
+}
+
+/// One certificate of the presented chain.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainCertificate {
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    /// e.g. "RSA 2048", "ECDSA P-256"
+    pub key_type: String,
+    /// e.g. "sha256WithRSAEncryption"
+    pub signature_algorithm: String,
+    /// SHA-256 of the DER encoding, as colon-separated hex.
+    pub sha256_fingerprint: String,
+    /// The certificate itself, PEM-encoded (`--cert-pem`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pem: Option<String>,
+}
+
+impl TlsResult {
+    pub(crate) fn pending() -> Self {
+        TlsResult { status: "pending".to_string(), latency_ms: None, protocol: None, cipher_suite: None, alpn: None, sni: None, alpn_offered: Vec::new(), certificate: None, chain: Vec::new(), verification: None, verify_error: None, trust_anchor: None, trust_store: None, client_cert_requested: None, ocsp_stapled: None, revocation: None, ct: None, caa: None, resumption: None, client_cert: None, warning: None, error: None, error_code: None }
+    }
+
+    pub(crate) fn skipped() -> Self {
+        TlsResult { status: "skipped".to_string(), ..TlsResult::pending() }
+    }
+
+    pub(crate) fn failed(code: ErrorCode, error: String) -> Self {
+        TlsResult { status: "error".to_string(), error: Some(error), error_code: Some(code), ..TlsResult::pending() }
+    }
+}
+
+impl CertificateInfo {
+    /// Decode a DER certificate; `None` if it cannot be parsed.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let cert = x509::Certificate::parse(der)?;
+        Some(CertificateInfo {
+            subject: cert.subject.clone(),
+            issuer: cert.issuer.clone(),
+            sans: cert.subject_alt_names(),
+            not_before: cert.not_before.to_rfc3339(),
+            not_after: cert.not_after.to_rfc3339(),
+            days_until_expiry: (cert.not_after - chrono::Utc::now()).num_days(),
+        })
+    }
+}
+
+impl ChainCertificate {
+    /// Decode a DER certificate, keeping its PEM encoding if `pem` is set; `None` if it cannot be parsed.
+    pub fn from_der(der: &[u8], pem: bool) -> Option<Self> {
+        let cert = x509::Certificate::parse(der)?;
+        let fingerprint = ring::digest::digest(&ring::digest::SHA256, der);
+        Some(ChainCertificate {
+            subject: cert.subject.clone(),
+            issuer: cert.issuer.clone(),
+            not_before: cert.not_before.to_rfc3339(),
+            not_after: cert.not_after.to_rfc3339(),
+            key_type: cert.key_type.clone(),
+            signature_algorithm: cert.signature_algorithm.clone(),
+            sha256_fingerprint: fingerprint.as_ref().iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":"),
+            pem: pem.then(|| to_pem(der)),
+        })
+    }
+}
+
+/// PEM encoding of a DER certificate, 64 base64 characters per line.
+fn to_pem(der: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Wraps the standard WebPKI verifier but never aborts the handshake:
+/// the verdict is recorded so certificate details can still be reported
+/// for expired, self-signed or mismatched certificates.
+struct RecordingVerifier {
+    inner: WebPkiVerifier,
+    verdict: Mutex<Option<Result<(), rustls::Error>>>,
+    /// OCSP response stapled by the server; empty if none.
+    staple: Mutex<Vec<u8>>,
+    /// SCTs sent in the handshake's signed_certificate_timestamp extension.
+    scts: Mutex<Vec<Vec<u8>>>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verdict = self.inner.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now).map(|_| ());
+        *self.verdict.lock().unwrap() = Some(verdict);
+        *self.staple.lock().unwrap() = ocsp_response.to_vec();
+        *self.scts.lock().unwrap() = scts.map(|sct| sct.to_vec()).collect();
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Client certificate chain and key for mutual TLS (`--cert` / `--key`).
+#[derive(Clone)]
+pub struct ClientIdentity {
+    key: Arc<CertifiedKey>,
+    /// Subject of the client certificate, for display.
+    pub subject: String,
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientIdentity").field("subject", &self.subject).finish_non_exhaustive()
+    }
+}
+
+impl ClientIdentity {
+    /// Load a PEM certificate chain with its key (from `key`, or from the same file),
+    /// or a PKCS#12 bundle, recognised by not being PEM and opened with `password`.
+    pub fn load(cert: &Path, key: Option<&Path>, password: Option<&str>) -> Result<Self, String> {
+        let read = |path: &Path| std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e));
+        let data = read(cert)?;
+        let (certs, key) = if data.starts_with(b"-----") || data.windows(11).any(|w| w == b"-----BEGIN ") {
+            let mut items = pem_items(&data);
+            if let Some(key) = key {
+                items.extend(pem_items(&read(key)?));
+            }
+            let certs: Vec<Certificate> = items.iter().filter_map(|item| match item {
+                rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der.clone())),
+                _ => None,
+            }).collect();
+            let private_key = items.into_iter().find_map(|item| match item {
+                rustls_pemfile::Item::PKCS8Key(der) | rustls_pemfile::Item::RSAKey(der) | rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+                _ => None,
+            });
+            let private_key = private_key.ok_or_else(|| format!("No unencrypted private key in {}", key.unwrap_or(cert).display()))?;
+            (certs, private_key)
+        } else {
+            let identity = pkcs12::parse(&data, password.unwrap_or("")).map_err(|e| format!("{}: {}", cert.display(), e))?;
+            (identity.certificates.into_iter().map(Certificate).collect(), PrivateKey(identity.key))
+        };
+        let leaf = certs.first().ok_or_else(|| format!("No certificate in {}", cert.display()))?;
+        let subject = x509::Certificate::parse(&leaf.0).map(|c| c.subject).unwrap_or_default();
+        let signing_key = rustls::sign::any_supported_type(&key).map_err(|_| "Unsupported client key type (expected RSA, ECDSA or Ed25519)".to_string())?;
+        Ok(ClientIdentity { key: Arc::new(CertifiedKey::new(certs, signing_key)), subject })
+    }
+}
+
+fn pem_items(data: &[u8]) -> Vec<rustls_pemfile::Item> {
+    rustls_pemfile::read_all(&mut &data[..]).unwrap_or_default()
+}
+
+/// Presents the client certificate, if any, and notes whether the server asked for one.
+pub(crate) struct ClientAuth {
+    identity: Option<ClientIdentity>,
+    requested: AtomicBool,
+}
+
+impl ClientAuth {
+    pub(crate) fn new(options: &ProbeOptions) -> Arc<Self> {
+        Arc::new(ClientAuth { identity: options.client_identity.clone(), requested: AtomicBool::new(false) })
+    }
+}
+
+impl ResolvesClientCert for ClientAuth {
+    fn resolve(&self, _acceptable_issuers: &[&[u8]], _sigschemes: &[SignatureScheme]) -> Option<Arc<CertifiedKey>> {
+        self.requested.store(true, Ordering::Relaxed);
+        self.identity.as_ref().map(|identity| identity.key.clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        self.identity.is_some()
+    }
+}
+
+/// CA certificates trusted instead of the bundled roots (`--cacert`).
+#[derive(Debug, Clone)]
+pub struct CaBundle {
+    /// The file the certificates came from, reported as `trust_store`.
+    pub path: String,
+    roots: RootCertStore,
+}
+
+impl CaBundle {
+    /// Read every certificate of a PEM file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let certs: Vec<Certificate> = pem_items(&data)
+            .into_iter()
+            .filter_map(|item| match item {
+                rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der)),
+                _ => None,
+            })
+            .collect();
+        if certs.is_empty() {
+            return Err(format!("No PEM certificate in {}", path.display()));
+        }
+        let mut roots = RootCertStore::empty();
+        for cert in &certs {
+            roots.add(cert).map_err(|e| format!("Unusable CA certificate in {}: {}", path.display(), e))?;
+        }
+        Ok(CaBundle { path: path.display().to_string(), roots })
+    }
+}
+
+/// Accepts any certificate: the HTTP stage under `--insecure`.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// The roots certificates are checked against: the `--cacert` bundle if any, else the bundled ones.
+pub(crate) fn trusted_roots(options: &ProbeOptions) -> RootCertStore {
+    match &options.ca_bundle {
+        Some(bundle) => bundle.roots.clone(),
+        None => root_store(),
+    }
+}
+
+/// TLS settings of the HTTP stage: `--cacert`, `--insecure` and the `--cert` identity.
+pub(crate) fn client_config(options: &ProbeOptions) -> ClientConfig {
+    ClientConfig::builder().with_safe_defaults().with_custom_certificate_verifier(verifier(options)).with_client_cert_resolver(ClientAuth::new(options))
+}
+
+/// Certificate verifier of the HTTP stage, following `--cacert` and `--insecure`.
+pub(crate) fn verifier(options: &ProbeOptions) -> Arc<dyn ServerCertVerifier> {
+    if options.insecure {
+        return Arc::new(NoVerification);
+    }
+    Arc::new(WebPkiVerifier::new(trusted_roots(options), None))
+}
+
+/// Client configuration of QUIC connections (DNS-over-QUIC, HTTP/3) offering `alpn`.
+/// quinn runs on a newer rustls than the TCP stages; certificates still go through
+/// `verifier`, so both trust the same roots.
+pub(crate) fn quic_client_config(verifier: Arc<dyn ServerCertVerifier>, alpn: &[u8]) -> Result<quinn::ClientConfig, String> {
+    let provider = Arc::new(rustls23::crypto::ring::default_provider());
+    let mut config = rustls23::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls23::version::TLS13])
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(QuicVerifier { verifier, provider }))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![alpn.to_vec()];
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(config).map_err(|e| e.to_string())?;
+    Ok(quinn::ClientConfig::new(Arc::new(crypto)))
+}
+
+/// A verifier of this module seen through the rustls release quinn uses.
+struct QuicVerifier {
+    verifier: Arc<dyn ServerCertVerifier>,
+    provider: Arc<rustls23::crypto::CryptoProvider>,
+}
+
+impl fmt::Debug for QuicVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("QuicVerifier")
+    }
+}
+
+impl rustls23::client::danger::ServerCertVerifier for QuicVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls23::pki_types::CertificateDer<'_>,
+        intermediates: &[rustls23::pki_types::CertificateDer<'_>],
+        name: &rustls23::pki_types::ServerName<'_>,
+        ocsp_response: &[u8],
+        now: rustls23::pki_types::UnixTime,
+    ) -> Result<rustls23::client::danger::ServerCertVerified, rustls23::Error> {
+        let name = server_name(&name.to_str()).map_err(rustls23::Error::General)?;
+        let intermediates: Vec<Certificate> = intermediates.iter().map(|cert| Certificate(cert.to_vec())).collect();
+        let now = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(now.as_secs());
+        match self.verifier.verify_server_cert(&Certificate(end_entity.to_vec()), &intermediates, &name, &mut std::iter::empty(), ocsp_response, now) {
+            Ok(_) => Ok(rustls23::client::danger::ServerCertVerified::assertion()),
+            Err(e) => Err(rustls23::Error::General(e.to_string())),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls23::pki_types::CertificateDer<'_>,
+        dss: &rustls23::DigitallySignedStruct,
+    ) -> Result<rustls23::client::danger::HandshakeSignatureValid, rustls23::Error> {
+        rustls23::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls23::pki_types::CertificateDer<'_>,
+        dss: &rustls23::DigitallySignedStruct,
+    ) -> Result<rustls23::client::danger::HandshakeSignatureValid, rustls23::Error> {
+        rustls23::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls23::SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Mozilla's root programme, as bundled by webpki-roots.
+pub(crate) fn root_store() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+    }));
+    roots
+}
+
+/// SNI / verification name for a URL host; IP literals (bracketed or not) are kept as addresses.
+pub(crate) fn server_name(host: &str) -> Result<ServerName, String> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => Ok(ServerName::IpAddress(ip)),
+        Err(_) => ServerName::try_from(host).map_err(|e| format!("Invalid server name: {}", e)),
+    }
+}
+
+/// The CA a verified chain ends at: the issuer of the topmost certificate presented
+/// (a root sent along is its own issuer).
+fn trust_anchor(chain: &[Certificate]) -> Option<String> {
+    let top = x509::Certificate::parse(&chain.last()?.0)?;
+    Some(top.issuer)
+}
+
+/// Render rustls' `TLSv1_3` as the conventional `TLSv1.3`.
+fn protocol_name(version: rustls::ProtocolVersion) -> String {
+    format!("{:?}", version).replace('_', ".")
+}
+
+/// Open a fresh connection to `addr` and perform a TLS handshake for `host`,
+/// offering `alpn`: the HTTP stage's protocols for web servers, a service's own otherwise.
+pub async fn handshake(addr: SocketAddr, host: &str, alpn: Vec<Vec<u8>>, options: &ProbeOptions) -> TlsResult {
+    let stream = match tokio::time::timeout(options.connect_timeout(), options.source.connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return TlsResult::failed(ErrorCode::TlsConnect, e.to_string()),
+        Err(_) => return TlsResult::failed(ErrorCode::TlsConnect, "Connection timed out".to_string()),
+    };
+    handshake_on(stream, host, alpn, options).await
+}
+
+/// Perform a TLS handshake for `host` on an open connection, such as one
+/// just upgraded with STARTTLS. Resumption is tested on a fresh connection
+/// to the same address, so it only makes sense for direct TLS.
+pub(crate) async fn handshake_on(stream: TcpStream, host: &str, alpn: Vec<Vec<u8>>, options: &ProbeOptions) -> TlsResult {
+    let mut result = TlsResult::pending();
+    let timeout = options.tls_timeout();
+    let Ok(addr) = stream.peer_addr() else {
+        return TlsResult::failed(ErrorCode::TlsConnect, "Connection closed before the handshake".to_string());
+    };
+    if options.verbose {
+        result.sni = Some(host.to_string());
+        result.alpn_offered = alpn.iter().map(|p| String::from_utf8_lossy(p).into_owned()).collect();
+    }
+
+    let server_name = match server_name(host) {
+        Ok(name) => name,
+        Err(e) => {
+            result.status = "error".to_string();
+            result.error = Some(e);
+            result.error_code = Some(ErrorCode::TlsServerName);
+            return result;
+        },
+    };
+
+    let verifier = Arc::new(RecordingVerifier { inner: WebPkiVerifier::new(trusted_roots(options), None), verdict: Mutex::new(None), staple: Mutex::new(Vec::new()), scts: Mutex::new(Vec::new()) });
+    let client_auth = ClientAuth::new(options);
+    let mut config = ClientConfig::builder().with_safe_defaults().with_custom_certificate_verifier(verifier.clone()).with_client_cert_resolver(client_auth.clone());
+    config.alpn_protocols = alpn;
+    let sessions = Arc::new(SessionRecorder::new());
+    if options.check_resumption {
+        config.resumption = Resumption::store(sessions.clone());
+    }
+    let connector = TlsConnector::from(Arc::new(config));
+
+    // Only the handshake itself is timed; the TCP connect is already reported by step 2.
+    tracing::debug!(%addr, sni = host, "TLS handshake");
+    let start_tls = Instant::now();
+    let mut tls_stream = match tokio::time::timeout(timeout, connector.connect(server_name.clone(), stream)).await {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => {
+            result.status = "error".to_string();
+            result.error = Some(e.to_string());
+            result.error_code = Some(ErrorCode::TlsHandshake);
+            return result;
+        },
+        Err(_) => {
+            result.status = "error".to_string();
+            result.error = Some("Handshake timed out".to_string());
+            result.error_code = Some(ErrorCode::TlsTimeout);
+            return result;
+        }
+    };
+    result.latency_ms = Some(start_tls.elapsed().as_secs_f64() * 1000.0);
+
+    let (_, conn) = tls_stream.get_ref();
+    result.protocol = conn.protocol_version().map(protocol_name);
+    result.cipher_suite = conn.negotiated_cipher_suite().map(|cs| format!("{:?}", cs.suite()));
+    result.alpn = conn.alpn_protocol().map(|p| String::from_utf8_lossy(p).into_owned());
+    tracing::debug!(protocol = result.protocol.as_deref(), cipher_suite = result.cipher_suite.as_deref(), alpn = result.alpn.as_deref(), "handshake complete");
+    let requested = client_auth.requested.load(Ordering::Relaxed);
+    result.client_cert_requested = Some(requested);
+    result.client_cert = client_auth.identity.as_ref().filter(|_| requested).map(|identity| identity.subject.clone());
+    result.certificate = conn.peer_certificates().and_then(|certs| certs.first()).and_then(|leaf| CertificateInfo::from_der(&leaf.0));
+
+    let chain = conn.peer_certificates().unwrap_or_default();
+    let staple = verifier.staple.lock().unwrap().clone();
+    result.ocsp_stapled = Some(!staple.is_empty());
+    if options.cert_chain {
+        result.chain = chain.iter().filter_map(|cert| ChainCertificate::from_der(&cert.0, options.cert_pem)).collect();
+    }
+    match verifier.verdict.lock().unwrap().take() {
+        Some(Err(e)) if options.insecure => {
+            result.status = "ok".to_string();
+            result.verification = Some("skipped".to_string());
+            result.verify_error = Some(e.to_string());
+        },
+        Some(Err(e)) => {
+            result.status = "error".to_string();
+            result.verification = Some("failed".to_string());
+            result.error = Some(format!("Certificate verification failed: {}", e));
+            result.error_code = Some(ErrorCode::certificate(&e));
+        },
+        _ => {
+            result.status = "ok".to_string();
+            result.verification = Some(if options.insecure { "skipped" } else { "ok" }.to_string());
+            result.trust_anchor = trust_anchor(chain);
+            result.trust_store = Some(options.ca_bundle.as_ref().map_or("bundled".to_string(), |bundle| bundle.path.clone()));
+        },
+    }
+
+    if options.check_revocation && result.status == "ok" {
+        let client = http::side_client(&options.proxy, client_config(options), timeout);
+        let revocation = revocation::check(chain, &staple, &client).await;
+        if revocation.status == "revoked" {
+            result.status = "error".to_string();
+            result.error = Some(match (&revocation.revoked_at, &revocation.reason) {
+                (Some(at), Some(reason)) => format!("Certificate revoked on {} ({})", at, reason),
+                (Some(at), None) => format!("Certificate revoked on {}", at),
+                _ => "Certificate revoked".to_string(),
+            });
+            result.error_code = Some(ErrorCode::TlsRevokedCert);
+        }
+        result.revocation = Some(revocation);
+    }
+
+    if options.check_ct {
+        let ct = ct::check(chain, &verifier.scts.lock().unwrap(), options.ct_logs.as_ref());
+        if let (Some(e), "ok") = (&ct.error, result.status.as_str()) {
+            result.status = "error".to_string();
+            result.error = Some(e.clone());
+            result.error_code = Some(ErrorCode::TlsCtMissing);
+        }
+        result.ct = Some(ct);
+    }
+
+    if options.check_resumption && result.status != "error" {
+        let verified = || verifier.verdict.lock().unwrap().take().is_some();
+        let resumption = resumption::check(&mut tls_stream, &sessions, &connector, addr, server_name, verified, options).await;
+        result.resumption = Some(ResumptionResult { full_ms: result.latency_ms.unwrap_or_default(), ..resumption });
+    }
+
+    if let (Some(threshold), Some(cert)) = (options.cert_warn_days, &result.certificate) {
+        if cert.days_until_expiry < threshold {
+            result.warning = Some(if cert.days_until_expiry < 0 {
+                format!("Certificate expired {} days ago", -cert.days_until_expiry)
+            } else {
+                format!("Certificate expires in {} days (threshold: {} days)", cert.days_until_expiry, threshold)
+            });
+            if result.status == "ok" {
+                result.status = "warning".to_string();
+            }
+        }
+    }
+
+    // CAA only applies to names; a certificate for an IP address has none to check.
+    if let (true, Some(cert), Ok(ServerName::DnsName(_))) = (options.check_caa, &result.certificate, self::server_name(host)) {
+        let caa = caa::check(host, cert, options).await;
+        if caa.status == "unauthorized" {
+            if result.status == "ok" {
+                result.status = "warning".to_string();
+            }
+            if result.warning.is_none() {
+                result.warning = caa.error.clone();
+            }
+        }
+        result.caa = Some(caa);
+    }
+
+    result
+}
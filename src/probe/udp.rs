@@ -0,0 +1,296 @@
+//! Optional: UDP transport probe (`--udp`), replacing the TCP, TLS and HTTP stages.
+//!
+//! UDP has no handshake, so the probe sends one datagram the service should
+//! answer and waits: a reply proves the service is up, an ICMP port
+//! unreachable proves nothing listens, silence is ambiguous (dropped, filtered,
+//! or a service that ignores the payload). NTP, SIP and SNMP replies are
+//! decoded further by [`ntp`], [`sip`] and [`snmp`].
+
+use super::error_code::ErrorCode;
+use super::ntp::{self, NtpResult};
+use super::sip;
+use super::snmp::{self, SnmpResult};
+use super::source::SourceBinding;
+use crate::resolver::wire::{self, Message, Query};
+use serde::Serialize;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Minimum size of a datagram carrying a QUIC Initial packet (RFC 9000, 14.1).
+const QUIC_MIN_DATAGRAM: usize = 1200;
+/// Reserved "greasing" version (RFC 9000, 15): servers answer it with a Version Negotiation packet.
+const QUIC_PROBE_VERSION: u32 = 0x1a2a_3a4a;
+const NTP_PACKET: usize = 48;
+
+/// What to send in the probe datagram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UdpPayload {
+    /// Picked from the port by `for_port`.
+    Auto,
+    /// A recursive query for the root NS records.
+    Dns,
+    /// An NTPv4 client request (SNTP).
+    Ntp,
+    /// A SIP OPTIONS request.
+    Sip,
+    /// An SNMPv2c GET for sysDescr and sysUpTime, with this community.
+    Snmp(String),
+    /// A QUIC long-header packet with an unsupported version.
+    Quic,
+    Raw(Vec<u8>),
+}
+
+impl UdpPayload {
+    /// The payload a service on a well-known `port` answers; an empty datagram otherwise.
+    pub fn for_port(port: u16) -> Self {
+        match port {
+            53 => UdpPayload::Dns,
+            123 => UdpPayload::Ntp,
+            161 => UdpPayload::Snmp(snmp::DEFAULT_COMMUNITY.to_string()),
+            443 => UdpPayload::Quic,
+            5060 => UdpPayload::Sip,
+            _ => UdpPayload::Raw(Vec::new()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UdpPayload::Auto => "auto",
+            UdpPayload::Dns => "dns",
+            UdpPayload::Ntp => "ntp",
+            UdpPayload::Sip => "sip",
+            UdpPayload::Snmp(_) => "snmp",
+            UdpPayload::Quic => "quic",
+            UdpPayload::Raw(_) => "raw",
+        }
+    }
+
+    /// `Auto` picked from `port`, and SNMP requests sent with `community`.
+    pub fn resolve(self, port: u16, community: &str) -> Self {
+        match self {
+            UdpPayload::Auto => UdpPayload::for_port(port).resolve(port, community),
+            UdpPayload::Snmp(_) => UdpPayload::Snmp(community.to_string()),
+            other => other,
+        }
+    }
+
+    /// The datagram to send at `now` from `local` to `peer` (NTP requests carry
+    /// their transmit time, SIP requests both addresses, SNMP requests an id derived from it).
+    fn encode(&self, now: SystemTime, local: SocketAddr, peer: SocketAddr) -> Vec<u8> {
+        match self {
+            UdpPayload::Dns => Query::new(".", wire::TYPE_NS).encode().expect("the root name encodes"),
+            UdpPayload::Ntp => ntp::request(now),
+            UdpPayload::Sip => sip::options_request("UDP", local, &peer.to_string()),
+            UdpPayload::Snmp(community) => snmp::request(community, now),
+            UdpPayload::Quic => {
+                let mut packet = vec![0xc0];
+                packet.extend_from_slice(&QUIC_PROBE_VERSION.to_be_bytes());
+                // Destination and source connection IDs, 8 bytes each.
+                for id in [[0x6e; 8], [0x70; 8]] {
+                    packet.push(id.len() as u8);
+                    packet.extend_from_slice(&id);
+                }
+                packet.resize(QUIC_MIN_DATAGRAM, 0);
+                packet
+            },
+            UdpPayload::Raw(bytes) => bytes.clone(),
+            UdpPayload::Auto => Vec::new(),
+        }
+    }
+
+    /// One-line description of a reply to this payload.
+    fn describe(&self, reply: &[u8]) -> String {
+        match self {
+            UdpPayload::Dns => match Message::parse(reply) {
+                Some(message) => format!("DNS {}, {} answer(s)", wire::rcode_name(message.rcode), message.answers.len()),
+                None => format!("{} bytes, not a DNS message", reply.len()),
+            },
+            UdpPayload::Ntp if reply.len() >= NTP_PACKET && reply[0] & 0x07 == 4 => format!("NTP server, stratum {}", reply[1]),
+            UdpPayload::Sip => match sip::parse(reply) {
+                Ok(response) => match &response.server {
+                    Some(server) => format!("SIP {} ({})", response.status(), server),
+                    None => format!("SIP {}", response.status()),
+                },
+                Err(e) => e,
+            },
+            UdpPayload::Quic => match quic_versions(reply) {
+                Some(versions) => format!("QUIC versions {}", versions.iter().map(|v| format!("0x{:08x}", v)).collect::<Vec<_>>().join(", ")),
+                None => format!("{} bytes, not a QUIC version negotiation", reply.len()),
+            },
+            _ => format!("{} bytes: {}", reply.len(), wire::hex(&reply[..reply.len().min(16)])),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UdpResult {
+    pub status: String,
+    pub port: u16,
+    /// "dns" | "ntp" | "sip" | "snmp" | "quic" | "raw"
+    pub payload: String,
+    /// "reply" | "unreachable" | "timeout"
+    pub outcome: String,
+    /// Round trip of the reply (or of the ICMP error).
+    pub latency_ms: Option<f64>,
+    pub bytes_sent: usize,
+    pub bytes_received: Option<usize>,
+    /// Local address the datagram went out from (`--source-ip`, `--interface`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// DSCP the packets were marked with (`--dscp`, `--tos`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+    /// What the reply says, decoded according to the payload.
+    pub reply: Option<String>,
+    /// Stratum, clock offset and delay of an NTP server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntp: Option<NtpResult>,
+    /// System description and uptime reported by an SNMP agent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snmp: Option<SnmpResult>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "UDP_UNREACHABLE".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+    /// The reply datagram itself, for callers that decode it further.
+    #[serde(skip)]
+    pub(crate) data: Option<Vec<u8>>,
+}
+
+/// Send `payload` to `addr` from `source` and wait up to `timeout` for an answer.
+pub async fn probe(addr: SocketAddr, payload: &UdpPayload, timeout: Duration, source: &SourceBinding) -> UdpResult {
+    let payload = match payload {
+        UdpPayload::Auto => UdpPayload::for_port(addr.port()),
+        other => other.clone(),
+    };
+    let fallback = UdpResult::failed(addr.port(), &payload, 0, ErrorCode::UdpLocal, "UDP probe task failed".to_string());
+    let source = source.clone();
+    tokio::task::spawn_blocking(move || exchange(addr, &payload, timeout, &source)).await.unwrap_or(fallback)
+}
+
+/// Blocking send/receive: epoll does not wake async readers for the ICMP error
+/// queued on the socket, so the exchange runs on a plain std socket.
+fn exchange(addr: SocketAddr, payload: &UdpPayload, timeout: Duration, source: &SourceBinding) -> UdpResult {
+    // A connected socket is what makes the kernel report ICMP port unreachable as ECONNREFUSED.
+    let socket = match source.udp(addr) {
+        Ok(socket) => socket,
+        Err(e) => return UdpResult::failed(addr.port(), payload, 0, ErrorCode::UdpLocal, e.to_string()),
+    };
+    let local = socket.local_addr().unwrap_or_else(|_| if addr.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() });
+    let sent_at = SystemTime::now();
+    let datagram = payload.encode(sent_at, local, addr);
+    let _ = socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))));
+
+    let silence = match payload {
+        // Agents ignore a community they do not accept.
+        UdpPayload::Snmp(_) => format!("No reply within {:?} (no SNMP agent, or wrong community)", timeout),
+        _ => format!("No reply within {:?}", timeout),
+    };
+    let mut result = UdpResult::failed(addr.port(), payload, datagram.len(), ErrorCode::UdpTimeout, silence);
+    if source.is_set() {
+        result.source = Some(local.to_string());
+    }
+    result.dscp = source.dscp();
+    let started = Instant::now();
+    let mut buf = vec![0; 65_535];
+    match socket.send(&datagram).and_then(|_| socket.recv(&mut buf)) {
+        Ok(len) => {
+            result.status = "ok".to_string();
+            result.outcome = "reply".to_string();
+            result.latency_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+            result.bytes_received = Some(len);
+            result.reply = Some(payload.describe(&buf[..len]));
+            result.data = Some(buf[..len].to_vec());
+            result.error = None;
+            result.error_code = None;
+            if *payload == UdpPayload::Ntp {
+                // A reply that is not a usable time fails an NTP probe.
+                match ntp::decode(&buf[..len], sent_at, SystemTime::now()) {
+                    Ok(decoded) => {
+                        result.error = ntp::unsynchronized(&decoded);
+                        result.ntp = Some(decoded);
+                    },
+                    Err(e) => result.error = Some(e),
+                }
+                if result.error.is_some() {
+                    result.status = "error".to_string();
+                    result.error_code = Some(ErrorCode::UdpBadReply);
+                }
+            }
+            if *payload == UdpPayload::Sip {
+                result.error = match sip::parse(&buf[..len]) {
+                    Ok(response) => response.failure(),
+                    Err(e) => Some(e),
+                };
+                if result.error.is_some() {
+                    result.status = "error".to_string();
+                    result.error_code = Some(ErrorCode::UdpBadReply);
+                }
+            }
+            if let UdpPayload::Snmp(_) = payload {
+                match snmp::decode(&buf[..len], sent_at) {
+                    Ok(decoded) => {
+                        result.reply = Some(format!("SNMPv{} response", decoded.version));
+                        result.snmp = Some(decoded);
+                    },
+                    Err(e) => {
+                        result.status = "error".to_string();
+                        result.error = Some(e);
+                        result.error_code = Some(ErrorCode::UdpBadReply);
+                    },
+                }
+            }
+        },
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {},
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            result.outcome = "unreachable".to_string();
+            result.latency_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+            result.error = Some("Port unreachable (ICMP)".to_string());
+            result.error_code = Some(ErrorCode::UdpUnreachable);
+        },
+        Err(e) => {
+            result.outcome = "unreachable".to_string();
+            result.error = Some(e.to_string());
+            result.error_code = Some(ErrorCode::UdpUnreachable);
+        },
+    }
+    result
+}
+
+impl UdpResult {
+    /// A probe that got no reply, with `error` as the reason; outcome "timeout" until refined.
+    fn failed(port: u16, payload: &UdpPayload, bytes_sent: usize, code: ErrorCode, error: String) -> Self {
+        UdpResult {
+            status: "error".to_string(),
+            port,
+            payload: payload.name().to_string(),
+            outcome: "timeout".to_string(),
+            latency_ms: None,
+            bytes_sent,
+            bytes_received: None,
+            source: None,
+            dscp: None,
+            reply: None,
+            ntp: None,
+            snmp: None,
+            error: Some(error),
+            error_code: Some(code),
+            data: None,
+        }
+    }
+}
+
+/// Versions listed in a QUIC Version Negotiation packet (long header, version 0).
+fn quic_versions(packet: &[u8]) -> Option<Vec<u32>> {
+    if packet.first()? & 0x80 == 0 || packet.get(1..5)? != [0, 0, 0, 0] {
+        return None;
+    }
+    let mut pos = 5;
+    for _ in 0..2 {
+        let len = *packet.get(pos)? as usize;
+        pos += 1 + len;
+    }
+    let versions: Vec<u32> = packet.get(pos..)?.chunks_exact(4).map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]])).collect();
+    (!versions.is_empty()).then_some(versions)
+}
@@ -0,0 +1,318 @@
+//! Optional: WebSocket upgrade (`--ws`, or `ws://` / `wss://` targets).
+//!
+//! Replaces the request of the HTTP stage with the opening handshake of RFC 6455:
+//! a GET carrying `Upgrade: websocket`, answered by `101 Switching Protocols` and
+//! a `Sec-WebSocket-Accept` derived from our key. An optional ping checks that
+//! frames flow; the connection then ends with a close frame, and how the server
+//! answers it (close frame, dropped connection, nothing) is reported.
+
+use super::error_code::{CodedError, ErrorCode, WithCode};
+use super::tls;
+use super::ProbeOptions;
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// Appended to the key before hashing it into `Sec-WebSocket-Accept`.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const USER_AGENT: &str = "NetProbe/1.0";
+/// Longest response head accepted.
+const MAX_HEAD: usize = 16 * 1024;
+/// Larger frames are not buffered.
+const MAX_FRAME: u64 = 1024 * 1024;
+/// Payload of our ping, echoed in the pong.
+const PING_PAYLOAD: &[u8] = b"netprobe";
+/// Normal closure.
+const CLOSE_NORMAL: u16 = 1000;
+
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSocketResult {
+    /// "ok" when the upgrade (and the ping, if sent) succeeded, "error" otherwise.
+    pub status: String,
+    /// The `ws://` or `wss://` URL upgraded.
+    pub url: String,
+    /// Status of the handshake response; 101 on success.
+    pub status_code: Option<u16>,
+    /// From sending the upgrade request to receiving the response head.
+    pub handshake_ms: Option<f64>,
+    /// Subprotocol the server picked (`Sec-WebSocket-Protocol`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    /// Round trip of a ping frame to its pong (`--ws-ping`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ping_ms: Option<f64>,
+    /// Data frames the server sent before our close (greetings, subscriptions...).
+    pub messages_received: u32,
+    /// How the connection ended; `None` when the handshake failed.
+    pub close: Option<WebSocketClose>,
+    pub error: Option<String>,
+    /// Stable code of `error`, e.g. "WS_REJECTED".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSocketClose {
+    /// "clean" (close frame answered), "dropped" (connection closed without a
+    /// close frame) or "timeout" (no answer to our close frame).
+    pub behavior: String,
+    /// "client" when the server answered our close frame, "server" when it closed first.
+    pub initiated_by: String,
+    /// Status code of the server's close frame, e.g. 1000.
+    pub code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// From sending our close frame (or the ping, when the server closed first) to the end.
+    pub latency_ms: f64,
+}
+
+impl WebSocketResult {
+    fn pending(url: &Url) -> Self {
+        WebSocketResult { status: "error".to_string(), url: ws_url(url), status_code: None, handshake_ms: None, protocol: None, ping_ms: None, messages_received: 0, close: None, error: None, error_code: None }
+    }
+}
+
+/// Upgrade a fresh connection to `addr` for `url`; over TLS for `wss://` and `https://`.
+pub async fn probe(addr: SocketAddr, url: &Url, options: &ProbeOptions) -> WebSocketResult {
+    let mut result = WebSocketResult::pending(url);
+    let stream = match tokio::time::timeout(options.connect_timeout(), options.source.connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            result.error = Some(e.to_string());
+            result.error_code = Some(ErrorCode::WsConnect);
+            return result;
+        },
+        Err(_) => {
+            result.error = Some("Connection timed out".to_string());
+            result.error_code = Some(ErrorCode::WsConnect);
+            return result;
+        },
+    };
+    tracing::debug!(%addr, url = %result.url, "opening websocket");
+    // Frames are small writes; without this the ping would wait for the handshake's ACK (Nagle).
+    let _ = stream.set_nodelay(true);
+
+    let outcome = if matches!(url.scheme(), "wss" | "https") {
+        // The TLS stage already reported on the certificate; this connection only carries the upgrade.
+        let host = options.sni.as_deref().or(url.host_str()).unwrap_or_default();
+        let mut config = rustls::ClientConfig::builder().with_safe_defaults().with_custom_certificate_verifier(tls::verifier(options)).with_client_cert_resolver(tls::ClientAuth::new(options));
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let connect = async { TlsConnector::from(Arc::new(config)).connect(tls::server_name(host)?, stream).await.map_err(|e| format!("TLS handshake failed: {}", e)) };
+        match tokio::time::timeout(options.tls_timeout(), connect).await {
+            Ok(Ok(mut stream)) => session(&mut stream, url, &mut result, options).await,
+            Ok(Err(e)) => Err(CodedError::new(ErrorCode::WsTls, e)),
+            Err(_) => Err(CodedError::new(ErrorCode::WsTimeout, "Handshake timed out")),
+        }
+    } else {
+        let mut stream = stream;
+        session(&mut stream, url, &mut result, options).await
+    };
+    match outcome {
+        Ok(()) => result.status = "ok".to_string(),
+        Err(e) => {
+            result.error = Some(e.message);
+            result.error_code = Some(e.code);
+        },
+    }
+    result
+}
+
+/// Handshake, optional ping, then close.
+async fn session<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, url: &Url, result: &mut WebSocketResult, options: &ProbeOptions) -> Result<(), CodedError> {
+    let timeout = options.http_timeout();
+    let mut key = [0u8; 16];
+    SystemRandom::new().fill(&mut key).map_err(|_| "No random numbers").code(ErrorCode::WsProtocol)?;
+    let key = base64::engine::general_purpose::STANDARD.encode(key);
+
+    let started = Instant::now();
+    let request = upgrade_request(url, &key, options).code(ErrorCode::WsProtocol)?;
+    stream.write_all(request.as_bytes()).await.code(ErrorCode::WsClosed)?;
+    let head = tokio::time::timeout(timeout, read_head(stream)).await.map_err(|_| format!("No response within {:.1}s", timeout.as_secs_f64())).code(ErrorCode::WsTimeout)??;
+    result.handshake_ms = Some(ms_since(started));
+
+    let mut lines = head.lines();
+    let status_line = lines.next().unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let code = match (parts.next(), parts.next().and_then(|code| code.parse::<u16>().ok())) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/") => code,
+        _ => return Err(CodedError::new(ErrorCode::WsProtocol, format!("Not an HTTP response: {}", status_line))),
+    };
+    result.status_code = Some(code);
+    if code != 101 {
+        let status = status_line.split_once(' ').map_or(status_line, |(_, status)| status);
+        return Err(CodedError::new(ErrorCode::WsRejected, format!("Server answered {} instead of 101 Switching Protocols", status)));
+    }
+    let headers: Vec<(String, &str)> = lines.filter_map(|line| line.split_once(':')).map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim())).collect();
+    let header = |name: &str| headers.iter().find(|(key, _)| key == name).map(|(_, value)| *value);
+    if !header("upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) {
+        return Err(CodedError::new(ErrorCode::WsRejected, "Response to the upgrade lacks `Upgrade: websocket`"));
+    }
+    if header("sec-websocket-accept") != Some(accept_for(&key).as_str()) {
+        return Err(CodedError::new(ErrorCode::WsRejected, "Wrong Sec-WebSocket-Accept: the server (or a proxy) does not speak WebSocket"));
+    }
+    result.protocol = header("sec-websocket-protocol").map(str::to_string);
+
+    if options.websocket_ping {
+        let sent = Instant::now();
+        write_frame(stream, OPCODE_PING, PING_PAYLOAD).await.code(ErrorCode::WsClosed)?;
+        loop {
+            match tokio::time::timeout(timeout, read_frame(stream)).await {
+                Err(_) => return Err(CodedError::new(ErrorCode::WsTimeout, format!("No pong within {:.1}s", timeout.as_secs_f64()))),
+                Ok(Err(e)) => return Err(CodedError::new(ErrorCode::WsClosed, format!("Connection closed before the pong: {}", e))),
+                Ok(Ok((OPCODE_PONG, payload))) if payload == PING_PAYLOAD => break,
+                Ok(Ok((OPCODE_CLOSE, payload))) => {
+                    result.close = Some(closed_by_server(&payload, sent));
+                    return Err(CodedError::new(ErrorCode::WsClosed, "Server closed the connection instead of answering the ping"));
+                },
+                Ok(Ok((opcode, _))) => count_message(result, opcode),
+            }
+        }
+        result.ping_ms = Some(ms_since(sent));
+    }
+
+    let sent = Instant::now();
+    write_frame(stream, OPCODE_CLOSE, &CLOSE_NORMAL.to_be_bytes()).await.code(ErrorCode::WsClosed)?;
+    let mut close = WebSocketClose { behavior: "timeout".to_string(), initiated_by: "client".to_string(), code: None, reason: None, latency_ms: 0.0 };
+    loop {
+        match tokio::time::timeout(timeout, read_frame(stream)).await {
+            Err(_) => break,
+            Ok(Err(_)) => {
+                close.behavior = "dropped".to_string();
+                break;
+            },
+            Ok(Ok((OPCODE_CLOSE, payload))) => {
+                close = WebSocketClose { initiated_by: "client".to_string(), ..closed_by_server(&payload, sent) };
+                break;
+            },
+            Ok(Ok((opcode, _))) => count_message(result, opcode),
+        }
+    }
+    close.latency_ms = ms_since(sent);
+    result.close = Some(close);
+    Ok(())
+}
+
+/// The opening handshake, with the user's headers and credentials.
+fn upgrade_request(url: &Url, key: &str, options: &ProbeOptions) -> Result<String, String> {
+    let mut headers = vec![
+        ("Host".to_string(), url[url::Position::BeforeHost..url::Position::AfterPort].to_string()),
+        ("Upgrade".to_string(), "websocket".to_string()),
+        ("Connection".to_string(), "Upgrade".to_string()),
+        ("Sec-WebSocket-Key".to_string(), key.to_string()),
+        ("Sec-WebSocket-Version".to_string(), "13".to_string()),
+        ("User-Agent".to_string(), USER_AGENT.to_string()),
+    ];
+    if let Some(value) = options.auth.as_ref().and_then(|auth| auth.preemptive()) {
+        headers.push(("Authorization".to_string(), value));
+    }
+    // User headers replace the defaults of the same name (Origin, Sec-WebSocket-Protocol... are added).
+    for (name, value) in &options.headers {
+        if name.contains([':', '\r', '\n']) || value.contains(['\r', '\n']) {
+            return Err(format!("Invalid header '{}'", name));
+        }
+        headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+    }
+    headers.extend(options.headers.iter().cloned());
+    let path = &url[url::Position::BeforePath..url::Position::AfterQuery];
+    let mut request = format!("GET {} HTTP/1.1\r\n", if path.is_empty() { "/" } else { path });
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    Ok(request)
+}
+
+/// `Sec-WebSocket-Accept` expected for `key`.
+fn accept_for(key: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, format!("{}{}", key, ACCEPT_GUID).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest.as_ref())
+}
+
+/// Status line and headers, up to the empty line. Bytes are read one at a time
+/// so no frame sent right after the response is consumed.
+async fn read_head<S: AsyncRead + Unpin>(stream: &mut S) -> Result<String, CodedError> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD {
+            return Err(CodedError::new(ErrorCode::WsProtocol, "Response head too long"));
+        }
+        head.push(stream.read_u8().await.map_err(|_| "Connection closed by the server").code(ErrorCode::WsClosed)?);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Send one final frame; client frames must be masked.
+async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, opcode: u8, payload: &[u8]) -> Result<(), String> {
+    let mut mask = [0u8; 4];
+    SystemRandom::new().fill(&mut mask).map_err(|_| "No random numbers".to_string())?;
+    // Control frames carry at most 125 bytes, so the length always fits in 7 bits.
+    let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+    frame.extend(mask);
+    frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+    stream.write_all(&frame).await.map_err(|e| e.to_string())
+}
+
+/// Opcode and payload of the next frame; payloads over `MAX_FRAME` are skipped, not kept.
+async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(u8, Vec<u8>), String> {
+    let closed = |_| "connection closed".to_string();
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await.map_err(closed)?;
+    let length = match header[1] & 0x7f {
+        126 => u64::from(stream.read_u16().await.map_err(closed)?),
+        127 => stream.read_u64().await.map_err(closed)?,
+        length => u64::from(length),
+    };
+    let mut mask = None;
+    if header[1] & 0x80 != 0 {
+        let mut key = [0u8; 4];
+        stream.read_exact(&mut key).await.map_err(closed)?;
+        mask = Some(key);
+    }
+    if length > MAX_FRAME {
+        tokio::io::copy(&mut (&mut *stream).take(length), &mut tokio::io::sink()).await.map_err(closed)?;
+        return Ok((header[0] & 0x0f, Vec::new()));
+    }
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload).await.map_err(closed)?;
+    if let Some(mask) = mask {
+        payload.iter_mut().enumerate().for_each(|(i, byte)| *byte ^= mask[i % 4]);
+    }
+    Ok((header[0] & 0x0f, payload))
+}
+
+/// Text, binary and continuation frames count as messages; pings and stray pongs do not.
+fn count_message(result: &mut WebSocketResult, opcode: u8) {
+    if opcode < OPCODE_CLOSE {
+        result.messages_received += 1;
+    }
+}
+
+/// A close frame from the server: a 2-byte status code, then an optional UTF-8 reason.
+fn closed_by_server(payload: &[u8], since: Instant) -> WebSocketClose {
+    let code = payload.get(..2).map(|code| u16::from_be_bytes([code[0], code[1]]));
+    let reason = payload.get(2..).map(String::from_utf8_lossy).map(|reason| reason.into_owned()).filter(|reason| !reason.is_empty());
+    WebSocketClose { behavior: "clean".to_string(), initiated_by: "server".to_string(), code, reason, latency_ms: ms_since(since) }
+}
+
+/// `url` with its scheme turned into the WebSocket one.
+fn ws_url(url: &Url) -> String {
+    let mut ws = url.clone();
+    let scheme = if matches!(url.scheme(), "https" | "wss") { "wss" } else { "ws" };
+    // Switching between special schemes always succeeds.
+    let _ = ws.set_scheme(scheme);
+    ws.to_string()
+}
+
+fn ms_since(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
@@ -0,0 +1,268 @@
+//! Shareable reports of a run (`--report out.html` / `out.md`).
+//!
+//! A summary table of every target, a latency chart split by stage, then the
+//! stages, certificate and checks of each target. HTML reports are a single
+//! self-contained file (inline CSS, no scripts) so they can be attached to an
+//! incident ticket as-is.
+
+use crate::probe::ProbeResult;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Stages shown in the latency chart, with their HTML colour.
+const STAGES: [(&str, &str); 4] = [("DNS", "#8e44ad"), ("TCP", "#2980b9"), ("TLS", "#16a085"), ("HTTP", "#e67e22")];
+/// Width of the Markdown latency bars, in characters.
+const BAR_WIDTH: f64 = 40.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Html,
+    Markdown,
+}
+
+impl ReportFormat {
+    /// Pick the format from the file extension: `.html`/`.htm` or `.md`/`.markdown`.
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("html" | "htm") => Ok(ReportFormat::Html),
+            Some("md" | "markdown") => Ok(ReportFormat::Markdown),
+            _ => Err(format!("Cannot tell the report format of {} (use a .html or .md file)", path.display())),
+        }
+    }
+}
+
+/// Render `results` and write them to `path`, in the format its extension names.
+pub fn write(path: &Path, results: &[ProbeResult]) -> Result<(), String> {
+    let report = match ReportFormat::from_path(path)? {
+        ReportFormat::Html => html(results),
+        ReportFormat::Markdown => markdown(results),
+    };
+    std::fs::write(path, report).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+/// Stage latencies in chart order; `None` for stages that did not complete.
+fn stage_latencies(result: &ProbeResult) -> [Option<f64>; 4] {
+    [result.dns.latency_ms, result.tcp.latency_ms, result.tls.latency_ms, result.http.latency_ms]
+}
+
+fn verdict(result: &ProbeResult) -> &'static str {
+    if result.is_success() { "✅ OK" } else { "❌ Failed" }
+}
+
+/// The first stage error, as shown in the summary.
+fn first_error(result: &ProbeResult) -> Option<&str> {
+    [&result.dns.error, &result.tcp.error, &result.tls.error, &result.http.error].into_iter().find_map(|e| e.as_deref())
+}
+
+fn ms(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.2} ms", v))
+}
+
+/// (stage, status, latency, detail) rows of the stage table.
+fn stage_rows(result: &ProbeResult) -> Vec<[String; 4]> {
+    let detail = |e: &Option<String>| e.clone().unwrap_or_default();
+    let mut rows = vec![[String::from("DNS"), result.dns.status.clone(), ms(result.dns.latency_ms), result.dns.ip.clone().or_else(|| result.dns.error.clone()).unwrap_or_default()]];
+    if let Some(icmp) = &result.icmp {
+        rows.push(["ICMP".to_string(), icmp.status.clone(), ms(icmp.rtt_avg_ms), detail(&icmp.error)]);
+    }
+    if let Some(udp) = &result.udp {
+        rows.push(["UDP".to_string(), udp.status.clone(), ms(udp.latency_ms), detail(&udp.error)]);
+        return rows;
+    }
+    rows.push(["TCP".to_string(), result.tcp.status.clone(), ms(result.tcp.latency_ms), detail(&result.tcp.error)]);
+    if let Some(service) = &result.tcp.service {
+        rows.push([service.protocol.to_uppercase(), service.status.clone(), ms(service.greeting_ms), service.error.clone().or(service.banner.clone()).unwrap_or_default()]);
+    }
+    let tls = result.tls.protocol.iter().chain(&result.tls.cipher_suite).cloned().collect::<Vec<_>>().join(" ");
+    rows.push(["TLS".to_string(), result.tls.status.clone(), ms(result.tls.latency_ms), result.tls.error.clone().or(result.tls.warning.clone()).unwrap_or(tls)]);
+    let http_status = match (result.http.status_code, &result.http.error) {
+        (Some(_), _) => "ok",
+        (None, Some(_)) => "error",
+        (None, None) => "skipped",
+    };
+    if let Some(ws) = &result.websocket {
+        rows.push(["WebSocket".to_string(), ws.status.clone(), ms(ws.handshake_ms), ws.error.clone().unwrap_or_else(|| ws.url.clone())]);
+        return rows;
+    }
+    let http = result.http.status_code.map(|code| format!("{} {}", result.http.version.as_deref().unwrap_or("HTTP"), code));
+    rows.push(["HTTP".to_string(), http_status.to_string(), ms(result.http.latency_ms), http.or(result.http.error.clone()).unwrap_or_default()]);
+    if let Some(grpc) = &result.http.grpc {
+        rows.push(["gRPC".to_string(), grpc.status.clone(), ms(grpc.latency_ms), grpc.error.clone().or(grpc.serving_status.clone()).unwrap_or_default()]);
+    }
+    rows
+}
+
+/// Label and value rows describing the leaf certificate.
+fn certificate_rows(result: &ProbeResult) -> Vec<(&'static str, String)> {
+    let Some(cert) = &result.tls.certificate else { return Vec::new() };
+    vec![
+        ("Subject", cert.subject.clone()),
+        ("Issuer", cert.issuer.clone()),
+        ("SANs", cert.sans.join(", ")),
+        ("Valid from", cert.not_before.clone()),
+        ("Valid until", format!("{} ({} days left)", cert.not_after, cert.days_until_expiry)),
+        ("Verification", result.tls.verification.clone().unwrap_or_default()),
+    ]
+}
+
+/// Assertion and latency budget rows: (check, expected, actual, passed).
+fn check_rows(result: &ProbeResult) -> Vec<(String, String, String, bool)> {
+    let assertions = result.assertions.iter().map(|a| (a.name.clone(), a.expected.clone(), a.actual.clone().unwrap_or_else(|| "-".to_string()), a.passed));
+    let budgets = result.latency_checks.iter().map(|c| (format!("{} latency", c.stage), format!("≤ {:.2} ms", c.limit_ms), ms(c.latency_ms), c.status != "degraded"));
+    assertions.chain(budgets).collect()
+}
+
+// --- Markdown ---
+
+fn markdown(results: &[ProbeResult]) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let mut out = String::new();
+    let _ = writeln!(out, "# NetProbe Report\n");
+    let _ = writeln!(out, "Generated {} · {} target(s)\n", chrono::Local::now().to_rfc3339(), results.len());
+
+    let _ = writeln!(out, "## Summary\n");
+    let _ = writeln!(out, "| Target | Result | DNS | TCP | TLS | HTTP | Total | Error |");
+    let _ = writeln!(out, "|---|---|---|---|---|---|---|---|");
+    for result in results {
+        let [dns, tcp, tls, http] = stage_latencies(result).map(ms);
+        let status = result.http.status_code.map(|c| format!("{} ({})", c, http)).unwrap_or(http);
+        let _ = writeln!(out, "| {} | {} | {} | {} | {} | {} | {:.2} ms | {} |", cell(&result.target), verdict(result), dns, tcp, tls, status, result.duration_ms, cell(first_error(result).unwrap_or("")));
+    }
+
+    let _ = writeln!(out, "\n## Latency\n");
+    let _ = writeln!(out, "```text");
+    let longest = results.iter().map(|r| stage_latencies(r).iter().flatten().sum::<f64>()).fold(0.0, f64::max);
+    let width = results.iter().map(|r| r.target.chars().count()).max().unwrap_or(0);
+    for result in results {
+        let mut bar = String::new();
+        for (latency, symbol) in stage_latencies(result).iter().zip(['░', '▒', '▓', '█']) {
+            let cells = if longest > 0.0 { (latency.unwrap_or(0.0) / longest * BAR_WIDTH).round() as usize } else { 0 };
+            bar.extend(std::iter::repeat_n(symbol, cells));
+        }
+        let _ = writeln!(out, "{:<width$}  {:<bar_width$}  {:.2} ms", result.target, bar, stage_latencies(result).iter().flatten().sum::<f64>(), width = width, bar_width = BAR_WIDTH as usize);
+    }
+    let _ = writeln!(out, "\n░ DNS  ▒ TCP  ▓ TLS  █ HTTP");
+    let _ = writeln!(out, "```");
+
+    for result in results {
+        let _ = writeln!(out, "\n## {}\n", result.target);
+        let _ = writeln!(out, "{} · {} · {:.2} ms\n", verdict(result), result.timestamp, result.duration_ms);
+        let _ = writeln!(out, "| Stage | Status | Latency | Detail |");
+        let _ = writeln!(out, "|---|---|---|---|");
+        for [stage, status, latency, detail] in stage_rows(result) {
+            let _ = writeln!(out, "| {} | {} | {} | {} |", stage, status, latency, cell(&detail));
+        }
+        let certificate = certificate_rows(result);
+        if !certificate.is_empty() {
+            let _ = writeln!(out, "\n**Certificate**\n");
+            let _ = writeln!(out, "| | |\n|---|---|");
+            for (label, value) in certificate {
+                let _ = writeln!(out, "| {} | {} |", label, cell(&value));
+            }
+        }
+        let checks = check_rows(result);
+        if !checks.is_empty() {
+            let _ = writeln!(out, "\n**Checks**\n");
+            let _ = writeln!(out, "| Check | Expected | Actual | Result |\n|---|---|---|---|");
+            for (check, expected, actual, passed) in checks {
+                let _ = writeln!(out, "| {} | {} | {} | {} |", cell(&check), cell(&expected), cell(&actual), if passed { "✅" } else { "❌" });
+            }
+        }
+    }
+    out
+}
+
+// --- HTML ---
+
+const STYLE: &str = "body{font-family:-apple-system,Segoe UI,Helvetica,Arial,sans-serif;margin:2em auto;max-width:1100px;color:#222;padding:0 1em}\
+h1{margin-bottom:0}.meta{color:#777}table{border-collapse:collapse;margin:1em 0;width:100%}\
+th,td{border:1px solid #ddd;padding:6px 10px;text-align:left;font-size:14px}th{background:#f5f5f5}\
+.ok{color:#1e8449}.fail{color:#c0392b}.chart{margin:1em 0}.row{display:flex;align-items:center;margin:4px 0;font-size:13px}\
+.label{width:280px;overflow:hidden;text-overflow:ellipsis;white-space:nowrap}.bar{display:flex;flex:1;height:18px;background:#f5f5f5}\
+.bar div{height:100%}.total{width:90px;text-align:right}.legend span{display:inline-block;margin-right:1em;font-size:13px}\
+.legend i{display:inline-block;width:12px;height:12px;margin-right:4px;vertical-align:middle}";
+
+fn html(results: &[ProbeResult]) -> String {
+    let mut out = String::new();
+    let _ = write!(out, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>NetProbe Report</title><style>{}</style></head><body>\n", STYLE);
+    let _ = writeln!(out, "<h1>NetProbe Report</h1><p class=\"meta\">Generated {} · {} target(s)</p>", chrono::Local::now().to_rfc3339(), results.len());
+
+    let _ = writeln!(out, "<h2>Summary</h2><table><tr><th>Target</th><th>Result</th><th>DNS</th><th>TCP</th><th>TLS</th><th>HTTP</th><th>Total</th><th>Error</th></tr>");
+    for result in results {
+        let [dns, tcp, tls, http] = stage_latencies(result).map(ms);
+        let status = result.http.status_code.map(|c| format!("{} ({})", c, http)).unwrap_or(http);
+        let class = if result.is_success() { "ok" } else { "fail" };
+        let _ = writeln!(
+            out,
+            "<tr><td><a href=\"#target-{}\">{}</a></td><td class=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2} ms</td><td>{}</td></tr>",
+            anchor(&result.target),
+            escape(&result.target),
+            class,
+            verdict(result),
+            dns,
+            tcp,
+            tls,
+            escape(&status),
+            result.duration_ms,
+            escape(first_error(result).unwrap_or(""))
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    let _ = writeln!(out, "<h2>Latency</h2><div class=\"legend\">");
+    for (stage, colour) in STAGES {
+        let _ = write!(out, "<span><i style=\"background:{}\"></i>{}</span>", colour, stage);
+    }
+    let _ = writeln!(out, "</div><div class=\"chart\">");
+    let longest = results.iter().map(|r| stage_latencies(r).iter().flatten().sum::<f64>()).fold(0.0, f64::max);
+    for result in results {
+        let _ = write!(out, "<div class=\"row\"><div class=\"label\" title=\"{0}\">{0}</div><div class=\"bar\">", escape(&result.target));
+        for (latency, (stage, colour)) in stage_latencies(result).iter().zip(STAGES) {
+            if let Some(latency) = latency.filter(|_| longest > 0.0) {
+                let _ = write!(out, "<div style=\"width:{:.2}%;background:{}\" title=\"{} {:.2} ms\"></div>", latency / longest * 100.0, colour, stage, latency);
+            }
+        }
+        let _ = writeln!(out, "</div><div class=\"total\">{:.2} ms</div></div>", stage_latencies(result).iter().flatten().sum::<f64>());
+    }
+    let _ = writeln!(out, "</div>");
+
+    for result in results {
+        let _ = writeln!(out, "<h2 id=\"target-{}\">{}</h2>", anchor(&result.target), escape(&result.target));
+        let class = if result.is_success() { "ok" } else { "fail" };
+        let _ = writeln!(out, "<p class=\"meta\"><span class=\"{}\">{}</span> · {} · {:.2} ms</p>", class, verdict(result), escape(&result.timestamp), result.duration_ms);
+        let _ = writeln!(out, "<table><tr><th>Stage</th><th>Status</th><th>Latency</th><th>Detail</th></tr>");
+        for [stage, status, latency, detail] in stage_rows(result) {
+            let class = if status == "error" { "fail" } else { "" };
+            let _ = writeln!(out, "<tr><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{}</td></tr>", stage, class, status, latency, escape(&detail));
+        }
+        let _ = writeln!(out, "</table>");
+        let certificate = certificate_rows(result);
+        if !certificate.is_empty() {
+            let _ = writeln!(out, "<h3>Certificate</h3><table>");
+            for (label, value) in certificate {
+                let _ = writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", label, escape(&value));
+            }
+            let _ = writeln!(out, "</table>");
+        }
+        let checks = check_rows(result);
+        if !checks.is_empty() {
+            let _ = writeln!(out, "<h3>Checks</h3><table><tr><th>Check</th><th>Expected</th><th>Actual</th><th>Result</th></tr>");
+            for (check, expected, actual, passed) in checks {
+                let (class, mark) = if passed { ("ok", "✅") } else { ("fail", "❌") };
+                let _ = writeln!(out, "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td></tr>", escape(&check), escape(&expected), escape(&actual), class, mark);
+            }
+            let _ = writeln!(out, "</table>");
+        }
+    }
+    let _ = writeln!(out, "</body></html>");
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// An id attribute for a target's section.
+fn anchor(target: &str) -> String {
+    target.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect()
+}
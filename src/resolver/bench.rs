@@ -0,0 +1,142 @@
+//! Resolver benchmark (`netprobe dns-bench`): the same lookups through several
+//! resolvers, repeated, to rank them by how fast they answer.
+//!
+//! Every round asks each name of the list to all resolvers at once, so they are
+//! compared under the same network conditions. The first round mostly measures
+//! cache misses at the resolver, the following ones its cache; the median
+//! weighs both.
+
+use super::propagation::PUBLIC_RESOLVERS;
+use super::{wire, Resolver, Upstream};
+use crate::stats::LatencyStats;
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// Names looked up when none is given: popular domains served by different DNS providers.
+pub const BENCH_NAMES: [&str; 10] = [
+    "google.com",
+    "youtube.com",
+    "facebook.com",
+    "wikipedia.org",
+    "amazon.com",
+    "github.com",
+    "cloudflare.com",
+    "microsoft.com",
+    "apple.com",
+    "netflix.com",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bench {
+    pub names: Vec<String>,
+    #[serde(rename = "type")]
+    pub rtype: String,
+    pub iterations: u32,
+    pub timestamp: String,
+    pub status: String, // "ok" (every resolver answered) | "partial" (some never did) | "error" (none did)
+    /// Fastest first: by median latency, then p95; resolvers that never answered come last.
+    pub resolvers: Vec<ResolverBench>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolverBench {
+    /// 1 for the fastest; `None` for a resolver that never answered.
+    pub rank: Option<usize>,
+    /// Operator of a well-known public resolver, or "System" for the configured nameservers.
+    pub name: Option<String>,
+    pub server: String,
+    /// "udp" | "tls" | "https" | "quic"
+    pub transport: String,
+    pub queries: usize,
+    /// Queries that timed out or were answered with an error (SERVFAIL, REFUSED...).
+    pub failures: usize,
+    pub failure_rate: f64,
+    /// Latency of the answered queries.
+    pub latency: Option<LatencyStats>,
+    /// Most recent failure.
+    pub error: Option<String>,
+}
+
+/// Look up every name `iterations` times through each resolver in `resolvers` ((label, server)).
+pub async fn run(names: &[String], rtype: u16, resolvers: &[(Option<String>, Upstream)], iterations: u32, timeout: Duration) -> Bench {
+    let mut latencies: Vec<Vec<f64>> = vec![Vec::new(); resolvers.len()];
+    let mut failures = vec![0; resolvers.len()];
+    let mut errors: Vec<Option<String>> = vec![None; resolvers.len()];
+    for _ in 0..iterations {
+        for name in names {
+            let lookups = resolvers.iter().map(|(_, server)| async move { Resolver::new(vec![server.clone()], timeout).query(name, rtype).await });
+            for (i, outcome) in join_all(lookups).await.into_iter().enumerate() {
+                match outcome {
+                    Ok(response) => latencies[i].push(response.latency_ms),
+                    Err(e) => {
+                        failures[i] += 1;
+                        errors[i] = Some(format!("{}: {}", name, e));
+                    },
+                }
+            }
+        }
+    }
+
+    let queries = names.len() * iterations as usize;
+    let mut results: Vec<ResolverBench> = resolvers
+        .iter()
+        .enumerate()
+        .map(|(i, (label, server))| ResolverBench {
+            rank: None,
+            name: label.clone(),
+            server: server.to_string(),
+            transport: server.transport().to_string(),
+            queries,
+            failures: failures[i],
+            failure_rate: if queries == 0 { 0.0 } else { failures[i] as f64 * 100.0 / queries as f64 },
+            latency: LatencyStats::from_samples(&latencies[i]),
+            error: errors[i].take(),
+        })
+        .collect();
+    results.sort_by(|a, b| match (&a.latency, &b.latency) {
+        (Some(a), Some(b)) => a.p50_ms.total_cmp(&b.p50_ms).then(a.p95_ms.total_cmp(&b.p95_ms)),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    for (rank, result) in results.iter_mut().filter(|result| result.latency.is_some()).enumerate() {
+        result.rank = Some(rank + 1);
+    }
+
+    let answered = results.iter().filter(|result| result.latency.is_some()).count();
+    let status = match answered {
+        0 => "error",
+        n if n == results.len() => "ok",
+        _ => "partial",
+    };
+    Bench {
+        names: names.to_vec(),
+        rtype: wire::type_name(rtype),
+        iterations,
+        timestamp: chrono::Local::now().to_rfc3339(),
+        status: status.to_string(),
+        resolvers: results,
+    }
+}
+
+/// One address of each global [`PUBLIC_RESOLVERS`] operator, on port 53.
+pub fn default_resolvers() -> Vec<(Option<String>, Upstream)> {
+    let mut resolvers: Vec<(Option<String>, Upstream)> = Vec::new();
+    for (operator, region, address) in PUBLIC_RESOLVERS {
+        let Ok(ip) = address.parse::<IpAddr>() else {
+            continue;
+        };
+        if region == "global" && !resolvers.iter().any(|(name, _)| name.as_deref() == Some(operator)) {
+            resolvers.push((Some(operator.to_string()), Upstream::Plain(SocketAddr::new(ip, 53))));
+        }
+    }
+    resolvers
+}
+
+/// Operator of a public resolver address, for the servers given on the command line.
+pub fn label(server: &Upstream) -> Option<String> {
+    let Upstream::Plain(addr) = server else {
+        return None;
+    };
+    PUBLIC_RESOLVERS.iter().find(|(_, _, address)| address.parse::<IpAddr>().ok() == Some(addr.ip())).map(|(operator, _, _)| operator.to_string())
+}
@@ -0,0 +1,896 @@
+//! DNSSEC validation (RFC 4033-4035, 5155) of one answer, from the root trust
+//! anchor down (`--dnssec`).
+//!
+//! The queries go to an ordinary recursive server with the DO and CD bits set:
+//! it hands over signatures, keys and denials without judging them, and the
+//! chain is checked here zone by zone. The root's DNSKEY must match the trust
+//! anchor; each zone below has a DS in its parent that matches one of its
+//! DNSKEYs, and signs its own records. The first link that does not hold is the
+//! one a validating resolver would SERVFAIL on, and is reported as such.
+
+use super::wire::{self, RData, Record};
+use super::Resolver;
+use chrono::{TimeZone, Utc};
+use ring::{digest, signature};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Root KSKs as published by IANA (root-anchors.xml): key tag, algorithm, SHA-256 digest.
+const ROOT_ANCHORS: [(u16, u8, &str); 2] = [
+    (20326, 8, "e06d44b80b8f1d39a95c0b0d7c65d08458e880409bbc683457104237c7f8ec8d"),
+    (38696, 8, "683d2d0acb8c9b712a1948b27f741219298d0a450d612c483af444a4c0fb2b16"),
+];
+/// DNSKEY flags: the key signs the zone's records.
+const ZONE_KEY: u16 = 0x0100;
+/// NSEC3 flags: the span may cover unsigned delegations.
+const OPT_OUT: u8 = 0x01;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnssecResult {
+    /// "secure" | "insecure" | "bogus" | "indeterminate" (the records needed could not be fetched)
+    pub status: String,
+    /// Each link checked, from the root down, as far as the chain went.
+    pub chain: Vec<DnssecStep>,
+    /// Why the answer is not secure: where signing stops, or the link that broke.
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnssecStep {
+    /// Owner of the records checked (a zone for DS and DNSKEY).
+    pub name: String,
+    /// "DNSKEY" | "DS" | the type of the answer.
+    pub record: String,
+    /// "secure" | "insecure" | "bogus" | "indeterminate"
+    pub status: String,
+    pub detail: String,
+}
+
+/// Where a zone's keys stand.
+#[derive(Clone)]
+enum Verdict {
+    /// Authenticated from the trust anchor: these keys sign the zone.
+    Secure(Vec<Dnskey>),
+    Insecure(String),
+    Bogus(String),
+    Indeterminate(String),
+}
+
+#[derive(Clone)]
+struct Dnskey {
+    flags: u16,
+    algorithm: u8,
+    public_key: Vec<u8>,
+    rdata: Vec<u8>,
+    tag: u16,
+}
+
+struct Rrsig {
+    type_covered: u16,
+    algorithm: u8,
+    labels: u8,
+    original_ttl: u32,
+    expiration: u32,
+    inception: u32,
+    key_tag: u16,
+    signer: String,
+    signature: Vec<u8>,
+    /// RDATA up to the signer name, the start of the signed data.
+    header: Vec<u8>,
+}
+
+struct Ds {
+    key_tag: u16,
+    algorithm: u8,
+    digest_type: u8,
+    digest: Vec<u8>,
+}
+
+/// An NSEC whose signature verified: the span from `owner` to `next`, and the types at `owner`.
+struct Nsec {
+    owner: String,
+    next: String,
+    types: Vec<u8>,
+}
+
+/// An NSEC3 whose signature verified: the span between two hashed names of `zone`.
+struct Nsec3 {
+    zone: String,
+    flags: u8,
+    iterations: u16,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+    next: Vec<u8>,
+    types: Vec<u8>,
+}
+
+/// Validate the `qtype` records of `name`, CNAMEs leading to them included.
+pub async fn validate(resolver: &Resolver, name: &str, qtype: u16) -> DnssecResult {
+    let mut validator = Validator { resolver, now: now(), zones: HashMap::new(), chain: Vec::new() };
+    let verdict = validator.answer(&canonical(name), qtype).await;
+    let (status, reason) = match verdict {
+        Verdict::Secure(_) => ("secure", None),
+        Verdict::Insecure(reason) => ("insecure", Some(reason)),
+        Verdict::Bogus(reason) => ("bogus", Some(reason)),
+        Verdict::Indeterminate(reason) => ("indeterminate", Some(reason)),
+    };
+    DnssecResult { status: status.to_string(), chain: validator.chain, reason }
+}
+
+struct Validator<'a> {
+    resolver: &'a Resolver,
+    /// Seconds since the epoch, in RRSIG serial arithmetic.
+    now: u32,
+    /// Verdict of each zone checked, as several answers may share a zone.
+    zones: HashMap<String, Verdict>,
+    chain: Vec<DnssecStep>,
+}
+
+impl Validator<'_> {
+    /// Every RRset of the answer, each signed by its own zone.
+    async fn answer(&mut self, name: &str, qtype: u16) -> Verdict {
+        let message = match self.fetch(name, qtype).await {
+            Ok(message) => message,
+            Err(e) => return Verdict::Indeterminate(e),
+        };
+        let mut rrsets: Vec<(String, u16)> = Vec::new();
+        for record in message.answers.iter().filter(|record| record.rtype != wire::TYPE_RRSIG) {
+            let key = (canonical(&record.name), record.rtype);
+            if !rrsets.contains(&key) {
+                rrsets.push(key);
+            }
+        }
+        if rrsets.is_empty() {
+            return Verdict::Indeterminate(format!("No {} records to validate ({})", wire::type_name(qtype), wire::rcode_name(message.rcode)));
+        }
+
+        let mut verdict = Verdict::Secure(Vec::new());
+        for (owner, rtype) in rrsets {
+            let records = rrset(&message.answers, &owner, rtype);
+            let sigs = signatures(&message.answers, &owner, rtype);
+            let record = wire::type_name(rtype);
+            let outcome = match sigs.first().map(|sig| canonical(&sig.signer)) {
+                Some(signer) if !is_within(&owner, &signer) => self.fail(&owner, &record, format!("signed by {}, which is not one of its zones", signer)),
+                Some(signer) => match self.zone_keys(signer.clone()).await {
+                    Verdict::Secure(keys) => match self.verify_rrset(&records, &sigs, &signer, &keys) {
+                        Ok(detail) => {
+                            self.step(&owner, &record, "secure", detail);
+                            Verdict::Secure(keys)
+                        },
+                        Err(e) => self.fail(&owner, &record, e),
+                    },
+                    other => other,
+                },
+                // Unsigned: fine only if the zone holding it is unsigned too.
+                None => match self.enclosing_zone(&owner).await {
+                    Ok(zone) => match self.zone_keys(zone.clone()).await {
+                        Verdict::Secure(_) => self.fail(&owner, &record, format!("no RRSIG although {} is signed", zone)),
+                        other => other,
+                    },
+                    Err(e) => Verdict::Indeterminate(e),
+                },
+            };
+            verdict = match (verdict, outcome) {
+                (_, bogus @ Verdict::Bogus(_)) => return bogus,
+                (Verdict::Indeterminate(reason), _) | (_, Verdict::Indeterminate(reason)) => Verdict::Indeterminate(reason),
+                (Verdict::Insecure(reason), _) | (_, Verdict::Insecure(reason)) => Verdict::Insecure(reason),
+                (Verdict::Secure(_), secure) => secure,
+                (other, _) => other,
+            };
+        }
+        verdict
+    }
+
+    /// The keys of `zone`, authenticated from the root; memoized.
+    fn zone_keys(&mut self, zone: String) -> Pin<Box<dyn Future<Output = Verdict> + Send + '_>> {
+        Box::pin(async move {
+            if let Some(verdict) = self.zones.get(&zone) {
+                return verdict.clone();
+            }
+            let verdict = match zone.as_str() {
+                "." => self.root_keys().await,
+                _ => self.delegated_keys(&zone).await,
+            };
+            self.zones.insert(zone, verdict.clone());
+            verdict
+        })
+    }
+
+    /// The root DNSKEY set: one key must match the trust anchor and sign the set.
+    async fn root_keys(&mut self) -> Verdict {
+        let message = match self.fetch(".", wire::TYPE_DNSKEY).await {
+            Ok(message) => message,
+            Err(e) => return Verdict::Indeterminate(e),
+        };
+        let records = rrset(&message.answers, ".", wire::TYPE_DNSKEY);
+        let sigs = signatures(&message.answers, ".", wire::TYPE_DNSKEY);
+        if sigs.is_empty() {
+            let reason = "The DNS server returns no RRSIG records: it does not pass DNSSEC data on".to_string();
+            self.step(".", "DNSKEY", "indeterminate", reason.clone());
+            return Verdict::Indeterminate(reason);
+        }
+        let keys: Vec<Dnskey> = records.iter().filter_map(|record| Dnskey::parse(&record.raw)).collect();
+        let anchored: Vec<Dnskey> = keys
+            .iter()
+            .filter(|key| ROOT_ANCHORS.iter().any(|(tag, algorithm, digest)| key.tag == *tag && key.algorithm == *algorithm && ds_digest(".", key, 2).is_some_and(|computed| wire::hex(&computed) == *digest)))
+            .cloned()
+            .collect();
+        if anchored.is_empty() {
+            let tags: Vec<String> = ROOT_ANCHORS.iter().map(|(tag, _, _)| tag.to_string()).collect();
+            return self.fail(".", "DNSKEY", format!("no key matches the trust anchor (KSK {})", tags.join(" or ")));
+        }
+        match self.verify_rrset(&records, &sigs, ".", &anchored) {
+            Ok(detail) => {
+                self.step(".", "DNSKEY", "secure", format!("KSK {} matches the trust anchor; {}", anchored[0].tag, detail));
+                Verdict::Secure(keys)
+            },
+            Err(e) => self.fail(".", "DNSKEY", e),
+        }
+    }
+
+    /// A zone below the root: its DS in the parent, then its DNSKEY set.
+    async fn delegated_keys(&mut self, zone: &str) -> Verdict {
+        let message = match self.fetch(zone, wire::TYPE_DS).await {
+            Ok(message) => message,
+            Err(e) => return Verdict::Indeterminate(e),
+        };
+        let ds_records = rrset(&message.answers, zone, wire::TYPE_DS);
+        if ds_records.is_empty() {
+            return self.no_ds(zone, &message.authorities).await;
+        }
+        let ds_sigs = signatures(&message.answers, zone, wire::TYPE_DS);
+        let signer = ds_sigs.first().map(|sig| canonical(&sig.signer)).unwrap_or_else(|| parent(zone));
+        let parent_keys = match self.zone_keys(signer.clone()).await {
+            Verdict::Secure(keys) => keys,
+            other => return other,
+        };
+        match self.verify_rrset(&ds_records, &ds_sigs, &signer, &parent_keys) {
+            Ok(detail) => self.step(zone, "DS", "secure", format!("{} DS record(s) in {}; {}", ds_records.len(), signer, detail)),
+            Err(e) => return self.fail(zone, "DS", format!("in {}: {}", signer, e)),
+        }
+
+        let message = match self.fetch(zone, wire::TYPE_DNSKEY).await {
+            Ok(message) => message,
+            Err(e) => return Verdict::Indeterminate(e),
+        };
+        let records = rrset(&message.answers, zone, wire::TYPE_DNSKEY);
+        let keys: Vec<Dnskey> = records.iter().filter_map(|record| Dnskey::parse(&record.raw)).collect();
+        if keys.is_empty() {
+            return self.fail(zone, "DNSKEY", format!("none published although {} has a DS for the zone", signer));
+        }
+        let dss: Vec<Ds> = ds_records.iter().filter_map(|record| Ds::parse(&record.raw)).collect();
+        let matched: Vec<Dnskey> = keys.iter().filter(|key| dss.iter().any(|ds| ds.matches(zone, key))).cloned().collect();
+        if matched.is_empty() {
+            // Same tag but another digest: the DS was computed from a different key, or mistyped.
+            if let Some(ds) = dss.iter().find(|ds| keys.iter().any(|key| key.tag == ds.key_tag)) {
+                return self.fail(zone, "DNSKEY", format!("the DS in {} for key {} has digest {}, which does not match that DNSKEY", signer, ds.key_tag, wire::hex(&ds.digest)));
+            }
+            let tags: Vec<String> = dss.iter().map(|ds| ds.key_tag.to_string()).collect();
+            let published: Vec<String> = keys.iter().map(|key| key.tag.to_string()).collect();
+            return self.fail(zone, "DNSKEY", format!("none matches the DS in {} (DS for key {}, DNSKEY {})", signer, tags.join(", "), published.join(", ")));
+        }
+        let sigs = signatures(&message.answers, zone, wire::TYPE_DNSKEY);
+        match self.verify_rrset(&records, &sigs, zone, &matched) {
+            Ok(detail) => {
+                self.step(zone, "DNSKEY", "secure", format!("KSK {} matches the DS; {}", matched[0].tag, detail));
+                Verdict::Secure(keys)
+            },
+            Err(e) => self.fail(zone, "DNSKEY", e),
+        }
+    }
+
+    /// No DS for `zone`: an unsigned delegation, provided the parent proves it with a signed NSEC or NSEC3.
+    async fn no_ds(&mut self, zone: &str, authority: &[Record]) -> Verdict {
+        let signer = authority
+            .iter()
+            .filter(|record| record.rtype == wire::TYPE_RRSIG)
+            .filter_map(|record| Rrsig::parse(&record.raw))
+            .find(|sig| matches!(sig.type_covered, wire::TYPE_NSEC | wire::TYPE_NSEC3 | wire::TYPE_SOA))
+            .map(|sig| canonical(&sig.signer));
+        let Some(signer) = signer else {
+            // Nothing signed: acceptable only below an unsigned parent.
+            let parent_zone = authority.iter().find(|record| record.rtype == wire::TYPE_SOA).map(|record| canonical(&record.name)).unwrap_or_else(|| parent(zone));
+            return match self.zone_keys(parent_zone.clone()).await {
+                Verdict::Secure(_) => self.fail(zone, "DS", format!("none in {}, which does not prove its absence (no signed NSEC or NSEC3)", parent_zone)),
+                other => other,
+            };
+        };
+        let keys = match self.zone_keys(signer.clone()).await {
+            Verdict::Secure(keys) => keys,
+            other => return other,
+        };
+
+        // Check every record first: the proof may need several of them.
+        let mut errors = Vec::new();
+        let (mut nsecs, mut nsec3s) = (Vec::new(), Vec::new());
+        for record in authority.iter().filter(|record| matches!(record.rtype, wire::TYPE_NSEC | wire::TYPE_NSEC3)) {
+            let owner = canonical(&record.name);
+            if let Err(e) = self.verify_rrset(&[record], &signatures(authority, &owner, record.rtype), &signer, &keys) {
+                errors.push(format!("{} {}: {}", wire::type_name(record.rtype), owner, e));
+                continue;
+            }
+            match record.rtype {
+                wire::TYPE_NSEC => nsecs.extend(Nsec::parse(&owner, &record.raw)),
+                _ => nsec3s.extend(Nsec3::parse(&owner, &signer, &record.raw)),
+            }
+        }
+        let proof = nsec_denies_ds(&nsecs, zone).or_else(|| nsec3_denies_ds(&nsec3s, zone));
+        match proof {
+            Some(how) => {
+                self.step(zone, "DS", "insecure", format!("none in {}: {}", signer, how));
+                Verdict::Insecure(format!("{} is not signed: {} has no DS record for it", zone, signer))
+            },
+            None if errors.is_empty() => self.fail(zone, "DS", format!("none in {}, and no NSEC or NSEC3 proves its absence", signer)),
+            None => self.fail(zone, "DS", format!("none in {}, and the proof of its absence does not verify: {}", signer, errors.join("; "))),
+        }
+    }
+
+    /// The zone whose apex is `name` or closest above it, from the SOA of its answer or denial.
+    async fn enclosing_zone(&self, name: &str) -> Result<String, String> {
+        let message = self.fetch(name, wire::TYPE_SOA).await?;
+        let soa = message.answers.iter().chain(&message.authorities).find(|record| record.rtype == wire::TYPE_SOA);
+        Ok(soa.map(|record| canonical(&record.name)).unwrap_or_else(|| parent(name)))
+    }
+
+    async fn fetch(&self, name: &str, qtype: u16) -> Result<wire::Message, String> {
+        let response = self.resolver.query_dnssec(name, qtype).await.map_err(|e| format!("{} {} query failed: {}", name, wire::type_name(qtype), e))?;
+        Ok(response.message)
+    }
+
+    /// Check `records` against one of `sigs` made by `zone` with one of `keys`.
+    fn verify_rrset(&self, records: &[&Record], sigs: &[Rrsig], zone: &str, keys: &[Dnskey]) -> Result<String, String> {
+        if sigs.is_empty() {
+            return Err("no RRSIG".to_string());
+        }
+        let mut errors = Vec::new();
+        for sig in sigs {
+            if canonical(&sig.signer) != zone {
+                errors.push(format!("RRSIG made by {} instead of {}", canonical(&sig.signer), zone));
+                continue;
+            }
+            // Serial number arithmetic (RFC 4034 3.1.5): the fields wrap around in 2106.
+            if (self.now.wrapping_sub(sig.expiration) as i32) > 0 {
+                errors.push(format!("RRSIG expired on {}", self.date(sig.expiration)));
+                continue;
+            }
+            if (sig.inception.wrapping_sub(self.now) as i32) > 0 {
+                errors.push(format!("RRSIG not valid before {}", self.date(sig.inception)));
+                continue;
+            }
+            let candidates: Vec<&Dnskey> = keys.iter().filter(|key| key.tag == sig.key_tag && key.algorithm == sig.algorithm && key.flags & ZONE_KEY != 0).collect();
+            if candidates.is_empty() {
+                errors.push(format!("RRSIG by key {}, which is not among the DNSKEYs", sig.key_tag));
+                continue;
+            }
+            let data = match signed_data(sig, records) {
+                Ok(data) => data,
+                Err(e) => {
+                    errors.push(format!("RRSIG by key {}: {}", sig.key_tag, e));
+                    continue;
+                },
+            };
+            match candidates.iter().map(|key| verify(sig.algorithm, &key.public_key, &data, &sig.signature)).find(|outcome| outcome.is_ok()) {
+                Some(_) => return Ok(format!("RRSIG by key {} ({}) valid until {}", sig.key_tag, algorithm_name(sig.algorithm), self.date(sig.expiration))),
+                None => errors.push(match verify(sig.algorithm, &candidates[0].public_key, &data, &sig.signature) {
+                    Err(e) => format!("RRSIG by key {}: {}", sig.key_tag, e),
+                    Ok(_) => unreachable!("a verifying key ends the search"),
+                }),
+            }
+        }
+        Err(errors.join("; "))
+    }
+
+    /// RRSIG time as a UTC date, resolved around now.
+    fn date(&self, serial: u32) -> String {
+        let seconds = i64::from(self.now) + i64::from(serial.wrapping_sub(self.now) as i32);
+        let absolute = now_seconds() - i64::from(self.now) + seconds;
+        Utc.timestamp_opt(absolute, 0).single().map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string()).unwrap_or_else(|| serial.to_string())
+    }
+
+    fn step(&mut self, name: &str, record: &str, status: &str, detail: String) {
+        self.chain.push(DnssecStep { name: name.to_string(), record: record.to_string(), status: status.to_string(), detail });
+    }
+
+    /// Record a broken link as the chain's last step.
+    fn fail(&mut self, name: &str, record: &str, detail: String) -> Verdict {
+        let reason = format!("{} {}: {}", name, record, detail);
+        self.step(name, record, "bogus", detail);
+        Verdict::Bogus(reason)
+    }
+}
+
+impl Dnskey {
+    fn parse(rdata: &[u8]) -> Option<Self> {
+        // Protocol is always 3 (RFC 4034 2.1.2).
+        if rdata.len() < 5 || rdata[2] != 3 {
+            return None;
+        }
+        Some(Dnskey { flags: u16::from_be_bytes([rdata[0], rdata[1]]), algorithm: rdata[3], public_key: rdata[4..].to_vec(), rdata: rdata.to_vec(), tag: key_tag(rdata) })
+    }
+}
+
+impl Rrsig {
+    fn parse(rdata: &[u8]) -> Option<Self> {
+        let u32_at = |i: usize| Some(u32::from_be_bytes(rdata.get(i..i + 4)?.try_into().ok()?));
+        // The signer name is never compressed, so the RDATA alone decodes it.
+        let (signer, end) = wire::decode_name(rdata, 18)?;
+        Some(Rrsig {
+            type_covered: u16::from_be_bytes([*rdata.first()?, *rdata.get(1)?]),
+            algorithm: rdata[2],
+            labels: rdata[3],
+            original_ttl: u32_at(4)?,
+            expiration: u32_at(8)?,
+            inception: u32_at(12)?,
+            key_tag: u16::from_be_bytes([rdata[16], rdata[17]]),
+            signer,
+            signature: rdata[end..].to_vec(),
+            header: rdata[..18].to_vec(),
+        })
+    }
+}
+
+impl Ds {
+    fn parse(rdata: &[u8]) -> Option<Self> {
+        Some(Ds { key_tag: u16::from_be_bytes([*rdata.first()?, *rdata.get(1)?]), algorithm: *rdata.get(2)?, digest_type: *rdata.get(3)?, digest: rdata.get(4..)?.to_vec() })
+    }
+
+    fn matches(&self, zone: &str, key: &Dnskey) -> bool {
+        self.key_tag == key.tag && self.algorithm == key.algorithm && ds_digest(zone, key, self.digest_type).is_some_and(|digest| digest == self.digest)
+    }
+}
+
+impl Nsec {
+    fn parse(owner: &str, rdata: &[u8]) -> Option<Self> {
+        let (next, end) = wire::decode_name(rdata, 0)?;
+        Some(Nsec { owner: owner.to_string(), next: canonical(&next), types: rdata[end..].to_vec() })
+    }
+
+    /// `name` falls strictly inside the span; the last NSEC of the zone wraps around to the apex.
+    fn covers(&self, name: &str) -> bool {
+        let (after, before) = (canonical_order(&self.owner, name).is_lt(), canonical_order(name, &self.next).is_lt());
+        match canonical_order(&self.owner, &self.next).is_lt() {
+            true => after && before,
+            false => after || before,
+        }
+    }
+}
+
+impl Nsec3 {
+    /// Only SHA-1 (the one hash defined) and owners directly below the signing `zone` are usable.
+    fn parse(owner: &str, zone: &str, rdata: &[u8]) -> Option<Self> {
+        let (label, owner_zone) = owner.split_once('.')?;
+        if *rdata.first()? != 1 || owner_zone != zone {
+            return None;
+        }
+        let salt_length = usize::from(*rdata.get(4)?);
+        let hash_length = usize::from(*rdata.get(5 + salt_length)?);
+        Some(Nsec3 {
+            zone: zone.to_string(),
+            flags: *rdata.get(1)?,
+            iterations: u16::from_be_bytes([*rdata.get(2)?, *rdata.get(3)?]),
+            salt: rdata.get(5..5 + salt_length)?.to_vec(),
+            hash: base32hex(label)?,
+            next: rdata.get(6 + salt_length..6 + salt_length + hash_length)?.to_vec(),
+            types: rdata.get(6 + salt_length + hash_length..)?.to_vec(),
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        nsec3_hash(name, &self.salt, self.iterations).is_some_and(|hash| hash == self.hash)
+    }
+
+    /// The hash of `name` falls strictly inside the span; the last NSEC3 of the zone wraps around to the first.
+    fn covers(&self, name: &str) -> bool {
+        let Some(hash) = nsec3_hash(name, &self.salt, self.iterations) else {
+            return false;
+        };
+        let (after, before) = (self.hash < hash, hash < self.next);
+        match self.hash < self.next {
+            true => after && before,
+            false => after || before,
+        }
+    }
+}
+
+/// Records of `owner` and `rtype` in `section`.
+fn rrset<'a>(section: &'a [Record], owner: &str, rtype: u16) -> Vec<&'a Record> {
+    section.iter().filter(|record| record.rtype == rtype && canonical(&record.name) == owner).collect()
+}
+
+/// RRSIGs of `owner` covering `rtype` in `section`.
+fn signatures(section: &[Record], owner: &str, rtype: u16) -> Vec<Rrsig> {
+    rrset(section, owner, wire::TYPE_RRSIG).into_iter().filter_map(|record| Rrsig::parse(&record.raw)).filter(|sig| sig.type_covered == rtype).collect()
+}
+
+/// What an RRSIG signs (RFC 4034 3.1.8.1): its own fields, then the RRset in canonical form and order.
+fn signed_data(sig: &Rrsig, records: &[&Record]) -> Result<Vec<u8>, String> {
+    let mut data = sig.header.clone();
+    wire::encode_name(&canonical(&sig.signer), &mut data)?;
+    let Some(first) = records.first() else {
+        return Ok(data);
+    };
+    // A wildcard expansion is signed under the wildcard's name.
+    let owner = canonical(&first.name);
+    let labels: Vec<&str> = if owner == "." { Vec::new() } else { owner.split('.').collect() };
+    let owner = match usize::from(sig.labels) {
+        count if count < labels.len() => format!("*.{}", labels[labels.len() - count..].join(".")),
+        _ => owner,
+    };
+    let mut owner_wire = Vec::new();
+    wire::encode_name(&owner, &mut owner_wire)?;
+
+    let mut rdatas: Vec<Vec<u8>> = records.iter().map(|record| canonical_rdata(record)).collect::<Result<_, _>>()?;
+    rdatas.sort();
+    rdatas.dedup();
+    for rdata in rdatas {
+        data.extend_from_slice(&owner_wire);
+        data.extend_from_slice(&first.rtype.to_be_bytes());
+        data.extend_from_slice(&wire::CLASS_IN.to_be_bytes());
+        data.extend_from_slice(&sig.original_ttl.to_be_bytes());
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(&rdata);
+    }
+    Ok(data)
+}
+
+/// RDATA with its domain names uncompressed and lowercased (RFC 4034 6.2).
+fn canonical_rdata(record: &Record) -> Result<Vec<u8>, String> {
+    let mut rdata = Vec::new();
+    let name = |name: &str, rdata: &mut Vec<u8>| wire::encode_name(&name.to_ascii_lowercase(), rdata);
+    match &record.data {
+        RData::Ns(target) | RData::Cname(target) | RData::Ptr(target) => name(target, &mut rdata)?,
+        RData::Mx { preference, exchange } => {
+            rdata.extend_from_slice(&preference.to_be_bytes());
+            name(exchange, &mut rdata)?;
+        },
+        RData::Soa { mname, rname, serial, refresh, retry, expire, minimum } => {
+            name(mname, &mut rdata)?;
+            name(rname, &mut rdata)?;
+            for value in [serial, refresh, retry, expire, minimum] {
+                rdata.extend_from_slice(&value.to_be_bytes());
+            }
+        },
+        RData::Srv { priority, weight, port, target } => {
+            for value in [priority, weight, port] {
+                rdata.extend_from_slice(&value.to_be_bytes());
+            }
+            name(target, &mut rdata)?;
+        },
+        _ => return Ok(record.raw.clone()),
+    }
+    Ok(rdata)
+}
+
+/// Key tag of a DNSKEY (RFC 4034 appendix B).
+fn key_tag(rdata: &[u8]) -> u16 {
+    let mut sum: u32 = rdata.iter().enumerate().map(|(i, byte)| if i & 1 == 0 { u32::from(*byte) << 8 } else { u32::from(*byte) }).sum();
+    sum += (sum >> 16) & 0xffff;
+    (sum & 0xffff) as u16
+}
+
+/// Digest of a DS record for `key` of `zone` with digest type 1 (SHA-1), 2 (SHA-256) or 4 (SHA-384).
+fn ds_digest(zone: &str, key: &Dnskey, digest_type: u8) -> Option<Vec<u8>> {
+    let algorithm = match digest_type {
+        1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        2 => &digest::SHA256,
+        4 => &digest::SHA384,
+        _ => return None,
+    };
+    let mut data = Vec::new();
+    wire::encode_name(zone, &mut data).ok()?;
+    data.extend_from_slice(&key.rdata);
+    Some(digest::digest(algorithm, &data).as_ref().to_vec())
+}
+
+/// Verify `sig` over `data` with a DNSKEY public key of `algorithm`.
+fn verify(algorithm: u8, key: &[u8], data: &[u8], sig: &[u8]) -> Result<(), String> {
+    let bad = |_| "signature does not verify".to_string();
+    match algorithm {
+        5 | 7 | 8 | 10 => {
+            let params = match algorithm {
+                8 => &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                10 => &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+                _ => &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+            };
+            // RFC 3110: exponent length (one byte, or zero then two), exponent, modulus.
+            let (length, start) = match key.first() {
+                Some(0) => (usize::from(u16::from_be_bytes([*key.get(1).ok_or("truncated RSA key")?, *key.get(2).ok_or("truncated RSA key")?])), 3),
+                Some(length) => (usize::from(*length), 1),
+                None => return Err("empty RSA key".to_string()),
+            };
+            let exponent = key.get(start..start + length).ok_or("truncated RSA key")?;
+            let modulus = key.get(start + length..).ok_or("truncated RSA key")?;
+            signature::RsaPublicKeyComponents { n: modulus, e: exponent }.verify(params, data, sig).map_err(bad)
+        },
+        13 | 14 => {
+            let params = if algorithm == 13 { &signature::ECDSA_P256_SHA256_FIXED } else { &signature::ECDSA_P384_SHA384_FIXED };
+            // The DNSKEY holds the bare point; ring wants it uncompressed-tagged.
+            let mut point = vec![0x04];
+            point.extend_from_slice(key);
+            signature::UnparsedPublicKey::new(params, point).verify(data, sig).map_err(bad)
+        },
+        15 => signature::UnparsedPublicKey::new(&signature::ED25519, key).verify(data, sig).map_err(bad),
+        other => Err(format!("algorithm {} is not supported", algorithm_name(other))),
+    }
+}
+
+fn algorithm_name(algorithm: u8) -> String {
+    match algorithm {
+        5 => "RSASHA1".to_string(),
+        7 => "RSASHA1-NSEC3-SHA1".to_string(),
+        8 => "RSASHA256".to_string(),
+        10 => "RSASHA512".to_string(),
+        13 => "ECDSAP256SHA256".to_string(),
+        14 => "ECDSAP384SHA384".to_string(),
+        15 => "ED25519".to_string(),
+        16 => "ED448".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// How the NSECs of a denial prove `zone` has no DS (RFC 4035 5.4, RFC 6840 4.4):
+/// the delegation's own NSEC lists no DS, or one covers the name, which then does
+/// not exist, and another covers the wildcard that could have stood in for it.
+fn nsec_denies_ds(nsecs: &[Nsec], zone: &str) -> Option<String> {
+    if let Some(nsec) = nsecs.iter().find(|nsec| nsec.owner == zone) {
+        // With SOA it is the child's apex NSEC, which says nothing of the parent's side.
+        return [wire::TYPE_DS, wire::TYPE_CNAME, wire::TYPE_SOA]
+            .iter()
+            .all(|rtype| !has_type(&nsec.types, *rtype))
+            .then(|| format!("the NSEC of {} lists no DS", zone));
+    }
+    let cover = nsecs.iter().find(|nsec| nsec.covers(zone))?;
+    // A delegation between them means the parent's NSEC does not speak for names below it.
+    if is_within(zone, &cover.owner) && has_type(&cover.types, wire::TYPE_NS) && !has_type(&cover.types, wire::TYPE_SOA) {
+        return None;
+    }
+    let encloser = [&cover.owner, &cover.next].into_iter().map(|name| common_ancestor(zone, name)).max_by_key(|name| label_count(name))?;
+    let wildcard = wildcard(&encloser);
+    let no_wildcard = match nsecs.iter().find(|nsec| nsec.owner == wildcard) {
+        Some(nsec) => !has_type(&nsec.types, wire::TYPE_DS) && !has_type(&nsec.types, wire::TYPE_CNAME),
+        None => nsecs.iter().any(|nsec| nsec.covers(&wildcard)),
+    };
+    no_wildcard.then(|| format!("{} does not exist: the NSEC of {} covers it, and no wildcard of {} stands in", zone, cover.owner, encloser))
+}
+
+/// How the NSEC3s of a denial prove `zone` has no DS (RFC 5155 8.6): the hashed
+/// name matches one without DS, or a closest encloser is proven and the next
+/// closer name is covered, by an opt-out span or, with the wildcard covered too,
+/// as a name that does not exist.
+fn nsec3_denies_ds(nsec3s: &[Nsec3], zone: &str) -> Option<String> {
+    if let Some(nsec3) = nsec3s.iter().find(|nsec3| nsec3.matches(zone)) {
+        return (!has_type(&nsec3.types, wire::TYPE_DS) && !has_type(&nsec3.types, wire::TYPE_CNAME)).then(|| format!("the NSEC3 of {} lists no DS", zone));
+    }
+    let (encloser, next_closer) = closest_encloser(nsec3s, zone)?;
+    let cover = nsec3s.iter().find(|nsec3| nsec3.covers(&next_closer))?;
+    if cover.flags & OPT_OUT != 0 {
+        return Some(format!("{} is the closest encloser, and an opt-out NSEC3 span covers {}", encloser, next_closer));
+    }
+    let wildcard = wildcard(&encloser);
+    let no_wildcard = match nsec3s.iter().find(|nsec3| nsec3.matches(&wildcard)) {
+        Some(nsec3) => !has_type(&nsec3.types, wire::TYPE_DS) && !has_type(&nsec3.types, wire::TYPE_CNAME),
+        None => nsec3s.iter().any(|nsec3| nsec3.covers(&wildcard)),
+    };
+    no_wildcard.then(|| format!("{} does not exist: {} is the closest encloser, and NSEC3s cover {} and {}", zone, encloser, next_closer, wildcard))
+}
+
+/// The closest encloser proof (RFC 5155 8.3): the nearest ancestor of `name` with
+/// a matching NSEC3, and the next closer name one label below it towards `name`.
+fn closest_encloser(nsec3s: &[Nsec3], name: &str) -> Option<(String, String)> {
+    let zone = &nsec3s.first()?.zone;
+    let mut next_closer = name.to_string();
+    while next_closer != *zone && is_within(&next_closer, zone) {
+        let candidate = parent(&next_closer);
+        if let Some(nsec3) = nsec3s.iter().find(|nsec3| nsec3.matches(&candidate)) {
+            // Below a DNAME or a delegation the zone is not authoritative, so it proves nothing.
+            let delegation = has_type(&nsec3.types, wire::TYPE_NS) && !has_type(&nsec3.types, wire::TYPE_SOA);
+            return (!delegation && !has_type(&nsec3.types, wire::TYPE_DNAME)).then_some((candidate, next_closer));
+        }
+        next_closer = candidate;
+    }
+    None
+}
+
+/// Iterated, salted SHA-1 of the canonical name (RFC 5155 5).
+fn nsec3_hash(name: &str, salt: &[u8], iterations: u16) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    wire::encode_name(name, &mut data).ok()?;
+    let mut hash = data;
+    for _ in 0..=iterations {
+        hash.extend_from_slice(salt);
+        hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &hash).as_ref().to_vec();
+    }
+    Some(hash)
+}
+
+/// Whether an NSEC/NSEC3 type bitmap (RFC 4034 4.1.2) lists `rtype`.
+fn has_type(mut bitmaps: &[u8], rtype: u16) -> bool {
+    let (window, bit) = ((rtype >> 8) as u8, usize::from(rtype & 0xff));
+    while let [block, length, rest @ ..] = bitmaps {
+        let length = usize::from(*length);
+        let Some(bitmap) = rest.get(..length) else {
+            return false;
+        };
+        if *block == window {
+            return bitmap.get(bit / 8).is_some_and(|byte| byte & (0x80 >> (bit % 8)) != 0);
+        }
+        bitmaps = &rest[length..];
+    }
+    false
+}
+
+/// Decode base32 with the extended hex alphabet (RFC 4648 7), as NSEC3 owner labels are written.
+fn base32hex(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars() {
+        buffer = buffer << 5 | c.to_digit(32)?;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+/// Canonical DNS name order (RFC 4034 6.1): label by label from the root, each
+/// compared as lowercase bytes.
+fn canonical_order(a: &str, b: &str) -> Ordering {
+    let labels = |name: &str| -> Vec<Vec<u8>> {
+        match name {
+            "." => Vec::new(),
+            name => name.rsplit('.').map(|label| label.to_ascii_lowercase().into_bytes()).collect(),
+        }
+    };
+    labels(a).cmp(&labels(b))
+}
+
+/// The longest name both `a` and `b` are at or below.
+fn common_ancestor(a: &str, b: &str) -> String {
+    let labels = |name: &str| -> Vec<String> {
+        match name {
+            "." => Vec::new(),
+            name => name.rsplit('.').map(str::to_string).collect(),
+        }
+    };
+    let shared: Vec<String> = labels(a).into_iter().zip(labels(b)).take_while(|(a, b)| a == b).map(|(a, _)| a).collect();
+    match shared.is_empty() {
+        true => ".".to_string(),
+        false => shared.into_iter().rev().collect::<Vec<_>>().join("."),
+    }
+}
+
+fn label_count(name: &str) -> usize {
+    if name == "." { 0 } else { name.split('.').count() }
+}
+
+/// The wildcard directly below `name`.
+fn wildcard(name: &str) -> String {
+    match name {
+        "." => "*".to_string(),
+        name => format!("*.{}", name),
+    }
+}
+
+/// Lowercase name without its trailing dot; "." for the root.
+fn canonical(name: &str) -> String {
+    match name.trim_end_matches('.').to_ascii_lowercase() {
+        name if name.is_empty() => ".".to_string(),
+        name => name,
+    }
+}
+
+/// The name one label up; the root is its own parent.
+fn parent(name: &str) -> String {
+    name.split_once('.').map(|(_, parent)| parent.to_string()).unwrap_or_else(|| ".".to_string())
+}
+
+/// `name` is `zone` or below it.
+fn is_within(name: &str, zone: &str) -> bool {
+    zone == "." || name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+fn now_seconds() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs() as i64).unwrap_or_default()
+}
+
+fn now() -> u32 {
+    now_seconds() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const DNSKEY: &[u8] = include_bytes!("../../tests/data/dnssec-dnskey.bin");
+    const NSEC: &[u8] = include_bytes!("../../tests/data/dnssec-nsec.bin");
+    const NSEC3: &[u8] = include_bytes!("../../tests/data/dnssec-nsec3.bin");
+    const OPT_OUT_NSEC3: &[u8] = include_bytes!("../../tests/data/dnssec-optout.bin");
+    /// 2026-01-01, inside the fixtures' signature validity (2025 to 2036).
+    const NOW: u32 = 1_767_225_600;
+
+    /// The NSEC or NSEC3 records of `fixture` that match `names`, with their RRSIGs.
+    fn pick(fixture: &[u8], names: &[&str]) -> Vec<Record> {
+        let held = |record: &Record, name: &str| {
+            let owner = canonical(&record.name);
+            owner == name || owner.split('.').next().and_then(base32hex) == nsec3_hash(name, &[], 0)
+        };
+        let message = wire::Message::parse(fixture).unwrap();
+        message.authorities.into_iter().filter(|record| names.iter().any(|name| held(record, name))).collect()
+    }
+
+    /// The verdict on the missing DS of `zone`, with the keys of `signer` already trusted.
+    async fn no_ds(signer: &str, zone: &str, authority: &[Record]) -> (&'static str, String) {
+        let resolver = Resolver::new(Vec::new(), Duration::from_secs(1));
+        let mut validator = Validator { resolver: &resolver, now: NOW, zones: HashMap::new(), chain: Vec::new() };
+        validator.zones.insert(signer.to_string(), Verdict::Secure(vec![Dnskey::parse(DNSKEY).unwrap()]));
+        let detail = |validator: &Validator| validator.chain.last().map(|step| step.detail.clone()).unwrap_or_default();
+        match validator.no_ds(zone, authority).await {
+            Verdict::Insecure(_) => ("insecure", detail(&validator)),
+            Verdict::Bogus(_) => ("bogus", detail(&validator)),
+            Verdict::Secure(_) => ("secure", detail(&validator)),
+            Verdict::Indeterminate(reason) => ("indeterminate", reason),
+        }
+    }
+
+    #[tokio::test]
+    async fn nsec_proves_an_unsigned_delegation() {
+        let (status, detail) = no_ds("nsec.example", "insecure.nsec.example", &pick(NSEC, &["insecure.nsec.example"])).await;
+        assert_eq!(status, "insecure");
+        assert!(detail.ends_with("the NSEC of insecure.nsec.example lists no DS"), "{}", detail);
+        // The NSEC of a signed delegation lists its DS.
+        assert_eq!(no_ds("nsec.example", "secure.nsec.example", &pick(NSEC, &["secure.nsec.example"])).await.0, "bogus");
+    }
+
+    #[tokio::test]
+    async fn nsec_proves_a_missing_name_only_with_its_wildcard() {
+        // a.nsec.example covers gone.nsec.example, the apex NSEC covers *.nsec.example.
+        let (status, detail) = no_ds("nsec.example", "gone.nsec.example", &pick(NSEC, &["a.nsec.example", "nsec.example"])).await;
+        assert_eq!(status, "insecure");
+        assert!(detail.contains("gone.nsec.example does not exist"), "{}", detail);
+        assert_eq!(no_ds("nsec.example", "gone.nsec.example", &pick(NSEC, &["a.nsec.example"])).await.0, "bogus");
+    }
+
+    #[tokio::test]
+    async fn nsec3_proves_an_unsigned_delegation() {
+        let (status, detail) = no_ds("nsec3.example", "insecure.nsec3.example", &pick(NSEC3, &["insecure.nsec3.example"])).await;
+        assert_eq!(status, "insecure");
+        assert!(detail.ends_with("the NSEC3 of insecure.nsec3.example lists no DS"), "{}", detail);
+        assert_eq!(no_ds("nsec3.example", "secure.nsec3.example", &pick(NSEC3, &["secure.nsec3.example"])).await.0, "bogus");
+    }
+
+    #[tokio::test]
+    async fn nsec3_without_opt_out_proves_a_missing_name_with_the_closest_encloser() {
+        // The apex NSEC3 matches the closest encloser and covers the wildcard; a.nsec3.example's covers the next closer name.
+        let (status, detail) = no_ds("nsec3.example", "gone.nsec3.example", &pick(NSEC3, &["nsec3.example", "a.nsec3.example"])).await;
+        assert_eq!(status, "insecure");
+        assert!(detail.contains("nsec3.example is the closest encloser"), "{}", detail);
+        // A covering NSEC3 alone proves nothing.
+        assert_eq!(no_ds("nsec3.example", "gone.nsec3.example", &pick(NSEC3, &["a.nsec3.example"])).await.0, "bogus");
+    }
+
+    #[tokio::test]
+    async fn nsec3_opt_out_needs_the_closest_encloser() {
+        let (status, detail) = no_ds("optout.example", "insecure.optout.example", &pick(OPT_OUT_NSEC3, &["optout.example", "a.optout.example"])).await;
+        assert_eq!(status, "insecure");
+        assert!(detail.ends_with("an opt-out NSEC3 span covers insecure.optout.example"), "{}", detail);
+        assert_eq!(no_ds("optout.example", "insecure.optout.example", &pick(OPT_OUT_NSEC3, &["a.optout.example"])).await.0, "bogus");
+    }
+
+    #[tokio::test]
+    async fn a_denial_with_a_broken_signature_is_bogus() {
+        let mut authority = pick(NSEC, &["insecure.nsec.example"]);
+        let sig = authority.iter_mut().find(|record| record.rtype == wire::TYPE_RRSIG).unwrap();
+        *sig.raw.last_mut().unwrap() ^= 1;
+        let (status, detail) = no_ds("nsec.example", "insecure.nsec.example", &authority).await;
+        assert_eq!(status, "bogus");
+        assert!(detail.contains("signature does not verify"), "{}", detail);
+    }
+}
@@ -0,0 +1,222 @@
+//! Iterative resolution from the root (`--dns-trace`), the path `dig +trace`
+//! shows: each server asked without recursion, and the referral it hands back,
+//! down to the servers authoritative for the name.
+//!
+//! Referrals normally carry the addresses of the next servers (glue); when they
+//! do not, the nameserver names are resolved through the regular resolver.
+
+use super::wire::{self, Message, Query, RData, Record};
+use super::{query_server, IpFamily, Resolver};
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Root server hints (IANA named.root): name, IPv4, IPv6.
+const ROOT_SERVERS: [(&str, &str, &str); 13] = [
+    ("a.root-servers.net", "198.41.0.4", "2001:503:ba3e::2:30"),
+    ("b.root-servers.net", "170.247.170.2", "2801:1b8:10::b"),
+    ("c.root-servers.net", "192.33.4.12", "2001:500:2::c"),
+    ("d.root-servers.net", "199.7.91.13", "2001:500:2d::d"),
+    ("e.root-servers.net", "192.203.230.10", "2001:500:a8::e"),
+    ("f.root-servers.net", "192.5.5.241", "2001:500:2f::f"),
+    ("g.root-servers.net", "192.112.36.4", "2001:500:12::d0d"),
+    ("h.root-servers.net", "198.97.190.53", "2001:500:1::53"),
+    ("i.root-servers.net", "192.36.148.17", "2001:7fe::53"),
+    ("j.root-servers.net", "192.58.128.30", "2001:503:c27::2:30"),
+    ("k.root-servers.net", "193.0.14.129", "2001:7fd::1"),
+    ("l.root-servers.net", "199.7.83.42", "2001:500:9f::42"),
+    ("m.root-servers.net", "202.12.27.33", "2001:dc3::35"),
+];
+/// Referrals followed before giving up, CNAMEs included.
+const MAX_HOPS: usize = 30;
+/// Servers of one zone tried before giving up on it.
+const SERVERS_PER_ZONE: usize = 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnsTrace {
+    pub status: String, // "ok" | "error"
+    /// Each server asked, from the root down.
+    pub hops: Vec<TraceHop>,
+    /// The final answer, in presentation format.
+    pub answers: Vec<String>,
+    pub duration_ms: f64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceHop {
+    /// Zone the server was asked as an authority for ("." for the root).
+    pub zone: String,
+    /// Name queried (differs from the target after a CNAME).
+    pub name: String,
+    pub server: String,
+    pub address: String,
+    pub latency_ms: Option<f64>,
+    pub rcode: Option<String>,
+    /// Zone the server delegated to, and the nameservers it named.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referral: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nameservers: Vec<String>,
+    /// Answer records, when the server had them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub answers: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Follow referrals from the root to the `qtype` records of `name`. Servers are
+/// reached over `family` (IPv4 unless IPv6 only); `resolver` finds glueless nameservers.
+pub async fn trace(name: &str, qtype: u16, family: Option<IpFamily>, resolver: &Resolver, timeout: Duration) -> DnsTrace {
+    let started = Instant::now();
+    let family = family.unwrap_or(IpFamily::V4);
+    let mut result = DnsTrace { status: "error".to_string(), hops: Vec::new(), answers: Vec::new(), duration_ms: 0.0, error: None };
+    let mut name = name.trim_end_matches('.').to_ascii_lowercase();
+    let mut zone = ".".to_string();
+    let mut servers = root_servers(family);
+
+    let outcome = loop {
+        if result.hops.len() >= MAX_HOPS {
+            break Err(format!("Gave up after {} referrals", MAX_HOPS));
+        }
+        let Some((message, mut hop)) = ask(&servers, &zone, &name, qtype, timeout, &mut result.hops).await else {
+            break Err(format!("No server of {} answers", if zone == "." { "the root" } else { &zone }));
+        };
+        let rcode = message.rcode;
+        let answers: Vec<&Record> = message.answers.iter().filter(|record| record.name.trim_end_matches('.').eq_ignore_ascii_case(&name)).collect();
+        hop.answers = answers.iter().map(|record| format!("{} {}", wire::type_name(record.rtype), record.data)).collect();
+
+        if answers.iter().any(|record| record.rtype == qtype) || (rcode != wire::RCODE_NOERROR) {
+            result.hops.push(hop);
+            result.answers = message.answers.iter().map(|record| format!("{} {} {}", record.name.trim_end_matches('.'), wire::type_name(record.rtype), record.data)).collect();
+            break match rcode {
+                wire::RCODE_NOERROR => Ok(()),
+                rcode => Err(format!("{} answers {}", zone_label(&zone), wire::rcode_name(rcode))),
+            };
+        }
+        // A CNAME restarts the walk at the root for its target, as a resolver without the target's zone cached would.
+        if let Some(target) = answers.iter().find_map(|record| match &record.data {
+            RData::Cname(target) => Some(target.trim_end_matches('.').to_ascii_lowercase()),
+            _ => None,
+        }) {
+            result.hops.push(hop);
+            name = target;
+            zone = ".".to_string();
+            servers = root_servers(family);
+            continue;
+        }
+
+        // A referral: NS records for a zone below the current one, in the authority section.
+        let delegated = message.authorities.iter().find(|record| record.rtype == wire::TYPE_NS).map(|record| record.name.trim_end_matches('.').to_ascii_lowercase());
+        let Some(next_zone) = delegated.filter(|next| is_below(next, &zone) && is_within(&name, next)) else {
+            result.hops.push(hop);
+            break Err(format!("{} has no {} records for {} (and no referral)", zone_label(&zone), wire::type_name(qtype), name));
+        };
+        let names: Vec<String> = message
+            .authorities
+            .iter()
+            .filter_map(|record| match &record.data {
+                RData::Ns(target) if record.name.trim_end_matches('.').eq_ignore_ascii_case(&next_zone) => Some(target.trim_end_matches('.').to_ascii_lowercase()),
+                _ => None,
+            })
+            .collect();
+        hop.referral = Some(next_zone.clone());
+        hop.nameservers = names.clone();
+        result.hops.push(hop);
+
+        servers = glue(&message, &names, family);
+        if servers.is_empty() {
+            servers = glueless(&names, family, resolver).await;
+        }
+        if servers.is_empty() {
+            break Err(format!("Cannot find the address of any nameserver of {} ({})", next_zone, names.join(", ")));
+        }
+        zone = next_zone;
+    };
+
+    result.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match outcome {
+        Ok(()) => result.status = "ok".to_string(),
+        Err(e) => result.error = Some(e),
+    }
+    result
+}
+
+/// Ask the first servers of `zone` in turn until one answers; the failed attempts are recorded as hops.
+async fn ask(servers: &[(String, IpAddr)], zone: &str, name: &str, qtype: u16, timeout: Duration, hops: &mut Vec<TraceHop>) -> Option<(Message, TraceHop)> {
+    for (server, ip) in servers.iter().take(SERVERS_PER_ZONE) {
+        let mut hop = TraceHop { zone: zone.to_string(), name: name.to_string(), server: server.clone(), address: ip.to_string(), latency_ms: None, rcode: None, referral: None, nameservers: Vec::new(), answers: Vec::new(), error: None };
+        let query = Query { recursion_desired: false, ..Query::new(name, qtype) };
+        match query_server(SocketAddr::new(*ip, 53), &query, timeout).await {
+            // A refusal or failure from one server is that server's problem, not the zone's.
+            Ok(response) if matches!(response.message.rcode, wire::RCODE_SERVFAIL | wire::RCODE_REFUSED) => {
+                hop.latency_ms = Some(response.latency_ms);
+                hop.error = Some(format!("answered {}", wire::rcode_name(response.message.rcode)));
+                hops.push(hop);
+            },
+            Ok(response) => {
+                hop.latency_ms = Some(response.latency_ms);
+                hop.rcode = Some(wire::rcode_name(response.message.rcode));
+                return Some((response.message, hop));
+            },
+            Err(e) => {
+                hop.error = Some(e.to_string());
+                hops.push(hop);
+            },
+        }
+    }
+    None
+}
+
+fn root_servers(family: IpFamily) -> Vec<(String, IpAddr)> {
+    ROOT_SERVERS
+        .iter()
+        .filter_map(|(name, v4, v6)| {
+            let address = if family == IpFamily::V6 { v6 } else { v4 };
+            Some((name.to_string(), address.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Addresses of `names` in the additional section of a referral.
+fn glue(message: &Message, names: &[String], family: IpFamily) -> Vec<(String, IpAddr)> {
+    message
+        .additionals
+        .iter()
+        .filter_map(|record| {
+            let owner = record.name.trim_end_matches('.').to_ascii_lowercase();
+            let ip = match (&record.data, family) {
+                (RData::A(ip), IpFamily::V4) => IpAddr::V4(*ip),
+                (RData::Aaaa(ip), IpFamily::V6) => IpAddr::V6(*ip),
+                _ => return None,
+            };
+            names.contains(&owner).then_some((owner, ip))
+        })
+        .collect()
+}
+
+/// Addresses of nameservers the referral gave no glue for, through the regular resolver.
+async fn glueless(names: &[String], family: IpFamily, resolver: &Resolver) -> Vec<(String, IpAddr)> {
+    for name in names {
+        if let Ok(lookup) = resolver.lookup_ip(name, Some(family)).await {
+            return lookup.ips.into_iter().map(|ip| (name.clone(), ip)).collect();
+        }
+    }
+    Vec::new()
+}
+
+fn zone_label(zone: &str) -> String {
+    match zone {
+        "." => "The root".to_string(),
+        zone => zone.to_string(),
+    }
+}
+
+/// `zone` is strictly below `parent`.
+fn is_below(zone: &str, parent: &str) -> bool {
+    zone != parent && is_within(zone, parent)
+}
+
+/// `name` is `zone` or below it.
+fn is_within(name: &str, zone: &str) -> bool {
+    zone == "." || name == zone || name.ends_with(&format!(".{}", zone))
+}
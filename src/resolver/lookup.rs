@@ -0,0 +1,163 @@
+//! Address lookups through hickory-resolver: on a classic DNS server
+//! (`--dns-server`), or with the system's resolver configuration.
+//!
+//! The other transports, and queries carrying a client subnet, go through
+//! [`Resolver::query`](super::Resolver::query) instead: hickory's resolver
+//! sends no EDNS Client Subnet option, and DNSSEC, TTL and trace reports need
+//! the header bits and raw records its answers leave out.
+
+use super::records::RecordInfo;
+use super::{wire, AddressLookup, IpFamily, ResolveError, Upstream};
+use hickory_resolver::config::{LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts, ServerOrderingStrategy};
+use hickory_resolver::error::{ResolveError as HickoryError, ResolveErrorKind};
+use hickory_resolver::lookup::Lookup;
+use hickory_resolver::proto::error::ProtoErrorKind;
+use hickory_resolver::proto::op::ResponseCode;
+use hickory_resolver::proto::rr::{RData, Record, RecordType};
+use hickory_resolver::proto::serialize::binary::BinEncodable;
+use hickory_resolver::TokioAsyncResolver;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Every address of `name` on `server`, A and AAAA asked concurrently (only one of
+/// them when `family` is set). IPv4 addresses come first.
+pub(super) async fn lookup_ip(server: SocketAddr, name: &str, family: Option<IpFamily>, timeout: Duration) -> Result<AddressLookup, ResolveError> {
+    // One resolver per server, so the answer is known to come from it.
+    let resolver = TokioAsyncResolver::tokio(config(server), options(timeout));
+    let fqdn = format!("{}.", name.trim_end_matches('.'));
+    let timed = |rtype: RecordType| {
+        let (resolver, fqdn) = (&resolver, &fqdn);
+        async move {
+            let started = Instant::now();
+            let outcome = match tokio::time::timeout(timeout, resolver.lookup(fqdn.as_str(), rtype)).await {
+                Ok(outcome) => outcome.map_err(resolve_error),
+                Err(_) => Err(ResolveError::Timeout),
+            };
+            (outcome, started.elapsed().as_secs_f64() * 1000.0)
+        }
+    };
+    let wanted = |f: IpFamily| family.is_none_or(|only| only == f);
+    let (v4, v6) = tokio::join!(
+        async {
+            match wanted(IpFamily::V4) {
+                true => Some(timed(RecordType::A).await),
+                false => None,
+            }
+        },
+        async {
+            match wanted(IpFamily::V6) {
+                true => Some(timed(RecordType::AAAA).await),
+                false => None,
+            }
+        }
+    );
+
+    let mut lookups: Vec<(Lookup, f64)> = Vec::new();
+    let mut first_error = None;
+    for (outcome, latency_ms) in [v4, v6].into_iter().flatten() {
+        match outcome {
+            Ok(lookup) => lookups.push((lookup, latency_ms)),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            },
+        }
+    }
+    let records: Vec<&Record> = lookups.iter().flat_map(|(lookup, _)| lookup.records()).collect();
+    let ips: Vec<IpAddr> = records
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::A(a)) => Some(IpAddr::V4(a.0)),
+            Some(RData::AAAA(aaaa)) => Some(IpAddr::V6(aaaa.0)),
+            _ => None,
+        })
+        .collect();
+    if ips.is_empty() {
+        return Err(first_error.unwrap_or(ResolveError::NoRecords));
+    }
+
+    Ok(AddressLookup {
+        ips,
+        server: Upstream::Plain(server),
+        transport: "udp",
+        // Both queries run concurrently: the lookup took as long as the slower one.
+        latency_ms: lookups.iter().map(|(_, latency_ms)| *latency_ms).fold(0.0, f64::max),
+        handshake_ms: None,
+        ttl: records.iter().filter(|record| matches!(record.record_type(), RecordType::A | RecordType::AAAA)).map(|record| record.ttl()).min(),
+        client_subnet_scope: None,
+        answers: records.iter().map(|record| record_info(record)).collect(),
+    })
+}
+
+/// Every address of `name` through the system's nameservers, search domains and hosts
+/// file (`/etc/resolv.conf` and `/etc/hosts`, or the registry on Windows), IPv4 first.
+/// IP literals, bracketed or not, are returned as they are.
+pub async fn lookup_system(name: &str, timeout: Duration) -> Result<Vec<IpAddr>, ResolveError> {
+    let name = name.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = name.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    let (config, mut options) = hickory_resolver::system_conf::read_system_conf().map_err(resolve_error)?;
+    options.timeout = options.timeout.min(timeout);
+    // Like getaddrinfo: both families, whichever the caller goes on to use.
+    options.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+    // A fresh resolver per lookup, so that no answer comes from the cache of an earlier run.
+    let resolver = TokioAsyncResolver::tokio(config, options);
+    let lookup = match tokio::time::timeout(timeout, resolver.lookup_ip(name)).await {
+        Ok(lookup) => lookup.map_err(resolve_error)?,
+        Err(_) => return Err(ResolveError::Timeout),
+    };
+    let (mut ips, v6): (Vec<IpAddr>, Vec<IpAddr>) = lookup.iter().partition(IpAddr::is_ipv4);
+    ips.extend(v6);
+    match ips.is_empty() {
+        true => Err(ResolveError::NoRecords),
+        false => Ok(ips),
+    }
+}
+
+/// The server over UDP, and over TCP for the answers too large for a datagram.
+fn config(server: SocketAddr) -> ResolverConfig {
+    let mut config = ResolverConfig::new();
+    config.add_name_server(NameServerConfig::new(server, Protocol::Udp));
+    config.add_name_server(NameServerConfig::new(server, Protocol::Tcp));
+    config
+}
+
+fn options(timeout: Duration) -> ResolverOpts {
+    let mut options = ResolverOpts::default();
+    options.timeout = timeout;
+    options.attempts = 1;
+    options.edns0 = true;
+    options.use_hosts_file = false;
+    options.num_concurrent_reqs = 1;
+    options.server_ordering_strategy = ServerOrderingStrategy::UserProvidedOrder;
+    // Keep the CNAMEs on the way, for the answer section shown with -v.
+    options.preserve_intermediates = true;
+    options
+}
+
+fn resolve_error(error: HickoryError) -> ResolveError {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound { response_code: ResponseCode::NXDomain, .. } => ResolveError::NxDomain,
+        ResolveErrorKind::NoRecordsFound { response_code: ResponseCode::NoError, .. } => ResolveError::NoRecords,
+        ResolveErrorKind::NoRecordsFound { response_code, .. } => ResolveError::ServerError(u16::from(*response_code) as u8),
+        ResolveErrorKind::Timeout => ResolveError::Timeout,
+        ResolveErrorKind::Io(e) => ResolveError::Io(io::Error::new(e.kind(), e.to_string())),
+        ResolveErrorKind::Proto(e) => match e.kind() {
+            ProtoErrorKind::Timeout => ResolveError::Timeout,
+            ProtoErrorKind::Io(e) => ResolveError::Io(io::Error::new(e.kind(), e.to_string())),
+            _ => ResolveError::Malformed,
+        },
+        _ => ResolveError::Io(io::Error::other(error.to_string())),
+    }
+}
+
+fn record_info(record: &Record) -> RecordInfo {
+    RecordInfo {
+        name: record.name().to_string().trim_end_matches('.').to_string(),
+        rtype: wire::type_name(u16::from(record.record_type())),
+        ttl: record.ttl(),
+        data: record.data().map(ToString::to_string).unwrap_or_default(),
+        raw: record.data().and_then(|data| data.to_bytes().ok()).map(|bytes| wire::hex(&bytes)).unwrap_or_default(),
+    }
+}
@@ -0,0 +1,573 @@
+//! DNS client used when a specific DNS server is requested instead of the
+//! operating system's resolver. Address lookups on a classic server go through
+//! hickory-resolver ([`lookup`]). Everything else is asked with the messages of
+//! [`wire`], whose header bits and raw records DNSSEC validation, the iterative
+//! trace, TTL reports and EDNS Client Subnet need. Besides classic UDP/TCP it
+//! speaks DNS-over-TLS (RFC 7858), DNS-over-HTTPS (RFC 8484) and
+//! DNS-over-QUIC (RFC 9250).
+
+pub mod bench;
+pub mod dnssec;
+pub mod iterative;
+mod lookup;
+pub mod propagation;
+pub mod records;
+pub mod ttl;
+pub mod wire;
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio_rustls::TlsConnector;
+use records::RecordInfo;
+pub use lookup::lookup_system;
+use wire::{ClientSubnet, Message, Query, RData};
+
+/// Why a lookup produced no usable answer.
+#[derive(Debug)]
+pub enum ResolveError {
+    /// No server answered within the timeout.
+    Timeout,
+    /// The name does not exist (authoritative NXDOMAIN).
+    NxDomain,
+    /// The name exists but has no records of the requested type.
+    NoRecords,
+    /// The server answered with an error code (SERVFAIL, REFUSED...).
+    ServerError(u8),
+    /// The reply could not be decoded.
+    Malformed,
+    /// The DNS-over-HTTPS request failed or was answered with a non-200 status.
+    Http(String),
+    /// The DNS-over-QUIC connection failed: handshake, protocol error or close by the server.
+    Quic(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::Timeout => write!(f, "DNS query timed out"),
+            ResolveError::NxDomain => write!(f, "NXDOMAIN: name does not exist"),
+            ResolveError::NoRecords => write!(f, "No IP found"),
+            ResolveError::ServerError(rcode) => write!(f, "DNS server returned {}", wire::rcode_name(*rcode)),
+            ResolveError::Malformed => write!(f, "Malformed DNS response"),
+            ResolveError::Http(e) => write!(f, "DoH request failed: {}", e),
+            ResolveError::Quic(e) => write!(f, "DoQ connection failed: {}", e),
+            ResolveError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl ResolveError {
+    /// Stable name of the failure: "timeout" | "nxdomain" | "no_records" | "server_error" | "malformed" | "http" | "quic" | "io".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ResolveError::Timeout => "timeout",
+            ResolveError::NxDomain => "nxdomain",
+            ResolveError::NoRecords => "no_records",
+            ResolveError::ServerError(_) => "server_error",
+            ResolveError::Malformed => "malformed",
+            ResolveError::Http(_) => "http",
+            ResolveError::Quic(_) => "quic",
+            ResolveError::Io(_) => "io",
+        }
+    }
+}
+
+impl From<io::Error> for ResolveError {
+    fn from(e: io::Error) -> Self {
+        ResolveError::Io(e)
+    }
+}
+
+/// Address family restriction for lookups and connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    pub fn of(ip: IpAddr) -> Self {
+        if ip.is_ipv4() {
+            IpFamily::V4
+        } else {
+            IpFamily::V6
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IpFamily::V4 => "ipv4",
+            IpFamily::V6 => "ipv6",
+        }
+    }
+}
+
+/// A DNS server and the transport used to reach it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upstream {
+    /// Classic DNS over UDP, retried over TCP when the reply is truncated.
+    Plain(SocketAddr),
+    /// DNS-over-TLS; `name` is checked against the server's certificate.
+    Tls { addr: SocketAddr, name: String },
+    /// DNS-over-HTTPS: wire-format messages POSTed to this URL.
+    Https(String),
+    /// DNS-over-QUIC; `host` is resolved at query time and checked against the server's certificate.
+    Quic { host: String, port: u16 },
+}
+
+impl Upstream {
+    /// Transport name as reported in results.
+    pub fn transport(&self) -> &'static str {
+        match self {
+            Upstream::Plain(_) => "udp",
+            Upstream::Tls { .. } => "tls",
+            Upstream::Https(_) => "https",
+            Upstream::Quic { .. } => "quic",
+        }
+    }
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upstream::Plain(addr) | Upstream::Tls { addr, .. } => write!(f, "{}", addr),
+            Upstream::Https(url) => write!(f, "{}", url),
+            Upstream::Quic { host, port } if host.contains(':') => write!(f, "quic://[{}]:{}", host, port),
+            Upstream::Quic { host, port } => write!(f, "quic://{}:{}", host, port),
+        }
+    }
+}
+
+/// A reply together with where it came from and how long it took.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub server: Upstream,
+    /// How the reply actually travelled: "udp" | "tcp" | "tls" | "https" | "quic".
+    pub transport: &'static str,
+    pub message: Message,
+    pub latency_ms: f64,
+    /// Part of `latency_ms` spent setting up the connection: the TLS handshake for DoT,
+    /// the QUIC handshake for DoQ; `None` for the other transports.
+    pub handshake_ms: Option<f64>,
+}
+
+/// Every address of a name, and the answer they came in.
+#[derive(Debug, Clone)]
+pub struct AddressLookup {
+    /// IPv4 addresses first.
+    pub ips: Vec<IpAddr>,
+    pub server: Upstream,
+    /// "udp" | "tcp" | "tls" | "https" | "quic"
+    pub transport: &'static str,
+    /// The slower of the A and AAAA lookups, which run concurrently.
+    pub latency_ms: f64,
+    /// TLS or QUIC handshake part of `latency_ms` (DoT, DoQ).
+    pub handshake_ms: Option<f64>,
+    /// Lowest TTL of the address records.
+    pub ttl: Option<u32>,
+    /// Scope prefix of the answer to a query with a client subnet.
+    pub client_subnet_scope: Option<u8>,
+    /// Answer sections of the A and AAAA responses.
+    pub answers: Vec<RecordInfo>,
+}
+
+/// Queries a list of servers in order, moving on when one fails.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    pub servers: Vec<Upstream>,
+    pub timeout: Duration,
+    /// Sent with every query (`--ecs`).
+    pub client_subnet: Option<ClientSubnet>,
+    /// Client of DoH queries; one without proxy or TLS settings when `None`.
+    pub http_client: Option<reqwest::Client>,
+}
+
+impl Resolver {
+    pub fn new(servers: Vec<Upstream>, timeout: Duration) -> Self {
+        Resolver { servers, timeout, client_subnet: None, http_client: None }
+    }
+
+    /// Ask on behalf of clients in `subnet` (EDNS Client Subnet).
+    pub fn with_client_subnet(mut self, subnet: Option<ClientSubnet>) -> Self {
+        self.client_subnet = subnet;
+        self
+    }
+
+    /// Send DoH queries with `client`, e.g. to go through the probe's proxy.
+    pub fn with_http_client(mut self, client: Option<reqwest::Client>) -> Self {
+        self.http_client = client;
+        self
+    }
+
+    /// Send one query, falling back to the next server on transport errors,
+    /// SERVFAIL or REFUSED. NXDOMAIN and NOERROR answers are final.
+    pub async fn query(&self, name: &str, qtype: u16) -> Result<Response, ResolveError> {
+        self.send(name, qtype, false).await
+    }
+
+    /// [`query`](Self::query) asking for the RRSIG, NSEC and NSEC3 records that
+    /// DNSSEC validation needs, whether or not the server validates them itself.
+    pub async fn query_dnssec(&self, name: &str, qtype: u16) -> Result<Response, ResolveError> {
+        self.send(name, qtype, true).await
+    }
+
+    async fn send(&self, name: &str, qtype: u16, dnssec: bool) -> Result<Response, ResolveError> {
+        let mut last_error = ResolveError::Timeout;
+        for server in &self.servers {
+            match self.send_to(server, name, qtype, dnssec).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    tracing::debug!(server = %server, error = %e, "server failed, trying the next one");
+                    last_error = e;
+                },
+            }
+        }
+        Err(last_error)
+    }
+
+    /// One query to one server; SERVFAIL and REFUSED answers are errors.
+    async fn send_to(&self, server: &Upstream, name: &str, qtype: u16, dnssec: bool) -> Result<Response, ResolveError> {
+        tracing::debug!(server = %server, name, qtype = wire::type_name(qtype), dnssec, "querying");
+        let query = Query { dnssec, client_subnet: self.client_subnet, ..Query::new(name, qtype) };
+        let response = query_upstream(server, &query, self.timeout, self.http_client.as_ref()).await?;
+        if matches!(response.message.rcode, wire::RCODE_SERVFAIL | wire::RCODE_REFUSED) {
+            return Err(ResolveError::ServerError(response.message.rcode));
+        }
+        tracing::trace!(server = %server, transport = response.transport, rcode = response.message.rcode, answers = response.message.answers.len(), latency_ms = response.latency_ms, "answer");
+        Ok(response)
+    }
+
+    /// Resolve `name` to all of its addresses, querying A and AAAA concurrently
+    /// (only one of them when `family` is set). IPv4 addresses come first.
+    /// The next server is asked when neither query got an answer; NXDOMAIN and
+    /// an empty answer are final.
+    pub async fn lookup_ip(&self, name: &str, family: Option<IpFamily>) -> Result<AddressLookup, ResolveError> {
+        let mut last_error = ResolveError::Timeout;
+        for server in &self.servers {
+            // Both lookups are boxed: their futures are large, and every probe future would carry them.
+            let outcome = match server {
+                Upstream::Plain(addr) if self.client_subnet.is_none() => {
+                    tracing::debug!(server = %server, name, "looking up addresses");
+                    Box::pin(lookup::lookup_ip(*addr, name, family, self.timeout)).await
+                },
+                _ => Box::pin(self.lookup_ip_on(server, name, family)).await,
+            };
+            match outcome {
+                Ok(found) => return Ok(found),
+                Err(e @ (ResolveError::NxDomain | ResolveError::NoRecords)) => return Err(e),
+                Err(e) => {
+                    tracing::debug!(server = %server, error = %e, "server failed, trying the next one");
+                    last_error = e;
+                },
+            }
+        }
+        Err(last_error)
+    }
+
+    /// [`lookup_ip`](Self::lookup_ip) on one server, with [`wire`] queries.
+    async fn lookup_ip_on(&self, server: &Upstream, name: &str, family: Option<IpFamily>) -> Result<AddressLookup, ResolveError> {
+        let wanted = |f: IpFamily| family.is_none_or(|only| only == f);
+        let (v4, v6) = tokio::join!(
+            async {
+                match wanted(IpFamily::V4) {
+                    true => Some(self.send_to(server, name, wire::TYPE_A, false).await),
+                    false => None,
+                }
+            },
+            async {
+                match wanted(IpFamily::V6) {
+                    true => Some(self.send_to(server, name, wire::TYPE_AAAA, false).await),
+                    false => None,
+                }
+            }
+        );
+
+        let mut ips = Vec::new();
+        let mut responses = Vec::new();
+        let mut first_error = None;
+        for outcome in [v4, v6].into_iter().flatten() {
+            match outcome.and_then(|response| addresses(&response.message).map(|found| (found, response))) {
+                Ok((found, response)) => {
+                    ips.extend(found);
+                    responses.push(response);
+                },
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                },
+            }
+        }
+        // Both queries run concurrently: the lookup took as long as the slower one.
+        let Some(slowest) = responses.iter().max_by(|a, b| a.latency_ms.total_cmp(&b.latency_ms)).filter(|_| !ips.is_empty()) else {
+            return Err(first_error.unwrap_or(ResolveError::NoRecords));
+        };
+        let answers = || responses.iter().flat_map(|response| &response.message.answers);
+        Ok(AddressLookup {
+            server: slowest.server.clone(),
+            transport: slowest.transport,
+            latency_ms: slowest.latency_ms,
+            handshake_ms: slowest.handshake_ms,
+            ttl: answers().filter(|record| matches!(record.rtype, wire::TYPE_A | wire::TYPE_AAAA)).map(|record| record.ttl).min(),
+            client_subnet_scope: self.client_subnet.and(responses.iter().find_map(|response| response.message.client_subnet_scope())),
+            answers: answers().map(RecordInfo::from).collect(),
+            ips,
+        })
+    }
+}
+
+/// The `in-addr.arpa` / `ip6.arpa` name queried for PTR records of `ip`.
+pub fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        },
+        IpAddr::V6(v6) => {
+            let nibbles: Vec<String> = v6.octets().iter().rev().flat_map(|byte| [byte & 0x0f, byte >> 4]).map(|n| format!("{:x}", n)).collect();
+            format!("{}.ip6.arpa", nibbles.join("."))
+        },
+    }
+}
+
+/// Extract A/AAAA data from an answer section, mapping error codes.
+fn addresses(message: &Message) -> Result<Vec<IpAddr>, ResolveError> {
+    match message.rcode {
+        wire::RCODE_NOERROR => {},
+        wire::RCODE_NXDOMAIN => return Err(ResolveError::NxDomain),
+        rcode => return Err(ResolveError::ServerError(rcode)),
+    }
+    Ok(message
+        .answers
+        .iter()
+        .filter_map(|r| match r.data {
+            RData::A(ip) => Some(IpAddr::V4(ip)),
+            RData::Aaaa(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// The query on the wire; a name that cannot be encoded is rejected before anything is sent.
+fn encode(query: &Query) -> Result<Vec<u8>, ResolveError> {
+    query.encode().map_err(|e| ResolveError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))
+}
+
+/// Query a single upstream with whichever transport it uses; DoH goes through `http_client` if set.
+pub async fn query_upstream(server: &Upstream, query: &Query, timeout: Duration, http_client: Option<&reqwest::Client>) -> Result<Response, ResolveError> {
+    let (addr, name) = match server {
+        Upstream::Plain(addr) => return query_server(*addr, query, timeout).await,
+        Upstream::Tls { addr, name } => (addr, name),
+        Upstream::Https(url) => {
+            let started = Instant::now();
+            let message = exchange_https(url, &encode(query)?, timeout, http_client).await?;
+            return Ok(Response { server: server.clone(), transport: server.transport(), message, latency_ms: started.elapsed().as_secs_f64() * 1000.0, handshake_ms: None });
+        },
+        Upstream::Quic { host, port } => {
+            // The server's own name is looked up before the clock starts: only QUIC and DNS are measured.
+            let addr = SocketAddr::new(lookup_system(host, timeout).await?[0], *port);
+            let started = Instant::now();
+            // Boxed: the QUIC connection would otherwise swell every future that may resolve a name.
+            let packet = encode(query)?;
+            let exchange = Box::pin(exchange_quic(addr, host, &packet));
+            let (message, handshake_ms) = match tokio::time::timeout(timeout, exchange).await {
+                Ok(result) => result?,
+                Err(_) => return Err(ResolveError::Timeout),
+            };
+            return Ok(Response { server: server.clone(), transport: server.transport(), message, latency_ms: started.elapsed().as_secs_f64() * 1000.0, handshake_ms: Some(handshake_ms) });
+        },
+    };
+
+    // The TLS handshake is part of the measured latency, as it is for every fresh DoT lookup.
+    let started = Instant::now();
+    let (message, handshake_ms) = match tokio::time::timeout(timeout, exchange_tls(*addr, name, &encode(query)?)).await {
+        Ok(result) => result?,
+        Err(_) => return Err(ResolveError::Timeout),
+    };
+    Ok(Response { server: server.clone(), transport: server.transport(), message, latency_ms: started.elapsed().as_secs_f64() * 1000.0, handshake_ms: Some(handshake_ms) })
+}
+
+/// Query a single server over UDP, retrying over TCP when the reply is truncated.
+pub async fn query_server(server: SocketAddr, query: &Query, timeout: Duration) -> Result<Response, ResolveError> {
+    let started = Instant::now();
+    let packet = encode(query)?;
+
+    let message = match tokio::time::timeout(timeout, exchange_udp(server, &packet, query.id)).await {
+        Ok(result) => result?,
+        Err(_) => return Err(ResolveError::Timeout),
+    };
+    let (message, transport) = if message.truncated {
+        let remaining = timeout.saturating_sub(started.elapsed());
+        match tokio::time::timeout(remaining, exchange_tcp(server, &packet)).await {
+            Ok(result) => (result?, "tcp"),
+            Err(_) => return Err(ResolveError::Timeout),
+        }
+    } else {
+        (message, "udp")
+    };
+
+    Ok(Response { server: Upstream::Plain(server), transport, message, latency_ms: started.elapsed().as_secs_f64() * 1000.0, handshake_ms: None })
+}
+
+async fn exchange_udp(server: SocketAddr, packet: &[u8], id: u16) -> Result<Message, ResolveError> {
+    let bind: SocketAddr = if server.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await?;
+    socket.send(packet).await?;
+
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let n = socket.recv(&mut buf).await?;
+        // Ignore stray datagrams that do not answer our query.
+        match Message::parse(&buf[..n]) {
+            Some(message) if message.id == id => return Ok(message),
+            _ => continue,
+        }
+    }
+}
+
+async fn exchange_tcp(server: SocketAddr, packet: &[u8]) -> Result<Message, ResolveError> {
+    exchange_stream(TcpStream::connect(server).await?, packet).await
+}
+
+/// Returns the answer and the time spent on TCP connect and TLS handshake, in milliseconds.
+async fn exchange_tls(server: SocketAddr, name: &str, packet: &[u8]) -> Result<(Message, f64), ResolveError> {
+    let started = Instant::now();
+    let server_name = rustls::ServerName::try_from(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid TLS name '{}'", name)))?;
+    let config = rustls::ClientConfig::builder().with_safe_defaults().with_root_certificates(crate::probe::tls::root_store()).with_no_client_auth();
+    let tcp = TcpStream::connect(server).await?;
+    let stream = TlsConnector::from(Arc::new(config)).connect(server_name, tcp).await?;
+    let handshake_ms = started.elapsed().as_secs_f64() * 1000.0;
+    Ok((exchange_stream(stream, packet).await?, handshake_ms))
+}
+
+/// Application protocol of DNS-over-QUIC.
+const DOQ_ALPN: &[u8] = b"doq";
+
+/// One query on a fresh DNS-over-QUIC connection (RFC 9250), verifying the server's
+/// certificate for `name`. Returns the answer and how long the QUIC handshake took.
+async fn exchange_quic(server: SocketAddr, name: &str, packet: &[u8]) -> Result<(Message, f64), ResolveError> {
+    let started = Instant::now();
+    let verifier = Arc::new(rustls::client::WebPkiVerifier::new(crate::probe::tls::root_store(), None));
+    let config = crate::probe::tls::quic_client_config(verifier, DOQ_ALPN).map_err(ResolveError::Quic)?;
+    let bind: SocketAddr = if server.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+    let mut endpoint = quinn::Endpoint::client(bind)?;
+    endpoint.set_default_client_config(config);
+    let quic_error = |e: &dyn fmt::Display| ResolveError::Quic(e.to_string());
+    let connection = endpoint.connect(server, name).map_err(|e| quic_error(&e))?.await.map_err(|e| quic_error(&e))?;
+    let handshake_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    // One query per stream, with message ID 0, and the stream closed after it (RFC 9250 4.2).
+    let (mut send, mut recv) = connection.open_bi().await.map_err(|e| quic_error(&e))?;
+    let mut framed = (packet.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&[0, 0]);
+    framed.extend_from_slice(packet.get(2..).unwrap_or_default());
+    send.write_all(&framed).await.map_err(|e| quic_error(&e))?;
+    send.finish().map_err(|e| quic_error(&e))?;
+    let len = recv.read_u16().await? as usize;
+    let mut buf = vec![0u8; len];
+    recv.read_exact(&mut buf).await.map_err(|e| quic_error(&e))?;
+    // DOQ_NO_ERROR
+    connection.close(0u32.into(), b"");
+    Ok((Message::parse(&buf).ok_or(ResolveError::Malformed)?, handshake_ms))
+}
+
+/// Send one length-prefixed message over a stream transport and read the reply (RFC 1035 4.2.2).
+async fn exchange_stream<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, packet: &[u8]) -> Result<Message, ResolveError> {
+    let mut framed = (packet.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(packet);
+    stream.write_all(&framed).await?;
+
+    let len = stream.read_u16().await? as usize;
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).await?;
+    Message::parse(&buf).ok_or(ResolveError::Malformed)
+}
+
+async fn exchange_https(url: &str, packet: &[u8], timeout: Duration, client: Option<&reqwest::Client>) -> Result<Message, ResolveError> {
+    let default;
+    let client = match client {
+        Some(client) => client,
+        None => {
+            default = reqwest::Client::builder().timeout(timeout).user_agent("NetProbe/1.0").build().map_err(|e| ResolveError::Http(e.to_string()))?;
+            &default
+        },
+    };
+    let response = client
+        .post(url)
+        .timeout(timeout)
+        .header(reqwest::header::CONTENT_TYPE, "application/dns-message")
+        .header(reqwest::header::ACCEPT, "application/dns-message")
+        .body(packet.to_vec())
+        .send()
+        .await
+        .map_err(|e| if e.is_timeout() { ResolveError::Timeout } else { ResolveError::Http(e.to_string()) })?;
+    if !response.status().is_success() {
+        return Err(ResolveError::Http(format!("server answered {}", response.status())));
+    }
+    let body = response.bytes().await.map_err(|e| ResolveError::Http(e.to_string()))?;
+    Message::parse(&body).ok_or(ResolveError::Malformed)
+}
+
+/// Parse `1.1.1.1`, `1.1.1.1:5353`, `2606:4700::1111` or `[2606:4700::1111]:53`.
+pub fn parse_server(input: &str) -> Result<SocketAddr, String> {
+    if let Ok(addr) = input.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    input.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)).map_err(|_| format!("invalid DNS server '{}' (expected IP[:port])", input))
+}
+
+/// Parse a DNS-over-TLS server: `9.9.9.9`, `9.9.9.9:853`, or `9.9.9.9#dns.quad9.net`
+/// to verify the certificate against a hostname instead of the IP address.
+pub fn parse_dot_server(input: &str) -> Result<Upstream, String> {
+    let (addr, name) = match input.split_once('#') {
+        Some((addr, name)) => (addr, Some(name)),
+        None => (input, None),
+    };
+    let addr = match addr.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(_) => addr.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 853)).map_err(|_| format!("invalid DoT server '{}' (expected IP[:port][#name])", input))?,
+    };
+    let name = name.map(String::from).unwrap_or_else(|| addr.ip().to_string());
+    Ok(Upstream::Tls { addr, name })
+}
+
+/// Parse a DNS-over-HTTPS endpoint such as `https://cloudflare-dns.com/dns-query`.
+pub fn parse_doh_url(input: &str) -> Result<Upstream, String> {
+    match url::Url::parse(input) {
+        Ok(url) if url.scheme() == "https" => Ok(Upstream::Https(url.to_string())),
+        _ => Err(format!("invalid DoH URL '{}' (expected https://...)", input)),
+    }
+}
+
+/// Parse a DNS-over-QUIC server such as `quic://dns.adguard.com` or `quic://94.140.14.14:853`.
+pub fn parse_doq_url(input: &str) -> Result<Upstream, String> {
+    let invalid = || format!("invalid DoQ URL '{}' (expected quic://HOST[:PORT])", input);
+    match url::Url::parse(input) {
+        Ok(url) if url.scheme() == "quic" && url.path().trim_matches('/').is_empty() => {
+            let host = url.host_str().ok_or_else(invalid)?.trim_start_matches('[').trim_end_matches(']').to_string();
+            Ok(Upstream::Quic { host, port: url.port().unwrap_or(853) })
+        },
+        _ => Err(invalid()),
+    }
+}
+
+/// Nameservers configured for the operating system (`/etc/resolv.conf`).
+/// Empty when they cannot be determined (e.g. on Windows).
+pub fn system_servers() -> Vec<SocketAddr> {
+    let Ok(conf) = std::fs::read_to_string("/etc/resolv.conf") else {
+        return Vec::new();
+    };
+    conf.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        // Strip IPv6 zone ids (fe80::1%eth0), which SocketAddr cannot carry.
+        .filter_map(|server| server.trim().split('%').next()?.parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect()
+}
@@ -0,0 +1,151 @@
+//! DNS propagation check (`netprobe dns --propagation`): the same question to a
+//! set of public resolvers at once, to see which ones still serve an old answer
+//! from cache after a change.
+//!
+//! The answer is "current" when it is the `--expect`ed one or, without it, the
+//! one most resolvers return; any other answer is "stale", and its TTL tells how
+//! long that resolver may keep serving it.
+
+use super::{wire, Resolver, Upstream};
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// Public resolvers queried by default: operator, region, address.
+pub const PUBLIC_RESOLVERS: [(&str, &str, &str); 16] = [
+    ("Google", "global", "8.8.8.8"),
+    ("Google", "global", "8.8.4.4"),
+    ("Cloudflare", "global", "1.1.1.1"),
+    ("Cloudflare", "global", "1.0.0.1"),
+    ("Quad9", "global", "9.9.9.9"),
+    ("OpenDNS", "global", "208.67.222.222"),
+    ("Level3", "global", "4.2.2.1"),
+    ("Hurricane Electric", "global", "74.82.42.42"),
+    ("AdGuard", "global", "94.140.14.140"),
+    ("Control D", "global", "76.76.2.0"),
+    ("DNS.WATCH", "Germany", "84.200.69.80"),
+    ("Yandex", "Russia", "77.88.8.8"),
+    ("CIRA Shield", "Canada", "149.112.121.10"),
+    ("AliDNS", "China", "223.5.5.5"),
+    ("114DNS", "China", "114.114.114.114"),
+    ("Quad101", "Taiwan", "101.101.101.101"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Propagation {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub rtype: String,
+    pub timestamp: String,
+    pub status: String, // "ok" (every answering resolver is current) | "partial" | "error" (none answered)
+    /// The answer counted as current: `--expect`, or the most common one.
+    pub current: Vec<String>,
+    pub resolvers: Vec<ResolverAnswer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolverAnswer {
+    pub name: String,
+    pub region: String,
+    pub server: String,
+    pub status: String, // "current" | "stale" | "error"
+    /// Records in presentation format, sorted.
+    pub records: Vec<String>,
+    /// Lowest TTL of the answer: how long the resolver may still serve it.
+    pub ttl: Option<u32>,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Ask every resolver in `resolvers` ((operator, region, address)) for the `rtype` records of `name`.
+pub async fn check(name: &str, rtype: u16, resolvers: &[(String, String, SocketAddr)], expected: &[String], timeout: Duration) -> Propagation {
+    let mut answers = join_all(resolvers.iter().map(|(operator, region, addr)| ask(name, rtype, operator, region, *addr, timeout))).await;
+
+    let current = match expected {
+        [] => most_common(&answers),
+        expected => {
+            let mut expected: Vec<String> = expected.iter().map(|value| normalize(value, rtype)).collect();
+            expected.sort();
+            expected
+        },
+    };
+    for answer in answers.iter_mut().filter(|answer| answer.error.is_none()) {
+        answer.status = if answer.records == current { "current" } else { "stale" }.to_string();
+    }
+    let status = match (answers.iter().any(|answer| answer.status == "current"), answers.iter().any(|answer| answer.status == "stale")) {
+        (true, false) => "ok",
+        (false, false) => "error",
+        _ => "partial",
+    };
+    Propagation { name: name.to_string(), rtype: wire::type_name(rtype), timestamp: chrono::Local::now().to_rfc3339(), status: status.to_string(), current, resolvers: answers }
+}
+
+/// The built-in [`PUBLIC_RESOLVERS`] on port 53.
+pub fn public_resolvers() -> Vec<(String, String, SocketAddr)> {
+    PUBLIC_RESOLVERS
+        .iter()
+        .filter_map(|(operator, region, address)| Some((operator.to_string(), region.to_string(), SocketAddr::new(address.parse::<IpAddr>().ok()?, 53))))
+        .collect()
+}
+
+async fn ask(name: &str, rtype: u16, operator: &str, region: &str, addr: SocketAddr, timeout: Duration) -> ResolverAnswer {
+    let mut result = ResolverAnswer { name: operator.to_string(), region: region.to_string(), server: addr.to_string(), status: "error".to_string(), records: Vec::new(), ttl: None, latency_ms: None, error: None };
+    let response = match Resolver::new(vec![Upstream::Plain(addr)], timeout).query(name, rtype).await {
+        Ok(response) => response,
+        Err(e) => {
+            result.error = Some(e.to_string());
+            return result;
+        },
+    };
+    result.latency_ms = Some(response.latency_ms);
+    let message = &response.message;
+    match message.rcode {
+        // An NXDOMAIN or empty answer can be the stale one, before a record is added.
+        wire::RCODE_NOERROR | wire::RCODE_NXDOMAIN => {},
+        rcode => {
+            result.error = Some(format!("Server answered {}", wire::rcode_name(rcode)));
+            return result;
+        },
+    }
+    // A CNAME answer is reported as such when the name no longer has the type itself.
+    let records: Vec<&wire::Record> = match message.answers.iter().filter(|record| record.rtype == rtype).collect::<Vec<_>>() {
+        records if records.is_empty() => message.answers.iter().collect(),
+        records => records,
+    };
+    result.records = records
+        .iter()
+        .map(|record| match &record.data {
+            wire::RData::Txt(strings) => strings.concat(),
+            data => normalize(&data.to_string(), record.rtype),
+        })
+        .collect();
+    result.records.sort();
+    result.records.dedup();
+    result.ttl = records.iter().map(|record| record.ttl).min();
+    result
+}
+
+/// The answer most resolvers agree on; ties go to the first resolver's.
+fn most_common(answers: &[ResolverAnswer]) -> Vec<String> {
+    let mut counts: Vec<(&Vec<String>, usize)> = Vec::new();
+    for answer in answers.iter().filter(|answer| answer.error.is_none()) {
+        match counts.iter_mut().find(|(records, _)| *records == &answer.records) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((&answer.records, 1)),
+        }
+    }
+    let top = counts.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    counts.into_iter().find(|(_, count)| *count == top).map(|(records, _)| records.clone()).unwrap_or_default()
+}
+
+/// Compare names without regard to case or the trailing dot, and addresses in their
+/// canonical form; TXT strings are taken as is, joined.
+fn normalize(value: &str, rtype: u16) -> String {
+    match rtype {
+        wire::TYPE_TXT => value.to_string(),
+        // 2001:DB8:0::1 and 2001:db8::1 are the same address.
+        _ if value.trim().parse::<IpAddr>().is_ok() => value.trim().parse::<IpAddr>().map(|ip| ip.to_string()).unwrap_or_default(),
+        _ => value.trim().trim_end_matches('.').to_ascii_lowercase(),
+    }
+}
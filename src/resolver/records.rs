@@ -0,0 +1,107 @@
+//! Arbitrary record-type lookups (`netprobe dns`).
+
+use super::{wire, Resolver};
+use futures_util::future::join_all;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordLookup {
+    pub name: String,
+    pub timestamp: String,
+    /// Client subnet sent with the queries (`--ecs`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_subnet: Option<String>,
+    pub queries: Vec<TypeLookup>,
+}
+
+/// Answer to one question (one record type).
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeLookup {
+    #[serde(rename = "type")]
+    pub rtype: String,
+    pub status: String, // "ok" | "error"
+    pub rcode: Option<String>,
+    pub server: Option<String>,
+    /// "udp" | "tcp" | "tls" | "https" | "quic"
+    pub transport: Option<String>,
+    pub latency_ms: Option<f64>,
+    /// Part of `latency_ms` spent on the TLS or QUIC handshake (DoT, DoQ).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake_ms: Option<f64>,
+    /// Scope prefix of the answer to a query with a client subnet (0: not tailored to it).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_subnet_scope: Option<u8>,
+    pub records: Vec<RecordInfo>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub rtype: String,
+    pub ttl: u32,
+    /// Presentation format (as in a zone file).
+    pub data: String,
+    /// RDATA bytes, hex encoded.
+    pub raw: String,
+}
+
+impl From<&wire::Record> for RecordInfo {
+    fn from(record: &wire::Record) -> Self {
+        RecordInfo {
+            name: record.name.clone(),
+            rtype: wire::type_name(record.rtype),
+            ttl: record.ttl,
+            data: record.data.to_string(),
+            raw: wire::hex(&record.raw),
+        }
+    }
+}
+
+/// Query every type in `types` concurrently.
+pub async fn lookup(resolver: &Resolver, name: &str, types: &[u16]) -> RecordLookup {
+    let queries = types.iter().map(|&rtype| lookup_type(resolver, name, rtype));
+    RecordLookup {
+        name: name.to_string(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+        client_subnet: resolver.client_subnet.map(|subnet| subnet.to_string()),
+        queries: join_all(queries).await,
+    }
+}
+
+async fn lookup_type(resolver: &Resolver, name: &str, rtype: u16) -> TypeLookup {
+    let mut result = TypeLookup {
+        rtype: wire::type_name(rtype),
+        status: "error".to_string(),
+        rcode: None,
+        server: None,
+        transport: None,
+        latency_ms: None,
+        handshake_ms: None,
+        client_subnet_scope: None,
+        records: Vec::new(),
+        error: None,
+    };
+
+    match resolver.query(name, rtype).await {
+        Ok(response) => {
+            let message = &response.message;
+            result.rcode = Some(wire::rcode_name(message.rcode));
+            result.server = Some(response.server.to_string());
+            result.transport = Some(response.transport.to_string());
+            result.latency_ms = Some(response.latency_ms);
+            result.handshake_ms = response.handshake_ms;
+            result.client_subnet_scope = resolver.client_subnet.and(message.client_subnet_scope());
+            result.records = message.answers.iter().map(RecordInfo::from).collect();
+            if message.rcode == wire::RCODE_NOERROR {
+                result.status = "ok".to_string();
+            } else {
+                result.error = Some(format!("Server answered {}", wire::rcode_name(message.rcode)));
+            }
+        },
+        Err(e) => result.error = Some(e.to_string()),
+    }
+
+    result
+}
@@ -0,0 +1,138 @@
+//! TTL countdown of a cached answer (`netprobe dns --ttl-watch`).
+//!
+//! A caching resolver hands out its copy of a record with the TTL that remains,
+//! so successive queries should see it count down with the clock, then jump
+//! back to the full value once the copy expires and is fetched again. The
+//! authoritative TTL is the ceiling: a resolver that answers above it, or whose
+//! countdown stalls or restarts early, does not honour the TTL and may keep
+//! serving a record after it has changed.
+
+use super::wire::{self, Query, RData};
+use super::{query_server, Resolver};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Seconds of slack for rounding and the time a query takes.
+const SLACK: u32 = 2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TtlWatch {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub rtype: String,
+    /// TTL the zone's own servers give the records.
+    pub authoritative_ttl: Option<u32>,
+    pub samples: Vec<TtlSample>,
+    /// "ok" (the countdown followed the clock) | "ignores_ttl"
+    pub status: String,
+    /// What did not follow the TTL, one line per finding.
+    pub findings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TtlSample {
+    /// Seconds since the first query.
+    pub elapsed_s: f64,
+    pub server: Option<String>,
+    /// Lowest TTL of the answer.
+    pub ttl: Option<u32>,
+    /// TTL the previous answer implies by now, if it had not expired.
+    pub expected_ttl: Option<u32>,
+    /// "first" | "countdown" | "refreshed" (fetched again after expiring) | "above_authoritative"
+    /// | "reset" (back up before expiring) | "stalled" (not counting down) | "error"
+    pub verdict: String,
+    pub records: Vec<String>,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl TtlWatch {
+    pub fn new(name: &str, rtype: u16, authoritative_ttl: Option<u32>) -> Self {
+        TtlWatch { name: name.to_string(), rtype: wire::type_name(rtype), authoritative_ttl, samples: Vec::new(), status: "ok".to_string(), findings: Vec::new() }
+    }
+
+    /// Judge `sample` against the start of the current countdown and add it.
+    pub fn push(&mut self, mut sample: TtlSample) {
+        // The countdown starts at the first answer and again whenever the TTL goes back up;
+        // measuring from there keeps short intervals and rounding from hiding a stall.
+        let start = self.samples.iter().rev().find(|previous| matches!(previous.verdict.as_str(), "first" | "refreshed" | "reset" | "above_authoritative"));
+        let previous = self.samples.iter().rev().find(|previous| previous.ttl.is_some());
+        sample.verdict = match (start, sample.ttl) {
+            (_, None) => "error",
+            (_, Some(ttl)) if self.authoritative_ttl.is_some_and(|ceiling| ttl > ceiling + SLACK) => "above_authoritative",
+            (None, Some(_)) => "first",
+            (Some(start), Some(ttl)) => {
+                let passed = (sample.elapsed_s - start.elapsed_s).max(0.0).round() as u32;
+                let expected = start.ttl.unwrap_or_default().saturating_sub(passed);
+                sample.expected_ttl = Some(expected);
+                match ttl {
+                    _ if expected == 0 => "refreshed",
+                    ttl if passed > SLACK && ttl + SLACK >= start.ttl.unwrap_or_default() => "stalled",
+                    ttl if ttl > expected + SLACK => "reset",
+                    // Lower than expected is fine: a resolver may expire records early.
+                    _ => "countdown",
+                }
+            },
+        }
+        .to_string();
+        // A stall, or answers above the ceiling, are one finding however many samples they last.
+        let repeated = matches!(sample.verdict.as_str(), "stalled" | "above_authoritative") && previous.is_some_and(|previous| previous.verdict == sample.verdict);
+        let at = format!("at {:.0}s", sample.elapsed_s);
+        let finding = match sample.verdict.as_str() {
+            "above_authoritative" => Some(format!("TTL {} {} is above the authoritative {}: the resolver extends TTLs", sample.ttl.unwrap_or_default(), at, self.authoritative_ttl.unwrap_or_default())),
+            "reset" => Some(format!("TTL went back up to {} {} while {} remained: a restarted countdown, or another cache behind the same address", sample.ttl.unwrap_or_default(), at, sample.expected_ttl.unwrap_or_default())),
+            "stalled" => Some(format!("TTL stayed at {} {}: the resolver does not count it down (or does not cache at all)", sample.ttl.unwrap_or_default(), at)),
+            _ => None,
+        };
+        if let Some(finding) = finding.filter(|_| !repeated) {
+            self.status = "ignores_ttl".to_string();
+            self.findings.push(finding);
+        }
+        self.samples.push(sample);
+    }
+}
+
+/// One query of `name` through `resolver`, `started` being when the watch began.
+pub async fn sample(resolver: &Resolver, name: &str, rtype: u16, started: Instant) -> TtlSample {
+    let mut sample = TtlSample { elapsed_s: started.elapsed().as_secs_f64(), server: None, ttl: None, expected_ttl: None, verdict: String::new(), records: Vec::new(), latency_ms: None, error: None };
+    match resolver.query(name, rtype).await {
+        Ok(response) => {
+            let records: Vec<&wire::Record> = response.message.answers.iter().filter(|record| record.rtype == rtype).collect();
+            sample.server = Some(response.server.to_string());
+            sample.latency_ms = Some(response.latency_ms);
+            sample.ttl = records.iter().map(|record| record.ttl).min();
+            sample.records = records.iter().map(|record| record.data.to_string()).collect();
+            if records.is_empty() {
+                sample.error = Some(format!("No {} records ({})", wire::type_name(rtype), wire::rcode_name(response.message.rcode)));
+            }
+        },
+        Err(e) => sample.error = Some(e.to_string()),
+    }
+    sample
+}
+
+/// TTL of the `rtype` records of `name` as one of the zone's servers gives it, found through `resolver`.
+pub async fn authoritative_ttl(resolver: &Resolver, name: &str, rtype: u16, timeout: Duration) -> Option<u32> {
+    // The zone is the owner of the SOA in the answer, or in the authority section below the apex.
+    let soa = resolver.query(name, wire::TYPE_SOA).await.ok()?;
+    let zone = soa.message.answers.iter().chain(&soa.message.authorities).find(|record| record.rtype == wire::TYPE_SOA)?.name.clone();
+    let ns = resolver.query(&zone, wire::TYPE_NS).await.ok()?;
+    for target in ns.message.answers.iter().filter_map(|record| match &record.data {
+        RData::Ns(target) => Some(target),
+        _ => None,
+    }) {
+        let Ok(ips) = resolver.lookup_ip(target, None).await.map(|lookup| lookup.ips) else {
+            continue;
+        };
+        for ip in ips {
+            let query = Query { recursion_desired: false, ..Query::new(name, rtype) };
+            if let Ok(response) = query_server(SocketAddr::new(ip, 53), &query, timeout).await {
+                if response.message.authoritative {
+                    return response.message.answers.iter().filter(|record| record.rtype == rtype).map(|record| record.ttl).min();
+                }
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,539 @@
+//! DNS wire format (RFC 1035) encoding and decoding.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+// --- Record types & classes ---
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_DNAME: u16 = 39;
+pub const TYPE_OPT: u16 = 41;
+pub const TYPE_DS: u16 = 43;
+pub const TYPE_RRSIG: u16 = 46;
+pub const TYPE_NSEC: u16 = 47;
+pub const TYPE_DNSKEY: u16 = 48;
+pub const TYPE_NSEC3: u16 = 50;
+pub const TYPE_CAA: u16 = 257;
+pub const CLASS_IN: u16 = 1;
+
+/// Types that can be requested by name, with their codes.
+const TYPE_NAMES: &[(&str, u16)] = &[
+    ("A", TYPE_A),
+    ("NS", TYPE_NS),
+    ("CNAME", TYPE_CNAME),
+    ("SOA", TYPE_SOA),
+    ("PTR", TYPE_PTR),
+    ("MX", TYPE_MX),
+    ("TXT", TYPE_TXT),
+    ("AAAA", TYPE_AAAA),
+    ("SRV", TYPE_SRV),
+    ("DNAME", TYPE_DNAME),
+    ("OPT", TYPE_OPT),
+    ("DS", TYPE_DS),
+    ("RRSIG", TYPE_RRSIG),
+    ("NSEC", TYPE_NSEC),
+    ("DNSKEY", TYPE_DNSKEY),
+    ("NSEC3", TYPE_NSEC3),
+    ("CAA", TYPE_CAA),
+];
+
+// --- Response codes ---
+
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_SERVFAIL: u8 = 2;
+pub const RCODE_NXDOMAIN: u8 = 3;
+pub const RCODE_REFUSED: u8 = 5;
+
+/// Human name of a response code, as printed by dig.
+pub fn rcode_name(rcode: u8) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+/// Mnemonic of a record type (`A`, `MX`...), `TYPEnnn` when unknown.
+pub fn type_name(rtype: u16) -> String {
+    match TYPE_NAMES.iter().find(|(_, code)| *code == rtype) {
+        Some((name, _)) => name.to_string(),
+        None => format!("TYPE{}", rtype),
+    }
+}
+
+/// Parse a type mnemonic (case-insensitive) or the generic `TYPEnnn` form.
+pub fn type_from_name(name: &str) -> Option<u16> {
+    let upper = name.trim().to_ascii_uppercase();
+    if let Some(code) = upper.strip_prefix("TYPE").and_then(|n| n.parse().ok()) {
+        return Some(code);
+    }
+    TYPE_NAMES.iter().find(|(n, _)| *n == upper).map(|(_, code)| *code)
+}
+
+// --- Queries ---
+
+/// A single-question query.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub id: u16,
+    pub name: String,
+    pub qtype: u16,
+    pub recursion_desired: bool,
+    /// Ask for DNSSEC records (EDNS0 DO bit) and for answers the server has not
+    /// validated itself (CD bit), so a broken chain can be examined rather than SERVFAIL.
+    pub dnssec: bool,
+    /// Network the query is asked on behalf of (EDNS Client Subnet).
+    pub client_subnet: Option<ClientSubnet>,
+}
+
+impl Query {
+    pub fn new(name: &str, qtype: u16) -> Self {
+        Query { id: random_id(), name: name.to_string(), qtype, recursion_desired: true, dnssec: false, client_subnet: None }
+    }
+
+    /// The query on the wire; an error if the name does not fit in one.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&self.id.to_be_bytes());
+        let mut flags: u16 = if self.recursion_desired { 0x0100 } else { 0 };
+        if self.dnssec {
+            flags |= 0x0010; // CD
+        }
+        buf.extend_from_slice(&flags.to_be_bytes());
+        buf.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+        buf.extend_from_slice(&0u16.to_be_bytes()); // ANCOUNT
+        buf.extend_from_slice(&0u16.to_be_bytes()); // NSCOUNT
+        let edns = self.dnssec || self.client_subnet.is_some();
+        buf.extend_from_slice(&u16::from(edns).to_be_bytes()); // ARCOUNT
+        encode_name(&self.name, &mut buf)?;
+        buf.extend_from_slice(&self.qtype.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        if edns {
+            // OPT pseudo-record (RFC 6891): root owner, 1232-byte UDP payload, DO flag for DNSSEC.
+            let mut options = Vec::new();
+            if let Some(subnet) = &self.client_subnet {
+                subnet.encode(&mut options);
+            }
+            buf.push(0);
+            buf.extend_from_slice(&TYPE_OPT.to_be_bytes());
+            buf.extend_from_slice(&1232u16.to_be_bytes());
+            buf.extend_from_slice(&[0, 0, if self.dnssec { 0x80 } else { 0 }, 0]);
+            buf.extend_from_slice(&(options.len() as u16).to_be_bytes());
+            buf.extend_from_slice(&options);
+        }
+        Ok(buf)
+    }
+}
+
+/// EDNS option code of the client subnet (RFC 7871).
+pub const OPTION_CLIENT_SUBNET: u16 = 8;
+
+/// An EDNS Client Subnet, written `203.0.113.0/24`: GeoDNS servers answer as they
+/// would for a client in that network. A bare address stands for itself (/32, /128).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientSubnet {
+    /// Network address, host bits cleared.
+    pub address: IpAddr,
+    pub prefix: u8,
+}
+
+impl ClientSubnet {
+    /// The option as it goes in the OPT record: family, source prefix, scope prefix (0 in queries),
+    /// then only the address bytes the prefix covers.
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let (family, octets): (u16, Vec<u8>) = match self.address {
+            IpAddr::V4(ip) => (1, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (2, ip.octets().to_vec()),
+        };
+        let len = (self.prefix as usize).div_ceil(8);
+        buf.extend_from_slice(&OPTION_CLIENT_SUBNET.to_be_bytes());
+        buf.extend_from_slice(&(4 + len as u16).to_be_bytes());
+        buf.extend_from_slice(&family.to_be_bytes());
+        buf.extend_from_slice(&[self.prefix, 0]);
+        buf.extend_from_slice(&octets[..len]);
+    }
+}
+
+impl FromStr for ClientSubnet {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let (address, prefix) = input.split_once('/').unwrap_or((input, ""));
+        let address: IpAddr = address.parse().map_err(|_| format!("invalid subnet '{}' (expected e.g. 203.0.113.0/24)", input))?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            "" => max,
+            prefix => prefix.parse::<u8>().ok().filter(|&prefix| prefix <= max).ok_or_else(|| format!("invalid prefix length '{}' in '{}' (0-{})", prefix, input, max))?,
+        };
+        // Host bits are cleared: servers reject an address with bits set beyond the prefix.
+        let address = match address {
+            IpAddr::V4(ip) => IpAddr::V4(Ipv4Addr::from(u32::from(ip) & u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0))),
+            IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0))),
+        };
+        Ok(ClientSubnet { address, prefix })
+    }
+}
+
+impl fmt::Display for ClientSubnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// Query IDs only need to be unpredictable enough to match replies; the
+/// std hasher's random keys are plenty for a diagnostic tool.
+fn random_id() -> u16 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0));
+    hasher.finish() as u16
+}
+
+/// Append `name` in wire format; labels are at most 63 bytes and names 255 (RFC 1035 2.3.4).
+pub fn encode_name(name: &str, buf: &mut Vec<u8>) -> Result<(), String> {
+    let start = buf.len();
+    for label in name.trim_end_matches('.').split('.').filter(|l| !l.is_empty()) {
+        let bytes = label.as_bytes();
+        if bytes.len() > 63 {
+            buf.truncate(start);
+            return Err(format!("label '{}' in '{}' is longer than 63 bytes", label, name));
+        }
+        buf.push(bytes.len() as u8);
+        buf.extend_from_slice(bytes);
+    }
+    buf.push(0);
+    if buf.len() - start > 255 {
+        buf.truncate(start);
+        return Err(format!("name '{}' is longer than 255 bytes", name));
+    }
+    Ok(())
+}
+
+// --- Responses ---
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: u16,
+    pub rcode: u8,
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_available: bool,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RData,
+    /// RDATA exactly as received (names may be compressed).
+    pub raw: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ns(String),
+    Ptr(String),
+    Mx { preference: u16, exchange: String },
+    Txt(Vec<String>),
+    Soa { mname: String, rname: String, serial: u32, refresh: u32, retry: u32, expire: u32, minimum: u32 },
+    Srv { priority: u16, weight: u16, port: u16, target: String },
+    Caa { flags: u8, tag: String, value: String },
+    Other(Vec<u8>),
+}
+
+impl fmt::Display for RData {
+    /// Zone-file presentation format, as printed by dig.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A(ip) => write!(f, "{}", ip),
+            RData::Aaaa(ip) => write!(f, "{}", ip),
+            RData::Cname(name) | RData::Ns(name) | RData::Ptr(name) => write!(f, "{}.", name.trim_end_matches('.')),
+            RData::Mx { preference, exchange } => write!(f, "{} {}.", preference, exchange.trim_end_matches('.')),
+            RData::Txt(strings) => {
+                let quoted: Vec<String> = strings.iter().map(|s| format!("{:?}", s)).collect();
+                write!(f, "{}", quoted.join(" "))
+            },
+            RData::Soa { mname, rname, serial, refresh, retry, expire, minimum } => {
+                write!(f, "{}. {}. {} {} {} {} {}", mname.trim_end_matches('.'), rname.trim_end_matches('.'), serial, refresh, retry, expire, minimum)
+            },
+            RData::Srv { priority, weight, port, target } => write!(f, "{} {} {} {}.", priority, weight, port, target.trim_end_matches('.')),
+            RData::Caa { flags, tag, value } => write!(f, "{} {} {:?}", flags, tag, value),
+            // RFC 3597 generic encoding.
+            RData::Other(bytes) => write!(f, "\\# {} {}", bytes.len(), hex(bytes)),
+        }
+    }
+}
+
+/// Lowercase hex encoding.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Message {
+    /// Decode a full DNS message; `None` if it is malformed.
+    pub fn parse(buf: &[u8]) -> Option<Message> {
+        let header = buf.get(..12)?;
+        let id = u16::from_be_bytes([header[0], header[1]]);
+        let flags = u16::from_be_bytes([header[2], header[3]]);
+        let count = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]) as usize;
+        let (qdcount, ancount, nscount, arcount) = (count(4), count(6), count(8), count(10));
+
+        let mut pos = 12;
+        for _ in 0..qdcount {
+            let (_, next) = decode_name(buf, pos)?;
+            pos = next + 4;
+        }
+
+        let read_section = |n: usize, pos: &mut usize| -> Option<Vec<Record>> {
+            // The counts come from the packet: never reserve more than it can hold (11 bytes per record at least).
+            let mut records = Vec::with_capacity(n.min(buf.len() / 11));
+            for _ in 0..n {
+                let (record, next) = parse_record(buf, *pos)?;
+                records.push(record);
+                *pos = next;
+            }
+            Some(records)
+        };
+        let answers = read_section(ancount, &mut pos)?;
+        let authorities = read_section(nscount, &mut pos)?;
+        let additionals = read_section(arcount, &mut pos)?;
+
+        Some(Message {
+            id,
+            rcode: (flags & 0x000f) as u8,
+            authoritative: flags & 0x0400 != 0,
+            truncated: flags & 0x0200 != 0,
+            recursion_available: flags & 0x0080 != 0,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+
+    /// Scope prefix of the client subnet echoed in the OPT record: how much of the subnet
+    /// the answer depends on (0: not at all). `None` when the server ignored the option.
+    pub fn client_subnet_scope(&self) -> Option<u8> {
+        let opt = self.additionals.iter().find(|record| record.rtype == TYPE_OPT)?;
+        let mut options = opt.raw.as_slice();
+        while options.len() >= 4 {
+            let code = u16::from_be_bytes([options[0], options[1]]);
+            let len = u16::from_be_bytes([options[2], options[3]]) as usize;
+            let data = options.get(4..4 + len)?;
+            if code == OPTION_CLIENT_SUBNET {
+                return data.get(3).copied();
+            }
+            options = &options[4 + len..];
+        }
+        None
+    }
+}
+
+fn parse_record(buf: &[u8], pos: usize) -> Option<(Record, usize)> {
+    let (name, pos) = decode_name(buf, pos)?;
+    let fixed = buf.get(pos..pos + 10)?;
+    let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let class = u16::from_be_bytes([fixed[2], fixed[3]]);
+    let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+    let rdlen = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+    let start = pos + 10;
+    let rdata = buf.get(start..start + rdlen)?;
+
+    let data = parse_rdata(buf, rtype, start, rdata).unwrap_or_else(|| RData::Other(rdata.to_vec()));
+
+    Some((Record { name, rtype, class, ttl, data, raw: rdata.to_vec() }, start + rdlen))
+}
+
+/// Decode known RDATA layouts; `None` falls back to opaque bytes.
+fn parse_rdata(buf: &[u8], rtype: u16, start: usize, rdata: &[u8]) -> Option<RData> {
+    let u16_at = |i: usize| -> Option<u16> { Some(u16::from_be_bytes([*rdata.get(i)?, *rdata.get(i + 1)?])) };
+    let u32_at = |i: usize| -> Option<u32> { Some(u32::from_be_bytes(rdata.get(i..i + 4)?.try_into().ok()?)) };
+
+    Some(match rtype {
+        TYPE_A if rdata.len() == 4 => RData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+        TYPE_AAAA if rdata.len() == 16 => RData::Aaaa(Ipv6Addr::from(<[u8; 16]>::try_from(rdata).ok()?)),
+        TYPE_CNAME => RData::Cname(decode_name(buf, start)?.0),
+        TYPE_NS => RData::Ns(decode_name(buf, start)?.0),
+        TYPE_PTR => RData::Ptr(decode_name(buf, start)?.0),
+        TYPE_MX => RData::Mx { preference: u16_at(0)?, exchange: decode_name(buf, start + 2)?.0 },
+        TYPE_TXT => {
+            let mut strings = Vec::new();
+            let mut i = 0;
+            while i < rdata.len() {
+                let len = rdata[i] as usize;
+                strings.push(String::from_utf8_lossy(rdata.get(i + 1..i + 1 + len)?).into_owned());
+                i += 1 + len;
+            }
+            RData::Txt(strings)
+        },
+        TYPE_SOA => {
+            let (mname, next) = decode_name(buf, start)?;
+            let (rname, next) = decode_name(buf, next)?;
+            let offset = next - start;
+            RData::Soa {
+                mname,
+                rname,
+                serial: u32_at(offset)?,
+                refresh: u32_at(offset + 4)?,
+                retry: u32_at(offset + 8)?,
+                expire: u32_at(offset + 12)?,
+                minimum: u32_at(offset + 16)?,
+            }
+        },
+        TYPE_SRV => RData::Srv { priority: u16_at(0)?, weight: u16_at(2)?, port: u16_at(4)?, target: decode_name(buf, start + 6)?.0 },
+        TYPE_CAA => {
+            let tag_len = *rdata.get(1)? as usize;
+            RData::Caa {
+                flags: rdata[0],
+                tag: String::from_utf8_lossy(rdata.get(2..2 + tag_len)?).into_owned(),
+                value: String::from_utf8_lossy(rdata.get(2 + tag_len..)?).into_owned(),
+            }
+        },
+        _ => return None,
+    })
+}
+
+/// Decode a (possibly compressed) name at `pos`; returns the name and the
+/// offset just past it in the original position.
+pub fn decode_name(buf: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bounds the number of compression pointers followed, defeating loops.
+    let mut jumps = 0;
+    loop {
+        let len = *buf.get(pos)? as usize;
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                end.get_or_insert(pos + 1);
+                break;
+            },
+            0x00 => {
+                let label = buf.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            },
+            0xc0 => {
+                let pointer = ((len & 0x3f) << 8) | *buf.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                jumps += 1;
+                if jumps > 64 {
+                    return None;
+                }
+                pos = pointer;
+            },
+            _ => return None,
+        }
+    }
+    let name = if labels.is_empty() { ".".to_string() } else { labels.join(".") };
+    Some((name, end?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `www.example.com` CNAME `example.com` (compressed) A 192.0.2.1, then an OPT record
+    /// echoing the client subnet 203.0.113.0/24 with scope 16.
+    fn response() -> Vec<u8> {
+        let mut buf = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 1];
+        encode_name("www.example.com", &mut buf).unwrap();
+        buf.extend_from_slice(&[0, 1, 0, 1]);
+        // CNAME: owner points at the question name (offset 12), target at "example.com" (offset 16).
+        buf.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 1, 44, 0, 2, 0xc0, 16]);
+        buf.extend_from_slice(&[0xc0, 16, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1]);
+        buf.extend_from_slice(&[0, 0, 41, 4, 208, 0, 0, 0, 0, 0, 11, 0, 8, 0, 7, 0, 1, 24, 16, 203, 0, 113]);
+        buf
+    }
+
+    #[test]
+    fn parses_a_response_with_compressed_names() {
+        let message = Message::parse(&response()).unwrap();
+        assert_eq!(message.id, 0x1234);
+        assert_eq!(message.rcode, 0);
+        assert!(message.recursion_available && !message.truncated && !message.authoritative);
+        assert_eq!(message.answers.len(), 2);
+        assert_eq!(message.answers[0].name, "www.example.com");
+        assert!(matches!(&message.answers[0].data, RData::Cname(target) if target == "example.com"));
+        assert_eq!((message.answers[1].name.as_str(), message.answers[1].ttl), ("example.com", 60));
+        assert!(matches!(message.answers[1].data, RData::A(ip) if ip == Ipv4Addr::new(192, 0, 2, 1)));
+        assert_eq!(message.client_subnet_scope(), Some(16));
+    }
+
+    #[test]
+    fn rejects_truncated_responses() {
+        let buf = response();
+        for len in 0..buf.len() {
+            assert!(Message::parse(&buf[..len]).is_none(), "parsed {} of {} bytes", len, buf.len());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_names() {
+        // A pointer to itself, and the reserved 0x40 label type.
+        assert!(decode_name(&[0xc0, 0], 0).is_none());
+        assert!(decode_name(&[0x40, 0], 0).is_none());
+        // A label running past the end.
+        assert!(decode_name(&[5, b'a', b'b', 0], 0).is_none());
+        assert_eq!(decode_name(&[0], 0), Some((".".to_string(), 1)));
+    }
+
+    #[test]
+    fn header_counts_are_not_trusted() {
+        // 65535 answers announced, none present: fails without reserving room for them.
+        let mut buf = response()[..12].to_vec();
+        buf[4..12].copy_from_slice(&[0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert!(Message::parse(&buf).is_none());
+    }
+
+    #[test]
+    fn encodes_names_and_rejects_long_labels() {
+        let mut buf = Vec::new();
+        encode_name("example.com.", &mut buf).unwrap();
+        assert_eq!(buf, b"\x07example\x03com\x00");
+        buf.clear();
+        encode_name(".", &mut buf).unwrap();
+        assert_eq!(buf, [0]);
+
+        let mut buf = vec![1, 2];
+        assert!(encode_name(&format!("{}.com", "a".repeat(63)), &mut Vec::new()).is_ok());
+        assert!(encode_name(&format!("{}.com", "a".repeat(64)), &mut buf).unwrap_err().contains("longer than 63 bytes"));
+        assert!(encode_name(&vec!["a".repeat(60); 5].join("."), &mut buf).unwrap_err().contains("longer than 255 bytes"));
+        // Nothing is left behind on failure.
+        assert_eq!(buf, [1, 2]);
+        assert!(Query::new(&"a".repeat(64), TYPE_A).encode().is_err());
+    }
+
+    #[test]
+    fn encodes_queries_with_a_client_subnet() {
+        let subnet: ClientSubnet = "203.0.113.77/24".parse().unwrap();
+        assert_eq!(subnet.to_string(), "203.0.113.0/24");
+        assert!("203.0.113.0/33".parse::<ClientSubnet>().is_err());
+
+        let query = Query { client_subnet: Some(subnet), ..Query::new("example.com", TYPE_A) };
+        let buf = query.encode().unwrap();
+        assert_eq!(&buf[2..12], [1, 0, 0, 1, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(&buf[12..29], b"\x07example\x03com\x00\x00\x01\x00\x01");
+        assert_eq!(&buf[29..], [0, 0, 41, 4, 208, 0, 0, 0, 0, 0, 11, 0, 8, 0, 7, 0, 1, 24, 0, 203, 0, 113]);
+    }
+}
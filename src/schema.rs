@@ -0,0 +1,343 @@
+//! JSON Schema of the probe report (`netprobe --schema`).
+//!
+//! Kept by hand next to the result types. The stages every report has are
+//! described field by field; the optional blocks, each present only with its
+//! flag, as objects. Every object accepts properties it does not list, so a
+//! report with fields added later still validates. Parsers should ignore what
+//! they do not know and check `schema_version`, which goes up only when a field
+//! is removed, renamed or changes meaning.
+
+use crate::probe::SCHEMA_VERSION;
+use serde_json::{json, Map, Value};
+
+/// The schema of one `ProbeResult`, as printed by `--json`.
+pub fn probe_result() -> Value {
+    let mut schema = object(
+        "Result of probing one target",
+        &["schema_version", "target", "timestamp", "duration_ms", "dns", "tcp", "tls", "http"],
+        json!({
+            "schema_version": { "const": SCHEMA_VERSION, "description": "Version of this layout" },
+            "target": string("Target as probed, with its scheme"),
+            "timestamp": { "type": "string", "format": "date-time", "description": "Start of the probe (RFC 3339)" },
+            "duration_ms": number("Wall-clock time of the whole probe"),
+            "dns": dns(),
+            "geo": block("Location and network owner of the resolved IP (--geo)"),
+            "icmp": stage_block(
+                "ICMP echo replies (--ping)",
+                &["ok", "error"],
+                &["ICMP_PERMISSION", "ICMP_LOCAL", "ICMP_SOCKET", "ICMP_TIMEOUT"],
+            ),
+            "tcp": tcp(),
+            "udp": stage_block(
+                "UDP probe, replacing the TCP, TLS and HTTP stages (--udp)",
+                &["ok", "error"],
+                &["UDP_TIMEOUT", "UDP_UNREACHABLE", "UDP_BAD_REPLY", "UDP_LOCAL"],
+            ),
+            "tls": tls(),
+            "http": http(),
+            "websocket": stage_block(
+                "WebSocket upgrade, replacing the HTTP request (--ws, ws:// and wss:// targets)",
+                &["ok", "error"],
+                &["WS_CONNECT", "WS_TIMEOUT", "WS_TLS", "WS_REJECTED", "WS_CLOSED", "WS_PROTOCOL"],
+            ),
+            "cdn": block("CDN or WAF in front of the target (--detect-cdn)"),
+            "http3": stage_block(
+                "HTTP/3 advertisement and request, timed against the HTTP stage (--http3)",
+                &["ok", "error"],
+                &["HTTP3_LOCAL", "HTTP3_HANDSHAKE", "HTTP3_TIMEOUT", "HTTP3_PROTOCOL"],
+            ),
+            "stacks": block("IPv4 and IPv6 side by side (--compare-stacks)"),
+            "assertions": array(
+                "Outcome of every --expect-* check",
+                object(
+                    "",
+                    &["name", "expected", "actual", "passed"],
+                    json!({ "name": string("What was checked"), "expected": string(""), "actual": nullable("string", "Observed value"), "passed": boolean("") }),
+                ),
+            ),
+            "latency_checks": array(
+                "Stage latencies against the --max-* thresholds",
+                object(
+                    "",
+                    &["stage", "status", "limit_ms", "latency_ms"],
+                    json!({
+                        "stage": one_of("", &["dns", "tcp", "tls", "http", "total"]),
+                        "status": one_of("", &["ok", "degraded", "skipped"]),
+                        "limit_ms": number(""),
+                        "latency_ms": nullable("number", "None when the stage did not complete"),
+                    }),
+                ),
+            ),
+            "stats": block("Latency distribution per stage over every sample (--count); the other fields are the last sample"),
+        }),
+    );
+    let root = schema.as_object_mut().expect("object schema");
+    root.insert("$schema".to_string(), json!("https://json-schema.org/draft/2020-12/schema"));
+    root.insert("$id".to_string(), json!(format!("https://github.com/kiy0ni/netprobe/schema/probe-result/v{}.json", SCHEMA_VERSION)));
+    root.insert("title".to_string(), json!("netprobe ProbeResult"));
+    schema
+}
+
+fn dns() -> Value {
+    object(
+        "Step 1: name resolution",
+        &["status", "ip", "ipv4", "ipv6", "family", "latency_ms", "server", "transport", "error"],
+        json!({
+            "status": one_of("", &["ok", "error", "skipped", "pending", "interrupted"]),
+            "ip": nullable("string", "Address used by the following stages"),
+            "ipv4": array("", string("")),
+            "ipv6": array("", string("")),
+            "family": { "enum": ["ipv4", "ipv6", null], "description": "Family of ip" },
+            "latency_ms": nullable("number", ""),
+            "handshake_ms": number("Part of latency_ms spent on the TLS or QUIC handshake (DoT, DoQ)"),
+            "ttl": integer("Lowest TTL of the address records"),
+            "client_subnet": string("Client subnet sent with the queries (--ecs)"),
+            "client_subnet_scope": integer("Scope prefix the server answered for"),
+            "server": nullable("string", "DNS server that answered; null for the system resolver"),
+            "transport": one_of("", &["system", "udp", "tcp", "tls", "https", "quic", "override"]),
+            "ptr": string("Reverse name of ip (--rdns)"),
+            "error": nullable("string", ""),
+            "failure": one_of("Why the lookup failed", &["timeout", "nxdomain", "no_records", "server_error", "malformed", "http", "quic", "io"]),
+            "error_code": error_code(&["DNS_TIMEOUT", "DNS_NXDOMAIN", "DNS_NO_RECORDS", "DNS_SERVER_ERROR", "DNS_MALFORMED", "DNS_TRANSPORT"]),
+            "attempts": attempts(),
+            "answers": array("Answer sections of the address queries (-v with --dns-server)", block("")),
+            "dnssec": block("Chain of trust of the address records (--dnssec)"),
+            "trace": block("Referrals from the root to the address records (--dns-trace)"),
+        }),
+    )
+}
+
+fn tcp() -> Value {
+    object(
+        "Step 2: TCP handshake",
+        &["status", "port", "latency_ms", "error"],
+        json!({
+            "status": one_of("", &["ok", "error", "skipped", "pending", "interrupted"]),
+            "port": integer(""),
+            "latency_ms": nullable("number", ""),
+            "error": nullable("string", ""),
+            "failure": one_of("Why the connection failed", &["refused", "timeout", "unreachable", "reset", "denied", "local", "other"]),
+            "error_code": error_code(&["TCP_REFUSED", "TCP_TIMEOUT", "TCP_UNREACHABLE", "TCP_RESET", "TCP_DENIED", "TCP_LOCAL", "TCP_OTHER"]),
+            "source": string("Local address the connection went out from (--source-ip, --interface)"),
+            "dscp": integer("DSCP the packets were marked with (--dscp, --tos)"),
+            "happy_eyeballs": block("Connection race between the resolved addresses (--happy-eyeballs)"),
+            "all_ips": array("Every resolved address (--all-ips)", block("")),
+            "attempts": attempts(),
+            "ports": array(
+                "Extra ports checked (--ports)",
+                object(
+                    "",
+                    &["port", "status", "latency_ms", "error"],
+                    json!({ "port": integer(""), "status": one_of("", &["open", "closed", "filtered"]), "latency_ms": nullable("number", ""), "error": nullable("string", "") }),
+                ),
+            ),
+            "banner": string("What the server sent first (--banner), non-printable bytes escaped"),
+            "service": stage_block(
+                "Greeting of the service on a well-known port",
+                &["ok", "error", "skipped"],
+                &["SERVICE_CONNECT", "SERVICE_TIMEOUT", "SERVICE_TLS", "SERVICE_PROTOCOL"],
+            ),
+            "quality": block("Connect spread and kernel metrics over repeated connections (--tcp-quality)"),
+        }),
+    )
+}
+
+fn tls() -> Value {
+    object(
+        "Step 3: TLS handshake (https and wss)",
+        &[
+            "status",
+            "latency_ms",
+            "protocol",
+            "cipher_suite",
+            "alpn",
+            "certificate",
+            "verification",
+            "trust_anchor",
+            "trust_store",
+            "client_cert_requested",
+            "ocsp_stapled",
+            "warning",
+            "error",
+        ],
+        json!({
+            "status": one_of("", &["ok", "warning", "error", "skipped", "pending", "interrupted"]),
+            "latency_ms": nullable("number", ""),
+            "protocol": nullable("string", "e.g. TLSv1.3"),
+            "cipher_suite": nullable("string", ""),
+            "alpn": nullable("string", "Application protocol agreed through ALPN"),
+            "sni": string("Server name sent (-v)"),
+            "alpn_offered": array("Application protocols offered (-v)", string("")),
+            "certificate": {
+                "oneOf": [
+                    { "type": "null" },
+                    object(
+                        "Leaf certificate",
+                        &["subject", "issuer", "sans", "not_before", "not_after", "days_until_expiry"],
+                        json!({
+                            "subject": string(""),
+                            "issuer": string(""),
+                            "sans": array("", string("")),
+                            "not_before": string(""),
+                            "not_after": string(""),
+                            "days_until_expiry": integer(""),
+                        }),
+                    ),
+                ],
+            },
+            "chain": array("Every certificate presented, leaf first (--cert-chain)", block("")),
+            "verification": { "enum": ["ok", "failed", "skipped", null] },
+            "verify_error": string("Why verification would have failed, when --insecure skipped it"),
+            "trust_anchor": nullable("string", ""),
+            "trust_store": nullable("string", "\"bundled\" or the --cacert file"),
+            "client_cert_requested": nullable("boolean", ""),
+            "ocsp_stapled": nullable("boolean", ""),
+            "revocation": block("Revocation status of the leaf (--check-revocation)"),
+            "ct": block("Certificate Transparency timestamps (--check-ct)"),
+            "caa": block("CAA records against the issuer (--check-caa)"),
+            "resumption": block("Session resumption and 0-RTT (--check-resumption)"),
+            "client_cert": string("Subject of the client certificate sent (--cert)"),
+            "warning": nullable("string", "Set when the certificate expires within --cert-warn-days"),
+            "error": nullable("string", ""),
+            "error_code": error_code(&[
+                "TLS_CONNECT",
+                "TLS_TIMEOUT",
+                "TLS_HANDSHAKE",
+                "TLS_SERVER_NAME",
+                "TLS_NO_STARTTLS",
+                "TLS_EXPIRED_CERT",
+                "TLS_NOT_YET_VALID_CERT",
+                "TLS_HOSTNAME_MISMATCH",
+                "TLS_UNTRUSTED_CERT",
+                "TLS_REVOKED_CERT",
+                "TLS_INVALID_CERT",
+                "TLS_CT_MISSING",
+            ]),
+        }),
+    )
+}
+
+fn http() -> Value {
+    object(
+        "Step 4: HTTP request",
+        &["status_code", "version", "peer_ip", "latency_ms", "timing", "headers", "error"],
+        json!({
+            "status_code": nullable("integer", ""),
+            "version": { "enum": ["HTTP/1.0", "HTTP/1.1", "HTTP/2", null] },
+            "peer_ip": nullable("string", "Address the request was sent to; the proxy's when proxied"),
+            "auth": string("Authentication scheme of the final request"),
+            "cookies": array("Names of the cookies the server set", string("")),
+            "proxy": string("Proxy the request went through, without credentials"),
+            "latency_ms": nullable("number", "Whole stage, redirects included"),
+            "timing": {
+                "oneOf": [
+                    { "type": "null" },
+                    object(
+                        "Phases of the request reported",
+                        &["dns_ms", "connect_ms", "proxy_ms", "tls_ms", "ttfb_ms", "transfer_ms", "total_ms"],
+                        json!({
+                            "dns_ms": number(""),
+                            "connect_ms": number(""),
+                            "proxy_ms": nullable("number", ""),
+                            "tls_ms": nullable("number", "null for plain HTTP"),
+                            "ttfb_ms": number(""),
+                            "transfer_ms": number(""),
+                            "total_ms": number(""),
+                        }),
+                    ),
+                ],
+            },
+            "headers": { "type": ["object", "null"], "additionalProperties": { "type": "string" }, "description": "Response headers, lowercase names" },
+            "redirects": array("Every redirect followed", block("")),
+            "security": block("Security headers grade (--security-audit)"),
+            "download": block("Size and speed of the response body"),
+            "warm_request": block("The request replayed on the same connection (--warm)"),
+            "request": block("The request as sent (-v)"),
+            "grpc": stage_block(
+                "Health check outcome (--grpc)",
+                &["ok", "error"],
+                &["GRPC_UNSUPPORTED", "GRPC_CALL_FAILED", "GRPC_MALFORMED", "GRPC_NOT_SERVING"],
+            ),
+            "error": nullable("string", ""),
+            "error_code": error_code(&[
+                "HTTP_TIMEOUT",
+                "HTTP_INVALID_REQUEST",
+                "HTTP_DNS",
+                "HTTP_CONNECT",
+                "HTTP_PROXY",
+                "HTTP_TLS",
+                "HTTP_CONNECTION_CLOSED",
+                "HTTP_MALFORMED_RESPONSE",
+                "HTTP_TOO_MANY_REDIRECTS",
+                "HTTP_PROTOCOL",
+            ]),
+            "attempts": attempts(),
+        }),
+    )
+}
+
+/// Each try of a stage when retrying (`--retries`).
+fn attempts() -> Value {
+    array(
+        "Each try when retrying (--retries), the last one being reported",
+        object("", &["latency_ms", "error"], json!({ "latency_ms": number(""), "error": nullable("string", "") })),
+    )
+}
+
+/// An optional stage: its flag, its statuses and the codes of its `error`; other fields are not listed.
+fn stage_block(description: &str, statuses: &[&str], codes: &[&str]) -> Value {
+    object(
+        description,
+        &["status", "error"],
+        json!({ "status": one_of("", statuses), "error": nullable("string", ""), "error_code": error_code(codes) }),
+    )
+}
+
+fn error_code(codes: &[&str]) -> Value {
+    one_of("Stable code of error, for scripts; more codes may be added", codes)
+}
+
+fn object(description: &str, required: &[&str], properties: Value) -> Value {
+    describe(json!({ "type": "object", "required": required, "properties": properties }), description)
+}
+
+/// An optional block described only by the flag that adds it.
+fn block(description: &str) -> Value {
+    describe(json!({ "type": "object" }), description)
+}
+
+fn array(description: &str, items: Value) -> Value {
+    describe(json!({ "type": "array", "items": items }), description)
+}
+
+fn one_of(description: &str, values: &[&str]) -> Value {
+    describe(json!({ "type": "string", "enum": values }), description)
+}
+
+fn nullable(kind: &str, description: &str) -> Value {
+    describe(json!({ "type": [kind, "null"] }), description)
+}
+
+fn string(description: &str) -> Value {
+    describe(json!({ "type": "string" }), description)
+}
+
+fn number(description: &str) -> Value {
+    describe(json!({ "type": "number" }), description)
+}
+
+fn integer(description: &str) -> Value {
+    describe(json!({ "type": "integer" }), description)
+}
+
+fn boolean(description: &str) -> Value {
+    describe(json!({ "type": "boolean" }), description)
+}
+
+fn describe(mut schema: Value, description: &str) -> Value {
+    if !description.is_empty() {
+        let fields: &mut Map<String, Value> = schema.as_object_mut().expect("object schema");
+        fields.insert("description".to_string(), json!(description));
+    }
+    schema
+}
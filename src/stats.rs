@@ -0,0 +1,165 @@
+//! Latency aggregation shared by the repeated-probe modes.
+
+use crate::probe::ProbeResult;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Latencies kept for the percentile: a watch that runs for days must not grow without bound.
+pub(crate) const WINDOW: usize = 1000;
+
+/// Running tally of probe outcomes and latencies. The minimum and average cover
+/// every run; the percentile covers the last `WINDOW` of them.
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub runs: u64,
+    pub successes: u64,
+    /// Runs with a latency: the failures counted by `record_failure` have none.
+    timed: u64,
+    min: Option<f64>,
+    sum: f64,
+    window: VecDeque<f64>,
+}
+
+/// Point-in-time view of a `Summary`, ready for display or JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct SummarySnapshot {
+    pub runs: u64,
+    pub successes: u64,
+    pub success_rate: f64,
+    pub latency_min_ms: Option<f64>,
+    pub latency_avg_ms: Option<f64>,
+    pub latency_p95_ms: Option<f64>,
+}
+
+impl Summary {
+    pub fn record(&mut self, success: bool, latency_ms: f64) {
+        self.runs += 1;
+        if success {
+            self.successes += 1;
+        }
+        self.timed += 1;
+        self.min = Some(self.min.map_or(latency_ms, |min| min.min(latency_ms)));
+        self.sum += latency_ms;
+        if self.window.len() == WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(latency_ms);
+    }
+
+    /// A run that failed before producing a result: it counts against the success
+    /// rate but has no latency to add.
+    pub fn record_failure(&mut self) {
+        self.runs += 1;
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        if self.runs == 0 {
+            return 0.0;
+        }
+        self.successes as f64 * 100.0 / self.runs as f64
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    pub fn avg(&self) -> Option<f64> {
+        (self.timed > 0).then(|| self.sum / self.timed as f64)
+    }
+
+    /// Nearest-rank percentile of the recent latencies, `p` in 0..=100.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        percentile(&self.window.iter().copied().collect::<Vec<_>>(), p)
+    }
+
+    pub fn snapshot(&self) -> SummarySnapshot {
+        SummarySnapshot {
+            runs: self.runs,
+            successes: self.successes,
+            success_rate: self.success_rate(),
+            latency_min_ms: self.min(),
+            latency_avg_ms: self.avg(),
+            latency_p95_ms: self.percentile(95.0),
+        }
+    }
+}
+
+/// Nearest-rank percentile of `values`, `p` in 0..=100.
+pub fn percentile(values: &[f64], p: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Distribution of one stage's latency over several samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyStats {
+    /// Samples in which the stage completed.
+    pub samples: usize,
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    /// Population standard deviation.
+    pub stddev_ms: f64,
+}
+
+impl LatencyStats {
+    pub fn from_samples(values: &[f64]) -> Option<LatencyStats> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        let avg = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - avg).powi(2)).sum::<f64>() / n;
+        Some(LatencyStats {
+            samples: values.len(),
+            min_ms: values.iter().copied().fold(f64::INFINITY, f64::min),
+            avg_ms: avg,
+            max_ms: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            p50_ms: percentile(values, 50.0)?,
+            p95_ms: percentile(values, 95.0)?,
+            p99_ms: percentile(values, 99.0)?,
+            stddev_ms: variance.sqrt(),
+        })
+    }
+}
+
+/// Aggregate of repeated probes of one target (`--count`).
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeStats {
+    pub count: usize,
+    /// Samples that did not succeed (see `ProbeResult::is_success`).
+    pub failures: usize,
+    /// Share of failed samples in percent, like ping's packet loss.
+    pub failure_rate: f64,
+    pub dns: Option<LatencyStats>,
+    pub tcp: Option<LatencyStats>,
+    pub tls: Option<LatencyStats>,
+    pub http: Option<LatencyStats>,
+    /// Whole-probe duration.
+    pub total: Option<LatencyStats>,
+}
+
+impl ProbeStats {
+    pub fn from_results(results: &[ProbeResult]) -> ProbeStats {
+        let stage = |latency: fn(&ProbeResult) -> Option<f64>| LatencyStats::from_samples(&results.iter().filter_map(latency).collect::<Vec<_>>());
+        let failures = results.iter().filter(|r| !r.is_success()).count();
+        ProbeStats {
+            count: results.len(),
+            failures,
+            failure_rate: if results.is_empty() { 0.0 } else { failures as f64 * 100.0 / results.len() as f64 },
+            dns: stage(|r| r.dns.latency_ms),
+            tcp: stage(|r| r.tcp.latency_ms),
+            tls: stage(|r| r.tls.latency_ms),
+            http: stage(|r| r.http.latency_ms),
+            total: stage(|r| Some(r.duration_ms)),
+        }
+    }
+}
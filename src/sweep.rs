@@ -0,0 +1,125 @@
+//! Subnet sweep (`netprobe sweep`): which hosts of a CIDR block answer on a few TCP ports.
+//!
+//! A host counts as alive when any port is open or actively refuses the
+//! connection; only silence on every port (or an ICMP unreachable) means down.
+
+use crate::probe::source::SourceBinding;
+use crate::probe::tcp::{self, PortResult};
+use crate::probe::{self, ProbeError, Starts};
+use ipnet::IpNet;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Largest block swept in one run (a /16 for IPv4).
+pub const MAX_HOSTS: usize = 65_536;
+
+#[derive(Debug, Clone)]
+pub struct SweepOptions {
+    pub ports: Vec<u16>,
+    /// Connect timeout per port.
+    pub timeout: Duration,
+    /// Hosts probed at the same time.
+    pub concurrency: usize,
+    /// New hosts started per second (see `probe::MIN_RATE` and `probe::MAX_RATE`);
+    /// `None` starts them as fast as `concurrency` allows.
+    pub rate: Option<f64>,
+}
+
+impl Default for SweepOptions {
+    fn default() -> Self {
+        SweepOptions { ports: vec![22, 80, 443], timeout: Duration::from_secs(1), concurrency: 64, rate: Some(100.0) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepResult {
+    pub network: String,
+    pub timestamp: String,
+    pub ports: Vec<u16>,
+    pub hosts_scanned: usize,
+    pub hosts_alive: usize,
+    pub duration_ms: f64,
+    /// Hosts started per second, from the first start to the last; 0 for a single host.
+    pub rate_per_s: f64,
+    /// Alive hosts only, in address order.
+    pub hosts: Vec<HostResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostResult {
+    pub ip: String,
+    pub open_ports: Vec<u16>,
+    /// Fastest connect among the open ports.
+    pub latency_ms: Option<f64>,
+    pub ports: Vec<PortResult>,
+}
+
+/// Every host address of `network` (`10.0.0.0/24`, or a bare IP).
+/// IPv4 blocks larger than a /31 skip the network and broadcast addresses.
+pub fn hosts(network: &str) -> Result<(IpNet, Vec<IpAddr>), ProbeError> {
+    let network = network.trim();
+    let net: IpNet = match network.parse::<IpNet>() {
+        Ok(net) => net,
+        Err(_) => network.parse::<IpAddr>().map(IpNet::from).map_err(|_| ProbeError::InvalidNetwork(format!("'{}' is not a CIDR block or IP address", network)))?,
+    };
+    let host_bits = (net.max_prefix_len() - net.prefix_len()) as u32;
+    if host_bits > MAX_HOSTS.trailing_zeros() {
+        return Err(ProbeError::InvalidNetwork(format!("{} has too many addresses (at most {} per sweep)", net, MAX_HOSTS)));
+    }
+    Ok((net, net.hosts().collect()))
+}
+
+/// Probe every host of `network` on `options.ports`.
+pub async fn sweep(network: &str, options: &SweepOptions) -> Result<SweepResult, ProbeError> {
+    let started = Instant::now();
+    let timestamp = chrono::Local::now().to_rfc3339();
+    let (net, addresses) = hosts(network)?;
+
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut pacing = options.rate.and_then(probe::pacer);
+    let mut starts = Starts::default();
+    let mut handles = Vec::with_capacity(addresses.len());
+    for &ip in &addresses {
+        if let Some(pacing) = pacing.as_mut() {
+            pacing.tick().await;
+        }
+        let permit = semaphore.clone().acquire_owned().await.expect("semaphore closed");
+        starts.record(Instant::now());
+        let ports = options.ports.clone();
+        let timeout = options.timeout;
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            (ip, tcp::scan_ports(ip, &ports, timeout, &SourceBinding::default()).await)
+        }));
+    }
+
+    let mut hosts = Vec::new();
+    for handle in handles {
+        let Ok((ip, ports)) = handle.await else { continue };
+        if ports.iter().all(|port| port.status == "filtered") {
+            continue;
+        }
+        let open: Vec<&PortResult> = ports.iter().filter(|port| port.status == "open").collect();
+        hosts.push(HostResult {
+            ip: ip.to_string(),
+            open_ports: open.iter().map(|port| port.port).collect(),
+            latency_ms: open.iter().filter_map(|port| port.latency_ms).reduce(f64::min),
+            ports,
+        });
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    Ok(SweepResult {
+        network: net.to_string(),
+        timestamp,
+        ports: options.ports.clone(),
+        hosts_scanned: addresses.len(),
+        hosts_alive: hosts.len(),
+        duration_ms: elapsed * 1000.0,
+        rate_per_s: starts.rate().unwrap_or(0.0),
+        hosts,
+    })
+}
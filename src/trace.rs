@@ -0,0 +1,257 @@
+//! TTL-incrementing traceroute (`netprobe trace`).
+//!
+//! Probes are sent with an increasing TTL (hop limit) and the ICMP
+//! "time exceeded" replies from intermediate routers are collected on a raw
+//! ICMP socket, so this requires the same privileges as `--ping`.
+
+use crate::probe::{self, dns, icmp, ProbeError};
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// First destination port used by UDP probes, as in classic traceroute.
+const BASE_UDP_PORT: u16 = 33434;
+
+// ICMP message types signalling "expired in transit" and "unreachable".
+const TIME_EXCEEDED_V4: u8 = 11;
+const DEST_UNREACHABLE_V4: u8 = 3;
+const TIME_EXCEEDED_V6: u8 = 3;
+const DEST_UNREACHABLE_V6: u8 = 1;
+
+/// Which packets are sent towards the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceProtocol {
+    Icmp,
+    Udp,
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceOptions {
+    pub protocol: TraceProtocol,
+    pub max_hops: u8,
+    /// Probes sent per hop.
+    pub queries: u8,
+    /// How long to wait for each probe's reply.
+    pub timeout: Duration,
+    /// Look up PTR names for every hop.
+    pub resolve_names: bool,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions { protocol: TraceProtocol::Icmp, max_hops: 30, queries: 3, timeout: Duration::from_secs(2), resolve_names: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceResult {
+    pub target: String,
+    pub timestamp: String,
+    pub destination: String,
+    pub protocol: TraceProtocol,
+    pub reached: bool,
+    pub hops: Vec<Hop>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Hop {
+    pub ttl: u8,
+    /// First address that answered at this distance.
+    pub ip: Option<String>,
+    pub hostname: Option<String>,
+    /// One entry per probe; `None` means the probe timed out.
+    pub rtts_ms: Vec<Option<f64>>,
+}
+
+/// What an ICMP message tells us about one of our probes.
+pub(crate) enum Reply {
+    /// An intermediate router dropped the probe (TTL exceeded).
+    Hop(IpAddr),
+    /// The destination itself answered (echo reply or port unreachable).
+    Destination(IpAddr),
+}
+
+/// Resolve `target` and walk the path towards it hop by hop.
+pub fn trace(target: &str, options: &TraceOptions) -> Result<TraceResult, ProbeError> {
+    let url = probe::parse_target(target)?;
+    let host = url.host_str().unwrap_or("").to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let mut result = TraceResult {
+        target: target.to_string(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+        destination: String::new(),
+        protocol: options.protocol,
+        reached: false,
+        hops: Vec::new(),
+    };
+
+    let (dns_result, resolved) = dns::resolve_system_blocking(&host, port);
+    let dest = match resolved.first() {
+        Some(addr) => addr.ip(),
+        None => return Err(ProbeError::Trace(dns_result.error.unwrap_or_else(|| "DNS resolution failed".to_string()))),
+    };
+    result.destination = dest.to_string();
+
+    let receiver = open_receiver(dest).map_err(|e| ProbeError::Trace(e.to_string()))?;
+    let sender = open_sender(dest, options.protocol).map_err(|e| ProbeError::Trace(e.to_string()))?;
+    let ident = std::process::id() as u16;
+
+    let mut seq: u16 = 0;
+    for ttl in 1..=options.max_hops {
+        let mut hop = Hop { ttl, ip: None, hostname: None, rtts_ms: Vec::new() };
+        let socket = sender.as_ref().unwrap_or(&receiver);
+        set_hop_limit(socket, dest, ttl).map_err(|e| ProbeError::Trace(e.to_string()))?;
+
+        for _ in 0..options.queries {
+            seq = seq.wrapping_add(1);
+            let sent_at = Instant::now();
+            if send_probe(socket, dest, options.protocol, ident, seq).is_err() {
+                hop.rtts_ms.push(None);
+                continue;
+            }
+
+            match wait_for_reply(&receiver, dest, options, ident, seq, sent_at) {
+                Some(reply) => {
+                    let rtt = sent_at.elapsed().as_secs_f64() * 1000.0;
+                    let ip = match reply {
+                        Reply::Hop(ip) => ip,
+                        Reply::Destination(ip) => {
+                            result.reached = true;
+                            ip
+                        },
+                    };
+                    hop.ip.get_or_insert_with(|| ip.to_string());
+                    hop.rtts_ms.push(Some(rtt));
+                },
+                None => hop.rtts_ms.push(None),
+            }
+        }
+
+        if options.resolve_names {
+            hop.hostname = hop.ip.as_ref().and_then(|ip| ip.parse().ok()).and_then(dns::reverse_lookup);
+        }
+        result.hops.push(hop);
+        if result.reached {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Raw ICMP socket on which routers' replies arrive.
+pub(crate) fn open_receiver(dest: IpAddr) -> io::Result<Socket> {
+    let (domain, protocol) = if dest.is_ipv4() { (Domain::IPV4, Protocol::ICMPV4) } else { (Domain::IPV6, Protocol::ICMPV6) };
+    Socket::new(domain, Type::RAW, Some(protocol)).map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => io::Error::new(e.kind(), icmp::PRIVILEGE_HINT),
+        _ => e,
+    })
+}
+
+/// Socket the UDP probes go out on; ICMP echoes go out on the receiving socket itself.
+pub(crate) fn open_sender(dest: IpAddr, protocol: TraceProtocol) -> io::Result<Option<Socket>> {
+    if protocol == TraceProtocol::Icmp {
+        return Ok(None);
+    }
+    let domain = if dest.is_ipv4() { Domain::IPV4 } else { Domain::IPV6 };
+    let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+    let any: SocketAddr = if dest.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+    socket.bind(&any.into())?;
+    Ok(Some(socket))
+}
+
+/// Send probe `seq`: an echo request, or a datagram to the port that identifies it.
+pub(crate) fn send_probe(socket: &Socket, dest: IpAddr, protocol: TraceProtocol, ident: u16, seq: u16) -> io::Result<usize> {
+    match protocol {
+        TraceProtocol::Icmp => socket.send_to(&icmp::echo_request(dest, ident, seq, 32), &SocketAddr::new(dest, 0).into()),
+        TraceProtocol::Udp => socket.send_to(&[0u8; 32], &SocketAddr::new(dest, BASE_UDP_PORT.wrapping_add(seq)).into()),
+    }
+}
+
+pub(crate) fn set_hop_limit(socket: &Socket, dest: IpAddr, ttl: u8) -> io::Result<()> {
+    if dest.is_ipv4() {
+        socket.set_ttl(ttl as u32)
+    } else {
+        socket.set_unicast_hops_v6(ttl as u32)
+    }
+}
+
+/// Read ICMP messages until one matches probe `seq` or the timeout expires.
+fn wait_for_reply(receiver: &Socket, dest: IpAddr, options: &TraceOptions, ident: u16, seq: u16, sent_at: Instant) -> Option<Reply> {
+    let mut buf = [MaybeUninit::<u8>::uninit(); 1500];
+    loop {
+        let remaining = options.timeout.saturating_sub(sent_at.elapsed());
+        if remaining.is_zero() {
+            return None;
+        }
+        receiver.set_read_timeout(Some(remaining)).ok()?;
+        let (n, from) = match receiver.recv_from(&mut buf) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => continue,
+            Err(_) => return None,
+        };
+        // SAFETY: recv_from initialized the first `n` bytes.
+        let packet: Vec<u8> = buf[..n].iter().map(|b| unsafe { b.assume_init() }).collect();
+        let Some(from) = from.as_socket().map(|a| a.ip()) else { continue };
+        match answer(&packet, dest, from, options.protocol, ident) {
+            Some((answered, reply)) if answered == seq => return Some(reply),
+            _ => continue,
+        }
+    }
+}
+
+/// Decide whether `packet` (as read from the raw socket) answers one of our probes, and which.
+pub(crate) fn answer(packet: &[u8], dest: IpAddr, from: IpAddr, protocol: TraceProtocol, ident: u16) -> Option<(u16, Reply)> {
+    let v4 = dest.is_ipv4();
+    let message = icmp::icmp_payload(packet, v4)?;
+    let kind = *message.first()?;
+
+    let (echo_reply, time_exceeded, unreachable) = if v4 {
+        (icmp::ECHO_REPLY_V4, TIME_EXCEEDED_V4, DEST_UNREACHABLE_V4)
+    } else {
+        (icmp::ECHO_REPLY_V6, TIME_EXCEEDED_V6, DEST_UNREACHABLE_V6)
+    };
+
+    if kind == echo_reply {
+        let matches = protocol == TraceProtocol::Icmp && message.len() >= 8 && u16::from_be_bytes([message[4], message[5]]) == ident;
+        return matches.then(|| (u16::from_be_bytes([message[6], message[7]]), Reply::Destination(from)));
+    }
+    if kind != time_exceeded && kind != unreachable {
+        return None;
+    }
+
+    // Error messages quote the offending packet after the 8-byte ICMP header.
+    let quoted = message.get(8..)?;
+    let (inner_proto, inner) = if v4 {
+        let ihl = (*quoted.first()? & 0x0f) as usize * 4;
+        (*quoted.get(9)?, quoted.get(ihl..)?)
+    } else {
+        (*quoted.get(6)?, quoted.get(40..)?)
+    };
+    if inner.len() < 8 {
+        return None;
+    }
+
+    let seq = match protocol {
+        TraceProtocol::Icmp => {
+            let icmp_proto = if v4 { 1 } else { 58 };
+            if inner_proto != icmp_proto || u16::from_be_bytes([inner[4], inner[5]]) != ident {
+                return None;
+            }
+            u16::from_be_bytes([inner[6], inner[7]])
+        },
+        TraceProtocol::Udp if inner_proto == 17 => u16::from_be_bytes([inner[2], inner[3]]).wrapping_sub(BASE_UDP_PORT),
+        TraceProtocol::Udp => return None,
+    };
+
+    if kind == time_exceeded {
+        Some((seq, Reply::Hop(from)))
+    } else {
+        Some((seq, Reply::Destination(from)))
+    }
+}
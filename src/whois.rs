@@ -0,0 +1,314 @@
+//! Domain registration lookup (`netprobe whois`): registrar, creation and
+//! expiry dates, nameservers and statuses of a domain.
+//!
+//! RDAP (RFC 9083) comes first: its server is found in the IANA bootstrap file
+//! (RFC 9224) and its answer is structured JSON. Registries without RDAP, or
+//! whose RDAP server fails, are asked over whois (RFC 3912, port 43), the server
+//! named by `whois.iana.org`; its free-form text is read for the usual keys.
+
+use crate::probe::proxy::ProxySettings;
+use crate::probe::{http, tls};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Registry list of the RDAP servers of each TLD.
+pub const RDAP_BOOTSTRAP: &str = "https://data.iana.org/rdap/dns.json";
+/// Whois server that refers a TLD to its registry's.
+pub const IANA_WHOIS: &str = "whois.iana.org";
+const WHOIS_PORT: u16 = 43;
+/// Largest whois reply read.
+const MAX_WHOIS_REPLY: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct WhoisOptions {
+    /// Timeout of each HTTP request and whois exchange.
+    pub timeout: Duration,
+    /// RDAP base URL to ask instead of the one from the bootstrap file.
+    pub rdap_url: Option<String>,
+    /// Whois server (HOST[:PORT]) to ask instead of the one IANA refers to; skips RDAP.
+    pub whois_server: Option<String>,
+    /// Warn when the domain expires within this many days.
+    pub expiry_warn_days: Option<i64>,
+    /// Proxy of the RDAP requests (`--proxy`, `HTTPS_PROXY` & co).
+    pub proxy: ProxySettings,
+}
+
+impl Default for WhoisOptions {
+    fn default() -> Self {
+        WhoisOptions { timeout: Duration::from_secs(10), rdap_url: None, whois_server: None, expiry_warn_days: None, proxy: ProxySettings::default() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WhoisResult {
+    pub domain: String,
+    pub timestamp: String,
+    pub duration_ms: f64,
+    pub status: String, // "ok" | "warning" | "error"
+    /// "rdap" | "whois"
+    pub source: Option<String>,
+    /// RDAP URL or whois server that answered.
+    pub server: Option<String>,
+    pub registrar: Option<String>,
+    /// RFC 3339, or as written by the registry when it is not a recognized date.
+    pub created: Option<String>,
+    pub expires: Option<String>,
+    pub days_until_expiry: Option<i64>,
+    pub nameservers: Vec<String>,
+    /// EPP statuses: "client transfer prohibited" over RDAP, "clientTransferProhibited" over whois.
+    pub statuses: Vec<String>,
+    /// Why RDAP was not used, when whois answered instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rdap_error: Option<String>,
+    /// The domain expires within `--expiry-warn-days`.
+    pub warning: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Registration data as read from either protocol.
+#[derive(Default)]
+struct Registration {
+    registrar: Option<String>,
+    created: Option<String>,
+    expires: Option<String>,
+    nameservers: Vec<String>,
+    statuses: Vec<String>,
+}
+
+/// Look `domain` up over RDAP, then whois.
+pub async fn lookup(domain: &str, options: &WhoisOptions) -> WhoisResult {
+    let started = Instant::now();
+    let domain = domain_name(domain);
+    let mut result = WhoisResult {
+        domain: domain.clone(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+        duration_ms: 0.0,
+        status: "error".to_string(),
+        source: None,
+        server: None,
+        registrar: None,
+        created: None,
+        expires: None,
+        days_until_expiry: None,
+        nameservers: Vec::new(),
+        statuses: Vec::new(),
+        rdap_error: None,
+        warning: None,
+        error: None,
+    };
+
+    let rdap = match &options.whois_server {
+        Some(_) => Err(RdapError::Skipped),
+        None => rdap(&domain, options).await,
+    };
+    let answer = match rdap {
+        Ok((url, registration)) => Ok(("rdap", url, registration)),
+        // The registry answered that the domain does not exist: whois would only say the same.
+        Err(RdapError::NotFound(url)) => {
+            result.source = Some("rdap".to_string());
+            result.server = Some(url);
+            Err("Domain not found: it is not registered".to_string())
+        },
+        Err(e) => {
+            if let RdapError::Failed(reason) = e {
+                result.rdap_error = Some(reason);
+            }
+            whois(&domain, options).await.map(|(server, registration)| ("whois", server, registration))
+        },
+    };
+
+    match answer {
+        Ok((source, server, registration)) => {
+            result.status = "ok".to_string();
+            result.source = Some(source.to_string());
+            result.server = Some(server);
+            result.registrar = registration.registrar;
+            let expires = registration.expires.as_deref().and_then(parse_date);
+            result.created = registration.created.map(|raw| parse_date(&raw).map(|date| date.to_rfc3339()).unwrap_or(raw));
+            result.expires = registration.expires.map(|raw| parse_date(&raw).map(|date| date.to_rfc3339()).unwrap_or(raw));
+            result.days_until_expiry = expires.map(|date| (date - Utc::now()).num_days());
+            result.nameservers = registration.nameservers;
+            result.statuses = registration.statuses;
+        },
+        // Registries only know registered domains, not the names under them.
+        Err(e) if e.starts_with("Domain not found") && domain.matches('.').count() > 1 => {
+            let registered = domain.splitn(domain.matches('.').count(), '.').last().unwrap_or(&domain);
+            result.error = Some(format!("{} (try {})", e, registered));
+        },
+        Err(e) => result.error = Some(e),
+    }
+
+    if let (Some(threshold), Some(days)) = (options.expiry_warn_days, result.days_until_expiry) {
+        if days < threshold {
+            result.warning = Some(if days < 0 {
+                format!("Domain expired {} days ago", -days)
+            } else {
+                format!("Domain expires in {} days (threshold: {} days)", days, threshold)
+            });
+            if result.status == "ok" {
+                result.status = "warning".to_string();
+            }
+        }
+    }
+    result.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    result
+}
+
+/// "example.com" from "Example.COM.", "https://www.example.com/path"...
+fn domain_name(input: &str) -> String {
+    let input = input.trim();
+    let host = match url::Url::parse(input) {
+        Ok(url) if input.contains("://") => url.host_str().unwrap_or(input).to_string(),
+        _ => input.to_string(),
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// --- RDAP ---
+
+enum RdapError {
+    /// `--whois-server` asked for whois only.
+    Skipped,
+    /// The server answered 404 for the domain.
+    NotFound(String),
+    Failed(String),
+}
+
+async fn rdap(domain: &str, options: &WhoisOptions) -> Result<(String, Registration), RdapError> {
+    let tls = rustls::ClientConfig::builder().with_safe_defaults().with_root_certificates(tls::root_store()).with_no_client_auth();
+    let client = http::side_client(&options.proxy, tls, options.timeout);
+    let base = match &options.rdap_url {
+        Some(url) => url.clone(),
+        None => rdap_base(&client, domain).await.map_err(RdapError::Failed)?,
+    };
+    let url = format!("{}/domain/{}", base.trim_end_matches('/'), domain);
+    let response = client.get(&url).header(reqwest::header::ACCEPT, "application/rdap+json").send().await.map_err(|e| RdapError::Failed(format!("{}: {}", url, e.without_url())))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(RdapError::NotFound(url));
+    }
+    if !response.status().is_success() {
+        return Err(RdapError::Failed(format!("{}: HTTP {}", url, response.status())));
+    }
+    let body: Value = response.json().await.map_err(|e| RdapError::Failed(format!("{}: {}", url, e.without_url())))?;
+    Ok((url, rdap_registration(&body)))
+}
+
+/// Base URL of the RDAP server for the TLD of `domain`, from the bootstrap file.
+async fn rdap_base(client: &reqwest::Client, domain: &str) -> Result<String, String> {
+    let bootstrap: Value = match client.get(RDAP_BOOTSTRAP).send().await {
+        Ok(response) if response.status().is_success() => response.json().await.map_err(|e| format!("RDAP bootstrap: {}", e.without_url()))?,
+        Ok(response) => return Err(format!("RDAP bootstrap: HTTP {}", response.status())),
+        Err(e) => return Err(format!("RDAP bootstrap: {}", e.without_url())),
+    };
+    let tld = domain.rsplit('.').next().unwrap_or(domain);
+    let services = bootstrap["services"].as_array().map(Vec::as_slice).unwrap_or_default();
+    // Each service is [[TLDs...], [URLs...]]; HTTPS URLs come first by convention, but pick one explicitly.
+    let urls = services
+        .iter()
+        .find(|service| service[0].as_array().is_some_and(|tlds| tlds.iter().any(|entry| entry.as_str().is_some_and(|entry| entry.eq_ignore_ascii_case(tld)))))
+        .and_then(|service| service[1].as_array())
+        .ok_or_else(|| format!("No RDAP server for .{}", tld))?;
+    let urls: Vec<&str> = urls.iter().filter_map(Value::as_str).collect();
+    urls.iter().find(|url| url.starts_with("https://")).or(urls.first()).map(|url| url.to_string()).ok_or_else(|| format!("No RDAP server for .{}", tld))
+}
+
+fn rdap_registration(body: &Value) -> Registration {
+    let event = |action: &str| {
+        body["events"].as_array()?.iter().find(|event| event["eventAction"] == action).and_then(|event| event["eventDate"].as_str()).map(str::to_string)
+    };
+    let registrar = body["entities"].as_array().and_then(|entities| {
+        let entity = entities.iter().find(|entity| entity["roles"].as_array().is_some_and(|roles| roles.iter().any(|role| role == "registrar")))?;
+        // The name is the "fn" property of the jCard (RFC 7095): ["vcard", [["fn", {}, "text", NAME], ...]].
+        let name = entity["vcardArray"][1].as_array().and_then(|properties| properties.iter().find(|property| property[0] == "fn")).and_then(|property| property[3].as_str());
+        name.or_else(|| entity["handle"].as_str()).map(str::to_string)
+    });
+    Registration {
+        registrar,
+        created: event("registration"),
+        expires: event("expiration"),
+        nameservers: body["nameservers"].as_array().map(|servers| servers.iter().filter_map(|server| server["ldhName"].as_str()).map(|name| name.trim_end_matches('.').to_ascii_lowercase()).collect()).unwrap_or_default(),
+        statuses: body["status"].as_array().map(|statuses| statuses.iter().filter_map(Value::as_str).map(str::to_string).collect()).unwrap_or_default(),
+    }
+}
+
+// --- whois ---
+
+async fn whois(domain: &str, options: &WhoisOptions) -> Result<(String, Registration), String> {
+    let server = match &options.whois_server {
+        Some(server) => server.clone(),
+        None => {
+            let tld = domain.rsplit('.').next().unwrap_or(domain);
+            let referral = query(IANA_WHOIS, tld, options.timeout).await?;
+            let refer = field(&referral, &["refer"]).next();
+            refer.ok_or_else(|| format!("{} knows no whois server for .{}", IANA_WHOIS, tld))?
+        },
+    };
+    let reply = query(&server, domain, options.timeout).await?;
+    let registration = Registration {
+        registrar: field(&reply, &["registrar", "sponsoring registrar", "registrar name"]).next(),
+        created: field(&reply, &["creation date", "created", "created on", "registered on", "registration time", "registered"]).next(),
+        expires: field(&reply, &["registry expiry date", "registrar registration expiration date", "expiration date", "expiry date", "expires", "expires on", "paid-till", "expiration time"]).next(),
+        nameservers: dedup(field(&reply, &["name server", "nserver", "nameserver", "nameservers"]).filter_map(|server| server.split_whitespace().next().map(|name| name.trim_end_matches('.').to_ascii_lowercase()))),
+        // "clientTransferProhibited https://icann.org/epp#clientTransferProhibited": the code without its link.
+        statuses: dedup(field(&reply, &["domain status", "status"]).filter_map(|status| status.split_whitespace().next().map(str::to_string))),
+    };
+    if registration.registrar.is_none() && registration.expires.is_none() && registration.nameservers.is_empty() {
+        // Each registry words "no such domain" its own way.
+        let lower = reply.to_ascii_lowercase();
+        return Err(match ["no match for", "not found", "no data found", "no entries found", "status: free", "status: available"].iter().any(|marker| lower.contains(marker)) {
+            true => format!("Domain not found: {} has no record of it", server),
+            false => format!("{} answered without registration data", server),
+        });
+    }
+    Ok((server, registration))
+}
+
+/// Send `question` to the whois server `server` (HOST[:PORT]) and read the reply until it closes.
+async fn query(server: &str, question: &str, timeout: Duration) -> Result<String, String> {
+    let address = if server.contains(':') { server.to_string() } else { format!("{}:{}", server, WHOIS_PORT) };
+    let exchange = async {
+        let mut stream = TcpStream::connect(&address).await.map_err(|e| format!("{}: {}", server, e))?;
+        stream.write_all(format!("{}\r\n", question).as_bytes()).await.map_err(|e| format!("{}: {}", server, e))?;
+        let mut reply = Vec::new();
+        stream.take(MAX_WHOIS_REPLY).read_to_end(&mut reply).await.map_err(|e| format!("{}: {}", server, e))?;
+        Ok(String::from_utf8_lossy(&reply).into_owned())
+    };
+    tokio::time::timeout(timeout, exchange).await.map_err(|_| format!("{}: no reply within {:.1}s", server, timeout.as_secs_f64()))?
+}
+
+/// Values of the `Key: value` lines whose key is one of `keys` (case-insensitive), in order.
+fn field<'a>(reply: &'a str, keys: &'a [&str]) -> impl Iterator<Item = String> + 'a {
+    reply.lines().filter_map(move |line| {
+        let (key, value) = line.split_once(':')?;
+        let value = value.trim();
+        (keys.iter().any(|wanted| key.trim().eq_ignore_ascii_case(wanted)) && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+fn dedup(values: impl Iterator<Item = String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for value in values {
+        if !unique.contains(&value) {
+            unique.push(value);
+        }
+    }
+    unique
+}
+
+/// A registry date: RFC 3339 from RDAP; whois servers write many other formats.
+fn parse_date(raw: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(raw) {
+        return Some(date.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.fZ", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(raw.trim_end_matches(" UTC"), format) {
+            return Some(date.and_utc());
+        }
+    }
+    let day = raw.split_whitespace().next()?;
+    ["%Y-%m-%d", "%Y.%m.%d", "%Y/%m/%d", "%d-%b-%Y", "%d.%m.%Y"].iter().find_map(|format| NaiveDate::parse_from_str(day, format).ok()).and_then(|date| date.and_hms_opt(0, 0, 0)).map(|date| date.and_utc())
+}
@@ -0,0 +1,568 @@
+//! Minimal DER / X.509 reader.
+//!
+//! Only the fields NetProbe reports are decoded; anything else is skipped.
+//! Parsing is lenient: malformed input yields `None` rather than a panic.
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// --- DER primitives ---
+
+pub(crate) const TAG_BOOLEAN: u8 = 0x01;
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_BIT_STRING: u8 = 0x03;
+pub(crate) const TAG_OCTET_STRING: u8 = 0x04;
+pub(crate) const TAG_NULL: u8 = 0x05;
+pub(crate) const TAG_OID: u8 = 0x06;
+pub(crate) const TAG_ENUMERATED: u8 = 0x0a;
+pub(crate) const TAG_UTC_TIME: u8 = 0x17;
+pub(crate) const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+
+/// One decoded TLV element.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Tlv<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+    /// The whole element, header included (what signatures and hashes cover).
+    pub raw: &'a [u8],
+}
+
+/// Sequential reader over concatenated DER elements.
+#[derive(Debug, Clone)]
+pub(crate) struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Der { data }
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    pub fn read(&mut self) -> Option<Tlv<'a>> {
+        let tag = *self.data.first()?;
+        let first_len = *self.data.get(1)?;
+        let (len, header) = if first_len & 0x80 == 0 {
+            (first_len as usize, 2)
+        } else {
+            let n = (first_len & 0x7f) as usize;
+            if n == 0 || n > 4 {
+                return None;
+            }
+            let bytes = self.data.get(2..2 + n)?;
+            (bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize), 2 + n)
+        };
+        let end = header.checked_add(len)?;
+        let content = self.data.get(header..end)?;
+        let raw = &self.data[..end];
+        self.data = &self.data[end..];
+        Some(Tlv { tag, content, raw })
+    }
+
+    /// Read the next element, requiring a specific tag.
+    pub fn expect(&mut self, tag: u8) -> Option<Tlv<'a>> {
+        let tlv = self.read()?;
+        (tlv.tag == tag).then_some(tlv)
+    }
+
+    /// Read the next element only if it carries `tag`.
+    pub fn optional(&mut self, tag: u8) -> Option<Tlv<'a>> {
+        if self.peek_tag() == Some(tag) {
+            self.read()
+        } else {
+            None
+        }
+    }
+}
+
+/// Encode one DER element.
+pub(crate) fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend_from_slice(&bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Contents of a BIT STRING without its unused-bits byte.
+pub(crate) fn bit_string<'a>(tlv: &Tlv<'a>) -> &'a [u8] {
+    tlv.content.get(1..).unwrap_or_default()
+}
+
+/// Decode an OBJECT IDENTIFIER body into dotted notation.
+pub(crate) fn oid_to_string(content: &[u8]) -> String {
+    let mut parts: Vec<u64> = Vec::new();
+    let mut value: u64 = 0;
+    for b in content {
+        value = (value << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = (value / 40).min(2);
+                parts.push(first);
+                parts.push(value - first * 40);
+            } else {
+                parts.push(value);
+            }
+            value = 0;
+        }
+    }
+    parts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(".")
+}
+
+/// Parse a UTCTime or GeneralizedTime in the `Z` form mandated by RFC 5280.
+pub(crate) fn parse_time(tlv: &Tlv) -> Option<DateTime<Utc>> {
+    let text = std::str::from_utf8(tlv.content).ok()?.trim_end_matches('Z');
+    let (year, rest) = match tlv.tag {
+        TAG_UTC_TIME => {
+            let yy: i32 = text.get(0..2)?.parse().ok()?;
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, text.get(2..)?)
+        },
+        TAG_GENERALIZED_TIME => (text.get(0..4)?.parse().ok()?, text.get(4..)?),
+        _ => return None,
+    };
+    let field = |i: usize| -> Option<u32> { rest.get(i..i + 2)?.parse().ok() };
+    let date = NaiveDate::from_ymd_opt(year, field(0)?, field(2)?)?;
+    let datetime = date.and_hms_opt(field(4)?, field(6)?, field(8).unwrap_or(0))?;
+    Some(Utc.from_utc_datetime(&datetime))
+}
+
+/// Decode the string types commonly found in certificate names.
+fn decode_string(tlv: &Tlv) -> String {
+    match tlv.tag {
+        // BMPString: UTF-16BE
+        0x1e => {
+            let units: Vec<u16> = tlv.content.chunks(2).filter(|c| c.len() == 2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        },
+        _ => String::from_utf8_lossy(tlv.content).into_owned(),
+    }
+}
+
+fn attribute_name(oid: &str) -> String {
+    match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.5" => "serialNumber",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "1.2.840.113549.1.9.1" => "emailAddress",
+        other => return other.to_string(),
+    }
+    .to_string()
+}
+
+/// Render a distinguished name as `CN=example.com, O=Example`.
+pub(crate) fn name_to_string(name: &[u8]) -> String {
+    let mut parts = Vec::new();
+    let mut rdns = Der::new(name);
+    while let Some(set) = rdns.read() {
+        let mut attrs = Der::new(set.content);
+        while let Some(attr) = attrs.expect(TAG_SEQUENCE) {
+            let mut fields = Der::new(attr.content);
+            if let (Some(oid), Some(value)) = (fields.expect(TAG_OID), fields.read()) {
+                parts.push(format!("{}={}", attribute_name(&oid_to_string(oid.content)), decode_string(&value)));
+            }
+        }
+    }
+    parts.join(", ")
+}
+
+// --- Certificate ---
+
+/// The subset of an X.509 certificate NetProbe cares about.
+#[derive(Debug, Clone)]
+pub(crate) struct Certificate<'a> {
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// Signature algorithm of the issuer, e.g. "sha256WithRSAEncryption".
+    pub signature_algorithm: String,
+    /// Subject public key, e.g. "RSA 2048", "ECDSA P-256".
+    pub key_type: String,
+    pub extensions: Vec<Extension<'a>>,
+    /// Serial number, as the INTEGER's bytes.
+    pub serial: &'a [u8],
+    /// The encoded issuer name.
+    pub raw_issuer: &'a [u8],
+    /// The subjectPublicKey bits (RSAPublicKey, or the EC point).
+    pub public_key: &'a [u8],
+    /// The whole encoded SubjectPublicKeyInfo.
+    pub raw_public_key_info: &'a [u8],
+    /// The signed part of the certificate and the issuer's signature over it.
+    pub raw_tbs: &'a [u8],
+    pub signature: &'a [u8],
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Extension<'a> {
+    pub oid: String,
+    pub value: &'a [u8],
+}
+
+pub(crate) const OID_SUBJECT_ALT_NAME: &str = "2.5.29.17";
+pub(crate) const OID_AUTHORITY_INFO_ACCESS: &str = "1.3.6.1.5.5.7.1.1";
+pub(crate) const OID_CRL_DISTRIBUTION_POINTS: &str = "2.5.29.31";
+pub(crate) const OID_EXTENDED_KEY_USAGE: &str = "2.5.29.37";
+/// Signed certificate timestamps embedded by the CA (RFC 6962, 3.3).
+pub(crate) const OID_SCT_LIST: &str = "1.3.6.1.4.1.11129.2.4.2";
+/// accessMethod of an OCSP responder in the authorityInfoAccess extension.
+pub(crate) const OID_AD_OCSP: &str = "1.3.6.1.5.5.7.48.1";
+
+impl<'a> Certificate<'a> {
+    pub fn parse(der: &'a [u8]) -> Option<Self> {
+        let cert = Der::new(der).expect(TAG_SEQUENCE)?;
+        let mut cert_fields = Der::new(cert.content);
+        let tbs = cert_fields.expect(TAG_SEQUENCE)?;
+
+        let mut tbs_fields = Der::new(tbs.content);
+        tbs_fields.optional(0xa0); // version
+        let serial = tbs_fields.expect(TAG_INTEGER)?;
+        let signature = tbs_fields.expect(TAG_SEQUENCE)?;
+        let issuer = tbs_fields.expect(TAG_SEQUENCE)?;
+        let validity = tbs_fields.expect(TAG_SEQUENCE)?;
+        let subject = tbs_fields.expect(TAG_SEQUENCE)?;
+        let public_key = tbs_fields.expect(TAG_SEQUENCE)?;
+        tbs_fields.optional(0x81); // issuerUniqueID
+        tbs_fields.optional(0x82); // subjectUniqueID
+
+        let mut extensions = Vec::new();
+        if let Some(wrapper) = tbs_fields.optional(0xa3) {
+            let list = Der::new(wrapper.content).expect(TAG_SEQUENCE)?;
+            let mut items = Der::new(list.content);
+            while let Some(ext) = items.expect(TAG_SEQUENCE) {
+                let mut ext_fields = Der::new(ext.content);
+                let oid = ext_fields.expect(TAG_OID)?;
+                ext_fields.optional(TAG_BOOLEAN); // critical
+                let value = ext_fields.expect(TAG_OCTET_STRING)?;
+                extensions.push(Extension { oid: oid_to_string(oid.content), value: value.content });
+            }
+        }
+
+        cert_fields.expect(TAG_SEQUENCE)?; // signatureAlgorithm, same as in the TBS
+        let signature_value = cert_fields.expect(TAG_BIT_STRING)?;
+
+        let mut times = Der::new(validity.content);
+        let not_before = parse_time(&times.read()?)?;
+        let not_after = parse_time(&times.read()?)?;
+
+        Some(Certificate {
+            subject: name_to_string(subject.content),
+            issuer: name_to_string(issuer.content),
+            not_before,
+            not_after,
+            signature_algorithm: signature_algorithm_name(&oid_to_string(Der::new(signature.content).expect(TAG_OID)?.content)),
+            key_type: key_type(public_key.content),
+            extensions,
+            serial: serial.content,
+            raw_issuer: issuer.raw,
+            public_key: public_key_bits(public_key.content)?,
+            raw_public_key_info: public_key.raw,
+            raw_tbs: tbs.raw,
+            signature: bit_string(&signature_value),
+        })
+    }
+
+    pub fn extension(&self, oid: &str) -> Option<&'a [u8]> {
+        self.extensions.iter().find(|e| e.oid == oid).map(|e| e.value)
+    }
+
+    /// Whether `issuer`'s key produced this certificate's signature.
+    pub fn is_signed_by(&self, issuer: &Certificate) -> bool {
+        verify_signature(&issuer.key_type, issuer.public_key, &self.signature_algorithm, self.raw_tbs, self.signature)
+    }
+
+    /// The signed part of the certificate re-encoded without the extension `oid`
+    /// (how a precertificate's TBS is recovered from the final certificate).
+    pub fn tbs_without_extension(&self, oid: &str) -> Option<Vec<u8>> {
+        let tbs = Der::new(self.raw_tbs).expect(TAG_SEQUENCE)?;
+        let mut fields = Der::new(tbs.content);
+        let mut content = Vec::new();
+        while let Some(field) = fields.read() {
+            if field.tag != 0xa3 {
+                content.extend_from_slice(field.raw);
+                continue;
+            }
+            let list = Der::new(field.content).expect(TAG_SEQUENCE)?;
+            let mut items = Der::new(list.content);
+            let mut kept = Vec::new();
+            while let Some(ext) = items.expect(TAG_SEQUENCE) {
+                if oid_to_string(Der::new(ext.content).expect(TAG_OID)?.content) != oid {
+                    kept.extend_from_slice(ext.raw);
+                }
+            }
+            content.extend(encode(0xa3, &encode(TAG_SEQUENCE, &kept)));
+        }
+        Some(encode(TAG_SEQUENCE, &content))
+    }
+
+    /// OCSP responder URLs from the authorityInfoAccess extension.
+    pub fn ocsp_urls(&self) -> Vec<String> {
+        let mut urls = Vec::new();
+        let Some(list) = self.extension(OID_AUTHORITY_INFO_ACCESS).and_then(|value| Der::new(value).expect(TAG_SEQUENCE)) else {
+            return urls;
+        };
+        let mut items = Der::new(list.content);
+        while let Some(description) = items.expect(TAG_SEQUENCE) {
+            let mut fields = Der::new(description.content);
+            let method = fields.expect(TAG_OID).map(|oid| oid_to_string(oid.content));
+            // uniformResourceIdentifier [6]
+            if let (Some(OID_AD_OCSP), Some(location)) = (method.as_deref(), fields.expect(0x86)) {
+                urls.push(String::from_utf8_lossy(location.content).into_owned());
+            }
+        }
+        urls
+    }
+
+    /// CRL URLs from the cRLDistributionPoints extension.
+    pub fn crl_urls(&self) -> Vec<String> {
+        let mut urls = Vec::new();
+        let Some(list) = self.extension(OID_CRL_DISTRIBUTION_POINTS).and_then(|value| Der::new(value).expect(TAG_SEQUENCE)) else {
+            return urls;
+        };
+        let mut points = Der::new(list.content);
+        while let Some(point) = points.expect(TAG_SEQUENCE) {
+            // distributionPoint [0] { fullName [0] GeneralNames }
+            let Some(name) = Der::new(point.content).optional(0xa0).and_then(|name| Der::new(name.content).expect(0xa0)) else {
+                continue;
+            };
+            let mut names = Der::new(name.content);
+            while let Some(general_name) = names.read() {
+                if general_name.tag == 0x86 {
+                    urls.push(String::from_utf8_lossy(general_name.content).into_owned());
+                }
+            }
+        }
+        urls
+    }
+
+    /// The extendedKeyUsage extension lists `purpose` (a dotted OID).
+    pub fn has_extended_key_usage(&self, purpose: &str) -> bool {
+        let Some(list) = self.extension(OID_EXTENDED_KEY_USAGE).and_then(|value| Der::new(value).expect(TAG_SEQUENCE)) else {
+            return false;
+        };
+        let mut items = Der::new(list.content);
+        std::iter::from_fn(|| items.expect(TAG_OID)).any(|oid| oid_to_string(oid.content) == purpose)
+    }
+
+    /// DNS names and IP addresses from the subjectAltName extension.
+    pub fn subject_alt_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let Some(value) = self.extension(OID_SUBJECT_ALT_NAME) else {
+            return names;
+        };
+        let Some(list) = Der::new(value).expect(TAG_SEQUENCE) else {
+            return names;
+        };
+        let mut items = Der::new(list.content);
+        while let Some(item) = items.read() {
+            match item.tag {
+                // dNSName
+                0x82 => names.push(String::from_utf8_lossy(item.content).into_owned()),
+                // iPAddress
+                0x87 => match item.content.len() {
+                    4 => names.push(Ipv4Addr::from(<[u8; 4]>::try_from(item.content).unwrap()).to_string()),
+                    16 => names.push(Ipv6Addr::from(<[u8; 16]>::try_from(item.content).unwrap()).to_string()),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        names
+    }
+}
+
+pub(crate) fn signature_algorithm_name(oid: &str) -> String {
+    match oid {
+        "1.2.840.113549.1.1.5" => "sha1WithRSAEncryption",
+        "1.2.840.113549.1.1.11" => "sha256WithRSAEncryption",
+        "1.2.840.113549.1.1.12" => "sha384WithRSAEncryption",
+        "1.2.840.113549.1.1.13" => "sha512WithRSAEncryption",
+        "1.2.840.113549.1.1.10" => "rsassaPss",
+        "1.2.840.10045.4.3.2" => "ecdsa-with-SHA256",
+        "1.2.840.10045.4.3.3" => "ecdsa-with-SHA384",
+        "1.2.840.10045.4.3.4" => "ecdsa-with-SHA512",
+        "1.3.101.112" => "Ed25519",
+        "1.3.101.113" => "Ed448",
+        other => return other.to_string(),
+    }
+    .to_string()
+}
+
+/// Algorithm and size of a SubjectPublicKeyInfo: "RSA 2048", "ECDSA P-256", "Ed25519".
+fn key_type(spki: &[u8]) -> String {
+    let mut fields = Der::new(spki);
+    let Some(algorithm) = fields.expect(TAG_SEQUENCE) else {
+        return "unknown".to_string();
+    };
+    let mut algorithm = Der::new(algorithm.content);
+    let oid = algorithm.expect(TAG_OID).map(|oid| oid_to_string(oid.content)).unwrap_or_default();
+    match oid.as_str() {
+        "1.2.840.113549.1.1.1" => {
+            // The key is a BIT STRING (one byte of unused-bit count) around RSAPublicKey { modulus, exponent }.
+            let modulus = fields
+                .expect(TAG_BIT_STRING)
+                .and_then(|key| Der::new(key.content.get(1..)?).expect(TAG_SEQUENCE))
+                .and_then(|key| Der::new(key.content).expect(TAG_INTEGER));
+            match modulus {
+                Some(modulus) => {
+                    let bytes = modulus.content.iter().skip_while(|&&b| b == 0).collect::<Vec<_>>();
+                    let bits = bytes.first().map_or(0, |first| bytes.len() * 8 - first.leading_zeros() as usize);
+                    format!("RSA {}", bits)
+                },
+                None => "RSA".to_string(),
+            }
+        },
+        "1.2.840.10045.2.1" => {
+            let curve = algorithm.expect(TAG_OID).map(|curve| oid_to_string(curve.content)).unwrap_or_default();
+            match curve.as_str() {
+                "1.2.840.10045.3.1.7" => "ECDSA P-256".to_string(),
+                "1.3.132.0.34" => "ECDSA P-384".to_string(),
+                "1.3.132.0.35" => "ECDSA P-521".to_string(),
+                other => format!("ECDSA {}", other),
+            }
+        },
+        "1.3.101.112" => "Ed25519".to_string(),
+        "1.3.101.113" => "Ed448".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// The subjectPublicKey bits of a SubjectPublicKeyInfo.
+fn public_key_bits(spki: &[u8]) -> Option<&[u8]> {
+    let mut fields = Der::new(spki);
+    fields.expect(TAG_SEQUENCE)?; // algorithm
+    Some(bit_string(&fields.expect(TAG_BIT_STRING)?))
+}
+
+/// Key type (as `key_type` names it) and key bits of an encoded SubjectPublicKeyInfo.
+pub(crate) fn parse_public_key_info(der: &[u8]) -> Option<(String, &[u8])> {
+    let spki = Der::new(der).expect(TAG_SEQUENCE)?;
+    Some((key_type(spki.content), public_key_bits(spki.content)?))
+}
+
+/// Check a signature made with a `key_type` key; `algorithm` as returned by `signature_algorithm_name`.
+pub(crate) fn verify_signature(key_type: &str, public_key: &[u8], algorithm: &str, message: &[u8], signature: &[u8]) -> bool {
+    use ring::signature::{self, VerificationAlgorithm};
+    let verifier: &dyn VerificationAlgorithm = match (algorithm, key_type) {
+        ("sha1WithRSAEncryption", _) => &signature::RSA_PKCS1_2048_8192_SHA1_FOR_LEGACY_USE_ONLY,
+        ("sha256WithRSAEncryption", _) => &signature::RSA_PKCS1_2048_8192_SHA256,
+        ("sha384WithRSAEncryption", _) => &signature::RSA_PKCS1_2048_8192_SHA384,
+        ("sha512WithRSAEncryption", _) => &signature::RSA_PKCS1_2048_8192_SHA512,
+        ("ecdsa-with-SHA256", "ECDSA P-256") => &signature::ECDSA_P256_SHA256_ASN1,
+        ("ecdsa-with-SHA384", "ECDSA P-256") => &signature::ECDSA_P256_SHA384_ASN1,
+        ("ecdsa-with-SHA256", "ECDSA P-384") => &signature::ECDSA_P384_SHA256_ASN1,
+        ("ecdsa-with-SHA384", "ECDSA P-384") => &signature::ECDSA_P384_SHA384_ASN1,
+        ("Ed25519", _) => &signature::ED25519,
+        _ => return false,
+    };
+    signature::UnparsedPublicKey::new(verifier, public_key).verify(message, signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEAF: &[u8] = include_bytes!("../tests/data/leaf.der");
+    const CA: &[u8] = include_bytes!("../tests/data/ca.der");
+
+    #[test]
+    fn parses_a_certificate() {
+        let leaf = Certificate::parse(LEAF).unwrap();
+        assert_eq!(leaf.subject, "O=NetProbe Test, CN=client.example.test");
+        assert_eq!(leaf.issuer, "C=FR, O=NetProbe Test, CN=NetProbe Test CA");
+        assert_eq!(leaf.serial, [1, 2, 3, 4]);
+        assert_eq!(leaf.signature_algorithm, "ecdsa-with-SHA256");
+        assert_eq!(leaf.key_type, "RSA 2048");
+        // UTCTime before 2050, GeneralizedTime after.
+        assert_eq!(leaf.not_before.format("%Y-%m-%d").to_string(), "2026-10-16");
+        assert_eq!(leaf.not_after.format("%Y-%m-%d").to_string(), "2126-09-22");
+        assert_eq!(leaf.subject_alt_names(), ["client.example.test", "www.example.test", "192.0.2.7", "2001:db8::7"]);
+        assert_eq!(leaf.ocsp_urls(), ["http://ocsp.example.test"]);
+        assert_eq!(leaf.crl_urls(), ["http://crl.example.test/ca.crl"]);
+        assert!(leaf.has_extended_key_usage("1.3.6.1.5.5.7.3.2"));
+        assert!(!leaf.has_extended_key_usage("1.3.6.1.5.5.7.3.3"));
+    }
+
+    #[test]
+    fn checks_signatures() {
+        let leaf = Certificate::parse(LEAF).unwrap();
+        let ca = Certificate::parse(CA).unwrap();
+        assert_eq!(ca.key_type, "ECDSA P-256");
+        assert!(leaf.is_signed_by(&ca));
+        assert!(ca.is_signed_by(&ca));
+        assert!(!leaf.is_signed_by(&leaf));
+    }
+
+    #[test]
+    fn removes_an_extension_from_the_tbs() {
+        let leaf = Certificate::parse(LEAF).unwrap();
+        assert_eq!(leaf.tbs_without_extension("1.2.3.4").unwrap(), leaf.raw_tbs);
+        let stripped = leaf.tbs_without_extension(OID_SUBJECT_ALT_NAME).unwrap();
+        assert!(stripped.len() < leaf.raw_tbs.len());
+        let tbs = Der::new(&stripped).expect(TAG_SEQUENCE).unwrap();
+        assert_eq!(tbs.raw.len(), stripped.len());
+    }
+
+    #[test]
+    fn rejects_truncated_certificates() {
+        for len in 0..LEAF.len() {
+            assert!(Certificate::parse(&LEAF[..len]).is_none(), "prefix of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lengths() {
+        // Indefinite, five-byte and past-the-end lengths.
+        for bad in [&[0x30, 0x80, 0x00, 0x00][..], &[0x30, 0x85, 1, 0, 0, 0, 0], &[0x30, 0x84, 0xff, 0xff, 0xff, 0xff, 0x00], &[0x30, 0x03, 0x02, 0x01]] {
+            assert!(Der::new(bad).read().is_none(), "{:02x?}", bad);
+            assert!(Certificate::parse(bad).is_none());
+        }
+        // A certificate whose outer length claims one byte more than there is.
+        let mut longer = LEAF.to_vec();
+        longer[3] += 1;
+        assert!(Certificate::parse(&longer).is_none());
+        // Garbage where the validity should be: no panic, no certificate.
+        let mut garbled = LEAF.to_vec();
+        let validity = garbled.windows(2).position(|w| w == [0x17, 0x0d]).unwrap();
+        garbled[validity + 2..validity + 8].copy_from_slice(b"99x3z1");
+        assert!(Certificate::parse(&garbled).is_none());
+    }
+
+    #[test]
+    fn encodes_and_reads_back_every_length_form() {
+        for len in [0, 1, 127, 128, 255, 256, 70_000] {
+            let content = vec![0x5a; len];
+            let encoded = encode(TAG_OCTET_STRING, &content);
+            let mut der = Der::new(&encoded);
+            let tlv = der.read().unwrap();
+            assert_eq!((tlv.tag, tlv.content.len(), tlv.raw.len()), (TAG_OCTET_STRING, len, encoded.len()));
+            assert!(der.read().is_none());
+        }
+    }
+
+    #[test]
+    fn decodes_oids_and_times() {
+        assert_eq!(oid_to_string(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b]), "1.2.840.113549.1.1.11");
+        assert_eq!(oid_to_string(&[0x55, 0x1d, 0x11]), "2.5.29.17");
+        let time = |tag, text: &str| parse_time(&Tlv { tag, content: text.as_bytes(), raw: &[] });
+        assert_eq!(time(TAG_UTC_TIME, "491231235959Z").unwrap().to_rfc3339(), "2049-12-31T23:59:59+00:00");
+        assert_eq!(time(TAG_UTC_TIME, "500101000000Z").unwrap().to_rfc3339(), "1950-01-01T00:00:00+00:00");
+        assert_eq!(time(TAG_GENERALIZED_TIME, "21260922062523Z").unwrap().to_rfc3339(), "2126-09-22T06:25:23+00:00");
+        assert!(time(TAG_UTC_TIME, "261332000000Z").is_none());
+        assert!(time(TAG_UTC_TIME, "2610").is_none());
+        assert!(time(TAG_OCTET_STRING, "261016062523Z").is_none());
+    }
+}
@@ -0,0 +1,102 @@
+"""Signed NSEC and NSEC3 denials for the DNSSEC tests (see README.md).
+
+Signs three small zones with one Ed25519 key and writes each zone's whole denial
+chain as the authority section of a DNS response; the tests pick the records a
+server would return for each DS query.
+"""
+import base64, hashlib, struct
+from cryptography.hazmat.primitives.asymmetric.ed25519 import Ed25519PrivateKey
+from cryptography.hazmat.primitives import serialization
+
+key = Ed25519PrivateKey.from_private_bytes(bytes(range(32)))
+pub = key.public_key().public_bytes(serialization.Encoding.Raw, serialization.PublicFormat.Raw)
+dnskey = struct.pack(">HBB", 257, 3, 15) + pub
+def key_tag(rd):
+    s = sum((b << 8) if i % 2 == 0 else b for i, b in enumerate(rd))
+    s += (s >> 16) & 0xffff
+    return s & 0xffff
+TAG = key_tag(dnskey)
+INCEPTION, EXPIRATION = 1735689600, 2082758400
+
+def wire(name):
+    out = b""
+    for l in name.strip(".").split("."):
+        if l: out += bytes([len(l)]) + l.encode()
+    return out + b"\0"
+
+TYPES = {"A":1,"NS":2,"SOA":6,"MX":15,"TXT":16,"AAAA":28,"DS":43,"RRSIG":46,"NSEC":47,"DNSKEY":48,"NSEC3":50,"NSEC3PARAM":51}
+def bitmap(types):
+    codes = sorted(TYPES[t] for t in types)
+    out = b""
+    for window in sorted({c >> 8 for c in codes}):
+        bits = bytearray(32)
+        for c in codes:
+            if c >> 8 == window:
+                bits[(c & 0xff) // 8] |= 0x80 >> (c % 8)
+        n = max(i for i, b in enumerate(bits) if b) + 1
+        out += bytes([window, n]) + bytes(bits[:n])
+    return out
+
+def rr(owner, rtype, rdata, ttl=3600):
+    return wire(owner) + struct.pack(">HHIH", rtype, 1, ttl, len(rdata)) + rdata
+
+def signed(owner, rtype, rdata, zone):
+    labels = len([l for l in owner.split(".") if l])
+    head = struct.pack(">HBBIIIH", rtype, 15, labels, 3600, EXPIRATION, INCEPTION, TAG) + wire(zone)
+    data = head + rr(owner, rtype, rdata)
+    sig = key.sign(data)
+    return [rr(owner, rtype, rdata), rr(owner, 46, head + sig)]
+
+def message(records):
+    return struct.pack(">HHHHHH", 0, 0x8180, 0, 0, len(records), 0) + b"".join(records)
+
+def canon_key(name):
+    return [l.encode() for l in reversed(name.split("."))]
+
+def nsec_zone(zone, names):
+    names = sorted(names, key=lambda n: canon_key(n[0]))
+    out = []
+    for i, (name, types) in enumerate(names):
+        nxt = names[(i + 1) % len(names)][0]
+        out += signed(name, 47, wire(nxt) + bitmap(types + ["RRSIG", "NSEC"]), zone)
+    return out
+
+def h(name):
+    d = wire(name.lower())
+    d = hashlib.sha1(d).digest()
+    return d
+
+def nsec3_zone(zone, names, flags):
+    hashed = sorted((h(n), n, t) for n, t in names)
+    out = []
+    for i, (hh, name, types) in enumerate(hashed):
+        nxt = hashed[(i + 1) % len(hashed)][0]
+        owner = base64.b32hexencode(hh).decode().lower() + "." + zone
+        rd = struct.pack(">BBHB", 1, flags, 0, 0) + bytes([20]) + nxt + bitmap(types + (["RRSIG"] if types != ["NS"] else []))
+        out += signed(owner, 50, rd, zone)
+    return out
+
+def write(path, data):
+    with open(path, "wb") as out:
+        out.write(data)
+
+write("dnssec-dnskey.bin", dnskey)
+write("dnssec-nsec.bin", message(nsec_zone("nsec.example", [
+    ("nsec.example", ["SOA", "NS", "DNSKEY"]),
+    ("a.nsec.example", ["A"]),
+    ("insecure.nsec.example", ["NS"]),
+    ("m.nsec.example", ["A"]),
+    ("secure.nsec.example", ["NS", "DS"]),
+])))
+write("dnssec-nsec3.bin", message(nsec3_zone("nsec3.example", [
+    ("nsec3.example", ["SOA", "NS", "DNSKEY", "NSEC3PARAM"]),
+    ("a.nsec3.example", ["A"]),
+    ("insecure.nsec3.example", ["NS"]),
+    ("secure.nsec3.example", ["NS", "DS"]),
+], 0)))
+# insecure.optout.example is an unsigned delegation left out of the opt-out chain.
+write("dnssec-optout.bin", message(nsec3_zone("optout.example", [
+    ("optout.example", ["SOA", "NS", "DNSKEY", "NSEC3PARAM"]),
+    ("a.optout.example", ["A"]),
+    ("secure.optout.example", ["NS", "DS"]),
+], 1)))